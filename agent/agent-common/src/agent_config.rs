@@ -0,0 +1,73 @@
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::fs;
+use std::path::{Path, PathBuf};
+use testsys_model::constants::{AGENT_CONFIG_FILE, AGENT_CONFIG_PATH};
+
+pub use serde_json::{Map, Value};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read agent config file '{}': {}", path.display(), source))]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Unable to parse agent config file '{}': {}", path.display(), source))]
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Agent config file '{}' does not contain a JSON object", path.display()))]
+    NotAnObject { path: PathBuf },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Load the `config_blob` of the agent, which the controller mounts as a file in the agent's
+/// container, into `configuration`. Fields of the blob take precedence over fields of the same name
+/// in `configuration`.
+pub fn load_config_blob(configuration: &mut Map<String, Value>) -> Result<()> {
+    load_config_blob_from(
+        configuration,
+        &Path::new(AGENT_CONFIG_PATH).join(AGENT_CONFIG_FILE),
+    )
+}
+
+/// Load the config blob in the file at `path` into `configuration`.
+pub fn load_config_blob_from(configuration: &mut Map<String, Value>, path: &Path) -> Result<()> {
+    let contents = fs::read_to_string(path).context(ReadFileSnafu { path })?;
+    let blob = match serde_json::from_str(&contents).context(ParseSnafu { path })? {
+        Value::Object(blob) => Some(blob),
+        _ => None,
+    }
+    .context(NotAnObjectSnafu { path })?;
+    configuration.extend(blob);
+    Ok(())
+}
+
+#[test]
+fn test() {
+    let tempdir = tempfile::TempDir::new().unwrap();
+    let path = tempdir.path().join(AGENT_CONFIG_FILE);
+    fs::write(&path, r#"{"region": "us-west-2", "nodes": {"count": 3}}"#).unwrap();
+    let mut configuration = Map::new();
+    configuration.insert("region".to_string(), Value::from("us-east-1"));
+    configuration.insert("cluster".to_string(), Value::from("my-cluster"));
+    load_config_blob_from(&mut configuration, &path).unwrap();
+    assert_eq!(
+        Value::Object(configuration),
+        serde_json::json!({
+            "region": "us-west-2",
+            "cluster": "my-cluster",
+            "nodes": {"count": 3},
+        })
+    );
+
+    fs::write(&path, "[1, 2]").unwrap();
+    assert!(matches!(
+        load_config_blob_from(&mut Map::new(), &path),
+        Err(Error::NotAnObject { .. })
+    ));
+}
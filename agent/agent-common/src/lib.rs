@@ -5,4 +5,5 @@ crates).
 
 !*/
 
+pub mod agent_config;
 pub mod secrets;
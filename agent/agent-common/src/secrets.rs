@@ -2,7 +2,7 @@ use snafu::{OptionExt, ResultExt};
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use testsys_model::constants::SECRETS_PATH;
 use testsys_model::SecretName;
 
@@ -67,41 +67,47 @@ impl SecretsReader {
     /// Get the key/value pairs from a Kubernetes generic/[opaque] secret.
     /// [opaque]: https://kubernetes.io/docs/concepts/configuration/secret/#opaque-secrets
     pub fn get_secret(&self, secret_name: &SecretName) -> Result<SecretData> {
-        let mut map = SecretData::new();
-        let directory = self.dir.join(secret_name.as_str());
-        let read_dir = fs::read_dir(&directory).with_context(|_| error::ListDirectorySnafu {
+        read_secret_directory(secret_name, &self.dir.join(secret_name.as_str()))
+    }
+}
+
+/// Get the key/value pairs of the Kubernetes generic/[opaque] secret `secret_name` that is mounted
+/// in `directory`.
+/// [opaque]: https://kubernetes.io/docs/concepts/configuration/secret/#opaque-secrets
+pub fn read_secret_directory(secret_name: &SecretName, directory: &Path) -> Result<SecretData> {
+    let mut map = SecretData::new();
+    let read_dir = fs::read_dir(directory).with_context(|_| error::ListDirectorySnafu {
+        name: secret_name.to_owned(),
+        directory,
+    })?;
+    for entry in read_dir.map(|result| {
+        result.with_context(|_| error::ListDirectorySnafu {
             name: secret_name.to_owned(),
-            directory: &directory,
-        })?;
-        for entry in read_dir.map(|result| {
-            result.with_context(|_| error::ListDirectorySnafu {
-                name: secret_name.to_owned(),
-                directory: &directory,
-            })
-        }) {
-            let entry = entry?;
-            if entry.path().is_file() {
-                let path = entry.path();
-                let key = path
-                    .file_name()
-                    .with_context(|| error::MissingFilenameSnafu {
-                        name: secret_name.to_owned(),
-                        path: &path,
-                    })?
-                    .to_str()
-                    .with_context(|| error::NonUtf8FilenameSnafu {
-                        name: secret_name.to_owned(),
-                        path: &path,
-                    })?;
-                let value = fs::read(&path).with_context(|_| error::ReadFileSnafu {
+            directory,
+        })
+    }) {
+        let entry = entry?;
+        if entry.path().is_file() {
+            let path = entry.path();
+            let key = path
+                .file_name()
+                .with_context(|| error::MissingFilenameSnafu {
+                    name: secret_name.to_owned(),
+                    path: &path,
+                })?
+                .to_str()
+                .with_context(|| error::NonUtf8FilenameSnafu {
                     name: secret_name.to_owned(),
                     path: &path,
                 })?;
-                map.insert(key.into(), value);
-            }
+            let value = fs::read(&path).with_context(|_| error::ReadFileSnafu {
+                name: secret_name.to_owned(),
+                path: &path,
+            })?;
+            map.insert(key.into(), value);
         }
-        Ok(map)
     }
+    Ok(map)
 }
 
 impl Default for SecretsReader {
@@ -121,6 +121,10 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                     #[serde(skip)]
                     retries: Option<u32>,
                     #[serde(skip)]
+                    informational: Option<bool>,
+                    #[serde(skip)]
+                    requires: Vec<String>,
+                    #[serde(skip)]
                     keep_running: Option<bool>,
                     #[serde(skip)]
                     capabilities: Vec<String>,
@@ -218,6 +222,28 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                         self
                     }
 
+                    pub fn informational(&mut self, informational: bool) -> &mut Self {
+                        self.informational = Some(informational);
+                        self
+                    }
+
+                    pub fn set_informational(&mut self, informational: Option<bool>) -> &mut Self {
+                        self.informational = informational;
+                        self
+                    }
+
+                    pub fn requires<S1>(&mut self, requires: S1) -> &mut Self
+                    where
+                    S1: Into<String> {
+                        self.requires.push(requires.into());
+                        self
+                    }
+
+                    pub fn set_requires(&mut self, requires: Option<Vec<String>>) -> &mut Self {
+                        self.requires = requires.unwrap_or_default();
+                        self
+                    }
+
                     pub fn keep_running(&mut self, keep_running: bool) -> &mut Self {
                         self.keep_running = Some(keep_running);
                         self
@@ -264,6 +290,15 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                                 resources: self.resources.clone(),
                                 depends_on: Some(self.depends_on.clone()),
                                 retries: Some(self.retries.as_ref().cloned().unwrap_or(5)),
+                                informational: self.informational.unwrap_or_default(),
+                                requires: self.requires.clone(),
+                                metadata: Default::default(),
+                                agents: Default::default(),
+                                schedule: None,
+                                assertions: Default::default(),
+                                wait_for_endpoints: Default::default(),
+                                suspend: false,
+                                destroy_resources: false,
                                 agent: testsys_model::Agent {
                                     name: "agent".to_string(),
                                     image: self.image.as_ref().cloned().ok_or_else(|| "Image is required to build a test".to_string())?,
@@ -273,7 +308,39 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                                     secrets: Some(self.secrets.clone()),
                                     capabilities: Some(self.capabilities.clone()),
                                     privileged: self.privileged,
-                                    timeout: None
+                                    timeout: None,
+                                    fs_group: None,
+                                    fs_group_change_policy: None,
+                                    service_account: None,
+                                    restart_policy: Default::default(),
+                                    container_resources: None,
+                                    persistent_volumes: None,
+                                    completions: None,
+                                    success_threshold_percent: None,
+                                    host_aliases: None,
+                                    backoff_limit: None,
+                                    secret_mounts: None,
+                                    startup_probe: None,
+                                    env: None,
+                                    seccomp_profile: None,
+                                    app_armor_profile: None,
+                                    destroy_image: None,
+                                    completions_deadline: None,
+                                    results_format: None,
+                                    node_selector: None,
+                                    arch: None,
+                                    capacity_type: None,
+                                    dns_config: None,
+                                    dns_search_domains: None,
+                                    config_blob: None,
+                                    pod_labels: None,
+                                    scheduling_gates: None,
+                                    qos: None,
+                                    readiness_probe: None,
+                                    oom_retry: None,
+                                    artifacts_on: None,
+                                    ports: None,
+                                    headless_service: None,
                                 },
                             },
                         ))
@@ -455,8 +522,42 @@ pub(crate) fn build_struct(ast: &syn::DeriveInput) -> TokenStream {
                                 capabilities: Some(self.capabilities.clone()),
                                 timeout: None,
                                 privileged: self.privileged,
+                                fs_group: None,
+                                fs_group_change_policy: None,
+                                service_account: None,
+                                restart_policy: Default::default(),
+                                container_resources: None,
+                                persistent_volumes: None,
+                                completions: None,
+                                success_threshold_percent: None,
+                                host_aliases: None,
+                                backoff_limit: None,
+                                secret_mounts: None,
+                                startup_probe: None,
+                                env: None,
+                                seccomp_profile: None,
+                                app_armor_profile: None,
+                                destroy_image: None,
+                                completions_deadline: None,
+                                results_format: None,
+                                node_selector: None,
+                                arch: None,
+                                capacity_type: None,
+                                dns_config: None,
+                                dns_search_domains: None,
+                                config_blob: None,
+                                pod_labels: None,
+                                scheduling_gates: None,
+                                qos: None,
+                                readiness_probe: None,
+                                oom_retry: None,
+                                artifacts_on: None,
+                                ports: None,
+                                headless_service: None,
                             },
-                            destruction_policy: self.destruction_policy.as_ref().cloned().unwrap_or_default()
+                            destruction_policy: self.destruction_policy.as_ref().cloned().unwrap_or_default(),
+                            pool: None,
+                            shared: None,
                         },
                         ))
                     }
@@ -12,12 +12,15 @@ use resource_agent::provider::{
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use testsys_model::Configuration;
+use std::collections::BTreeMap;
+use testsys_model::{Configuration, OutputField, OutputType};
 
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Memo {
     info: Option<DuplicationConfig>,
+    /// The tags that a real provider would apply to the cloud resources it creates.
+    tags: BTreeMap<String, String>,
 }
 
 impl Configuration for Memo {}
@@ -48,6 +51,11 @@ impl Create for DuplicationCreator {
     type Info = Memo;
     type Resource = DuplicatedData;
 
+    fn output_schema(&self) -> Vec<OutputField> {
+        vec![OutputField::new("info", OutputType::Any)
+            .with_description("A copy of the `info` given in the configuration")]
+    }
+
     async fn create<I>(
         &self,
         spec: Spec<Self::Config>,
@@ -61,6 +69,7 @@ impl Create for DuplicationCreator {
             .await
             .context(Resources::Clear, "Unable to get info from client")?;
         memo.info = Some(spec.configuration.clone());
+        memo.tags = spec.identity.standard_tags();
         client.send_info(memo.clone()).await.context(
             Resources::Remaining,
             "Error sending cluster created message",
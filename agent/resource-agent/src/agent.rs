@@ -6,12 +6,16 @@ provider.
 !*/
 
 use crate::clients::{AgentClient, InfoClient};
-use crate::error::AgentResult;
-use crate::provider::{Create, Destroy};
+use crate::error::{AgentError, AgentResult};
+use crate::provider::{
+    Create, Destroy, ErrorKind, ProviderError, ProviderResult, Resources, Spec,
+    DEFAULT_POLL_INTERVAL,
+};
 use crate::{BootstrapData, Configuration, ResourceAction};
 use log::{debug, error, info, trace};
+use std::future::Future;
 use std::marker::PhantomData;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, timeout, Duration};
 
 /// The `Agent` drives the main program of a resource provider. It takes several injected types.
 ///
@@ -56,6 +60,11 @@ where
     creator: Creator,
     destroyer: Destroyer,
     action: ResourceAction,
+
+    /// The maximum amount of time that `Creator::create` and `Destroyer::destroy` may take.
+    create_timeout: Option<Duration>,
+    destroy_timeout: Option<Duration>,
+    poll_interval: Duration,
 }
 
 /// The `Agent` requires specifying a lot of data types. The `Types` struct makes specifying these
@@ -115,9 +124,33 @@ where
             creator,
             destroyer,
             action: bootstrap_data.action,
+            create_timeout: None,
+            destroy_timeout: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
         })
     }
 
+    /// Fail the `create` operation with an [`ErrorKind::Timeout`] error if it does not finish
+    /// within `create_timeout`.
+    pub fn with_create_timeout(mut self, create_timeout: Duration) -> Self {
+        self.create_timeout = Some(create_timeout);
+        self
+    }
+
+    /// Fail the `destroy` operation with an [`ErrorKind::Timeout`] error if it does not finish
+    /// within `destroy_timeout`.
+    pub fn with_destroy_timeout(mut self, destroy_timeout: Duration) -> Self {
+        self.destroy_timeout = Some(destroy_timeout);
+        self
+    }
+
+    /// Pass `poll_interval` to the provider in its [`Spec`] as the time to wait between checks of
+    /// the resource's status. The default is [`DEFAULT_POLL_INTERVAL`].
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
     /// Either create or destroy resources based on which operation was requested when the `Agent`
     /// was instantiated.
     pub async fn run(&self) -> AgentResult<()> {
@@ -141,19 +174,51 @@ where
     async fn create(&self) -> AgentResult<()> {
         trace!("sending create start signal");
         self.agent_client.send_create_starting().await?;
+        self.agent_client.send_ready(false).await?;
+        let output_schema = self.creator.output_schema();
+        if !output_schema.is_empty() {
+            self.agent_client.send_output_schema(&output_schema).await?;
+        }
         debug!("Getting configuration");
-        let config = self.agent_client.get_spec().await?;
+        let config = Spec {
+            poll_interval: self.poll_interval,
+            ..self.agent_client.get_spec().await?
+        };
         trace!("config\n{:?}", config);
-        match self.creator.create(config, &self.info_client).await {
-            Ok(resource) => Ok(self.agent_client.send_create_succeeded(resource).await?),
-            Err(e) => {
-                if let Err(client_error) = self.agent_client.send_create_failed(&e).await {
-                    error!("Unable to send error to Kubernetes: {}", client_error);
-                    error!("The error we failed to send is: {}", e);
-                }
-                Err(e.into())
-            }
+        let resource = match with_timeout(
+            self.create_timeout,
+            "create",
+            self.creator.create(config, &self.info_client),
+        )
+        .await
+        {
+            Ok(resource) => resource,
+            Err(e) => return Err(self.create_failed(e).await),
+        };
+        self.agent_client
+            .send_create_succeeded(resource.clone())
+            .await?;
+
+        debug!("Waiting for the created resource to be ready");
+        match with_timeout(
+            self.create_timeout,
+            "ready",
+            self.creator.ready(&resource, &self.info_client),
+        )
+        .await
+        {
+            Ok(()) => Ok(self.agent_client.send_ready(true).await?),
+            Err(e) => Err(self.create_failed(e).await),
+        }
+    }
+
+    /// Notify Kubernetes that resource creation failed with `e`.
+    async fn create_failed(&self, e: ProviderError) -> AgentError {
+        if let Err(client_error) = self.agent_client.send_create_failed(&e).await {
+            error!("Unable to send error to Kubernetes: {}", client_error);
+            error!("The error we failed to send is: {}", e);
         }
+        e.into()
     }
 
     /// Destroy resources.
@@ -168,17 +233,22 @@ where
         };
 
         let spec = match self.agent_client.get_spec::<Config>().await {
-            Ok(r) => Some(r),
+            Ok(r) => Some(Spec {
+                poll_interval: self.poll_interval,
+                ..r
+            }),
             Err(e) => {
                 error!("Unable to obtain resource config from Kubernetes: {}", e);
                 None
             }
         };
 
-        match self
-            .destroyer
-            .destroy(spec, resource, &self.info_client)
-            .await
+        match with_timeout(
+            self.destroy_timeout,
+            "destroy",
+            self.destroyer.destroy(spec, resource, &self.info_client),
+        )
+        .await
         {
             Ok(()) => Ok(self.agent_client.send_destroy_succeeded().await?),
             Err(e) => {
@@ -217,3 +287,29 @@ where
         }
     }
 }
+
+/// Run the provider `operation`, converting it into an [`ErrorKind::Timeout`] error if it takes
+/// longer than `duration`. Since the operation was interrupted, it is unknown whether it left
+/// resources behind.
+async fn with_timeout<T, F>(
+    duration: Option<Duration>,
+    operation: &str,
+    future: F,
+) -> ProviderResult<T>
+where
+    F: Future<Output = ProviderResult<T>>,
+{
+    match duration {
+        None => future.await,
+        Some(duration) => timeout(duration, future).await.unwrap_or_else(|_| {
+            Err(ProviderError::new_with_context(
+                Resources::Unknown,
+                format!(
+                    "The {} operation did not complete within {:?}",
+                    operation, duration
+                ),
+            )
+            .with_kind(ErrorKind::Timeout))
+        }),
+    }
+}
@@ -7,7 +7,9 @@ container environment to construct the [`Agent`] and all of its parts.
 use crate::ResourceAction;
 use snafu::{ResultExt, Snafu};
 use std::str::FromStr;
-use testsys_model::constants::{ENV_RESOURCE_ACTION, ENV_RESOURCE_NAME};
+use testsys_model::constants::{
+    ENV_RESOURCE_ACTION, ENV_RESOURCE_NAME, ENV_TEST_NAME, ENV_TEST_UID,
+};
 
 /// The public error type for the default [`Bootstrap`].
 #[derive(Debug, Snafu)]
@@ -39,6 +41,11 @@ pub struct BootstrapData {
     pub resource_name: String,
     /// The action that we should take.
     pub action: ResourceAction,
+    /// The name of the TestSys Test that the resource is created for, if the controller knew of one
+    /// when it started the agent.
+    pub test_name: Option<String>,
+    /// The UID of the TestSys Test that the resource is created for.
+    pub test_uid: Option<String>,
 }
 
 impl BootstrapData {
@@ -53,6 +60,12 @@ impl BootstrapData {
                 key: ENV_RESOURCE_NAME,
             })?,
             action,
+            test_name: std::env::var(ENV_TEST_NAME)
+                .ok()
+                .filter(|name| !name.is_empty()),
+            test_uid: std::env::var(ENV_TEST_UID)
+                .ok()
+                .filter(|uid| !uid.is_empty()),
         })
     }
 }
@@ -2,7 +2,7 @@ use super::error::ClientResult;
 use crate::provider::{ProviderError, Spec};
 use crate::{BootstrapData, ResourceAction};
 use testsys_model::clients::ResourceClient;
-use testsys_model::Configuration;
+use testsys_model::{Configuration, OutputField};
 
 /// `AgentClient` allows the [`Agent`] to communicate with Kubernetes.
 ///
@@ -10,7 +10,7 @@ use testsys_model::Configuration;
 /// testing purposes. In practice you will use the [`DefaultAgentClient`].
 ///
 #[async_trait::async_trait]
-pub trait AgentClient: Sized {
+pub trait AgentClient: Sized + Send + Sync {
     /// Create a new `AgentClient`.
     async fn new(data: BootstrapData) -> ClientResult<Self>;
 
@@ -36,6 +36,15 @@ pub trait AgentClient: Sized {
     where
         Resource: Configuration;
 
+    /// Record the fields of the created resource that the resource provider declares. The default
+    /// implementation records nothing.
+    async fn send_output_schema(&self, _output_schema: &[OutputField]) -> ClientResult<()> {
+        Ok(())
+    }
+
+    /// Notify Kubernetes whether the created resource is ready for use.
+    async fn send_ready(&self, ready: bool) -> ClientResult<()>;
+
     /// Notify Kubernetes that the creation of resources failed and provide an error message.
     async fn send_create_failed(&self, error: &ProviderError) -> ClientResult<()>;
 
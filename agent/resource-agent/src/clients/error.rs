@@ -22,6 +22,9 @@ pub enum ClientError {
 
     /// An error occurred while reading a secrets file.
     SecretsError(Option<Box<dyn std::error::Error + Send + Sync + 'static>>),
+
+    /// An error occurred while reading the agent's config blob file.
+    ConfigBlobError(Option<Box<dyn std::error::Error + Send + Sync + 'static>>),
 }
 
 impl ErrorEnum for ClientError {
@@ -32,6 +35,7 @@ impl ErrorEnum for ClientError {
             ClientError::RequestFailed(_) => "Request failed",
             ClientError::Serialization(_) => "Serialization error",
             ClientError::SecretsError(_) => "Secrets error",
+            ClientError::ConfigBlobError(_) => "Config blob error",
         }
     }
 
@@ -44,6 +48,7 @@ impl ErrorEnum for ClientError {
             ClientError::RequestFailed(e) => e.as_ref().map(|some| some.as_ref()),
             ClientError::Serialization(e) => e.as_ref().map(|some| some.as_ref()),
             ClientError::SecretsError(e) => e.as_ref().map(|some| some.as_ref()),
+            ClientError::ConfigBlobError(e) => e.as_ref().map(|some| some.as_ref()),
         }
     }
 }
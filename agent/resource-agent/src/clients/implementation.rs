@@ -1,11 +1,15 @@
 use super::error::ClientResult;
-use crate::clients::{AgentClient, ClientError, DefaultAgentClient, DefaultInfoClient, InfoClient};
-use crate::provider::{ProviderError, Resources, Spec};
+use crate::clients::{
+    load_secret_mounts, AgentClient, ClientError, DefaultAgentClient, DefaultInfoClient, InfoClient,
+};
+use crate::provider::{Identity, ProviderError, Resources, Spec, DEFAULT_POLL_INTERVAL};
 use crate::{BootstrapData, ResourceAction};
+use agent_common::agent_config::load_config_blob;
 use agent_common::secrets::{SecretData, SecretsReader};
 use testsys_model::clients::{CrdClient, ResourceClient};
 use testsys_model::{
-    Configuration, Error as ModelError, ErrorResources, ResourceError, SecretName, TaskState,
+    Configuration, Error as ModelError, ErrorResources, OutputField, ResourceError, SecretName,
+    TaskState,
 };
 
 impl From<testsys_model::clients::Error> for ClientError {
@@ -58,6 +62,20 @@ impl InfoClient for DefaultInfoClient {
         Ok(())
     }
 
+    async fn get_resource_output<Output>(&self, resource_name: &str) -> ClientResult<Output>
+    where
+        Output: Configuration,
+    {
+        self.client
+            .get_created_resource(resource_name)
+            .await?
+            .ok_or_else(|| {
+                ClientError::MissingData(Some(
+                    format!("Resource '{}' has not been created", resource_name).into(),
+                ))
+            })
+    }
+
     async fn get_secret(&self, secret_name: &SecretName) -> ClientResult<SecretData> {
         let secret_reader = SecretsReader::new();
         secret_reader
@@ -93,14 +111,29 @@ impl AgentClient for DefaultAgentClient {
         Config: Configuration,
     {
         let resource = self.resource_client.get(&self.data.resource_name).await?;
-        let config = Config::from_map(
-            self.resource_client
-                .resolve_templated_config(resource.spec.agent.configuration.unwrap_or_default())
-                .await?,
+        let mut configuration = resource.spec.agent.configuration.unwrap_or_default();
+        if resource.spec.agent.config_blob.is_some() {
+            load_config_blob(&mut configuration)
+                .map_err(|e| ClientError::ConfigBlobError(Some(Box::new(e))))?;
+        }
+        let mut configuration = self
+            .resource_client
+            .resolve_templated_config(configuration)
+            .await?;
+        load_secret_mounts(
+            &mut configuration,
+            resource.spec.agent.secret_mounts.iter().flatten(),
         )?;
+        let config = Config::from_map(configuration)?;
         Ok(Spec {
             configuration: config,
             secrets: resource.spec.agent.secrets.unwrap_or_default(),
+            identity: Identity {
+                resource_name: self.data.resource_name.clone(),
+                test_name: self.data.test_name.clone(),
+                test_uid: self.data.test_uid.clone(),
+            },
+            poll_interval: DEFAULT_POLL_INTERVAL,
         })
     }
 
@@ -137,6 +170,22 @@ impl AgentClient for DefaultAgentClient {
         Ok(())
     }
 
+    async fn send_output_schema(&self, output_schema: &[OutputField]) -> ClientResult<()> {
+        let _ = self
+            .resource_client
+            .send_output_schema(&self.data.resource_name, output_schema)
+            .await?;
+        Ok(())
+    }
+
+    async fn send_ready(&self, ready: bool) -> ClientResult<()> {
+        let _ = self
+            .resource_client
+            .send_ready(&self.data.resource_name, ready)
+            .await?;
+        Ok(())
+    }
+
     async fn send_create_failed(&self, error: &ProviderError) -> ClientResult<()> {
         let _ = self
             .resource_client
@@ -31,6 +31,13 @@ pub trait InfoClient: Sized + Send + Sync {
     where
         Info: Configuration;
 
+    /// Get the output (i.e. the created resource) of another `Resource` by name. This allows a
+    /// provider to consume the data produced by a resource that it depends on. Returns a
+    /// `MissingData` error if the named resource has not been created yet.
+    async fn get_resource_output<Output>(&self, resource_name: &str) -> ClientResult<Output>
+    where
+        Output: Configuration;
+
     /// Get the key/value pairs of a Kubernetes generic/[opaque] secret.
     /// [opaque]: https://kubernetes.io/docs/concepts/configuration/secret/#opaque-secrets
     async fn get_secret(&self, secret_name: &SecretName) -> ClientResult<SecretData>;
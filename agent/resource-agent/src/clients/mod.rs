@@ -8,7 +8,9 @@ mod agent_client;
 mod error;
 mod implementation;
 mod info_client;
+mod secret_mounts;
 
 pub use agent_client::{AgentClient, DefaultAgentClient};
 pub use error::{ClientError, ClientResult};
 pub use info_client::{DefaultInfoClient, InfoClient};
+pub use secret_mounts::load_secret_mounts;
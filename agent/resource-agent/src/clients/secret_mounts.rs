@@ -0,0 +1,44 @@
+use super::error::{ClientError, ClientResult};
+use agent_common::secrets::read_secret_directory;
+use serde_json::{Map, Value};
+use std::path::Path;
+use testsys_model::SecretMount;
+
+/// Load the secrets that are mounted with a `config_field` into `configuration`. Each of these
+/// fields is set to a map of the secret's keys and values, read from the files the secret is
+/// mounted as, so that providers receive credentials without them being exposed as environment
+/// variables or stored in the `Resource`.
+pub fn load_secret_mounts<'a, I>(
+    configuration: &mut Map<String, Value>,
+    secret_mounts: I,
+) -> ClientResult<()>
+where
+    I: IntoIterator<Item = &'a SecretMount>,
+{
+    for secret_mount in secret_mounts {
+        let config_field = match &secret_mount.config_field {
+            Some(config_field) => config_field,
+            None => continue,
+        };
+        let data = read_secret_directory(
+            &secret_mount.secret_name,
+            Path::new(&secret_mount.mount_path),
+        )
+        .map_err(|e| ClientError::SecretsError(Some(Box::new(e))))?;
+        let mut values = Map::new();
+        for (key, value) in data {
+            let value = String::from_utf8(value).map_err(|e| {
+                ClientError::SecretsError(Some(
+                    format!(
+                        "The value of '{}' in secret '{}' is not valid UTF-8: {}",
+                        key, secret_mount.secret_name, e
+                    )
+                    .into(),
+                ))
+            })?;
+            values.insert(key, Value::String(value));
+        }
+        configuration.insert(config_field.to_owned(), Value::Object(values));
+    }
+    Ok(())
+}
@@ -64,12 +64,25 @@ impl AsResources for &Resources {
     }
 }
 
+/// Classifies a [`ProviderError`] so that callers can decide how to react to it.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// The operation did not complete within its allotted time.
+    Timeout,
+    /// Any other error.
+    #[default]
+    Other,
+}
+
 /// The error type returned by [`Create`] and [`Destroy`] implementations.
 #[derive(Debug)]
 pub struct ProviderError {
     /// Whether or not the error has left resources behind.
     resources: Resources,
 
+    /// The classification of the error.
+    kind: ErrorKind,
+
     /// Any message to be included with the error. This will be included in the formatted display
     /// before `inner`.
     context: Option<String>,
@@ -90,6 +103,7 @@ impl ProviderError {
     {
         Self {
             resources: resources.as_resources(),
+            kind: ErrorKind::default(),
             context: Some(context.into()),
             inner: Some(source.into()),
         }
@@ -102,6 +116,7 @@ impl ProviderError {
     {
         Self {
             resources: resources.as_resources(),
+            kind: ErrorKind::default(),
             context: None,
             inner: Some(source.into()),
         }
@@ -114,15 +129,26 @@ impl ProviderError {
     {
         Self {
             resources: resources.as_resources(),
+            kind: ErrorKind::default(),
             context: Some(context.into()),
             inner: None,
         }
     }
 
+    /// Set the classification of the error.
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     pub fn resources(&self) -> Resources {
         self.resources
     }
 
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
     pub fn context(&self) -> Option<&str> {
         self.context.as_deref()
     }
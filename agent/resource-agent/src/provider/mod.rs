@@ -1,10 +1,16 @@
 mod error;
+mod wait;
 
-pub use self::error::{AsResources, IntoProviderError, ProviderError, ProviderResult, Resources};
+pub use self::error::{
+    AsResources, ErrorKind, IntoProviderError, ProviderError, ProviderResult, Resources,
+};
+pub use self::wait::{backoff_intervals, poll_with_backoff, wait_until, DEFAULT_POLL_INTERVAL};
 use crate::clients::InfoClient;
 use serde::Serialize;
 use std::collections::BTreeMap;
-use testsys_model::{Configuration, SecretName, SecretType};
+use std::time::Duration;
+use testsys_model::constants::{TAG_RESOURCE_NAME, TAG_TEST_NAME, TAG_TEST_UID};
+use testsys_model::{Configuration, OutputField, SecretName, SecretType};
 
 #[derive(Debug, Default, Clone, Serialize)]
 pub struct Spec<C>
@@ -13,6 +19,40 @@ where
 {
     pub configuration: C,
     pub secrets: BTreeMap<SecretType, SecretName>,
+    /// Who the resource is created for.
+    pub identity: Identity,
+    /// How long to wait between checks of the resource's status, e.g. with [`wait_until`].
+    pub poll_interval: Duration,
+}
+
+/// Identifies the TestSys `Resource` being provided and the `Test` it is created for. Providers
+/// should tag the cloud resources they create with [`Identity::standard_tags`] so that costs can be
+/// attributed and leaked resources can be traced back to a test.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    /// The name of the TestSys `Resource`.
+    pub resource_name: String,
+    /// The name of the TestSys `Test` the resource is created for. Resources can be shared by
+    /// several tests, this is the one that the controller found when it started the agent, if any.
+    pub test_name: Option<String>,
+    /// The UID of the TestSys `Test` the resource is created for.
+    pub test_uid: Option<String>,
+}
+
+impl Identity {
+    /// The tags to apply to every cloud resource the provider creates, keyed by the `TAG_*`
+    /// constants in [`testsys_model::constants`]. Tags for unknown values are left out.
+    pub fn standard_tags(&self) -> BTreeMap<String, String> {
+        [
+            (TAG_RESOURCE_NAME, Some(&self.resource_name)),
+            (TAG_TEST_NAME, self.test_name.as_ref()),
+            (TAG_TEST_UID, self.test_uid.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|value| (key.to_string(), value.to_owned())))
+        .collect()
+    }
 }
 
 /// You implement the [`Create`] trait in order to create resources. This type is then injected into
@@ -39,6 +79,13 @@ pub trait Create: Sized + Send + Sync {
     type Info: Configuration;
     type Resource: Configuration;
 
+    /// The fields of `Resource` that consumers of the resource can depend on. They are recorded in
+    /// the resource's status before creation starts so that they can be discovered without
+    /// creating the resource. The default implementation declares no fields.
+    fn output_schema(&self) -> Vec<OutputField> {
+        Vec::new()
+    }
+
     /// Create resources as defined by the `spec`. You may use `client` to record information
     /// with the Kubernetes CRD.
     async fn create<I>(
@@ -48,6 +95,16 @@ pub trait Create: Sized + Send + Sync {
     ) -> ProviderResult<Self::Resource>
     where
         I: InfoClient;
+
+    /// Wait until the `resource` returned by `create` is ready for use. Tests that need the
+    /// resource will not start until this returns successfully. The default implementation
+    /// considers the resource ready as soon as it has been created.
+    async fn ready<I>(&self, _resource: &Self::Resource, _client: &I) -> ProviderResult<()>
+    where
+        I: InfoClient,
+    {
+        Ok(())
+    }
 }
 
 /// You implement the [`Destroy`] trait in order to destroy resources that you have previously
@@ -0,0 +1,90 @@
+use super::{ErrorKind, ProviderError, ProviderResult, Resources};
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use tokio::time::{sleep, timeout as tokio_timeout, Duration};
+
+/// How long providers wait between checks of a resource's status, unless the [`Agent`] is
+/// configured with a different interval.
+///
+/// [`Agent`]: crate::Agent
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Call `predicate` every `interval` until it returns `true`. Errors returned by `predicate` are
+/// returned immediately. If `predicate` has not returned `true` within `timeout`, an
+/// [`ErrorKind::Timeout`] error is returned. Since the resource was not ready, it is unknown whether
+/// resources were left behind.
+///
+/// Providers should usually poll with the interval given in their [`Spec`](super::Spec).
+pub async fn wait_until<F, Fut>(
+    mut predicate: F,
+    interval: Duration,
+    timeout: Duration,
+) -> ProviderResult<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ProviderResult<bool>>,
+{
+    let poll = async {
+        while !predicate().await? {
+            sleep(interval).await;
+        }
+        Ok(())
+    };
+    tokio_timeout(timeout, poll).await.unwrap_or_else(|_| {
+        Err(ProviderError::new_with_context(
+            Resources::Unknown,
+            format!("The resource was not ready within {:?}", timeout),
+        )
+        .with_kind(ErrorKind::Timeout))
+    })
+}
+
+/// Call `predicate` until it returns `true`, waiting `initial` after the first call and twice as
+/// long after each following call, up to `max`. Each wait is shortened by a random amount of up to
+/// half of it so that agents polling the same API do not do so in lockstep. Errors returned by
+/// `predicate` are returned immediately. If `predicate` has not returned `true` within `timeout`, an
+/// [`ErrorKind::Timeout`] error is returned.
+///
+/// Prefer this to [`wait_until`] for cloud APIs that are rate limited.
+pub async fn poll_with_backoff<F, Fut>(
+    mut predicate: F,
+    initial: Duration,
+    max: Duration,
+    timeout: Duration,
+) -> ProviderResult<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ProviderResult<bool>>,
+{
+    let poll = async {
+        let mut intervals = backoff_intervals(initial, max);
+        while !predicate().await? {
+            sleep(jitter(intervals.next().unwrap_or(max))).await;
+        }
+        Ok(())
+    };
+    tokio_timeout(timeout, poll).await.unwrap_or_else(|_| {
+        Err(ProviderError::new_with_context(
+            Resources::Unknown,
+            format!("The resource was not ready within {:?}", timeout),
+        )
+        .with_kind(ErrorKind::Timeout))
+    })
+}
+
+/// The intervals [`poll_with_backoff`] waits between calls before jitter is applied: `initial`,
+/// doubled after each call, up to `max`.
+pub fn backoff_intervals(initial: Duration, max: Duration) -> impl Iterator<Item = Duration> {
+    std::iter::successors(Some(initial.min(max)), move |interval| {
+        Some(interval.saturating_mul(2).min(max))
+    })
+}
+
+/// Shorten `interval` by a random amount of up to half of it.
+fn jitter(interval: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let half = interval / 2;
+    let nanos = u64::try_from(half.as_nanos()).unwrap_or(u64::MAX);
+    interval - Duration::from_nanos(random.checked_rem(nanos.max(1)).unwrap_or_default())
+}
@@ -0,0 +1,30 @@
+use resource_agent::provider::Identity;
+use testsys_model::constants::{TAG_RESOURCE_NAME, TAG_TEST_NAME, TAG_TEST_UID};
+
+#[test]
+fn standard_tags_identify_resource_and_test() {
+    let identity = Identity {
+        resource_name: "my-cluster".to_string(),
+        test_name: Some("my-test".to_string()),
+        test_uid: Some("8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e".to_string()),
+    };
+    let tags = identity.standard_tags();
+    assert_eq!(tags.len(), 3);
+    assert_eq!(tags[TAG_RESOURCE_NAME], "my-cluster");
+    assert_eq!(tags[TAG_TEST_NAME], "my-test");
+    assert_eq!(tags[TAG_TEST_UID], "8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e");
+    assert_eq!(TAG_TEST_NAME, "testsys.system/test-name");
+}
+
+/// A resource that no test requires is only tagged with its own name.
+#[test]
+fn standard_tags_without_test() {
+    let identity = Identity {
+        resource_name: "my-cluster".to_string(),
+        ..Identity::default()
+    };
+    assert_eq!(
+        identity.standard_tags().into_iter().collect::<Vec<_>>(),
+        vec![(TAG_RESOURCE_NAME.to_string(), "my-cluster".to_string())]
+    );
+}
@@ -1,7 +1,11 @@
 use resource_agent::clients::{AgentClient, ClientResult};
 use resource_agent::provider::{ProviderError, Spec};
 use resource_agent::{BootstrapData, ResourceAction};
-use testsys_model::Configuration;
+use std::sync::Mutex;
+use testsys_model::{Configuration, OutputField};
+
+/// The output schema most recently sent by a [`MockAgentClient`].
+pub(crate) static OUTPUT_SCHEMA: Mutex<Option<Vec<OutputField>>> = Mutex::new(None);
 
 /// Create an [`AgentClient`] that does nothing so that we can test without Kubernetes.
 pub(crate) struct MockAgentClient;
@@ -42,6 +46,15 @@ impl AgentClient for MockAgentClient {
         Ok(())
     }
 
+    async fn send_output_schema(&self, output_schema: &[OutputField]) -> ClientResult<()> {
+        *OUTPUT_SCHEMA.lock().unwrap() = Some(output_schema.to_vec());
+        Ok(())
+    }
+
+    async fn send_ready(&self, _ready: bool) -> ClientResult<()> {
+        Ok(())
+    }
+
     /// Notify Kubernetes that the creation of resources failed and provide an error message.
     async fn send_create_failed(&self, _error: &ProviderError) -> ClientResult<()> {
         Ok(())
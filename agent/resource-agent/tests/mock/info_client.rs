@@ -1,15 +1,21 @@
 use agent_common::secrets::SecretData;
-use resource_agent::clients::{ClientResult, InfoClient};
+use resource_agent::clients::{ClientError, ClientResult, InfoClient};
 use resource_agent::BootstrapData;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
 use testsys_model::{Configuration, SecretName};
 
 /// Create an [`InfoClient`] that does nothing so that we can test without Kubernetes.
-pub(crate) struct MockInfoClient {}
+#[derive(Default)]
+pub(crate) struct MockInfoClient {
+    /// The outputs of other resources, keyed by resource name, that can be read by a provider.
+    pub(crate) resource_outputs: BTreeMap<String, Map<String, Value>>,
+}
 
 #[async_trait::async_trait]
 impl InfoClient for MockInfoClient {
     async fn new(_data: BootstrapData) -> ClientResult<Self> {
-        Ok(Self {})
+        Ok(Self::default())
     }
 
     async fn get_info<Info>(&self) -> ClientResult<Info>
@@ -26,6 +32,17 @@ impl InfoClient for MockInfoClient {
         Ok(())
     }
 
+    async fn get_resource_output<Output>(&self, resource_name: &str) -> ClientResult<Output>
+    where
+        Output: Configuration,
+    {
+        let output = self
+            .resource_outputs
+            .get(resource_name)
+            .ok_or_else(|| ClientError::MissingData(Some(resource_name.into())))?;
+        Ok(Output::from_map(output.to_owned())?)
+    }
+
     async fn get_secret(&self, _secret_name: &SecretName) -> ClientResult<SecretData> {
         Ok(SecretData::default())
     }
@@ -13,11 +13,22 @@ pub(crate) mod info_client;
 use resource_agent::clients::InfoClient;
 use resource_agent::provider::{Create, Destroy, ProviderError, ProviderResult, Resources, Spec};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use testsys_model::Configuration;
 
 /// InstanceCreator pretends to create instances for the sake demonstrating a mock resource provider.
 pub(crate) struct InstanceCreator {}
 
+/// InstanceCounter pretends to create a resource from the output of an upstream `InstanceCreator`
+/// for the sake of demonstrating how a provider reads another resource's output.
+pub(crate) struct InstanceCounter {}
+
+/// SlowInstanceCreator pretends to create instances but takes `delay` to do so, for the sake of
+/// demonstrating provider timeouts.
+pub(crate) struct SlowInstanceCreator {
+    pub(crate) delay: Duration,
+}
+
 /// InstanceDestroyer pretends to destroy instances for the sake demonstrating a mock resource
 /// provider.
 pub(crate) struct InstanceDestroyer {}
@@ -42,11 +53,28 @@ impl Configuration for InstanceConfig {}
 #[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CreatedInstances {
-    instance_ids: Vec<String>,
+    pub(crate) instance_ids: Vec<String>,
 }
 
 impl Configuration for CreatedInstances {}
 
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CounterConfig {
+    /// The name of the resource that created the instances.
+    pub(crate) instances_resource: String,
+}
+
+impl Configuration for CounterConfig {}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceCount {
+    pub(crate) count: usize,
+}
+
+impl Configuration for InstanceCount {}
+
 #[async_trait::async_trait]
 impl Create for InstanceCreator {
     type Config = InstanceConfig;
@@ -73,6 +101,49 @@ impl Create for InstanceCreator {
     }
 }
 
+#[async_trait::async_trait]
+impl Create for SlowInstanceCreator {
+    type Config = InstanceConfig;
+    type Info = Memo;
+    type Resource = CreatedInstances;
+
+    async fn create<I>(
+        &self,
+        spec: Spec<Self::Config>,
+        client: &I,
+    ) -> ProviderResult<Self::Resource>
+    where
+        I: InfoClient,
+    {
+        tokio::time::sleep(self.delay).await;
+        InstanceCreator {}.create(spec, client).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Create for InstanceCounter {
+    type Config = CounterConfig;
+    type Info = Memo;
+    type Resource = InstanceCount;
+
+    async fn create<I>(
+        &self,
+        spec: Spec<Self::Config>,
+        client: &I,
+    ) -> ProviderResult<Self::Resource>
+    where
+        I: InfoClient,
+    {
+        let instances: CreatedInstances = client
+            .get_resource_output(&spec.configuration.instances_resource)
+            .await
+            .map_err(|e| ProviderError::new_with_source(Resources::Clear, e))?;
+        Ok(InstanceCount {
+            count: instances.instance_ids.len(),
+        })
+    }
+}
+
 #[async_trait::async_trait]
 impl Destroy for InstanceDestroyer {
     type Config = InstanceConfig;
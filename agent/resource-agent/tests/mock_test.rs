@@ -2,9 +2,16 @@ pub(crate) mod mock;
 
 use mock::agent_client::MockAgentClient;
 use mock::info_client::MockInfoClient;
-use mock::{InstanceCreator, InstanceDestroyer};
+use mock::{
+    CounterConfig, CreatedInstances, InstanceCounter, InstanceCreator, InstanceDestroyer,
+    SlowInstanceCreator,
+};
+use resource_agent::error::AgentError;
+use resource_agent::provider::{Create, ErrorKind, Spec, DEFAULT_POLL_INTERVAL};
 use resource_agent::{Agent, BootstrapData, ResourceAction, Types};
 use std::marker::PhantomData;
+use std::time::Duration;
+use testsys_model::Configuration;
 
 /// This test demonstrates the the use of mock clients so that [`Create`] and [`Destroy`] implementations can be  tested
 /// in the absence of Kubernetes.
@@ -20,6 +27,8 @@ async fn mock_test() {
         BootstrapData {
             resource_name: "some-instances".to_string(),
             action: ResourceAction::Create,
+            test_name: None,
+            test_uid: None,
         },
         InstanceCreator {},
         InstanceDestroyer {},
@@ -39,6 +48,8 @@ async fn mock_test() {
         BootstrapData {
             resource_name: "some-instances".to_string(),
             action: ResourceAction::Destroy,
+            test_name: None,
+            test_uid: None,
         },
         InstanceCreator {},
         InstanceDestroyer {},
@@ -47,3 +58,97 @@ async fn mock_test() {
     .unwrap();
     agent.run().await.unwrap();
 }
+
+/// This test demonstrates a [`Create`] implementation that reads the output of a resource that it
+/// depends on.
+#[tokio::test]
+async fn resource_output_test() {
+    let created_instances = CreatedInstances {
+        instance_ids: vec!["123".to_string(), "456".to_string()],
+    };
+    let info_client = MockInfoClient {
+        resource_outputs: [(
+            "some-instances".to_string(),
+            created_instances.into_map().unwrap(),
+        )]
+        .into_iter()
+        .collect(),
+    };
+
+    let instance_count = InstanceCounter {}
+        .create(
+            Spec {
+                configuration: CounterConfig {
+                    instances_resource: "some-instances".to_string(),
+                },
+                secrets: Default::default(),
+                identity: Default::default(),
+                poll_interval: DEFAULT_POLL_INTERVAL,
+            },
+            &info_client,
+        )
+        .await
+        .unwrap();
+    assert_eq!(instance_count.count, 2);
+
+    // The upstream resource has not been created.
+    assert!(InstanceCounter {}
+        .create(
+            Spec {
+                configuration: CounterConfig {
+                    instances_resource: "other-instances".to_string(),
+                },
+                secrets: Default::default(),
+                identity: Default::default(),
+                poll_interval: DEFAULT_POLL_INTERVAL,
+            },
+            &info_client,
+        )
+        .await
+        .is_err());
+}
+
+/// This test demonstrates that a provider operation that takes too long fails with a timeout
+/// error.
+#[tokio::test]
+async fn provider_timeout_test() {
+    let new_agent = |delay: Duration| async move {
+        Agent::new(
+            Types {
+                info_client: PhantomData::<MockInfoClient>,
+                agent_client: PhantomData::<MockAgentClient>,
+            },
+            BootstrapData {
+                resource_name: "some-instances".to_string(),
+                action: ResourceAction::Create,
+                test_name: None,
+                test_uid: None,
+            },
+            SlowInstanceCreator { delay },
+            InstanceDestroyer {},
+        )
+        .await
+        .unwrap()
+        .with_create_timeout(Duration::from_millis(100))
+    };
+
+    // An operation that finishes within the timeout succeeds.
+    new_agent(Duration::from_millis(0))
+        .await
+        .run()
+        .await
+        .unwrap();
+
+    // An operation that takes too long fails with a timeout error.
+    let error = new_agent(Duration::from_secs(60))
+        .await
+        .run()
+        .await
+        .unwrap_err();
+    match error {
+        AgentError::Provider(provider_error) => {
+            assert_eq!(provider_error.kind(), ErrorKind::Timeout)
+        }
+        other => panic!("Expected a provider error but got: {}", other),
+    }
+}
@@ -0,0 +1,62 @@
+#[path = "mock/agent_client.rs"]
+mod agent_client;
+#[path = "mock/info_client.rs"]
+mod info_client;
+#[path = "../examples/duplicator_resource_agent/provider.rs"]
+mod provider;
+
+use agent_client::{MockAgentClient, OUTPUT_SCHEMA};
+use info_client::MockInfoClient;
+use provider::{DuplicationCreator, DuplicationDestroyer};
+use resource_agent::{Agent, BootstrapData, ResourceAction, Types};
+use std::marker::PhantomData;
+use testsys_model::{OutputField, OutputType, Resource, ResourceSpec, ResourceStatus};
+
+/// The duplicator's output schema is recorded when it creates its resource and can be read back
+/// from the resource's status.
+#[tokio::test]
+async fn duplicator_output_schema_is_recorded() {
+    let types = Types {
+        info_client: PhantomData::<MockInfoClient>,
+        agent_client: PhantomData::<MockAgentClient>,
+    };
+    let agent = Agent::new(
+        types,
+        BootstrapData {
+            resource_name: "duplicated".to_string(),
+            action: ResourceAction::Create,
+            test_name: None,
+            test_uid: None,
+        },
+        DuplicationCreator {},
+        DuplicationDestroyer {},
+    )
+    .await
+    .unwrap();
+    agent.run().await.unwrap();
+
+    let output_schema = OUTPUT_SCHEMA.lock().unwrap().clone().unwrap();
+    assert_eq!(
+        output_schema,
+        vec![OutputField {
+            name: "info".to_string(),
+            field_type: OutputType::Any,
+            description: Some("A copy of the `info` given in the configuration".to_string()),
+            sensitive: false,
+        }]
+    );
+
+    // The schema survives the round trip through the resource's status.
+    let mut resource = Resource::new("duplicated", ResourceSpec::default());
+    resource.status = Some(ResourceStatus {
+        output_schema: Some(output_schema.clone()),
+        ..ResourceStatus::default()
+    });
+    let resource: Resource =
+        serde_json::from_value(serde_json::to_value(&resource).unwrap()).unwrap();
+    assert_eq!(resource.output_schema(), Some(output_schema.as_slice()));
+    assert_eq!(
+        serde_json::to_value(&output_schema[0]).unwrap()["type"],
+        serde_json::json!("any")
+    );
+}
@@ -0,0 +1,60 @@
+use resource_agent::clients::load_secret_mounts;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use testsys_model::{Configuration, SecretMount, SecretName};
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProviderConfig {
+    region: String,
+    credentials: Credentials,
+}
+
+impl Configuration for ProviderConfig {}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+}
+
+/// A provider's configuration receives the credentials from the files its secret is mounted as.
+#[test]
+fn credentials_from_mounted_secret() {
+    let mount_dir = tempfile::TempDir::new().unwrap();
+    fs::write(mount_dir.path().join("accessKeyId"), "AKIDEXAMPLE").unwrap();
+    fs::write(mount_dir.path().join("secretAccessKey"), "wJalrXUtnFEMI").unwrap();
+    let secret_mounts = vec![
+        SecretMount {
+            secret_name: SecretName::new("aws-creds").unwrap(),
+            mount_path: mount_dir.path().to_str().unwrap().to_string(),
+            config_field: Some("credentials".to_string()),
+        },
+        // Secrets without a configuration field are only mounted.
+        SecretMount {
+            secret_name: SecretName::new("kubeconfig").unwrap(),
+            mount_path: "/does/not/exist".to_string(),
+            config_field: None,
+        },
+    ];
+
+    let mut configuration = ProviderConfig {
+        region: "us-west-2".to_string(),
+        ..ProviderConfig::default()
+    }
+    .into_map()
+    .unwrap();
+    load_secret_mounts(&mut configuration, &secret_mounts).unwrap();
+
+    assert_eq!(
+        ProviderConfig::from_map(configuration).unwrap(),
+        ProviderConfig {
+            region: "us-west-2".to_string(),
+            credentials: Credentials {
+                access_key_id: "AKIDEXAMPLE".to_string(),
+                secret_access_key: "wJalrXUtnFEMI".to_string(),
+            },
+        }
+    );
+}
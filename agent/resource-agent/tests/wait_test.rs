@@ -0,0 +1,84 @@
+use resource_agent::provider::{backoff_intervals, poll_with_backoff, wait_until, ErrorKind};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The predicate is polled until it returns `true`.
+#[tokio::test]
+async fn wait_until_ready_after_polls() {
+    let polls = AtomicUsize::new(0);
+    let result = wait_until(
+        || async { Ok(polls.fetch_add(1, Ordering::SeqCst) == 2) },
+        Duration::from_millis(10),
+        Duration::from_secs(10),
+    )
+    .await;
+    assert!(result.is_ok());
+    assert_eq!(polls.load(Ordering::SeqCst), 3);
+}
+
+/// A predicate that never returns `true` fails with a timeout error.
+#[tokio::test]
+async fn wait_until_times_out() {
+    let polls = AtomicUsize::new(0);
+    let error = wait_until(
+        || async {
+            polls.fetch_add(1, Ordering::SeqCst);
+            Ok(false)
+        },
+        Duration::from_millis(10),
+        Duration::from_millis(100),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Timeout);
+    assert!(polls.load(Ordering::SeqCst) > 1);
+}
+
+/// The interval doubles after each poll until it reaches the maximum.
+#[test]
+fn backoff_intervals_grow_to_max() {
+    let intervals: Vec<Duration> =
+        backoff_intervals(Duration::from_millis(100), Duration::from_secs(1))
+            .take(6)
+            .collect();
+    assert_eq!(
+        intervals,
+        [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+    );
+}
+
+/// The predicate is polled with backoff until it returns `true`.
+#[tokio::test]
+async fn poll_with_backoff_ready_after_polls() {
+    let polls = AtomicUsize::new(0);
+    let result = poll_with_backoff(
+        || async { Ok(polls.fetch_add(1, Ordering::SeqCst) == 3) },
+        Duration::from_millis(5),
+        Duration::from_millis(20),
+        Duration::from_secs(10),
+    )
+    .await;
+    assert!(result.is_ok());
+    assert_eq!(polls.load(Ordering::SeqCst), 4);
+}
+
+/// A predicate that never returns `true` fails with a timeout error, after fewer polls than a fixed
+/// interval of `initial` would have made.
+#[tokio::test]
+async fn poll_with_backoff_times_out() {
+    let polls = AtomicUsize::new(0);
+    let error = poll_with_backoff(
+        || async {
+            polls.fetch_add(1, Ordering::SeqCst);
+            Ok(false)
+        },
+        Duration::from_millis(10),
+        Duration::from_millis(80),
+        Duration::from_millis(300),
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Timeout);
+    let polls = polls.load(Ordering::SeqCst);
+    assert!(polls > 1 && polls < 30, "{} polls", polls);
+}
@@ -6,7 +6,7 @@ use std::fs::File;
 use std::path::PathBuf;
 use std::time::Duration;
 use tar::Builder;
-use testsys_model::{Outcome, TestResults};
+use testsys_model::{ArtifactsOn, Outcome, TestResults};
 use tokio::time::sleep;
 
 /// The `TestAgent` is the main entrypoint for the program running in a TestPod. It starts a test
@@ -62,16 +62,27 @@ where
     /// is `false`.
     pub async fn run(&mut self) -> Result<(), C::E, R::E> {
         let result = self.run_inner().await;
-        let tar_result = self.tar_results().await;
 
         match &result {
             Ok(_) => info!("Test execution finished without returning an error."),
             Err(e) => error!("Test execution returned an error: {}", e),
         }
-        match &tar_result {
-            Ok(_) => info!("Test output tarball created."),
-            Err(e) => error!("Error creating output tarball: {}", e),
-        }
+
+        let passed = match &result {
+            Ok(results) => self.passed(results).await,
+            Err(_) => false,
+        };
+        let tar_result = if self.artifacts_on().await.collect(passed) {
+            let tar_result = self.tar_results().await;
+            match &tar_result {
+                Ok(_) => info!("Test output tarball created."),
+                Err(e) => error!("Error creating output tarball: {}", e),
+            }
+            tar_result
+        } else {
+            info!("Test output tarball skipped because of 'artifacts_on'.");
+            Ok(())
+        };
 
         if self.keep_running().await {
             info!("'keep_running' is true.");
@@ -81,12 +92,12 @@ where
         // We want the running error first if there was one.
         match result {
             Err(e) => Err(e),
-            Ok(()) => tar_result,
+            Ok(_) => tar_result,
         }
     }
 
-    /// Run the `TestAgent`. This function returns once the test has completed.
-    async fn run_inner(&mut self) -> Result<(), C::E, R::E> {
+    /// Run the `TestAgent`. This function returns the final results once the test has completed.
+    async fn run_inner(&mut self) -> Result<TestResults, C::E, R::E> {
         debug!("running test");
         self.client
             .send_test_starting()
@@ -126,6 +137,7 @@ where
                 retry_count + 1,
                 retries
             );
+            self.finish_updates_best_effort().await;
             if let Err(e) = self
                 .client
                 .send_test_results(test_results.clone())
@@ -161,9 +173,10 @@ where
             retry_count += 1;
         }
 
+        self.finish_updates_best_effort().await;
         if let Err(e) = self
             .client
-            .send_test_results(test_results)
+            .send_test_results(test_results.clone())
             .await
             .map_err(error::Error::Client)
         {
@@ -190,7 +203,7 @@ where
             return Err(e);
         }
 
-        Ok(())
+        Ok(test_results)
     }
 
     /// Returns `true` if the error was successfully sent, `false` if the error could not be sent.
@@ -203,6 +216,14 @@ where
         }
     }
 
+    /// Waits for the updates the `Runner` sent to reach the test's status, so that they do not
+    /// overwrite the results that are sent next. Logs an error if they cannot be sent.
+    async fn finish_updates_best_effort(&mut self) {
+        if let Err(e) = self.info_client.finish_updates().await {
+            error!("unable to finish sending test updates: {}", e);
+        }
+    }
+
     /// Tells the `Runner` to terminate. If an error occurs, tries to send it to k8s, but logs it
     /// if it cannot be sent to k8s.
     async fn terminate_best_effort(&mut self) {
@@ -251,6 +272,35 @@ where
         }
     }
 
+    /// Whether the test passed, i.e. the runner's final results passed and they satisfy the test's
+    /// assertions, which is how the controller judges the test.
+    async fn passed(&self, results: &TestResults) -> bool {
+        if results.outcome != Outcome::Pass {
+            return false;
+        }
+        match self.client.assertions().await {
+            Err(e) => {
+                error!("Unable to communicate with Kubernetes: '{}'", e);
+                // Without the assertions the test may not have passed, so its results are treated
+                // as those of a failed test.
+                false
+            }
+            Ok(assertions) => assertions.iter().all(|assertion| assertion.holds(results)),
+        }
+    }
+
+    async fn artifacts_on(&self) -> ArtifactsOn {
+        match self.client.artifacts_on().await {
+            Err(e) => {
+                error!("Unable to communicate with Kubernetes: '{}'", e);
+                // If we can't communicate with Kubernetes, it's safest to keep the results in case
+                // they are needed for debugging.
+                ArtifactsOn::Always
+            }
+            Ok(value) => value,
+        }
+    }
+
     async fn loop_while_keep_running_is_true(&self) {
         loop {
             sleep(Duration::from_secs(10)).await;
@@ -6,13 +6,18 @@ container environment to construct the [`Agent`] and all of its parts.
 !*/
 
 use snafu::{ResultExt, Snafu};
-use testsys_model::constants::ENV_TEST_NAME;
+use testsys_model::constants::{ENV_TEST_AGENT_NAME, ENV_TEST_NAME, ENV_TEST_UID};
 
 #[derive(Clone)]
 /// Data that is read from the TestPod's container environment and filesystem.
 pub struct BootstrapData {
     /// The name of the TestSys Test.
     pub test_name: String,
+    /// The UID of the TestSys Test, used to associate events with it.
+    pub test_uid: Option<String>,
+    /// The name of the agent from the Test's `spec.agents` that this agent runs as, or `None` if it
+    /// is the Test's main `spec.agent`.
+    pub agent_name: Option<String>,
 }
 
 /// The public error type for the default [`Bootstrap`].
@@ -33,6 +38,12 @@ impl BootstrapData {
     pub fn from_env() -> Result<BootstrapData, BootstrapError> {
         Ok(BootstrapData {
             test_name: std::env::var(ENV_TEST_NAME).context(EnvReadSnafu { key: ENV_TEST_NAME })?,
+            test_uid: std::env::var(ENV_TEST_UID)
+                .ok()
+                .filter(|uid| !uid.is_empty()),
+            agent_name: std::env::var(ENV_TEST_AGENT_NAME)
+                .ok()
+                .filter(|agent_name| !agent_name.is_empty()),
         })
     }
 }
@@ -96,3 +96,24 @@ impl Display for InfoClientError {
 }
 
 impl std::error::Error for InfoClientError {}
+
+pub type ParseResultsResult<T> = std::result::Result<T, ParseResultsError>;
+
+/// An error parsing a test framework's results file with a [`ResultsParser`].
+///
+/// [`ResultsParser`]: crate::ResultsParser
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+pub enum ParseResultsError {
+    #[snafu(display("Unable to read results file '{}': {}", path.display(), source))]
+    ReadResults {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Invalid {} results: {}", format, message))]
+    InvalidResults {
+        format: testsys_model::ResultsFormat,
+        message: String,
+    },
+}
@@ -1,16 +1,19 @@
 use crate::error::{InfoClientError, InfoClientResult};
+use crate::results_stream::ResultsStream;
 use crate::{
     BootstrapData, Client, DefaultClient, DefaultInfoClient, InfoClient, Spec, TestResults,
 };
+use agent_common::agent_config::load_config_blob;
 use async_trait::async_trait;
+use log::warn;
 use serde_json::Value;
-use snafu::{ResultExt, Snafu};
+use snafu::{OptionExt, ResultExt, Snafu};
 use std::fmt::{Debug, Display};
 use std::path::PathBuf;
 use tempfile::TempDir;
 use testsys_model::clients::{CrdClient, ResourceClient, TestClient};
-use testsys_model::constants::TESTSYS_RESULTS_FILE;
-use testsys_model::{Configuration, TaskState};
+use testsys_model::constants::{ENV_MAX_STATUS_FIELD_LEN, TESTSYS_RESULTS_FILE};
+use testsys_model::{Agent, ArtifactsOn, Configuration, ResultAssertion, TaskState};
 
 /// The public error type for the default [`Client`].
 #[derive(Debug, Snafu)]
@@ -30,6 +33,11 @@ pub(crate) enum InnerError {
     #[snafu(display("Unable to deserialize test configuration: {}", source))]
     Deserialization { source: serde_json::Error },
 
+    #[snafu(display("Unable to load config blob: {}", source))]
+    LoadConfigBlob {
+        source: agent_common::agent_config::Error,
+    },
+
     #[snafu(display("Unable to create resource client: {}", source))]
     ResourceClientCreate {
         source: testsys_model::clients::Error,
@@ -42,6 +50,40 @@ pub(crate) enum InnerError {
 
     #[snafu(display("An error occurred while creating a `TempDir`: {}", source))]
     TempDirCreate { source: std::io::Error },
+
+    #[snafu(display("The test has no agent named '{}'", agent_name))]
+    MissingAgent { agent_name: String },
+}
+
+/// A `TestClient` that truncates the strings it writes to the test's status to the length the
+/// controller passed to the agent, or the default length if it did not pass one.
+async fn test_client() -> testsys_model::clients::Result<TestClient> {
+    let client = TestClient::new().await?;
+    Ok(
+        match std::env::var(ENV_MAX_STATUS_FIELD_LEN)
+            .ok()
+            .and_then(|max_len| max_len.trim().parse().ok())
+        {
+            Some(max_len) => client.with_max_status_field_len(max_len),
+            None => client,
+        },
+    )
+}
+
+impl DefaultClient {
+    /// Get the test and the agent in its spec that this agent runs as.
+    async fn get_agent(&self) -> Result<Agent, ClientError> {
+        let test_data = self.client.get(&self.name).await.context(K8sSnafu)?;
+        match &self.agent_name {
+            None => Ok(test_data.spec.agent),
+            Some(agent_name) => Ok(test_data
+                .spec
+                .agents
+                .into_iter()
+                .find(|agent| &agent.name == agent_name)
+                .context(MissingAgentSnafu { agent_name })?),
+        }
+    }
 }
 
 #[async_trait]
@@ -49,16 +91,32 @@ impl Client for DefaultClient {
     type E = ClientError;
 
     async fn new(bootstrap_data: BootstrapData) -> Result<Self, Self::E> {
+        let client = test_client().await.context(K8sSnafu)?;
         Ok(Self {
-            client: TestClient::new().await.context(K8sSnafu)?,
+            client: match &bootstrap_data.agent_name {
+                Some(agent_name) => client.for_agent(agent_name),
+                None => client,
+            },
             name: bootstrap_data.test_name,
+            agent_name: bootstrap_data.agent_name,
             results_dir: TempDir::new().context(TempDirCreateSnafu)?,
         })
     }
 
     async fn keep_running(&self) -> Result<bool, Self::E> {
+        Ok(self.get_agent().await?.keep_running)
+    }
+
+    async fn artifacts_on(&self) -> Result<ArtifactsOn, Self::E> {
+        Ok(self.get_agent().await?.artifacts_on.unwrap_or_default())
+    }
+
+    async fn assertions(&self) -> Result<Vec<ResultAssertion>, Self::E> {
+        if self.agent_name.is_some() {
+            return Ok(Vec::new());
+        }
         let test_data = self.client.get(&self.name).await.context(K8sSnafu)?;
-        Ok(test_data.spec.agent.keep_running)
+        Ok(test_data.spec.assertions)
     }
 
     async fn retries(&self) -> Result<u32, Self::E> {
@@ -70,12 +128,15 @@ impl Client for DefaultClient {
     where
         C: Configuration,
     {
-        let test_data = self.client.get(&self.name).await.context(K8sSnafu)?;
+        let agent = self.get_agent().await?;
 
-        let raw_config = match test_data.spec.agent.configuration {
+        let mut raw_config = match agent.configuration {
             Some(serde_map) => serde_map,
             None => Default::default(),
         };
+        if agent.config_blob.is_some() {
+            load_config_blob(&mut raw_config).context(LoadConfigBlobSnafu)?;
+        }
 
         let resource_client = ResourceClient::new()
             .await
@@ -91,8 +152,9 @@ impl Client for DefaultClient {
         Ok(Spec {
             name: self.name.clone(),
             configuration,
-            secrets: test_data.spec.agent.secrets.unwrap_or_default(),
+            secrets: agent.secrets.unwrap_or_default(),
             results_dir: self.results_dir.path().to_path_buf(),
+            results_format: agent.results_format,
         })
     }
 
@@ -152,18 +214,56 @@ impl Client for DefaultClient {
 impl InfoClient for DefaultInfoClient {
     async fn new(d: BootstrapData) -> InfoClientResult<Self> {
         Ok(Self {
-            client: TestClient::new()
+            client: test_client()
                 .await
                 .map_err(|e| InfoClientError::InitializationFailed(Some(e.into())))?,
+            results_stream: ResultsStream::from_env(&d.test_name),
             data: d,
         })
     }
 
     async fn send_test_update(&self, results: TestResults) -> InfoClientResult<()> {
+        if let Some(results_stream) = &self.results_stream {
+            match results_stream.send(results.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!(
+                    "Unable to stream results, updating the test's status instead: {}",
+                    e
+                ),
+            }
+        }
         self.client
             .send_test_update(&self.data.test_name, results)
             .await
             .map_err(|e| InfoClientError::RequestFailed(Some(e.into())))?;
         Ok(())
     }
+
+    async fn record_event(&self, reason: &str, message: &str) -> InfoClientResult<()> {
+        self.client
+            .record_event(
+                &self.data.test_name,
+                self.data.test_uid.as_deref(),
+                reason,
+                message,
+            )
+            .await
+            .map_err(|e| InfoClientError::RequestFailed(Some(e.into())))?;
+        Ok(())
+    }
+
+    async fn request_extension(&self, duration: std::time::Duration) -> InfoClientResult<()> {
+        self.client
+            .request_extension(&self.data.test_name, duration)
+            .await
+            .map_err(|e| InfoClientError::RequestFailed(Some(e.into())))?;
+        Ok(())
+    }
+
+    async fn finish_updates(&self) -> InfoClientResult<()> {
+        match &self.results_stream {
+            Some(results_stream) => results_stream.finish().await,
+            None => Ok(()),
+        }
+    }
 }
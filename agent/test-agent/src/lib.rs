@@ -10,21 +10,24 @@ mod agent;
 mod bootstrap;
 pub mod error;
 mod k8s_client;
+mod results_parser;
+mod results_stream;
 
 pub use crate::agent::TestAgent;
 use agent_common::secrets::{Result as SecretsResult, SecretData, SecretsReader};
 use async_trait::async_trait;
 pub use bootstrap::{BootstrapData, BootstrapError};
-use error::InfoClientResult;
+use error::{InfoClientError, InfoClientResult, ParseResultsResult};
 pub use k8s_client::ClientError;
 use log::info;
+pub use results_parser::{parser_for, JunitParser, ResultsParser, TapParser};
 use std::collections::BTreeMap;
 use std::fmt::{Debug, Display};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 use testsys_model::clients::TestClient;
-pub use testsys_model::{Configuration, TestResults};
-use testsys_model::{Outcome, SecretName, SecretType};
+pub use testsys_model::{ArtifactsOn, Configuration, ResultsFormat, TestResults};
+use testsys_model::{Outcome, ResultAssertion, SecretName, SecretType};
 
 /// Information that a test [`Runner`] needs before it can begin a test.
 #[derive(Debug, Clone)]
@@ -33,6 +36,21 @@ pub struct Spec<C: Configuration> {
     pub configuration: C,
     pub secrets: BTreeMap<SecretType, SecretName>,
     pub results_dir: PathBuf,
+    /// The format of the results file the agent's test framework writes, if the agent declared
+    /// one.
+    pub results_format: Option<ResultsFormat>,
+}
+
+impl<C: Configuration> Spec<C> {
+    /// Parse the results file at `path` in the agent's declared `results_format`. Returns `None` if
+    /// the agent did not declare one.
+    pub fn parse_results<P>(&self, path: P) -> Option<ParseResultsResult<TestResults>>
+    where
+        P: AsRef<Path>,
+    {
+        self.results_format
+            .map(|format| parser_for(format).parse_file(path.as_ref()))
+    }
 }
 
 /// The `Runner` trait provides a wrapper for any testing modality. You must implement this trait
@@ -96,7 +114,7 @@ pub trait Runner<I: InfoClient>: Sized + Send {
 /// k8s cluster. In practice you will use the provided implementation by calling
 /// `DefaultClient::new()`.
 #[async_trait]
-pub trait Client: Sized {
+pub trait Client: Sized + Send + Sync {
     /// The error type returned by this trait's functions.
     type E: Debug + Display + Send + Sync + 'static;
 
@@ -118,6 +136,18 @@ pub trait Client: Sized {
     /// Determine if the pod should keep running after it has finished or encountered and error.
     async fn keep_running(&self) -> Result<bool, Self::E>;
 
+    /// Determine when the test's results directory should be saved to the results file. The default
+    /// implementation always saves it.
+    async fn artifacts_on(&self) -> Result<ArtifactsOn, Self::E> {
+        Ok(ArtifactsOn::default())
+    }
+
+    /// Get the rules that the test's results must satisfy for the test to pass. Only the test's
+    /// main agent is judged by them. The default implementation has no rules.
+    async fn assertions(&self) -> Result<Vec<ResultAssertion>, Self::E> {
+        Ok(Vec::new())
+    }
+
     /// Determine the number of retries the agent is expected to perform for failed tests.
     async fn retries(&self) -> Result<u32, Self::E>;
 
@@ -145,6 +175,7 @@ pub trait Client: Sized {
 pub struct DefaultClient {
     client: TestClient,
     name: String,
+    agent_name: Option<String>,
     results_dir: TempDir,
 }
 
@@ -152,9 +183,32 @@ pub struct DefaultClient {
 pub trait InfoClient: Sized + Send + Sync {
     async fn new(d: BootstrapData) -> InfoClientResult<Self>;
     async fn send_test_update(&self, results: TestResults) -> InfoClientResult<()>;
+    /// Record a k8s event about the test, e.g. "instance launched" or "waiting for DNS", that
+    /// shows up in `kubectl describe test`. The default implementation records nothing.
+    async fn record_event(&self, _reason: &str, _message: &str) -> InfoClientResult<()> {
+        Ok(())
+    }
+    /// Ask the controller to extend the agent's `timeout` by `duration`. Whether the request was
+    /// granted shows up in the test's status. The controller enforces the `timeout` itself, the
+    /// agent's job has no `activeDeadlineSeconds`, so the extension moves the point at which the
+    /// controller fails the test. The default implementation cannot ask for more time and returns
+    /// an error.
+    async fn request_extension(&self, _duration: std::time::Duration) -> InfoClientResult<()> {
+        Err(InfoClientError::RequestFailed(None))
+    }
+    /// Wait until the updates sent with `send_test_update` so far are in the test's status. The
+    /// [`TestAgent`] calls this before it sends the results of a run so that a late update cannot
+    /// overwrite them. The default implementation sends updates right away and has nothing to
+    /// wait for.
+    async fn finish_updates(&self) -> InfoClientResult<()> {
+        Ok(())
+    }
 }
 
+/// Provides the default [`InfoClient`] implementation. It streams updates to the controller if the
+/// controller serves a results endpoint, and patches the test's status with them otherwise.
 pub struct DefaultInfoClient {
     client: TestClient,
     data: BootstrapData,
+    results_stream: Option<results_stream::ResultsStream>,
 }
@@ -0,0 +1,201 @@
+use crate::error::{InvalidResultsSnafu, ParseResultsResult, ReadResultsSnafu};
+use snafu::ResultExt;
+use std::path::Path;
+use testsys_model::{Outcome, ResultsFormat, TestResults};
+use xmlparser::{ElementEnd, Token, Tokenizer};
+
+/// Parses the results file written by a test framework into [`TestResults`], so that agents that
+/// run a framework with a common output format do not need to parse it themselves. Use
+/// [`parser_for`] to get the parser of the format declared in the agent's spec.
+pub trait ResultsParser {
+    /// Parse the `contents` of a results file.
+    fn parse(&self, contents: &str) -> ParseResultsResult<TestResults>;
+
+    /// Parse the results file at `path`.
+    fn parse_file(&self, path: &Path) -> ParseResultsResult<TestResults> {
+        let contents = std::fs::read_to_string(path).context(ReadResultsSnafu { path })?;
+        self.parse(&contents)
+    }
+}
+
+/// The built-in parser of results in the given `format`.
+pub fn parser_for(format: ResultsFormat) -> Box<dyn ResultsParser + Send + Sync> {
+    match format {
+        ResultsFormat::Junit => Box::new(JunitParser),
+        ResultsFormat::Tap => Box::new(TapParser),
+    }
+}
+
+/// Parses JUnit XML. Each `<testcase>` is a test, which failed if it has a `<failure>` or an
+/// `<error>` and was skipped if it has a `<skipped>`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct JunitParser;
+
+impl ResultsParser for JunitParser {
+    fn parse(&self, contents: &str) -> ParseResultsResult<TestResults> {
+        let mut counts = Counts::default();
+        // The names of the open elements, and what the open test case has reported.
+        let mut elements: Vec<&str> = Vec::new();
+        let mut test_case = TestCase::Passed;
+        for token in Tokenizer::from(contents) {
+            let token = token.map_err(|e| {
+                InvalidResultsSnafu {
+                    format: ResultsFormat::Junit,
+                    message: e.to_string(),
+                }
+                .build()
+            })?;
+            match token {
+                Token::ElementStart { local, .. } => {
+                    let name = local.as_str();
+                    if elements.last() == Some(&"testcase") {
+                        match name {
+                            "failure" | "error" => test_case = TestCase::Failed,
+                            "skipped" if test_case == TestCase::Passed => {
+                                test_case = TestCase::Skipped
+                            }
+                            _ => {}
+                        }
+                    } else if name == "testcase" {
+                        test_case = TestCase::Passed;
+                    }
+                    elements.push(name);
+                }
+                Token::ElementEnd {
+                    end: ElementEnd::Close(..) | ElementEnd::Empty,
+                    ..
+                } => {
+                    let closed = elements.pop();
+                    counts.add_if(closed == Some("testcase"), test_case);
+                }
+                _ => {}
+            }
+        }
+        if !elements.is_empty() {
+            return InvalidResultsSnafu {
+                format: ResultsFormat::Junit,
+                message: format!("the '{}' element is not closed", elements.join("/")),
+            }
+            .fail();
+        }
+        Ok(counts.into_results(false))
+    }
+}
+
+/// Parses the Test Anything Protocol. Each top level `ok` or `not ok` line is a test, a `# SKIP`
+/// directive marks it as skipped and a `# TODO` directive marks a failing test as skipped. Tests
+/// that were planned with `1..N` but never reported count as failed, as does `Bail out!`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TapParser;
+
+impl ResultsParser for TapParser {
+    fn parse(&self, contents: &str) -> ParseResultsResult<TestResults> {
+        let mut counts = Counts::default();
+        let mut planned: Option<u64> = None;
+        let mut bailed_out = false;
+        // Subtests are indented and are summed up by their parent's line.
+        for line in contents
+            .lines()
+            .filter(|line| !line.starts_with(char::is_whitespace))
+        {
+            if let Some(plan) = line.strip_prefix("1..") {
+                let count = plan.split_whitespace().next().unwrap_or_default();
+                planned = Some(count.parse().map_err(|_| {
+                    InvalidResultsSnafu {
+                        format: ResultsFormat::Tap,
+                        message: format!("invalid plan '{}'", line),
+                    }
+                    .build()
+                })?);
+            } else if line.starts_with("Bail out!") {
+                bailed_out = true;
+                break;
+            } else if let Some(rest) = line.strip_prefix("not ok") {
+                counts.add(match directive(rest) {
+                    Some(Directive::Skip | Directive::Todo) => TestCase::Skipped,
+                    None => TestCase::Failed,
+                });
+            } else if let Some(rest) = line.strip_prefix("ok") {
+                counts.add(match directive(rest) {
+                    Some(Directive::Skip) => TestCase::Skipped,
+                    Some(Directive::Todo) | None => TestCase::Passed,
+                });
+            }
+        }
+        if let Some(planned) = planned {
+            counts.failed += planned.saturating_sub(counts.total());
+        }
+        Ok(counts.into_results(bailed_out))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TestCase {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    passed: u64,
+    failed: u64,
+    skipped: u64,
+}
+
+impl Counts {
+    fn add(&mut self, test_case: TestCase) {
+        match test_case {
+            TestCase::Passed => self.passed += 1,
+            TestCase::Failed => self.failed += 1,
+            TestCase::Skipped => self.skipped += 1,
+        }
+    }
+
+    fn add_if(&mut self, condition: bool, test_case: TestCase) {
+        if condition {
+            self.add(test_case)
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.passed + self.failed + self.skipped
+    }
+
+    /// The results are a failure if any test failed or the run was `aborted`, and are unknown if
+    /// no test passed.
+    fn into_results(self, aborted: bool) -> TestResults {
+        let outcome = if aborted || self.failed > 0 {
+            Outcome::Fail
+        } else if self.passed > 0 {
+            Outcome::Pass
+        } else {
+            Outcome::Unknown
+        };
+        TestResults {
+            outcome,
+            num_passed: self.passed,
+            num_failed: self.failed,
+            num_skipped: self.skipped,
+            other_info: None,
+        }
+    }
+}
+
+enum Directive {
+    Skip,
+    Todo,
+}
+
+/// The directive at the end of a TAP test line, e.g. `ok 3 - install # SKIP no network`.
+fn directive(line: &str) -> Option<Directive> {
+    let (_, directive) = line.split_once('#')?;
+    let directive = directive.trim_start().to_ascii_lowercase();
+    if directive.starts_with("skip") {
+        Some(Directive::Skip)
+    } else if directive.starts_with("todo") {
+        Some(Directive::Todo)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,126 @@
+use crate::error::{InfoClientError, InfoClientResult};
+use crate::TestResults;
+use log::{debug, warn};
+use std::env;
+use testsys_model::constants::ENV_RESULTS_ENDPOINT;
+use testsys_model::results_stream::proto::results_client::ResultsClient;
+use testsys_model::results_stream::proto::{self, StreamSummary};
+use testsys_model::results_stream::TEST_NAME_METADATA;
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tonic::metadata::MetadataValue;
+
+/// The ServiceAccount token that the agent proves who it is to the controller with.
+const SERVICE_ACCOUNT_TOKEN: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// Streams a test's results to the controller's results endpoint instead of patching the test's
+/// status for each of them. Only the latest results are kept until they are sent, so an agent that
+/// reports faster than the controller takes them does not build up a backlog.
+pub(crate) struct ResultsStream {
+    endpoint: String,
+    test_name: String,
+    stream: Mutex<Option<OpenStream>>,
+}
+
+struct OpenStream {
+    sender: watch::Sender<Option<TestResults>>,
+    call: JoinHandle<InfoClientResult<StreamSummary>>,
+}
+
+impl ResultsStream {
+    /// The stream of the results of the test `test_name`, if the controller gave the agent a
+    /// results endpoint.
+    pub(crate) fn from_env(test_name: &str) -> Option<Self> {
+        let endpoint = env::var(ENV_RESULTS_ENDPOINT).ok()?;
+        Some(Self {
+            endpoint,
+            test_name: test_name.to_string(),
+            stream: Mutex::new(None),
+        })
+    }
+
+    /// Send `results` to the controller, opening a stream if there is none. Returns an error if the
+    /// stream could not be opened or has failed.
+    pub(crate) async fn send(&self, results: TestResults) -> InfoClientResult<()> {
+        let mut stream = self.stream.lock().await;
+        if let Some(open) = stream.take() {
+            if open.call.is_finished() {
+                // The controller ended the call early, which only happens if it failed.
+                finish(open).await?;
+            } else {
+                *stream = Some(open);
+            }
+        }
+        let open = match stream.take() {
+            Some(open) => open,
+            None => self.open()?,
+        };
+        open.sender.send_replace(Some(results));
+        *stream = Some(open);
+        Ok(())
+    }
+
+    /// End the stream, if one is open, and wait for the controller to write the last results that
+    /// were sent.
+    pub(crate) async fn finish(&self) -> InfoClientResult<()> {
+        match self.stream.lock().await.take() {
+            Some(open) => finish(open).await.map(|summary| {
+                debug!(
+                    "Streamed {} results in {} status updates",
+                    summary.results, summary.status_updates
+                )
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn open(&self) -> InfoClientResult<OpenStream> {
+        let token = std::fs::read_to_string(SERVICE_ACCOUNT_TOKEN).map_err(request_failed)?;
+        let (sender, receiver) = watch::channel(None);
+        // The stream ends when the sender is dropped.
+        let updates = futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.changed().await.ok()?;
+            let results = receiver.borrow_and_update().clone()?;
+            Some((proto::TestResults::from(results), receiver))
+        });
+        let mut request = tonic::Request::new(updates);
+        request.metadata_mut().insert(
+            "authorization",
+            MetadataValue::try_from(format!("Bearer {}", token.trim())).map_err(request_failed)?,
+        );
+        request.metadata_mut().insert(
+            TEST_NAME_METADATA,
+            MetadataValue::try_from(self.test_name.as_str()).map_err(request_failed)?,
+        );
+        let endpoint = self.endpoint.clone();
+        let call = tokio::spawn(async move {
+            let mut client = ResultsClient::connect(endpoint)
+                .await
+                .map_err(request_failed)?;
+            client
+                .stream(request)
+                .await
+                .map(tonic::Response::into_inner)
+                .map_err(request_failed)
+        });
+        Ok(OpenStream { sender, call })
+    }
+}
+
+async fn finish(open: OpenStream) -> InfoClientResult<StreamSummary> {
+    drop(open.sender);
+    match open.call.await {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("The results stream stopped unexpectedly: {}", e);
+            Err(request_failed(e))
+        }
+    }
+}
+
+fn request_failed<E>(e: E) -> InfoClientError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    InfoClientError::RequestFailed(Some(e.into()))
+}
@@ -0,0 +1,187 @@
+/*!
+
+Checks that the [`TestAgent`] only saves the results tarball when the agent's `artifacts_on` setting
+calls for it given the outcome of the test.
+
+!*/
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Display};
+use std::path::PathBuf;
+use tempfile::{tempdir, TempDir};
+use test_agent::error::InfoClientResult;
+use test_agent::{ArtifactsOn, BootstrapData, Client, InfoClient, Runner, TestAgent};
+use test_agent::{Spec, TestResults};
+use testsys_model::{Comparison, Configuration, Outcome, ResultAssertion, ResultField};
+
+/// A runner that fails if the test's name starts with `fail`, and passes otherwise.
+struct OutcomeRunner {
+    outcome: Outcome,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct EmptyConfig {}
+
+impl Configuration for EmptyConfig {}
+
+#[async_trait]
+impl<I> Runner<I> for OutcomeRunner
+where
+    I: InfoClient,
+{
+    type C = EmptyConfig;
+    type E = String;
+
+    async fn new(spec: Spec<Self::C>, _: &I) -> Result<Self, Self::E> {
+        let outcome = if spec.name.starts_with("fail") {
+            Outcome::Fail
+        } else {
+            Outcome::Pass
+        };
+        Ok(Self { outcome })
+    }
+
+    async fn run(&mut self, _: &I) -> Result<TestResults, Self::E> {
+        Ok(TestResults {
+            outcome: self.outcome,
+            ..TestResults::default()
+        })
+    }
+
+    async fn terminate(&mut self) -> Result<(), Self::E> {
+        Ok(())
+    }
+}
+
+/// A client for an agent with `artifacts_on` set to `OnFailure`.
+struct OnFailureClient {
+    test_name: String,
+    results_dir: TempDir,
+    results_file: TempDir,
+}
+
+#[async_trait]
+impl Client for OnFailureClient {
+    type E = String;
+
+    async fn new(bootstrap_data: BootstrapData) -> Result<Self, Self::E> {
+        Ok(Self {
+            test_name: bootstrap_data.test_name,
+            results_dir: tempdir().unwrap(),
+            results_file: tempdir().unwrap(),
+        })
+    }
+
+    async fn spec<C>(&self) -> Result<Spec<C>, Self::E>
+    where
+        C: Configuration,
+    {
+        Ok(Spec {
+            name: self.test_name.clone(),
+            configuration: C::default(),
+            secrets: Default::default(),
+            results_dir: Default::default(),
+            results_format: None,
+        })
+    }
+
+    async fn send_test_starting(&self) -> Result<(), Self::E> {
+        Ok(())
+    }
+
+    async fn send_test_update(&self, _: TestResults) -> Result<(), Self::E> {
+        Ok(())
+    }
+
+    async fn send_test_results(&self, _: TestResults) -> Result<(), Self::E> {
+        Ok(())
+    }
+
+    async fn send_error<E>(&self, _: E) -> Result<(), Self::E>
+    where
+        E: Debug + Display + Send + Sync,
+    {
+        Ok(())
+    }
+
+    async fn keep_running(&self) -> Result<bool, Self::E> {
+        Ok(false)
+    }
+
+    async fn results_directory(&self) -> Result<PathBuf, Self::E> {
+        Ok(self.results_dir.path().to_path_buf())
+    }
+
+    async fn results_file(&self) -> Result<PathBuf, Self::E> {
+        Ok(self.results_file.path().join("result.tar.gz"))
+    }
+
+    async fn artifacts_on(&self) -> Result<ArtifactsOn, Self::E> {
+        Ok(ArtifactsOn::OnFailure)
+    }
+
+    /// A test whose name starts with `asserted` requires at least one test case to pass, which the
+    /// runner's results never satisfy.
+    async fn assertions(&self) -> Result<Vec<ResultAssertion>, Self::E> {
+        Ok(if self.test_name.starts_with("asserted") {
+            vec![ResultAssertion {
+                field: ResultField::NumPassed,
+                comparison: Comparison::Ge,
+                value: 1,
+            }]
+        } else {
+            Vec::new()
+        })
+    }
+
+    async fn retries(&self) -> Result<u32, Self::E> {
+        Ok(0)
+    }
+
+    async fn send_test_completed(&self) -> Result<(), Self::E> {
+        Ok(())
+    }
+}
+
+struct NoopInfoClient {}
+
+#[async_trait]
+impl InfoClient for NoopInfoClient {
+    async fn new(_: BootstrapData) -> InfoClientResult<Self> {
+        Ok(Self {})
+    }
+
+    async fn send_test_update(&self, _: TestResults) -> InfoClientResult<()> {
+        Ok(())
+    }
+}
+
+/// Runs a test named `test_name` and returns whether its results tarball was saved.
+async fn results_saved(test_name: &str) -> bool {
+    let mut agent =
+        TestAgent::<OnFailureClient, OutcomeRunner, NoopInfoClient>::new(BootstrapData {
+            test_name: test_name.to_string(),
+            test_uid: None,
+            agent_name: None,
+        })
+        .await
+        .unwrap();
+    agent.run().await.unwrap();
+    agent.results_file().await.unwrap().is_file()
+}
+
+#[tokio::test]
+async fn on_failure_saves_results_of_failed_test() {
+    assert!(results_saved("failing-test").await);
+}
+
+#[tokio::test]
+async fn on_failure_skips_results_of_passing_test() {
+    assert!(!results_saved("passing-test").await);
+}
+
+#[tokio::test]
+async fn on_failure_saves_results_of_test_failing_its_assertions() {
+    assert!(results_saved("asserted-passing-test").await);
+}
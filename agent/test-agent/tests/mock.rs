@@ -11,7 +11,7 @@ use std::fmt::{Debug, Display};
 use std::path::PathBuf;
 use tempfile::{tempdir, TempDir};
 use test_agent::error::InfoClientResult;
-use test_agent::{BootstrapData, Client, InfoClient, Runner};
+use test_agent::{ArtifactsOn, BootstrapData, Client, InfoClient, Runner};
 use test_agent::{Spec, TestResults};
 use testsys_model::{Configuration, Outcome};
 use tokio::time::{sleep, Duration};
@@ -94,6 +94,7 @@ impl Client for MockClient {
             configuration: C::default(),
             secrets: Default::default(),
             results_dir: Default::default(),
+            results_format: None,
         })
     }
 
@@ -132,6 +133,10 @@ impl Client for MockClient {
         Ok(self.results_file.path().join("result.tar.gz"))
     }
 
+    async fn artifacts_on(&self) -> Result<ArtifactsOn, Self::E> {
+        Ok(ArtifactsOn::Always)
+    }
+
     async fn retries(&self) -> Result<u32, Self::E> {
         Ok(0)
     }
@@ -155,6 +160,16 @@ impl InfoClient for MyInfoClient {
         println!("MyInfoClient::send_test_update");
         Ok(())
     }
+
+    async fn record_event(&self, _reason: &str, _message: &str) -> InfoClientResult<()> {
+        println!("MyInfoClient::record_event");
+        Ok(())
+    }
+
+    async fn request_extension(&self, _duration: std::time::Duration) -> InfoClientResult<()> {
+        println!("MyInfoClient::request_extension");
+        Ok(())
+    }
 }
 
 /// This test runs [`MyRunner`] inside a [`TestAgent`] with k8s and the container environment mocked
@@ -164,6 +179,8 @@ async fn mock_test() -> std::io::Result<()> {
     let mut agent_main =
         test_agent::TestAgent::<MockClient, MyRunner, MyInfoClient>::new(BootstrapData {
             test_name: String::from("hello-test"),
+            test_uid: None,
+            agent_name: None,
         })
         .await
         .unwrap();
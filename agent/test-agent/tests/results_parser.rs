@@ -0,0 +1,60 @@
+use std::path::PathBuf;
+use test_agent::{parser_for, JunitParser, ResultsFormat, ResultsParser, TapParser};
+use testsys_model::Outcome;
+
+fn data_file(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+        .join(name)
+}
+
+#[test]
+fn junit_results() {
+    let results = JunitParser.parse_file(&data_file("results.xml")).unwrap();
+    assert_eq!(results.outcome, Outcome::Fail);
+    assert_eq!(results.num_passed, 2);
+    assert_eq!(results.num_failed, 2);
+    assert_eq!(results.num_skipped, 1);
+}
+
+#[test]
+fn junit_passing_results() {
+    let results = parser_for(ResultsFormat::Junit)
+        .parse(r#"<testsuite><testcase name="a"/><testcase name="b"></testcase></testsuite>"#)
+        .unwrap();
+    assert_eq!(results.outcome, Outcome::Pass);
+    assert_eq!(results.num_passed, 2);
+}
+
+#[test]
+fn junit_unclosed_element() {
+    assert!(JunitParser
+        .parse(r#"<testsuite><testcase name="a">"#)
+        .is_err());
+}
+
+#[test]
+fn tap_results() {
+    let results = TapParser.parse_file(&data_file("results.tap")).unwrap();
+    assert_eq!(results.outcome, Outcome::Fail);
+    assert_eq!(results.num_passed, 2);
+    // The sysctl test and the test that was planned but never reported.
+    assert_eq!(results.num_failed, 2);
+    assert_eq!(results.num_skipped, 2);
+}
+
+#[test]
+fn tap_bail_out() {
+    let results = parser_for(ResultsFormat::Tap)
+        .parse("ok 1 - setup\nBail out! no cluster\n")
+        .unwrap();
+    assert_eq!(results.outcome, Outcome::Fail);
+    assert_eq!(results.num_passed, 1);
+}
+
+#[test]
+fn no_tests_is_unknown() {
+    let results = TapParser.parse("TAP version 13\n").unwrap();
+    assert_eq!(results.outcome, Outcome::Unknown);
+}
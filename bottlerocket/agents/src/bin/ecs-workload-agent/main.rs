@@ -502,6 +502,8 @@ async fn run() -> Result<(), test_agent::error::Error<ClientError, Error>> {
     let mut agent = TestAgent::<DefaultClient, EcsWorkloadTestRunner, DefaultInfoClient>::new(
         BootstrapData::from_env().unwrap_or_else(|_| BootstrapData {
             test_name: "ecs_workload_test".to_string(),
+            test_uid: None,
+            agent_name: None,
         }),
     )
     .await?;
@@ -144,6 +144,8 @@ async fn run() -> Result<(), test_agent::error::Error<ClientError, Error>> {
     let mut agent = TestAgent::<DefaultClient, WorkloadTestRunner, DefaultInfoClient>::new(
         BootstrapData::from_env().unwrap_or_else(|_| BootstrapData {
             test_name: "workload_test".to_string(),
+            test_uid: None,
+            agent_name: None,
         }),
     )
     .await?;
@@ -177,6 +177,8 @@ async fn run() -> Result<(), test_agent::error::Error<ClientError, Error>> {
     let mut agent = TestAgent::<DefaultClient, SonobuoyTestRunner, DefaultInfoClient>::new(
         BootstrapData::from_env().unwrap_or_else(|_| BootstrapData {
             test_name: "sonobuoy_test".to_string(),
+            test_uid: None,
+            agent_name: None,
         }),
     )
     .await?;
@@ -0,0 +1,21 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use testsys_model::test_manager::TestManager;
+
+/// Archive a test. Its job and resources are torn down, but the test is kept with an archived
+/// status and left out of `status` unless `--include-archived` is passed.
+#[derive(Debug, Parser)]
+pub(crate) struct Archive {
+    /// The name of the test to be archived.
+    #[clap()]
+    test_name: String,
+}
+
+impl Archive {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        client
+            .archive_test(&self.test_name)
+            .await
+            .context("Unable to archive the test")
+    }
+}
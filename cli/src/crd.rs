@@ -0,0 +1,15 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use testsys_model::test_manager::crd_manifest;
+
+/// Print the YAML manifest of the TestSys CRDs, e.g. `cli crd > crd.yaml`. No cluster is needed.
+#[derive(Debug, Parser)]
+pub(crate) struct Crd {}
+
+impl Crd {
+    pub(crate) fn run(self) -> Result<()> {
+        let manifest = crd_manifest().context("Unable to generate the CRD manifest")?;
+        print!("{}", manifest);
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use clap::Parser;
+use testsys_model::system::ControllerOptions;
 use testsys_model::test_manager::{ImageConfig, TestManager};
 
 /// The install subcommand is responsible for putting all of the necessary components for testsys in
@@ -17,6 +18,43 @@ pub(crate) struct Install {
 
     #[clap(long = "archive-logs")]
     archive_logs: bool,
+
+    /// Forward the logs of running test agents to this sink. Either `stdout` to write them to
+    /// the controller's output, or an HTTP endpoint to post batches of them to as newline
+    /// delimited JSON.
+    #[clap(long = "log-sink")]
+    log_sink: Option<String>,
+
+    /// Serve the controller's HTTP API for creating and querying tests on this address, e.g.
+    /// `0.0.0.0:8080`.
+    #[clap(long = "api-address")]
+    api_address: Option<String>,
+
+    /// Serve the gRPC endpoint that test agents stream their results to on this address, e.g.
+    /// `0.0.0.0:50051`. A `testsys-controller` service exposes it to the agents.
+    #[clap(long = "results-address")]
+    results_address: Option<String>,
+
+    /// Serve the mutating admission webhook that fills the controller's agent defaults into new
+    /// tests on this address, e.g. `0.0.0.0:8443`. The webhook is registered with the cluster and
+    /// served over TLS with a certificate that is created for it.
+    #[clap(long = "webhook-address")]
+    webhook_address: Option<String>,
+
+    /// Skip tests with names matching this glob pattern, e.g. `*-flaky`. Can be given more than
+    /// once.
+    #[clap(long = "quarantine")]
+    quarantine: Vec<String>,
+
+    /// Delete tests that finished longer ago than this, e.g. `7d` or `12h`. Completed tests are
+    /// kept until they are deleted manually if this is not provided.
+    #[clap(long = "test-retention")]
+    test_retention: Option<String>,
+
+    /// Only run test agent images that start with this prefix, e.g. an approved registry. Can be
+    /// given more than once. Every image is allowed if this is not provided.
+    #[clap(long = "allowed-image")]
+    allowed_images: Vec<String>,
 }
 
 impl Install {
@@ -26,7 +64,19 @@ impl Install {
             (None, image) => ImageConfig::Image(image),
         };
         client
-            .install(controller_image, self.archive_logs)
+            .install(
+                controller_image,
+                self.archive_logs,
+                ControllerOptions {
+                    log_sink: self.log_sink,
+                    api_address: self.api_address,
+                    results_address: self.results_address,
+                    webhook_address: self.webhook_address,
+                    quarantine: self.quarantine,
+                    test_retention: self.test_retention,
+                    allowed_images: self.allowed_images,
+                },
+            )
             .await
             .context(
                 "Unable to install testsys to the cluster. (Some artifacts may be left behind)",
@@ -0,0 +1,52 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::path::PathBuf;
+use testsys_model::test_manager::{CrdState, SelectionParams, TestManager};
+
+/// Render the outcome of a suite of tests as a JUnit XML report.
+#[derive(Debug, Parser)]
+pub(crate) struct Junit {
+    /// Only include tests with the specified labels ("foo=bar,biz=baz")
+    #[clap(long)]
+    labels: Option<String>,
+
+    /// Only include tests with the specified state ("completed", "running", "not-finished",
+    /// "passed", "failed")
+    #[clap(long)]
+    state: Option<CrdState>,
+
+    /// The name of the test suite in the report, defaults to the labels.
+    #[clap(long)]
+    suite_name: Option<String>,
+
+    /// The file the report should be written to, the report is printed if this is not provided.
+    #[clap(long)]
+    destination: Option<PathBuf>,
+}
+
+impl Junit {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let suite_name = self
+            .suite_name
+            .or_else(|| self.labels.clone())
+            .unwrap_or_else(|| "testsys".to_string());
+        let selection_params = SelectionParams {
+            labels: self.labels,
+            state: self.state,
+            ..Default::default()
+        };
+        let report = client
+            .junit_report(&suite_name, &selection_params)
+            .await
+            .context("Unable to create JUnit report")?;
+
+        match self.destination {
+            Some(destination) => std::fs::write(&destination, report).context(format!(
+                "Unable to write JUnit report to '{}'",
+                destination.display()
+            ))?,
+            None => print!("{}", report),
+        }
+        Ok(())
+    }
+}
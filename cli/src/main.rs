@@ -5,9 +5,12 @@ This is the command line interface for setting up a TestSys Cluster and running
 !*/
 
 mod add_secret;
+mod archive;
+mod crd;
 mod delete;
 mod describe;
 mod install;
+mod junit;
 mod logs;
 mod restart;
 mod restart_test;
@@ -16,6 +19,7 @@ mod run;
 mod run_file;
 mod status;
 mod uninstall;
+mod validate;
 
 use anyhow::{Context, Result};
 use clap::Parser;
@@ -61,6 +65,14 @@ enum Command {
     Delete(delete::Delete),
     /// Get the YAML representation of testsys objects.
     Describe(describe::Describe),
+    /// Get a JUnit XML report of testsys tests.
+    Junit(junit::Junit),
+    /// Archive a test, tearing down its job and resources but keeping its history.
+    Archive(archive::Archive),
+    /// Print the YAML manifest of the testsys CRDs.
+    Crd(crd::Crd),
+    /// Check the tests in a file against the cluster without creating them.
+    Validate(validate::Validate),
 }
 
 #[tokio::main]
@@ -74,28 +86,39 @@ async fn main() {
 }
 
 async fn run(args: Args) -> Result<()> {
-    let client = match args.kubeconfig {
+    let kubeconfig = args.kubeconfig;
+    match args.command {
+        // The CRDs are generated from the model, they do not need a cluster.
+        Command::Crd(crd) => crd.run(),
+        Command::Install(install) => install.run(client(kubeconfig).await?).await,
+        Command::Uninstall(uninstall) => uninstall.run(client(kubeconfig).await?).await,
+        Command::Restart(restart) => restart.run(client(kubeconfig).await?).await,
+        Command::Run(run) => run.run(client(kubeconfig).await?).await,
+        Command::Logs(logs) => logs.run(client(kubeconfig).await?).await,
+        Command::AddSecret(add_secret) => add_secret.run(client(kubeconfig).await?).await,
+        Command::Status(status) => status.run(client(kubeconfig).await?).await,
+        Command::Results(results) => results.run(client(kubeconfig).await?).await,
+        Command::Delete(delete) => delete.run(client(kubeconfig).await?).await,
+        Command::Describe(describe) => describe.run(client(kubeconfig).await?).await,
+        Command::Junit(junit) => junit.run(client(kubeconfig).await?).await,
+        Command::Archive(archive) => archive.run(client(kubeconfig).await?).await,
+        Command::Validate(validate) => validate.run(client(kubeconfig).await?).await,
+    }
+}
+
+/// The `TestManager` for the cluster of the `kubeconfig` at the given path, or of the default
+/// kubeconfig.
+async fn client(kubeconfig: Option<PathBuf>) -> Result<TestManager> {
+    match kubeconfig {
         Some(path) => TestManager::new_from_kubeconfig_path(&path)
             .await
             .context(format!(
                 "Unable to create testsys client from path '{:?}'",
                 path
-            ))?,
+            )),
         None => TestManager::new()
             .await
-            .context("Unable to create default testsys client")?,
-    };
-    match args.command {
-        Command::Install(install) => install.run(client).await,
-        Command::Uninstall(uninstall) => uninstall.run(client).await,
-        Command::Restart(restart) => restart.run(client).await,
-        Command::Run(run) => run.run(client).await,
-        Command::Logs(logs) => logs.run(client).await,
-        Command::AddSecret(add_secret) => add_secret.run(client).await,
-        Command::Status(status) => status.run(client).await,
-        Command::Results(results) => results.run(client).await,
-        Command::Delete(delete) => delete.run(client).await,
-        Command::Describe(describe) => describe.run(client).await,
+            .context("Unable to create default testsys client"),
     }
 }
 
@@ -1,22 +1,53 @@
 use anyhow::{Context, Result};
 use clap::{value_parser, Parser};
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use testsys_model::clients::CrdClient;
 use testsys_model::test_manager::{read_manifest, TestManager};
+use testsys_model::{Crd, CrdExt, Test, TestUserState};
+
+/// How often the tests are checked while waiting for them to finish.
+const WAIT_INTERVAL: Duration = Duration::from_secs(10);
 
 /// Run a test stored in a YAML file at `path`.
+///
+/// With `--wait` the command waits for the file's tests to finish, prints a summary of their
+/// outcomes and exits with the code of the worst outcome:
+///
+/// - `0`: every test passed, or was quarantined.
+/// - `1`: the command itself failed, e.g. the tests could not be created.
+/// - `2`: a test agent finished without reporting any tests.
+/// - `3`: a test failed.
+/// - `4`: a test could not be run because its spec is invalid, the cluster is missing a capability
+///   it requires, or it was archived.
+/// - `5`: a resource of a test could not be created.
+/// - `6`: a test agent reported an error.
+/// - `7`: the tests did not finish before `--timeout`.
 #[derive(Debug, Parser)]
 pub(crate) struct RunFile {
     /// Path to test crd YAML file.
     #[clap(value_parser = value_parser!(PathBuf))]
     path: PathBuf,
+
+    /// Wait for the tests to finish and exit with a code that reflects their outcome.
+    #[clap(long)]
+    wait: bool,
+
+    /// Stop waiting after this many seconds. Waits until the tests finish if not given.
+    #[clap(long, requires = "wait")]
+    timeout: Option<u64>,
 }
 
 impl RunFile {
     pub(crate) async fn run(&self, client: TestManager) -> Result<()> {
         // Create the resource objects from its path.
         let crds = read_manifest(&self.path).context("Unable to read manifest")?;
+        let mut test_names = Vec::new();
         for crd in crds {
             let name = crd.name();
+            if let (Crd::Test(_), Some(name)) = (&crd, &name) {
+                test_names.push(name.clone());
+            }
             client
                 .create_object(crd)
                 .await
@@ -25,6 +56,133 @@ impl RunFile {
                 println!("Successfully added '{}'.", name);
             }
         }
+        if !self.wait {
+            return Ok(());
+        }
+        let exit_code = self.wait_for_tests(&client, &test_names).await?;
+        if exit_code != ExitCode::Passed {
+            std::process::exit(exit_code as i32);
+        }
         Ok(())
     }
+
+    /// Wait for the tests to reach a terminal state, print their outcomes and return the exit code
+    /// of the worst one.
+    async fn wait_for_tests(
+        &self,
+        client: &TestManager,
+        test_names: &[String],
+    ) -> Result<ExitCode> {
+        let test_client = client.test_client();
+        let deadline = self
+            .timeout
+            .map(|timeout| Instant::now() + Duration::from_secs(timeout));
+        let mut tests: Vec<Test>;
+        loop {
+            tests = Vec::new();
+            for name in test_names {
+                tests.push(
+                    test_client
+                        .get(name)
+                        .await
+                        .with_context(|| format!("Unable to get test '{}'", name))?,
+                );
+            }
+            if tests
+                .iter()
+                .all(|test| test.test_user_state().is_terminal())
+            {
+                break;
+            }
+            if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                print_summary(&tests);
+                return Ok(ExitCode::Timeout);
+            }
+            tokio::time::sleep(WAIT_INTERVAL).await;
+        }
+        print_summary(&tests);
+        Ok(tests
+            .iter()
+            .map(|test| ExitCode::from(test.test_user_state()))
+            .max()
+            .unwrap_or(ExitCode::Passed))
+    }
+}
+
+/// Print each test's state and the counts of its latest results.
+fn print_summary(tests: &[Test]) {
+    for test in tests {
+        let agent_status = test.agent_status();
+        match agent_status.results.last() {
+            Some(results) => println!(
+                "{}: {} ({} passed, {} failed, {} skipped)",
+                test.object_name(),
+                test.test_user_state(),
+                results.num_passed,
+                results.num_failed,
+                results.num_skipped
+            ),
+            None => println!("{}: {}", test.object_name(), test.test_user_state()),
+        }
+    }
+}
+
+/// The exit codes of `--wait`, see [`RunFile`]. Their order is the order of severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ExitCode {
+    Passed = 0,
+    NoTests = 2,
+    Failed = 3,
+    NotRun = 4,
+    ResourceError = 5,
+    Error = 6,
+    Timeout = 7,
+}
+
+impl From<TestUserState> for ExitCode {
+    fn from(state: TestUserState) -> Self {
+        match state {
+            TestUserState::Passed | TestUserState::Quarantined => Self::Passed,
+            TestUserState::NoTests => Self::NoTests,
+            TestUserState::Failed => Self::Failed,
+            TestUserState::InvalidSpec
+            | TestUserState::PreflightFailed
+            | TestUserState::Archived => Self::NotRun,
+            TestUserState::ResourceError => Self::ResourceError,
+            TestUserState::Error => Self::Error,
+            // Tests are only waited for until they are terminal, unfinished states time out.
+            TestUserState::Unknown
+            | TestUserState::Waiting
+            | TestUserState::Running
+            | TestUserState::Suspended
+            | TestUserState::Deleting => Self::Timeout,
+        }
+    }
+}
+
+#[test]
+fn terminal_states_map_to_exit_codes() {
+    let code = |state| ExitCode::from(state) as i32;
+    assert_eq!(code(TestUserState::Passed), 0);
+    assert_eq!(code(TestUserState::Quarantined), 0);
+    assert_eq!(code(TestUserState::NoTests), 2);
+    assert_eq!(code(TestUserState::Failed), 3);
+    assert_eq!(code(TestUserState::InvalidSpec), 4);
+    assert_eq!(code(TestUserState::PreflightFailed), 4);
+    assert_eq!(code(TestUserState::Archived), 4);
+    assert_eq!(code(TestUserState::ResourceError), 5);
+    assert_eq!(code(TestUserState::Error), 6);
+    assert_eq!(code(TestUserState::Running), 7);
+    // The worst outcome decides the exit code.
+    assert_eq!(
+        [
+            TestUserState::Passed,
+            TestUserState::Error,
+            TestUserState::Failed
+        ]
+        .into_iter()
+        .map(ExitCode::from)
+        .max(),
+        Some(ExitCode::Error)
+    );
 }
@@ -38,10 +38,40 @@ pub(crate) struct Status {
     /// Only include objects with the specified name
     #[clap(long)]
     name: Option<String>,
+
+    /// Include archived `Test`s
+    #[clap(long)]
+    include_archived: bool,
+
+    /// List every `Test` and `Resource` with the `Job`s and finalizers the controller manages for
+    /// it, and the controller's `Job`s that belong to no object
+    #[clap(
+        long,
+        conflicts_with_all = &["tests", "resources", "labels", "state", "name"]
+    )]
+    managed: bool,
 }
 
 impl Status {
     pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        if self.managed {
+            let managed = client
+                .managed()
+                .await
+                .context("Unable to get managed objects")?;
+            if self.json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&managed)
+                        .context("Could not create string from managed objects.")?
+                );
+            } else {
+                let (terminal_size::Width(width), _) =
+                    terminal_size::terminal_size().unwrap_or((Width(120), Height(0)));
+                println!("{:width$}", managed, width = width as usize);
+            }
+            return Ok(());
+        }
         let crd_type = match (self.tests, self.resources) {
             (true, false) => Some(CrdType::Test),
             (false, true) => Some(CrdType::Resource),
@@ -52,6 +82,7 @@ impl Status {
             labels: self.labels,
             name: self.name,
             state: self.state,
+            include_archived: self.include_archived,
         };
         let mut status = client
             .status(&selection_params)
@@ -0,0 +1,45 @@
+use anyhow::{Context, Error, Result};
+use clap::{value_parser, Parser};
+use std::path::PathBuf;
+use testsys_model::test_manager::{read_manifest, TestManager};
+use testsys_model::Crd;
+
+/// Check the tests in a YAML file against the cluster without creating them: that their specs are
+/// usable, that the secrets, service accounts, volumes, resources and tests they refer to exist,
+/// and that you are allowed to create tests. Resources and tests defined in the same file count as
+/// existing. All problems are reported at once.
+#[derive(Debug, Parser)]
+pub(crate) struct Validate {
+    /// Path to test crd YAML file.
+    #[clap(value_parser = value_parser!(PathBuf))]
+    path: PathBuf,
+}
+
+impl Validate {
+    pub(crate) async fn run(self, client: TestManager) -> Result<()> {
+        let crds = read_manifest(&self.path).context("Unable to read manifest")?;
+        let mut problem_count = 0;
+        for crd in &crds {
+            let test = match crd {
+                Crd::Test(test) => test,
+                Crd::Resource(_) => continue,
+            };
+            let name = crd.name().unwrap_or_default();
+            let problems = client
+                .validate_test(test, &crds)
+                .await
+                .with_context(|| format!("Unable to validate test '{}'", name))?;
+            if problems.is_empty() {
+                println!("{}: valid", name);
+            }
+            for problem in &problems {
+                println!("{}: {}", name, problem);
+            }
+            problem_count += problems.len();
+        }
+        if problem_count > 0 {
+            return Err(Error::msg(format!("Found {} problems", problem_count)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+use crate::api_server::{error_response, json_response};
+use crate::config::{AgentDefaults, ControllerConfig};
+use crate::error::Result;
+use anyhow::Context;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use json_patch::{AddOperation, Patch, PatchOperation};
+use kube::core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview};
+use kube::core::DynamicObject;
+use log::{debug, info, warn};
+use serde_json::Value;
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use testsys_model::{Agent, Test};
+use tokio::net::TcpListener;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+/// The address the mutating admission webhook should listen on. Returns `None` if the webhook is
+/// not enabled.
+pub(crate) fn webhook_address(config: &ControllerConfig) -> Option<SocketAddr> {
+    let address = config.webhook_address.as_ref()?;
+    match address.trim().parse() {
+        Ok(address) => Some(address),
+        Err(e) => {
+            warn!(
+                "Invalid webhook address '{}', the webhook will not be started: {}",
+                address, e
+            );
+            None
+        }
+    }
+}
+
+/// Serve the mutating admission webhook for tests on `address`, see [`mutate`]. The k8s API server
+/// only calls webhooks over TLS, so it is served with the certificate `tls.crt` and key `tls.key`
+/// in `tls_path`.
+///
+/// - `POST /mutate` answers an `AdmissionReview` for a test.
+pub(crate) async fn run_webhook_server(
+    address: SocketAddr,
+    tls_path: &Path,
+    agent_defaults: AgentDefaults,
+) -> Result<()> {
+    let acceptor = tls_acceptor(tls_path)?;
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Unable to bind the webhook to '{}'", address))?;
+    info!("Serving the mutating admission webhook on '{}'", address);
+    serve_webhook(listener, acceptor, agent_defaults).await;
+    Ok(())
+}
+
+/// Answer the webhook's requests on each connection that `listener` accepts.
+async fn serve_webhook(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    agent_defaults: AgentDefaults,
+) {
+    let agent_defaults = Arc::new(agent_defaults);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Unable to accept a webhook connection: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let agent_defaults = agent_defaults.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("Webhook TLS handshake failed: {}", e);
+                    return;
+                }
+            };
+            let service = service_fn(move |request| handle(agent_defaults.clone(), request));
+            if let Err(e) = Http::new().serve_connection(stream, service).await {
+                debug!("Webhook connection failed: {}", e);
+            }
+        });
+    }
+}
+
+/// The TLS acceptor for the PEM encoded certificate chain and key in `tls_path`.
+fn tls_acceptor(tls_path: &Path) -> Result<TlsAcceptor> {
+    let open = |name: &str| {
+        let path = tls_path.join(name);
+        File::open(&path)
+            .map(BufReader::new)
+            .with_context(|| format!("Unable to open '{}'", path.display()))
+    };
+    let certificates = rustls_pemfile::certs(&mut open("tls.crt")?)
+        .context("Unable to read the webhook certificate")?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::read_all(&mut open("tls.key")?)
+        .context("Unable to read the webhook key")?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .context("The webhook key file has no private key")?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .context("Unable to use the webhook certificate")?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+async fn handle(
+    agent_defaults: Arc<AgentDefaults>,
+    request: Request<Body>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    if (request.method(), request.uri().path()) != (&Method::POST, "/mutate") {
+        return Ok(error_response(
+            StatusCode::NOT_FOUND,
+            format!("Unknown path '{}'", request.uri().path()),
+        ));
+    }
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(e) => {
+            return Ok(error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Unable to read request: {}", e),
+            ))
+        }
+    };
+    Ok(match serde_json::from_slice(&body) {
+        Ok(review) => json_response(StatusCode::OK, &mutate(review, &agent_defaults)),
+        Err(e) => error_response(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid admission review: {}", e),
+        ),
+    })
+}
+
+/// Answer the `AdmissionReview` of a mutating admission webhook for tests. The response patches
+/// the controller's agent defaults into the agents of the test that do not set them, so the stored
+/// test shows the settings its agents actually run with. Tests are always admitted, even ones that
+/// cannot be read as a `Test`; the controller still validates them when they are reconciled.
+pub(crate) fn mutate(
+    review: AdmissionReview<DynamicObject>,
+    defaults: &AgentDefaults,
+) -> AdmissionReview<DynamicObject> {
+    let request: AdmissionRequest<DynamicObject> = match review.try_into() {
+        Ok(request) => request,
+        Err(e) => return AdmissionResponse::invalid(e.to_string()).into_review(),
+    };
+    let response = AdmissionResponse::from(&request);
+    let test = match request
+        .object
+        .as_ref()
+        .map(|object| serde_json::to_value(object).and_then(serde_json::from_value::<Test>))
+    {
+        Some(Ok(test)) => test,
+        Some(Err(e)) => {
+            warn!(
+                "Admitting test '{}' without defaults, it is not a valid test: {}",
+                request.name, e
+            );
+            return response.into_review();
+        }
+        None => return response.into_review(),
+    };
+    let patch = default_patch(&test, defaults);
+    if patch.0.is_empty() {
+        return response.into_review();
+    }
+    match response.clone().with_patch(patch) {
+        Ok(response) => response.into_review(),
+        Err(e) => {
+            warn!(
+                "Unable to patch the defaults into test '{}': {}",
+                request.name, e
+            );
+            response.into_review()
+        }
+    }
+}
+
+/// The JSON patch that sets `defaults` in each of the `test`'s agents that does not set them.
+pub(crate) fn default_patch(test: &Test, defaults: &AgentDefaults) -> Patch {
+    let agents = std::iter::once(("/spec/agent".to_string(), &test.spec.agent)).chain(
+        test.spec
+            .agents
+            .iter()
+            .enumerate()
+            .map(|(i, agent)| (format!("/spec/agents/{}", i), agent)),
+    );
+    Patch(
+        agents
+            .flat_map(|(path, agent)| agent_patch(&path, agent, defaults))
+            .collect(),
+    )
+}
+
+fn agent_patch(path: &str, agent: &Agent, defaults: &AgentDefaults) -> Vec<PatchOperation> {
+    let mut operations = Vec::new();
+    let mut add = |field: &str, value: Value| {
+        operations.push(PatchOperation::Add(AddOperation {
+            path: format!("{}/{}", path, field),
+            value,
+        }))
+    };
+    if let (None, Some(service_account)) = (&agent.service_account, &defaults.service_account) {
+        add("serviceAccount", Value::String(service_account.clone()));
+    }
+    if let (None, Some(container_resources)) =
+        (&agent.container_resources, &defaults.container_resources)
+    {
+        add("containerResources", serde_json::json!(container_resources));
+    }
+    operations
+}
+
+#[test]
+fn minimal_test_patch() {
+    use std::collections::BTreeMap;
+    use testsys_model::{ContainerResources, TestSpec};
+
+    let test = Test::new(
+        "minimal",
+        TestSpec {
+            agent: Agent {
+                name: "agent".to_string(),
+                image: "example.com/agent:v1".to_string(),
+                ..Agent::default()
+            },
+            agents: vec![Agent {
+                name: "second".to_string(),
+                image: "example.com/agent:v1".to_string(),
+                service_account: Some("custom".to_string()),
+                ..Agent::default()
+            }],
+            ..TestSpec::default()
+        },
+    );
+    let defaults = AgentDefaults {
+        service_account: Some("testsys-agent".to_string()),
+        container_resources: Some(ContainerResources {
+            limits: None,
+            requests: Some(BTreeMap::from([("cpu".to_string(), "500m".to_string())])),
+        }),
+    };
+
+    assert_eq!(
+        serde_json::json!(default_patch(&test, &defaults)),
+        serde_json::json!([
+            {
+                "op": "add",
+                "path": "/spec/agent/serviceAccount",
+                "value": "testsys-agent"
+            },
+            {
+                "op": "add",
+                "path": "/spec/agent/containerResources",
+                "value": { "limits": null, "requests": { "cpu": "500m" } }
+            },
+            {
+                "op": "add",
+                "path": "/spec/agents/0/containerResources",
+                "value": { "limits": null, "requests": { "cpu": "500m" } }
+            }
+        ])
+    );
+    // The patch applies cleanly to the test as it is sent to the webhook.
+    let mut patched = serde_json::json!(test);
+    assert!(json_patch::patch(&mut patched, &default_patch(&test, &defaults)).is_ok());
+    assert_eq!(
+        patched["spec"]["agent"]["serviceAccount"],
+        serde_json::json!("testsys-agent")
+    );
+    assert!(default_patch(&test, &AgentDefaults::default()).0.is_empty());
+}
+
+/// An `AdmissionReview` of the creation of `object`.
+#[cfg(test)]
+fn creation_review(object: Value) -> Value {
+    serde_json::json!({
+        "apiVersion": "admission.k8s.io/v1",
+        "kind": "AdmissionReview",
+        "request": {
+            "uid": "8e0e1b5c-4a4c-4d1f-9b0e-6c2f4b8b7f1a",
+            "kind": { "group": "testsys.system", "version": "v1", "kind": "Test" },
+            "resource": { "group": "testsys.system", "version": "v1", "resource": "tests" },
+            "name": "minimal",
+            "namespace": "testsys",
+            "operation": "CREATE",
+            "userInfo": { "username": "jane" },
+            "object": object,
+            "dryRun": false
+        }
+    })
+}
+
+#[test]
+fn tests_that_cannot_be_read_are_admitted() {
+    let defaults = AgentDefaults {
+        service_account: Some("testsys-agent".to_string()),
+        container_resources: None,
+    };
+    // The agent is missing its image.
+    let review = creation_review(serde_json::json!({
+        "apiVersion": "testsys.system/v1",
+        "kind": "Test",
+        "metadata": { "name": "minimal", "namespace": "testsys" },
+        "spec": { "agent": { "name": "agent" } }
+    }));
+    let response = serde_json::from_value(review)
+        .map(|review| serde_json::json!(mutate(review, &defaults)))
+        .unwrap_or_default();
+    assert_eq!(response["response"]["allowed"], serde_json::json!(true));
+    assert_eq!(response["response"]["patch"], Value::Null);
+}
+
+#[tokio::test]
+async fn webhook_is_served_over_tls() {
+    let host = "testsys-controller.testsys.svc";
+    let result = async {
+        let certificate = rcgen::generate_simple_self_signed(vec![host.to_string()])?;
+        let tls_path = std::env::temp_dir().join(format!("testsys-webhook-{}", std::process::id()));
+        std::fs::create_dir_all(&tls_path)?;
+        std::fs::write(tls_path.join("tls.crt"), certificate.serialize_pem()?)?;
+        std::fs::write(
+            tls_path.join("tls.key"),
+            certificate.serialize_private_key_pem(),
+        )?;
+        let acceptor = tls_acceptor(&tls_path)?;
+        std::fs::remove_dir_all(&tls_path)?;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let defaults = AgentDefaults {
+            service_account: Some("testsys-agent".to_string()),
+            container_resources: None,
+        };
+        tokio::spawn(serve_webhook(listener, acceptor, defaults));
+
+        let client = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(
+                certificate.serialize_pem()?.as_bytes(),
+            )?)
+            .resolve(host, address)
+            .build()?;
+        let test = serde_json::json!(Test::new(
+            "minimal",
+            testsys_model::TestSpec {
+                agent: Agent {
+                    name: "agent".to_string(),
+                    image: "example.com/agent:v1".to_string(),
+                    ..Agent::default()
+                },
+                ..Default::default()
+            },
+        ));
+        let response = client
+            .post(format!("https://{}:{}/mutate", host, address.port()))
+            .body(creation_review(test).to_string())
+            .send()
+            .await?
+            .text()
+            .await?;
+        Ok::<_, anyhow::Error>(serde_json::from_str::<Value>(&response)?)
+    }
+    .await;
+    // A failure shows up in the assertions.
+    let review = result.unwrap_or_else(|e| serde_json::json!({ "error": format!("{:?}", e) }));
+    assert_eq!(review["response"]["allowed"], serde_json::json!(true));
+    assert_eq!(
+        review["response"]["patchType"],
+        serde_json::json!("JSONPatch")
+    );
+}
@@ -0,0 +1,166 @@
+use crate::error::Result;
+use anyhow::Context;
+use k8s_openapi::api::authentication::v1::{TokenReview, TokenReviewSpec, UserInfo};
+use k8s_openapi::api::authorization::v1::{
+    NonResourceAttributes, ResourceAttributes, SubjectAccessReview, SubjectAccessReviewSpec,
+};
+use kube::api::PostParams;
+use kube::Api;
+use testsys_model::constants::{NAMESPACE, TESTSYS};
+
+/// What a caller of the API server needs to be allowed to do by k8s RBAC. Each endpoint of the
+/// API needs the access that doing the same with the `Test` CRD directly would need.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Access<'a> {
+    /// `verb` on tests, or on the test `name` if there is one.
+    Tests {
+        verb: &'a str,
+        name: Option<&'a str>,
+        subresource: Option<&'a str>,
+    },
+    /// `get` on a path that is not a k8s resource, e.g. `/metrics`.
+    Path(&'a str),
+}
+
+impl<'a> Access<'a> {
+    pub(crate) fn tests(verb: &'a str, name: Option<&'a str>) -> Self {
+        Self::Tests {
+            verb,
+            name,
+            subresource: None,
+        }
+    }
+
+    fn review_spec(self, user: &UserInfo) -> SubjectAccessReviewSpec {
+        let (resource_attributes, non_resource_attributes) = match self {
+            Access::Tests {
+                verb,
+                name,
+                subresource,
+            } => (
+                Some(ResourceAttributes {
+                    group: Some(TESTSYS.to_string()),
+                    namespace: Some(NAMESPACE.to_string()),
+                    resource: Some("tests".to_string()),
+                    subresource: subresource.map(str::to_string),
+                    name: name.map(str::to_string),
+                    verb: Some(verb.to_string()),
+                    ..ResourceAttributes::default()
+                }),
+                None,
+            ),
+            Access::Path(path) => (
+                None,
+                Some(NonResourceAttributes {
+                    path: Some(path.to_string()),
+                    verb: Some("get".to_string()),
+                }),
+            ),
+        };
+        SubjectAccessReviewSpec {
+            user: user.username.clone(),
+            groups: user.groups.clone(),
+            uid: user.uid.clone(),
+            extra: user.extra.clone(),
+            resource_attributes,
+            non_resource_attributes,
+        }
+    }
+}
+
+/// The user or ServiceAccount that `token` belongs to. Returns `None` if k8s does not accept the
+/// token.
+pub(crate) async fn authenticate(
+    k8s_client: kube::Client,
+    token: &str,
+) -> Result<Option<UserInfo>> {
+    let review = TokenReview {
+        spec: TokenReviewSpec {
+            token: Some(token.to_string()),
+            audiences: None,
+        },
+        ..TokenReview::default()
+    };
+    let review = Api::<TokenReview>::all(k8s_client)
+        .create(&PostParams::default(), &review)
+        .await
+        .context("Unable to review the caller's token")?;
+    Ok(review
+        .status
+        .filter(|status| status.authenticated == Some(true))
+        .and_then(|status| status.user))
+}
+
+/// Whether k8s RBAC allows `user` the `access`.
+pub(crate) async fn is_allowed(
+    k8s_client: kube::Client,
+    user: &UserInfo,
+    access: Access<'_>,
+) -> Result<bool> {
+    let review = SubjectAccessReview {
+        spec: access.review_spec(user),
+        ..SubjectAccessReview::default()
+    };
+    let review = Api::<SubjectAccessReview>::all(k8s_client)
+        .create(&PostParams::default(), &review)
+        .await
+        .context("Unable to review the caller's access")?;
+    Ok(review
+        .status
+        .map(|status| status.allowed && status.denied != Some(true))
+        .unwrap_or(false))
+}
+
+/// A fake k8s API server that authenticates every token as `user` and gives it the `allowed`
+/// access.
+#[cfg(test)]
+pub(crate) fn fake_auth_client(user: &str, allowed: bool) -> kube::Client {
+    crate::fake_api::fake_k8s_client(vec![
+        (
+            "/tokenreviews",
+            serde_json::json!({
+                "apiVersion": "authentication.k8s.io/v1",
+                "kind": "TokenReview",
+                "metadata": {},
+                "spec": {},
+                "status": { "authenticated": true, "user": { "username": user } }
+            }),
+        ),
+        (
+            "/subjectaccessreviews",
+            serde_json::json!({
+                "apiVersion": "authorization.k8s.io/v1",
+                "kind": "SubjectAccessReview",
+                "metadata": {},
+                "spec": {},
+                "status": { "allowed": allowed }
+            }),
+        ),
+    ])
+}
+
+#[test]
+fn test_access_review() {
+    let user = UserInfo {
+        username: Some("jane".to_string()),
+        groups: Some(vec!["qa".to_string()]),
+        ..UserInfo::default()
+    };
+    let spec = Access::tests("delete", Some("my-test")).review_spec(&user);
+    assert_eq!(spec.user.as_deref(), Some("jane"));
+    assert_eq!(spec.groups, Some(vec!["qa".to_string()]));
+    let attributes = spec.resource_attributes.unwrap_or_default();
+    assert_eq!(attributes.group.as_deref(), Some(TESTSYS));
+    assert_eq!(attributes.namespace.as_deref(), Some(NAMESPACE));
+    assert_eq!(attributes.resource.as_deref(), Some("tests"));
+    assert_eq!(attributes.name.as_deref(), Some("my-test"));
+    assert_eq!(attributes.verb.as_deref(), Some("delete"));
+
+    let spec = Access::Path("/metrics").review_spec(&user);
+    assert_eq!(spec.resource_attributes, None);
+    assert_eq!(
+        spec.non_resource_attributes
+            .and_then(|attributes| attributes.path),
+        Some("/metrics".to_string())
+    );
+}
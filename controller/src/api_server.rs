@@ -0,0 +1,738 @@
+use crate::api_auth::{authenticate, is_allowed, Access};
+use crate::config::ControllerConfig;
+use crate::error::Result;
+use crate::instance::Instance;
+use crate::version::{kube_server_version, CONTROLLER_VERSION};
+use anyhow::Context;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use testsys_model::clients::{CrdClient, HttpStatusCode, TestClient};
+use testsys_model::constants::LABEL_CONTROLLER_INSTANCE;
+use testsys_model::{create_test_crd, Test, TestSpec};
+
+/// The maximum length of a k8s object name.
+const MAX_NAME_LEN: usize = 253;
+
+/// The body of a request to create a test.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct CreateTestRequest {
+    /// The name of the test.
+    name: String,
+    /// Labels to add to the test.
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    /// The spec of the test, in the same form as the `Test` CRD's spec.
+    spec: TestSpec,
+}
+
+/// The body of a response to `GET /info`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InfoResponse {
+    controller_version: &'static str,
+    kube_server_version: Option<String>,
+}
+
+/// What the API server's endpoints need to handle requests.
+#[derive(Clone)]
+struct ApiContext {
+    test_client: TestClient,
+    /// Reviews the tokens and the access of the API's callers.
+    auth_client: kube::Client,
+    /// Tests claimed by other controller instances are hidden from the API.
+    instance: Instance,
+}
+
+/// The body of a response for a request that failed.
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// The address the API server should listen on. Returns `None` if the API server is not enabled.
+pub(crate) fn api_address(config: &ControllerConfig) -> Option<SocketAddr> {
+    let address = config.api_address.as_ref()?;
+    match address.trim().parse() {
+        Ok(address) => Some(address),
+        Err(e) => {
+            warn!(
+                "Invalid API address '{}', the API server will not be started: {}",
+                address, e
+            );
+            None
+        }
+    }
+}
+
+/// Serve the HTTP API for creating and querying TestSys tests on `address`. The API returns tests
+/// exactly as they are stored in the `Test` CRD.
+///
+/// - `GET /tests` lists all tests.
+/// - `POST /tests` creates a test from a [`CreateTestRequest`].
+/// - `GET /tests/<name>` gets a test.
+/// - `DELETE /tests/<name>` deletes a test.
+/// - `POST /tests/<name>/reconcile` asks the controller to reconcile a test right away.
+/// - `GET /info` reports the versions of the controller and the k8s API server.
+/// - `GET /metrics` reports the controller's metrics in the Prometheus text format.
+///
+/// Callers authenticate with the bearer token of a k8s user or ServiceAccount, and k8s RBAC
+/// decides what they may do, see [`required_access`]. Each endpoint needs the access to the `Test`
+/// CRD that doing the same with `kubectl` would need.
+///
+/// Tests claimed by other controller instances cannot be seen or changed through the API, and the
+/// tests it creates are claimed by this instance.
+pub(crate) async fn run_api_server(
+    k8s_client: kube::Client,
+    address: SocketAddr,
+    instance: Instance,
+) -> Result<()> {
+    let context = Arc::new(ApiContext {
+        test_client: TestClient::new_from_k8s_client(k8s_client.clone()),
+        auth_client: k8s_client,
+        instance,
+    });
+    let make_service = make_service_fn(move |_| {
+        let context = context.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(context.clone(), request))) }
+    });
+    let server = Server::try_bind(&address)
+        .with_context(|| format!("Unable to bind the API server to '{}'", address))?;
+    info!("Serving the TestSys API on '{}'", address);
+    server
+        .serve(make_service)
+        .await
+        .context("The API server stopped")
+}
+
+/// Route the `request` to the endpoint that handles it.
+async fn handle(
+    context: Arc<ApiContext>,
+    request: Request<Body>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let test_client = &context.test_client;
+    let method = request.method().clone();
+    let path = request.uri().path().trim_matches('/').to_string();
+    debug!("API request '{} /{}'", method, path);
+    let segments: Vec<&str> = path.split('/').collect();
+    if let Some(access) = required_access(&method, &segments) {
+        if let Err(response) = authorize(&context, &request, access).await {
+            return Ok(response);
+        }
+    }
+    let response = match (&method, segments.as_slice()) {
+        (&Method::GET, ["tests"]) => list_tests(&context).await,
+        (&Method::POST, ["tests"]) => create_test(&context, request.into_body()).await,
+        (&Method::GET, ["tests", name]) => get_test(&context, name).await,
+        (&Method::DELETE, ["tests", name]) => delete_test(&context, name).await,
+        (&Method::POST, ["tests", name, "reconcile"]) => reconcile_test(&context, name).await,
+        (&Method::GET, ["info"]) => info(test_client).await,
+        (&Method::GET, ["metrics"]) => metrics(),
+        (_, ["tests"] | ["tests", _] | ["tests", _, "reconcile"] | ["info"] | ["metrics"]) => {
+            error_response(
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("'{}' is not supported for '/{}'", method, path),
+            )
+        }
+        _ => error_response(StatusCode::NOT_FOUND, format!("Unknown path '/{}'", path)),
+    };
+    Ok(response)
+}
+
+/// The access that the caller of `method` on the path with `segments` needs, or `None` if anyone
+/// may call it.
+fn required_access<'a>(method: &Method, segments: &[&'a str]) -> Option<Access<'a>> {
+    match (method, segments) {
+        (&Method::GET, ["tests"]) => Some(Access::tests("list", None)),
+        (&Method::POST, ["tests"]) => Some(Access::tests("create", None)),
+        (&Method::GET, ["tests", name]) => Some(Access::tests("get", Some(name))),
+        (&Method::DELETE, ["tests", name]) => Some(Access::tests("delete", Some(name))),
+        // Requesting a reconciliation annotates the test.
+        (&Method::POST, ["tests", name, "reconcile"]) => Some(Access::tests("patch", Some(name))),
+        (&Method::GET, ["info"]) => Some(Access::Path("/info")),
+        (&Method::GET, ["metrics"]) => Some(Access::Path("/metrics")),
+        _ => None,
+    }
+}
+
+/// Check that the caller of `request` authenticated and is allowed the `access`. Returns the
+/// response to send instead if they are not.
+async fn authorize(
+    context: &ApiContext,
+    request: &Request<Body>,
+    access: Access<'_>,
+) -> std::result::Result<(), Response<Body>> {
+    let token = request
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| error_response(StatusCode::UNAUTHORIZED, "A bearer token is required"))?;
+    let user = match authenticate(context.auth_client.clone(), token).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(error_response(
+                StatusCode::UNAUTHORIZED,
+                "The bearer token is not valid",
+            ))
+        }
+        Err(e) => {
+            return Err(error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("{:?}", e),
+            ))
+        }
+    };
+    match is_allowed(context.auth_client.clone(), &user, access).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(error_response(
+            StatusCode::FORBIDDEN,
+            format!(
+                "'{}' is not allowed {:?}",
+                user.username.as_deref().unwrap_or_default(),
+                access
+            ),
+        )),
+        Err(e) => Err(error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("{:?}", e),
+        )),
+    }
+}
+
+async fn info(test_client: &TestClient) -> Response<Body> {
+    let k8s_client = test_client.api().clone().into_client();
+    json_response(
+        StatusCode::OK,
+        &InfoResponse {
+            controller_version: CONTROLLER_VERSION,
+            kube_server_version: kube_server_version(&k8s_client).await,
+        },
+    )
+}
+
+fn metrics() -> Response<Body> {
+    let mut response = Response::new(Body::from(crate::metrics::render()));
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("text/plain; version=0.0.4"),
+    );
+    response
+}
+
+async fn list_tests(context: &ApiContext) -> Response<Body> {
+    match context.test_client.get_all().await {
+        Ok(tests) => {
+            let tests: Vec<Test> = tests
+                .into_iter()
+                .filter(|test| context.instance.other_owner(test).is_none())
+                .collect();
+            json_response(StatusCode::OK, &tests)
+        }
+        Err(e) => client_error_response(e),
+    }
+}
+
+/// Get the test `name`, which is not found if another controller instance has claimed it.
+async fn visible_test(
+    context: &ApiContext,
+    name: &str,
+) -> std::result::Result<Test, Response<Body>> {
+    let test = context
+        .test_client
+        .get(name)
+        .await
+        .map_err(client_error_response)?;
+    match context.instance.other_owner(&test) {
+        Some(_) => Err(error_response(
+            StatusCode::NOT_FOUND,
+            format!("Test '{}' not found", name),
+        )),
+        None => Ok(test),
+    }
+}
+
+async fn get_test(context: &ApiContext, name: &str) -> Response<Body> {
+    match visible_test(context, name).await {
+        Ok(test) => json_response(StatusCode::OK, &test),
+        Err(response) => response,
+    }
+}
+
+async fn create_test(context: &ApiContext, body: Body) -> Response<Body> {
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                format!("Unable to read request: {}", e),
+            )
+        }
+    };
+    let request: CreateTestRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return error_response(StatusCode::BAD_REQUEST, format!("Invalid test: {}", e));
+        }
+    };
+    if let Err(e) = validate(&request) {
+        return error_response(StatusCode::BAD_REQUEST, format!("Invalid test: {}", e));
+    }
+    let mut labels = request.labels;
+    if let Some(instance_id) = context.instance.id() {
+        labels.insert(
+            LABEL_CONTROLLER_INSTANCE.to_string(),
+            instance_id.to_string(),
+        );
+    }
+    let test = create_test_crd(request.name, Some(&labels), request.spec);
+    match context.test_client.create(test).await {
+        Ok(test) => json_response(StatusCode::CREATED, &test),
+        Err(e) => client_error_response(e),
+    }
+}
+
+async fn delete_test(context: &ApiContext, name: &str) -> Response<Body> {
+    if let Err(response) = visible_test(context, name).await {
+        return response;
+    }
+    match context.test_client.delete(name).await {
+        // The test still exists until the controller has removed its finalizers.
+        Ok(Some(test)) => json_response(StatusCode::ACCEPTED, &test),
+        Ok(None) => empty_response(StatusCode::NO_CONTENT),
+        Err(e) => client_error_response(e),
+    }
+}
+
+/// Ask the controller to reconcile the test now, e.g. while debugging it, instead of waiting for
+/// its next requeue.
+async fn reconcile_test(context: &ApiContext, name: &str) -> Response<Body> {
+    if let Err(response) = visible_test(context, name).await {
+        return response;
+    }
+    match context.test_client.request_reconcile(name).await {
+        Ok(test) => json_response(StatusCode::ACCEPTED, &test),
+        Err(e) => client_error_response(e),
+    }
+}
+
+/// Check the parts of a test that k8s cannot check for us.
+fn validate(request: &CreateTestRequest) -> std::result::Result<(), String> {
+    let name = &request.name;
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!(
+            "the name must be between 1 and {} characters",
+            MAX_NAME_LEN
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+        || !name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        || !name.ends_with(|c: char| c.is_ascii_alphanumeric())
+    {
+        return Err(format!(
+            "the name '{}' must consist of lower case alphanumeric characters, '-' or '.', and \
+            must start and end with an alphanumeric character",
+            name
+        ));
+    }
+    if request.spec.agent.image.is_empty() {
+        return Err("the agent image is required".to_string());
+    }
+    Ok(())
+}
+
+pub(crate) fn json_response<T>(status: StatusCode, value: &T) -> Response<Body>
+where
+    T: Serialize,
+{
+    match serde_json::to_vec(value) {
+        Ok(body) => {
+            let mut response = Response::new(Body::from(body));
+            *response.status_mut() = status;
+            response.headers_mut().insert(
+                hyper::header::CONTENT_TYPE,
+                hyper::header::HeaderValue::from_static("application/json"),
+            );
+            response
+        }
+        Err(e) => error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unable to serialize response: {}", e),
+        ),
+    }
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+pub(crate) fn error_response<S>(status: StatusCode, error: S) -> Response<Body>
+where
+    S: Into<String>,
+{
+    let body = ErrorResponse {
+        error: error.into(),
+    };
+    let mut response = Response::new(Body::from(
+        serde_json::to_vec(&body).unwrap_or_else(|_| b"{}".to_vec()),
+    ));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// Respond with the status code of the k8s API call that failed, if there was one.
+fn client_error_response(e: testsys_model::clients::Error) -> Response<Body> {
+    let status = e.status_code().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    error_response(status, e.to_string())
+}
+
+/// The context of an API server that uses `test_client` and allows every caller.
+#[cfg(test)]
+fn api_context(test_client: &TestClient) -> Arc<ApiContext> {
+    Arc::new(ApiContext {
+        test_client: test_client.clone(),
+        auth_client: crate::api_auth::fake_auth_client("jane", true),
+        instance: Instance::default(),
+    })
+}
+
+/// Send a request to the API and return the status code and JSON body of the response. The body
+/// is `null` if the response was empty.
+#[cfg(test)]
+async fn call(
+    test_client: &TestClient,
+    method: Method,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    call_as(api_context(test_client), Some("token"), method, path, body).await
+}
+
+/// Send a request with the bearer `token`, if any, to the API with `context`.
+#[cfg(test)]
+async fn call_as(
+    context: Arc<ApiContext>,
+    token: Option<&str>,
+    method: Method,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let mut request = Request::builder().method(method).uri(path);
+    if let Some(token) = token {
+        request = request.header(hyper::header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let request = request.body(
+        body.map(|body| Body::from(body.to_string()))
+            .unwrap_or_default(),
+    );
+    let response = match request {
+        Ok(request) => match handle(context, request).await {
+            Ok(response) => response,
+            Err(infallible) => match infallible {},
+        },
+        Err(e) => return (StatusCode::BAD_REQUEST, serde_json::json!(e.to_string())),
+    };
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .ok()
+        .and_then(|body| serde_json::from_slice(&body).ok())
+        .unwrap_or_default();
+    (status, body)
+}
+
+#[cfg(test)]
+fn create_test_request(name: &str, image: &str) -> serde_json::Value {
+    serde_json::json!({
+        "name": name,
+        "labels": { "team": "portal" },
+        "spec": {
+            "resources": [],
+            "agent": {
+                "name": "sonobuoy",
+                "image": image,
+                "keepRunning": false,
+            }
+        }
+    })
+}
+
+#[tokio::test]
+async fn create_get_list_and_delete_tests() {
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+
+    let (status, test) = call(
+        &test_client,
+        Method::POST,
+        "/tests",
+        Some(create_test_request("my-test", "example.com/sonobuoy:v1")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(test["metadata"]["name"], "my-test");
+    assert_eq!(test["metadata"]["labels"]["team"], "portal");
+
+    let (status, test) = call(&test_client, Method::GET, "/tests/my-test", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(test["spec"]["agent"]["image"], "example.com/sonobuoy:v1");
+
+    let (status, tests) = call(&test_client, Method::GET, "/tests", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tests.as_array().map(Vec::len), Some(1));
+    assert_eq!(tests[0]["metadata"]["name"], "my-test");
+
+    // The same test cannot be created twice.
+    let (status, _) = call(
+        &test_client,
+        Method::POST,
+        "/tests",
+        Some(create_test_request("my-test", "example.com/sonobuoy:v1")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = call(&test_client, Method::DELETE, "/tests/my-test", None).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, error) = call(&test_client, Method::GET, "/tests/my-test", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(error["error"].is_string());
+}
+
+#[tokio::test]
+async fn tests_of_other_instances_are_hidden() {
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+    let instance = |id: &str| {
+        Instance::new(&crate::config::ControllerConfig {
+            instance_id: Some(id.to_string()),
+            ..Default::default()
+        })
+    };
+    let as_instance = |id: &str| {
+        Arc::new(ApiContext {
+            instance: instance(id),
+            ..(*api_context(&test_client)).clone()
+        })
+    };
+    let (status, test) = call_as(
+        as_instance("blue"),
+        Some("token"),
+        Method::POST,
+        "/tests",
+        Some(create_test_request("blue-test", "example.com/sonobuoy:v1")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(
+        test["metadata"]["labels"][LABEL_CONTROLLER_INSTANCE],
+        serde_json::json!("blue")
+    );
+
+    let green = |method, path| call_as(as_instance("green"), Some("token"), method, path, None);
+    let (_, tests) = green(Method::GET, "/tests").await;
+    assert_eq!(tests.as_array().map(Vec::len), Some(0));
+    let (status, _) = green(Method::GET, "/tests/blue-test").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = green(Method::POST, "/tests/blue-test/reconcile").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = green(Method::DELETE, "/tests/blue-test").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // The test is still there for the instance that claimed it.
+    let (status, _) = call(&test_client, Method::GET, "/tests/blue-test", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn create_invalid_test() {
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+    for request in [
+        create_test_request("my-test", ""),
+        create_test_request("My_Test", "example.com/sonobuoy:v1"),
+        serde_json::json!({ "name": "my-test" }),
+    ] {
+        let (status, error) = call(&test_client, Method::POST, "/tests", Some(request)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(error["error"]
+            .as_str()
+            .map(|error| error.starts_with("Invalid test: "))
+            .unwrap_or(false));
+    }
+    // Nothing was created.
+    let (_, tests) = call(&test_client, Method::GET, "/tests", None).await;
+    assert_eq!(tests.as_array().map(Vec::len), Some(0));
+}
+
+#[tokio::test]
+async fn reconcile_requested_test() {
+    use testsys_model::constants::ANNOTATION_RECONCILE_REQUESTED;
+
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+    for name in ["my-test", "other-test"] {
+        let (status, _) = call(
+            &test_client,
+            Method::POST,
+            "/tests",
+            Some(create_test_request(name, "example.com/sonobuoy:v1")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (status, test) = call(&test_client, Method::POST, "/tests/my-test/reconcile", None).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    // Changing the test is what makes the controller's watch reconcile it.
+    assert!(test["metadata"]["annotations"][ANNOTATION_RECONCILE_REQUESTED].is_string());
+    let (_, other_test) = call(&test_client, Method::GET, "/tests/other-test", None).await;
+    assert!(other_test["metadata"]["annotations"][ANNOTATION_RECONCILE_REQUESTED].is_null());
+
+    let (status, _) = call(&test_client, Method::POST, "/tests/no-test/reconcile", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn callers_must_authenticate() {
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+    for path in ["/tests", "/tests/my-test", "/info", "/metrics"] {
+        let (status, _) = call_as(api_context(&test_client), None, Method::GET, path, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", path);
+    }
+    let (status, _) = call_as(
+        api_context(&test_client),
+        Some(""),
+        Method::POST,
+        "/tests",
+        Some(create_test_request("my-test", "example.com/sonobuoy:v1")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // Nothing was created.
+    let (_, tests) = call(&test_client, Method::GET, "/tests", None).await;
+    assert_eq!(tests.as_array().map(Vec::len), Some(0));
+}
+
+#[tokio::test]
+async fn callers_must_be_allowed_by_rbac() {
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+    let forbidden = || {
+        Arc::new(ApiContext {
+            auth_client: crate::api_auth::fake_auth_client("jane", false),
+            ..(*api_context(&test_client)).clone()
+        })
+    };
+    let (status, error) = call_as(
+        forbidden(),
+        Some("token"),
+        Method::POST,
+        "/tests",
+        Some(create_test_request("my-test", "example.com/sonobuoy:v1")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(error["error"]
+        .as_str()
+        .map(|error| error.starts_with("'jane' is not allowed"))
+        .unwrap_or(false));
+    let (status, _) = call_as(forbidden(), Some("token"), Method::GET, "/tests", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (_, tests) = call(&test_client, Method::GET, "/tests", None).await;
+    assert_eq!(tests.as_array().map(Vec::len), Some(0));
+}
+
+#[test]
+fn reconcile_is_authorized_as_a_patch_of_the_test() {
+    assert_eq!(
+        required_access(&Method::POST, &["tests", "my-test", "reconcile"]),
+        Some(Access::tests("patch", Some("my-test")))
+    );
+    // Patching one test does not allow reconciling another.
+    assert_ne!(
+        required_access(&Method::POST, &["tests", "other-test", "reconcile"]),
+        Some(Access::tests("patch", Some("my-test")))
+    );
+}
+
+#[tokio::test]
+async fn reconcile_needs_patch_access() {
+    use testsys_model::constants::ANNOTATION_RECONCILE_REQUESTED;
+
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+    let (status, _) = call(
+        &test_client,
+        Method::POST,
+        "/tests",
+        Some(create_test_request("my-test", "example.com/sonobuoy:v1")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let forbidden = Arc::new(ApiContext {
+        auth_client: crate::api_auth::fake_auth_client("jane", false),
+        ..(*api_context(&test_client)).clone()
+    });
+    let (status, _) = call_as(
+        forbidden,
+        Some("token"),
+        Method::POST,
+        "/tests/my-test/reconcile",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call_as(
+        api_context(&test_client),
+        None,
+        Method::POST,
+        "/tests/my-test/reconcile",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (_, test) = call(&test_client, Method::GET, "/tests/my-test", None).await;
+    assert!(test["metadata"]["annotations"][ANNOTATION_RECONCILE_REQUESTED].is_null());
+}
+
+#[tokio::test]
+async fn unknown_paths() {
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![]));
+    let (status, _) = call(&test_client, Method::GET, "/resources", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = call(&test_client, Method::PUT, "/tests/my-test", None).await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn info_reports_versions() {
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_client(vec![(
+        "/version",
+        serde_json::json!({
+            "major": "1",
+            "minor": "24",
+            "gitVersion": "v1.24.10",
+            "gitCommit": "",
+            "gitTreeState": "clean",
+            "buildDate": "",
+            "goVersion": "",
+            "compiler": "gc",
+            "platform": "linux/amd64"
+        }),
+    )]));
+    let (status, info) = call(&test_client, Method::GET, "/info", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(info["controllerVersion"], CONTROLLER_VERSION);
+    assert_eq!(info["kubeServerVersion"], "v1.24.10");
+}
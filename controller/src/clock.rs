@@ -0,0 +1,53 @@
+use k8s_openapi::chrono::{DateTime, Utc};
+
+/// The source of the current time for the controller's time-based decisions, e.g. whether an agent
+/// has run past its timeout or a finished test has outlived the retention window. Tests use a
+/// [`FakeClock`] to decide what time it is.
+pub(crate) trait Clock: std::fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+impl<C: Clock + ?Sized> Clock for std::sync::Arc<C> {
+    fn now(&self) -> DateTime<Utc> {
+        self.as_ref().now()
+    }
+}
+
+/// The system's wall clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that stands still until it is moved.
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct FakeClock(std::sync::Mutex<DateTime<Utc>>);
+
+#[cfg(test)]
+impl FakeClock {
+    pub(crate) fn new(now: DateTime<Utc>) -> Self {
+        Self(std::sync::Mutex::new(now))
+    }
+
+    /// Move the clock forward by `duration`.
+    pub(crate) fn advance(&self, duration: k8s_openapi::chrono::Duration) {
+        if let Ok(mut now) = self.0.lock() {
+            *now += duration;
+        }
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        match self.0.lock() {
+            Ok(now) => *now,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+}
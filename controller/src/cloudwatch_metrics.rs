@@ -0,0 +1,335 @@
+/*!
+
+Publishes CloudWatch metrics for tests that reach a terminal state. The metrics are written to
+CloudWatch Logs in the [embedded metric format], which CloudWatch extracts them from, so that no
+client other than the one used to archive logs is needed. The log group defaults to
+`testsys-metrics` and can be configured with `cloudwatchMetricsLogGroup`. Credentials come from the default AWS
+provider chain, e.g. IRSA for the controller's service account.
+
+[embedded metric format]: https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/CloudWatch_Embedded_Metric_Format_Specification.html
+
+!*/
+
+use crate::error::Result;
+use anyhow::Context;
+use aws_sdk_cloudwatchlogs::model::InputLogEvent;
+use futures::future::BoxFuture;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::ResourceExt;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use testsys_model::{Test, TestUserState};
+use tokio::sync::OnceCell;
+
+/// The log group that the metric log events are written to unless another one is configured.
+pub(crate) const DEFAULT_METRICS_LOG_GROUP: &str = "testsys-metrics";
+
+/// The log stream in the metrics log group that the metric log events are written to.
+const METRICS_LOG_STREAM: &str = "controller";
+
+/// Writes log events in the embedded metric format to CloudWatch Logs.
+pub(crate) trait EmfClient: Send + Sync {
+    fn put_log_event(&self, timestamp: DateTime<Utc>, message: String)
+        -> BoxFuture<'_, Result<()>>;
+}
+
+/// Publishes the metrics of finished tests under a CloudWatch `namespace`.
+#[derive(Clone)]
+pub(crate) struct CloudWatchMetrics {
+    namespace: String,
+    client: Arc<dyn EmfClient>,
+}
+
+impl CloudWatchMetrics {
+    /// Publish metrics under `namespace` to the CloudWatch Logs group `log_group`, with the
+    /// controller's AWS credentials.
+    pub(crate) fn new<S1, S2>(namespace: S1, log_group: S2) -> Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        Self::with_client(
+            namespace,
+            Arc::new(CloudWatchLogsClient::new(log_group.into())),
+        )
+    }
+
+    pub(crate) fn with_client<S: Into<String>>(namespace: S, client: Arc<dyn EmfClient>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            client,
+        }
+    }
+
+    /// Publish the metrics of a `test` that finished at `finished_at`.
+    pub(crate) async fn publish(&self, test: &Test, finished_at: DateTime<Utc>) -> Result<()> {
+        let message = test_metrics(&self.namespace, test, finished_at).to_string();
+        self.client.put_log_event(finished_at, message).await
+    }
+}
+
+/// A metric of a finished test.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MetricDatum {
+    pub(crate) name: &'static str,
+    pub(crate) value: f64,
+    pub(crate) unit: &'static str,
+}
+
+/// The metrics of a finished `test`: whether it passed or failed, how long it took from its
+/// creation until `finished_at`, and how many of its test cases passed, failed and were skipped if
+/// its agent reported results.
+pub(crate) fn metric_data(test: &Test, finished_at: DateTime<Utc>) -> Vec<MetricDatum> {
+    let count = |name, value: u64| MetricDatum {
+        name,
+        value: value as f64,
+        unit: "Count",
+    };
+    let passed = test.test_user_state() == TestUserState::Passed;
+    let mut data = vec![
+        count("Passed", passed as u64),
+        count("Failed", !passed as u64),
+    ];
+    if let Some(created) = test.creation_timestamp() {
+        data.push(MetricDatum {
+            name: "Duration",
+            value: (finished_at - created.0).num_milliseconds().max(0) as f64 / 1000.0,
+            unit: "Seconds",
+        });
+    }
+    if let Some(results) = test.agent_status().results.last() {
+        data.push(count("TestCasesPassed", results.num_passed));
+        data.push(count("TestCasesFailed", results.num_failed));
+        data.push(count("TestCasesSkipped", results.num_skipped));
+    }
+    data
+}
+
+/// The embedded metric format document with the metrics of a finished `test`, dimensioned by the
+/// test's name and the name of its agent.
+pub(crate) fn test_metrics(namespace: &str, test: &Test, finished_at: DateTime<Utc>) -> Value {
+    let data = metric_data(test, finished_at);
+    let mut document = Map::new();
+    document.insert(
+        "_aws".to_string(),
+        json!({
+            "Timestamp": finished_at.timestamp_millis(),
+            "CloudWatchMetrics": [{
+                "Namespace": namespace,
+                "Dimensions": [["Agent", "Test"]],
+                "Metrics": data
+                    .iter()
+                    .map(|datum| json!({ "Name": datum.name, "Unit": datum.unit }))
+                    .collect::<Vec<_>>(),
+            }],
+        }),
+    );
+    document.insert("Agent".to_string(), json!(test.spec.agent.name));
+    document.insert("Test".to_string(), json!(test.name_any()));
+    for datum in data {
+        document.insert(datum.name.to_string(), json!(datum.value));
+    }
+    Value::Object(document)
+}
+
+/// Writes the metric log events to `log_group` with the CloudWatch Logs API. The client is built
+/// once, and the log group and stream are created, if they do not exist yet, before the first log
+/// event is put.
+struct CloudWatchLogsClient {
+    log_group: String,
+    client: OnceCell<aws_sdk_cloudwatchlogs::Client>,
+    log_stream_created: OnceCell<()>,
+}
+
+impl CloudWatchLogsClient {
+    fn new(log_group: String) -> Self {
+        Self {
+            log_group,
+            client: OnceCell::new(),
+            log_stream_created: OnceCell::new(),
+        }
+    }
+
+    async fn client(&self) -> &aws_sdk_cloudwatchlogs::Client {
+        self.client
+            .get_or_init(|| async {
+                aws_sdk_cloudwatchlogs::Client::new(&aws_config::from_env().load().await)
+            })
+            .await
+    }
+
+    /// Create the log group and stream unless they were created, or found to exist, before.
+    async fn create_log_stream(&self) -> Result<()> {
+        self.log_stream_created
+            .get_or_try_init(|| async {
+                let client = self.client().await;
+                if let Err(e) = client
+                    .create_log_group()
+                    .log_group_name(&self.log_group)
+                    .send()
+                    .await
+                {
+                    let e = e.into_service_error();
+                    if !e.is_resource_already_exists_exception() {
+                        return Err(e).with_context(|| {
+                            format!(
+                                "Unable to create the metrics log group '{}'",
+                                self.log_group
+                            )
+                        });
+                    }
+                }
+                if let Err(e) = client
+                    .create_log_stream()
+                    .log_group_name(&self.log_group)
+                    .log_stream_name(METRICS_LOG_STREAM)
+                    .send()
+                    .await
+                {
+                    let e = e.into_service_error();
+                    if !e.is_resource_already_exists_exception() {
+                        return Err(e).context("Unable to create the metrics log stream");
+                    }
+                }
+                Ok(())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+impl EmfClient for CloudWatchLogsClient {
+    fn put_log_event(
+        &self,
+        timestamp: DateTime<Utc>,
+        message: String,
+    ) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            self.create_log_stream().await?;
+            self.client()
+                .await
+                .put_log_events()
+                .log_group_name(&self.log_group)
+                .log_stream_name(METRICS_LOG_STREAM)
+                .log_events(
+                    InputLogEvent::builder()
+                        .message(message)
+                        .timestamp(timestamp.timestamp_millis())
+                        .build(),
+                )
+                .send()
+                .await
+                .context("Unable to put the metrics log event")?;
+            Ok(())
+        })
+    }
+}
+
+/// Records the log events it is asked to put instead of sending them.
+#[cfg(test)]
+#[derive(Default)]
+struct RecordingClient {
+    messages: std::sync::Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl EmfClient for RecordingClient {
+    fn put_log_event(&self, _: DateTime<Utc>, message: String) -> BoxFuture<'_, Result<()>> {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.push(message);
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[tokio::test]
+async fn passed_test_metrics() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::Duration;
+    use testsys_model::{AgentStatus, Outcome, TaskState, TestResults, TestStatus};
+
+    let created = Utc::now() - Duration::seconds(90);
+    let mut test = Test::default();
+    test.metadata.name = Some("my-test".to_string());
+    test.metadata.creation_timestamp = Some(Time(created));
+    test.spec.agent.name = "sonobuoy".to_string();
+    test.status = Some(TestStatus {
+        agent: AgentStatus {
+            task_state: TaskState::Completed,
+            results: vec![TestResults {
+                outcome: Outcome::Pass,
+                num_passed: 40,
+                num_failed: 0,
+                num_skipped: 2,
+                other_info: None,
+            }],
+            ..AgentStatus::default()
+        },
+        ..TestStatus::default()
+    });
+
+    let client = Arc::new(RecordingClient::default());
+    let metrics = CloudWatchMetrics::with_client("TestSys", client.clone());
+    assert!(metrics
+        .publish(&test, created + Duration::seconds(90))
+        .await
+        .is_ok());
+    let messages = client
+        .messages
+        .lock()
+        .map(|messages| messages.clone())
+        .unwrap_or_default();
+    assert_eq!(messages.len(), 1);
+    let document: Value = messages
+        .first()
+        .and_then(|message| serde_json::from_str(message).ok())
+        .unwrap_or_default();
+
+    let metrics = &document["_aws"]["CloudWatchMetrics"][0];
+    assert_eq!(metrics["Namespace"], "TestSys");
+    assert_eq!(metrics["Dimensions"], json!([["Agent", "Test"]]));
+    assert_eq!(
+        metrics["Metrics"][2],
+        json!({ "Name": "Duration", "Unit": "Seconds" })
+    );
+    assert_eq!(document["Agent"], "sonobuoy");
+    assert_eq!(document["Test"], "my-test");
+    assert_eq!(document["Passed"], 1.0);
+    assert_eq!(document["Failed"], 0.0);
+    assert_eq!(document["Duration"], 90.0);
+    assert_eq!(document["TestCasesPassed"], 40.0);
+    assert_eq!(document["TestCasesSkipped"], 2.0);
+}
+
+#[test]
+fn errored_test_metrics() {
+    use testsys_model::{AgentStatus, TaskState, TestStatus};
+
+    let test = Test {
+        status: Some(TestStatus {
+            agent: AgentStatus {
+                task_state: TaskState::Error,
+                error: Some("The job failed".to_string()),
+                ..AgentStatus::default()
+            },
+            ..TestStatus::default()
+        }),
+        ..Test::default()
+    };
+    // Without a creation time or results only the outcome is known.
+    assert_eq!(
+        metric_data(&test, Utc::now()),
+        vec![
+            MetricDatum {
+                name: "Passed",
+                value: 0.0,
+                unit: "Count"
+            },
+            MetricDatum {
+                name: "Failed",
+                value: 1.0,
+                unit: "Count"
+            },
+        ]
+    );
+}
@@ -0,0 +1,545 @@
+use crate::error::Result;
+use anyhow::Context;
+use clap::{Args, Parser};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use testsys_model::system::{
+    TESTSYS_CONTROLLER_ALLOWED_IMAGES, TESTSYS_CONTROLLER_ANNOTATE_INPUT_HASH,
+    TESTSYS_CONTROLLER_API_ADDRESS, TESTSYS_CONTROLLER_ARCHIVE_LOGS,
+    TESTSYS_CONTROLLER_CAPACITY_TYPE_LABEL, TESTSYS_CONTROLLER_CA_BUNDLE,
+    TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_LOG_GROUP,
+    TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_NAMESPACE, TESTSYS_CONTROLLER_ENDPOINT_WAIT_IMAGE,
+    TESTSYS_CONTROLLER_IMAGE_PULL_GRACE_PERIOD, TESTSYS_CONTROLLER_INSTALL_CRDS,
+    TESTSYS_CONTROLLER_INSTANCE_ID, TESTSYS_CONTROLLER_KUBE_BURST, TESTSYS_CONTROLLER_KUBE_QPS,
+    TESTSYS_CONTROLLER_LABEL_SELECTOR, TESTSYS_CONTROLLER_LOG_SINK,
+    TESTSYS_CONTROLLER_MAX_RESOURCES_PER_TEST, TESTSYS_CONTROLLER_MAX_STATUS_FIELD_LEN,
+    TESTSYS_CONTROLLER_MAX_TIMELINE_ENTRIES, TESTSYS_CONTROLLER_MAX_TIMEOUT_EXTENSION,
+    TESTSYS_CONTROLLER_OBSERVE_ONLY, TESTSYS_CONTROLLER_PROTECTED_LABELS,
+    TESTSYS_CONTROLLER_QUARANTINE, TESTSYS_CONTROLLER_RESULTS_ADDRESS,
+    TESTSYS_CONTROLLER_TEST_RETENTION, TESTSYS_CONTROLLER_WEBHOOK_ADDRESS,
+};
+use testsys_model::ContainerResources;
+
+/// The controller's settings. They are read from the YAML file given with `--config`, and each of
+/// them can be overridden by its environment variable, which can in turn be overridden by its
+/// command line flag.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct ControllerConfig {
+    /// Archive the logs of agent pods to CloudWatch before their jobs are deleted.
+    pub(crate) archive_logs: bool,
+    /// Forward the logs of running test agents to this sink, either `stdout` or an HTTP endpoint.
+    pub(crate) log_sink: Option<String>,
+    /// Serve the controller's HTTP API for tests on this address.
+    pub(crate) api_address: Option<String>,
+    /// Serve the gRPC endpoint that test agents stream their results to on this address. The
+    /// agents reach it through the `testsys-controller` service.
+    pub(crate) results_address: Option<String>,
+    /// Serve the mutating admission webhook for tests over TLS on this address. The k8s API server
+    /// calls it through the `testsys-controller` service.
+    pub(crate) webhook_address: Option<String>,
+    /// Skip tests with names matching one of these glob patterns.
+    pub(crate) quarantine: Vec<String>,
+    /// Delete tests that finished longer ago than this duration, e.g. `7d` or `12h`.
+    pub(crate) test_retention: Option<String>,
+    /// Only run test agent images that start with one of these prefixes.
+    pub(crate) allowed_images: Vec<String>,
+    /// Install the TestSys CRDs at startup if they are missing instead of exiting. The controller's
+    /// cluster role does not grant `create` on `customresourcedefinitions`, which has to be granted
+    /// to the controller separately for this.
+    pub(crate) install_crds: bool,
+    /// Log the actions the controller would take without taking them, e.g. to validate a new
+    /// controller version. Nothing in the cluster is changed.
+    pub(crate) observe_only: bool,
+    /// Truncate strings written to a test's status, e.g. agent errors, to this many bytes so that
+    /// the test stays within etcd's object size limit.
+    pub(crate) max_status_field_len: Option<usize>,
+    /// Fail tests whose agent image could not be pulled for this long, e.g. `5m`. Defaults to five
+    /// minutes.
+    pub(crate) image_pull_grace_period: Option<String>,
+    /// Limit the controller's requests to the k8s API server to this many per second.
+    pub(crate) kube_qps: Option<u32>,
+    /// The number of requests to the k8s API server that can be sent at once when `kube_qps` is
+    /// set. Defaults to `kube_qps`.
+    pub(crate) kube_burst: Option<u32>,
+    /// Fail tests that declare more than this many resources, so that a runaway test cannot
+    /// create a large number of cloud resources.
+    pub(crate) max_resources_per_test: Option<usize>,
+    /// Publish CloudWatch metrics for tests that reach a terminal state under this namespace.
+    /// Requires the controller to be built with the `cloudwatch-metrics` feature.
+    pub(crate) cloudwatch_metrics_namespace: Option<String>,
+    /// The CloudWatch Logs group that the metrics are written to. Defaults to `testsys-metrics`.
+    pub(crate) cloudwatch_metrics_log_group: Option<String>,
+    /// Identifies this controller among the TestSys controllers that share a cluster. The
+    /// controller claims tests that have not been claimed yet and ignores tests claimed by other
+    /// instances. Every test is reconciled if no instance ID is set.
+    pub(crate) instance_id: Option<String>,
+    /// Labels that are always set on agent jobs and pods, e.g. the labels that network policies
+    /// select agent pods by. Tests whose agents set one of these labels to another value are
+    /// invalid.
+    pub(crate) protected_labels: BTreeMap<String, String>,
+    /// Only watch and reconcile tests that match this label selector, e.g. `team=platform`. Other
+    /// tests are never seen by the test controller, and are not deleted by the retention sweep or
+    /// run by the scheduler.
+    pub(crate) label_selector: Option<String>,
+    /// The node label that selects the capacity type of the nodes an agent's `capacity_type`
+    /// schedules it on, either `karpenter.sh/capacity-type` (the default) or
+    /// `eks.amazonaws.com/capacityType` for EKS managed node groups.
+    pub(crate) capacity_type_label: Option<String>,
+    /// A CA bundle that is mounted in every agent pod, e.g. for private registries or endpoints
+    /// whose certificates are signed by a private CA. It is the name of a `ConfigMap`, or
+    /// `secret/<name>` for a `Secret`, in the `testsys` namespace with the PEM bundle under the key
+    /// `ca-bundle.crt`. `SSL_CERT_FILE` and `AWS_CA_BUNDLE` point the agents at it, which replaces
+    /// their system trust store, so the controller publishes it together with the system's root
+    /// certificates when it starts. It has to be restarted to publish a changed bundle.
+    pub(crate) ca_bundle: Option<String>,
+    /// Keep a timeline of each test's state changes in its status, with at most this many of the
+    /// most recent entries. No timeline is kept if this is not set.
+    pub(crate) max_timeline_entries: Option<usize>,
+    /// The most time, in total, that is added to a test agent's `timeout` when the agent asks for
+    /// more, e.g. `2h`. Agents cannot extend their timeout if this is not set.
+    pub(crate) max_timeout_extension: Option<String>,
+    /// The image of the init container that waits for a test's `wait_for_endpoints`. It must
+    /// provide `sh` and `wget`, a public `busybox` image is used if this is not set.
+    pub(crate) endpoint_wait_image: Option<String>,
+    /// Annotate test agent pods with a hash of the resource outputs and the `env` that they are
+    /// given, so that runs with different inputs can be told apart.
+    pub(crate) annotate_input_hash: bool,
+    /// Defaults that the mutating admission webhook fills into the agents of tests
+    /// that do not set them. They can only be set in the configuration file.
+    pub(crate) agent_defaults: AgentDefaults,
+}
+
+/// Agent settings that are filled in at apply time for agents that do not set them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct AgentDefaults {
+    /// The service account of agents that do not set one.
+    pub(crate) service_account: Option<String>,
+    /// The compute resources of agents that do not set any.
+    pub(crate) container_resources: Option<ContainerResources>,
+}
+
+/// The controller's command line arguments.
+#[derive(Debug, Parser)]
+struct Arguments {
+    /// A YAML file with the controller's settings.
+    #[clap(long = "config")]
+    config: Option<PathBuf>,
+
+    #[clap(flatten)]
+    overrides: Overrides,
+}
+
+/// Settings that take precedence over the ones in the configuration file. Settings that are not
+/// given keep their value from the file.
+#[derive(Debug, Default, Args)]
+struct Overrides {
+    /// Archive the logs of agent pods to CloudWatch.
+    #[clap(long = "archive-logs")]
+    archive_logs: Option<bool>,
+
+    /// Forward the logs of running test agents to this sink.
+    #[clap(long = "log-sink")]
+    log_sink: Option<String>,
+
+    /// Serve the controller's HTTP API on this address.
+    #[clap(long = "api-address")]
+    api_address: Option<String>,
+
+    /// Serve the gRPC endpoint that test agents stream their results to on this address.
+    #[clap(long = "results-address")]
+    results_address: Option<String>,
+
+    /// Serve the mutating admission webhook for tests on this address.
+    #[clap(long = "webhook-address")]
+    webhook_address: Option<String>,
+
+    /// Skip tests with names matching this glob pattern. Can be given more than once.
+    #[clap(long = "quarantine")]
+    quarantine: Option<Vec<String>>,
+
+    /// Delete tests that finished longer ago than this.
+    #[clap(long = "test-retention")]
+    test_retention: Option<String>,
+
+    /// Only run test agent images that start with this prefix. Can be given more than once.
+    #[clap(long = "allowed-image")]
+    allowed_images: Option<Vec<String>>,
+
+    /// Install the TestSys CRDs if they are missing. Needs `create` on
+    /// `customresourcedefinitions`, which the controller's cluster role does not grant.
+    #[clap(long = "install-crds")]
+    install_crds: Option<bool>,
+
+    /// Log what the controller would do without changing anything in the cluster.
+    #[clap(long = "observe-only", num_args = 0..=1, default_missing_value = "true")]
+    observe_only: Option<bool>,
+
+    /// Truncate strings written to a test's status to this many bytes.
+    #[clap(long = "max-status-field-len")]
+    max_status_field_len: Option<usize>,
+
+    /// Fail tests whose agent image could not be pulled for this long.
+    #[clap(long = "image-pull-grace-period")]
+    image_pull_grace_period: Option<String>,
+
+    /// Limit requests to the k8s API server to this many per second.
+    #[clap(long = "kube-qps")]
+    kube_qps: Option<u32>,
+
+    /// The number of requests to the k8s API server that can be sent at once.
+    #[clap(long = "kube-burst")]
+    kube_burst: Option<u32>,
+
+    /// Fail tests that declare more than this many resources.
+    #[clap(long = "max-resources-per-test")]
+    max_resources_per_test: Option<usize>,
+
+    /// Publish CloudWatch metrics for finished tests under this namespace.
+    #[clap(long = "cloudwatch-metrics-namespace")]
+    cloudwatch_metrics_namespace: Option<String>,
+
+    /// Write the CloudWatch metrics to this CloudWatch Logs group.
+    #[clap(long = "cloudwatch-metrics-log-group")]
+    cloudwatch_metrics_log_group: Option<String>,
+
+    /// Only reconcile tests claimed by, or claimable for, this controller instance.
+    #[clap(long = "instance-id")]
+    instance_id: Option<String>,
+
+    /// Always set this `key=value` label on agent jobs and pods. Can be given more than once.
+    #[clap(long = "protected-label")]
+    protected_labels: Option<Vec<String>>,
+
+    /// Only reconcile tests that match this label selector.
+    #[clap(long = "label-selector")]
+    label_selector: Option<String>,
+
+    /// The node label that selects the capacity type of agent nodes.
+    #[clap(long = "capacity-type-label")]
+    capacity_type_label: Option<String>,
+
+    /// Mount the CA bundle from this `ConfigMap`, or `secret/<name>`, in every agent pod.
+    #[clap(long = "ca-bundle")]
+    ca_bundle: Option<String>,
+
+    /// Keep a timeline of at most this many state changes in each test's status.
+    #[clap(long = "max-timeline-entries")]
+    max_timeline_entries: Option<usize>,
+
+    /// The most time, in total, that test agents may add to their timeout, e.g. `2h`.
+    #[clap(long = "max-timeout-extension")]
+    max_timeout_extension: Option<String>,
+
+    /// The image of the init container that waits for a test's endpoints.
+    #[clap(long = "endpoint-wait-image")]
+    endpoint_wait_image: Option<String>,
+
+    /// Annotate test agent pods with a hash of their resolved inputs.
+    #[clap(long = "annotate-input-hash", num_args = 0..=1, default_missing_value = "true")]
+    annotate_input_hash: Option<bool>,
+}
+
+impl Overrides {
+    /// Read the settings from the controller's environment variables, looking each of them up with
+    /// `var`. Lists are comma separated.
+    fn from_env<F>(var: F) -> Self
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let list = |name| var(name).map(|value| split_list(&value));
+        Self {
+            archive_logs: var(TESTSYS_CONTROLLER_ARCHIVE_LOGS).map(|value| value.trim() == "true"),
+            log_sink: var(TESTSYS_CONTROLLER_LOG_SINK),
+            api_address: var(TESTSYS_CONTROLLER_API_ADDRESS),
+            results_address: var(TESTSYS_CONTROLLER_RESULTS_ADDRESS),
+            webhook_address: var(TESTSYS_CONTROLLER_WEBHOOK_ADDRESS),
+            quarantine: list(TESTSYS_CONTROLLER_QUARANTINE),
+            test_retention: var(TESTSYS_CONTROLLER_TEST_RETENTION),
+            allowed_images: list(TESTSYS_CONTROLLER_ALLOWED_IMAGES),
+            install_crds: var(TESTSYS_CONTROLLER_INSTALL_CRDS).map(|value| value.trim() == "true"),
+            observe_only: var(TESTSYS_CONTROLLER_OBSERVE_ONLY).map(|value| value.trim() == "true"),
+            max_status_field_len: var(TESTSYS_CONTROLLER_MAX_STATUS_FIELD_LEN)
+                .and_then(|value| value.trim().parse().ok()),
+            image_pull_grace_period: var(TESTSYS_CONTROLLER_IMAGE_PULL_GRACE_PERIOD),
+            kube_qps: var(TESTSYS_CONTROLLER_KUBE_QPS).and_then(|value| value.trim().parse().ok()),
+            kube_burst: var(TESTSYS_CONTROLLER_KUBE_BURST)
+                .and_then(|value| value.trim().parse().ok()),
+            max_resources_per_test: var(TESTSYS_CONTROLLER_MAX_RESOURCES_PER_TEST)
+                .and_then(|value| value.trim().parse().ok()),
+            cloudwatch_metrics_namespace: var(TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_NAMESPACE),
+            cloudwatch_metrics_log_group: var(TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_LOG_GROUP),
+            instance_id: var(TESTSYS_CONTROLLER_INSTANCE_ID),
+            protected_labels: list(TESTSYS_CONTROLLER_PROTECTED_LABELS),
+            label_selector: var(TESTSYS_CONTROLLER_LABEL_SELECTOR),
+            capacity_type_label: var(TESTSYS_CONTROLLER_CAPACITY_TYPE_LABEL),
+            ca_bundle: var(TESTSYS_CONTROLLER_CA_BUNDLE),
+            max_timeline_entries: var(TESTSYS_CONTROLLER_MAX_TIMELINE_ENTRIES)
+                .and_then(|value| value.trim().parse().ok()),
+            max_timeout_extension: var(TESTSYS_CONTROLLER_MAX_TIMEOUT_EXTENSION),
+            endpoint_wait_image: var(TESTSYS_CONTROLLER_ENDPOINT_WAIT_IMAGE),
+            annotate_input_hash: var(TESTSYS_CONTROLLER_ANNOTATE_INPUT_HASH)
+                .map(|value| value.trim() == "true"),
+        }
+    }
+}
+
+impl ControllerConfig {
+    /// Load the configuration file given with `--config`, if any, and apply the environment
+    /// variables and command line flags on top of it.
+    pub(crate) fn load() -> Result<Self> {
+        let arguments = Arguments::parse();
+        let file = match &arguments.config {
+            Some(path) => Some(Self::from_file(path)?),
+            None => None,
+        };
+        Ok(Self::merge(
+            file,
+            Overrides::from_env(|name| env::var(name).ok()),
+            arguments.overrides,
+        ))
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read controller config '{}'", path.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Invalid controller config '{}'", path.display()))
+    }
+
+    /// The label selector that limits the tests the controller acts on, if it has one.
+    pub(crate) fn test_label_selector(&self) -> Option<&str> {
+        self.label_selector
+            .as_deref()
+            .map(str::trim)
+            .filter(|selector| !selector.is_empty())
+    }
+
+    /// Flags take precedence over environment variables, which take precedence over the file,
+    /// which takes precedence over the defaults.
+    fn merge(file: Option<Self>, env: Overrides, flags: Overrides) -> Self {
+        let mut config = file.unwrap_or_default();
+        config.apply(env);
+        config.apply(flags);
+        config
+    }
+
+    fn apply(&mut self, overrides: Overrides) {
+        if let Some(archive_logs) = overrides.archive_logs {
+            self.archive_logs = archive_logs;
+        }
+        if let Some(log_sink) = overrides.log_sink {
+            self.log_sink = Some(log_sink);
+        }
+        if let Some(api_address) = overrides.api_address {
+            self.api_address = Some(api_address);
+        }
+        if let Some(results_address) = overrides.results_address {
+            self.results_address = Some(results_address);
+        }
+        if let Some(webhook_address) = overrides.webhook_address {
+            self.webhook_address = Some(webhook_address);
+        }
+        if let Some(quarantine) = overrides.quarantine {
+            self.quarantine = quarantine;
+        }
+        if let Some(test_retention) = overrides.test_retention {
+            self.test_retention = Some(test_retention);
+        }
+        if let Some(allowed_images) = overrides.allowed_images {
+            self.allowed_images = allowed_images;
+        }
+        if let Some(install_crds) = overrides.install_crds {
+            self.install_crds = install_crds;
+        }
+        if let Some(observe_only) = overrides.observe_only {
+            self.observe_only = observe_only;
+        }
+        if let Some(max_status_field_len) = overrides.max_status_field_len {
+            self.max_status_field_len = Some(max_status_field_len);
+        }
+        if let Some(image_pull_grace_period) = overrides.image_pull_grace_period {
+            self.image_pull_grace_period = Some(image_pull_grace_period);
+        }
+        if let Some(kube_qps) = overrides.kube_qps {
+            self.kube_qps = Some(kube_qps);
+        }
+        if let Some(kube_burst) = overrides.kube_burst {
+            self.kube_burst = Some(kube_burst);
+        }
+        if let Some(max_resources_per_test) = overrides.max_resources_per_test {
+            self.max_resources_per_test = Some(max_resources_per_test);
+        }
+        if let Some(cloudwatch_metrics_namespace) = overrides.cloudwatch_metrics_namespace {
+            self.cloudwatch_metrics_namespace = Some(cloudwatch_metrics_namespace);
+        }
+        if let Some(cloudwatch_metrics_log_group) = overrides.cloudwatch_metrics_log_group {
+            self.cloudwatch_metrics_log_group = Some(cloudwatch_metrics_log_group);
+        }
+        if let Some(instance_id) = overrides.instance_id {
+            self.instance_id = Some(instance_id);
+        }
+        if let Some(protected_labels) = overrides.protected_labels {
+            self.protected_labels = split_labels(&protected_labels);
+        }
+        if let Some(label_selector) = overrides.label_selector {
+            self.label_selector = Some(label_selector);
+        }
+        if let Some(capacity_type_label) = overrides.capacity_type_label {
+            self.capacity_type_label = Some(capacity_type_label);
+        }
+        if let Some(ca_bundle) = overrides.ca_bundle {
+            self.ca_bundle = Some(ca_bundle);
+        }
+        if let Some(max_timeline_entries) = overrides.max_timeline_entries {
+            self.max_timeline_entries = Some(max_timeline_entries);
+        }
+        if let Some(max_timeout_extension) = overrides.max_timeout_extension {
+            self.max_timeout_extension = Some(max_timeout_extension);
+        }
+        if let Some(endpoint_wait_image) = overrides.endpoint_wait_image {
+            self.endpoint_wait_image = Some(endpoint_wait_image);
+        }
+        if let Some(annotate_input_hash) = overrides.annotate_input_hash {
+            self.annotate_input_hash = annotate_input_hash;
+        }
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Split `key=value` labels into a map. Items without a `=` are ignored.
+fn split_labels(labels: &[String]) -> BTreeMap<String, String> {
+    labels
+        .iter()
+        .filter_map(|label| label.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+#[test]
+fn config_precedence() {
+    let file = serde_yaml::from_str::<ControllerConfig>(
+        r#"
+logSink: stdout
+apiAddress: 0.0.0.0:8080
+quarantine: ["flaky-*"]
+testRetention: 7d
+"#,
+    )
+    .unwrap_or_default();
+    let env = Overrides::from_env(|name| {
+        match name {
+            TESTSYS_CONTROLLER_API_ADDRESS => Some("127.0.0.1:8080"),
+            TESTSYS_CONTROLLER_TEST_RETENTION => Some("1d"),
+            TESTSYS_CONTROLLER_ALLOWED_IMAGES => Some("ecr.aws, public.ecr.aws/testsys/"),
+            _ => None,
+        }
+        .map(str::to_string)
+    });
+    let flags = Arguments::try_parse_from(["controller", "--test-retention", "12h"])
+        .map(|arguments| arguments.overrides)
+        .unwrap_or_default();
+
+    assert_eq!(
+        ControllerConfig::merge(Some(file), env, flags),
+        ControllerConfig {
+            // Default
+            archive_logs: false,
+            results_address: None,
+            webhook_address: None,
+            install_crds: false,
+            observe_only: false,
+            max_status_field_len: None,
+            image_pull_grace_period: None,
+            kube_qps: None,
+            kube_burst: None,
+            max_resources_per_test: None,
+            cloudwatch_metrics_namespace: None,
+            cloudwatch_metrics_log_group: None,
+            instance_id: None,
+            protected_labels: BTreeMap::new(),
+            label_selector: None,
+            capacity_type_label: None,
+            ca_bundle: None,
+            max_timeline_entries: None,
+            max_timeout_extension: None,
+            endpoint_wait_image: None,
+            annotate_input_hash: false,
+            agent_defaults: AgentDefaults::default(),
+            // File
+            log_sink: Some("stdout".to_string()),
+            quarantine: vec!["flaky-*".to_string()],
+            // Environment variable
+            api_address: Some("127.0.0.1:8080".to_string()),
+            allowed_images: vec!["ecr.aws".to_string(), "public.ecr.aws/testsys/".to_string()],
+            // Flag
+            test_retention: Some("12h".to_string()),
+        }
+    );
+}
+
+#[test]
+fn config_without_file() {
+    assert_eq!(
+        ControllerConfig::merge(None, Overrides::default(), Overrides::default()),
+        ControllerConfig::default()
+    );
+}
+
+#[test]
+fn observe_only_flag() {
+    let flags = |args: &[&str]| {
+        Arguments::try_parse_from(args)
+            .map(|arguments| arguments.overrides.observe_only)
+            .unwrap_or_default()
+    };
+    assert_eq!(flags(&["controller", "--observe-only"]), Some(true));
+    assert_eq!(
+        flags(&["controller", "--observe-only", "false"]),
+        Some(false)
+    );
+    assert_eq!(flags(&["controller"]), None);
+}
+
+#[test]
+fn protected_labels() {
+    let file = serde_yaml::from_str::<ControllerConfig>(
+        r#"
+protectedLabels:
+  network-policy: testsys-agent
+"#,
+    )
+    .unwrap_or_default();
+    let env = Overrides::from_env(|name| {
+        (name == TESTSYS_CONTROLLER_PROTECTED_LABELS)
+            .then(|| "team=platform, network-policy=agents".to_string())
+    });
+    assert_eq!(
+        ControllerConfig::merge(
+            Some(file.clone()),
+            Overrides::default(),
+            Overrides::default()
+        )
+        .protected_labels,
+        BTreeMap::from([("network-policy".to_string(), "testsys-agent".to_string())])
+    );
+    assert_eq!(
+        ControllerConfig::merge(Some(file), env, Overrides::default()).protected_labels,
+        BTreeMap::from([
+            ("network-policy".to_string(), "agents".to_string()),
+            ("team".to_string(), "platform".to_string()),
+        ])
+    );
+}
@@ -0,0 +1,75 @@
+use crate::error::Result;
+use anyhow::Context;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::api::PostParams;
+use kube::{Api, CustomResourceExt, ResourceExt};
+use testsys_model::clients::AllowNotFound;
+use testsys_model::{Resource, Test};
+
+/// The names of the TestSys CRDs that the controller watches but that are not installed in the
+/// cluster. Without them the controller's watches fail with errors that do not say what is wrong.
+pub(crate) async fn missing_crds(k8s_client: kube::Client) -> Result<Vec<String>> {
+    let api = Api::<CustomResourceDefinition>::all(k8s_client);
+    let mut missing = Vec::new();
+    for name in [Test::crd().name_any(), Resource::crd().name_any()] {
+        let crd = api
+            .get(&name)
+            .await
+            .allow_not_found(|_| ())
+            .with_context(|| format!("Unable to get CRD '{}'", name))?;
+        if crd.is_none() {
+            missing.push(name);
+        }
+    }
+    Ok(missing)
+}
+
+/// Create the TestSys CRDs named in `missing`.
+pub(crate) async fn install_crds(k8s_client: kube::Client, missing: &[String]) -> Result<()> {
+    let api = Api::<CustomResourceDefinition>::all(k8s_client);
+    for crd in [Test::crd(), Resource::crd()] {
+        if missing.contains(&crd.name_any()) {
+            api.create(&PostParams::default(), &crd)
+                .await
+                .with_context(|| format!("Unable to create CRD '{}'", crd.name_any()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn installed_crd(crd: CustomResourceDefinition) -> (String, serde_json::Value) {
+    (
+        format!("/customresourcedefinitions/{}", crd.name_any()),
+        serde_json::json!(crd),
+    )
+}
+
+#[tokio::test]
+async fn test_crd_is_missing() {
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![installed_crd(Resource::crd())]);
+    let missing = missing_crds(k8s_client).await;
+    assert!(
+        matches!(&missing, Ok(missing) if missing == &["tests.testsys.system"]),
+        "{:?}",
+        missing
+    );
+}
+
+#[tokio::test]
+async fn all_crds_are_installed() {
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![
+        installed_crd(Test::crd()),
+        installed_crd(Resource::crd()),
+    ]);
+    assert!(matches!(missing_crds(k8s_client).await, Ok(missing) if missing.is_empty()));
+}
+
+#[tokio::test]
+async fn install_missing_crds() {
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(Resource::crd())]);
+    let missing = missing_crds(k8s_client.clone()).await.unwrap_or_default();
+    assert_eq!(missing, ["tests.testsys.system"]);
+    assert!(install_crds(k8s_client.clone(), &missing).await.is_ok());
+    assert!(matches!(missing_crds(k8s_client).await, Ok(missing) if missing.is_empty()));
+}
@@ -0,0 +1,62 @@
+use crate::error::Result;
+use anyhow::Context;
+use k8s_openapi::api::core::v1::{Event, EventSource};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, Time};
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::PostParams;
+use kube::{Api, Resource, ResourceExt};
+use testsys_model::constants::NAMESPACE;
+
+/// The component that k8s events created by the controller are attributed to.
+const EVENT_COMPONENT: &str = "testsys-controller";
+
+/// Create a `Warning` event for `object` so that the problem shows up in `kubectl describe`. The
+/// event happened at `now`.
+pub(crate) async fn record_warning<K>(
+    k8s_client: kube::Client,
+    object: &K,
+    reason: &str,
+    message: &str,
+    now: DateTime<Utc>,
+) -> Result<()>
+where
+    K: Resource<DynamicType = ()>,
+{
+    let now = Time(now);
+    let event = Event {
+        metadata: ObjectMeta {
+            // Event names only need to be unique, this follows the convention used by `kubectl`.
+            name: Some(format!(
+                "{}.{:x}",
+                object.name_any(),
+                now.0.timestamp_nanos()
+            )),
+            namespace: Some(NAMESPACE.to_string()),
+            ..Default::default()
+        },
+        involved_object: object.object_ref(&()),
+        reason: Some(reason.to_string()),
+        message: Some(message.to_string()),
+        type_: Some("Warning".to_string()),
+        source: Some(EventSource {
+            component: Some(EVENT_COMPONENT.to_string()),
+            ..Default::default()
+        }),
+        reporting_component: Some(EVENT_COMPONENT.to_string()),
+        count: Some(1),
+        first_timestamp: Some(now.clone()),
+        last_timestamp: Some(now),
+        ..Default::default()
+    };
+    Api::<Event>::namespaced(k8s_client, NAMESPACE)
+        .create(&PostParams::default(), &event)
+        .await
+        .with_context(|| {
+            format!(
+                "Unable to create '{}' event for '{}'",
+                reason,
+                object.name_any()
+            )
+        })?;
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use http::{Method, Request, Response, StatusCode};
+use hyper::Body;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use testsys_model::constants::NAMESPACE;
+
+/// Create a `kube::Client` backed by a fake k8s API server. A request for a path ending with one of
+/// the `objects` paths is answered with that object, every other request is answered with a `404`.
+pub(crate) fn fake_k8s_client<S>(objects: Vec<(S, Value)>) -> kube::Client
+where
+    S: Into<String>,
+{
+    fake_k8s_client_counting_writes(objects).0
+}
+
+/// Like [`fake_k8s_client`], but also counts the requests that would change something in the
+/// cluster, i.e. every request that is not a `GET`.
+pub(crate) fn fake_k8s_client_counting_writes<S>(
+    objects: Vec<(S, Value)>,
+) -> (kube::Client, Arc<AtomicUsize>)
+where
+    S: Into<String>,
+{
+    let objects: Vec<(String, Value)> = objects
+        .into_iter()
+        .map(|(path, object)| (path.into(), object))
+        .collect();
+    let writes = Arc::new(AtomicUsize::new(0));
+    let service_writes = writes.clone();
+    let service = tower::service_fn(move |request: Request<Body>| {
+        if request.method() != Method::GET {
+            service_writes.fetch_add(1, Ordering::SeqCst);
+        }
+        let object = objects
+            .iter()
+            .find(|(path, _)| request.uri().path().ends_with(path.as_str()))
+            .map(|(_, object)| object.to_string());
+        async move {
+            let response = match object {
+                Some(object) => Response::new(Body::from(object)),
+                None => status_response(StatusCode::NOT_FOUND, "NotFound"),
+            };
+            Ok::<_, Infallible>(response)
+        }
+    });
+    (kube::Client::new(service, NAMESPACE), writes)
+}
+
+/// Create a `kube::Client` backed by a fake k8s API server that keeps namespaced objects in memory,
+/// starting with `objects`. Objects can be listed, fetched, created, replaced, deleted, JSON
+/// patched and merge patched. Created objects get a `uid` unless they already have one.
+/// Lists can be limited by label selectors made of `key=value` requirements. Objects deleted in the
+/// foreground are only marked for deletion, as if their dependents were never gone, until they are
+/// deleted again in the background.
+pub(crate) fn fake_k8s_store(objects: Vec<Value>) -> kube::Client {
+    let store: Arc<Mutex<BTreeMap<(String, String), Value>>> = Arc::new(Mutex::new(
+        objects
+            .into_iter()
+            .map(|object| ((object_plural(&object), object_name(&object)), object))
+            .collect(),
+    ));
+    let uids = Arc::new(AtomicUsize::new(0));
+    let service = tower::service_fn(move |request: Request<Body>| {
+        let (store, uids) = (store.clone(), uids.clone());
+        async move {
+            let (parts, body) = request.into_parts();
+            let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+            // Paths look like
+            // `/apis/<group>/<version>/namespaces/<namespace>/<plural>[/<name>[/<subresource>]]`,
+            // or `/apis/<group>/<version>/<plural>[/<name>]` for cluster scoped objects.
+            let segments: Vec<&str> = parts.uri.path().split('/').collect();
+            let index = segments
+                .iter()
+                .position(|segment| *segment == "namespaces")
+                .map(|index| index + 2)
+                .unwrap_or(if segments.get(1) == Some(&"apis") {
+                    4
+                } else {
+                    3
+                });
+            let plural = segments.get(index).copied().unwrap_or_default();
+            let name = segments.get(index + 1);
+            let key = |name: &str| (plural.to_string(), name.to_string());
+            let mut store = match store.lock() {
+                Ok(store) => store,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let response = match (parts.method, name) {
+                (Method::GET, None) => {
+                    let selector = label_selector(parts.uri.query().unwrap_or_default());
+                    json_response(json!({
+                        "apiVersion": "v1",
+                        "kind": "List",
+                        "metadata": {},
+                        "items": store
+                            .iter()
+                            .filter(|((object_plural, _), _)| object_plural == plural)
+                            .map(|(_, object)| object)
+                            .filter(|object| {
+                                selector
+                                    .iter()
+                                    .all(|(key, value)| object["metadata"]["labels"][key] == *value)
+                            })
+                            .collect::<Vec<_>>(),
+                    }))
+                }
+                (Method::POST, None) => match serde_json::from_slice::<Value>(&body) {
+                    Ok(object) if store.contains_key(&key(&object_name(&object))) => {
+                        status_response(StatusCode::CONFLICT, "AlreadyExists")
+                    }
+                    Ok(mut object) => {
+                        if object["metadata"]["uid"].is_null() {
+                            let uid = uids.fetch_add(1, Ordering::SeqCst);
+                            object["metadata"]["uid"] = json!(format!("{:08x}", uid));
+                        }
+                        store.insert(key(&object_name(&object)), object.clone());
+                        json_response(object)
+                    }
+                    Err(_) => status_response(StatusCode::BAD_REQUEST, "BadRequest"),
+                },
+                (Method::PUT, Some(name)) => match serde_json::from_slice::<Value>(&body) {
+                    Ok(object) => match store.get_mut(&key(name)) {
+                        Some(existing) => {
+                            *existing = object.clone();
+                            json_response(object)
+                        }
+                        None => status_response(StatusCode::NOT_FOUND, "NotFound"),
+                    },
+                    Err(_) => status_response(StatusCode::BAD_REQUEST, "BadRequest"),
+                },
+                (Method::GET, Some(name)) => match store.get(&key(name)) {
+                    Some(object) => json_response(object.clone()),
+                    None => status_response(StatusCode::NOT_FOUND, "NotFound"),
+                },
+                (Method::DELETE, Some(name)) => {
+                    let foreground = serde_json::from_slice::<Value>(&body)
+                        .map(|options| options["propagationPolicy"] == "Foreground")
+                        .unwrap_or(false);
+                    let deleted = if foreground {
+                        store.get_mut(&key(name)).map(|object| {
+                            object["metadata"]["deletionTimestamp"] = json!("2022-01-01T00:00:00Z");
+                            object.clone()
+                        })
+                    } else {
+                        store.remove(&key(name))
+                    };
+                    match deleted {
+                        Some(object) => json_response(object),
+                        None => status_response(StatusCode::NOT_FOUND, "NotFound"),
+                    }
+                }
+                // Objects and their status subresource are patched the same way.
+                (Method::PATCH, Some(name))
+                    if parts.headers.get(http::header::CONTENT_TYPE)
+                        == Some(&http::HeaderValue::from_static(
+                            "application/merge-patch+json",
+                        )) =>
+                {
+                    match (
+                        store.get_mut(&key(name)),
+                        serde_json::from_slice::<Value>(&body),
+                    ) {
+                        (Some(object), Ok(patch)) => {
+                            json_patch::merge(object, &patch);
+                            json_response(object.clone())
+                        }
+                        (None, _) => status_response(StatusCode::NOT_FOUND, "NotFound"),
+                        (_, Err(_)) => status_response(StatusCode::BAD_REQUEST, "BadRequest"),
+                    }
+                }
+                (Method::PATCH, Some(name)) => {
+                    let patch = serde_json::from_slice::<json_patch::Patch>(&body);
+                    match (store.get_mut(&key(name)), patch) {
+                        (Some(object), Ok(mut patch)) => {
+                            // Like the k8s API server, a test for `null` passes if the path is
+                            // missing.
+                            patch.0.retain(|operation| {
+                                !matches!(operation, json_patch::PatchOperation::Test(test)
+                                    if test.value.is_null() && object.pointer(&test.path).is_none())
+                            });
+                            let mut patched = object.clone();
+                            match json_patch::patch(&mut patched, &patch) {
+                                Ok(()) => {
+                                    *object = patched.clone();
+                                    json_response(patched)
+                                }
+                                Err(_) => status_response(StatusCode::CONFLICT, "Conflict"),
+                            }
+                        }
+                        (None, _) => status_response(StatusCode::NOT_FOUND, "NotFound"),
+                        (_, Err(_)) => status_response(StatusCode::BAD_REQUEST, "BadRequest"),
+                    }
+                }
+                _ => status_response(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed"),
+            };
+            Ok::<_, Infallible>(response)
+        }
+    });
+    kube::Client::new(service, NAMESPACE)
+}
+
+/// Create a `kube::Client` backed by a fake k8s API server whose lists of objects are empty and
+/// whose watches immediately expire, as they do when their resource version is too old. Also
+/// returns the number of lists that were requested.
+pub(crate) fn fake_expiring_watch_client() -> (kube::Client, Arc<AtomicUsize>) {
+    let lists = Arc::new(AtomicUsize::new(0));
+    let service_lists = lists.clone();
+    let service = tower::service_fn(move |request: Request<Body>| {
+        let watch = request
+            .uri()
+            .query()
+            .unwrap_or_default()
+            .contains("watch=true");
+        if !watch {
+            service_lists.fetch_add(1, Ordering::SeqCst);
+        }
+        async move {
+            let body = if watch {
+                json!({
+                    "type": "ERROR",
+                    "object": {
+                        "kind": "Status",
+                        "apiVersion": "v1",
+                        "status": "Failure",
+                        "message": "too old resource version: 1 (2)",
+                        "reason": "Expired",
+                        "code": 410,
+                    },
+                })
+            } else {
+                json!({ "metadata": { "resourceVersion": "1" }, "items": [] })
+            };
+            Ok::<_, Infallible>(Response::new(Body::from(format!("{}\n", body))))
+        }
+    });
+    (kube::Client::new(service, NAMESPACE), lists)
+}
+
+/// A `PodList` of `pods`, to answer requests for the pods of a job.
+pub(crate) fn pod_list(pods: Vec<Value>) -> Value {
+    json!({
+        "apiVersion": "v1",
+        "kind": "PodList",
+        "metadata": {},
+        "items": pods,
+    })
+}
+
+/// The `key=value` requirements of the label selector in the `query` of a list request.
+fn label_selector(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter_map(|parameter| parameter.strip_prefix("labelSelector="))
+        .flat_map(|selector| {
+            let selector = selector
+                .replace("%3D", "=")
+                .replace("%2C", ",")
+                .replace("%2F", "/");
+            selector
+                .split(',')
+                .filter_map(|requirement| requirement.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The plural name of the object's kind as it appears in API paths, e.g. `tests` for a `Test`.
+fn object_plural(object: &Value) -> String {
+    format!(
+        "{}s",
+        object["kind"].as_str().unwrap_or_default().to_lowercase()
+    )
+}
+
+fn object_name(object: &Value) -> String {
+    object["metadata"]["name"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn json_response(object: Value) -> Response<Body> {
+    Response::new(Body::from(object.to_string()))
+}
+
+/// A k8s `Status` response for a failed request.
+fn status_response(status: StatusCode, reason: &str) -> Response<Body> {
+    let mut response = Response::new(Body::from(
+        json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "status": "Failure",
+            "message": reason,
+            "reason": reason,
+            "code": status.as_u16(),
+        })
+        .to_string(),
+    ));
+    *response.status_mut() = status;
+    response
+}
@@ -0,0 +1,130 @@
+use crate::events::record_warning;
+use crate::metrics::increment_finalizer_failures;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::ResourceExt;
+use log::warn;
+use testsys_model::clients::{CrdClient, Result};
+
+/// The reason given for events about finalizers that could not be added or removed.
+const FINALIZER_FAILED_REASON: &str = "FinalizerFailed";
+
+/// Add `finalizer` to `crd`. Nothing is done if `crd` already has the finalizer. Failures are
+/// counted and recorded as a k8s event on `crd` that happened at `now`.
+pub(crate) async fn add_finalizer<C>(
+    client: &C,
+    finalizer: &str,
+    crd: &C::Crd,
+    now: DateTime<Utc>,
+) -> Result<C::Crd>
+where
+    C: CrdClient + Sync,
+{
+    let result = client.add_finalizer(finalizer, crd).await;
+    if let Err(e) = &result {
+        report_failure(
+            client,
+            crd,
+            format!("Unable to add finalizer '{}': {}", finalizer, e),
+            now,
+        )
+        .await;
+    }
+    result
+}
+
+/// Remove `finalizer` from `crd`. Nothing is done if `crd` does not have the finalizer. Failures
+/// are counted and recorded as a k8s event on `crd` that happened at `now`.
+pub(crate) async fn remove_finalizer<C>(
+    client: &C,
+    finalizer: &str,
+    crd: &C::Crd,
+    now: DateTime<Utc>,
+) -> Result<C::Crd>
+where
+    C: CrdClient + Sync,
+{
+    let result = client.remove_finalizer(finalizer, crd).await;
+    if let Err(e) = &result {
+        report_failure(
+            client,
+            crd,
+            format!("Unable to remove finalizer '{}': {}", finalizer, e),
+            now,
+        )
+        .await;
+    }
+    result
+}
+
+async fn report_failure<C>(client: &C, crd: &C::Crd, message: String, now: DateTime<Utc>)
+where
+    C: CrdClient + Sync,
+{
+    increment_finalizer_failures();
+    let k8s_client = client.api().clone().into_client();
+    if let Err(e) = record_warning(k8s_client, crd, FINALIZER_FAILED_REASON, &message, now).await {
+        // The caller reports the original error, the event is only for visibility.
+        warn!(
+            "Unable to record finalizer failure for '{}': {:?}",
+            crd.name_any(),
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+fn test_with_finalizers(finalizers: &[&str]) -> testsys_model::Test {
+    let mut test = testsys_model::Test::new("my-test", testsys_model::TestSpec::default());
+    test.metadata.namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.metadata.finalizers = Some(finalizers.iter().map(|s| s.to_string()).collect());
+    test
+}
+
+#[tokio::test]
+async fn finalizer_changes_that_are_already_done_are_skipped() {
+    use testsys_model::CrdExt;
+
+    // Every request to this client fails, so these only succeed if no request is made.
+    let k8s_client = crate::fake_api::fake_k8s_client::<&str>(vec![]);
+    let test_client = testsys_model::clients::TestClient::new_from_k8s_client(k8s_client);
+
+    let test = test_with_finalizers(&["foo"]);
+    let added = add_finalizer(&test_client, "foo", &test, Utc::now()).await;
+    assert!(matches!(&added, Ok(test) if test.has_finalizer("foo")));
+    let removed = remove_finalizer(&test_client, "bar", &test, Utc::now()).await;
+    assert!(matches!(&removed, Ok(test) if test.finalizers() == ["foo"]));
+}
+
+#[tokio::test]
+async fn failed_finalizer_change_is_reported() {
+    // The test does not exist in the fake API, so patching it fails.
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![]);
+    let test_client = testsys_model::clients::TestClient::new_from_k8s_client(k8s_client.clone());
+    let failures = crate::metrics::finalizer_failures();
+
+    let test = test_with_finalizers(&[]);
+    assert!(add_finalizer(&test_client, "foo", &test, Utc::now())
+        .await
+        .is_err());
+    assert!(crate::metrics::finalizer_failures() > failures);
+
+    let events = kube::Api::<k8s_openapi::api::core::v1::Event>::namespaced(
+        k8s_client,
+        testsys_model::constants::NAMESPACE,
+    )
+    .list(&Default::default())
+    .await
+    .map(|events| events.items)
+    .unwrap_or_default();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.reason.as_deref(), Some(FINALIZER_FAILED_REASON));
+    assert_eq!(event.type_.as_deref(), Some("Warning"));
+    assert_eq!(event.involved_object.kind.as_deref(), Some("Test"));
+    assert_eq!(event.involved_object.name.as_deref(), Some("my-test"));
+    assert!(event
+        .message
+        .as_deref()
+        .unwrap_or_default()
+        .contains("Unable to add finalizer 'foo'"));
+}
@@ -0,0 +1,105 @@
+use crate::config::ControllerConfig;
+use kube::api::ListParams;
+use testsys_model::CrdExt;
+
+/// The controller instance this controller runs as, if tests and resources are claimed by the
+/// controller instances that share the cluster. Objects claimed by another instance are left to
+/// it by every part of the controller. An instance can also be limited to the tests that match its
+/// label selector.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Instance {
+    id: Option<String>,
+    label_selector: Option<String>,
+}
+
+impl Instance {
+    pub(crate) fn new(config: &ControllerConfig) -> Self {
+        Self {
+            id: config
+                .instance_id
+                .as_deref()
+                .map(str::trim)
+                .filter(|instance_id| !instance_id.is_empty())
+                .map(str::to_string),
+            label_selector: config.test_label_selector().map(str::to_string),
+        }
+    }
+
+    /// The parameters that list the tests the instance is limited to by its label selector.
+    pub(crate) fn list_params(&self) -> ListParams {
+        match &self.label_selector {
+            Some(selector) => ListParams::default().labels(selector),
+            None => ListParams::default(),
+        }
+    }
+
+    /// The ID of this controller instance, if objects are claimed by controller instances.
+    pub(crate) fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The ID of the other controller instance that claimed the `object`, if it was claimed by
+    /// one.
+    pub(crate) fn other_owner<'a, T: CrdExt>(&self, object: &'a T) -> Option<&'a str> {
+        let id = self.id()?;
+        object.controller_instance().filter(|owner| *owner != id)
+    }
+
+    /// Whether the `object` still has to be claimed by this instance before it acts on it.
+    pub(crate) fn must_claim<T: CrdExt>(&self, object: &T) -> bool {
+        self.id.is_some() && object.controller_instance().is_none()
+    }
+
+    /// Whether this instance has claimed the `object`, which every object is if objects are not
+    /// claimed. Objects that no instance has claimed yet are not this instance's.
+    pub(crate) fn owns<T: CrdExt>(&self, object: &T) -> bool {
+        match self.id() {
+            Some(id) => object.controller_instance() == Some(id),
+            None => true,
+        }
+    }
+}
+
+#[test]
+fn ownership() {
+    use testsys_model::constants::LABEL_CONTROLLER_INSTANCE;
+    use testsys_model::Test;
+
+    let claimed_by = |owner: Option<&str>| {
+        let mut test = Test::default();
+        test.metadata.labels = owner.map(|owner| {
+            [(LABEL_CONTROLLER_INSTANCE.to_string(), owner.to_string())]
+                .into_iter()
+                .collect()
+        });
+        test
+    };
+    let green = Instance::new(&ControllerConfig {
+        instance_id: Some(" green ".to_string()),
+        ..ControllerConfig::default()
+    });
+    assert!(green.owns(&claimed_by(Some("green"))));
+    assert!(!green.owns(&claimed_by(Some("blue"))));
+    assert!(!green.owns(&claimed_by(None)));
+    assert!(green.must_claim(&claimed_by(None)));
+    assert_eq!(green.other_owner(&claimed_by(Some("blue"))), Some("blue"));
+    assert_eq!(green.other_owner(&claimed_by(None)), None);
+
+    let unnamed = Instance::new(&ControllerConfig::default());
+    assert!(unnamed.owns(&claimed_by(Some("blue"))));
+    assert!(!unnamed.must_claim(&claimed_by(None)));
+    assert_eq!(unnamed.other_owner(&claimed_by(Some("blue"))), None);
+    assert_eq!(unnamed.list_params().label_selector, None);
+}
+
+#[test]
+fn label_selector() {
+    let instance = Instance::new(&ControllerConfig {
+        label_selector: Some(" team=platform ".to_string()),
+        ..ControllerConfig::default()
+    });
+    assert_eq!(
+        instance.list_params().label_selector.as_deref(),
+        Some("team=platform")
+    );
+}
@@ -0,0 +1,143 @@
+use crate::error::Result;
+use crate::job::job_builder::create_or_replace;
+use anyhow::{ensure, Context};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::Api;
+use log::info;
+use std::collections::BTreeMap;
+use testsys_model::constants::{CA_BUNDLE_FILE, NAMESPACE};
+
+/// The `ConfigMap` with the CA bundle that is mounted in agent pods: the controller's CA bundle
+/// followed by the system's root certificates.
+pub(crate) const AGENT_CA_BUNDLE: &str = "testsys-agent-ca-bundle";
+
+/// The files with the system's root certificates in the images the controller is built on, in the
+/// order they are looked for.
+const SYSTEM_CA_BUNDLES: &[&str] = &[
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/certs/ca-certificates.crt",
+];
+
+/// Publish the controller's CA bundle `ca_bundle`, the name of a `ConfigMap` or `secret/<name>` for
+/// a `Secret`, in [`AGENT_CA_BUNDLE`] for the agent pods. `SSL_CERT_FILE` and `AWS_CA_BUNDLE`
+/// replace the trust store of the agents' TLS clients instead of adding to it, so the bundle is
+/// followed by the system's root certificates that a bundle with only a private CA would lack.
+pub(crate) async fn publish_agent_ca_bundle(client: kube::Client, ca_bundle: &str) -> Result<()> {
+    let (path, system_roots) = SYSTEM_CA_BUNDLES
+        .iter()
+        .find_map(|path| Some((path, std::fs::read_to_string(path).ok()?)))
+        .with_context(|| {
+            format!(
+                "Unable to find the system's root certificates in any of {}",
+                SYSTEM_CA_BUNDLES.join(", ")
+            )
+        })?;
+    info!(
+        "Publishing the CA bundle '{}' with the root certificates in '{}' for the agents",
+        ca_bundle, path
+    );
+    publish(client, ca_bundle, &system_roots).await
+}
+
+async fn publish(client: kube::Client, ca_bundle: &str, system_roots: &str) -> Result<()> {
+    let bundle = read_ca_bundle(client.clone(), ca_bundle).await?;
+    let certificates = rustls_pemfile::certs(&mut bundle.as_bytes())
+        .with_context(|| format!("Unable to read the certificates in '{}'", ca_bundle))?;
+    ensure!(
+        !certificates.is_empty(),
+        "The CA bundle '{}' has no certificates",
+        ca_bundle
+    );
+    create_or_replace(client, &agent_ca_bundle(&bundle, system_roots))
+        .await
+        .with_context(|| format!("Unable to create the CA bundle '{}'", AGENT_CA_BUNDLE))?;
+    Ok(())
+}
+
+/// The PEM bundle under [`CA_BUNDLE_FILE`] in the `ConfigMap`, or `secret/<name>`, `ca_bundle`.
+async fn read_ca_bundle(client: kube::Client, ca_bundle: &str) -> Result<String> {
+    let bundle = match ca_bundle.strip_prefix("secret/") {
+        Some(secret_name) => Api::<Secret>::namespaced(client, NAMESPACE)
+            .get(secret_name)
+            .await
+            .with_context(|| format!("Unable to get the CA bundle '{}'", ca_bundle))?
+            .data
+            .and_then(|data| data.get(CA_BUNDLE_FILE).cloned())
+            .map(|bundle| String::from_utf8_lossy(&bundle.0).into_owned()),
+        None => Api::<ConfigMap>::namespaced(client, NAMESPACE)
+            .get(ca_bundle.trim_start_matches("configmap/"))
+            .await
+            .with_context(|| format!("Unable to get the CA bundle '{}'", ca_bundle))?
+            .data
+            .and_then(|mut data| data.remove(CA_BUNDLE_FILE)),
+    };
+    bundle.with_context(|| format!("The CA bundle '{}' has no '{}'", ca_bundle, CA_BUNDLE_FILE))
+}
+
+fn agent_ca_bundle(bundle: &str, system_roots: &str) -> ConfigMap {
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(AGENT_CA_BUNDLE.to_owned()),
+            namespace: Some(NAMESPACE.to_owned()),
+            ..ObjectMeta::default()
+        },
+        data: Some(BTreeMap::from([(
+            CA_BUNDLE_FILE.to_owned(),
+            format!("{}\n{}", bundle.trim_end(), system_roots),
+        )])),
+        ..ConfigMap::default()
+    }
+}
+
+#[tokio::test]
+async fn ca_bundle_is_published_with_system_roots() {
+    use k8s_openapi::ByteString;
+
+    let certificate = rcgen::generate_simple_self_signed(vec!["ca.internal".to_string()])
+        .and_then(|certificate| certificate.serialize_pem())
+        .unwrap_or_default();
+    let secret = Secret {
+        metadata: ObjectMeta {
+            name: Some("internal-ca".to_string()),
+            namespace: Some(NAMESPACE.to_string()),
+            ..ObjectMeta::default()
+        },
+        data: Some(BTreeMap::from([(
+            CA_BUNDLE_FILE.to_string(),
+            ByteString(certificate.clone().into_bytes()),
+        )])),
+        ..Secret::default()
+    };
+    let client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(secret)]);
+    let published = async {
+        publish(client.clone(), "secret/internal-ca", "SYSTEM ROOTS\n").await?;
+        Ok::<_, anyhow::Error>(
+            Api::<ConfigMap>::namespaced(client.clone(), NAMESPACE)
+                .get(AGENT_CA_BUNDLE)
+                .await?,
+        )
+    }
+    .await;
+    assert!(matches!(
+        published,
+        Ok(ConfigMap { data: Some(data), .. })
+            if data.get(CA_BUNDLE_FILE) == Some(&format!("{}\nSYSTEM ROOTS\n", certificate.trim_end()))
+    ));
+
+    // A bundle without certificates is not published.
+    let empty = ConfigMap {
+        metadata: ObjectMeta {
+            name: Some("empty-ca".to_string()),
+            namespace: Some(NAMESPACE.to_string()),
+            ..ObjectMeta::default()
+        },
+        data: Some(BTreeMap::from([(
+            CA_BUNDLE_FILE.to_string(),
+            String::new(),
+        )])),
+        ..ConfigMap::default()
+    };
+    let client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(empty)]);
+    assert!(publish(client, "empty-ca", "SYSTEM ROOTS\n").await.is_err());
+}
@@ -0,0 +1,115 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use snafu::{OptionExt, Snafu};
+use testsys_model::Agent;
+
+/// An agent environment variable refers to metadata that does not exist.
+#[derive(Debug, Snafu)]
+#[snafu(display("Unable to resolve environment variable '{}': {}", name, reason))]
+pub(crate) struct EnvTemplateError {
+    name: String,
+    reason: String,
+}
+
+/// Resolve the agent's environment variables, replacing each `{{ .name }}`, `{{ .labels.<key> }}`
+/// and `{{ .annotations.<key> }}` in their values with the name, label or annotation from
+/// `metadata`. Any other reference, or a reference to a label or annotation that does not exist, is
+/// an error.
+pub(crate) fn resolve_env<'a>(
+    agent: &'a Agent,
+    metadata: &ObjectMeta,
+) -> Result<Vec<(&'a str, String)>, EnvTemplateError> {
+    agent
+        .env
+        .iter()
+        .flatten()
+        .map(|(name, template)| Ok((name.as_str(), resolve(name, template, metadata)?)))
+        .collect()
+}
+
+fn resolve(name: &str, template: &str, metadata: &ObjectMeta) -> Result<String, EnvTemplateError> {
+    let mut resolved = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        resolved.push_str(&rest[..start]);
+        let end = rest[start..].find("}}").context(EnvTemplateSnafu {
+            name,
+            reason: "a '{{' is not closed by '}}'",
+        })?;
+        let reference = rest[start + 2..start + end].trim();
+        resolved.push_str(
+            &lookup(reference, metadata).with_context(|| EnvTemplateSnafu {
+                name,
+                reason: format!("'{}' does not refer to existing metadata", reference),
+            })?,
+        );
+        rest = &rest[start + end + 2..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+fn lookup(reference: &str, metadata: &ObjectMeta) -> Option<String> {
+    let reference = reference.strip_prefix('.')?;
+    if reference == "name" {
+        return metadata.name.clone();
+    }
+    let (map, key) = reference.split_once('.')?;
+    match map {
+        "labels" => metadata.labels.as_ref()?.get(key).cloned(),
+        "annotations" => metadata.annotations.as_ref()?.get(key).cloned(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+fn env_template_test_metadata() -> ObjectMeta {
+    ObjectMeta {
+        name: Some("my-test".to_string()),
+        labels: Some([("region".to_string(), "us-west-2".to_string())].into()),
+        annotations: Some([("owner".to_string(), "team-a".to_string())].into()),
+        ..ObjectMeta::default()
+    }
+}
+
+#[test]
+fn env_template_substitution() {
+    let agent = Agent {
+        env: Some(
+            [
+                ("REGION".to_string(), "{{ .labels.region }}".to_string()),
+                (
+                    "RUN".to_string(),
+                    "{{.name}} for {{ .annotations.owner }}".to_string(),
+                ),
+                ("PLAIN".to_string(), "value".to_string()),
+            ]
+            .into(),
+        ),
+        ..Agent::default()
+    };
+    let env = resolve_env(&agent, &env_template_test_metadata()).unwrap_or_default();
+    assert_eq!(
+        env,
+        vec![
+            ("PLAIN", "value".to_string()),
+            ("REGION", "us-west-2".to_string()),
+            ("RUN", "my-test for team-a".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn env_template_unresolved_reference() {
+    for template in ["{{ .labels.zone }}", "{{ .spec.agent }}", "{{ .name"] {
+        let agent = Agent {
+            env: Some([("ZONE".to_string(), template.to_string())].into()),
+            ..Agent::default()
+        };
+        let result = resolve_env(&agent, &env_template_test_metadata());
+        assert!(
+            matches!(&result, Err(EnvTemplateError { name, .. }) if name == "ZONE"),
+            "{:?}",
+            result
+        );
+    }
+}
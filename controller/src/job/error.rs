@@ -13,6 +13,26 @@ pub(crate) enum JobError {
     #[snafu(display("Unable to create job: {}", source))]
     Create { source: kube::Error },
 
+    #[snafu(display(
+        "Unable to create the agent config map of job '{}': {}",
+        job_name,
+        source
+    ))]
+    CreateAgentConfig {
+        job_name: String,
+        source: kube::Error,
+    },
+
+    #[snafu(display(
+        "Unable to create the headless service of job '{}': {}",
+        job_name,
+        source
+    ))]
+    CreateService {
+        job_name: String,
+        source: kube::Error,
+    },
+
     #[snafu(display("Unable to create log event '{}': {:?}", log_event, source))]
     CreateLogEvent {
         log_event: String,
@@ -38,6 +58,18 @@ pub(crate) enum JobError {
     #[snafu(display("Unable to get job: {}", source))]
     Get { source: kube::Error },
 
+    #[snafu(display("Unable to list the jobs of test '{}': {}", test_uid, source))]
+    ListJobs {
+        test_uid: String,
+        source: kube::Error,
+    },
+
+    #[snafu(display("Unable to list the pods of job '{}': {}", job_name, source))]
+    ListPods {
+        job_name: String,
+        source: kube::Error,
+    },
+
     #[snafu(display("Unable to read logs for pod '{}': {}", pod, source))]
     NoLogs { pod: String, source: kube::Error },
 
@@ -1,19 +1,89 @@
-use crate::job::error::{JobError, JobResult};
+use crate::config::ControllerConfig;
+use crate::job::ca_bundle::AGENT_CA_BUNDLE;
+use crate::job::delete_job;
+use crate::job::error::{CreateAgentConfigSnafu, CreateServiceSnafu, JobError, JobResult};
+use http::StatusCode;
 use k8s_openapi::api::batch::v1::{Job, JobSpec};
 use k8s_openapi::api::core::v1::{
-    Capabilities, Container, EnvVar, LocalObjectReference, PodSpec, PodTemplateSpec,
-    SecretVolumeSource, SecurityContext, Volume, VolumeMount,
+    Capabilities, ConfigMap, ConfigMapVolumeSource, Container, ContainerPort, EnvVar, ExecAction,
+    HTTPGetAction, HostAlias, KeyToPath, LocalObjectReference, PersistentVolumeClaimVolumeSource,
+    PodDNSConfig, PodDNSConfigOption, PodSchedulingGate, PodSecurityContext, PodSpec,
+    PodTemplateSpec, Probe, ResourceRequirements, SeccompProfile, SecretVolumeSource,
+    SecurityContext, Service, ServicePort, ServiceSpec, TCPSocketAction, Toleration, Volume,
+    VolumeMount,
 };
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
-use kube::api::PostParams;
-use kube::Api;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use k8s_openapi::NamespaceResourceScope;
+use kube::api::{Patch, PatchParams, PostParams};
+use kube::{Api, Resource, ResourceExt};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use snafu::ResultExt;
+use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::fmt::Debug;
+use testsys_model::clients::HttpStatusCode;
 use testsys_model::constants::{
-    APP_COMPONENT, APP_CREATED_BY, APP_INSTANCE, APP_MANAGED_BY, APP_NAME, APP_PART_OF, CONTROLLER,
-    NAMESPACE, RESOURCE_AGENT, RESOURCE_AGENT_SERVICE_ACCOUNT, SECRETS_PATH, TESTSYS, TEST_AGENT,
+    AGENT_CONFIG_FILE, AGENT_CONFIG_PATH, ANNOTATION_INPUT_HASH, ANNOTATION_SPEC_HASH,
+    APP_COMPONENT, APP_CREATED_BY, APP_INSTANCE, APP_MANAGED_BY, APP_NAME, APP_PART_OF,
+    CA_BUNDLE_FILE, CA_BUNDLE_PATH, CONTROLLER, LABEL_TEST_UID, NAMESPACE, RESOURCE_AGENT,
+    RESOURCE_AGENT_SERVICE_ACCOUNT, RESOURCE_OUTPUTS_PATH, SECRETS_PATH, TESTSYS, TEST_AGENT,
     TEST_AGENT_SERVICE_ACCOUNT,
 };
-use testsys_model::Agent;
+use testsys_model::{fnv1a_hash, Agent, CapacityType, Qos, RestartPolicy};
+#[cfg(test)]
+use testsys_model::{ContainerResources, PersistentVolumeMount, PortProtocol};
+
+/// The number of times a failed agent container is restarted in place when the agent's restart
+/// policy is `OnFailure`.
+const ON_FAILURE_BACKOFF_LIMIT: i32 = 3;
+
+/// The number of times a failed resource agent pod is replaced before its job fails. Provisioning
+/// often fails transiently, while test agents fail fast.
+const RESOURCE_AGENT_BACKOFF_LIMIT: i32 = 2;
+
+/// The prefix of the pod annotation that sets the AppArmor profile of the container named by the
+/// rest of the annotation's key.
+const APP_ARMOR_ANNOTATION_PREFIX: &str = "container.apparmor.security.beta.kubernetes.io/";
+
+/// The well-known node label with the node's CPU architecture.
+const ARCH_LABEL: &str = "kubernetes.io/arch";
+
+/// The node label with the capacity type of nodes provisioned by Karpenter.
+const KARPENTER_CAPACITY_TYPE_LABEL: &str = "karpenter.sh/capacity-type";
+
+/// The node label with the capacity type of the nodes of EKS managed node groups, whose values are
+/// upper case.
+const EKS_CAPACITY_TYPE_LABEL: &str = "eks.amazonaws.com/capacityType";
+
+/// The name of the init container that waits for the test's endpoints.
+pub(crate) const ENDPOINT_WAIT_CONTAINER_NAME: &str = "wait-for-endpoints";
+
+/// The CPU and memory that the init container that waits for the test's endpoints requests, which
+/// are also its limits.
+const ENDPOINT_WAIT_CPU: &str = "50m";
+const ENDPOINT_WAIT_MEMORY: &str = "32Mi";
+
+/// The user the init container that waits for the test's endpoints runs as, `nobody`.
+const ENDPOINT_WAIT_USER: i64 = 65534;
+
+/// The image of the init container that waits for the test's endpoints if the controller is not
+/// configured with one.
+const DEFAULT_ENDPOINT_WAIT_IMAGE: &str = "public.ecr.aws/docker/library/busybox:stable";
+
+/// Polls each URL it is given as an argument, in order, until it responds.
+const ENDPOINT_WAIT_SCRIPT: &str = r#"for url in "$@"; do
+  until wget -q -T 5 -O /dev/null "$url"; do echo "Waiting for $url"; sleep 5; done
+done"#;
+
+/// The name of the agent container if the agent's name has nothing that can be used.
+const DEFAULT_CONTAINER_NAME: &str = "agent";
+
+/// Container names are DNS labels, which are at most 63 characters long.
+const MAX_CONTAINER_NAME_LEN: usize = 63;
 
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum JobType {
@@ -21,80 +91,303 @@ pub(crate) enum JobType {
     ResourceAgent,
 }
 
+/// The controller-wide settings that every agent job is built with.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct JobSettings {
+    /// Labels that the job and pod always have, whatever the agent's `pod_labels` are.
+    pub(crate) protected_labels: BTreeMap<String, String>,
+    /// The node label that selects the capacity type of nodes, [`KARPENTER_CAPACITY_TYPE_LABEL`]
+    /// if `None`.
+    pub(crate) capacity_type_label: Option<String>,
+    /// The `ConfigMap`, or `secret/<name>`, with the controller's CA bundle. If there is one,
+    /// [`AGENT_CA_BUNDLE`] is mounted in [`CA_BUNDLE_PATH`].
+    pub(crate) ca_bundle: Option<String>,
+    /// The image of the init container that waits for a test's endpoints,
+    /// [`DEFAULT_ENDPOINT_WAIT_IMAGE`] if `None`.
+    pub(crate) endpoint_wait_image: Option<String>,
+}
+
+impl JobSettings {
+    pub(crate) fn new(config: &ControllerConfig) -> Self {
+        Self {
+            protected_labels: config.protected_labels.clone(),
+            capacity_type_label: config.capacity_type_label.clone(),
+            ca_bundle: config.ca_bundle.clone(),
+            endpoint_wait_image: config.endpoint_wait_image.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct JobBuilder<'a> {
     pub(crate) agent: &'a Agent,
     pub(crate) job_name: &'a str,
     pub(crate) job_type: JobType,
+    /// The agent's own `env` followed by the variables that identify the test or resource to the
+    /// agent. A variable replaces an earlier one with the same name, so the agent's `env` can
+    /// override the controller's defaults, like the CA bundle, but not the identifying variables.
     pub(crate) environment_variables: Vec<(&'a str, String)>,
+    /// The name of the `Secret` with the outputs of the test's resources, which is mounted in
+    /// [`RESOURCE_OUTPUTS_PATH`].
+    pub(crate) resource_outputs: Option<&'a str>,
+    pub(crate) settings: &'a JobSettings,
+    /// The memory limit of the agent container instead of the one in its `container_resources`,
+    /// after it ran out of memory with that one.
+    pub(crate) memory_limit: Option<&'a str>,
+    /// URLs that an init container polls until each of them responds, before the agent starts.
+    pub(crate) wait_for_endpoints: &'a [String],
+    /// The [`input_hash`] of the agent that the pod is annotated with. It is not part of the spec
+    /// hash, so a change of the resource outputs alone does not replace the agent's jobs.
+    pub(crate) input_hash: Option<String>,
+    /// The uid of the test whose agent the job runs. The job, but not its pod, is labeled with it
+    /// so that all of the test's jobs can be found, including those of agents since removed from
+    /// its spec.
+    pub(crate) test_uid: Option<&'a str>,
 }
 
 impl JobBuilder<'_> {
     pub(crate) async fn deploy(self, client: kube::Client) -> JobResult<Job> {
+        let job_name = self.job_name.to_owned();
+        // The agent's config and headless service are in place before its pods start, which wait
+        // for neither of them.
+        let config_map = self
+            .agent
+            .config_blob
+            .clone()
+            .map(|config_blob| agent_config(&job_name, config_blob));
+        if let Some(config_map) = &config_map {
+            create_or_replace(client.clone(), config_map)
+                .await
+                .context(CreateAgentConfigSnafu {
+                    job_name: &job_name,
+                })?;
+        }
+        let service = headless_service(self.agent, self.job_name);
+        if let Some(service) = &service {
+            create_or_replace(client.clone(), service)
+                .await
+                .context(CreateServiceSnafu {
+                    job_name: &job_name,
+                })?;
+        }
         let job = self.build();
-        let api: Api<Job> = Api::namespaced(client, NAMESPACE);
-        api.create(&PostParams::default(), &job)
+        let api: Api<Job> = Api::namespaced(client.clone(), NAMESPACE);
+        let job = api
+            .create(&PostParams::default(), &job)
             .await
-            .map_err(JobError::create)
+            .map_err(JobError::create)?;
+        // Without their owner the config and service would outlive the job, so the job is deployed
+        // again instead of being left without them.
+        if let Err(e) = adopt_dependents(
+            client.clone(),
+            &job,
+            config_map.is_some(),
+            service.is_some(),
+        )
+        .await
+        {
+            if let Err(delete_error) = delete_job(client, &job_name).await {
+                warn!(
+                    "Unable to delete job '{}' after its dependents could not be created: {}",
+                    job_name, delete_error
+                );
+            }
+            return Err(e);
+        }
+        Ok(job)
+    }
+
+    /// The hash of the spec of the job that would be deployed, which the job is annotated with.
+    pub(crate) fn spec_hash(self) -> String {
+        job_spec_hash(&self.build()).unwrap_or_default().to_string()
     }
 
     fn build(self) -> Job {
-        let vars = env_vars(self.environment_variables);
-        let labels = create_labels(self.job_type, &self.agent.name, self.job_name);
+        let ca_bundle = self.settings.ca_bundle.as_deref();
+        let capacity_type_label = self.settings.capacity_type_label.as_deref();
+        // The CA bundle variables are controller defaults that the agent's `env` can override.
+        let mut environment_variables = Vec::new();
+        if ca_bundle.is_some() {
+            let ca_bundle_file = format!("{}/{}", CA_BUNDLE_PATH, CA_BUNDLE_FILE);
+            environment_variables.push((SSL_CERT_FILE, ca_bundle_file.clone()));
+            environment_variables.push((AWS_CA_BUNDLE, ca_bundle_file));
+        }
+        environment_variables.extend(self.environment_variables);
+        let vars = env_vars(environment_variables);
+        let mut labels = job_labels(
+            self.agent,
+            create_labels(self.job_type, &self.agent.name, self.job_name),
+            &self.settings.protected_labels,
+        );
+        let container_name = container_name(&self.agent.name);
         // Set up the container's security context
-        let security_context = Some(SecurityContext {
-            capabilities: self.agent.capabilities.as_ref().map(|c| Capabilities {
-                add: Some(c.to_owned()),
-                ..Capabilities::default()
-            }),
-            privileged: self.agent.privileged,
-            ..SecurityContext::default()
+        let security_context =
+            Some(SecurityContext {
+                capabilities: self.agent.capabilities.as_ref().map(|c| Capabilities {
+                    add: Some(c.to_owned()),
+                    ..Capabilities::default()
+                }),
+                privileged: self.agent.privileged,
+                seccomp_profile: self.agent.seccomp_profile.as_ref().map(|profile| {
+                    SeccompProfile {
+                        type_: profile.profile_type.to_string(),
+                        localhost_profile: profile.localhost_profile.to_owned(),
+                    }
+                }),
+                ..SecurityContext::default()
+            });
+        let pod_annotations = self.agent.app_armor_profile.as_ref().map(|profile| {
+            BTreeMap::from([(
+                format!("{}{}", APP_ARMOR_ANNOTATION_PREFIX, container_name),
+                profile.to_owned(),
+            )])
         });
+        // Set up the pod's security context if the agent needs one
+        let pod_security_context =
+            if self.agent.fs_group.is_some() || self.agent.fs_group_change_policy.is_some() {
+                Some(PodSecurityContext {
+                    fs_group: self.agent.fs_group,
+                    fs_group_change_policy: self.agent.fs_group_change_policy.to_owned(),
+                    ..PodSecurityContext::default()
+                })
+            } else {
+                None
+            };
 
+        let mut spec = JobSpec {
+            backoff_limit: Some(backoff_limit(self.agent, self.job_type)),
+            completions: self.agent.completions,
+            parallelism: self.agent.completions,
+            completion_mode: self.agent.completions.map(|_| "Indexed".to_string()),
+            template: PodTemplateSpec {
+                spec: Some(PodSpec {
+                    containers: vec![Container {
+                        name: container_name,
+                        image: Some(self.agent.image.to_owned()),
+                        env: if vars.is_empty() { None } else { Some(vars) },
+                        volume_mounts: mounts(self.agent, self.resource_outputs, ca_bundle),
+                        security_context,
+                        resources: resources(self.agent, self.memory_limit),
+                        startup_probe: self.agent.startup_probe.as_ref().map(probe),
+                        readiness_probe: self.agent.readiness_probe.as_ref().map(probe),
+                        ports: container_ports(self.agent),
+                        ..Container::default()
+                    }],
+                    init_containers: endpoint_wait_containers(
+                        self.wait_for_endpoints,
+                        self.settings.endpoint_wait_image.as_deref(),
+                        ca_bundle,
+                    ),
+                    restart_policy: Some(self.agent.restart_policy.to_string()),
+                    image_pull_secrets: self.agent.pull_secret.as_ref().map(|secret| {
+                        vec![LocalObjectReference {
+                            name: Some(secret.into()),
+                        }]
+                    }),
+                    service_account: Some(self.agent.service_account.to_owned().unwrap_or_else(
+                        || match self.job_type {
+                            JobType::TestAgent => TEST_AGENT_SERVICE_ACCOUNT.to_owned(),
+                            JobType::ResourceAgent => RESOURCE_AGENT_SERVICE_ACCOUNT.to_owned(),
+                        },
+                    )),
+                    volumes: volumes(self.agent, self.job_name, self.resource_outputs, ca_bundle),
+                    host_aliases: host_aliases(self.agent),
+                    dns_config: dns_config(self.agent),
+                    node_selector: node_selector(self.agent, capacity_type_label),
+                    tolerations: tolerations(self.agent, capacity_type_label),
+                    scheduling_gates: self.agent.scheduling_gates.as_ref().map(|gates| {
+                        gates
+                            .iter()
+                            .map(|name| PodSchedulingGate {
+                                name: name.to_owned(),
+                            })
+                            .collect()
+                    }),
+                    security_context: pod_security_context,
+                    // The pods get DNS names under the headless service.
+                    subdomain: headless_service(self.agent, self.job_name)
+                        .map(|_| self.job_name.to_owned()),
+                    ..PodSpec::default()
+                }),
+                metadata: Some(ObjectMeta {
+                    labels: Some(labels.clone()),
+                    annotations: pod_annotations,
+                    ..ObjectMeta::default()
+                }),
+            },
+            ..JobSpec::default()
+        };
+        let spec_hash = spec_hash(&spec);
+        if let (Some(input_hash), Some(metadata)) =
+            (self.input_hash, spec.template.metadata.as_mut())
+        {
+            metadata
+                .annotations
+                .get_or_insert_with(BTreeMap::new)
+                .insert(ANNOTATION_INPUT_HASH.to_string(), input_hash);
+        }
+        if let Some(test_uid) = self.test_uid {
+            labels.insert(LABEL_TEST_UID.to_string(), test_uid.to_string());
+        }
         Job {
             metadata: ObjectMeta {
                 name: Some(self.job_name.into()),
                 namespace: Some(NAMESPACE.to_owned()),
-                labels: Some(labels.clone()),
+                labels: Some(labels),
+                annotations: Some(BTreeMap::from([(
+                    ANNOTATION_SPEC_HASH.to_string(),
+                    spec_hash,
+                )])),
                 ..ObjectMeta::default()
             },
-            spec: Some(JobSpec {
-                backoff_limit: Some(0),
-                template: PodTemplateSpec {
-                    spec: Some(PodSpec {
-                        containers: vec![Container {
-                            name: self.job_name.into(),
-                            image: Some(self.agent.image.to_owned()),
-                            env: if vars.is_empty() { None } else { Some(vars) },
-                            volume_mounts: mounts(self.agent),
-                            security_context,
-                            ..Container::default()
-                        }],
-                        restart_policy: Some(String::from("Never")),
-                        image_pull_secrets: self.agent.pull_secret.as_ref().map(|secret| {
-                            vec![LocalObjectReference {
-                                name: Some(secret.into()),
-                            }]
-                        }),
-                        service_account: Some(match self.job_type {
-                            JobType::TestAgent => TEST_AGENT_SERVICE_ACCOUNT.to_owned(),
-                            JobType::ResourceAgent => RESOURCE_AGENT_SERVICE_ACCOUNT.to_owned(),
-                        }),
-                        volumes: volumes(self.agent),
-                        ..PodSpec::default()
-                    }),
-                    metadata: Some(ObjectMeta {
-                        labels: Some(labels),
-                        ..ObjectMeta::default()
-                    }),
-                },
-                ..JobSpec::default()
-            }),
+            spec: Some(spec),
             ..Job::default()
         }
     }
 }
 
+/// The name of the agent container, which is the agent's name made into a valid DNS label so that
+/// the pod is admitted however the agent and the job are named.
+pub(crate) fn container_name(agent_name: &str) -> String {
+    let name: String = agent_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(MAX_CONTAINER_NAME_LEN)
+        .collect();
+    // The name must start and end with an alphanumeric character.
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        DEFAULT_CONTAINER_NAME.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// A hash of the job's spec, see [`fnv1a_hash`].
+fn spec_hash(spec: &JobSpec) -> String {
+    hex_hash(&serde_json::to_vec(spec).unwrap_or_default())
+}
+
+/// A hash of the agent's resolved `env` and of the serialized resource outputs that it is given,
+/// which only changes when the agent's inputs do.
+pub(crate) fn input_hash(env: &[(&str, String)], resource_outputs: &str) -> String {
+    hex_hash(&serde_json::to_vec(&(env, resource_outputs)).unwrap_or_default())
+}
+
+fn hex_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", fnv1a_hash(bytes))
+}
+
+/// The hash of the spec the job was built with, if it was annotated with one.
+pub(crate) fn job_spec_hash(job: &Job) -> Option<&str> {
+    job.metadata
+        .annotations
+        .as_ref()?
+        .get(ANNOTATION_SPEC_HASH)
+        .map(String::as_str)
+}
+
 /// Creates the labels that we will add to the test pod deployment.
 fn create_labels<S1, S2>(job_type: JobType, agent: S1, instance: S2) -> BTreeMap<String, String>
 where
@@ -120,51 +413,1872 @@ where
     .collect()
 }
 
-fn env_vars(raw_vars: Vec<(&str, String)>) -> Vec<EnvVar> {
-    raw_vars
+/// The labels of the job and its pod: the agent's `pod_labels`, overridden by the TestSys `labels`
+/// and the `protected_labels`.
+fn job_labels(
+    agent: &Agent,
+    labels: BTreeMap<String, String>,
+    protected_labels: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    agent
+        .pod_labels
+        .clone()
+        .unwrap_or_default()
         .into_iter()
-        .map(|(name, value)| EnvVar {
-            name: name.to_owned(),
-            value: Some(value),
-            value_from: None,
-        })
+        .chain(labels)
+        .chain(protected_labels.clone())
         .collect()
 }
 
-fn mounts(agent: &Agent) -> Option<Vec<VolumeMount>> {
-    let secrets = agent.secret_names();
-    if secrets.is_empty() {
+/// The number of failed pods the job tolerates, unless the agent overrides it the default depends
+/// on the type of agent: a failed resource agent pod is replaced a few times, while a test agent
+/// fails fast. A job that runs indexed completions tolerates at least one failure for each of its
+/// completions, so that a failed completion does not make k8s fail the job and stop the others,
+/// and whether enough of them succeeded is decided by the agent's success threshold. A job whose
+/// containers are restarted in place allows each of its completions the restarts of a single
+/// container.
+fn backoff_limit(agent: &Agent, job_type: JobType) -> i32 {
+    let limit = agent
+        .backoff_limit
+        .unwrap_or(match (agent.restart_policy, job_type) {
+            (RestartPolicy::OnFailure, _) => ON_FAILURE_BACKOFF_LIMIT,
+            (RestartPolicy::Never, JobType::TestAgent) => 0,
+            (RestartPolicy::Never, JobType::ResourceAgent) => RESOURCE_AGENT_BACKOFF_LIMIT,
+        });
+    match agent.completions {
+        Some(completions) => completions.saturating_mul(limit.max(1)),
+        None => limit,
+    }
+}
+
+/// The container's variables, each name once. A variable keeps the position where its name first
+/// appears and the value of its last occurrence, because k8s does not define which of several
+/// variables with the same name a container sees.
+fn env_vars(raw_vars: Vec<(&str, String)>) -> Vec<EnvVar> {
+    let mut vars: Vec<EnvVar> = Vec::with_capacity(raw_vars.len());
+    for (name, value) in raw_vars {
+        match vars.iter_mut().find(|var| var.name == name) {
+            Some(var) => var.value = Some(value),
+            None => vars.push(EnvVar {
+                name: name.to_owned(),
+                value: Some(value),
+                value_from: None,
+            }),
+        }
+    }
+    vars
+}
+
+/// The init container that holds the agent back until each of the `endpoints` responds. It trusts
+/// the controller's `ca_bundle` like the agent does, and runs unprivileged with a small, fixed
+/// amount of CPU and memory.
+fn endpoint_wait_containers(
+    endpoints: &[String],
+    image: Option<&str>,
+    ca_bundle: Option<&str>,
+) -> Option<Vec<Container>> {
+    if endpoints.is_empty() {
         return None;
     }
-    Some(
-        secrets
-            .iter()
-            .map(|&name| VolumeMount {
-                mount_path: format!("{}/{}", SECRETS_PATH, name),
-                name: name.as_str().into(),
+    let ca_bundle_file = format!("{}/{}", CA_BUNDLE_PATH, CA_BUNDLE_FILE);
+    let quantities = BTreeMap::from([
+        ("cpu".to_owned(), Quantity(ENDPOINT_WAIT_CPU.to_owned())),
+        (
+            "memory".to_owned(),
+            Quantity(ENDPOINT_WAIT_MEMORY.to_owned()),
+        ),
+    ]);
+    Some(vec![Container {
+        name: ENDPOINT_WAIT_CONTAINER_NAME.to_owned(),
+        image: Some(image.unwrap_or(DEFAULT_ENDPOINT_WAIT_IMAGE).to_owned()),
+        // The endpoints are passed as arguments so that they are never interpreted by the shell.
+        command: Some(vec![
+            "sh".to_owned(),
+            "-c".to_owned(),
+            ENDPOINT_WAIT_SCRIPT.to_owned(),
+            ENDPOINT_WAIT_CONTAINER_NAME.to_owned(),
+        ]),
+        args: Some(endpoints.to_vec()),
+        env: ca_bundle.map(|_| {
+            env_vars(vec![
+                (SSL_CERT_FILE, ca_bundle_file.clone()),
+                (AWS_CA_BUNDLE, ca_bundle_file),
+            ])
+        }),
+        volume_mounts: ca_bundle.map(|_| {
+            vec![VolumeMount {
+                mount_path: CA_BUNDLE_PATH.to_owned(),
+                name: CA_BUNDLE_VOLUME_NAME.to_owned(),
                 read_only: Some(true),
                 ..VolumeMount::default()
+            }]
+        }),
+        resources: Some(ResourceRequirements {
+            limits: Some(quantities.clone()),
+            requests: Some(quantities),
+            ..ResourceRequirements::default()
+        }),
+        security_context: Some(SecurityContext {
+            allow_privilege_escalation: Some(false),
+            capabilities: Some(Capabilities {
+                drop: Some(vec!["ALL".to_owned()]),
+                ..Capabilities::default()
+            }),
+            privileged: Some(false),
+            read_only_root_filesystem: Some(true),
+            run_as_non_root: Some(true),
+            run_as_user: Some(ENDPOINT_WAIT_USER),
+            seccomp_profile: Some(SeccompProfile {
+                type_: "RuntimeDefault".to_owned(),
+                localhost_profile: None,
+            }),
+            ..SecurityContext::default()
+        }),
+        ..Container::default()
+    }])
+}
+
+/// Only the limits and requests that were provided are set so that the cluster's `LimitRange`
+/// defaults apply to the rest.
+fn resources(agent: &Agent, memory_limit: Option<&str>) -> Option<ResourceRequirements> {
+    let quantities = |amounts: &Option<BTreeMap<String, String>>| {
+        amounts.as_ref().map(|amounts| {
+            amounts
+                .iter()
+                .map(|(resource, amount)| (resource.to_owned(), Quantity(amount.to_owned())))
+                .collect()
+        })
+    };
+    let mut container_resources = Cow::Borrowed(agent.container_resources.as_ref()?);
+    if let Some(memory_limit) = memory_limit {
+        container_resources
+            .to_mut()
+            .limits
+            .get_or_insert_with(BTreeMap::new)
+            .insert("memory".to_string(), memory_limit.to_string());
+    }
+    if agent.qos == Some(Qos::Guaranteed) {
+        container_resources = Cow::Owned(container_resources.guaranteed());
+    }
+    Some(ResourceRequirements {
+        limits: quantities(&container_resources.limits),
+        requests: quantities(&container_resources.requests),
+        ..ResourceRequirements::default()
+    })
+}
+
+fn probe(probe: &testsys_model::Probe) -> Probe {
+    Probe {
+        exec: probe.exec.as_ref().map(|command| ExecAction {
+            command: Some(command.to_owned()),
+        }),
+        http_get: probe.http_get.as_ref().map(|http_get| HTTPGetAction {
+            path: Some(http_get.path.to_owned()),
+            port: IntOrString::Int(http_get.port),
+            ..HTTPGetAction::default()
+        }),
+        tcp_socket: probe.tcp_socket_port.map(|port| TCPSocketAction {
+            port: IntOrString::Int(port),
+            ..TCPSocketAction::default()
+        }),
+        initial_delay_seconds: probe.initial_delay_seconds,
+        period_seconds: probe.period_seconds,
+        timeout_seconds: probe.timeout_seconds,
+        failure_threshold: probe.failure_threshold,
+        ..Probe::default()
+    }
+}
+
+fn node_selector(
+    agent: &Agent,
+    capacity_type_label: Option<&str>,
+) -> Option<BTreeMap<String, String>> {
+    let mut node_selector = agent.node_selector.to_owned().unwrap_or_default();
+    if let Some(arch) = &agent.arch {
+        node_selector.insert(ARCH_LABEL.to_string(), arch.to_owned());
+    }
+    if let Some((label, value)) = capacity_type_selector(agent, capacity_type_label) {
+        node_selector.insert(label.to_string(), value.to_string());
+    }
+    Some(node_selector).filter(|node_selector| !node_selector.is_empty())
+}
+
+/// The node label and value that select nodes of the agent's capacity type, if it has one.
+fn capacity_type_selector<'a>(
+    agent: &Agent,
+    capacity_type_label: Option<&'a str>,
+) -> Option<(&'a str, &'static str)> {
+    let label = capacity_type_label.unwrap_or(KARPENTER_CAPACITY_TYPE_LABEL);
+    let value = match (agent.capacity_type?, label == EKS_CAPACITY_TYPE_LABEL) {
+        (CapacityType::Spot, false) => "spot",
+        (CapacityType::OnDemand, false) => "on-demand",
+        (CapacityType::Spot, true) => "SPOT",
+        (CapacityType::OnDemand, true) => "ON_DEMAND",
+    };
+    Some((label, value))
+}
+
+/// Agents that run on spot capacity tolerate spot nodes that are tainted with the capacity type
+/// label so that only workloads that can be interrupted are scheduled on them.
+fn tolerations(agent: &Agent, capacity_type_label: Option<&str>) -> Option<Vec<Toleration>> {
+    let (label, value) = capacity_type_selector(agent, capacity_type_label)?;
+    (agent.capacity_type == Some(CapacityType::Spot)).then(|| {
+        vec![Toleration {
+            key: Some(label.to_string()),
+            operator: Some("Equal".to_string()),
+            value: Some(value.to_string()),
+            effect: Some("NoSchedule".to_string()),
+            ..Toleration::default()
+        }]
+    })
+}
+
+/// The agent's DNS config with its `dns_search_domains` appended to its searches. The pod keeps the
+/// default `ClusterFirst` DNS policy, so these settings are added to those of the cluster's DNS.
+fn dns_config(agent: &Agent) -> Option<PodDNSConfig> {
+    let dns_config = agent.dns_config.to_owned().unwrap_or_default();
+    let mut searches = dns_config.searches.unwrap_or_default();
+    for domain in agent.dns_search_domains.iter().flatten() {
+        if !searches.contains(domain) {
+            searches.push(domain.to_owned());
+        }
+    }
+    let pod_dns_config = PodDNSConfig {
+        nameservers: dns_config
+            .nameservers
+            .filter(|nameservers| !nameservers.is_empty()),
+        searches: Some(searches).filter(|searches| !searches.is_empty()),
+        options: dns_config
+            .options
+            .filter(|options| !options.is_empty())
+            .map(|options| {
+                options
+                    .into_iter()
+                    .map(|option| PodDNSConfigOption {
+                        name: Some(option.name),
+                        value: option.value,
+                    })
+                    .collect()
+            }),
+    };
+    Some(pod_dns_config).filter(|pod_dns_config| pod_dns_config != &PodDNSConfig::default())
+}
+
+fn host_aliases(agent: &Agent) -> Option<Vec<HostAlias>> {
+    agent
+        .host_aliases
+        .as_ref()
+        .filter(|host_aliases| !host_aliases.is_empty())
+        .map(|host_aliases| {
+            host_aliases
+                .iter()
+                .map(|host_alias| HostAlias {
+                    ip: Some(host_alias.ip.to_owned()),
+                    hostnames: Some(host_alias.hostnames.to_owned()),
+                })
+                .collect()
+        })
+}
+
+/// The name of the pod volume for the outputs of the test's resources.
+const RESOURCE_OUTPUTS_VOLUME_NAME: &str = "resource-outputs";
+
+/// The name of the pod volume for the agent's `config_blob`.
+const AGENT_CONFIG_VOLUME_NAME: &str = "agent-config";
+
+/// The name of the `ConfigMap` with the `config_blob` of the agent that the job `job_name` runs.
+fn agent_config_name(job_name: &str) -> String {
+    format!("{}-{}", job_name, AGENT_CONFIG_VOLUME_NAME)
+}
+
+/// The `ConfigMap` with the `config_blob` of the agent that the job `job_name` runs, which is
+/// mounted in the agent's pod.
+fn agent_config(
+    job_name: &str,
+    config_blob: serde_json::Map<String, serde_json::Value>,
+) -> ConfigMap {
+    ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(agent_config_name(job_name)),
+            namespace: Some(NAMESPACE.to_owned()),
+            ..ObjectMeta::default()
+        },
+        data: Some(BTreeMap::from([(
+            AGENT_CONFIG_FILE.to_owned(),
+            format!("{:#}", serde_json::Value::Object(config_blob)),
+        )])),
+        ..ConfigMap::default()
+    }
+}
+
+/// Make the `job` the owner of the agent config and headless service that were created for it, so
+/// that they are deleted with the job.
+async fn adopt_dependents(
+    client: kube::Client,
+    job: &Job,
+    config_map: bool,
+    service: bool,
+) -> JobResult<()> {
+    let job_name = job.name_any();
+    if config_map {
+        adopt::<ConfigMap>(client.clone(), &agent_config_name(&job_name), job)
+            .await
+            .context(CreateAgentConfigSnafu {
+                job_name: &job_name,
+            })?;
+    }
+    if service {
+        adopt::<Service>(client, &job_name, job)
+            .await
+            .context(CreateServiceSnafu {
+                job_name: &job_name,
+            })?;
+    }
+    Ok(())
+}
+
+/// The ports of the agent container.
+fn container_ports(agent: &Agent) -> Option<Vec<ContainerPort>> {
+    agent.ports.as_ref().map(|ports| {
+        ports
+            .iter()
+            .map(|port| ContainerPort {
+                name: port.name.to_owned(),
+                container_port: port.container_port,
+                protocol: port.protocol.map(|protocol| protocol.to_string()),
+                ..ContainerPort::default()
             })
-            .collect(),
-    )
+            .collect()
+    })
 }
 
-fn volumes(agent: &Agent) -> Option<Vec<Volume>> {
-    let secrets = agent.secret_names();
-    if secrets.is_empty() {
+/// The headless `Service` that selects the pods of the job `job_name`, if the agent asks for one.
+/// Its addresses are published before the pods are ready so that the pods of a distributed test
+/// can find each other while they start.
+fn headless_service(agent: &Agent, job_name: &str) -> Option<Service> {
+    if !agent.headless_service.unwrap_or_default() {
         return None;
     }
-    Some(
-        secrets
+    Some(Service {
+        metadata: ObjectMeta {
+            name: Some(job_name.to_owned()),
+            namespace: Some(NAMESPACE.to_owned()),
+            ..ObjectMeta::default()
+        },
+        spec: Some(ServiceSpec {
+            cluster_ip: Some("None".to_owned()),
+            selector: Some(BTreeMap::from([(APP_NAME.to_owned(), job_name.to_owned())])),
+            ports: agent.ports.as_ref().map(|ports| {
+                ports
+                    .iter()
+                    .map(|port| ServicePort {
+                        name: port.name.to_owned(),
+                        port: port.container_port,
+                        protocol: port.protocol.map(|protocol| protocol.to_string()),
+                        target_port: Some(IntOrString::Int(port.container_port)),
+                        ..ServicePort::default()
+                    })
+                    .collect()
+            }),
+            publish_not_ready_addresses: Some(true),
+            ..ServiceSpec::default()
+        }),
+        ..Service::default()
+    })
+}
+
+/// Create the `object` that a job needs before the job, replacing the one that an earlier job with
+/// the same name left behind. That one is either owned by the earlier job, which is being deleted,
+/// or by no job if the earlier job could not be deployed, and is replaced either way since the new
+/// job may need it to be different.
+pub(crate) async fn create_or_replace<K>(client: kube::Client, object: &K) -> Result<K, kube::Error>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + Clone
+        + Debug
+        + Serialize
+        + DeserializeOwned,
+{
+    let api: Api<K> = Api::namespaced(client, NAMESPACE);
+    match api.create(&PostParams::default(), object).await {
+        Err(e) if e.is_status_code(StatusCode::CONFLICT) => {
+            let existing = api.get(&object.name_any()).await?;
+            let mut object = object.clone();
+            object.meta_mut().resource_version = existing.resource_version();
+            api.replace(&object.name_any(), &PostParams::default(), &object)
+                .await
+        }
+        result => result,
+    }
+}
+
+/// Make the `job` the owner of the object `name` that was created for it before the job, so that
+/// the object is deleted with the job.
+async fn adopt<K>(client: kube::Client, name: &str, job: &Job) -> Result<(), kube::Error>
+where
+    K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+        + Clone
+        + Debug
+        + DeserializeOwned,
+{
+    let owner = match job.controller_owner_ref(&()) {
+        Some(owner) => owner,
+        None => return Ok(()),
+    };
+    let patch: json_patch::Patch = serde_json::from_value(serde_json::json!([{
+        "op": "add",
+        "path": "/metadata/ownerReferences",
+        "value": [owner],
+    }]))
+    .map_err(kube::Error::SerdeError)?;
+    let api: Api<K> = Api::namespaced(client, NAMESPACE);
+    api.patch(name, &PatchParams::default(), &Patch::Json::<()>(patch))
+        .await
+        .map(|_| ())
+}
+
+/// The name of the pod volume for the controller's CA bundle.
+const CA_BUNDLE_VOLUME_NAME: &str = "ca-bundle";
+
+/// The environment variable that OpenSSL, and most TLS clients built on it, read CA certificates
+/// from.
+const SSL_CERT_FILE: &str = "SSL_CERT_FILE";
+
+/// The environment variable that the AWS SDKs and CLI read CA certificates from.
+const AWS_CA_BUNDLE: &str = "AWS_CA_BUNDLE";
+
+/// The pod volume for the controller's CA bundle, which is published with the system's root
+/// certificates in [`AGENT_CA_BUNDLE`]. Only the bundle's key is mounted.
+fn ca_bundle_volume() -> Volume {
+    Volume {
+        name: CA_BUNDLE_VOLUME_NAME.to_owned(),
+        config_map: Some(ConfigMapVolumeSource {
+            name: Some(AGENT_CA_BUNDLE.to_owned()),
+            items: Some(vec![KeyToPath {
+                key: CA_BUNDLE_FILE.to_owned(),
+                path: CA_BUNDLE_FILE.to_owned(),
+                mode: None,
+            }]),
+            ..ConfigMapVolumeSource::default()
+        }),
+        ..Volume::default()
+    }
+}
+
+/// The name of the pod volume for the agent's `index`th secret mount.
+fn secret_mount_volume_name(index: usize) -> String {
+    format!("secret-mount-{}", index)
+}
+
+/// The name of the pod volume for the agent's `index`th persistent volume.
+fn persistent_volume_name(index: usize) -> String {
+    format!("persistent-volume-{}", index)
+}
+
+fn mounts(
+    agent: &Agent,
+    resource_outputs: Option<&str>,
+    ca_bundle: Option<&str>,
+) -> Option<Vec<VolumeMount>> {
+    let secret_mounts = agent.secret_names().into_iter().map(|name| VolumeMount {
+        mount_path: format!("{}/{}", SECRETS_PATH, name),
+        name: name.as_str().into(),
+        read_only: Some(true),
+        ..VolumeMount::default()
+    });
+    let persistent_volume_mounts =
+        agent
+            .persistent_volumes
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, persistent_volume)| VolumeMount {
+                mount_path: persistent_volume.mount_path.to_owned(),
+                name: persistent_volume_name(index),
+                read_only: persistent_volume.read_only,
+                ..VolumeMount::default()
+            });
+    let secret_file_mounts =
+        agent
+            .secret_mounts
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, secret_mount)| VolumeMount {
+                mount_path: secret_mount.mount_path.to_owned(),
+                name: secret_mount_volume_name(index),
+                read_only: Some(true),
+                ..VolumeMount::default()
+            });
+    let resource_outputs_mount = resource_outputs.map(|_| VolumeMount {
+        mount_path: RESOURCE_OUTPUTS_PATH.to_owned(),
+        name: RESOURCE_OUTPUTS_VOLUME_NAME.to_owned(),
+        read_only: Some(true),
+        ..VolumeMount::default()
+    });
+    let agent_config_mount = agent.config_blob.as_ref().map(|_| VolumeMount {
+        mount_path: AGENT_CONFIG_PATH.to_owned(),
+        name: AGENT_CONFIG_VOLUME_NAME.to_owned(),
+        read_only: Some(true),
+        ..VolumeMount::default()
+    });
+    let ca_bundle_mount = ca_bundle.map(|_| VolumeMount {
+        mount_path: CA_BUNDLE_PATH.to_owned(),
+        name: CA_BUNDLE_VOLUME_NAME.to_owned(),
+        read_only: Some(true),
+        ..VolumeMount::default()
+    });
+    let mounts: Vec<VolumeMount> = secret_mounts
+        .chain(secret_file_mounts)
+        .chain(persistent_volume_mounts)
+        .chain(resource_outputs_mount)
+        .chain(agent_config_mount)
+        .chain(ca_bundle_mount)
+        .collect();
+    if mounts.is_empty() {
+        None
+    } else {
+        Some(mounts)
+    }
+}
+
+fn volumes(
+    agent: &Agent,
+    job_name: &str,
+    resource_outputs: Option<&str>,
+    ca_bundle: Option<&str>,
+) -> Option<Vec<Volume>> {
+    let secret_volumes = agent.secret_names().into_iter().map(|name| Volume {
+        name: name.as_str().into(),
+        secret: Some(SecretVolumeSource {
+            secret_name: Some(name.as_str().into()),
+            ..SecretVolumeSource::default()
+        }),
+        ..Volume::default()
+    });
+    let persistent_volumes =
+        agent
+            .persistent_volumes
             .iter()
-            .map(|&name| Volume {
-                name: name.as_str().into(),
+            .flatten()
+            .enumerate()
+            .map(|(index, persistent_volume)| Volume {
+                name: persistent_volume_name(index),
+                persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                    claim_name: persistent_volume.claim_name.to_owned(),
+                    read_only: persistent_volume.read_only,
+                }),
+                ..Volume::default()
+            });
+    let secret_mount_volumes =
+        agent
+            .secret_mounts
+            .iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, secret_mount)| Volume {
+                name: secret_mount_volume_name(index),
                 secret: Some(SecretVolumeSource {
-                    secret_name: Some(name.as_str().into()),
+                    secret_name: Some(secret_mount.secret_name.as_str().into()),
                     ..SecretVolumeSource::default()
                 }),
                 ..Volume::default()
-            })
+            });
+    let resource_outputs_volume = resource_outputs.map(|secret_name| Volume {
+        name: RESOURCE_OUTPUTS_VOLUME_NAME.to_owned(),
+        secret: Some(SecretVolumeSource {
+            secret_name: Some(secret_name.to_owned()),
+            ..SecretVolumeSource::default()
+        }),
+        ..Volume::default()
+    });
+    let agent_config_volume = agent.config_blob.as_ref().map(|_| Volume {
+        name: AGENT_CONFIG_VOLUME_NAME.to_owned(),
+        config_map: Some(ConfigMapVolumeSource {
+            name: Some(agent_config_name(job_name)),
+            ..ConfigMapVolumeSource::default()
+        }),
+        ..Volume::default()
+    });
+    let volumes: Vec<Volume> = secret_volumes
+        .chain(secret_mount_volumes)
+        .chain(persistent_volumes)
+        .chain(resource_outputs_volume)
+        .chain(agent_config_volume)
+        .chain(ca_bundle.map(|_| ca_bundle_volume()))
+        .collect();
+    if volumes.is_empty() {
+        None
+    } else {
+        Some(volumes)
+    }
+}
+
+/// A builder of a test agent job named `job` without any inputs, which tests override the fields
+/// they are about.
+#[cfg(test)]
+fn test_job<'a>(agent: &'a Agent, settings: &'a JobSettings) -> JobBuilder<'a> {
+    JobBuilder {
+        agent,
+        job_name: "job",
+        job_type: JobType::TestAgent,
+        environment_variables: Vec::new(),
+        resource_outputs: None,
+        settings,
+        memory_limit: None,
+        wait_for_endpoints: &[],
+        input_hash: None,
+        test_uid: None,
+    }
+}
+
+#[cfg(test)]
+fn pod_spec(agent: &Agent, job_type: JobType) -> Option<PodSpec> {
+    JobBuilder {
+        job_type,
+        ..test_job(agent, &JobSettings::default())
+    }
+    .build()
+    .spec
+    .and_then(|job_spec| job_spec.template.spec)
+}
+
+#[cfg(test)]
+fn pod_security_context(agent: &Agent) -> Option<PodSecurityContext> {
+    pod_spec(agent, JobType::TestAgent).and_then(|pod_spec| pod_spec.security_context)
+}
+
+#[test]
+fn fs_group_pod_security_context() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        fs_group: Some(2000),
+        fs_group_change_policy: Some("OnRootMismatch".into()),
+        ..Agent::default()
+    };
+    let security_context = pod_security_context(&agent);
+    assert_eq!(
+        security_context
+            .as_ref()
+            .and_then(|context| context.fs_group),
+        Some(2000)
+    );
+    assert_eq!(
+        security_context
+            .as_ref()
+            .and_then(|context| context.fs_group_change_policy.as_deref()),
+        Some("OnRootMismatch")
+    );
+}
+
+#[test]
+fn seccomp_profile_security_context() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        seccomp_profile: Some(testsys_model::SeccompProfile::default()),
+        ..Agent::default()
+    };
+    let seccomp_profile = pod_spec(&agent, JobType::TestAgent)
+        .and_then(|pod_spec| pod_spec.containers.into_iter().next())
+        .and_then(|container| container.security_context)
+        .and_then(|security_context| security_context.seccomp_profile);
+    assert_eq!(
+        seccomp_profile,
+        Some(SeccompProfile {
+            type_: "RuntimeDefault".to_string(),
+            localhost_profile: None,
+        })
+    );
+}
+
+#[test]
+fn app_armor_profile_annotation() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        app_armor_profile: Some("localhost/testsys-agent".into()),
+        ..Agent::default()
+    };
+    let annotations = JobBuilder {
+        job_type: JobType::ResourceAgent,
+        ..test_job(&agent, &JobSettings::default())
+    }
+    .build()
+    .spec
+    .and_then(|job_spec| job_spec.template.metadata)
+    .and_then(|metadata| metadata.annotations)
+    .unwrap_or_default();
+    assert_eq!(
+        annotations
+            .get("container.apparmor.security.beta.kubernetes.io/agent")
+            .map(String::as_str),
+        Some("localhost/testsys-agent")
+    );
+}
+
+#[test]
+fn no_pod_security_context() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    assert!(pod_security_context(&agent).is_none());
+}
+
+#[test]
+fn custom_service_account() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        service_account: Some("my-service-account".into()),
+        ..Agent::default()
+    };
+    for job_type in [JobType::TestAgent, JobType::ResourceAgent] {
+        assert_eq!(
+            pod_spec(&agent, job_type).and_then(|pod_spec| pod_spec.service_account),
+            Some("my-service-account".to_string())
+        );
+    }
+}
+
+#[test]
+fn default_service_account() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    assert_eq!(
+        pod_spec(&agent, JobType::TestAgent).and_then(|pod_spec| pod_spec.service_account),
+        Some(TEST_AGENT_SERVICE_ACCOUNT.to_string())
+    );
+    assert_eq!(
+        pod_spec(&agent, JobType::ResourceAgent).and_then(|pod_spec| pod_spec.service_account),
+        Some(RESOURCE_AGENT_SERVICE_ACCOUNT.to_string())
+    );
+}
+
+#[test]
+fn never_restart_policy() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    let job = test_job(&agent, &JobSettings::default()).build();
+    let job_spec = job.spec.as_ref();
+    assert_eq!(job_spec.and_then(|spec| spec.backoff_limit), Some(0));
+    assert_eq!(
+        job_spec
+            .and_then(|spec| spec.template.spec.as_ref())
+            .and_then(|pod_spec| pod_spec.restart_policy.as_deref()),
+        Some("Never")
+    );
+}
+
+#[test]
+fn on_failure_restart_policy() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        restart_policy: RestartPolicy::OnFailure,
+        ..Agent::default()
+    };
+    let job = test_job(&agent, &JobSettings::default()).build();
+    let job_spec = job.spec.as_ref();
+    assert_eq!(
+        job_spec.and_then(|spec| spec.backoff_limit),
+        Some(ON_FAILURE_BACKOFF_LIMIT)
+    );
+    assert_eq!(
+        job_spec
+            .and_then(|spec| spec.template.spec.as_ref())
+            .and_then(|pod_spec| pod_spec.restart_policy.as_deref()),
+        Some("OnFailure")
+    );
+}
+
+#[cfg(test)]
+fn container_resources(agent: &Agent) -> Option<ResourceRequirements> {
+    pod_spec(agent, JobType::TestAgent)
+        .and_then(|pod_spec| pod_spec.containers.into_iter().next())
+        .and_then(|container| container.resources)
+}
+
+#[cfg(test)]
+fn cpu_and_memory(cpu: &str, memory: &str) -> BTreeMap<String, String> {
+    [("cpu", cpu), ("memory", memory)]
+        .into_iter()
+        .map(|(resource, amount)| (resource.to_string(), amount.to_string()))
+        .collect()
+}
+
+#[test]
+fn limits_only() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        container_resources: Some(ContainerResources {
+            limits: Some(cpu_and_memory("1", "2Gi")),
+            requests: None,
+        }),
+        ..Agent::default()
+    };
+    let resources = container_resources(&agent);
+    assert_eq!(
+        resources
+            .as_ref()
+            .and_then(|resources| resources.limits.as_ref())
+            .and_then(|limits| limits.get("memory")),
+        Some(&Quantity("2Gi".to_string()))
+    );
+    assert!(resources
+        .as_ref()
+        .map(|resources| resources.requests.is_none())
+        .unwrap_or(false));
+}
+
+#[test]
+fn requests_only() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        container_resources: Some(ContainerResources {
+            limits: None,
+            requests: Some(cpu_and_memory("250m", "512Mi")),
+        }),
+        ..Agent::default()
+    };
+    let resources = container_resources(&agent);
+    assert_eq!(
+        resources
+            .as_ref()
+            .and_then(|resources| resources.requests.as_ref())
+            .and_then(|requests| requests.get("cpu")),
+        Some(&Quantity("250m".to_string()))
+    );
+    assert!(resources
+        .as_ref()
+        .map(|resources| resources.limits.is_none())
+        .unwrap_or(false));
+}
+
+#[test]
+fn no_container_resources() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    assert!(container_resources(&agent).is_none());
+}
+
+#[test]
+fn persistent_volume_claim() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        persistent_volumes: Some(vec![PersistentVolumeMount {
+            claim_name: "scratch-claim".into(),
+            mount_path: "/scratch".into(),
+            read_only: None,
+        }]),
+        ..Agent::default()
+    };
+    let pod_spec = pod_spec(&agent, JobType::TestAgent);
+    let volume = pod_spec
+        .as_ref()
+        .and_then(|pod_spec| pod_spec.volumes.as_ref())
+        .and_then(|volumes| volumes.first());
+    assert_eq!(
+        volume.map(|volume| volume.name.as_str()),
+        Some("persistent-volume-0")
+    );
+    assert_eq!(
+        volume
+            .and_then(|volume| volume.persistent_volume_claim.as_ref())
+            .map(|claim| claim.claim_name.as_str()),
+        Some("scratch-claim")
+    );
+    let mount = pod_spec
+        .as_ref()
+        .and_then(|pod_spec| pod_spec.containers.first())
+        .and_then(|container| container.volume_mounts.as_ref())
+        .and_then(|mounts| mounts.first());
+    assert_eq!(
+        mount.map(|mount| (mount.name.as_str(), mount.mount_path.as_str())),
+        Some(("persistent-volume-0", "/scratch"))
+    );
+}
+
+#[test]
+fn indexed_completions() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        completions: Some(10),
+        success_threshold_percent: Some(90),
+        ..Agent::default()
+    };
+    let job = test_job(&agent, &JobSettings::default()).build();
+    let job_spec = job.spec.as_ref();
+    assert_eq!(job_spec.and_then(|spec| spec.completions), Some(10));
+    assert_eq!(job_spec.and_then(|spec| spec.parallelism), Some(10));
+    assert_eq!(
+        job_spec.and_then(|spec| spec.completion_mode.as_deref()),
+        Some("Indexed")
+    );
+    // The first failed completion does not fail the job.
+    assert_eq!(job_spec.and_then(|spec| spec.backoff_limit), Some(10));
+
+    // Each completion's container may be restarted in place as often as a single container.
+    let agent = Agent {
+        restart_policy: RestartPolicy::OnFailure,
+        ..agent
+    };
+    let job = test_job(&agent, &JobSettings::default()).build();
+    assert_eq!(
+        job.spec.and_then(|spec| spec.backoff_limit),
+        Some(10 * ON_FAILURE_BACKOFF_LIMIT)
+    );
+}
+
+#[test]
+fn host_alias() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        host_aliases: Some(vec![testsys_model::HostAlias {
+            ip: "10.0.0.10".into(),
+            hostnames: vec!["api.example.com".into(), "auth.example.com".into()],
+        }]),
+        ..Agent::default()
+    };
+    assert_eq!(
+        pod_spec(&agent, JobType::TestAgent).and_then(|pod_spec| pod_spec.host_aliases),
+        Some(vec![HostAlias {
+            ip: Some("10.0.0.10".to_string()),
+            hostnames: Some(vec![
+                "api.example.com".to_string(),
+                "auth.example.com".to_string()
+            ]),
+        }])
+    );
+}
+
+#[test]
+fn dns_search_domains() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        dns_config: Some(testsys_model::DnsConfig {
+            searches: Some(vec!["corp.example.com".into()]),
+            options: Some(vec![testsys_model::DnsOption {
+                name: "ndots".into(),
+                value: Some("2".into()),
+            }]),
+            ..Default::default()
+        }),
+        dns_search_domains: Some(vec!["svc.internal".into(), "corp.example.com".into()]),
+        ..Agent::default()
+    };
+    let spec = pod_spec(&agent, JobType::TestAgent).unwrap_or_default();
+    assert_eq!(
+        spec.dns_config,
+        Some(PodDNSConfig {
+            nameservers: None,
+            searches: Some(vec![
+                "corp.example.com".to_string(),
+                "svc.internal".to_string()
+            ]),
+            options: Some(vec![PodDNSConfigOption {
+                name: Some("ndots".to_string()),
+                value: Some("2".to_string()),
+            }]),
+        })
+    );
+    // Cluster names still resolve.
+    assert_eq!(spec.dns_policy, None);
+
+    let agent = Agent {
+        dns_search_domains: Some(Vec::new()),
+        ..agent
+    };
+    assert_eq!(
+        pod_spec(
+            &Agent {
+                dns_config: None,
+                ..agent
+            },
+            JobType::TestAgent
+        )
+        .and_then(|pod_spec| pod_spec.dns_config),
+        None
+    );
+}
+
+#[test]
+fn no_host_aliases() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        host_aliases: Some(Vec::new()),
+        ..Agent::default()
+    };
+    assert!(pod_spec(&agent, JobType::TestAgent)
+        .and_then(|pod_spec| pod_spec.host_aliases)
+        .is_none());
+}
+
+#[cfg(test)]
+fn job_backoff_limit(agent: &Agent, job_type: JobType) -> Option<i32> {
+    JobBuilder {
+        job_type,
+        ..test_job(agent, &JobSettings::default())
+    }
+    .build()
+    .spec
+    .and_then(|spec| spec.backoff_limit)
+}
+
+#[test]
+fn default_backoff_limits() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    assert_eq!(job_backoff_limit(&agent, JobType::TestAgent), Some(0));
+    assert_eq!(
+        job_backoff_limit(&agent, JobType::ResourceAgent),
+        Some(RESOURCE_AGENT_BACKOFF_LIMIT)
+    );
+}
+
+#[test]
+fn custom_backoff_limit() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        backoff_limit: Some(1),
+        ..Agent::default()
+    };
+    assert_eq!(job_backoff_limit(&agent, JobType::TestAgent), Some(1));
+    assert_eq!(job_backoff_limit(&agent, JobType::ResourceAgent), Some(1));
+}
+
+#[test]
+fn secret_mounts_are_mounted_read_only() {
+    use testsys_model::{SecretMount, SecretName};
+
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        secret_mounts: SecretName::new("aws-creds").ok().map(|secret_name| {
+            vec![SecretMount {
+                secret_name,
+                mount_path: "/etc/aws-creds".into(),
+                config_field: Some("credentials".into()),
+            }]
+        }),
+        ..Agent::default()
+    };
+    let volumes = volumes(&agent, "job", None, None).unwrap_or_default();
+    let mounts = mounts(&agent, None, None).unwrap_or_default();
+    assert_eq!(volumes.len(), 1);
+    assert_eq!(mounts.len(), 1);
+    assert_eq!(
+        volumes[0]
+            .secret
+            .as_ref()
+            .and_then(|secret| secret.secret_name.as_deref()),
+        Some("aws-creds")
+    );
+    assert_eq!(mounts[0].name, volumes[0].name);
+    assert_eq!(mounts[0].mount_path, "/etc/aws-creds");
+    assert_eq!(mounts[0].read_only, Some(true));
+}
+
+#[test]
+fn startup_probe() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        startup_probe: Some(testsys_model::Probe {
+            http_get: Some(testsys_model::HttpGetProbe {
+                path: "/healthz".into(),
+                port: 8080,
+            }),
+            period_seconds: Some(10),
+            failure_threshold: Some(30),
+            ..Default::default()
+        }),
+        ..Agent::default()
+    };
+    let startup_probe = pod_spec(&agent, JobType::TestAgent)
+        .and_then(|pod_spec| pod_spec.containers.into_iter().next())
+        .and_then(|container| container.startup_probe);
+    assert_eq!(
+        startup_probe,
+        Some(Probe {
+            http_get: Some(HTTPGetAction {
+                path: Some("/healthz".to_string()),
+                port: IntOrString::Int(8080),
+                ..HTTPGetAction::default()
+            }),
+            period_seconds: Some(10),
+            failure_threshold: Some(30),
+            ..Probe::default()
+        })
+    );
+}
+
+#[test]
+fn no_startup_probe() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    assert!(pod_spec(&agent, JobType::TestAgent)
+        .and_then(|pod_spec| pod_spec.containers.into_iter().next())
+        .and_then(|container| container.startup_probe)
+        .is_none());
+}
+
+#[test]
+fn arch_node_selector() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        node_selector: Some(BTreeMap::from([
+            ("kubernetes.io/arch".to_string(), "amd64".to_string()),
+            ("node-pool".to_string(), "testing".to_string()),
+        ])),
+        arch: Some("arm64".into()),
+        ..Agent::default()
+    };
+    assert_eq!(
+        pod_spec(&agent, JobType::TestAgent).and_then(|pod_spec| pod_spec.node_selector),
+        Some(BTreeMap::from([
+            ("kubernetes.io/arch".to_string(), "arm64".to_string()),
+            ("node-pool".to_string(), "testing".to_string()),
+        ]))
+    );
+}
+
+#[test]
+fn long_job_name_container_name() {
+    let is_dns_label = |name: &str| {
+        !name.is_empty()
+            && name.len() <= MAX_CONTAINER_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && !name.starts_with('-')
+            && !name.ends_with('-')
+    };
+    let container_name = |agent_name: &str| {
+        let agent = Agent {
+            name: agent_name.into(),
+            image: "image".into(),
+            ..Agent::default()
+        };
+        JobBuilder {
+            job_name: &"a-very-long-test-name-".repeat(10),
+            ..test_job(&agent, &JobSettings::default())
+        }
+        .build()
+        .spec
+        .and_then(|job_spec| job_spec.template.spec)
+        .and_then(|pod_spec| pod_spec.containers.into_iter().next())
+        .map(|container| container.name)
+        .unwrap_or_default()
+    };
+    assert_eq!(container_name("eks-provider"), "eks-provider");
+    assert_eq!(container_name("My_Agent"), "my-agent");
+    assert_eq!(container_name("_"), "agent");
+    let long_name = container_name(&format!("{}-x", "a".repeat(62)));
+    assert!(is_dns_label(&long_name), "{}", long_name);
+    assert_eq!(long_name, "a".repeat(62));
+}
+
+#[tokio::test]
+async fn config_blob_is_mounted_from_config_map() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        config_blob: serde_json::json!({ "clusterName": "my-cluster", "nodes": 3 })
+            .as_object()
+            .cloned(),
+        ..Agent::default()
+    };
+    let client = crate::fake_api::fake_k8s_store(vec![]);
+    let result = async {
+        test_job(&agent, &JobSettings::default())
+            .deploy(client.clone())
+            .await?;
+        let config_map: ConfigMap = Api::namespaced(client, NAMESPACE)
+            .get("job-agent-config")
+            .await
+            .map_err(JobError::get)?;
+        Ok::<_, JobError>(config_map)
+    }
+    .await;
+    let contents = result
+        .ok()
+        .and_then(|config_map| config_map.data)
+        .and_then(|data| data.get(AGENT_CONFIG_FILE).cloned())
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok());
+    assert_eq!(
+        contents,
+        Some(serde_json::json!({ "clusterName": "my-cluster", "nodes": 3 }))
+    );
+
+    let pod_spec = pod_spec(&agent, JobType::TestAgent);
+    let mount = pod_spec
+        .as_ref()
+        .and_then(|pod_spec| pod_spec.containers.first())
+        .and_then(|container| container.volume_mounts.as_ref())
+        .and_then(|mounts| {
+            mounts
+                .iter()
+                .find(|mount| mount.mount_path == AGENT_CONFIG_PATH)
+        })
+        .cloned();
+    let volume = pod_spec
+        .and_then(|pod_spec| pod_spec.volumes)
+        .and_then(|volumes| {
+            volumes
+                .into_iter()
+                .find(|volume| Some(&volume.name) == mount.as_ref().map(|mount| &mount.name))
+        });
+    assert_eq!(mount.and_then(|mount| mount.read_only), Some(true));
+    assert_eq!(
+        volume
+            .and_then(|volume| volume.config_map)
+            .and_then(|config_map| config_map.name),
+        Some("job-agent-config".to_string())
+    );
+}
+
+#[tokio::test]
+async fn agent_config_of_failed_deploy_is_replaced() {
+    let config_blob = |nodes: u32| {
+        serde_json::json!({ "clusterName": "my-cluster", "nodes": nodes })
+            .as_object()
+            .cloned()
+    };
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        config_blob: config_blob(3),
+        ..Agent::default()
+    };
+    // An earlier deploy created the config for an older spec but not the job.
+    let earlier = config_blob(1).map(|config_blob| agent_config("job", config_blob));
+    let client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(earlier)]);
+    let result = async {
+        let job = test_job(&agent, &JobSettings::default())
+            .deploy(client.clone())
+            .await?;
+        let config_map: ConfigMap = Api::namespaced(client, NAMESPACE)
+            .get("job-agent-config")
+            .await
+            .map_err(JobError::get)?;
+        Ok::<_, JobError>((job, config_map))
+    }
+    .await;
+    // The new job owns the config, which has the agent's current `config_blob`.
+    let replaced = result.map(|(job, config_map)| {
+        let owners: Vec<_> = config_map
+            .owner_references()
+            .iter()
+            .map(|owner| Some(owner.uid.clone()))
+            .collect();
+        (
+            owners == vec![job.uid()],
+            config_map
+                .data
+                .and_then(|data| data.get(AGENT_CONFIG_FILE).cloned())
+                .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok()),
+        )
+    });
+    assert!(matches!(
+        replaced,
+        Ok((true, Some(contents))) if contents["nodes"] == 3
+    ));
+}
+
+#[test]
+fn only_the_job_is_labeled_with_its_test() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    let settings = JobSettings::default();
+    let builder = JobBuilder {
+        test_uid: Some("8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e"),
+        ..test_job(&agent, &settings)
+    };
+    // The label is not part of the spec, so jobs created without it are not replaced.
+    assert_eq!(
+        builder.clone().spec_hash(),
+        test_job(&agent, &settings).spec_hash()
+    );
+    let job = builder.build();
+    let pod_labels = job
+        .spec
+        .and_then(|job_spec| job_spec.template.metadata)
+        .and_then(|metadata| metadata.labels)
+        .unwrap_or_default();
+    assert!(!pod_labels.contains_key(LABEL_TEST_UID));
+    assert_eq!(
+        job.metadata
+            .labels
+            .unwrap_or_default()
+            .get(LABEL_TEST_UID)
+            .map(String::as_str),
+        Some("8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e")
+    );
+}
+
+#[test]
+fn protected_labels_override_pod_labels() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        pod_labels: Some(BTreeMap::from([
+            ("team".to_string(), "platform".to_string()),
+            ("network-policy".to_string(), "open".to_string()),
+            (APP_MANAGED_BY.to_string(), "someone-else".to_string()),
+        ])),
+        ..Agent::default()
+    };
+    let settings = JobSettings {
+        protected_labels: BTreeMap::from([("network-policy".to_string(), "agents".to_string())]),
+        ..JobSettings::default()
+    };
+    let job = test_job(&agent, &settings).build();
+    let pod_labels = job
+        .spec
+        .and_then(|job_spec| job_spec.template.metadata)
+        .and_then(|metadata| metadata.labels)
+        .unwrap_or_default();
+    let job_labels = job.metadata.labels.unwrap_or_default();
+    for labels in [pod_labels, job_labels] {
+        assert_eq!(
+            labels.get("network-policy").map(String::as_str),
+            Some("agents")
+        );
+        assert_eq!(labels.get("team").map(String::as_str), Some("platform"));
+        assert_eq!(
+            labels.get(APP_MANAGED_BY).map(String::as_str),
+            Some(CONTROLLER)
+        );
+    }
+}
+
+#[test]
+fn spot_capacity_type() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        capacity_type: Some(CapacityType::Spot),
+        ..Agent::default()
+    };
+    let pod_spec = pod_spec(&agent, JobType::TestAgent).unwrap_or_default();
+    assert_eq!(
+        pod_spec.node_selector,
+        Some(BTreeMap::from([(
+            "karpenter.sh/capacity-type".to_string(),
+            "spot".to_string()
+        )]))
+    );
+    assert_eq!(
+        pod_spec.tolerations,
+        Some(vec![Toleration {
+            key: Some("karpenter.sh/capacity-type".to_string()),
+            operator: Some("Equal".to_string()),
+            value: Some("spot".to_string()),
+            effect: Some("NoSchedule".to_string()),
+            ..Toleration::default()
+        }])
+    );
+
+    // EKS managed node groups label their nodes with upper case capacity types.
+    assert_eq!(
+        node_selector(&agent, Some(EKS_CAPACITY_TYPE_LABEL)),
+        Some(BTreeMap::from([(
+            EKS_CAPACITY_TYPE_LABEL.to_string(),
+            "SPOT".to_string()
+        )]))
+    );
+}
+
+#[test]
+fn on_demand_capacity_type() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        node_selector: Some(BTreeMap::from([(
+            EKS_CAPACITY_TYPE_LABEL.to_string(),
+            "SPOT".to_string(),
+        )])),
+        capacity_type: Some(CapacityType::OnDemand),
+        ..Agent::default()
+    };
+    assert_eq!(
+        node_selector(&agent, Some(EKS_CAPACITY_TYPE_LABEL)),
+        Some(BTreeMap::from([(
+            EKS_CAPACITY_TYPE_LABEL.to_string(),
+            "ON_DEMAND".to_string()
+        )]))
+    );
+    assert_eq!(tolerations(&agent, Some(EKS_CAPACITY_TYPE_LABEL)), None);
+}
+
+#[test]
+fn ca_bundle_is_mounted() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    let settings = JobSettings {
+        ca_bundle: Some("internal-ca".to_string()),
+        ..JobSettings::default()
+    };
+    let pod_spec = test_job(&agent, &settings)
+        .build()
+        .spec
+        .and_then(|job_spec| job_spec.template.spec)
+        .unwrap_or_default();
+    let container = pod_spec.containers.first().cloned().unwrap_or_default();
+    let mount = container
+        .volume_mounts
+        .unwrap_or_default()
+        .into_iter()
+        .find(|mount| mount.name == CA_BUNDLE_VOLUME_NAME);
+    assert_eq!(
+        mount.map(|mount| (mount.mount_path, mount.read_only)),
+        Some(("/ca-bundle".to_string(), Some(true)))
+    );
+    let config_map = pod_spec
+        .volumes
+        .unwrap_or_default()
+        .into_iter()
+        .find(|volume| volume.name == CA_BUNDLE_VOLUME_NAME)
+        .and_then(|volume| volume.config_map)
+        .unwrap_or_default();
+    assert_eq!(config_map.name.as_deref(), Some(AGENT_CA_BUNDLE));
+    assert_eq!(
+        config_map
+            .items
+            .unwrap_or_default()
+            .first()
+            .map(|item| item.path.as_str()),
+        Some("ca-bundle.crt")
+    );
+    let env = container.env.unwrap_or_default();
+    for name in ["SSL_CERT_FILE", "AWS_CA_BUNDLE"] {
+        assert_eq!(
+            env.iter()
+                .find(|var| var.name == name)
+                .and_then(|var| var.value.as_deref()),
+            Some("/ca-bundle/ca-bundle.crt")
+        );
+    }
+
+    // Without a CA bundle nothing is mounted and the agent's TLS clients use the system's CAs.
+    let pod_spec = test_job(&agent, &JobSettings::default())
+        .build()
+        .spec
+        .and_then(|job_spec| job_spec.template.spec)
+        .unwrap_or_default();
+    assert_eq!(pod_spec.volumes, None);
+    assert_eq!(
+        pod_spec
+            .containers
+            .first()
+            .and_then(|container| container.env.clone()),
+        None
+    );
+}
+
+#[test]
+fn scheduling_gates() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        scheduling_gates: Some(vec!["example.com/quota-approval".to_string()]),
+        ..Agent::default()
+    };
+    assert_eq!(
+        pod_spec(&agent, JobType::TestAgent).and_then(|pod_spec| pod_spec.scheduling_gates),
+        Some(vec![PodSchedulingGate {
+            name: "example.com/quota-approval".to_string()
+        }])
+    );
+}
+
+#[test]
+fn guaranteed_qos() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        container_resources: Some(ContainerResources {
+            limits: Some(BTreeMap::from([("cpu".to_string(), "2".to_string())])),
+            requests: Some(cpu_and_memory("1", "2Gi")),
+        }),
+        qos: Some(Qos::Guaranteed),
+        ..Agent::default()
+    };
+    let resources = container_resources(&agent).unwrap_or_default();
+    let expected = Some(
+        [("cpu", "2"), ("memory", "2Gi")]
+            .into_iter()
+            .map(|(resource, amount)| (resource.to_string(), Quantity(amount.to_string())))
             .collect(),
-    )
+    );
+    assert_eq!(resources.limits, expected);
+    assert_eq!(resources.requests, expected);
+
+    // Burstable resources are used as they are.
+    let agent = Agent {
+        qos: Some(Qos::Burstable),
+        ..agent
+    };
+    let resources = container_resources(&agent).unwrap_or_default();
+    assert_eq!(resources.limits.map(|limits| limits.len()), Some(1));
+    assert_eq!(resources.requests.map(|requests| requests.len()), Some(2));
+}
+
+#[test]
+fn memory_limit_overrides_container_resources() {
+    let agent = Agent {
+        container_resources: Some(ContainerResources {
+            limits: Some(cpu_and_memory("1", "1Gi")),
+            requests: None,
+        }),
+        qos: Some(Qos::Guaranteed),
+        ..Agent::default()
+    };
+    let resources = resources(&agent, Some("2Gi")).unwrap_or_default();
+    let memory = |amounts: Option<BTreeMap<String, Quantity>>| {
+        amounts.and_then(|amounts| amounts.get("memory").cloned())
+    };
+    assert_eq!(memory(resources.limits), Some(Quantity("2Gi".to_string())));
+    assert_eq!(
+        memory(resources.requests),
+        Some(Quantity("2Gi".to_string()))
+    );
+}
+
+#[test]
+fn endpoint_wait_init_container() {
+    let agent = Agent {
+        name: "agent".to_string(),
+        image: "example.com/agent:v1".to_string(),
+        ..Agent::default()
+    };
+    let endpoints = vec!["https://my-cluster.example.com/healthz".to_string()];
+    let settings = JobSettings {
+        endpoint_wait_image: Some("example.com/busybox:v1".to_string()),
+        ..JobSettings::default()
+    };
+    let builder = |wait_for_endpoints| JobBuilder {
+        wait_for_endpoints,
+        ..test_job(&agent, &settings)
+    };
+    let init_containers = |builder: JobBuilder<'_>| {
+        builder
+            .build()
+            .spec
+            .and_then(|job_spec| job_spec.template.spec)
+            .and_then(|pod_spec| pod_spec.init_containers)
+    };
+
+    let init_container = init_containers(builder(&endpoints))
+        .and_then(|containers| containers.into_iter().next())
+        .unwrap_or_default();
+    assert_eq!(init_container.name, ENDPOINT_WAIT_CONTAINER_NAME);
+    assert_eq!(
+        init_container.image.as_deref(),
+        Some("example.com/busybox:v1")
+    );
+    assert_eq!(init_container.args, Some(endpoints.clone()));
+    let security_context = init_container.security_context.unwrap_or_default();
+    assert_eq!(security_context.run_as_non_root, Some(true));
+    assert_eq!(security_context.allow_privilege_escalation, Some(false));
+    assert_eq!(
+        init_container
+            .resources
+            .and_then(|resources| resources.limits)
+            .and_then(|limits| limits.get("memory").cloned()),
+        Some(Quantity(ENDPOINT_WAIT_MEMORY.to_string()))
+    );
+    // The init container trusts the controller's CA bundle.
+    let with_ca_bundle = JobSettings {
+        ca_bundle: Some("internal-ca".to_string()),
+        ..settings.clone()
+    };
+    let init_container = init_containers(JobBuilder {
+        wait_for_endpoints: &endpoints,
+        ..test_job(&agent, &with_ca_bundle)
+    })
+    .and_then(|containers| containers.into_iter().next())
+    .unwrap_or_default();
+    assert!(init_container
+        .volume_mounts
+        .unwrap_or_default()
+        .iter()
+        .any(|mount| mount.name == CA_BUNDLE_VOLUME_NAME && mount.mount_path == CA_BUNDLE_PATH));
+    assert!(init_container
+        .env
+        .unwrap_or_default()
+        .iter()
+        .any(|var| var.name == SSL_CERT_FILE));
+    // Without endpoints the agent starts right away.
+    assert_eq!(init_containers(builder(&[])), None);
+}
+
+#[test]
+fn test_env_overrides_controller_default() {
+    let agent = Agent {
+        name: "agent".to_string(),
+        image: "example.com/agent:v1".to_string(),
+        ..Agent::default()
+    };
+    let settings = JobSettings {
+        ca_bundle: Some("internal-ca".to_string()),
+        ..JobSettings::default()
+    };
+    let job = JobBuilder {
+        environment_variables: vec![
+            (SSL_CERT_FILE, "/etc/custom/ca.pem".to_string()),
+            ("TESTSYS_TEST_NAME", "my-test".to_string()),
+        ],
+        ..test_job(&agent, &settings)
+    }
+    .build();
+    let env: Vec<(String, Option<String>)> = job
+        .spec
+        .and_then(|job_spec| job_spec.template.spec)
+        .and_then(|pod_spec| pod_spec.containers.into_iter().next())
+        .and_then(|container| container.env)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|var| (var.name, var.value))
+        .collect();
+    let ca_bundle_file = format!("{}/{}", CA_BUNDLE_PATH, CA_BUNDLE_FILE);
+    assert_eq!(
+        env,
+        vec![
+            (
+                SSL_CERT_FILE.to_string(),
+                Some("/etc/custom/ca.pem".to_string())
+            ),
+            (AWS_CA_BUNDLE.to_string(), Some(ca_bundle_file)),
+            ("TESTSYS_TEST_NAME".to_string(), Some("my-test".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn input_hash_annotation() {
+    let env = [("REGION", "us-west-2".to_string())];
+    let outputs = r#"{"cluster": {"endpoint": "https://a.example.com"}}"#;
+    // The same inputs always have the same hash, and different ones have different hashes.
+    assert_eq!(input_hash(&env, outputs), input_hash(&env, outputs));
+    assert_ne!(
+        input_hash(&env, outputs),
+        input_hash(&[("REGION", "us-east-1".to_string())], outputs)
+    );
+    assert_ne!(
+        input_hash(&env, outputs),
+        input_hash(
+            &env,
+            r#"{"cluster": {"endpoint": "https://b.example.com"}}"#
+        )
+    );
+
+    let agent = Agent {
+        name: "agent".to_string(),
+        image: "example.com/agent:v1".to_string(),
+        ..Agent::default()
+    };
+    let settings = JobSettings::default();
+    let build = |input_hash: Option<String>| {
+        JobBuilder {
+            input_hash,
+            ..test_job(&agent, &settings)
+        }
+        .build()
+    };
+    let pod_annotation = |job: &Job| {
+        job.spec
+            .as_ref()
+            .and_then(|job_spec| job_spec.template.metadata.as_ref())
+            .and_then(|metadata| metadata.annotations.as_ref())
+            .and_then(|annotations| annotations.get(ANNOTATION_INPUT_HASH))
+            .cloned()
+    };
+    let hash = input_hash(&env, outputs);
+    let annotated = build(Some(hash.clone()));
+    let unannotated = build(None);
+    assert_eq!(pod_annotation(&annotated), Some(hash));
+    assert_eq!(pod_annotation(&unannotated), None);
+    // The inputs do not change the spec hash.
+    assert_eq!(job_spec_hash(&annotated), job_spec_hash(&unannotated));
+}
+
+#[tokio::test]
+async fn ports_and_headless_service() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ports: Some(vec![
+            testsys_model::ContainerPort {
+                name: Some("peer".to_string()),
+                container_port: 7000,
+                protocol: None,
+            },
+            testsys_model::ContainerPort {
+                name: Some("gossip".to_string()),
+                container_port: 7001,
+                protocol: Some(PortProtocol::Udp),
+            },
+        ]),
+        headless_service: Some(true),
+        ..Agent::default()
+    };
+    let pod_spec = pod_spec(&agent, JobType::TestAgent);
+    let ports = pod_spec
+        .as_ref()
+        .and_then(|pod_spec| pod_spec.containers.first())
+        .and_then(|container| container.ports.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|port| (port.name, port.container_port, port.protocol))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ports,
+        vec![
+            (Some("peer".to_string()), 7000, None),
+            (Some("gossip".to_string()), 7001, Some("UDP".to_string())),
+        ]
+    );
+    assert_eq!(
+        pod_spec.and_then(|pod_spec| pod_spec.subdomain),
+        Some("job".to_string())
+    );
+
+    let client = crate::fake_api::fake_k8s_store(vec![]);
+    let result = async {
+        test_job(&agent, &JobSettings::default())
+            .deploy(client.clone())
+            .await?;
+        let service: Service = Api::namespaced(client, NAMESPACE)
+            .get("job")
+            .await
+            .map_err(JobError::get)?;
+        Ok::<_, JobError>(service)
+    }
+    .await;
+    let spec = result.ok().and_then(|service| service.spec);
+    assert_eq!(
+        spec.as_ref().and_then(|spec| spec.cluster_ip.clone()),
+        Some("None".to_string())
+    );
+    assert_eq!(
+        spec.as_ref().and_then(|spec| spec.selector.clone()),
+        Some(BTreeMap::from([(APP_NAME.to_string(), "job".to_string())]))
+    );
+    assert_eq!(
+        spec.and_then(|spec| spec.ports)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|port| port.port)
+            .collect::<Vec<_>>(),
+        vec![7000, 7001]
+    );
+}
+
+#[tokio::test]
+async fn headless_service_of_earlier_job_is_replaced() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ports: Some(vec![testsys_model::ContainerPort {
+            name: Some("peer".to_string()),
+            container_port: 7000,
+            protocol: None,
+        }]),
+        headless_service: Some(true),
+        ..Agent::default()
+    };
+    // The job that was deleted to relaunch the agent left its service behind.
+    let mut earlier = headless_service(&agent, "job");
+    if let Some(service) = earlier.as_mut() {
+        service.metadata.owner_references = Some(vec![
+            k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference {
+                api_version: "batch/v1".to_string(),
+                kind: "Job".to_string(),
+                name: "job".to_string(),
+                uid: "earlier".to_string(),
+                controller: Some(true),
+                ..Default::default()
+            },
+        ]);
+        service.spec = Some(ServiceSpec::default());
+    }
+    let client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(earlier)]);
+    let result = async {
+        let job = test_job(&agent, &JobSettings::default())
+            .deploy(client.clone())
+            .await?;
+        let service: Service = Api::namespaced(client, NAMESPACE)
+            .get("job")
+            .await
+            .map_err(JobError::get)?;
+        Ok::<_, JobError>((job, service))
+    }
+    .await;
+    // The new job owns the service, which is the one the agent asks for.
+    let replaced = result.map(|(job, service)| {
+        let owners: Vec<_> = service
+            .owner_references()
+            .iter()
+            .map(|owner| Some(owner.uid.clone()))
+            .collect();
+        (
+            owners == vec![job.uid()],
+            service.spec.and_then(|spec| spec.cluster_ip),
+        )
+    });
+    assert!(matches!(replaced, Ok((true, Some(cluster_ip))) if cluster_ip == "None"));
+}
+
+#[test]
+fn no_headless_service() {
+    let agent = Agent {
+        name: "agent".into(),
+        image: "image".into(),
+        ..Agent::default()
+    };
+    assert!(headless_service(&agent, "job").is_none());
+    let pod_spec = pod_spec(&agent, JobType::TestAgent);
+    assert_eq!(
+        pod_spec
+            .as_ref()
+            .and_then(|pod_spec| pod_spec.containers.first())
+            .and_then(|container| container.ports.clone()),
+        None
+    );
+    assert_eq!(pod_spec.and_then(|pod_spec| pod_spec.subdomain), None);
 }
@@ -0,0 +1,398 @@
+use crate::clock::Clock;
+use crate::job::{get_job_state, get_pod, JobState};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::api::LogParams;
+use kube::Api;
+use log::{debug, trace, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use testsys_model::constants::NAMESPACE;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::JoinHandle;
+
+/// How long to wait before trying to follow a job's logs again after the pod was not ready or the
+/// log stream was interrupted.
+const FOLLOW_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The most forwarded lines that wait to be written to the sink. Following logs waits while the
+/// sink is this far behind instead of buffering without limit.
+const LOG_BUFFER_CAPACITY: usize = 10_000;
+
+/// The most lines that are written to the sink at once.
+const MAX_BATCH_SIZE: usize = 500;
+
+/// Where forwarded agent logs are sent.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum LogSink {
+    /// Write each log line as a JSON object to the controller's stdout.
+    Stdout,
+    /// `POST` batches of log lines to the given HTTP endpoint as newline delimited JSON objects.
+    Http(String),
+}
+
+impl LogSink {
+    /// Parse the configured log sink. Returns `None` if log forwarding is not enabled.
+    pub(crate) fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "" => None,
+            "stdout" => Some(Self::Stdout),
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                Some(Self::Http(url.to_string()))
+            }
+            other => {
+                warn!(
+                    "Unsupported log sink '{}', agent logs will not be forwarded",
+                    other
+                );
+                None
+            }
+        }
+    }
+}
+
+/// A single forwarded line of agent output.
+#[derive(Debug, Serialize)]
+struct LogRecord<'a> {
+    test: &'a str,
+    pod: &'a str,
+    message: &'a str,
+}
+
+/// Follows the logs of running test agent pods and forwards each line to a [`LogSink`]. There is
+/// at most one log following task per test.
+#[derive(Clone)]
+pub(crate) struct LogForwarder {
+    k8s_client: kube::Client,
+    sender: Sender<String>,
+    tasks: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LogForwarder {
+    /// Create a `LogForwarder` and start the task that writes forwarded lines to `sink`.
+    pub(crate) fn new(k8s_client: kube::Client, sink: LogSink, clock: Arc<dyn Clock>) -> Self {
+        let (sender, receiver) = channel(LOG_BUFFER_CAPACITY);
+        tokio::spawn(write_records(sink, receiver));
+        Self {
+            k8s_client,
+            sender,
+            tasks: Default::default(),
+            clock,
+        }
+    }
+
+    /// Start following the logs of the test's job if we are not doing so already.
+    pub(crate) fn start(&self, test_name: &str, job_name: &str) {
+        let mut tasks = match self.tasks.lock() {
+            Ok(tasks) => tasks,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if tasks
+            .get(test_name)
+            .map(|task| !task.is_finished())
+            .unwrap_or(false)
+        {
+            return;
+        }
+        debug!("Forwarding logs for test '{}'", test_name);
+        let task = tokio::spawn(follow_job_logs(
+            self.k8s_client.clone(),
+            test_name.to_string(),
+            job_name.to_string(),
+            self.sender.clone(),
+            self.clock.clone(),
+        ));
+        tasks.insert(test_name.to_string(), task);
+    }
+
+    /// Stop following the logs of the test's job.
+    pub(crate) fn stop(&self, test_name: &str) {
+        let mut tasks = match self.tasks.lock() {
+            Ok(tasks) => tasks,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(task) = tasks.remove(test_name) {
+            debug!("No longer forwarding logs for test '{}'", test_name);
+            task.abort();
+        }
+    }
+}
+
+/// Follow the logs of the pod running the job `job_name` until the job is no longer running. If
+/// the log stream ends while the job is still running (for example because the pod was restarted)
+/// we find the job's pod again and continue following it after the last line that was forwarded.
+async fn follow_job_logs(
+    k8s_client: kube::Client,
+    test_name: String,
+    job_name: String,
+    sender: Sender<String>,
+    clock: Arc<dyn Clock>,
+) {
+    let pod_api: Api<Pod> = Api::namespaced(k8s_client.clone(), NAMESPACE);
+    // The pod we last followed and the timestamp of the last line forwarded from it.
+    let mut last_followed: Option<(String, DateTime<Utc>)> = None;
+    loop {
+        match get_job_state(k8s_client.clone(), &job_name, clock.now()).await {
+            Ok(JobState::Unknown | JobState::Deleting | JobState::Running(_)) => {}
+            Ok(_) => break,
+            Err(e) => {
+                warn!("Unable to get job state for '{}': {}", job_name, e);
+                break;
+            }
+        }
+
+        let pod_name = match get_pod(k8s_client.clone(), &job_name).await {
+            Ok(pod_name) => pod_name,
+            Err(e) => {
+                trace!(
+                    "Pod for '{}' is not ready for log forwarding: {}",
+                    job_name,
+                    e
+                );
+                tokio::time::sleep(FOLLOW_RETRY_INTERVAL).await;
+                continue;
+            }
+        };
+
+        // When reconnecting to the same pod, resume after the last line that was forwarded. k8s
+        // only takes whole seconds, so the lines at or before that line's timestamp are skipped.
+        let after = last_followed
+            .as_ref()
+            .filter(|(last_pod, _)| last_pod == &pod_name)
+            .map(|(_, last_line)| *last_line);
+        let log_params = LogParams {
+            follow: true,
+            since_seconds: after.map(|after| (clock.now() - after).num_seconds().max(0) + 1),
+            timestamps: true,
+            ..Default::default()
+        };
+        match pod_api.log_stream(&pod_name, &log_params).await {
+            Ok(stream) => {
+                let last_line = forward_lines(stream, &test_name, &pod_name, &sender, after).await;
+                last_followed = last_line.map(|last_line| (pod_name, last_line));
+            }
+            Err(e) => {
+                trace!("Unable to follow logs for pod '{}': {}", pod_name, e);
+                tokio::time::sleep(FOLLOW_RETRY_INTERVAL).await;
+            }
+        }
+    }
+    trace!("Done forwarding logs for '{}'", job_name);
+}
+
+/// Split the chunks of `stream`, whose lines begin with their timestamp, into lines and send each
+/// line after `after` to `sender` as a [`LogRecord`]. Returns the timestamp of the last line that
+/// was sent, or `after` if none was.
+async fn forward_lines<S>(
+    stream: S,
+    test_name: &str,
+    pod_name: &str,
+    sender: &Sender<String>,
+    after: Option<DateTime<Utc>>,
+) -> Option<DateTime<Utc>>
+where
+    S: Stream<Item = kube::Result<Bytes>>,
+{
+    let mut last_line = after;
+    let mut send = |line: &[u8]| {
+        let line = String::from_utf8_lossy(line);
+        let (timestamp, message) = split_timestamp(line.trim_end_matches('\r'));
+        if let (Some(timestamp), Some(after)) = (timestamp, after) {
+            if timestamp <= after {
+                return None;
+            }
+        }
+        if timestamp.is_some() {
+            last_line = timestamp;
+        }
+        let record = LogRecord {
+            test: test_name,
+            pod: pod_name,
+            message,
+        };
+        match serde_json::to_string(&record) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                warn!("Unable to serialize log line for '{}': {}", test_name, e);
+                None
+            }
+        }
+    };
+
+    let mut buffer = Vec::new();
+    futures::pin_mut!(stream);
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                debug!("Log stream for pod '{}' was interrupted: {}", pod_name, e);
+                break;
+            }
+        };
+        buffer.extend_from_slice(&chunk);
+        while let Some(newline) = buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=newline).collect();
+            if let Some(record) = send(&line[..line.len() - 1]) {
+                // The sink is gone if the receiver was dropped, so there is nothing left to do.
+                if sender.send(record).await.is_err() {
+                    return last_line;
+                }
+            }
+        }
+    }
+    if !buffer.is_empty() {
+        if let Some(record) = send(&buffer) {
+            let _ = sender.send(record).await;
+        }
+    }
+    last_line
+}
+
+/// Split the timestamp that k8s puts at the beginning of a log line from the line's message.
+fn split_timestamp(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    line.split_once(' ')
+        .and_then(|(timestamp, message)| {
+            let timestamp = DateTime::parse_from_rfc3339(timestamp).ok()?;
+            Some((Some(timestamp.with_timezone(&Utc)), message))
+        })
+        .unwrap_or((None, line))
+}
+
+/// Wait for the next records and take up to [`MAX_BATCH_SIZE`] of them. Returns `None` once all
+/// senders are dropped.
+async fn next_batch(receiver: &mut Receiver<String>) -> Option<Vec<String>> {
+    let mut batch = vec![receiver.recv().await?];
+    while batch.len() < MAX_BATCH_SIZE {
+        match receiver.try_recv() {
+            Ok(record) => batch.push(record),
+            Err(_) => break,
+        }
+    }
+    Some(batch)
+}
+
+/// Write every record received to `sink` in batches until all senders are dropped.
+async fn write_records(sink: LogSink, mut receiver: Receiver<String>) {
+    let http_client = reqwest::Client::new();
+    while let Some(batch) = next_batch(&mut receiver).await {
+        match &sink {
+            LogSink::Stdout => println!("{}", batch.join("\n")),
+            LogSink::Http(url) => {
+                if let Err(e) = http_client
+                    .post(url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+                    .body(batch.join("\n"))
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                {
+                    warn!(
+                        "Unable to forward {} log lines to '{}': {}",
+                        batch.len(),
+                        url,
+                        e
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn parse_log_sink() {
+    assert_eq!(LogSink::parse("stdout"), Some(LogSink::Stdout));
+    assert_eq!(
+        LogSink::parse("https://logs.example.com/ingest"),
+        Some(LogSink::Http("https://logs.example.com/ingest".to_string()))
+    );
+    assert_eq!(LogSink::parse(""), None);
+    assert_eq!(LogSink::parse("syslog"), None);
+}
+
+#[tokio::test]
+async fn forward_lines_with_test_name() {
+    let chunks: Vec<kube::Result<Bytes>> = vec![
+        Ok(Bytes::from("2026-10-15T12:00:00.1Z first li")),
+        Ok(Bytes::from("ne\n2026-10-15T12:00:00.2Z second line\r\n")),
+        Ok(Bytes::from("2026-10-15T12:00:01Z last line")),
+    ];
+    let (sender, mut receiver) = channel(10);
+    let last_line = forward_lines(
+        futures::stream::iter(chunks),
+        "my-test",
+        "my-test-pod",
+        &sender,
+        None,
+    )
+    .await;
+    drop(sender);
+
+    let mut records = Vec::new();
+    while let Some(record) = receiver.recv().await {
+        records.push(record);
+    }
+    assert_eq!(
+        records,
+        vec![
+            r#"{"test":"my-test","pod":"my-test-pod","message":"first line"}"#,
+            r#"{"test":"my-test","pod":"my-test-pod","message":"second line"}"#,
+            r#"{"test":"my-test","pod":"my-test-pod","message":"last line"}"#,
+        ]
+    );
+    assert_eq!(
+        last_line.map(|last_line| last_line.to_rfc3339()),
+        Some("2026-10-15T12:00:01+00:00".to_string())
+    );
+}
+
+#[tokio::test]
+async fn forwarding_resumes_after_the_last_line() {
+    let chunks: Vec<kube::Result<Bytes>> = vec![Ok(Bytes::from(
+        "2026-10-15T12:00:00.1Z forwarded\n2026-10-15T12:00:00.2Z new\n",
+    ))];
+    let (sender, mut receiver) = channel(10);
+    let after = DateTime::parse_from_rfc3339("2026-10-15T12:00:00.1Z")
+        .ok()
+        .map(|after| after.with_timezone(&Utc));
+    forward_lines(
+        futures::stream::iter(chunks),
+        "my-test",
+        "my-test-pod",
+        &sender,
+        after,
+    )
+    .await;
+    drop(sender);
+
+    let mut records = Vec::new();
+    while let Some(record) = receiver.recv().await {
+        records.push(record);
+    }
+    assert_eq!(
+        records,
+        vec![r#"{"test":"my-test","pod":"my-test-pod","message":"new"}"#]
+    );
+}
+
+#[tokio::test]
+async fn records_are_batched() {
+    let (sender, mut receiver) = channel(MAX_BATCH_SIZE + 10);
+    for i in 0..MAX_BATCH_SIZE + 10 {
+        let _ = sender.send(i.to_string()).await;
+    }
+    drop(sender);
+    assert_eq!(
+        next_batch(&mut receiver).await.map(|batch| batch.len()),
+        Some(MAX_BATCH_SIZE)
+    );
+    assert_eq!(
+        next_batch(&mut receiver).await.map(|batch| batch.len()),
+        Some(10)
+    );
+    assert_eq!(next_batch(&mut receiver).await, None);
+}
@@ -1,20 +1,28 @@
+mod ca_bundle;
+mod env_template;
 mod error;
 mod job_builder;
+mod log_forwarder;
 
+pub(crate) use crate::job::ca_bundle::publish_agent_ca_bundle;
+pub(crate) use crate::job::env_template::resolve_env;
 pub(crate) use crate::job::error::{JobError, JobResult};
 use aws_sdk_cloudwatchlogs::model::InputLogEvent;
-pub(crate) use job_builder::{JobBuilder, JobType};
-use k8s_openapi::api::batch::v1::Job;
+use job_builder::{container_name, job_spec_hash, ENDPOINT_WAIT_CONTAINER_NAME};
+pub(crate) use job_builder::{input_hash, JobBuilder, JobSettings, JobType};
+use k8s_openapi::api::batch::v1::{Job, JobStatus};
 use k8s_openapi::api::core::v1::Pod;
-use k8s_openapi::chrono::{Duration, Utc};
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
 use kube::api::{DeleteParams, ListParams, LogParams, PropagationPolicy};
 use kube::{Api, ResourceExt};
-use log::{debug, info, warn};
+use log::{debug, info};
+pub(crate) use log_forwarder::{LogForwarder, LogSink};
 use snafu::{ensure, OptionExt, ResultExt};
-use std::env;
+use std::collections::HashMap;
+use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
-use testsys_model::constants::NAMESPACE;
-use testsys_model::system::TESTSYS_CONTROLLER_ARCHIVE_LOGS;
+use testsys_model::constants::{LABEL_TEST_UID, NAMESPACE};
+use testsys_model::JobProgress;
 
 lazy_static::lazy_static! {
     /// The maximum amount of time for a test to begin running (in seconds).
@@ -34,6 +42,8 @@ pub(crate) enum JobState {
     /// The job exists but we cannot figure out the status of its container. Hopefully this is
     /// transient and you can check the job again later.
     Unknown,
+    /// The job is being deleted and waits for its pods to be gone.
+    Deleting,
     /// The job is running.
     Running(Option<Duration>),
     /// The job is no longer running, and the container exited with a failure code.
@@ -41,9 +51,17 @@ pub(crate) enum JobState {
     /// The job is no longer running, and the container exited with `0`. We avoid calling this
     /// 'success' because the agent may have reported an error to the CRD.
     Exited,
+    /// The job runs indexed completions and is no longer running. `failed` is the number of
+    /// completions that did not succeed.
+    Completions { succeeded: i32, failed: i32 },
 }
 
-pub(crate) async fn get_job_state<S>(k8s_client: kube::Client, name: S) -> JobResult<JobState>
+/// The state of the job `name`. How long a running job has been running is measured up to `now`.
+pub(crate) async fn get_job_state<S>(
+    k8s_client: kube::Client,
+    name: S,
+    now: DateTime<Utc>,
+) -> JobResult<JobState>
 where
     S: AsRef<str>,
 {
@@ -53,12 +71,16 @@ where
         Ok(JobState::None)
     } else {
         let job = result?;
-        parse_job_state(&job)
+        parse_job_state(&job, now)
     }
 }
 
 /// Transform the container counts in `job.status` to a `JobState`
-fn parse_job_state(job: &Job) -> JobResult<JobState> {
+fn parse_job_state(job: &Job, now: DateTime<Utc>) -> JobResult<JobState> {
+    // A job that is being deleted, e.g. to relaunch its agent, is replaced once it is gone.
+    if job.metadata.deletion_timestamp.is_some() {
+        return Ok(JobState::Deleting);
+    }
     // Return early if `job.status` is somehow `None`.
     let status = match &job.status {
         None => {
@@ -67,6 +89,10 @@ fn parse_job_state(job: &Job) -> JobResult<JobState> {
         Some(some) => some,
     };
 
+    if let Some(completions) = indexed_completions(job) {
+        return Ok(parse_indexed_job_state(status, completions, now));
+    }
+
     // Unwrap the container counts defaulting to zero if they are missing.
     let running = status.active.unwrap_or(0);
     let succeeded = status.succeeded.unwrap_or(0);
@@ -78,9 +104,11 @@ fn parse_job_state(job: &Job) -> JobResult<JobState> {
         return Ok(JobState::Unknown);
     }
 
-    // There should be exactly one container.
+    // There should be at most one container. A container that is restarted in place stays running,
+    // but every pod that had to be replaced is still counted as failed, so failures are ignored
+    // while a container is running or until the job's backoff limit is reached.
     ensure!(
-        running + succeeded + failed == 1,
+        running <= 1 && succeeded <= 1,
         error::TooManyJobContainersSnafu {
             job_name: job
                 .metadata
@@ -97,24 +125,469 @@ fn parse_job_state(job: &Job) -> JobResult<JobState> {
         let job_running_duration = status
             .start_time
             .as_ref()
-            .map(|start_time| Utc::now() - start_time.0);
+            .map(|start_time| now - start_time.0);
         Ok(JobState::Running(job_running_duration))
     } else if succeeded == 1 {
         Ok(JobState::Exited)
-    } else {
+    } else if failed > backoff_limit(job) || has_condition(status, "Failed") {
         Ok(JobState::Failed)
+    } else {
+        // A failed pod is about to be replaced.
+        Ok(JobState::Unknown)
+    }
+}
+
+/// The number of failed pods the job tolerates before it fails.
+fn backoff_limit(job: &Job) -> i32 {
+    job.spec
+        .as_ref()
+        .and_then(|spec| spec.backoff_limit)
+        .unwrap_or(0)
+}
+
+/// Whether the job has a condition of type `condition_type` that is `True`.
+fn has_condition(status: &JobStatus, condition_type: &str) -> bool {
+    status
+        .conditions
+        .iter()
+        .flatten()
+        .any(|condition| condition.type_ == condition_type && condition.status == "True")
+}
+
+/// The number of completions of a job that runs indexed completions.
+fn indexed_completions(job: &Job) -> Option<i32> {
+    job.spec
+        .as_ref()
+        .filter(|spec| spec.completion_mode.as_deref() == Some("Indexed"))
+        .and_then(|spec| spec.completions)
+}
+
+/// An indexed job runs many containers. It is running while any of them are, and is done once k8s
+/// has marked it complete or failed. The indexes that never succeeded count as failed, however
+/// often their pods were retried.
+fn parse_indexed_job_state(status: &JobStatus, completions: i32, now: DateTime<Utc>) -> JobState {
+    let running = status.active.unwrap_or(0);
+    let succeeded = status
+        .completed_indexes
+        .as_deref()
+        .map(count_indexes)
+        .unwrap_or_else(|| status.succeeded.unwrap_or(0));
+    if running > 0 {
+        let job_running_duration = status
+            .start_time
+            .as_ref()
+            .map(|start_time| now - start_time.0);
+        return JobState::Running(job_running_duration);
+    }
+    if has_condition(status, "Complete") || has_condition(status, "Failed") {
+        JobState::Completions {
+            succeeded,
+            failed: (completions - succeeded).max(0),
+        }
+    } else {
+        // Either no container has started yet or a failed completion is about to be retried.
+        JobState::Unknown
     }
 }
 
+/// The number of indexes in a job's `completedIndexes`, a list of indexes and ranges of indexes
+/// like `1,3-5,7`.
+fn count_indexes(indexes: &str) -> i32 {
+    indexes
+        .split(',')
+        .filter(|interval| !interval.trim().is_empty())
+        .map(|interval| match interval.split_once('-') {
+            Some((first, last)) => {
+                match (first.trim().parse::<i32>(), last.trim().parse::<i32>()) {
+                    (Ok(first), Ok(last)) => (last - first + 1).max(0),
+                    _ => 0,
+                }
+            }
+            None => interval.trim().parse::<i32>().map(|_| 1).unwrap_or(0),
+        })
+        .sum()
+}
+
+/// The hash of the spec the job was built with, or `None` if the job does not exist or was not
+/// annotated with one.
+pub(crate) async fn get_job_spec_hash(
+    k8s_client: kube::Client,
+    name: &str,
+) -> JobResult<Option<String>> {
+    let api: Api<Job> = Api::namespaced(k8s_client, NAMESPACE);
+    match api.get(name).await.map_err(JobError::get) {
+        Ok(job) => Ok(job_spec_hash(&job).map(str::to_string)),
+        Err(JobError::NotFound { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// How long before `now` the job started, or `None` if the job does not exist or has not started.
+pub(crate) async fn get_job_age(
+    k8s_client: kube::Client,
+    name: &str,
+    now: DateTime<Utc>,
+) -> JobResult<Option<Duration>> {
+    let api: Api<Job> = Api::namespaced(k8s_client, NAMESPACE);
+    match api.get(name).await.map_err(JobError::get) {
+        Ok(job) => Ok(job
+            .status
+            .and_then(|status| status.start_time)
+            .map(|start_time| now - start_time.0)),
+        Err(JobError::NotFound { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The progress of a job that runs indexed completions, or `None` if the job does not exist or does
+/// not run indexed completions.
+pub(crate) async fn get_job_progress(
+    k8s_client: kube::Client,
+    name: &str,
+) -> JobResult<Option<JobProgress>> {
+    let api: Api<Job> = Api::namespaced(k8s_client, NAMESPACE);
+    match api.get(name).await.map_err(JobError::get) {
+        Ok(job) => Ok(job_progress(&job)),
+        Err(JobError::NotFound { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Copy the counts of an indexed job's completions from its status.
+fn job_progress(job: &Job) -> Option<JobProgress> {
+    let desired = indexed_completions(job)?;
+    let status = job.status.as_ref();
+    let count = |f: fn(&JobStatus) -> Option<i32>| status.and_then(f).unwrap_or(0);
+    Some(JobProgress {
+        desired,
+        active: count(|status| status.active),
+        succeeded: count(|status| status.succeeded),
+        failed: count(|status| status.failed),
+    })
+}
+
+/// The reasons k8s gives for a container that is waiting because its image cannot be pulled.
+const IMAGE_PULL_ERRORS: [&str; 2] = ["ErrImagePull", "ImagePullBackOff"];
+
+/// The error pulling the image of a container of the job's pods, if one of them has been unable to
+/// pull its image for at least `grace_period` before `now`. Image pulls that fail for a short
+/// while, e.g. because a registry is briefly unavailable, are retried by k8s and are not reported.
+pub(crate) async fn get_image_pull_error(
+    k8s_client: kube::Client,
+    job_name: &str,
+    grace_period: Duration,
+    now: DateTime<Utc>,
+    failures: &ImagePullFailures,
+) -> JobResult<Option<String>> {
+    let pods = job_pods(k8s_client, job_name).await?;
+    let errors = pods
+        .iter()
+        .filter_map(|pod| Some((pod.uid()?, image_pull_error(pod)?)))
+        .collect();
+    Ok(failures.overdue(job_name, errors, grace_period, now))
+}
+
+/// When the controller first found each pod unable to pull its image. k8s does not record when a
+/// container started waiting for its image, so a pull's grace period starts with the first
+/// reconciliation that saw it fail rather than with the pod, which may have been pulling for a
+/// while before it failed.
+#[derive(Debug, Default)]
+pub(crate) struct ImagePullFailures {
+    /// The job name and the time of the first failure of each failing pod, by the pod's uid.
+    first_seen: Mutex<HashMap<String, (String, DateTime<Utc>)>>,
+}
+
+impl ImagePullFailures {
+    /// The first of the job's image pull `errors`, by pod uid, that has been seen for at least
+    /// `grace_period` before `now`. The job's pods that are no longer failing are forgotten, and
+    /// so is the job once an error is reported because the agent is failed for it.
+    fn overdue(
+        &self,
+        job_name: &str,
+        errors: Vec<(String, String)>,
+        grace_period: Duration,
+        now: DateTime<Utc>,
+    ) -> Option<String> {
+        let mut first_seen = self.first_seen();
+        first_seen.retain(|uid, (job, _)| {
+            job != job_name || errors.iter().any(|(failing, _)| failing == uid)
+        });
+        let mut overdue = None;
+        for (uid, error) in errors {
+            let (_, since) = first_seen
+                .entry(uid)
+                .or_insert_with(|| (job_name.to_string(), now));
+            if overdue.is_none() && now - *since >= grace_period {
+                overdue = Some(error);
+            }
+        }
+        if overdue.is_some() {
+            first_seen.retain(|_, (job, _)| job != job_name);
+        }
+        overdue
+    }
+
+    fn first_seen(&self) -> std::sync::MutexGuard<'_, HashMap<String, (String, DateTime<Utc>)>> {
+        match self.first_seen.lock() {
+            Ok(first_seen) => first_seen,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// The reason and message of the first container of the `pod` that is waiting to pull its image.
+fn image_pull_error(pod: &Pod) -> Option<String> {
+    let status = pod.status.as_ref()?;
+    status
+        .init_container_statuses
+        .iter()
+        .chain(status.container_statuses.iter())
+        .flatten()
+        .filter_map(|container| container.state.as_ref()?.waiting.as_ref())
+        .find_map(|waiting| {
+            let reason = waiting.reason.as_deref()?;
+            if !IMAGE_PULL_ERRORS.contains(&reason) {
+                return None;
+            }
+            Some(match &waiting.message {
+                Some(message) => format!("{}: {}", reason, message),
+                None => reason.to_string(),
+            })
+        })
+}
+
+/// The message that the container of one of the job's pods wrote to its termination log before it
+/// exited with an error, if any did.
+pub(crate) async fn get_termination_message(
+    k8s_client: kube::Client,
+    job_name: &str,
+) -> JobResult<Option<String>> {
+    let pods = job_pods(k8s_client, job_name).await?;
+    Ok(pods.iter().find_map(termination_message))
+}
+
+/// The termination message of the first container of the `pod` that exited with an error, either
+/// in its current state or, if it was restarted, in its last state.
+fn termination_message(pod: &Pod) -> Option<String> {
+    pod.status
+        .as_ref()?
+        .container_statuses
+        .iter()
+        .flatten()
+        .flat_map(|container| [container.state.as_ref(), container.last_state.as_ref()])
+        .flatten()
+        .filter_map(|state| state.terminated.as_ref())
+        .filter(|terminated| terminated.exit_code != 0)
+        .filter_map(|terminated| terminated.message.as_deref())
+        .map(str::trim)
+        .find(|message| !message.is_empty())
+        .map(str::to_string)
+}
+
+/// Whether the container of one of the job's pods was killed because it ran out of memory, either
+/// in its current state or, if it was restarted, in its last state.
+pub(crate) async fn get_out_of_memory(k8s_client: kube::Client, job_name: &str) -> JobResult<bool> {
+    let pods = job_pods(k8s_client, job_name).await?;
+    Ok(pods.iter().any(out_of_memory))
+}
+
+fn out_of_memory(pod: &Pod) -> bool {
+    pod.status
+        .iter()
+        .flat_map(|status| status.container_statuses.iter().flatten())
+        .flat_map(|container| [container.state.as_ref(), container.last_state.as_ref()])
+        .flatten()
+        .filter_map(|state| state.terminated.as_ref())
+        .any(|terminated| terminated.reason.as_deref() == Some("OOMKilled"))
+}
+
+/// How far the pods of a job whose agent has scheduling gates got towards running.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Scheduling {
+    /// One of the job's pods is pending because it still has scheduling gates, which an external
+    /// process removes to release it.
+    Gated,
+    /// The gates were removed but no pod has been scheduled yet.
+    Unscheduled,
+    /// The job's first pod was scheduled, once its gates were removed, at this time.
+    Scheduled(DateTime<Utc>),
+}
+
+/// How far the pods of the job `job_name` got past their scheduling gates.
+pub(crate) async fn get_scheduling(
+    k8s_client: kube::Client,
+    job_name: &str,
+) -> JobResult<Scheduling> {
+    let pods = job_pods(k8s_client, job_name).await?;
+    if pods.iter().any(scheduling_gated) {
+        return Ok(Scheduling::Gated);
+    }
+    Ok(pods
+        .iter()
+        .filter_map(scheduled_at)
+        .min()
+        .map(Scheduling::Scheduled)
+        .unwrap_or(Scheduling::Unscheduled))
+}
+
+/// When the `pod` was scheduled, or `None` if it has not been scheduled.
+fn scheduled_at(pod: &Pod) -> Option<DateTime<Utc>> {
+    pod.status
+        .as_ref()?
+        .conditions
+        .iter()
+        .flatten()
+        .filter(|condition| condition.type_ == "PodScheduled" && condition.status == "True")
+        .find_map(|condition| condition.last_transition_time.as_ref())
+        .map(|time| time.0)
+}
+
+/// Whether the `pod` is pending and is not scheduled until all of its scheduling gates are removed.
+fn scheduling_gated(pod: &Pod) -> bool {
+    let gated = pod
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.scheduling_gates.as_ref())
+        .map(|gates| !gates.is_empty())
+        .unwrap_or(false);
+    let pending = pod
+        .status
+        .as_ref()
+        .and_then(|status| status.phase.as_deref())
+        .map(|phase| phase == "Pending")
+        .unwrap_or(true);
+    gated && pending
+}
+
+/// When the init container of one of the job's pods finished waiting for the test's endpoints and
+/// released the agent, or `None` if the agent of every pod is still held back.
+pub(crate) async fn get_endpoints_reached_at(
+    k8s_client: kube::Client,
+    job_name: &str,
+) -> JobResult<Option<DateTime<Utc>>> {
+    let pods = job_pods(k8s_client, job_name).await?;
+    Ok(pods.iter().filter_map(endpoints_reached_at).max())
+}
+
+/// When the init container of the `pod` that waits for the test's endpoints exited successfully.
+fn endpoints_reached_at(pod: &Pod) -> Option<DateTime<Utc>> {
+    pod.status
+        .as_ref()?
+        .init_container_statuses
+        .iter()
+        .flatten()
+        .filter(|container| container.name == ENDPOINT_WAIT_CONTAINER_NAME)
+        .filter_map(|container| container.state.as_ref()?.terminated.as_ref())
+        .filter(|terminated| terminated.exit_code == 0)
+        .filter_map(|terminated| terminated.finished_at.as_ref())
+        .map(|finished_at| finished_at.0)
+        .max()
+}
+
+/// Whether the container of the agent `agent_name` in the job's pod passes its readiness probe,
+/// or `None` if the job's readiness was checked less than [`READINESS_CHECK_INTERVAL`] seconds
+/// before `now`.
+pub(crate) async fn get_agent_ready(
+    k8s_client: kube::Client,
+    job_name: &str,
+    agent_name: &str,
+    now: DateTime<Utc>,
+    checks: &ReadinessChecks,
+) -> JobResult<Option<bool>> {
+    if !checks.due(job_name, now) {
+        return Ok(None);
+    }
+    let container_name = container_name(agent_name);
+    let pods = job_pods(k8s_client, job_name).await?;
+    Ok(Some(
+        pods.iter().any(|pod| agent_ready(pod, &container_name)),
+    ))
+}
+
+/// How often, in seconds, the pods of a job are listed to check the readiness of its agent.
+const READINESS_CHECK_INTERVAL: i64 = 5;
+
+/// When the readiness of each job's agent was last checked. A running test is reconciled whenever
+/// its agent writes to its status, so its pods are only listed once per
+/// [`READINESS_CHECK_INTERVAL`] seconds, the interval that running tests are requeued at.
+#[derive(Debug, Default)]
+pub(crate) struct ReadinessChecks {
+    last_checked: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl ReadinessChecks {
+    /// Whether the job's readiness is due to be checked at `now`, which it is recorded to be if it
+    /// is. Checks that are no longer recent are forgotten.
+    fn due(&self, job_name: &str, now: DateTime<Utc>) -> bool {
+        let mut last_checked = match self.last_checked.lock() {
+            Ok(last_checked) => last_checked,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        last_checked
+            .retain(|_, checked| now - *checked < Duration::seconds(READINESS_CHECK_INTERVAL));
+        if last_checked.contains_key(job_name) {
+            return false;
+        }
+        last_checked.insert(job_name.to_string(), now);
+        true
+    }
+}
+
+/// Whether the agent's container `container_name` in the `pod` is ready. The pod's other
+/// containers, e.g. sidecars, do not make the agent unready.
+fn agent_ready(pod: &Pod, container_name: &str) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.container_statuses.as_ref())
+        .into_iter()
+        .flatten()
+        .any(|status| status.name == container_name && status.ready)
+}
+
+/// The names of the jobs that were labeled with the uid of the test `test_uid` when they were
+/// created, including jobs that are being deleted.
+pub(crate) async fn get_test_jobs(
+    k8s_client: kube::Client,
+    test_uid: &str,
+) -> JobResult<Vec<String>> {
+    let api: Api<Job> = Api::namespaced(k8s_client, NAMESPACE);
+    Ok(api
+        .list(&ListParams::default().labels(&format!("{}={}", LABEL_TEST_UID, test_uid)))
+        .await
+        .context(error::ListJobsSnafu { test_uid })?
+        .items
+        .iter()
+        .map(ResourceExt::name_any)
+        .collect())
+}
+
 pub(crate) async fn delete_job(k8s_client: kube::Client, name: &str) -> JobResult<()> {
+    delete_job_with_policy(k8s_client, name, PropagationPolicy::Background, Some(0)).await
+}
+
+/// Delete the job `name` in the foreground, which keeps the job, marked for deletion, until its
+/// pods are gone. The pods are given their usual grace period to terminate.
+pub(crate) async fn delete_job_in_foreground(
+    k8s_client: kube::Client,
+    name: &str,
+) -> JobResult<()> {
+    delete_job_with_policy(k8s_client, name, PropagationPolicy::Foreground, None).await
+}
+
+async fn delete_job_with_policy(
+    k8s_client: kube::Client,
+    name: &str,
+    propagation_policy: PropagationPolicy,
+    grace_period_seconds: Option<u32>,
+) -> JobResult<()> {
     let api: Api<Job> = Api::namespaced(k8s_client, NAMESPACE);
     let result = api
         .delete(
             name,
             &DeleteParams {
                 dry_run: false,
-                grace_period_seconds: Some(0),
-                propagation_policy: Some(PropagationPolicy::Background),
+                grace_period_seconds,
+                propagation_policy: Some(propagation_policy),
                 preconditions: None,
             },
         )
@@ -128,16 +601,22 @@ pub(crate) async fn delete_job(k8s_client: kube::Client, name: &str) -> JobResul
     Ok(())
 }
 
-async fn get_pod(k8s_client: kube::Client, job_name: &str) -> JobResult<String> {
+/// The pods that the job `job_name` created.
+async fn job_pods(k8s_client: kube::Client, job_name: &str) -> JobResult<Vec<Pod>> {
     let pod_api: Api<Pod> = Api::namespaced(k8s_client, NAMESPACE);
-    let name = pod_api
+    Ok(pod_api
         .list(&ListParams {
             label_selector: Some(format!("job-name={}", job_name)),
             ..Default::default()
         })
         .await
-        .context(error::NotFoundSnafu {})?
-        .items
+        .context(error::ListPodsSnafu { job_name })?
+        .items)
+}
+
+async fn get_pod(k8s_client: kube::Client, job_name: &str) -> JobResult<String> {
+    let name = job_pods(k8s_client, job_name)
+        .await?
         .first()
         .context(error::NoPodsSnafu {
             job: job_name.to_string(),
@@ -162,20 +641,6 @@ async fn pod_logs(k8s_client: kube::Client, pod_name: &str) -> JobResult<String>
 }
 
 pub(crate) async fn archive_logs(k8s_client: kube::Client, job_name: &str) -> JobResult<()> {
-    let archive_logs = match env::var(TESTSYS_CONTROLLER_ARCHIVE_LOGS) {
-        Ok(s) => s == true.to_string(),
-        Err(e) => {
-            warn!(
-                "Unable to read environment variable '{}': {}",
-                TESTSYS_CONTROLLER_ARCHIVE_LOGS, e
-            );
-            false
-        }
-    };
-
-    if !archive_logs {
-        return Ok(());
-    }
     let config = aws_config::from_env().load().await;
     let client = aws_sdk_cloudwatchlogs::Client::new(&config);
 
@@ -240,3 +705,312 @@ pub(crate) async fn archive_logs(k8s_client: kube::Client, job_name: &str) -> Jo
 
     Ok(())
 }
+
+#[cfg(test)]
+fn indexed_job(active: i32, succeeded: i32, failed: i32, condition: Option<&str>) -> Job {
+    serde_json::from_value(serde_json::json!({
+        "metadata": { "name": "job" },
+        "spec": {
+            "completions": 10,
+            "completionMode": "Indexed",
+            "template": {}
+        },
+        "status": {
+            "active": active,
+            "succeeded": succeeded,
+            "failed": failed,
+            "conditions": condition.map(|condition| vec![serde_json::json!({
+                "type": condition,
+                "status": "True"
+            })])
+        }
+    }))
+    .unwrap_or_default()
+}
+
+#[test]
+fn indexed_job_running() {
+    assert!(matches!(
+        parse_job_state(&indexed_job(3, 6, 1, None), Utc::now()),
+        Ok(JobState::Running(_))
+    ));
+    // A failed completion that has not been retried yet.
+    assert!(matches!(
+        parse_job_state(&indexed_job(0, 9, 1, None), Utc::now()),
+        Ok(JobState::Unknown)
+    ));
+}
+
+#[test]
+fn indexed_job_finished() {
+    assert!(matches!(
+        parse_job_state(&indexed_job(0, 10, 2, Some("Complete")), Utc::now()),
+        Ok(JobState::Completions {
+            succeeded: 10,
+            failed: 0
+        })
+    ));
+    assert!(matches!(
+        parse_job_state(&indexed_job(0, 9, 11, Some("Failed")), Utc::now()),
+        Ok(JobState::Completions {
+            succeeded: 9,
+            failed: 1
+        })
+    ));
+}
+
+#[test]
+fn completed_indexes_are_counted() {
+    assert_eq!(count_indexes(""), 0);
+    assert_eq!(count_indexes("4"), 1);
+    assert_eq!(count_indexes("1-9"), 9);
+    assert_eq!(count_indexes("1,3-5,7"), 5);
+}
+
+#[test]
+fn each_index_counts_once() {
+    let status = |completed_indexes: &str| {
+        serde_json::from_value::<JobStatus>(serde_json::json!({
+            "succeeded": 10,
+            "failed": 10,
+            "completedIndexes": completed_indexes,
+            "conditions": [{ "type": "Failed", "status": "True" }]
+        }))
+    };
+    // Index 0 failed every time it was retried, and one of the other indexes succeeded in two pods,
+    // which k8s may start for the same index. Each index counts once.
+    assert!(matches!(
+        status("1-9").map(|status| parse_indexed_job_state(&status, 10, Utc::now())),
+        Ok(JobState::Completions {
+            succeeded: 9,
+            failed: 1
+        })
+    ));
+}
+
+#[test]
+fn indexed_job_progress() {
+    assert_eq!(
+        job_progress(&indexed_job(3, 4, 1, None)),
+        Some(JobProgress {
+            desired: 10,
+            active: 3,
+            succeeded: 4,
+            failed: 1,
+        })
+    );
+    assert_eq!(
+        job_progress(&indexed_job(3, 4, 1, None)).map(|progress| progress.to_string()),
+        Some("completions: 4/10".to_string())
+    );
+    // Only jobs that run indexed completions have progress.
+    assert_eq!(job_progress(&retried_job(1, 0, 0)), None);
+}
+
+#[cfg(test)]
+fn retried_job(active: i32, failed: i32, backoff_limit: i32) -> Job {
+    serde_json::from_value(serde_json::json!({
+        "metadata": { "name": "job" },
+        "spec": { "backoffLimit": backoff_limit, "template": {} },
+        "status": { "active": active, "failed": failed }
+    }))
+    .unwrap_or_default()
+}
+
+#[test]
+fn failed_pods_within_backoff_limit() {
+    // The first pod failed and its replacement is running.
+    assert!(matches!(
+        parse_job_state(&retried_job(1, 1, 2), Utc::now()),
+        Ok(JobState::Running(_))
+    ));
+    // The replacement has not started yet.
+    assert!(matches!(
+        parse_job_state(&retried_job(0, 2, 2), Utc::now()),
+        Ok(JobState::Unknown)
+    ));
+    // There are no replacements left.
+    assert!(matches!(
+        parse_job_state(&retried_job(0, 3, 2), Utc::now()),
+        Ok(JobState::Failed)
+    ));
+    assert!(matches!(
+        parse_job_state(&retried_job(0, 1, 0), Utc::now()),
+        Ok(JobState::Failed)
+    ));
+}
+
+#[test]
+fn deleted_job_is_replaced_once_it_is_gone() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+
+    // The job's pod ran out of memory and the job is deleted in the foreground to relaunch it.
+    let mut job = retried_job(0, 1, 0);
+    job.metadata.deletion_timestamp = Some(Time(Utc::now()));
+    assert!(matches!(
+        parse_job_state(&job, Utc::now()),
+        Ok(JobState::Deleting)
+    ));
+}
+
+#[test]
+fn terminated_container_message() {
+    let pod: Pod = serde_json::from_value(serde_json::json!({
+        "status": {
+            "containerStatuses": [{
+                "name": "agent",
+                "image": "example.com/agent:v1",
+                "imageID": "",
+                "ready": false,
+                "restartCount": 0,
+                "state": {
+                    "terminated": {
+                        "exitCode": 1,
+                        "reason": "Error",
+                        "message": "Unable to reach the cluster endpoint\n",
+                    }
+                }
+            }]
+        }
+    }))
+    .unwrap_or_default();
+    assert_eq!(
+        termination_message(&pod).as_deref(),
+        Some("Unable to reach the cluster endpoint")
+    );
+}
+
+#[test]
+fn no_termination_message_on_success() {
+    let pod: Pod = serde_json::from_value(serde_json::json!({
+        "status": {
+            "containerStatuses": [{
+                "name": "agent",
+                "image": "example.com/agent:v1",
+                "imageID": "",
+                "ready": false,
+                "restartCount": 0,
+                "state": {
+                    "terminated": { "exitCode": 0, "message": "Done" }
+                }
+            }]
+        }
+    }))
+    .unwrap_or_default();
+    assert_eq!(termination_message(&pod), None);
+}
+
+#[test]
+fn gated_pod_is_pending() {
+    let pod = |phase: &str, gates: serde_json::Value| -> Pod {
+        serde_json::from_value(serde_json::json!({
+            "spec": {
+                "containers": [{ "name": "agent" }],
+                "schedulingGates": gates,
+            },
+            "status": {
+                "phase": phase,
+                "conditions": [{
+                    "type": "PodScheduled",
+                    "status": "False",
+                    "reason": "SchedulingGated",
+                }]
+            }
+        }))
+        .unwrap_or_default()
+    };
+    assert!(scheduling_gated(&pod(
+        "Pending",
+        serde_json::json!([{ "name": "example.com/quota-approval" }])
+    )));
+    // Once the gates are removed the pod is scheduled as usual.
+    assert!(!scheduling_gated(&pod("Pending", serde_json::json!([]))));
+    assert!(!scheduling_gated(&pod("Running", serde_json::json!(null))));
+}
+
+#[test]
+fn released_pod_is_scheduled() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+
+    let scheduled = Utc::now();
+    let pod = |status: &str| {
+        serde_json::from_value::<Pod>(serde_json::json!({
+            "spec": { "containers": [{ "name": "agent" }] },
+            "status": {
+                "phase": "Pending",
+                "conditions": [{
+                    "type": "PodScheduled",
+                    "status": status,
+                    "lastTransitionTime": Time(scheduled),
+                }]
+            }
+        }))
+    };
+    assert!(matches!(pod("True"), Ok(pod) if scheduled_at(&pod).is_some()));
+    assert!(matches!(pod("False"), Ok(pod) if scheduled_at(&pod).is_none()));
+}
+
+#[test]
+fn pod_is_ready_once_its_agent_container_is() {
+    let pod = |ready: bool| {
+        let status = |name: &str, ready: bool| {
+            serde_json::json!({
+                "name": name,
+                "image": "example.com/agent:v1",
+                "imageID": "",
+                "ready": ready,
+                "restartCount": 0,
+            })
+        };
+        serde_json::from_value::<Pod>(serde_json::json!({
+            "spec": { "containers": [{ "name": "agent" }, { "name": "proxy" }] },
+            "status": {
+                "phase": "Running",
+                "containerStatuses": [status("agent", ready), status("proxy", false)]
+            }
+        }))
+    };
+    // The sidecar that is not ready does not make the agent unready.
+    assert!(matches!(pod(true), Ok(pod) if agent_ready(&pod, "agent")));
+    assert!(matches!(pod(false), Ok(pod) if !agent_ready(&pod, "agent")));
+    // A pod without container statuses has not started its agent yet.
+    assert!(!agent_ready(&Pod::default(), "agent"));
+}
+
+#[test]
+fn readiness_is_checked_once_per_interval() {
+    let checks = ReadinessChecks::default();
+    let start = Utc::now();
+    let at = |seconds| start + Duration::seconds(seconds);
+
+    assert!(checks.due("job", at(0)));
+    assert!(!checks.due("job", at(4)));
+    // Each job is checked on its own.
+    assert!(checks.due("other-job", at(4)));
+    assert!(checks.due("job", at(5)));
+}
+
+#[test]
+fn image_pull_grace_starts_with_the_first_failure() {
+    let failures = ImagePullFailures::default();
+    let start = Utc::now();
+    let grace_period = Duration::minutes(2);
+    let failing = || vec![("uid-1".to_string(), "ErrImagePull".to_string())];
+    let overdue = |errors, minutes| {
+        failures.overdue(
+            "job",
+            errors,
+            grace_period,
+            start + Duration::minutes(minutes),
+        )
+    };
+
+    assert_eq!(overdue(failing(), 0), None);
+    assert_eq!(overdue(failing(), 1), None);
+    // A pull that succeeds in between starts over the next time it fails.
+    assert_eq!(overdue(Vec::new(), 2), None);
+    assert_eq!(overdue(failing(), 3), None);
+    assert_eq!(overdue(failing(), 5), Some("ErrImagePull".to_string()));
+    // The job is forgotten once its error has been reported.
+    assert_eq!(overdue(failing(), 6), None);
+}
@@ -17,25 +17,67 @@ and test agent pods when TestSys [`Test`] and [`Resource`] CRD instance is added
     clippy::unwrap_used
 )]
 
+use crate::admission::{run_webhook_server, webhook_address};
+use crate::api_server::{api_address, run_api_server};
+use crate::clock::{Clock, SystemClock};
+use crate::config::ControllerConfig;
+use crate::crds::{install_crds, missing_crds};
+use crate::instance::Instance;
+use crate::job::publish_agent_ca_bundle;
+use crate::rate_limit::{rate_limited_client, rate_limiter};
 use crate::resource_controller::run_resource_controller;
+use crate::results_server::{results_address, run_results_server};
+use crate::retention::{run_retention_sweep, test_retention};
+use crate::schedule::run_scheduler;
 use crate::test_controller::run_test_controller;
 use env_logger::Builder;
 use futures::join;
 use kube::Client;
-use log::{error, info, LevelFilter};
+use log::{error, info, warn, LevelFilter};
+use std::path::Path;
+use std::sync::Arc;
+use testsys_model::system::TESTSYS_CONTROLLER_WEBHOOK_TLS_PATH;
 
+mod admission;
+mod api_auth;
+mod api_server;
+mod clock;
+#[cfg(feature = "cloudwatch-metrics")]
+mod cloudwatch_metrics;
+mod config;
 mod constants;
+mod crds;
 mod error;
+mod events;
+#[cfg(test)]
+mod fake_api;
+mod finalizer;
+mod instance;
 mod job;
+mod metrics;
+mod rate_limit;
 mod resource_controller;
+mod results_server;
+mod resync;
+mod retention;
+mod schedule;
 mod test_controller;
 mod utils;
+mod version;
 
 #[tokio::main]
 async fn main() {
     init_logger();
     info!("Starting");
 
+    let config = match ControllerConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Unable to load the controller config: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Initialize the k8s client from in-cluster variables or KUBECONFIG.
     let client = match Client::try_default().await {
         Ok(client) => client,
@@ -44,12 +86,140 @@ async fn main() {
             std::process::exit(1);
         }
     };
+    // The clock that the parts of the controller outside of the reconcilers tell the time with.
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+
+    // Every part of the controller shares the client, so they share its rate limit.
+    let client = match rate_limiter(&config, clock.clone()) {
+        Some(rate_limiter) => rate_limited_client(client, rate_limiter),
+        None => client,
+    };
+
+    // The controller cannot do anything without its CRDs, and its watches would only fail with
+    // confusing errors.
+    match missing_crds(client.clone()).await {
+        Ok(missing) if missing.is_empty() => {}
+        Ok(missing) if config.install_crds && !config.observe_only => {
+            info!("Installing the missing TestSys CRDs {}", missing.join(", "));
+            if let Err(e) = install_crds(client.clone(), &missing).await {
+                error!("Unable to install the TestSys CRDs: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+        Ok(missing) => {
+            error!(
+                "The TestSys CRDs {} are not installed in the cluster. Install TestSys with \
+                `cli install` before starting the controller, or start the controller with \
+                `--install-crds true` to install them.",
+                missing.join(", ")
+            );
+            std::process::exit(1);
+        }
+        Err(e) => warn!(
+            "Unable to check whether the TestSys CRDs are installed: {:?}",
+            e
+        ),
+    }
+
+    if config.cloudwatch_metrics_namespace.is_some() && !cfg!(feature = "cloudwatch-metrics") {
+        warn!(
+            "CloudWatch metrics will not be published, the controller was built without the \
+            `cloudwatch-metrics` feature"
+        );
+    }
+
+    if config.observe_only {
+        info!("Observe only, the controller will log its actions without taking them");
+    } else if let Some(ca_bundle) = &config.ca_bundle {
+        // Agents would not be able to reach the endpoints that need the CA bundle without it.
+        if let Err(e) = publish_agent_ca_bundle(client.clone(), ca_bundle).await {
+            error!("Unable to publish the CA bundle for the agents: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Run the API server if it is enabled. It writes test statuses so it is not run when observing.
+    let api_server = {
+        let client = client.clone();
+        let address = api_address(&config).filter(|_| !config.observe_only);
+        let instance = Instance::new(&config);
+        async move {
+            if let Some(address) = address {
+                if let Err(e) = run_api_server(client, address, instance).await {
+                    error!("{:?}", e);
+                }
+            }
+        }
+    };
+
+    // Serve the endpoint that test agents stream their results to if it is enabled. It writes test
+    // statuses so it is not run when observing.
+    let results_server = {
+        let client = client.clone();
+        let address = results_address(&config).filter(|_| !config.observe_only);
+        async move {
+            if let Some(address) = address {
+                if let Err(e) = run_results_server(client, address).await {
+                    error!("{:?}", e);
+                }
+            }
+        }
+    };
+
+    // Serve the mutating admission webhook if it is enabled. It only answers the k8s API server and
+    // does not change the cluster itself, and tests created while observing should still get the
+    // defaults that their agents run with, so it is also run when observing.
+    let webhook_server = {
+        let address = webhook_address(&config);
+        let agent_defaults = config.agent_defaults.clone();
+        async move {
+            if let Some(address) = address {
+                let tls_path = Path::new(TESTSYS_CONTROLLER_WEBHOOK_TLS_PATH);
+                if let Err(e) = run_webhook_server(address, tls_path, agent_defaults).await {
+                    error!("{:?}", e);
+                }
+            }
+        }
+    };
+
+    // Delete old tests if a retention window is configured.
+    let retention_sweep = {
+        let client = client.clone();
+        let retention = test_retention(&config).filter(|_| !config.observe_only);
+        let instance = Instance::new(&config);
+        let clock = clock.clone();
+        async move {
+            if let Some(retention) = retention {
+                run_retention_sweep(client, retention, instance, clock).await;
+            }
+        }
+    };
+
+    // Create runs of scheduled tests.
+    let scheduler = {
+        let client = client.clone();
+        let observe_only = config.observe_only;
+        let instance = Instance::new(&config);
+        async move {
+            if !observe_only {
+                run_scheduler(client, instance, clock).await;
+            }
+        }
+    };
 
     // Run the controllers.
-    let future_1 = run_test_controller(client.clone());
-    let future_2 = run_resource_controller(client);
+    let future_1 = run_test_controller(client.clone(), &config);
+    let future_2 = run_resource_controller(client, &config);
 
-    let _ = join!(future_1, future_2);
+    let _ = join!(
+        future_1,
+        future_2,
+        api_server,
+        results_server,
+        webhook_server,
+        retention_sweep,
+        scheduler
+    );
 }
 
 /// The log level used when the `RUST_LOG` environment variable does not exist.
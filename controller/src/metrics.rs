@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of times the controller failed to add or remove a finalizer.
+static FINALIZER_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Count a failed attempt to add or remove a finalizer.
+pub(crate) fn increment_finalizer_failures() {
+    FINALIZER_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn finalizer_failures() -> u64 {
+    FINALIZER_FAILURES.load(Ordering::Relaxed)
+}
+
+/// Render the controller's metrics in the Prometheus text exposition format.
+pub(crate) fn render() -> String {
+    format!(
+        "# HELP testsys_controller_finalizer_failures_total The number of failed attempts to add \
+        or remove a finalizer.\n\
+        # TYPE testsys_controller_finalizer_failures_total counter\n\
+        testsys_controller_finalizer_failures_total {}\n",
+        finalizer_failures()
+    )
+}
+
+#[test]
+fn render_finalizer_failures() {
+    increment_finalizer_failures();
+    let metrics = render();
+    assert!(metrics.contains("# TYPE testsys_controller_finalizer_failures_total counter\n"));
+    let value = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("testsys_controller_finalizer_failures_total "))
+        .and_then(|value| value.parse::<u64>().ok());
+    assert!(matches!(value, Some(value) if value >= 1));
+}
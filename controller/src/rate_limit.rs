@@ -0,0 +1,120 @@
+use crate::clock::Clock;
+use crate::config::ControllerConfig;
+use k8s_openapi::chrono::{DateTime, Utc};
+use log::info;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Limits the rate of the controller's requests to the k8s API server. Requests beyond the limit
+/// are delayed until they fit within it, they are never dropped.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    bucket: Arc<Mutex<TokenBucket>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// A token bucket that holds up to `burst` tokens and is refilled with `qps` tokens per second.
+/// Each request takes a token, a request that finds the bucket empty reserves the next token and
+/// waits for it, so the bucket goes negative while requests are waiting.
+#[derive(Debug)]
+struct TokenBucket {
+    qps: f64,
+    burst: f64,
+    tokens: f64,
+    refilled_at: DateTime<Utc>,
+}
+
+impl RateLimiter {
+    /// Allow `qps` requests per second on average and up to `burst` requests at once.
+    pub(crate) fn new(qps: u32, burst: u32, clock: Arc<dyn Clock>) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                qps: f64::from(qps.max(1)),
+                burst,
+                tokens: burst,
+                refilled_at: clock.now(),
+            })),
+            clock,
+        }
+    }
+
+    /// Wait until a request may be sent.
+    pub(crate) async fn acquire(&self) {
+        let wait = {
+            let mut bucket = match self.bucket.lock() {
+                Ok(bucket) => bucket,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let now = self.clock.now();
+            let elapsed = (now - bucket.refilled_at).to_std().unwrap_or_default();
+            let refill = elapsed.as_secs_f64() * bucket.qps;
+            bucket.tokens = (bucket.tokens + refill).min(bucket.burst);
+            bucket.refilled_at = now;
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                None
+            } else {
+                Some(Duration::from_secs_f64(-bucket.tokens / bucket.qps))
+            }
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// The rate limiter for the controller's k8s client. Returns `None` if requests are not limited.
+/// The burst defaults to one second worth of requests.
+pub(crate) fn rate_limiter(
+    config: &ControllerConfig,
+    clock: Arc<dyn Clock>,
+) -> Option<RateLimiter> {
+    let qps = config.kube_qps.filter(|qps| *qps > 0)?;
+    let burst = config.kube_burst.unwrap_or(qps);
+    info!(
+        "Limiting requests to the k8s API server to {} per second with bursts of {}",
+        qps, burst
+    );
+    Some(RateLimiter::new(qps, burst, clock))
+}
+
+/// A client that sends its requests through `client` once `rate_limiter` allows them.
+pub(crate) fn rate_limited_client(client: kube::Client, rate_limiter: RateLimiter) -> kube::Client {
+    let default_namespace = client.default_namespace().to_string();
+    let service = tower::service_fn(move |request| {
+        let client = client.clone();
+        let rate_limiter = rate_limiter.clone();
+        async move {
+            rate_limiter.acquire().await;
+            client.send(request).await
+        }
+    });
+    kube::Client::new(service, default_namespace)
+}
+
+#[tokio::test]
+async fn bursts_are_delayed() {
+    let client = rate_limited_client(
+        crate::fake_api::fake_k8s_client(vec![(
+            "/version",
+            serde_json::json!({
+                "major": "1",
+                "minor": "24",
+                "gitVersion": "v1.24.10",
+                "gitCommit": "",
+                "gitTreeState": "clean",
+                "buildDate": "",
+                "goVersion": "",
+                "compiler": "gc",
+                "platform": "linux/amd64"
+            }),
+        )]),
+        RateLimiter::new(20, 2, Arc::new(crate::clock::SystemClock)),
+    );
+    let start = std::time::Instant::now();
+    let versions = futures::future::join_all((0..4).map(|_| client.apiserver_version())).await;
+    // The burst is sent right away and the other requests one every 50ms.
+    assert!(start.elapsed() >= Duration::from_millis(90));
+    assert!(versions.iter().all(|version| version.is_ok()));
+}
@@ -1,6 +1,8 @@
 use crate::error::Result;
-use crate::job::{JobState, TEST_START_TIME_LIMIT};
+use crate::job::{JobState, Scheduling, TEST_START_TIME_LIMIT};
 use crate::resource_controller::context::ResourceInterface;
+use crate::resource_controller::pool;
+use crate::test_controller::{invalid_agent, overridden_protected_label, unsupported_oom_retry};
 use crate::utils::parse_duration;
 use kube::core::object::HasSpec;
 use kube::ResourceExt;
@@ -16,6 +18,9 @@ use testsys_model::{CrdExt, DestructionPolicy, ResourceAction, TaskState, TestUs
 pub(super) enum Action {
     Creation(CreationAction),
     Destruction(DestructionAction),
+    /// Remove the reference of a test that no longer exists from the shared resource, e.g. because
+    /// its finalizers were removed before it removed its references.
+    RemoveStaleReference(String),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -25,6 +30,8 @@ pub(super) enum CreationAction {
     AddJobFinalizer,
     AddCleanupFinalizer,
     StartJob,
+    /// Take over the created resource of the named pool entry instead of starting a job.
+    BorrowFromPool(String),
     WaitForDependency(String),
     WaitForConflict(String),
     WaitForDependent,
@@ -37,9 +44,14 @@ pub(super) enum CreationAction {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(super) enum DestructionAction {
     StartResourceDeletion,
+    /// Stop the creation job of a resource that was deleted before it was created. The destruction
+    /// job then cleans up whatever the creation left behind.
+    CancelCreation,
     RemoveCreationJob,
     RemoveCreationJobFinalizer,
     StartDestructionJob,
+    /// Keep the created resource in its pool instead of destroying it.
+    ReturnToPool,
     Wait,
     RemoveDestructionJob,
     RemoveCleanupFinalizer,
@@ -57,9 +69,14 @@ pub(super) enum ErrorState {
     JobTimeout,
     TaskFailed,
     Zombie,
+    /// The resource's spec cannot be used, the creation job is not started.
+    InvalidSpec(String),
 }
 
 pub(super) async fn action(r: &ResourceInterface) -> Result<Action> {
+    if let Some(test_name) = stale_reference(r).await? {
+        return Ok(Action::RemoveStaleReference(test_name));
+    }
     if r.resource().is_delete_requested() || is_deletion_required(r).await? {
         Ok(Action::Destruction(destruction_action(r).await?))
     } else {
@@ -67,6 +84,21 @@ pub(super) async fn action(r: &ResourceInterface) -> Result<Action> {
     }
 }
 
+/// The first test that holds a reference to the shared resource but no longer exists.
+async fn stale_reference(r: &ResourceInterface) -> Result<Option<String>> {
+    if r.resource().references().is_empty() {
+        return Ok(None);
+    }
+    let tests = TestClient::new_from_k8s_client(r.k8s_client())
+        .get_all()
+        .await?;
+    Ok(r.resource()
+        .references()
+        .iter()
+        .find(|reference| !tests.iter().any(|test| test.name_any() == **reference))
+        .cloned())
+}
+
 async fn creation_action(r: &ResourceInterface) -> Result<CreationAction> {
     if r.resource().status.is_none() {
         return Ok(CreationAction::Initialize);
@@ -76,6 +108,26 @@ async fn creation_action(r: &ResourceInterface) -> Result<CreationAction> {
         return Ok(CreationAction::AddMainFinalizer);
     }
 
+    // Pool entries are created by the resource that was returned to the pool, which sends their
+    // status right after creating them. Then they wait in the pool to be borrowed.
+    if r.resource().is_pool_entry() {
+        return match r.resource().creation_task_state() {
+            TaskState::Completed => creation_completed_action(r).await,
+            _ => Ok(CreationAction::WaitForCreation),
+        };
+    }
+
+    // A resource whose spec cannot be used is never created.
+    if r.resource().creation_task_state() == TaskState::Unknown {
+        let agent = &r.resource().spec.agent;
+        if let Some(reason) = invalid_agent(agent)
+            .or_else(|| unsupported_oom_retry(agent))
+            .or_else(|| overridden_protected_label([agent], r.protected_labels()))
+        {
+            return Ok(CreationAction::Error(ErrorState::InvalidSpec(reason)));
+        }
+    }
+
     if let Some(wait_action) = dependency_wait_action(r).await? {
         return Ok(wait_action);
     }
@@ -174,13 +226,29 @@ async fn creation_not_done_action(
     if !r.resource().has_finalizer(FINALIZER_CLEANUP_REQUIRED) {
         return Ok(CreationAction::AddCleanupFinalizer);
     }
+    if !is_task_state_running {
+        if let Some(entry_name) = pool::available_entry(r).await? {
+            return Ok(CreationAction::BorrowFromPool(entry_name));
+        }
+    }
     let job_state = r.get_job_state(ResourceAction::Create).await?;
     match job_state {
         JobState::None if !is_task_state_running => Ok(CreationAction::StartJob),
         JobState::None => Ok(CreationAction::Error(ErrorState::JobRemoved)),
-        JobState::Unknown => Ok(CreationAction::WaitForCreation),
+        JobState::Unknown | JobState::Deleting => Ok(CreationAction::WaitForCreation),
         JobState::Running(None) => Ok(CreationAction::WaitForCreation),
         JobState::Running(Some(duration)) => {
+            // The time the pod is gated does not count against the agent's time limits.
+            let duration = if r.resource().spec.agent.scheduling_gates.is_none() {
+                duration
+            } else {
+                match r.get_scheduling(ResourceAction::Create).await? {
+                    Scheduling::Gated | Scheduling::Unscheduled => {
+                        return Ok(CreationAction::WaitForCreation)
+                    }
+                    Scheduling::Scheduled(scheduled_at) => r.now() - scheduled_at,
+                }
+            };
             if let Ok(std_duration) = duration.to_std() {
                 if r.resource()
                     .spec
@@ -201,8 +269,12 @@ async fn creation_not_done_action(
             }
             Ok(CreationAction::WaitForCreation)
         }
-        JobState::Failed => Ok(CreationAction::Error(ErrorState::JobFailed)),
-        JobState::Exited => Ok(CreationAction::Error(ErrorState::JobExited)),
+        JobState::Completions { failed: 0, .. } | JobState::Exited => {
+            Ok(CreationAction::Error(ErrorState::JobExited))
+        }
+        JobState::Failed | JobState::Completions { .. } => {
+            Ok(CreationAction::Error(ErrorState::JobFailed))
+        }
     }
 }
 
@@ -222,6 +294,7 @@ async fn is_deletion_required(r: &ResourceInterface) -> Result<bool> {
         destruction_policy,
         DestructionPolicy::OnTestCompletion | DestructionPolicy::OnTestSuccess
     ) || r.resource().created_resource().is_none()
+        || !r.resource().references().is_empty()
     {
         return Ok(false);
     }
@@ -277,6 +350,15 @@ async fn creation_cleanup_action(r: &ResourceInterface) -> Result<Option<Destruc
     let job_state = r.get_job_state(ResourceAction::Create).await?;
     if matches!(job_state, JobState::None) {
         Ok(Some(DestructionAction::RemoveCreationJobFinalizer))
+    } else if matches!(job_state, JobState::Deleting) {
+        // A cancelled creation job is gone once its pods are, so the destruction never runs while
+        // the creation is still running.
+        Ok(Some(DestructionAction::Wait))
+    } else if matches!(
+        r.resource().creation_task_state(),
+        TaskState::Unknown | TaskState::Running
+    ) {
+        Ok(Some(DestructionAction::CancelCreation))
     } else {
         Ok(Some(DestructionAction::RemoveCreationJob))
     }
@@ -296,12 +378,18 @@ async fn destruction_action_with_resources(r: &ResourceInterface) -> Result<Dest
                 r.name(),
                 destruction_policy
             );
-            if r.resource().has_finalizer(FINALIZER_CLEANUP_REQUIRED) {
-                return Ok(DestructionAction::RemoveCleanupFinalizer);
-            }
-            return Ok(DestructionAction::RemoveResourceFinalizer);
+            return Ok(skip_destruction_action(r));
         }
     }
+    // A shared resource is not destroyed while tests still reference it.
+    if !r.resource().references().is_empty()
+        && r.resource().destruction_task_state() == TaskState::Unknown
+    {
+        return Ok(DestructionAction::Wait);
+    }
+    if let Some(pool_action) = pool_return_action(r).await? {
+        return Ok(pool_action);
+    }
     match r.resource().destruction_task_state() {
         TaskState::Unknown => destruction_not_done_action(r, false).await,
         TaskState::Running => destruction_not_done_action(r, true).await,
@@ -322,6 +410,35 @@ async fn destruction_action_with_resources(r: &ResourceInterface) -> Result<Dest
     }
 }
 
+/// Remove the finalizers that keep the resource until it is destroyed, without destroying it.
+fn skip_destruction_action(r: &ResourceInterface) -> DestructionAction {
+    if r.resource().has_finalizer(FINALIZER_CLEANUP_REQUIRED) {
+        DestructionAction::RemoveCleanupFinalizer
+    } else {
+        DestructionAction::RemoveResourceFinalizer
+    }
+}
+
+/// A pooled resource that was created is returned to its pool instead of being destroyed, unless
+/// the pool is full.
+async fn pool_return_action(r: &ResourceInterface) -> Result<Option<DestructionAction>> {
+    if r.resource().pool_key().is_none()
+        || r.resource().is_pool_entry()
+        || r.resource().creation_task_state() != TaskState::Completed
+        || r.resource().destruction_task_state() != TaskState::Unknown
+    {
+        return Ok(None);
+    }
+    if pool::is_returned(r).await? {
+        debug!("Resource '{}' has been returned to its pool", r.name());
+        Ok(Some(skip_destruction_action(r)))
+    } else if pool::has_capacity(r).await? {
+        Ok(Some(DestructionAction::ReturnToPool))
+    } else {
+        Ok(None)
+    }
+}
+
 async fn destruction_not_done_action(
     r: &ResourceInterface,
     is_task_state_running: bool,
@@ -330,9 +447,20 @@ async fn destruction_not_done_action(
     match job_state {
         JobState::None if !is_task_state_running => Ok(DestructionAction::StartDestructionJob),
         JobState::None => Ok(DestructionAction::Error(ErrorState::JobRemoved)),
-        JobState::Unknown => Ok(DestructionAction::Wait),
+        JobState::Unknown | JobState::Deleting => Ok(DestructionAction::Wait),
         JobState::Running(None) => Ok(DestructionAction::Wait),
         JobState::Running(Some(duration)) => {
+            // The time the pod is gated does not count against the agent's time limits.
+            let duration = if r.resource().spec.agent.scheduling_gates.is_none() {
+                duration
+            } else {
+                match r.get_scheduling(ResourceAction::Destroy).await? {
+                    Scheduling::Gated | Scheduling::Unscheduled => {
+                        return Ok(DestructionAction::Wait)
+                    }
+                    Scheduling::Scheduled(scheduled_at) => r.now() - scheduled_at,
+                }
+            };
             if let Ok(std_duration) = duration.to_std() {
                 if r.resource()
                     .spec
@@ -353,8 +481,12 @@ async fn destruction_not_done_action(
             }
             Ok(DestructionAction::Wait)
         }
-        JobState::Failed => Ok(DestructionAction::Error(ErrorState::JobFailed)),
-        JobState::Exited => Ok(DestructionAction::Error(ErrorState::JobExited)),
+        JobState::Completions { failed: 0, .. } | JobState::Exited => {
+            Ok(DestructionAction::Error(ErrorState::JobExited))
+        }
+        JobState::Failed | JobState::Completions { .. } => {
+            Ok(DestructionAction::Error(ErrorState::JobFailed))
+        }
     }
 }
 
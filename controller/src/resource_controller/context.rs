@@ -1,21 +1,36 @@
+use crate::clock::{Clock, SystemClock};
+use crate::config::ControllerConfig;
 use crate::error::Result;
-use crate::job::{archive_logs, delete_job, get_job_state, JobBuilder, JobState, JobType};
+use crate::instance::Instance;
+use crate::job::{
+    archive_logs, delete_job, delete_job_in_foreground, get_job_state, get_scheduling, resolve_env,
+    JobBuilder, JobSettings, JobState, JobType, Scheduling,
+};
 use anyhow::Context as AnyhowContext;
-use kube::Api;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::{Api, ResourceExt};
 use log::{debug, error};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use testsys_model::clients::{CrdClient, ResourceClient};
-use testsys_model::constants::{ENV_RESOURCE_ACTION, ENV_RESOURCE_NAME};
+use testsys_model::clients::{CrdClient, ResourceClient, TestClient};
+use testsys_model::constants::{
+    ENV_RESOURCE_ACTION, ENV_RESOURCE_NAME, ENV_TEST_NAME, ENV_TEST_UID,
+};
 use testsys_model::test_manager::ResourceState;
-use testsys_model::{CrdExt, Resource, ResourceAction};
+use testsys_model::{Agent, CrdExt, Resource, ResourceAction, Test};
 
 /// This is used by `kube-runtime` to pass any custom information we need when [`reconcile`] is
 /// called.
 pub(super) type Context = Arc<ContextData>;
 
-pub(super) fn new_context(client: kube::Client) -> Context {
+pub(super) fn new_context(client: kube::Client, config: &ControllerConfig) -> Context {
     Arc::new(ContextData {
         resource_client: ResourceClient::new_from_k8s_client(client),
+        archive_logs: config.archive_logs,
+        observe_only: config.observe_only,
+        job_settings: JobSettings::new(config),
+        instance: Instance::new(config),
+        clock: Arc::new(SystemClock),
     })
 }
 
@@ -23,6 +38,16 @@ pub(super) fn new_context(client: kube::Client) -> Context {
 #[derive(Clone)]
 pub(crate) struct ContextData {
     resource_client: ResourceClient,
+    /// Whether the logs of resource agents are archived before their jobs are deleted.
+    archive_logs: bool,
+    /// Whether actions are only logged instead of taken.
+    observe_only: bool,
+    /// The settings that agent jobs are built with.
+    job_settings: JobSettings,
+    /// The controller instance that claims the resources it reconciles.
+    instance: Instance,
+    /// Tells the time for the controller's time-based decisions.
+    clock: Arc<dyn Clock>,
 }
 
 impl ContextData {
@@ -64,6 +89,26 @@ impl ResourceInterface {
         self.context.api()
     }
 
+    /// The current time according to the controller's clock.
+    pub(super) fn now(&self) -> DateTime<Utc> {
+        self.context.clock.now()
+    }
+
+    /// The labels that the controller sets on every agent pod, which agents cannot override.
+    pub(super) fn protected_labels(&self) -> &BTreeMap<String, String> {
+        &self.context.job_settings.protected_labels
+    }
+
+    /// Whether the controller only logs the actions it would take.
+    pub(super) fn is_observe_only(&self) -> bool {
+        self.context.observe_only
+    }
+
+    /// The controller instance that claims the resources it reconciles.
+    pub(super) fn instance(&self) -> &Instance {
+        &self.context.instance
+    }
+
     pub(super) fn resource_client(&self) -> &ResourceClient {
         &self.context.resource_client
     }
@@ -76,16 +121,55 @@ impl ResourceInterface {
         self.get_job_state_by_name(self.job_name(op)).await
     }
 
+    /// The test that requires the resource, if there is one. Resource agents tag the cloud
+    /// resources they create with its identity.
+    async fn requiring_test(&self) -> Result<Option<Test>> {
+        let tests = TestClient::new_from_k8s_client(self.k8s_client())
+            .get_all()
+            .await
+            .with_context(|| format!("Unable to list tests requiring '{}'", self.name()))?
+            .into_iter()
+            .filter(|test| self.instance().other_owner(test).is_none())
+            .collect();
+        Ok(oldest_requiring_test(tests, self.name()))
+    }
+
     pub(super) async fn start_job(&self, op: ResourceAction) -> Result<()> {
         let job_name = self.job_name(op);
+        let agent = &self.resource().spec.agent;
+        // The agent's `env` comes first so that it cannot override the variables that identify
+        // the resource, its action and the test that requires it.
+        let mut environment_variables = resolve_env(agent, &self.resource().metadata)
+            .with_context(|| format!("Unable to start job '{}'", job_name))?;
+        environment_variables.push((ENV_RESOURCE_ACTION, op.to_string()));
+        environment_variables.push((ENV_RESOURCE_NAME, self.name().to_owned()));
+        if let Some(test) = self.requiring_test().await? {
+            environment_variables.push((ENV_TEST_NAME, test.name_any()));
+            environment_variables.push((ENV_TEST_UID, test.uid().unwrap_or_default()));
+        }
+        // The destroy job runs the agent's destroy image if it has one.
+        let destroy_agent;
+        let agent = match (op, &agent.destroy_image) {
+            (ResourceAction::Destroy, Some(destroy_image)) => {
+                destroy_agent = Agent {
+                    image: destroy_image.to_owned(),
+                    ..agent.clone()
+                };
+                &destroy_agent
+            }
+            _ => agent,
+        };
         let deploy_result = JobBuilder {
-            agent: &self.resource().spec.agent,
+            agent,
             job_name,
             job_type: JobType::ResourceAgent,
-            environment_variables: vec![
-                (ENV_RESOURCE_ACTION, op.to_string()),
-                (ENV_RESOURCE_NAME, self.name().to_owned()),
-            ],
+            environment_variables,
+            resource_outputs: None,
+            settings: &self.context.job_settings,
+            memory_limit: None,
+            wait_for_endpoints: &[],
+            input_hash: None,
+            test_uid: None,
         }
         .deploy(self.resource_client().api().clone().into_client())
         .await;
@@ -102,21 +186,48 @@ impl ResourceInterface {
     }
 
     pub(super) async fn remove_job(&self, op: ResourceAction) -> Result<()> {
-        if let Err(e) = archive_logs(self.k8s_client(), self.job_name(op)).await {
-            error!(
-                "Unable to archive logs for job '{}': {}",
-                self.job_name(op),
-                e
-            );
-        }
+        self.archive_job_logs(op).await;
         delete_job(self.k8s_client(), self.job_name(op))
             .await
             .context(format!("Unable to remove job '{}'", self.job_name(op)))?;
         Ok(())
     }
 
+    /// Stop the job for `op` while it is still running. The job is deleted in the foreground, so it
+    /// is only gone once its pods have terminated.
+    pub(super) async fn cancel_job(&self, op: ResourceAction) -> Result<()> {
+        self.archive_job_logs(op).await;
+        delete_job_in_foreground(self.k8s_client(), self.job_name(op))
+            .await
+            .context(format!("Unable to cancel job '{}'", self.job_name(op)))?;
+        Ok(())
+    }
+
+    async fn archive_job_logs(&self, op: ResourceAction) {
+        if self.context.archive_logs {
+            if let Err(e) = archive_logs(self.k8s_client(), self.job_name(op)).await {
+                error!(
+                    "Unable to archive logs for job '{}': {}",
+                    self.job_name(op),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Whether the pod of the job for `op` is held back by scheduling gates that have not been
+    /// removed yet, or when it was scheduled once they were.
+    pub(super) async fn get_scheduling(&self, op: ResourceAction) -> Result<Scheduling> {
+        get_scheduling(self.k8s_client(), self.job_name(op))
+            .await
+            .context(format!(
+                "Unable to get the pods of job '{}'",
+                self.job_name(op)
+            ))
+    }
+
     async fn get_job_state_by_name(&self, job_name: &str) -> Result<JobState> {
-        get_job_state(self.k8s_client(), job_name)
+        get_job_state(self.k8s_client(), job_name, self.now())
             .await
             .context(format!("Unable to get state of job '{}'", job_name))
     }
@@ -128,3 +239,101 @@ impl ResourceInterface {
         }
     }
 }
+
+/// The oldest of the `tests` that require the resource `resource_name`. Tests created at the same
+/// time are ordered by name, so the same test is chosen whatever order the tests are listed in.
+fn oldest_requiring_test(tests: Vec<Test>, resource_name: &str) -> Option<Test> {
+    tests
+        .into_iter()
+        .filter(|test| test.spec.resources.iter().any(|name| name == resource_name))
+        .min_by(|a, b| {
+            a.creation_timestamp()
+                .cmp(&b.creation_timestamp())
+                .then_with(|| a.name_any().cmp(&b.name_any()))
+        })
+}
+
+#[test]
+fn oldest_requiring_test_is_chosen() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{TimeZone, Utc};
+    use testsys_model::TestSpec;
+
+    let test = |name: &str, hour: u32, resources: &[&str]| {
+        let mut test = Test::new(
+            name,
+            TestSpec {
+                resources: resources.iter().map(|name| name.to_string()).collect(),
+                ..TestSpec::default()
+            },
+        );
+        test.metadata.creation_timestamp = Utc
+            .with_ymd_and_hms(2023, 1, 1, hour, 0, 0)
+            .single()
+            .map(Time);
+        test
+    };
+    let tests = vec![
+        test("newer", 2, &["cluster"]),
+        test("oldest-b", 1, &["cluster"]),
+        test("oldest-a", 1, &["cluster"]),
+        test("unrelated", 0, &["instances"]),
+    ];
+    let chosen = |tests: Vec<Test>| oldest_requiring_test(tests, "cluster").map(|t| t.name_any());
+    assert_eq!(chosen(tests.clone()), Some("oldest-a".to_string()));
+    assert_eq!(
+        chosen(tests.into_iter().rev().collect()),
+        Some("oldest-a".to_string())
+    );
+    assert_eq!(chosen(Vec::new()), None);
+}
+
+#[tokio::test]
+async fn destroy_job_uses_destroy_image() {
+    use k8s_openapi::api::batch::v1::Job;
+    use testsys_model::constants::NAMESPACE;
+    use testsys_model::ResourceSpec;
+
+    let job_image = |destroy_image: Option<&str>, op: ResourceAction| {
+        let mut resource = Resource::new(
+            "cluster",
+            ResourceSpec {
+                agent: Agent {
+                    name: "eks-provider".to_string(),
+                    image: "eks-resource-agent:v1".to_string(),
+                    destroy_image: destroy_image.map(str::to_string),
+                    ..Agent::default()
+                },
+                ..ResourceSpec::default()
+            },
+        );
+        resource.metadata.namespace = Some(NAMESPACE.to_string());
+        resource.metadata.uid = Some("0123abcd".to_string());
+        async move {
+            let k8s_client =
+                crate::fake_api::fake_k8s_store(vec![serde_json::json!(resource.clone())]);
+            let context = new_context(k8s_client.clone(), &ControllerConfig::default());
+            let r = ResourceInterface::new(resource, context).ok()?;
+            r.start_job(op).await.ok()?;
+            let job: Job = Api::namespaced(k8s_client, NAMESPACE)
+                .get(r.job_name(op))
+                .await
+                .ok()?;
+            job.spec?.template.spec?.containers.first()?.image.clone()
+        }
+    };
+
+    assert_eq!(
+        job_image(Some("eks-destroyer:v1"), ResourceAction::Destroy).await,
+        Some("eks-destroyer:v1".to_string())
+    );
+    assert_eq!(
+        job_image(Some("eks-destroyer:v1"), ResourceAction::Create).await,
+        Some("eks-resource-agent:v1".to_string())
+    );
+    // Without a destroy image the agent's image destroys the resource.
+    assert_eq!(
+        job_image(None, ResourceAction::Destroy).await,
+        Some("eks-resource-agent:v1".to_string())
+    );
+}
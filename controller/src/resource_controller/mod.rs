@@ -1,18 +1,22 @@
 mod action;
 mod context;
+mod pool;
 
-use crate::constants::requeue;
+use crate::config::ControllerConfig;
+use crate::constants::{no_requeue, requeue, requeue_slow};
 use crate::error::{ReconciliationError, ReconciliationResult, Result};
+use crate::finalizer::{add_finalizer, remove_finalizer};
 use crate::resource_controller::action::{
     action, Action, CreationAction, DestructionAction, ErrorState,
 };
 use crate::resource_controller::context::{new_context, Context, ResourceInterface};
+use crate::resync::resync;
 use anyhow::Context as AnyhowContext;
 use futures::StreamExt;
 use kube::{Api, Client};
 use kube_runtime::controller::Action as RequeueAction;
 use kube_runtime::{controller, watcher, Controller};
-use log::{debug, error, trace, warn};
+use log::{debug, error, info, trace, warn};
 use std::ops::Deref;
 use std::sync::Arc;
 use testsys_model::clients::CrdClient;
@@ -22,15 +26,21 @@ use testsys_model::constants::{
 };
 use testsys_model::{CrdExt, ErrorResources, Resource, ResourceAction, ResourceError};
 
-pub(crate) async fn run_resource_controller(client: Client) {
-    let context = new_context(client.clone());
+pub(crate) async fn run_resource_controller(client: Client, config: &ControllerConfig) {
+    let context = new_context(client.clone(), config);
+    // Every resource is reconciled again once an expired watch has restarted.
+    let (resync, resync_trigger) = resync();
     Controller::new(
         Api::<Resource>::namespaced(client, NAMESPACE),
         watcher::Config::default(),
     )
+    .reconcile_all_on(resync_trigger)
     .run(reconcile, handle_reconciliation_error, context)
-    .for_each(|reconciliation_result| async move {
+    .for_each(|reconciliation_result| async {
         if let Err(reconciliation_err) = reconciliation_result {
+            if resync.on_error(&reconciliation_err) {
+                return;
+            }
             match &reconciliation_err {
                 controller::Error::ObjectNotFound { .. } => {
                     // TODO - not sure why we get this after object deletion
@@ -52,14 +62,75 @@ pub(super) async fn reconcile(
         "Reconciling resource: {}",
         interface.resource().object_name()
     );
+    // Resources claimed by other controller instances are left to them entirely.
+    let instance = interface.instance();
+    if let Some(owner) = instance.other_owner(interface.resource()) {
+        trace!(
+            "Ignoring '{}', it is reconciled by controller instance '{}'",
+            interface.name(),
+            owner
+        );
+        return Ok(no_requeue());
+    }
+    if let Some(instance_id) = instance
+        .id()
+        .filter(|_| instance.must_claim(interface.resource()))
+    {
+        if interface.is_observe_only() {
+            info!(
+                "Observe only, not claiming resource '{}' for controller instance '{}'",
+                interface.name(),
+                instance_id
+            );
+            return Ok(requeue_slow());
+        }
+        interface
+            .resource_client()
+            .claim_for_instance(interface.resource(), instance_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Unable to claim '{}' for controller instance '{}'",
+                    interface.name(),
+                    instance_id
+                )
+            })?;
+        return Ok(requeue());
+    }
 
     let action = action(&interface).await?;
     trace!("Action: {:?}", action);
+    if interface.is_observe_only() {
+        info!(
+            "Observe only, not taking action {:?} for resource '{}'",
+            action,
+            interface.name()
+        );
+        return Ok(requeue_slow());
+    }
     match action {
         Action::Creation(creation_action) => do_creation_action(interface, creation_action).await?,
         Action::Destruction(destruction_action) => {
             do_destruction_action(interface, destruction_action).await?
         }
+        Action::RemoveStaleReference(test_name) => {
+            info!(
+                "Removing the reference of test '{}', which no longer exists, from '{}'",
+                test_name,
+                interface.name()
+            );
+            let _ = interface
+                .resource_client()
+                .remove_reference(interface.resource(), &test_name)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Unable to remove reference of test '{}' from '{}'",
+                        test_name,
+                        interface.name()
+                    )
+                })?;
+        }
     }
     Ok(requeue())
 }
@@ -74,27 +145,32 @@ async fn do_creation_action(r: ResourceInterface, action: CreationAction) -> Res
                 .with_context(|| format!("Unable to initialize '{}'", r.name()))?;
         }
         CreationAction::AddMainFinalizer => {
-            let _ = r
-                .resource_client()
-                .add_finalizer(FINALIZER_MAIN, r.resource())
+            let _ = add_finalizer(r.resource_client(), FINALIZER_MAIN, r.resource(), r.now())
                 .await
                 .with_context(|| format!("Unable to add main finalizer to '{}'", r.name()))?;
         }
         CreationAction::AddJobFinalizer => {
-            let _ = r
-                .resource_client()
-                .add_finalizer(FINALIZER_CREATION_JOB, r.resource())
-                .await
-                .with_context(|| format!("Unable to creation job finalizer to '{}'", r.name()))?;
+            let _ = add_finalizer(
+                r.resource_client(),
+                FINALIZER_CREATION_JOB,
+                r.resource(),
+                r.now(),
+            )
+            .await
+            .with_context(|| format!("Unable to creation job finalizer to '{}'", r.name()))?;
         }
         CreationAction::AddCleanupFinalizer => {
-            let _ = r
-                .resource_client()
-                .add_finalizer(FINALIZER_CLEANUP_REQUIRED, r.resource())
-                .await
-                .with_context(|| format!("Unable to add resource finalizer to '{}'", r.name()))?;
+            let _ = add_finalizer(
+                r.resource_client(),
+                FINALIZER_CLEANUP_REQUIRED,
+                r.resource(),
+                r.now(),
+            )
+            .await
+            .with_context(|| format!("Unable to add resource finalizer to '{}'", r.name()))?;
         }
         CreationAction::StartJob => r.start_job(ResourceAction::Create).await?,
+        CreationAction::BorrowFromPool(entry_name) => pool::borrow(&r, &entry_name).await?,
         CreationAction::WaitForCreation => {
             debug!("waiting for creation of resource '{}'", r.name())
         }
@@ -116,11 +192,14 @@ async fn do_creation_action(r: ResourceInterface, action: CreationAction) -> Res
             debug!("'{}' is waiting for test that requires it", r.name());
         }
         CreationAction::AddResourceFinalizer => {
-            let _ = r
-                .resource_client()
-                .add_finalizer(FINALIZER_RESOURCE, r.resource())
-                .await
-                .with_context(|| format!("Unable to add resource finalizer to '{}'", r.name()))?;
+            let _ = add_finalizer(
+                r.resource_client(),
+                FINALIZER_RESOURCE,
+                r.resource(),
+                r.now(),
+            )
+            .await
+            .with_context(|| format!("Unable to add resource finalizer to '{}'", r.name()))?;
         }
         CreationAction::Done => {}
         CreationAction::Error(error_state) => {
@@ -135,46 +214,55 @@ async fn do_destruction_action(r: ResourceInterface, action: DestructionAction)
         DestructionAction::StartResourceDeletion => {
             r.resource_client().delete(r.name()).await?;
         }
+        DestructionAction::CancelCreation => cancel_creation(&r).await?,
         DestructionAction::RemoveCreationJob => {
             r.remove_job(ResourceAction::Create).await?;
         }
         DestructionAction::RemoveCreationJobFinalizer => {
-            r.resource_client()
-                .remove_finalizer(FINALIZER_CREATION_JOB, r.resource())
-                .await
-                .with_context(|| {
-                    format!(
-                        "Unable to remove creation job finalizer from '{}'",
-                        r.name()
-                    )
-                })?;
+            remove_finalizer(
+                r.resource_client(),
+                FINALIZER_CREATION_JOB,
+                r.resource(),
+                r.now(),
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "Unable to remove creation job finalizer from '{}'",
+                    r.name()
+                )
+            })?;
         }
         DestructionAction::StartDestructionJob => {
             r.start_job(ResourceAction::Destroy).await?;
         }
+        DestructionAction::ReturnToPool => pool::return_to_pool(&r).await?,
         DestructionAction::Wait => {}
         DestructionAction::RemoveDestructionJob => {
             r.remove_job(ResourceAction::Destroy).await?;
         }
         DestructionAction::RemoveCleanupFinalizer => {
-            r.resource_client()
-                .remove_finalizer(FINALIZER_CLEANUP_REQUIRED, r.resource())
-                .await
-                .with_context(|| {
-                    format!("Unable to cleanup resource finalizer from '{}'", r.name())
-                })?;
+            remove_finalizer(
+                r.resource_client(),
+                FINALIZER_CLEANUP_REQUIRED,
+                r.resource(),
+                r.now(),
+            )
+            .await
+            .with_context(|| format!("Unable to cleanup resource finalizer from '{}'", r.name()))?;
         }
         DestructionAction::RemoveResourceFinalizer => {
-            r.resource_client()
-                .remove_finalizer(FINALIZER_RESOURCE, r.resource())
-                .await
-                .with_context(|| {
-                    format!("Unable to remove resource finalizer from '{}'", r.name())
-                })?;
+            remove_finalizer(
+                r.resource_client(),
+                FINALIZER_RESOURCE,
+                r.resource(),
+                r.now(),
+            )
+            .await
+            .with_context(|| format!("Unable to remove resource finalizer from '{}'", r.name()))?;
         }
         DestructionAction::RemoveMainFinalizer => {
-            r.resource_client()
-                .remove_finalizer(FINALIZER_MAIN, r.resource())
+            remove_finalizer(r.resource_client(), FINALIZER_MAIN, r.resource(), r.now())
                 .await
                 .with_context(|| format!("Unable to remove main finalizer from '{}'", r.name()))?;
         }
@@ -185,6 +273,28 @@ async fn do_destruction_action(r: ResourceInterface, action: DestructionAction)
     Ok(())
 }
 
+/// Record that the creation of a deleted resource was cancelled and stop its creation job. The
+/// creation may have left resources behind, so they are marked as remaining for the destruction
+/// job, which gets whatever agent info the creation job recorded before it was stopped. The
+/// destruction only starts once the creation job's pods have terminated.
+async fn cancel_creation(r: &ResourceInterface) -> Result<()> {
+    info!(
+        "Cancelling the creation of resource '{}' because it was deleted",
+        r.name()
+    );
+    if r.resource().error(ResourceAction::Create).is_none() {
+        let resource_error = ResourceError {
+            error: "Creation was cancelled because the resource was deleted".to_string(),
+            error_resources: ErrorResources::Remaining,
+        };
+        r.resource_client()
+            .send_error(r.name(), ResourceAction::Create, &resource_error)
+            .await
+            .with_context(|| format!("Unable to send cancellation of '{}'", r.name()))?;
+    }
+    r.cancel_job(ResourceAction::Create).await
+}
+
 async fn handle_error_state(r: &ResourceInterface, a: ResourceAction, e: ErrorState) -> Result<()> {
     let message = format!(
         "{} error state for resource '{}': {}",
@@ -193,7 +303,8 @@ async fn handle_error_state(r: &ResourceInterface, a: ResourceAction, e: ErrorSt
             ResourceAction::Destroy => "Destruction",
         },
         r.name(),
-        match e {
+        match &e {
+            ErrorState::InvalidSpec(reason) => reason,
             ErrorState::JobStart => "Timeout before resource started",
             ErrorState::JobExited => "Container exited before it was done",
             ErrorState::JobFailed => "Container exited with an error",
@@ -235,3 +346,251 @@ pub(crate) fn handle_reconciliation_error(
     error!("Resource reconciliation error: {}", e);
     requeue()
 }
+
+#[tokio::test]
+async fn deletion_during_creation_cancels_creation() {
+    use crate::job::JobState;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use testsys_model::test_manager::ResourceState;
+    use testsys_model::{Agent, ResourceSpec, ResourceStatus, TaskState};
+
+    let mut resource = Resource::new(
+        "cluster",
+        ResourceSpec {
+            agent: Agent {
+                name: "eks-provider".to_string(),
+                image: "eks-resource-agent:v1".to_string(),
+                ..Agent::default()
+            },
+            ..ResourceSpec::default()
+        },
+    );
+    resource.metadata.namespace = Some(NAMESPACE.to_string());
+    resource.metadata.uid = Some("0123abcd".to_string());
+    resource.metadata.deletion_timestamp = Some(Time(k8s_openapi::chrono::Utc::now()));
+    resource.metadata.finalizers = Some(vec![
+        FINALIZER_MAIN.to_string(),
+        FINALIZER_CREATION_JOB.to_string(),
+        FINALIZER_CLEANUP_REQUIRED.to_string(),
+    ]);
+    let mut status = ResourceStatus::default();
+    status.creation.task_state = TaskState::Running;
+    status.agent_info = serde_json::json!({ "clusterName": "partial" })
+        .as_object()
+        .cloned();
+    resource.status = Some(status);
+    let client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(resource)]);
+    let context = new_context(client.clone(), &ControllerConfig::default());
+    let resource_client =
+        testsys_model::clients::ResourceClient::new_from_k8s_client(client.clone());
+    let interface = |resource: Resource| ResourceInterface::new(resource, context.clone());
+    let reconciled = || async {
+        let r = interface(resource_client.get("cluster").await?)?;
+        let action = action(&r).await?;
+        if let Action::Destruction(destruction_action) = &action {
+            do_destruction_action(r, destruction_action.clone()).await?;
+        }
+        Ok::<_, anyhow::Error>(action)
+    };
+    let job_state = |op| {
+        let (resource_client, interface) = (&resource_client, &interface);
+        async move {
+            interface(resource_client.get("cluster").await?)?
+                .get_job_state(op)
+                .await
+        }
+    };
+
+    // The resource was deleted while its creation job is still running.
+    let started = match interface(resource.clone()) {
+        Ok(r) => r.start_job(ResourceAction::Create).await,
+        Err(e) => Err(e),
+    };
+    assert!(started.is_ok(), "{:?}", started);
+
+    // The creation is cancelled instead of waiting for it to finish.
+    assert!(matches!(
+        reconciled().await,
+        Ok(Action::Destruction(DestructionAction::CancelCreation))
+    ));
+    assert!(matches!(
+        job_state(ResourceAction::Create).await,
+        Ok(JobState::Deleting)
+    ));
+    assert!(matches!(
+        resource_client.get("cluster").await,
+        Ok(cancelled) if cancelled.creation_task_state() == TaskState::Error
+            && matches!(
+                cancelled.error(ResourceAction::Create),
+                Some(error) if error.error_resources == ErrorResources::Remaining
+            )
+    ));
+
+    // Nothing is destroyed until the creation job's pods have terminated.
+    assert!(matches!(
+        reconciled().await,
+        Ok(Action::Destruction(DestructionAction::Wait))
+    ));
+    let creation_job = resource.job_name(ResourceState::Creation);
+    assert!(crate::job::delete_job(client.clone(), &creation_job)
+        .await
+        .is_ok());
+
+    // Then whatever the creation left behind is destroyed.
+    assert!(matches!(
+        reconciled().await,
+        Ok(Action::Destruction(
+            DestructionAction::RemoveCreationJobFinalizer
+        ))
+    ));
+    assert!(matches!(
+        reconciled().await,
+        Ok(Action::Destruction(DestructionAction::StartDestructionJob))
+    ));
+    assert!(!matches!(
+        job_state(ResourceAction::Destroy).await,
+        Ok(JobState::None) | Err(_)
+    ));
+}
+
+#[tokio::test]
+async fn resource_overriding_protected_label_is_not_created() {
+    use std::collections::BTreeMap;
+    use testsys_model::{Agent, ResourceSpec, ResourceStatus};
+
+    let mut resource = Resource::new(
+        "cluster",
+        ResourceSpec {
+            agent: Agent {
+                name: "eks-provider".to_string(),
+                image: "eks-resource-agent:v1".to_string(),
+                pod_labels: Some(BTreeMap::from([(
+                    "network-policy".to_string(),
+                    "open".to_string(),
+                )])),
+                ..Agent::default()
+            },
+            ..ResourceSpec::default()
+        },
+    );
+    resource.metadata.namespace = Some(NAMESPACE.to_string());
+    resource.metadata.finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+    resource.status = Some(ResourceStatus::default());
+    let client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(resource)]);
+    let config = ControllerConfig {
+        protected_labels: BTreeMap::from([("network-policy".to_string(), "agents".to_string())]),
+        ..ControllerConfig::default()
+    };
+    let context = new_context(client.clone(), &config);
+    let resource_client = testsys_model::clients::ResourceClient::new_from_k8s_client(client);
+
+    let reconciled = async {
+        let r = ResourceInterface::new(resource_client.get("cluster").await?, context)?;
+        let action = action(&r).await?;
+        if let Action::Creation(creation_action) = &action {
+            do_creation_action(r, creation_action.clone()).await?;
+        }
+        Ok::<_, anyhow::Error>(action)
+    };
+    assert!(matches!(
+        reconciled.await,
+        Ok(Action::Creation(CreationAction::Error(
+            ErrorState::InvalidSpec(_)
+        )))
+    ));
+    assert!(matches!(
+        resource_client.get("cluster").await,
+        Ok(invalid) if matches!(
+            invalid.error(ResourceAction::Create),
+            Some(error) if error.error.contains("protected label 'network-policy'")
+        )
+    ));
+}
+
+#[tokio::test]
+async fn resources_are_claimed_and_left_to_their_instance() {
+    use std::collections::BTreeMap;
+    use testsys_model::constants::LABEL_CONTROLLER_INSTANCE;
+    use testsys_model::ResourceSpec;
+
+    let resource = |name: &str, owner: Option<&str>| {
+        let mut resource = Resource::new(name, ResourceSpec::default());
+        resource.metadata.namespace = Some(NAMESPACE.to_string());
+        resource.metadata.labels = owner.map(|owner| {
+            BTreeMap::from([(LABEL_CONTROLLER_INSTANCE.to_string(), owner.to_string())])
+        });
+        resource
+    };
+    let client = crate::fake_api::fake_k8s_store(vec![
+        serde_json::json!(resource("unclaimed", None)),
+        serde_json::json!(resource("blue", Some("blue"))),
+    ]);
+    let context = new_context(
+        client.clone(),
+        &ControllerConfig {
+            instance_id: Some("green".to_string()),
+            ..ControllerConfig::default()
+        },
+    );
+    let resource_client = testsys_model::clients::ResourceClient::new_from_k8s_client(client);
+
+    let reconciled = |name: &'static str| {
+        let (resource_client, context) = (&resource_client, context.clone());
+        async move {
+            let resource = resource_client.get(name).await?;
+            reconcile(Arc::new(resource), context).await?;
+            Ok::<_, anyhow::Error>(resource_client.get(name).await?)
+        }
+    };
+    assert!(matches!(
+        reconciled("unclaimed").await,
+        Ok(claimed) if claimed.controller_instance() == Some("green") && claimed.status.is_none()
+    ));
+    // The resource of another instance is not even given a finalizer.
+    assert!(matches!(
+        reconciled("blue").await,
+        Ok(ignored) if ignored.controller_instance() == Some("blue")
+            && ignored.metadata.finalizers.is_none()
+    ));
+}
+
+#[tokio::test]
+async fn stale_references_are_removed() {
+    use testsys_model::{ResourceSpec, ResourceStatus, Test, TestSpec};
+
+    let mut resource = Resource::new(
+        "bastion",
+        ResourceSpec {
+            shared: Some(true),
+            ..ResourceSpec::default()
+        },
+    );
+    resource.metadata.namespace = Some(NAMESPACE.to_string());
+    resource.status = Some(ResourceStatus {
+        created_resource: serde_json::json!({ "ip": "10.0.0.1" }).as_object().cloned(),
+        references: Some(vec!["gone".to_string(), "running".to_string()]),
+        ..ResourceStatus::default()
+    });
+    let mut test = Test::new(
+        "running",
+        TestSpec {
+            resources: vec!["bastion".to_string()],
+            ..TestSpec::default()
+        },
+    );
+    test.metadata.namespace = Some(NAMESPACE.to_string());
+    let client =
+        crate::fake_api::fake_k8s_store(vec![serde_json::json!(resource), serde_json::json!(test)]);
+    let context = new_context(client.clone(), &ControllerConfig::default());
+    let resource_client = testsys_model::clients::ResourceClient::new_from_k8s_client(client);
+
+    let reconciled = async {
+        reconcile(Arc::new(resource_client.get("bastion").await?), context).await?;
+        Ok::<_, anyhow::Error>(resource_client.get("bastion").await?)
+    };
+    // The reference of the test that is gone is removed, the running test keeps its reference.
+    assert!(matches!(
+        reconciled.await,
+        Ok(bastion) if bastion.references() == ["running".to_string()]
+    ));
+}
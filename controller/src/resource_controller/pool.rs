@@ -0,0 +1,408 @@
+/*!
+
+Pooled resources are borrowed from, and returned to, a pool of idle resources that were created
+with the same agent. An idle resource is a pool entry, a `Resource` labeled with the pool's key that
+holds the status of the resource that was returned. Borrowing an entry claims it for the borrower,
+copies its status to the borrower and deletes the entry without destroying anything. Returning a
+resource creates an entry with its status, unless the pool is full.
+
+!*/
+
+use crate::error::Result;
+use crate::resource_controller::context::ResourceInterface;
+use anyhow::Context;
+use kube::ResourceExt;
+use log::info;
+use std::collections::BTreeMap;
+use testsys_model::clients::{AllowNotFound, CrdClient};
+use testsys_model::constants::{LABEL_POOL_BORROWER, LABEL_POOL_KEY, NAMESPACE};
+use testsys_model::{CrdExt, DestructionPolicy, Resource, ResourceSpec, TaskState};
+
+/// The name of the pool entry that `resource` is returned to.
+pub(super) fn pool_entry_name(resource: &Resource, pool_key: &str) -> String {
+    let uid = resource.uid().unwrap_or_default();
+    format!("pool-{}-{}", pool_key, uid.get(..8).unwrap_or(&uid))
+}
+
+/// Whether `resource` is an idle entry in the pool with `pool_key`.
+fn is_idle_entry(resource: &Resource, pool_key: &str) -> bool {
+    resource.labels().get(LABEL_POOL_KEY).map(String::as_str) == Some(pool_key)
+        && resource.creation_task_state() == TaskState::Completed
+        && !resource.is_delete_requested()
+}
+
+/// Whether `resource` is a pool entry that was claimed by the resource with `borrower_uid`.
+fn is_claimed_by(resource: &Resource, borrower_uid: &str) -> bool {
+    resource
+        .labels()
+        .get(LABEL_POOL_BORROWER)
+        .map(String::as_str)
+        == Some(borrower_uid)
+}
+
+async fn all_resources(r: &ResourceInterface) -> Result<Vec<Resource>> {
+    r.resource_client()
+        .get_all()
+        .await
+        .context("Unable to list pooled resources")
+}
+
+/// An idle pool entry that the resource can borrow instead of being created, if there is one. An
+/// entry that the resource already claimed comes first, so that an interrupted borrow is finished.
+pub(super) async fn available_entry(r: &ResourceInterface) -> Result<Option<String>> {
+    let pool_key = match r.resource().pool_key() {
+        Some(pool_key) if !r.resource().is_pool_entry() => pool_key,
+        _ => return Ok(None),
+    };
+    let uid = r.resource().uid().unwrap_or_default();
+    let resources = all_resources(r).await?;
+    Ok(resources
+        .iter()
+        .find(|resource| is_claimed_by(resource, &uid))
+        .or_else(|| {
+            resources
+                .iter()
+                .find(|resource| is_idle_entry(resource, &pool_key))
+        })
+        .map(ResourceExt::name_any))
+}
+
+/// Whether the resource has been returned to its pool.
+pub(super) async fn is_returned(r: &ResourceInterface) -> Result<bool> {
+    let pool_key = match r.resource().pool_key() {
+        Some(pool_key) => pool_key,
+        None => return Ok(false),
+    };
+    Ok(get_entry(r, &pool_entry_name(r.resource(), &pool_key))
+        .await?
+        .map(|entry| entry.creation_task_state() == TaskState::Completed)
+        .unwrap_or(false))
+}
+
+async fn get_entry(r: &ResourceInterface, entry_name: &str) -> Result<Option<Resource>> {
+    r.resource_client()
+        .get(entry_name)
+        .await
+        .allow_not_found(|_| ())
+        .with_context(|| format!("Unable to get pool entry '{}'", entry_name))
+}
+
+/// Whether the resource's pool has room for it to be returned.
+pub(super) async fn has_capacity(r: &ResourceInterface) -> Result<bool> {
+    let (pool_key, pool) = match (r.resource().pool_key(), &r.resource().spec.pool) {
+        (Some(pool_key), Some(pool)) => (pool_key, pool),
+        _ => return Ok(false),
+    };
+    let capacity = pool.capacity.unwrap_or(1) as usize;
+    let entries = all_resources(r)
+        .await?
+        .into_iter()
+        .filter(|resource| is_idle_entry(resource, &pool_key))
+        .count();
+    Ok(entries < capacity)
+}
+
+/// Take over the created resource of the pool entry `entry_name`. The entry is claimed with a
+/// single conditional patch that records the borrower, so if the borrow is interrupted the resource
+/// borrows the same entry again instead of leaving it unowned. The entry is deleted once its
+/// created resource has been copied.
+pub(super) async fn borrow(r: &ResourceInterface, entry_name: &str) -> Result<()> {
+    let pool_key = r.resource().pool_key().unwrap_or_default();
+    let uid = r.resource().uid().unwrap_or_default();
+    info!(
+        "Resource '{}' is borrowing pool entry '{}'",
+        r.name(),
+        entry_name
+    );
+    let entry = match get_entry(r, entry_name).await? {
+        Some(entry) if is_claimed_by(&entry, &uid) => entry,
+        _ => r
+            .resource_client()
+            .claim_pool_entry(entry_name, &pool_key, &uid)
+            .await
+            .with_context(|| format!("Unable to claim pool entry '{}'", entry_name))?,
+    };
+    r.resource_client()
+        .send_pooled_status(r.resource(), entry.pooled_status())
+        .await
+        .with_context(|| format!("Unable to copy pool entry '{}'", entry_name))?;
+    r.resource_client()
+        .delete(entry_name)
+        .await
+        .with_context(|| format!("Unable to delete pool entry '{}'", entry_name))?;
+    Ok(())
+}
+
+/// Create a pool entry with the resource's created resource so that another resource can borrow
+/// it. The entry is destroyed when it is deleted. An entry that was created before the return was
+/// interrupted is reused.
+pub(super) async fn return_to_pool(r: &ResourceInterface) -> Result<()> {
+    let pool_key = r.resource().pool_key().unwrap_or_default();
+    let entry_name = pool_entry_name(r.resource(), &pool_key);
+    info!(
+        "Returning resource '{}' to its pool as '{}'",
+        r.name(),
+        entry_name
+    );
+    let entry = match get_entry(r, &entry_name).await? {
+        Some(entry) => entry,
+        None => {
+            let mut entry = Resource::new(
+                &entry_name,
+                ResourceSpec {
+                    agent: r.resource().spec.agent.clone(),
+                    destruction_policy: DestructionPolicy::OnDeletion,
+                    pool: r.resource().spec.pool.clone(),
+                    ..ResourceSpec::default()
+                },
+            );
+            entry.metadata.namespace = Some(NAMESPACE.to_string());
+            entry.metadata.labels = Some(BTreeMap::from([(LABEL_POOL_KEY.to_string(), pool_key)]));
+            r.resource_client()
+                .create(entry)
+                .await
+                .with_context(|| format!("Unable to create pool entry '{}'", entry_name))?
+        }
+    };
+    r.resource_client()
+        .send_pooled_status(&entry, r.resource().pooled_status())
+        .await
+        .with_context(|| format!("Unable to copy '{}' to its pool entry", r.name()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn pooled_resource(name: &str, uid: &str, created: Option<&str>, finalizers: &[&str]) -> Resource {
+    let mut resource = Resource::new(
+        name,
+        ResourceSpec {
+            agent: testsys_model::Agent {
+                name: "eks-provider".to_string(),
+                image: "eks-resource-agent:v1".to_string(),
+                ..Default::default()
+            },
+            pool: Some(testsys_model::ResourcePool { capacity: Some(1) }),
+            ..ResourceSpec::default()
+        },
+    );
+    resource.metadata.namespace = Some(NAMESPACE.to_string());
+    resource.metadata.uid = Some(uid.to_string());
+    resource.metadata.finalizers = Some(finalizers.iter().map(|f| f.to_string()).collect());
+    let mut status = testsys_model::ResourceStatus::default();
+    if let Some(endpoint) = created {
+        status.creation.task_state = TaskState::Completed;
+        status.created_resource = serde_json::json!({ "endpoint": endpoint })
+            .as_object()
+            .cloned();
+    }
+    resource.status = Some(status);
+    resource
+}
+
+#[cfg(test)]
+fn pool_entry(resource: &Resource) -> Resource {
+    let pool_key = resource.pool_key().unwrap_or_default();
+    let mut entry = resource.clone();
+    entry.metadata.name = Some(pool_entry_name(resource, &pool_key));
+    entry.metadata.labels = Some(BTreeMap::from([(LABEL_POOL_KEY.to_string(), pool_key)]));
+    entry
+}
+
+#[cfg(test)]
+fn interface(client: kube::Client, resource: Resource) -> Result<ResourceInterface> {
+    let context = crate::resource_controller::context::new_context(
+        client,
+        &crate::config::ControllerConfig::default(),
+    );
+    ResourceInterface::new(resource, context)
+}
+
+#[cfg(test)]
+async fn pool_action(
+    client: kube::Client,
+    resource: Resource,
+) -> Result<crate::resource_controller::action::Action> {
+    crate::resource_controller::action::action(&interface(client, resource)?).await
+}
+
+#[tokio::test]
+async fn borrow_from_pool_or_create() {
+    use crate::resource_controller::action::{Action, CreationAction};
+    use testsys_model::constants::{
+        FINALIZER_CLEANUP_REQUIRED, FINALIZER_CREATION_JOB, FINALIZER_MAIN,
+    };
+
+    let finalizers = [
+        FINALIZER_MAIN,
+        FINALIZER_CREATION_JOB,
+        FINALIZER_CLEANUP_REQUIRED,
+    ];
+    let returned = pooled_resource("old-cluster", "0123abcd-0000", Some("https://eks"), &[]);
+    let entry = pool_entry(&returned);
+    let entry_name = entry.name_any();
+    let borrower = pooled_resource("cluster", "4567efab-0000", None, &finalizers);
+    let test = testsys_model::Test::new(
+        "test",
+        testsys_model::TestSpec {
+            resources: vec!["cluster".to_string(), "other-cluster".to_string()],
+            ..Default::default()
+        },
+    );
+    let client = crate::fake_api::fake_k8s_store(vec![
+        serde_json::json!(entry),
+        serde_json::json!(borrower),
+        serde_json::json!(test),
+    ]);
+    let resource_client =
+        testsys_model::clients::ResourceClient::new_from_k8s_client(client.clone());
+
+    // The idle entry in the pool is borrowed instead of creating a new resource.
+    assert!(matches!(
+        pool_action(client.clone(), borrower.clone()).await,
+        Ok(Action::Creation(CreationAction::BorrowFromPool(name))) if name == entry_name
+    ));
+    let borrowed = match interface(client.clone(), borrower) {
+        Ok(r) => borrow(&r, &entry_name).await,
+        Err(e) => Err(e),
+    };
+    assert!(borrowed.is_ok(), "{:?}", borrowed);
+    assert!(matches!(
+        resource_client.get("cluster").await,
+        Ok(borrowed) if borrowed.creation_task_state() == TaskState::Completed
+            && borrowed.created_resource() == returned.created_resource()
+    ));
+    assert!(matches!(
+        resource_client
+            .get(&entry_name)
+            .await
+            .allow_not_found(|_| ()),
+        Ok(None)
+    ));
+
+    // The pool is empty now, so the next resource is created.
+    let other = pooled_resource("other-cluster", "89abcdef-0000", None, &finalizers);
+    assert!(matches!(
+        pool_action(client, other).await,
+        Ok(Action::Creation(CreationAction::StartJob))
+    ));
+}
+
+#[tokio::test]
+async fn return_to_pool_or_destroy() {
+    use crate::resource_controller::action::{Action, DestructionAction};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use testsys_model::constants::{FINALIZER_MAIN, FINALIZER_RESOURCE};
+
+    let deleted = |name: &str, uid: &str| {
+        let mut resource = pooled_resource(
+            name,
+            uid,
+            Some("https://eks"),
+            &[FINALIZER_MAIN, FINALIZER_RESOURCE],
+        );
+        resource.metadata.deletion_timestamp = Some(Time(k8s_openapi::chrono::Utc::now()));
+        resource
+    };
+    let first = deleted("cluster", "0123abcd-0000");
+    let second = deleted("other-cluster", "4567efab-0000");
+    let client =
+        crate::fake_api::fake_k8s_store(vec![serde_json::json!(first), serde_json::json!(second)]);
+    let resource_client =
+        testsys_model::clients::ResourceClient::new_from_k8s_client(client.clone());
+
+    // The pool has room for the first resource, it is returned instead of destroyed.
+    assert!(matches!(
+        pool_action(client.clone(), first.clone()).await,
+        Ok(Action::Destruction(DestructionAction::ReturnToPool))
+    ));
+    let returned = match interface(client.clone(), first.clone()) {
+        Ok(r) => return_to_pool(&r).await,
+        Err(e) => Err(e),
+    };
+    assert!(returned.is_ok(), "{:?}", returned);
+    let entry_name = pool_entry_name(&first, &first.pool_key().unwrap_or_default());
+    assert!(matches!(
+        resource_client.get(&entry_name).await,
+        Ok(entry) if entry.is_pool_entry()
+            && entry.created_resource() == first.created_resource()
+            && entry.spec.destruction_policy == DestructionPolicy::OnDeletion
+    ));
+    // Once it is in the pool, its deletion proceeds without destroying it.
+    assert!(matches!(
+        pool_action(client.clone(), first).await,
+        Ok(Action::Destruction(
+            DestructionAction::RemoveResourceFinalizer
+        ))
+    ));
+
+    // The pool is full, so the second resource is destroyed.
+    assert!(matches!(
+        pool_action(client, second).await,
+        Ok(Action::Destruction(DestructionAction::StartDestructionJob))
+    ));
+}
+
+#[tokio::test]
+async fn interrupted_borrow_is_finished() {
+    use crate::resource_controller::action::{Action, CreationAction};
+    use testsys_model::constants::{
+        FINALIZER_CLEANUP_REQUIRED, FINALIZER_CREATION_JOB, FINALIZER_MAIN,
+    };
+
+    let returned = pooled_resource("old-cluster", "0123abcd-0000", Some("https://eks"), &[]);
+    let mut entry = pool_entry(&returned);
+    let entry_name = entry.name_any();
+    let borrower = pooled_resource(
+        "cluster",
+        "4567efab-0000",
+        None,
+        &[
+            FINALIZER_MAIN,
+            FINALIZER_CREATION_JOB,
+            FINALIZER_CLEANUP_REQUIRED,
+        ],
+    );
+    // The borrower claimed the entry, but did not copy it.
+    entry.metadata.labels = Some(BTreeMap::from([(
+        LABEL_POOL_BORROWER.to_string(),
+        "4567efab-0000".to_string(),
+    )]));
+    entry.spec.destruction_policy = DestructionPolicy::Never;
+    let test = testsys_model::Test::new(
+        "test",
+        testsys_model::TestSpec {
+            resources: vec!["cluster".to_string()],
+            ..Default::default()
+        },
+    );
+    let client = crate::fake_api::fake_k8s_store(vec![
+        serde_json::json!(entry),
+        serde_json::json!(borrower),
+        serde_json::json!(test),
+    ]);
+    let resource_client =
+        testsys_model::clients::ResourceClient::new_from_k8s_client(client.clone());
+
+    let borrowed = async {
+        let action = pool_action(client.clone(), borrower.clone()).await?;
+        assert!(matches!(
+            &action,
+            Action::Creation(CreationAction::BorrowFromPool(name)) if *name == entry_name
+        ));
+        borrow(&interface(client.clone(), borrower)?, &entry_name).await?;
+        Ok::<_, anyhow::Error>(resource_client.get("cluster").await?)
+    }
+    .await;
+    assert!(matches!(
+        borrowed,
+        Ok(borrowed) if borrowed.creation_task_state() == TaskState::Completed
+            && borrowed.created_resource() == returned.created_resource()
+    ));
+    assert!(matches!(
+        resource_client
+            .get(&entry_name)
+            .await
+            .allow_not_found(|_| ()),
+        Ok(None)
+    ));
+}
@@ -0,0 +1,418 @@
+use crate::api_auth::authenticate;
+use crate::config::ControllerConfig;
+use crate::error::Result;
+use anyhow::Context;
+use futures::{Stream, StreamExt};
+use k8s_openapi::api::authentication::v1::UserInfo;
+use k8s_openapi::api::core::v1::Pod;
+use kube::{Api, ResourceExt};
+use log::{info, warn};
+use std::net::SocketAddr;
+use std::time::Duration;
+use testsys_model::clients::{CrdClient, HttpStatusCode, TestClient};
+use testsys_model::constants::NAMESPACE;
+use testsys_model::results_stream::proto::results_server::{Results, ResultsServer};
+use testsys_model::results_stream::proto::{self, StreamSummary};
+use testsys_model::results_stream::{MAX_MESSAGE_SIZE, TEST_NAME_METADATA};
+use testsys_model::{Test, TestResults};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+/// How often results streamed by an agent are written to the test's status. Results received in
+/// between are coalesced so that only the latest one is written.
+const RESULT_BATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The extra information that k8s adds to the user of a pod's ServiceAccount token about the pod.
+const POD_NAME_EXTRA: &str = "authentication.kubernetes.io/pod-name";
+const POD_UID_EXTRA: &str = "authentication.kubernetes.io/pod-uid";
+
+/// The label that k8s sets on the pods of a `Job` to the name of the `Job`.
+const JOB_NAME_LABEL: &str = "job-name";
+
+/// The address the results endpoint should listen on. Returns `None` if it is not enabled.
+pub(crate) fn results_address(config: &ControllerConfig) -> Option<SocketAddr> {
+    let address = config.results_address.as_ref()?;
+    match address.trim().parse() {
+        Ok(address) => Some(address),
+        Err(e) => {
+            warn!(
+                "Invalid results address '{}', the results endpoint will not be started: {}",
+                address, e
+            );
+            None
+        }
+    }
+}
+
+/// Serve the gRPC endpoint that test agents stream their results to on `address`, see
+/// `model/proto/results.proto`. Each stream must be opened by the test agent pod of the test it
+/// names, which proves who it is with its ServiceAccount token.
+pub(crate) async fn run_results_server(
+    k8s_client: kube::Client,
+    address: SocketAddr,
+) -> Result<()> {
+    info!("Serving the results endpoint on '{}'", address);
+    Server::builder()
+        .add_service(results_service(k8s_client, RESULT_BATCH_INTERVAL))
+        .serve(address)
+        .await
+        .context("The results endpoint stopped")
+}
+
+fn results_service(k8s_client: kube::Client, interval: Duration) -> ResultsServer<ResultsService> {
+    ResultsServer::new(ResultsService {
+        test_client: TestClient::new_from_k8s_client(k8s_client.clone()),
+        k8s_client,
+        interval,
+    })
+    .max_decoding_message_size(MAX_MESSAGE_SIZE)
+}
+
+struct ResultsService {
+    test_client: TestClient,
+    /// Reviews the tokens of the agents and gets their pods.
+    k8s_client: kube::Client,
+    interval: Duration,
+}
+
+#[tonic::async_trait]
+impl Results for ResultsService {
+    async fn stream(
+        &self,
+        request: Request<Streaming<proto::TestResults>>,
+    ) -> std::result::Result<Response<StreamSummary>, Status> {
+        let metadata = request.metadata();
+        let name = metadata
+            .get(TEST_NAME_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| {
+                Status::invalid_argument(format!("'{}' metadata is required", TEST_NAME_METADATA))
+            })?
+            .to_string();
+        let token = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+            .ok_or_else(|| Status::unauthenticated("A bearer token is required"))?
+            .to_string();
+        authenticate_agent(&self.test_client, self.k8s_client.clone(), &token, &name).await?;
+        coalesce(
+            &self.test_client,
+            &name,
+            request.into_inner(),
+            self.interval,
+        )
+        .await
+        .map(Response::new)
+    }
+}
+
+/// Check that `token` belongs to the test agent pod of the test `name`.
+async fn authenticate_agent(
+    test_client: &TestClient,
+    k8s_client: kube::Client,
+    token: &str,
+    name: &str,
+) -> std::result::Result<(), Status> {
+    let user = authenticate(k8s_client.clone(), token)
+        .await
+        .map_err(|e| Status::internal(format!("{:?}", e)))?
+        .ok_or_else(|| Status::unauthenticated("The bearer token is not valid"))?;
+    let test = test_client
+        .get(name)
+        .await
+        .map_err(|e| match e.status_code() {
+            Some(code) if code.as_u16() == 404 => Status::not_found(format!("No test '{}'", name)),
+            _ => Status::internal(e.to_string()),
+        })?;
+    let pod = match user_extra(&user, POD_NAME_EXTRA) {
+        Some(pod_name) => Api::<Pod>::namespaced(k8s_client, NAMESPACE)
+            .get_opt(pod_name)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?,
+        None => None,
+    };
+    match pod {
+        Some(pod) if is_test_agent_pod(&pod, &user, &test) => Ok(()),
+        _ => Err(Status::permission_denied(format!(
+            "Only the test agent of test '{}' may stream its results",
+            name
+        ))),
+    }
+}
+
+/// Whether `pod` is the one whose ServiceAccount token authenticated as `user`, and runs the test
+/// agent of `test`.
+fn is_test_agent_pod(pod: &Pod, user: &UserInfo, test: &Test) -> bool {
+    pod.metadata.uid.as_deref().is_some()
+        && pod.metadata.uid.as_deref() == user_extra(user, POD_UID_EXTRA)
+        && pod.labels().get(JOB_NAME_LABEL) == Some(&test.job_name())
+}
+
+fn user_extra<'a>(user: &'a UserInfo, key: &str) -> Option<&'a str> {
+    user.extra
+        .as_ref()
+        .and_then(|extra| extra.get(key))
+        .and_then(|values| values.first())
+        .map(String::as_str)
+}
+
+/// Write the `results` an agent streams to the `currentTest` status of the test `name`. Only the
+/// latest result is written, at most once per `interval`, and once more when the stream ends.
+/// The agent ends the stream before it sends its final results the usual way, so that they are
+/// not overwritten.
+async fn coalesce<S>(
+    test_client: &TestClient,
+    name: &str,
+    mut results: S,
+    interval: Duration,
+) -> std::result::Result<StreamSummary, Status>
+where
+    S: Stream<Item = std::result::Result<proto::TestResults, Status>> + Unpin,
+{
+    let mut summary = StreamSummary::default();
+    let mut pending: Option<TestResults> = None;
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            message = results.next() => match message {
+                Some(Ok(message)) => {
+                    pending = Some(message.into());
+                    summary.results += 1;
+                }
+                Some(Err(status)) => return Err(status),
+                None => break,
+            },
+            _ = ticks.tick() => flush(test_client, name, &mut pending, &mut summary).await?,
+        }
+    }
+    flush(test_client, name, &mut pending, &mut summary).await?;
+    Ok(summary)
+}
+
+/// Write the `pending` results, if there are any, to the test's status.
+async fn flush(
+    test_client: &TestClient,
+    name: &str,
+    pending: &mut Option<TestResults>,
+    summary: &mut StreamSummary,
+) -> std::result::Result<(), Status> {
+    if let Some(results) = pending.take() {
+        test_client
+            .send_test_update(name, results)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        summary.status_updates += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn in_progress(num_passed: u64) -> proto::TestResults {
+    TestResults {
+        outcome: testsys_model::Outcome::InProgress,
+        num_passed,
+        ..TestResults::default()
+    }
+    .into()
+}
+
+#[tokio::test]
+async fn streamed_results_are_coalesced() {
+    use std::sync::atomic::Ordering;
+
+    let (k8s_client, writes) = crate::fake_api::fake_k8s_client_counting_writes(vec![(
+        "/tests/my-test/status",
+        serde_json::json!(Test::new("my-test", Default::default())),
+    )]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client);
+    let results = futures::stream::iter((1..=3).map(in_progress).map(Ok));
+
+    let summary = coalesce(&test_client, "my-test", results, RESULT_BATCH_INTERVAL)
+        .await
+        .map_err(|status| status.code());
+    assert_eq!(
+        summary,
+        Ok(StreamSummary {
+            results: 3,
+            status_updates: 1
+        })
+    );
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn pending_results_are_flushed_while_the_stream_is_idle() {
+    use std::sync::atomic::Ordering;
+
+    let (k8s_client, writes) = crate::fake_api::fake_k8s_client_counting_writes(vec![(
+        "/tests/my-test/status",
+        serde_json::json!(Test::new("my-test", Default::default())),
+    )]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client);
+    // The agent goes quiet for longer than the interval after its first result.
+    let results = futures::stream::iter([Ok(in_progress(1))]).chain(futures::stream::once(async {
+        tokio::time::sleep(RESULT_BATCH_INTERVAL * 3).await;
+        Ok(in_progress(2))
+    }));
+
+    let summary = coalesce(
+        &test_client,
+        "my-test",
+        Box::pin(results),
+        RESULT_BATCH_INTERVAL,
+    )
+    .await
+    .map_err(|status| status.code());
+    assert_eq!(
+        summary,
+        Ok(StreamSummary {
+            results: 2,
+            status_updates: 2
+        })
+    );
+    assert_eq!(writes.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(test)]
+fn agent_pod(uid: &str, job_name: &str) -> serde_json::Value {
+    serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": "agent-pod",
+            "namespace": NAMESPACE,
+            "uid": uid,
+            "labels": { JOB_NAME_LABEL: job_name }
+        }
+    })
+}
+
+/// The token review and the test that authenticate the agent pod `agent-pod` of `test`.
+#[cfg(test)]
+fn agent_auth_objects(test: &Test) -> Vec<(&'static str, serde_json::Value)> {
+    vec![
+        (
+            "/tokenreviews",
+            serde_json::json!({
+                "apiVersion": "authentication.k8s.io/v1",
+                "kind": "TokenReview",
+                "metadata": {},
+                "spec": {},
+                "status": {
+                    "authenticated": true,
+                    "user": {
+                        "username": "system:serviceaccount:testsys:testsys-test-agent",
+                        "extra": {
+                            POD_NAME_EXTRA: ["agent-pod"],
+                            POD_UID_EXTRA: ["pod-uid"]
+                        }
+                    }
+                }
+            }),
+        ),
+        ("/tests/my-test", serde_json::json!(test)),
+    ]
+}
+
+#[cfg(test)]
+fn agent_auth_client(test: &Test, pod: serde_json::Value) -> kube::Client {
+    let mut objects = agent_auth_objects(test);
+    objects.push(("/pods/agent-pod", pod));
+    crate::fake_api::fake_k8s_client(objects)
+}
+
+#[tokio::test]
+async fn only_the_test_agent_may_stream() {
+    let mut test = Test::new("my-test", Default::default());
+    test.metadata.uid = Some("test-uid".to_string());
+    let authenticated = |pod| async {
+        let k8s_client = agent_auth_client(&test, pod);
+        let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+        authenticate_agent(&test_client, k8s_client, "token", "my-test")
+            .await
+            .map_err(|status| status.code())
+    };
+
+    assert_eq!(
+        authenticated(agent_pod("pod-uid", &test.job_name())).await,
+        Ok(())
+    );
+    // The pod of another test.
+    assert_eq!(
+        authenticated(agent_pod("pod-uid", "other-job")).await,
+        Err(tonic::Code::PermissionDenied)
+    );
+    // A pod that was recreated with the same name.
+    assert_eq!(
+        authenticated(agent_pod("other-uid", &test.job_name())).await,
+        Err(tonic::Code::PermissionDenied)
+    );
+    // The results of a test that does not exist.
+    let k8s_client = agent_auth_client(&test, agent_pod("pod-uid", &test.job_name()));
+    let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+    assert_eq!(
+        authenticate_agent(&test_client, k8s_client, "token", "no-test")
+            .await
+            .map_err(|status| status.code()),
+        Err(tonic::Code::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn results_are_streamed_over_grpc() {
+    use testsys_model::results_stream::proto::results_client::ResultsClient;
+    use tonic::metadata::MetadataValue;
+
+    let mut test = Test::new("my-test", Default::default());
+    test.metadata.uid = Some("test-uid".to_string());
+    let mut objects = vec![
+        ("/tests/my-test/status", serde_json::json!(test)),
+        ("/pods/agent-pod", agent_pod("pod-uid", &test.job_name())),
+    ];
+    objects.extend(agent_auth_objects(&test));
+    let k8s_client = crate::fake_api::fake_k8s_client(objects);
+    let call = |token: Option<&'static str>| {
+        let mut request = tonic::Request::new(futures::stream::iter((1..=3).map(in_progress)));
+        request
+            .metadata_mut()
+            .insert(TEST_NAME_METADATA, MetadataValue::from_static("my-test"));
+        if let Some(token) = token {
+            request
+                .metadata_mut()
+                .insert("authorization", MetadataValue::from_static(token));
+        }
+        request
+    };
+    let summaries = async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(results_service(k8s_client, RESULT_BATCH_INTERVAL))
+                .serve_with_incoming(incoming),
+        );
+        let mut client = ResultsClient::connect(format!("http://{}", address)).await?;
+        let unauthenticated = client.stream(call(None)).await.map_err(|e| e.code());
+        let authenticated = client.stream(call(Some("Bearer token"))).await?;
+        Ok::<_, Box<dyn std::error::Error>>((unauthenticated.err(), authenticated.into_inner()))
+    }
+    .await;
+    assert!(matches!(
+        summaries,
+        Ok((
+            Some(tonic::Code::Unauthenticated),
+            StreamSummary {
+                results: 3,
+                status_updates: 1
+            }
+        ))
+    ));
+}
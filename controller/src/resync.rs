@@ -0,0 +1,103 @@
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use kube_runtime::{controller, watcher};
+use log::warn;
+
+/// The status code of a watch whose resource version is too old. The watcher lists its objects
+/// again and restarts the watch from the new resource version.
+const EXPIRED: u16 = 410;
+
+/// Requests a reconciliation of all of a controller's objects once its watch restarts after its
+/// resource version expired, so that no changes that were missed in between are left unreconciled.
+#[derive(Clone)]
+pub(crate) struct Resync {
+    sender: UnboundedSender<()>,
+}
+
+/// A [`Resync`] and the stream to pass to the controller's `reconcile_all_on`.
+pub(crate) fn resync() -> (Resync, UnboundedReceiver<()>) {
+    let (sender, receiver) = unbounded();
+    (Resync { sender }, receiver)
+}
+
+impl Resync {
+    /// Request a resync if `error` is an expired watch. Returns whether it was, in which case it
+    /// does not need to be reported as a reconciliation error.
+    pub(crate) fn on_error<E>(&self, error: &controller::Error<E, watcher::Error>) -> bool
+    where
+        E: std::error::Error + 'static,
+    {
+        let expired = matches!(
+            error,
+            controller::Error::QueueError(queue_error) if is_expired_watch(queue_error)
+        );
+        if expired {
+            warn!("The watch expired, resyncing: {}", error);
+            let _ = self.sender.unbounded_send(());
+        }
+        expired
+    }
+}
+
+/// Whether the watcher failed because the resource version it watched from is too old.
+pub(crate) fn is_expired_watch(error: &watcher::Error) -> bool {
+    match error {
+        watcher::Error::WatchError(response) => response.code == EXPIRED,
+        watcher::Error::InitialListFailed(kube::Error::Api(response))
+        | watcher::Error::WatchStartFailed(kube::Error::Api(response))
+        | watcher::Error::WatchFailed(kube::Error::Api(response)) => response.code == EXPIRED,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+fn expired_watch_error() -> watcher::Error {
+    watcher::Error::WatchError(kube::error::ErrorResponse {
+        status: "Failure".to_string(),
+        message: "too old resource version: 1 (2)".to_string(),
+        reason: "Expired".to_string(),
+        code: EXPIRED,
+    })
+}
+
+#[tokio::test]
+async fn expired_watch_requests_resync() {
+    use futures::StreamExt;
+
+    let (resync, mut trigger) = resync();
+    let error: controller::Error<std::io::Error, watcher::Error> =
+        controller::Error::QueueError(expired_watch_error());
+    assert!(resync.on_error(&error));
+    assert_eq!(trigger.next().await, Some(()));
+
+    let error: controller::Error<std::io::Error, watcher::Error> =
+        controller::Error::QueueError(watcher::Error::TooManyObjects);
+    assert!(!resync.on_error(&error));
+    drop(resync);
+    assert_eq!(trigger.next().await, None);
+}
+
+#[tokio::test]
+async fn watch_restarts_after_expired_resource_version() {
+    use futures::StreamExt;
+    use std::sync::atomic::Ordering;
+    use testsys_model::Test;
+
+    let (k8s_client, lists) = crate::fake_api::fake_expiring_watch_client();
+    let api: kube::Api<Test> = kube::Api::all(k8s_client);
+    let events: Vec<_> = watcher::watcher(api, watcher::Config::default())
+        .take(3)
+        .collect()
+        .await;
+
+    // The watcher lists the tests, the watch expires, and the watcher lists them again.
+    assert!(matches!(
+        events.first(),
+        Some(Ok(watcher::Event::Restarted(_)))
+    ));
+    assert!(matches!(events.get(1), Some(Err(error)) if is_expired_watch(error)));
+    assert!(matches!(
+        events.get(2),
+        Some(Ok(watcher::Event::Restarted(_)))
+    ));
+    assert_eq!(lists.load(Ordering::SeqCst), 2);
+}
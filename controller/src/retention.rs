@@ -0,0 +1,192 @@
+use crate::clock::Clock;
+use crate::config::ControllerConfig;
+use crate::error::Result;
+use crate::instance::Instance;
+use crate::utils::parse_duration;
+use anyhow::Context;
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use testsys_model::clients::{CrdClient, TestClient};
+use testsys_model::{CrdExt, Test};
+
+/// How often the controller looks for tests that have outlived the retention window.
+const SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// How long finished tests are kept. Returns `None` if finished tests are never deleted.
+pub(crate) fn test_retention(config: &ControllerConfig) -> Option<Duration> {
+    let retention = config.test_retention.as_ref()?;
+    match parse_duration(retention.trim()).map(Duration::from_std) {
+        Ok(Ok(retention)) => Some(retention),
+        _ => {
+            warn!(
+                "Invalid test retention '{}', finished tests will not be deleted",
+                retention
+            );
+            None
+        }
+    }
+}
+
+/// Periodically delete tests that finished longer than `retention` ago. Deleting a test goes
+/// through the same finalizers as deleting it by hand. Only the tests that `instance` has claimed
+/// are deleted.
+pub(crate) async fn run_retention_sweep<C>(
+    k8s_client: kube::Client,
+    retention: Duration,
+    instance: Instance,
+    clock: C,
+) where
+    C: Clock,
+{
+    let test_client = TestClient::new_from_k8s_client(k8s_client);
+    info!("Deleting tests that finished more than {} ago", retention);
+    loop {
+        if let Err(e) = sweep(&test_client, &instance, retention, clock.now()).await {
+            warn!("Unable to delete old tests: {:?}", e);
+        }
+        tokio::time::sleep(SWEEP_INTERVAL).await;
+    }
+}
+
+/// Delete every test of the `instance`, and that matches its label selector, that finished more than `retention` before `now` and return
+/// their names. A test that cannot be deleted is skipped, it is tried again in the next sweep.
+async fn sweep(
+    test_client: &TestClient,
+    instance: &Instance,
+    retention: Duration,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>> {
+    let tests = test_client
+        .api()
+        .list(&instance.list_params())
+        .await
+        .context("Unable to list tests")?;
+    let mut deleted = Vec::new();
+    for test in tests
+        .iter()
+        .filter(|test| instance.owns(*test) && is_expired(test, retention, now))
+    {
+        let name = test.metadata.name.as_deref().unwrap_or_default();
+        info!("Deleting test '{}' because its retention has expired", name);
+        match test_client.delete(name).await {
+            Ok(_) => deleted.push(name.to_string()),
+            Err(e) => warn!("Unable to delete test '{}': {}", name, e),
+        }
+    }
+    Ok(deleted)
+}
+
+fn is_expired(test: &Test, retention: Duration, now: DateTime<Utc>) -> bool {
+    !test.is_delete_requested()
+        && test
+            .finished_at()
+            .map(|finished_at| now - finished_at > retention)
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+fn finished_test(name: &str, finished_at: DateTime<Utc>) -> serde_json::Value {
+    serde_json::json!({
+        "apiVersion": "testsys.system/v1",
+        "kind": "Test",
+        "metadata": { "name": name, "namespace": "testsys" },
+        "spec": {
+            "resources": [],
+            "agent": { "name": "agent", "image": "image", "keepRunning": false }
+        },
+        "status": {
+            "controller": { "finishedAt": finished_at.to_rfc3339() },
+            "agent": { "taskState": "completed", "results": [] }
+        }
+    })
+}
+
+#[tokio::test]
+async fn old_tests_are_deleted() {
+    let now = Utc::now();
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![
+        finished_test("old-test", now - Duration::days(2)),
+        finished_test("fresh-test", now - Duration::minutes(1)),
+    ]));
+
+    let deleted = sweep(&test_client, &Instance::default(), Duration::days(1), now).await;
+    assert!(matches!(&deleted, Ok(deleted) if deleted == &["old-test"]));
+    let remaining: Vec<String> = test_client
+        .get_all()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|test| test.metadata.name)
+        .collect();
+    assert_eq!(remaining, vec!["fresh-test".to_string()]);
+}
+
+#[test]
+fn unfinished_tests_are_kept() {
+    let test = Test::default();
+    assert!(!is_expired(&test, Duration::zero(), Utc::now()));
+}
+
+#[tokio::test]
+async fn failed_delete_does_not_stop_sweep() {
+    let now = Utc::now();
+    let old_test = finished_test("old-test", now - Duration::days(2));
+    // The first test is listed but cannot be deleted.
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_client(vec![
+        (
+            "tests",
+            serde_json::json!({
+                "apiVersion": "testsys.system/v1",
+                "kind": "TestList",
+                "metadata": {},
+                "items": [finished_test("gone-test", now - Duration::days(3)), old_test],
+            }),
+        ),
+        ("tests/old-test", old_test),
+    ]));
+
+    let deleted = sweep(&test_client, &Instance::default(), Duration::days(1), now).await;
+    assert!(matches!(&deleted, Ok(deleted) if deleted == &["old-test"]));
+}
+
+#[tokio::test]
+async fn tests_of_other_instances_are_kept() {
+    use testsys_model::constants::LABEL_CONTROLLER_INSTANCE;
+
+    let now = Utc::now();
+    let claimed_by = |name: &str, owner: &str| {
+        let mut test = finished_test(name, now - Duration::days(2));
+        test["metadata"]["labels"] = serde_json::json!({ LABEL_CONTROLLER_INSTANCE: owner });
+        test
+    };
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![
+        claimed_by("green-test", "green"),
+        claimed_by("blue-test", "blue"),
+        finished_test("unclaimed-test", now - Duration::days(2)),
+    ]));
+    let green = Instance::new(&crate::config::ControllerConfig {
+        instance_id: Some("green".to_string()),
+        ..Default::default()
+    });
+
+    let deleted = sweep(&test_client, &green, Duration::days(1), now).await;
+    assert!(matches!(&deleted, Ok(deleted) if deleted == &["green-test"]));
+}
+
+#[tokio::test]
+async fn tests_outside_the_label_selector_are_kept() {
+    let now = Utc::now();
+    let mut platform_test = finished_test("platform-test", now - Duration::days(2));
+    platform_test["metadata"]["labels"] = serde_json::json!({ "team": "platform" });
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![
+        platform_test,
+        finished_test("other-test", now - Duration::days(2)),
+    ]));
+    let platform = Instance::new(&crate::config::ControllerConfig {
+        label_selector: Some("team=platform".to_string()),
+        ..Default::default()
+    });
+
+    let deleted = sweep(&test_client, &platform, Duration::days(1), now).await;
+    assert!(matches!(&deleted, Ok(deleted) if deleted == &["platform-test"]));
+}
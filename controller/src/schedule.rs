@@ -0,0 +1,513 @@
+use crate::clock::Clock;
+use crate::error::Result;
+use crate::instance::Instance;
+use anyhow::{ensure, Context};
+use k8s_openapi::chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use kube::ResourceExt;
+use log::{debug, info, warn};
+use std::collections::BTreeMap;
+use testsys_model::clients::{CrdClient, TestClient};
+use testsys_model::constants::{LABEL_SCHEDULED_AT, LABEL_SCHEDULED_BY};
+use testsys_model::{CrdExt, Schedule, Test, TestSpec};
+
+/// How often the controller looks for scheduled tests that are due.
+const SCHEDULE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// The number of runs of a scheduled test that are kept if the schedule does not say.
+const DEFAULT_KEEP_RUNS: u32 = 5;
+
+/// How far back the controller looks for a missed tick, e.g. after it was not running.
+const MAX_LOOKBACK_DAYS: i64 = 366;
+
+/// The format of the `scheduled-at` label, which has to be a valid label value.
+const SCHEDULED_AT_FORMAT: &str = "%Y%m%dT%H%MZ";
+
+/// A parsed five field cron expression. Each field is a bit set of the values it matches.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Cron {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Whether the day of month and the day of week fields are both restricted, in which case a
+    /// day matches if either of them does.
+    either_day: bool,
+}
+
+impl Cron {
+    pub(crate) fn parse(expression: &str) -> Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        ensure!(
+            fields.len() == 5,
+            "Cron expression '{}' must have 5 fields, it has {}",
+            expression,
+            fields.len()
+        );
+        let field = |index: usize, min, max| {
+            parse_field(fields[index], min, max).with_context(|| {
+                format!(
+                    "Invalid field '{}' in cron expression '{}'",
+                    fields[index], expression
+                )
+            })
+        };
+        let mut days_of_week = field(4, 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+        Ok(Self {
+            minutes: field(0, 0, 59)?,
+            hours: field(1, 0, 23)?,
+            days_of_month: field(2, 1, 31)?,
+            months: field(3, 1, 12)?,
+            days_of_week,
+            either_day: fields[2] != "*" && fields[4] != "*",
+        })
+    }
+
+    /// Whether the schedule ticks at the minute of `time`.
+    #[cfg(test)]
+    fn matches(&self, time: DateTime<Utc>) -> bool {
+        self.matches_day(time) && bit(self.hours, time.hour()) && bit(self.minutes, time.minute())
+    }
+
+    /// Whether the schedule ticks at all on the day of `time`.
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        let day_of_month = bit(self.days_of_month, time.day());
+        let day_of_week = bit(self.days_of_week, time.weekday().num_days_from_sunday());
+        let day = if self.either_day {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        };
+        bit(self.months, time.month()) && day
+    }
+
+    /// The latest tick of the schedule that is after `after` and not after `now`, if there is one.
+    pub(crate) fn latest_tick(
+        &self,
+        after: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let now = now.with_second(0)?.with_nanosecond(0)?;
+        let earliest = after.max(now - Duration::days(MAX_LOOKBACK_DAYS));
+        // Go back a day at a time, the latest tick is at the latest matching hour and minute of the
+        // latest matching day.
+        let mut latest = now;
+        while latest > earliest {
+            if self.matches_day(latest) {
+                if let Some(tick) = self.latest_tick_of_day(latest) {
+                    return Some(tick).filter(|tick| *tick > earliest);
+                }
+            }
+            let midnight = Utc.from_utc_datetime(&latest.date_naive().and_hms_opt(0, 0, 0)?);
+            latest = midnight - Duration::minutes(1);
+        }
+        None
+    }
+
+    /// The latest tick on the day of `latest` that is not after it, if there is one.
+    fn latest_tick_of_day(&self, latest: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let (hour, minute) = (0..=latest.hour())
+            .rev()
+            .filter(|hour| bit(self.hours, *hour))
+            .find_map(|hour| {
+                let last_minute = if hour == latest.hour() {
+                    latest.minute()
+                } else {
+                    59
+                };
+                (0..=last_minute)
+                    .rev()
+                    .find(|minute| bit(self.minutes, *minute))
+                    .map(|minute| (hour, minute))
+            })?;
+        latest.with_hour(hour)?.with_minute(minute)
+    }
+}
+
+/// Whether the bit set of a cron field matches `value`.
+fn bit(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parse a cron field made of comma separated `*`, values and `a-b` ranges, each optionally
+/// followed by a `/step`, into the bit set of the values it matches.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        ensure!(step > 0, "The step must be positive");
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse()?, end.parse()?)
+        } else {
+            let value = range.parse()?;
+            (value, if step == 1 { value } else { max })
+        };
+        ensure!(
+            min <= start && start <= end && end <= max,
+            "Values must be between {} and {}",
+            min,
+            max
+        );
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+/// Periodically create runs of the scheduled tests that are due. Only the scheduled tests that
+/// `instance` has claimed and that match its label selector are run. Their runs are claimed by
+/// the instance too, and keep the labels it selects tests by.
+pub(crate) async fn run_scheduler<C>(k8s_client: kube::Client, instance: Instance, clock: C)
+where
+    C: Clock,
+{
+    let test_client = TestClient::new_from_k8s_client(k8s_client);
+    loop {
+        if let Err(e) = schedule_runs(&test_client, &instance, clock.now()).await {
+            warn!("Unable to run scheduled tests: {:?}", e);
+        }
+        tokio::time::sleep(SCHEDULE_INTERVAL).await;
+    }
+}
+
+/// Create a run of every scheduled test that has a tick that is not later than `now` and has not
+/// been run yet, then delete the runs that are no longer kept. Returns the names of the new runs.
+async fn schedule_runs(
+    test_client: &TestClient,
+    instance: &Instance,
+    now: DateTime<Utc>,
+) -> Result<Vec<String>> {
+    let tests: Vec<Test> = test_client
+        .api()
+        .list(&instance.list_params())
+        .await
+        .context("Unable to list tests")?
+        .into_iter()
+        .filter(|test| instance.owns(test))
+        .collect();
+    let mut created = Vec::new();
+    for scheduled in &tests {
+        // A schedule that fails does not keep the others from running.
+        match schedule_run(test_client, scheduled, &tests, now).await {
+            Ok(Some(run_name)) => created.push(run_name),
+            Ok(None) => {}
+            Err(e) => warn!(
+                "Unable to run scheduled test '{}': {:?}",
+                scheduled.name_any(),
+                e
+            ),
+        }
+    }
+    Ok(created)
+}
+
+/// Create a run of the `scheduled` test if it has a tick that is not later than `now` and has not
+/// been run yet, then delete its runs among `tests` that are no longer kept. Returns the name of
+/// the new run.
+async fn schedule_run(
+    test_client: &TestClient,
+    scheduled: &Test,
+    tests: &[Test],
+    now: DateTime<Utc>,
+) -> Result<Option<String>> {
+    let schedule = match &scheduled.spec.schedule {
+        Some(schedule) if !scheduled.is_delete_requested() && !scheduled.spec.suspend => schedule,
+        _ => return Ok(None),
+    };
+    let name = scheduled.name_any();
+    let cron = match Cron::parse(&schedule.cron) {
+        Ok(cron) => cron,
+        // The invalid schedule is recorded in the test's status when it is reconciled.
+        Err(e) => {
+            debug!("Test '{}' has an invalid schedule: {:?}", name, e);
+            return Ok(None);
+        }
+    };
+    let mut runs: BTreeMap<DateTime<Utc>, String> = tests
+        .iter()
+        .filter_map(|test| Some((scheduled_at(test, &name)?, test.name_any())))
+        .collect();
+    // Runs are only created for ticks after the scheduled test was created.
+    let last_run = runs
+        .keys()
+        .next_back()
+        .copied()
+        .or_else(|| scheduled.creation_timestamp().map(|time| time.0))
+        .unwrap_or(now);
+    let mut created = None;
+    if let Some(tick) = cron.latest_tick(last_run, now) {
+        let run = scheduled_run(scheduled, tick);
+        let run_name = run.name_any();
+        info!("Creating run '{}' of scheduled test '{}'", run_name, name);
+        test_client
+            .create(run)
+            .await
+            .with_context(|| format!("Unable to create run '{}'", run_name))?;
+        runs.insert(tick, run_name.clone());
+        created = Some(run_name);
+    }
+    delete_old_runs(test_client, schedule, runs).await?;
+    Ok(created)
+}
+
+/// A copy of the scheduled test, without its schedule, for the run at `tick`.
+fn scheduled_run(scheduled: &Test, tick: DateTime<Utc>) -> Test {
+    let name = scheduled.name_any();
+    let scheduled_at = tick.format(SCHEDULED_AT_FORMAT).to_string();
+    let mut run = Test::new(
+        &format!("{}-{}", name, scheduled_at.to_lowercase()),
+        TestSpec {
+            schedule: None,
+            ..scheduled.spec.clone()
+        },
+    );
+    let mut labels = scheduled.labels().clone();
+    labels.insert(LABEL_SCHEDULED_BY.to_string(), name);
+    labels.insert(LABEL_SCHEDULED_AT.to_string(), scheduled_at);
+    run.metadata.labels = Some(labels);
+    run
+}
+
+/// When `test` was scheduled if it is a run of the scheduled test `scheduled_name`.
+fn scheduled_at(test: &Test, scheduled_name: &str) -> Option<DateTime<Utc>> {
+    let labels = test.metadata.labels.as_ref()?;
+    if labels.get(LABEL_SCHEDULED_BY).map(String::as_str) != Some(scheduled_name) {
+        return None;
+    }
+    let time =
+        NaiveDateTime::parse_from_str(labels.get(LABEL_SCHEDULED_AT)?, SCHEDULED_AT_FORMAT).ok()?;
+    Some(Utc.from_utc_datetime(&time))
+}
+
+/// Delete the oldest `runs` beyond the number the schedule keeps.
+async fn delete_old_runs(
+    test_client: &TestClient,
+    schedule: &Schedule,
+    runs: BTreeMap<DateTime<Utc>, String>,
+) -> Result<()> {
+    let keep_runs = schedule.keep_runs.unwrap_or(DEFAULT_KEEP_RUNS) as usize;
+    let old_runs = runs.len().saturating_sub(keep_runs);
+    for run_name in runs.into_values().take(old_runs) {
+        info!("Deleting old scheduled run '{}'", run_name);
+        test_client
+            .delete(&run_name)
+            .await
+            .with_context(|| format!("Unable to delete run '{}'", run_name))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn cron_parsing() {
+    let cron = Cron::parse("*/15 3 1,15 * 1-5");
+    assert!(matches!(
+        cron,
+        Ok(Cron {
+            minutes,
+            hours,
+            days_of_month,
+            days_of_week,
+            either_day: true,
+            ..
+        }) if minutes == 1 | 1 << 15 | 1 << 30 | 1 << 45
+            && hours == 1 << 3
+            && days_of_month == 1 << 1 | 1 << 15
+            && days_of_week == 0b111110
+    ));
+    for invalid in [
+        "* * * *",
+        "60 * * * *",
+        "* * 0 * *",
+        "*/0 * * * *",
+        "a * * * *",
+    ] {
+        assert!(Cron::parse(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn cron_latest_tick() {
+    let time = |day, hour, minute| {
+        Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .single()
+            .unwrap_or_default()
+    };
+    let latest_tick =
+        |after, now| Cron::parse("0 3 * * *").map(|cron| cron.latest_tick(after, now));
+    assert!(matches!(
+        latest_tick(time(14, 12, 0), time(15, 3, 30)),
+        Ok(Some(tick)) if tick == time(15, 3, 0)
+    ));
+    assert!(matches!(
+        latest_tick(time(15, 3, 0), time(15, 3, 30)),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn cron_latest_tick_is_the_latest_matching_minute() {
+    let now = Utc
+        .with_ymd_and_hms(2026, 10, 15, 3, 30, 0)
+        .single()
+        .unwrap_or_default();
+    let after = now - Duration::days(10);
+    for expression in [
+        "0 3 * * *",
+        "*/7 1-4 * * *",
+        "45 23 * * 1",
+        "59 * 13 * 5",
+        "0 0 1 1 *",
+    ] {
+        let latest_tick = Cron::parse(expression)
+            .map(|cron| (cron.latest_tick(after, now), cron))
+            .map(|(latest_tick, cron)| {
+                let expected =
+                    std::iter::successors(Some(now), |time| Some(*time - Duration::minutes(1)))
+                        .take_while(|time| *time > after)
+                        .find(|time| cron.matches(*time));
+                (latest_tick, expected)
+            });
+        assert!(
+            matches!(latest_tick, Ok((latest_tick, expected)) if latest_tick == expected),
+            "{}: {:?}",
+            expression,
+            latest_tick
+        );
+    }
+}
+
+#[cfg(test)]
+fn scheduled_test(name: &str, cron: &str, created: DateTime<Utc>) -> serde_json::Value {
+    let mut test = Test::new(
+        name,
+        TestSpec {
+            schedule: Some(Schedule {
+                cron: cron.to_string(),
+                keep_runs: Some(1),
+            }),
+            ..TestSpec::default()
+        },
+    );
+    test.metadata.namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.metadata.creation_timestamp = Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(
+        created,
+    ));
+    serde_json::json!(test)
+}
+
+#[tokio::test]
+async fn due_schedule_creates_run() {
+    let now = Utc
+        .with_ymd_and_hms(2026, 10, 15, 3, 30, 0)
+        .single()
+        .unwrap_or_default();
+    let scheduled = async {
+        let mut previous_run = scheduled_run(
+            &serde_json::from_value(scheduled_test("nightly", "0 3 * * *", now))?,
+            now - Duration::days(1) - Duration::minutes(30),
+        );
+        previous_run.metadata.namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+        let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![
+            // Due, its last run was yesterday.
+            scheduled_test("nightly", "0 3 * * *", now - Duration::days(7)),
+            serde_json::json!(previous_run),
+            // Not due, it was created after its last tick.
+            scheduled_test("new", "0 3 * * *", now - Duration::minutes(10)),
+        ]));
+
+        let created = schedule_runs(&test_client, &Instance::default(), now).await?;
+        let run = test_client.get("nightly-20261015t0300z").await?;
+        // Only the most recent run is kept.
+        let previous_run = test_client.get_opt(previous_run.name_any()).await?;
+        // The schedule has already run at this tick.
+        let created_again = schedule_runs(&test_client, &Instance::default(), now).await?;
+        Ok::<_, anyhow::Error>((created, run, previous_run, created_again))
+    }
+    .await;
+    assert!(
+        matches!(
+            &scheduled,
+            Ok((created, run, None, created_again))
+                if created == &["nightly-20261015t0300z"]
+                    && run.spec.schedule.is_none()
+                    && created_again.is_empty()
+        ),
+        "{:?}",
+        scheduled
+    );
+}
+
+#[tokio::test]
+async fn failing_schedule_does_not_stop_the_others() {
+    let now = Utc
+        .with_ymd_and_hms(2026, 10, 15, 3, 30, 0)
+        .single()
+        .unwrap_or_default();
+    // A test with the name of the run of `nightly` at this tick exists but is not a run of it, so
+    // the run cannot be created.
+    let mut other = Test::new("nightly-20261015t0300z", TestSpec::default());
+    other.metadata.namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![
+        serde_json::json!(other),
+        scheduled_test("nightly", "0 3 * * *", now - Duration::days(7)),
+        scheduled_test("weekly", "0 3 * * 4", now - Duration::days(7)),
+    ]));
+
+    let created = schedule_runs(&test_client, &Instance::default(), now).await;
+    assert!(
+        matches!(&created, Ok(created) if created == &["weekly-20261015t0300z"]),
+        "{:?}",
+        created
+    );
+}
+
+#[tokio::test]
+async fn only_the_instances_schedules_are_run() {
+    use testsys_model::constants::LABEL_CONTROLLER_INSTANCE;
+
+    let now = Utc
+        .with_ymd_and_hms(2026, 10, 15, 3, 30, 0)
+        .single()
+        .unwrap_or_default();
+    let labeled = |name: &str, labels: serde_json::Value| {
+        let mut test = scheduled_test(name, "0 3 * * *", now - Duration::days(7));
+        test["metadata"]["labels"] = labels;
+        test
+    };
+    let test_client = TestClient::new_from_k8s_client(crate::fake_api::fake_k8s_store(vec![
+        labeled(
+            "green",
+            serde_json::json!({ "team": "platform", LABEL_CONTROLLER_INSTANCE: "green" }),
+        ),
+        labeled(
+            "blue",
+            serde_json::json!({ "team": "platform", LABEL_CONTROLLER_INSTANCE: "blue" }),
+        ),
+        labeled(
+            "other-team",
+            serde_json::json!({ "team": "portal", LABEL_CONTROLLER_INSTANCE: "green" }),
+        ),
+    ]));
+    let green = Instance::new(&crate::config::ControllerConfig {
+        instance_id: Some("green".to_string()),
+        label_selector: Some("team=platform".to_string()),
+        ..Default::default()
+    });
+
+    let created = schedule_runs(&test_client, &green, now).await;
+    assert!(
+        matches!(&created, Ok(created) if created == &["green-20261015t0300z"]),
+        "{:?}",
+        created
+    );
+}
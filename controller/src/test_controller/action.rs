@@ -1,33 +1,106 @@
 use crate::error::Result;
-use crate::job::{JobState, TEST_START_TIME_LIMIT};
+use crate::job::{resolve_env, JobState, Scheduling, TEST_START_TIME_LIMIT};
 use crate::test_controller::context::TestInterface;
+use crate::test_controller::oom_retry::next_memory_limits;
+use crate::test_controller::preflight::missing_capabilities;
+use crate::test_controller::validation::{invalid_spec, overridden_protected_label};
 use crate::utils::parse_duration;
 use anyhow::Context;
 use kube::{Api, ResourceExt};
 use log::trace;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
 use testsys_model::clients::{CrdClient, HttpStatusCode, StatusCode};
 use testsys_model::constants::{FINALIZER_MAIN, FINALIZER_TEST_JOB, NAMESPACE};
-use testsys_model::{CrdExt, Outcome, Resource, ResourceAction, TaskState};
+use testsys_model::{
+    Completions, CrdExt, DestructionPolicy, JobProgress, Resource, ResourceAction, ResourceSummary,
+    RestartPolicy, TaskState, Test, TestUserState,
+};
 
 // These values configure how long to delay between tries.
 const MAX_RETRIES: u32 = 3;
 const BACKOFF_MS: u64 = 1000;
 
+/// The number of consecutive times we will try to create a test's `Job` before giving up.
+const MAX_JOB_CREATION_FAILURES: u32 = 5;
+
 /// The action that the controller needs to take in order to reconcile the `Test`.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub(super) enum Action {
+    /// Claim the test for this controller instance before acting on it.
+    ClaimForInstance(String),
+    /// The test has a schedule, it is not run itself but the scheduler creates runs of it.
+    Scheduled,
     Initialize,
+    /// The test's spec has `suspend` set, nothing is done until it is cleared or the test is
+    /// deleted.
+    Suspended,
+    Quarantine,
+    Quarantined,
     AddMainFinalizer,
+    ObserveGeneration(i64),
+    CopyMetadata,
+    /// The test's spec cannot be used, the reason is recorded in its status.
+    InvalidSpec(String),
+    /// The test's spec was found to be invalid and there is nothing to do until it changes.
+    InvalidSpecRecorded,
+    UpdateResourceSummaries(BTreeMap<String, ResourceSummary>),
+    /// Add the test's reference to a shared resource that it uses.
+    AddResourceReference(String),
     WaitForResources,
     RegisterResourceCreationError(String),
     WaitForDependency(String),
+    PreflightFailed(String),
     AddJobFinalizer,
     StartTest,
+    /// The test's agents changed before any of them started running, so their jobs are deleted to
+    /// be created again from the new spec.
+    RecreateJob,
     WaitForTest,
+    /// The agent's pod is not scheduled until its scheduling gates are removed, which is not a
+    /// failure to start.
+    SchedulingGated,
+    /// Copy the message the failed agent container wrote to its termination log to the test's
+    /// status.
+    RecordTerminationMessage(String),
+    /// Record the assertions of the test's spec that did not hold for the agent's results.
+    RecordFailedAssertions(Vec<String>),
+    /// Copy the progress of the agent's indexed completions from its job to the test's status.
+    UpdateProgress(JobProgress),
+    /// The agent ran out of memory, relaunch the test with the last of these memory limits, which
+    /// are recorded in the test's status.
+    RelaunchWithMoreMemory(Vec<String>),
+    /// Copy whether the agent's container passes its readiness probe to the test's status.
+    UpdateReadiness(bool),
+    /// Grant the agent's request for more time, extending its `timeout` by this many seconds in
+    /// total.
+    GrantExtension(u64),
+    /// Reject the agent's request for more time for this reason.
+    RejectExtension(String),
+    /// The agent's indexed completions are done, `passed` is whether enough of them succeeded.
+    CompletionsDone {
+        completions: Completions,
+        passed: bool,
+    },
+    /// The job of the additional agent named `agent_name` from `spec.agents` ended before the
+    /// agent finished.
+    AgentError {
+        agent_name: String,
+        error: ErrorState,
+    },
     DeleteJob,
+    /// Remove the reference of a deleted or archived test from a shared resource.
+    RemoveResourceReference(String),
+    /// Delete a resource of a deleted or archived test so that it is destroyed.
+    DestroyResource(String),
+    /// A resource of the test is being destroyed, the next one is destroyed once it is gone.
+    WaitForResourceDestruction(String),
     RemoveJobFinalizer,
+    /// Finalizers added by other controllers have to be removed before the main finalizer is.
+    WaitForForeignFinalizers,
     RemoveMainFinalizer,
+    Archive,
+    Archived,
     TestDone,
     Error(ErrorState),
 }
@@ -42,6 +115,14 @@ pub(super) enum ErrorState {
     JobExitBeforeDone,
     JobTimeout,
     HandleJobRemovedBeforeDone,
+    CircuitOpen(u32),
+    PreflightFailed(String),
+    ImageNotAllowed(String),
+    DuplicateAgentName(String),
+    EnvTemplate(String),
+    ImagePullFailed(String),
+    CompletionsDeadline,
+    ResourceBudgetExceeded { resources: usize, budget: usize },
 }
 
 impl Display for ErrorState {
@@ -64,35 +145,333 @@ impl Display for ErrorState {
             ErrorState::HandleJobRemovedBeforeDone => {
                 Display::fmt("The job was removed before the test completed", f)
             }
+            ErrorState::PreflightFailed(e) => Display::fmt(e, f),
+            ErrorState::ImageNotAllowed(e) => Display::fmt(e, f),
+            ErrorState::DuplicateAgentName(name) => write!(
+                f,
+                "More than one of the test's agents is named '{}', agent names must be unique",
+                name
+            ),
+            ErrorState::EnvTemplate(e) => Display::fmt(e, f),
+            ErrorState::CompletionsDeadline => Display::fmt(
+                "The test agent's completions did not finish within the completions deadline",
+                f,
+            ),
+            ErrorState::ResourceBudgetExceeded { resources, budget } => write!(
+                f,
+                "ResourceBudgetExceeded: the test declares {} resources, more than the {} that a \
+                test may declare",
+                resources, budget
+            ),
+            ErrorState::ImagePullFailed(e) => {
+                write!(f, "Unable to pull the test agent image: {}", e)
+            }
+            ErrorState::CircuitOpen(failures) => write!(
+                f,
+                "The job could not be created after {} attempts, the test will not be retried",
+                failures
+            ),
         }
     }
 }
 
 /// Inspect the `test` to determine which `Action` the controller should take.
 pub(super) async fn determine_action(t: &TestInterface) -> Result<Action> {
+    if let Some(instance_id) = t.instance().id() {
+        if t.instance().must_claim(t.test()) {
+            return Ok(Action::ClaimForInstance(instance_id.to_string()));
+        }
+    }
+
     if t.test().is_delete_requested() {
         return determine_delete_action(t).await;
     }
 
+    if t.test().spec.schedule.is_some() {
+        return Ok(scheduled_action(t.test()));
+    }
+
     if t.test().status.is_none() {
         return Ok(Action::Initialize);
     }
 
+    if t.test().is_archive_requested() {
+        return determine_archive_action(t).await;
+    }
+
+    if t.test().is_quarantined() {
+        return Ok(Action::Quarantined);
+    }
+
+    // Only tests that have not started yet are skipped.
+    if t.is_quarantined()
+        && t.test().agent_status().task_state == TaskState::Unknown
+        && !t.test().has_finalizer(FINALIZER_TEST_JOB)
+    {
+        return Ok(Action::Quarantine);
+    }
+
     if !t.test().has_finalizer(FINALIZER_MAIN) {
         return Ok(Action::AddMainFinalizer);
     }
 
+    if let Some(generation) = unobserved_generation(t.test()) {
+        return Ok(Action::ObserveGeneration(generation));
+    }
+
+    if t.test().spec.suspend {
+        return Ok(Action::Suspended);
+    }
+
+    if t.test().invalid_spec().is_some() {
+        return Ok(Action::InvalidSpecRecorded);
+    }
+
+    let agents = std::iter::once(&t.test().spec.agent).chain(&t.test().spec.agents);
+    if let Some(reason) =
+        invalid_spec(t.test()).or_else(|| overridden_protected_label(agents, t.protected_labels()))
+    {
+        return Ok(Action::InvalidSpec(reason));
+    }
+
+    if needs_metadata_copy(t.test()) {
+        return Ok(Action::CopyMetadata);
+    }
+
+    // Tests over the resource budget fail before any of their resources are looked at.
+    if t.test().agent_status().task_state == TaskState::Unknown {
+        if let Some((resources, budget)) = t.exceeded_resource_budget() {
+            return Ok(Action::Error(ErrorState::ResourceBudgetExceeded {
+                resources,
+                budget,
+            }));
+        }
+    }
+
+    if let Some(summaries) = changed_resource_summaries(t).await? {
+        return Ok(Action::UpdateResourceSummaries(summaries));
+    }
+
+    if let Some(resource_name) = missing_resource_reference(t).await? {
+        return Ok(Action::AddResourceReference(resource_name));
+    }
+
     let agent_status = t.test().agent_status();
     match agent_status.task_state {
         TaskState::Unknown => task_not_done_action(t, false).await,
         TaskState::Running => task_not_done_action(t, true).await,
-        TaskState::Completed => Ok(Action::TestDone),
+        // An agent that runs indexed completions may report that it is done, or that one of its
+        // completions failed, before all of its completions are done. The controller decides the
+        // outcome by the agent's success threshold once the job is done.
+        TaskState::Completed | TaskState::Error
+            if t.test().spec.agent.completions.is_some() && t.test().completions().is_none() =>
+        {
+            task_not_done_action(t, true).await
+        }
+        TaskState::Completed => match unevaluated_assertions(t.test()) {
+            Some(failed) => Ok(Action::RecordFailedAssertions(failed)),
+            None => additional_agents_action(t).await,
+        },
         TaskState::Error => Ok(Action::Error(ErrorState::TestError(
             t.test().agent_error().unwrap_or("Unknown error").to_owned(),
         ))),
     }
 }
 
+/// Once the main agent has completed, waits for the additional agents in `spec.agents` to finish.
+async fn additional_agents_action(t: &TestInterface) -> Result<Action> {
+    if let Some(action) = additional_agent_error(t).await? {
+        return Ok(action);
+    }
+    let done = t.test().spec.agents.iter().all(|agent| {
+        matches!(
+            t.test().additional_agent_status(&agent.name).task_state,
+            TaskState::Completed | TaskState::Error
+        )
+    });
+    if done {
+        Ok(Action::TestDone)
+    } else {
+        Ok(Action::WaitForTest)
+    }
+}
+
+/// The error of the first additional agent in `spec.agents` that has not finished but whose job
+/// did, or that did not start or finish in time. The additional agents' jobs are started with the
+/// test agent's, so this is checked for as long as any of them runs.
+async fn additional_agent_error(t: &TestInterface) -> Result<Option<Action>> {
+    for agent in &t.test().spec.agents {
+        let task_state = t.test().additional_agent_status(&agent.name).task_state;
+        if matches!(task_state, TaskState::Completed | TaskState::Error) {
+            continue;
+        }
+        let job_state = t.get_agent_job_state(&agent.name).await?;
+        if task_state == TaskState::Unknown
+            && matches!(job_state, JobState::Unknown | JobState::Running(_))
+        {
+            if let Some(image_pull_error) = t.get_agent_image_pull_error(&agent.name).await? {
+                return Ok(Some(Action::AgentError {
+                    agent_name: agent.name.clone(),
+                    error: ErrorState::ImagePullFailed(image_pull_error),
+                }));
+            }
+        }
+        let error = match job_state {
+            JobState::Unknown | JobState::Deleting | JobState::Running(None) => continue,
+            JobState::Running(Some(duration)) => {
+                let timed_out = match (duration.to_std(), agent.timeout.as_deref()) {
+                    (Ok(duration), Some(timeout)) => parse_duration(timeout)
+                        .map(|timeout| duration > timeout)
+                        .unwrap_or(false),
+                    _ => false,
+                };
+                if timed_out {
+                    ErrorState::JobTimeout
+                } else if task_state == TaskState::Unknown
+                    && agent.restart_policy == RestartPolicy::Never
+                    && duration >= *TEST_START_TIME_LIMIT
+                {
+                    ErrorState::JobStart
+                } else {
+                    continue;
+                }
+            }
+            JobState::Failed => ErrorState::JobFailure,
+            JobState::Exited | JobState::Completions { .. } => ErrorState::JobExitBeforeDone,
+            JobState::None => ErrorState::HandleJobRemovedBeforeDone,
+        };
+        return Ok(Some(Action::AgentError {
+            agent_name: agent.name.clone(),
+            error,
+        }));
+    }
+    Ok(None)
+}
+
+/// The first agent name that is used by more than one of the test's agents.
+fn duplicate_agent_name(test: &Test) -> Option<String> {
+    let mut names = BTreeSet::new();
+    std::iter::once(&test.spec.agent)
+        .chain(&test.spec.agents)
+        .find(|agent| !names.insert(agent.name.as_str()))
+        .map(|agent| agent.name.clone())
+}
+
+/// Why the environment variables of one of the test's agents cannot be resolved, if they cannot.
+fn env_template_error(test: &Test) -> Option<String> {
+    std::iter::once(&test.spec.agent)
+        .chain(&test.spec.agents)
+        .find_map(|agent| resolve_env(agent, &test.metadata).err())
+        .map(|e| e.to_string())
+}
+
+/// The assertions of the test's spec that do not hold for the agent's last results, if it has
+/// assertions that have not been evaluated yet. An agent without results has counted nothing.
+fn unevaluated_assertions(test: &Test) -> Option<Vec<String>> {
+    if test.spec.assertions.is_empty() || test.failed_assertions().is_some() {
+        return None;
+    }
+    let results = test
+        .agent_status()
+        .results
+        .last()
+        .cloned()
+        .unwrap_or_default();
+    Some(
+        test.spec
+            .assertions
+            .iter()
+            .filter(|assertion| !assertion.holds(&results))
+            .map(ToString::to_string)
+            .collect(),
+    )
+}
+
+/// A test with a schedule is not run itself, but its spec is validated like that of any test so
+/// that a schedule that can never create a valid run is reported in its status. Valid scheduled
+/// tests are not given a status.
+fn scheduled_action(test: &Test) -> Action {
+    let reason = invalid_spec(test);
+    if reason.is_none() && test.invalid_spec().is_none() {
+        return Action::Scheduled;
+    }
+    if test.status.is_none() {
+        return Action::Initialize;
+    }
+    // An edit of the spec resets the invalid spec that was recorded for an earlier generation.
+    if let Some(generation) = unobserved_generation(test) {
+        return Action::ObserveGeneration(generation);
+    }
+    match (reason, test.invalid_spec()) {
+        (Some(reason), None) => Action::InvalidSpec(reason),
+        (Some(_), Some(_)) => Action::InvalidSpecRecorded,
+        (None, _) => Action::Scheduled,
+    }
+}
+
+/// The generation of the test's spec if the controller has not seen it yet.
+fn unobserved_generation(test: &Test) -> Option<i64> {
+    test.metadata
+        .generation
+        .filter(|generation| test.observed_generation() != Some(*generation))
+}
+
+/// Whether the user metadata in the test's spec still needs to be copied into its status.
+fn needs_metadata_copy(test: &Test) -> bool {
+    !test.spec.metadata.is_empty() && test.status_metadata() != Some(&test.spec.metadata)
+}
+
+/// Summaries of the test's resources if they differ from the ones in its status. They are kept up
+/// to date until the test agent is done. Resources that do not exist yet are left out.
+async fn changed_resource_summaries(
+    t: &TestInterface,
+) -> Result<Option<BTreeMap<String, ResourceSummary>>> {
+    if t.test().spec.resources.is_empty()
+        || matches!(
+            t.test().agent_status().task_state,
+            TaskState::Completed | TaskState::Error
+        )
+    {
+        return Ok(None);
+    }
+    let resource_client: Api<Resource> = Api::namespaced(t.k8s_client(), NAMESPACE);
+    let mut summaries = BTreeMap::new();
+    for resource_name in &t.test().spec.resources {
+        if let Some(resource) = resource_client
+            .get_opt(resource_name)
+            .await
+            .with_context(|| format!("Unable to get resource '{}'", resource_name))?
+        {
+            summaries.insert(resource_name.clone(), resource.summary());
+        }
+    }
+    Ok(Some(summaries).filter(|summaries| t.test().resource_summaries() != Some(summaries)))
+}
+
+/// The first created shared resource of the test that does not hold a reference to it yet.
+async fn missing_resource_reference(t: &TestInterface) -> Result<Option<String>> {
+    if t.test().spec.resources.is_empty() {
+        return Ok(None);
+    }
+    let resource_client: Api<Resource> = Api::namespaced(t.k8s_client(), NAMESPACE);
+    for resource_name in &t.test().spec.resources {
+        if let Some(resource) = resource_client
+            .get_opt(resource_name)
+            .await
+            .with_context(|| format!("Unable to get resource '{}'", resource_name))?
+        {
+            if resource.is_shared()
+                && !resource.is_delete_requested()
+                && resource.created_resource().is_some()
+                && !resource.references().contains(&t.name().to_string())
+            {
+                return Ok(Some(resource_name.clone()));
+            }
+        }
+    }
+    Ok(None)
+}
+
 /// Determines what we should do next if the TestSys `Test` CRD has been marked for deletion.
 ///
 /// # Preconditions
@@ -102,11 +481,16 @@ pub(super) async fn determine_action(t: &TestInterface) -> Result<Action> {
 ///
 pub(super) async fn determine_delete_action(t: &TestInterface) -> Result<Action> {
     debug_assert!(t.test().is_delete_requested());
-    let job_state = t.get_job_state().await?;
-    if !matches!(job_state, JobState::None) {
+    if t.has_jobs().await? {
         Ok(Action::DeleteJob)
+    } else if let Some(teardown_action) =
+        resource_teardown_action(t, t.test().destroys_resources_on_deletion()).await?
+    {
+        Ok(teardown_action)
     } else if t.test().has_finalizer(FINALIZER_TEST_JOB) {
         Ok(Action::RemoveJobFinalizer)
+    } else if t.test().has_foreign_finalizers() {
+        Ok(Action::WaitForForeignFinalizers)
     } else if t.test().has_finalizer(FINALIZER_MAIN) {
         Ok(Action::RemoveMainFinalizer)
     } else {
@@ -114,6 +498,124 @@ pub(super) async fn determine_delete_action(t: &TestInterface) -> Result<Action>
     }
 }
 
+/// Determines what we should do next to archive a test. The job and the resources that no other
+/// test still needs are torn down as if the test was deleted, but the test itself is kept.
+async fn determine_archive_action(t: &TestInterface) -> Result<Action> {
+    if t.test().is_archived() {
+        return Ok(Action::Archived);
+    }
+    if t.has_jobs().await? {
+        return Ok(Action::DeleteJob);
+    }
+    if let Some(teardown_action) = resource_teardown_action(t, true).await? {
+        return Ok(teardown_action);
+    }
+    if t.test().has_finalizer(FINALIZER_TEST_JOB) {
+        Ok(Action::RemoveJobFinalizer)
+    } else if t.test().has_finalizer(FINALIZER_MAIN) {
+        Ok(Action::RemoveMainFinalizer)
+    } else {
+        Ok(Action::Archive)
+    }
+}
+
+/// The next step of tearing down the test's resources before the test is deleted or archived.
+/// Resources are destroyed one at a time in reverse dependency order: a resource is only destroyed
+/// once the test's resources that depend on it are gone, and resources that do not depend on each
+/// other are destroyed in the reverse of the order they are listed in. Resources that are never
+/// destroyed, or that other tests or resources still use, are kept. The test's references to shared
+/// resources are removed first, and shared resources are only destroyed once no references remain.
+/// If the resources are not to be `destroy`ed, e.g. because a deleted test did not opt into
+/// `destroy_resources`, only the references are removed. Returns `None` once there is nothing left
+/// to tear down.
+async fn resource_teardown_action(t: &TestInterface, destroy: bool) -> Result<Option<Action>> {
+    if t.test().spec.resources.is_empty() {
+        return Ok(None);
+    }
+    let resource_client: Api<Resource> = Api::namespaced(t.k8s_client(), NAMESPACE);
+    let resources = resource_client
+        .list(&Default::default())
+        .await
+        .context("Unable to list resources")?
+        .items;
+    if let Some(resource) = resources.iter().find(|resource| {
+        t.test().spec.resources.contains(&resource.name_any())
+            && resource.references().contains(&t.name().to_string())
+    }) {
+        return Ok(Some(Action::RemoveResourceReference(resource.name_any())));
+    }
+    if !destroy {
+        return Ok(None);
+    }
+    let other_tests = t
+        .test_client()
+        .get_all()
+        .await
+        .context("Unable to list tests")?;
+    let in_use_by_other_test = |resource_name: &String| {
+        other_tests.iter().any(|other| {
+            other.name_any() != t.name()
+                && !other.is_delete_requested()
+                && !other.is_archived()
+                && !other.is_archive_requested()
+                && other.spec.resources.contains(resource_name)
+        })
+    };
+    // The test's resources that will be destroyed, including those that are being destroyed.
+    let to_destroy: Vec<&Resource> = t
+        .test()
+        .spec
+        .resources
+        .iter()
+        .rev()
+        .filter_map(|resource_name| {
+            resources
+                .iter()
+                .find(|resource| &resource.name_any() == resource_name)
+        })
+        .filter(|resource| {
+            resource.spec.destruction_policy != DestructionPolicy::Never
+                && resource.references().is_empty()
+                && !in_use_by_other_test(&resource.name_any())
+        })
+        .collect();
+    let dependents = |resource: &Resource| {
+        let resource_name = resource.name_any();
+        resources
+            .iter()
+            .filter(move |other| {
+                other
+                    .spec
+                    .depends_on
+                    .as_ref()
+                    .map(|depends_on| depends_on.contains(&resource_name))
+                    .unwrap_or(false)
+            })
+            .map(|other| other.name_any())
+    };
+    // Resources that other resources depend on are kept, unless those will be destroyed first.
+    let to_destroy: Vec<&Resource> = to_destroy
+        .iter()
+        .filter(|resource| {
+            dependents(resource)
+                .all(|dependent| to_destroy.iter().any(|other| other.name_any() == dependent))
+        })
+        .copied()
+        .collect();
+    if let Some(resource) = to_destroy
+        .iter()
+        .find(|resource| resource.is_delete_requested())
+    {
+        return Ok(Some(Action::WaitForResourceDestruction(
+            resource.name_any(),
+        )));
+    }
+    Ok(to_destroy
+        .iter()
+        .find(|resource| dependents(resource).next().is_none())
+        .map(|resource| Action::DestroyResource(resource.name_any())))
+}
+
 enum Resources {
     NotReady,
     Ready,
@@ -158,6 +660,7 @@ async fn resource_readiness(t: &TestInterface) -> Result<Resources> {
         }
         match resource.task_state(ResourceAction::Create) {
             TaskState::Unknown | TaskState::Running => return Ok(Resources::NotReady),
+            TaskState::Completed if !resource.is_ready() => return Ok(Resources::NotReady),
             TaskState::Completed => continue,
             TaskState::Error => {
                 return Ok(Resources::Error(format!(
@@ -188,24 +691,122 @@ async fn dependency_wait_action(t: &TestInterface) -> Result<Option<Action>> {
             Err(_) => return Ok(Some(Action::WaitForDependency(needed.clone()))),
         };
 
-        if needed_test
-            .agent_status()
-            .results
-            .last()
-            .map(|results| results.outcome != Outcome::Pass)
-            .unwrap_or(true)
-        {
+        if needed_test.test_user_state() != TestUserState::Passed {
             return Ok(Some(Action::WaitForDependency(needed_test.name_any())));
         }
     }
     Ok(None)
 }
 
+/// Stops the controller from repeatedly trying to create a `Job` for a test that keeps failing.
+fn circuit_breaker_action(test: &Test) -> Option<Action> {
+    let failures = test.job_creation_failures();
+    if failures >= MAX_JOB_CREATION_FAILURES {
+        Some(Action::Error(ErrorState::CircuitOpen(failures)))
+    } else {
+        None
+    }
+}
+
+/// Make sure the cluster has all of the capabilities required by the test before it is started.
+async fn preflight_action(t: &TestInterface) -> Result<Action> {
+    let missing = missing_capabilities(t.k8s_client(), &t.test().spec.requires).await?;
+    if missing.is_empty() {
+        Ok(Action::StartTest)
+    } else {
+        Ok(Action::PreflightFailed(format!(
+            "The cluster is missing required capabilities: {}",
+            missing.join(", ")
+        )))
+    }
+}
+
 async fn task_not_done_action(t: &TestInterface, is_task_state_running: bool) -> Result<Action> {
+    if !is_task_state_running {
+        if let Some(action) = circuit_breaker_action(t.test()) {
+            return Ok(action);
+        }
+        if let Some(preflight_error) = t.test().preflight_error() {
+            return Ok(Action::Error(ErrorState::PreflightFailed(
+                preflight_error.to_owned(),
+            )));
+        }
+        if let Some(image_error) = t.disallowed_image() {
+            return Ok(Action::Error(ErrorState::ImageNotAllowed(image_error)));
+        }
+        if let Some(agent_name) = duplicate_agent_name(t.test()) {
+            return Ok(Action::Error(ErrorState::DuplicateAgentName(agent_name)));
+        }
+        if let Some(env_error) = env_template_error(t.test()) {
+            return Ok(Action::Error(ErrorState::EnvTemplate(env_error)));
+        }
+    }
     if !is_task_state_running && !t.test().has_finalizer(FINALIZER_TEST_JOB) {
         return Ok(Action::AddJobFinalizer);
     }
     let job_state = t.get_job_state().await?;
+    // Once an agent is running its job is left alone even if the test's agents change.
+    if !is_task_state_running
+        && matches!(job_state, JobState::Unknown | JobState::Running(_))
+        && t.job_spec_changed().await?
+    {
+        return Ok(Action::RecreateJob);
+    }
+    // The additional agents' jobs may be gone before the test agent's while they are deleted.
+    if !matches!(job_state, JobState::None | JobState::Deleting) {
+        if let Some(action) = additional_agent_error(t).await? {
+            return Ok(action);
+        }
+    }
+    if !is_task_state_running && matches!(job_state, JobState::Unknown | JobState::Running(_)) {
+        if let Some(image_pull_error) = t.get_image_pull_error().await? {
+            return Ok(Action::Error(ErrorState::ImagePullFailed(image_pull_error)));
+        }
+    }
+    if let Some(deadline) = completions_deadline(t.test()) {
+        // The job is neither running nor done between a failed completion and its retry.
+        if matches!(job_state, JobState::Unknown | JobState::Running(_))
+            && matches!(t.get_job_age().await?, Some(age) if age > deadline)
+        {
+            return Ok(Action::Error(ErrorState::CompletionsDeadline));
+        }
+    }
+    if !matches!(job_state, JobState::None) && t.test().spec.agent.completions.is_some() {
+        if let Some(progress) = t.get_job_progress().await? {
+            if t.test().agent_status().progress != Some(progress) {
+                return Ok(Action::UpdateProgress(progress));
+            }
+        }
+    }
+    if matches!(job_state, JobState::Running(_)) && t.test().spec.agent.readiness_probe.is_some() {
+        if let Some(ready) = t.is_agent_ready().await? {
+            if t.test().agent_status().ready != Some(ready) {
+                return Ok(Action::UpdateReadiness(ready));
+            }
+        }
+    }
+    if matches!(job_state, JobState::Running(_)) {
+        if let Some(action) = extension_action(t.test(), t.max_timeout_extension()) {
+            return Ok(action);
+        }
+    }
+    if matches!(
+        job_state,
+        JobState::Running(_) | JobState::Failed | JobState::Exited
+    ) && t.test().spec.agent.oom_retry.is_some()
+        && t.is_out_of_memory().await?
+    {
+        if let Some(memory_limits) = next_memory_limits(t.test()) {
+            return Ok(Action::RelaunchWithMoreMemory(memory_limits));
+        }
+    }
+    if matches!(job_state, JobState::Failed | JobState::Exited)
+        && t.test().agent_status().termination_message.is_none()
+    {
+        if let Some(message) = t.get_termination_message().await? {
+            return Ok(Action::RecordTerminationMessage(message));
+        }
+    }
     match job_state {
         JobState::None if !is_task_state_running => match resource_readiness(t).await? {
             Resources::NotReady => Ok(Action::WaitForResources),
@@ -216,34 +817,77 @@ async fn task_not_done_action(t: &TestInterface, is_task_state_running: bool) ->
                     Ok(Action::Error(ErrorState::ResourceErrorExists(s)))
                 }
             }
-            Resources::Ready => Ok(dependency_wait_action(t)
-                .await?
-                .unwrap_or(Action::StartTest)),
+            Resources::Ready => match dependency_wait_action(t).await? {
+                Some(action) => Ok(action),
+                // Jobs that were deleted to be recreated are only replaced once they are all gone.
+                None if t.has_jobs().await? => Ok(Action::WaitForTest),
+                None => preflight_action(t).await,
+            },
         },
         JobState::None => Ok(Action::Error(ErrorState::HandleJobRemovedBeforeDone)),
         JobState::Unknown => {
             trace!("Waiting for test agent '{}' container to start", t.name());
             Ok(Action::WaitForTest)
         }
+        JobState::Deleting => {
+            trace!("Waiting for the job of test '{}' to be deleted", t.name());
+            Ok(Action::WaitForTest)
+        }
         JobState::Running(None) => {
             trace!("Test '{}' is running", t.name());
             Ok(Action::WaitForTest)
         }
         JobState::Running(Some(duration)) => {
+            // The time the pod is gated does not count against the agent's time limits, they start
+            // once the pod is scheduled after its gates were removed.
+            let duration = if t.test().spec.agent.scheduling_gates.is_none() {
+                duration
+            } else {
+                match t.get_scheduling().await? {
+                    Scheduling::Gated => {
+                        trace!("Test '{}' is waiting for its scheduling gates", t.name());
+                        return Ok(Action::SchedulingGated);
+                    }
+                    Scheduling::Unscheduled => {
+                        trace!("Test '{}' is waiting to be scheduled", t.name());
+                        return Ok(Action::WaitForTest);
+                    }
+                    Scheduling::Scheduled(scheduled_at) => t.now() - scheduled_at,
+                }
+            };
+            // The time the pod waits for the test's endpoints does not count against the agent's
+            // time limits either, they start once the agent is released.
+            let duration = if t.test().spec.wait_for_endpoints.is_empty() {
+                duration
+            } else {
+                match t.get_endpoints_reached_at().await? {
+                    Some(reached_at) => t.now() - reached_at,
+                    None => {
+                        trace!("Test '{}' is waiting for its endpoints", t.name());
+                        return Ok(Action::WaitForTest);
+                    }
+                }
+            };
             if let Ok(std_duration) = duration.to_std() {
                 if t.test()
                     .spec
                     .agent
                     .timeout
                     .as_ref()
-                    .map(|timeout| parse_duration(timeout).map(|timeout| std_duration > timeout))
+                    .map(|timeout| {
+                        parse_duration(timeout)
+                            .map(|timeout| std_duration > timeout + timeout_extension(t.test()))
+                    })
                     .unwrap_or(Ok(false))
                     .unwrap_or(false)
                 {
                     return Ok(Action::Error(ErrorState::JobTimeout));
                 }
             }
+            // A container that is restarted in place may fail several times before it reports that
+            // it is running, its backoff limit decides when the job fails instead.
             if t.test().agent_status().task_state == TaskState::Unknown
+                && t.test().spec.agent.restart_policy == RestartPolicy::Never
                 && duration >= *TEST_START_TIME_LIMIT
             {
                 trace!(
@@ -257,5 +901,1846 @@ async fn task_not_done_action(t: &TestInterface, is_task_state_running: bool) ->
         }
         JobState::Failed => Ok(Action::Error(ErrorState::JobFailure)),
         JobState::Exited => Ok(Action::Error(ErrorState::JobExitBeforeDone)),
+        JobState::Completions { succeeded, failed } => Ok(Action::CompletionsDone {
+            completions: Completions { succeeded, failed },
+            passed: t.test().spec.agent.success_threshold_met(succeeded, failed),
+        }),
+    }
+}
+
+/// Whether to grant the agent's pending request for more time. It is granted as long as the
+/// extensions the agent has been granted add up to no more than `max_extension`.
+fn extension_action(test: &Test, max_extension: Option<std::time::Duration>) -> Option<Action> {
+    let request = test.agent_status().extension_request.clone()?;
+    let granted = test
+        .agent_status()
+        .timeout_extension_seconds
+        .unwrap_or_default();
+    let requested = match parse_duration(request.trim()) {
+        Ok(requested) => requested.as_secs(),
+        Err(_) => {
+            return Some(Action::RejectExtension(format!(
+                "Invalid duration '{}'",
+                request
+            )))
+        }
+    };
+    Some(match max_extension {
+        None => Action::RejectExtension("The controller does not extend timeouts".to_string()),
+        Some(max_extension) if granted + requested > max_extension.as_secs() => {
+            Action::RejectExtension(format!(
+                "Extending the timeout by '{}' would exceed the limit of {}s",
+                request,
+                max_extension.as_secs()
+            ))
+        }
+        Some(_) => Action::GrantExtension(granted + requested),
+    })
+}
+
+/// How much the agent's `timeout` was extended at its request.
+fn timeout_extension(test: &Test) -> std::time::Duration {
+    std::time::Duration::from_secs(
+        test.agent_status()
+            .timeout_extension_seconds
+            .unwrap_or_default(),
+    )
+}
+
+/// The deadline for all of the test agent's completions to be done, if it runs completions and has
+/// one. Deadlines that are not durations are rejected when the spec is validated.
+fn completions_deadline(test: &Test) -> Option<k8s_openapi::chrono::Duration> {
+    let agent = &test.spec.agent;
+    agent.completions?;
+    let deadline = parse_duration(agent.completions_deadline.as_ref()?).ok()?;
+    k8s_openapi::chrono::Duration::from_std(deadline).ok()
+}
+
+#[cfg(test)]
+fn test_with_job_creation_failures(job_creation_failures: u32) -> Test {
+    use testsys_model::{ControllerStatus, TestStatus};
+    Test {
+        status: Some(TestStatus {
+            controller: ControllerStatus {
+                job_creation_failures,
+                ..ControllerStatus::default()
+            },
+            ..TestStatus::default()
+        }),
+        ..Test::default()
+    }
+}
+
+#[test]
+fn circuit_closed_below_limit() {
+    for failures in 0..MAX_JOB_CREATION_FAILURES {
+        assert!(circuit_breaker_action(&test_with_job_creation_failures(failures)).is_none());
+    }
+}
+
+#[test]
+fn circuit_open_at_limit() {
+    for failures in [MAX_JOB_CREATION_FAILURES, MAX_JOB_CREATION_FAILURES + 1] {
+        assert_eq!(
+            circuit_breaker_action(&test_with_job_creation_failures(failures)),
+            Some(Action::Error(ErrorState::CircuitOpen(failures)))
+        );
+    }
+}
+
+#[tokio::test]
+async fn repeatedly_failing_test_is_not_started() {
+    use kube::core::ObjectMeta;
+
+    let action = |failures: u32| {
+        let mut test = test_with_job_creation_failures(failures);
+        test.metadata = ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        };
+        let context = crate::test_controller::context::new_context(
+            crate::fake_api::fake_k8s_client::<&str>(vec![]),
+            &crate::config::ControllerConfig::default(),
+        );
+        async move { determine_action(&TestInterface::new(test, context)?).await }
+    };
+    assert!(matches!(
+        action(MAX_JOB_CREATION_FAILURES - 1).await,
+        Ok(Action::StartTest)
+    ));
+    assert!(matches!(
+        action(MAX_JOB_CREATION_FAILURES).await,
+        Ok(Action::Error(ErrorState::CircuitOpen(
+            MAX_JOB_CREATION_FAILURES
+        )))
+    ));
+}
+
+/// Determine the action for a `Test` that is ready to start, using a fake k8s API server that only
+/// knows about the `present.example.com` CRD.
+#[cfg(test)]
+async fn preflight_test_action(
+    requires: &[&str],
+    status: testsys_model::TestStatus,
+) -> Result<Action> {
+    use kube::core::ObjectMeta;
+
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![(
+        "/customresourcedefinitions/present.example.com",
+        serde_json::json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": "present.example.com" },
+            "spec": {
+                "group": "example.com",
+                "names": { "kind": "Present", "plural": "present" },
+                "scope": "Cluster",
+                "versions": []
+            }
+        }),
+    )]);
+    let test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        spec: testsys_model::TestSpec {
+            requires: requires.iter().map(|s| s.to_string()).collect(),
+            ..testsys_model::TestSpec::default()
+        },
+        status: Some(status),
+    };
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn preflight_capabilities_present() {
+    let action = preflight_test_action(&["crd/present.example.com"], Default::default()).await;
+    assert!(matches!(action, Ok(Action::StartTest)));
+}
+
+#[tokio::test]
+async fn preflight_capability_missing() {
+    let action = preflight_test_action(
+        &["crd/present.example.com", "ebs-csi-driver", "gpu"],
+        Default::default(),
+    )
+    .await;
+    assert!(matches!(
+        action,
+        Ok(Action::PreflightFailed(message)) if message
+            == "The cluster is missing required capabilities: 'ebs-csi-driver', 'gpu' (unknown capability)"
+    ));
+}
+
+#[tokio::test]
+async fn preflight_failure_is_terminal() {
+    use testsys_model::{ControllerStatus, TestStatus};
+    let status = TestStatus {
+        controller: ControllerStatus {
+            preflight_error: Some("missing".to_string()),
+            ..ControllerStatus::default()
+        },
+        ..TestStatus::default()
+    };
+    let action = preflight_test_action(&["ebs-csi-driver"], status).await;
+    assert!(matches!(
+        action,
+        Ok(Action::Error(ErrorState::PreflightFailed(message))) if message == "missing"
+    ));
+}
+
+#[test]
+fn metadata_copy() {
+    use testsys_model::TestStatus;
+    let mut test = Test {
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    assert!(!needs_metadata_copy(&test));
+    test.spec
+        .metadata
+        .insert("gitSha".to_string(), "0123abcd".to_string());
+    assert!(needs_metadata_copy(&test));
+    if let Some(status) = test.status.as_mut() {
+        status.controller.metadata = Some(test.spec.metadata.clone());
     }
+    assert!(!needs_metadata_copy(&test));
+}
+
+/// Determine the action for a `Test` that needs the resource `my-cluster`, using a fake k8s API
+/// server where `my-cluster` has been created and reports the given readiness.
+#[cfg(test)]
+async fn resource_readiness_test_action(ready: Option<bool>) -> Result<Action> {
+    use kube::core::ObjectMeta;
+    use testsys_model::{ResourceAgentState, ResourceSpec, ResourceStatus, TestStatus};
+
+    let mut resource = Resource::new("my-cluster", ResourceSpec::default());
+    resource.status = Some(ResourceStatus {
+        creation: ResourceAgentState {
+            task_state: TaskState::Completed,
+            error: None,
+        },
+        ready,
+        ..ResourceStatus::default()
+    });
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![(
+        "/resources/my-cluster",
+        serde_json::to_value(&resource)?,
+    )]);
+    let test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        spec: testsys_model::TestSpec {
+            resources: vec!["my-cluster".to_string()],
+            ..testsys_model::TestSpec::default()
+        },
+        // The resource's summary is up to date.
+        status: Some(TestStatus {
+            resources: [("my-cluster".to_string(), resource.summary())].into(),
+            ..TestStatus::default()
+        }),
+    };
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn wait_for_resource_readiness() {
+    let action = resource_readiness_test_action(Some(false)).await;
+    assert!(matches!(action, Ok(Action::WaitForResources)));
+}
+
+#[tokio::test]
+async fn start_test_when_resource_ready() {
+    let action = resource_readiness_test_action(Some(true)).await;
+    assert!(matches!(action, Ok(Action::StartTest)));
+    // Resource agents that do not report readiness are ready once creation has completed.
+    let action = resource_readiness_test_action(None).await;
+    assert!(matches!(action, Ok(Action::StartTest)));
+}
+
+/// Determine the action for a `Test` whose agent has the given `restart_policy` and has not yet
+/// reported that it is running, using a fake k8s API server where the test's job has been active
+/// for a minute and one of its containers has already failed.
+#[cfg(test)]
+async fn restarting_job_test_action(restart_policy: RestartPolicy) -> Result<Action> {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, Utc};
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.restart_policy = restart_policy;
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![
+        (
+            format!("/jobs/{}", test.job_name()),
+            serde_json::json!({
+                "apiVersion": "batch/v1",
+                "kind": "Job",
+                "metadata": { "name": test.job_name() },
+                "status": {
+                    "active": 1,
+                    "failed": 1,
+                    "startTime": Time(Utc::now() - Duration::minutes(1)),
+                }
+            }),
+        ),
+        ("/pods".to_string(), crate::fake_api::pod_list(vec![])),
+    ]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn restarting_container_is_tolerated() {
+    let action = restarting_job_test_action(RestartPolicy::OnFailure).await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+}
+
+#[tokio::test]
+async fn never_restarting_container_must_start_in_time() {
+    let action = restarting_job_test_action(RestartPolicy::Never).await;
+    assert!(matches!(action, Ok(Action::Error(ErrorState::JobStart))));
+}
+
+#[tokio::test]
+async fn agent_timeout_follows_the_clock() {
+    use crate::clock::FakeClock;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, TimeZone, Utc};
+    use kube::core::ObjectMeta;
+    use std::sync::Arc;
+    use testsys_model::TestStatus;
+
+    let started = Utc
+        .with_ymd_and_hms(2026, 10, 15, 12, 0, 0)
+        .single()
+        .unwrap_or_default();
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.timeout = Some("10m".to_string());
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = TaskState::Running;
+    }
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![(
+        format!("/jobs/{}", test.job_name()),
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": test.job_name() },
+            "status": { "active": 1, "startTime": Time(started) }
+        }),
+    )]);
+    let clock = Arc::new(FakeClock::new(started + Duration::minutes(9)));
+    let context = crate::test_controller::context::with_clock(
+        &crate::test_controller::context::new_context(
+            k8s_client,
+            &crate::config::ControllerConfig::default(),
+        ),
+        clock.clone(),
+    );
+    let action = |test: &Test| {
+        let interface = TestInterface::new(test.clone(), context.clone());
+        async move { determine_action(&interface?).await }
+    };
+
+    assert!(matches!(action(&test).await, Ok(Action::WaitForTest)));
+    clock.advance(Duration::minutes(2));
+    assert!(matches!(
+        action(&test).await,
+        Ok(Action::Error(ErrorState::JobTimeout))
+    ));
+    // Time the agent was granted postpones its timeout.
+    if let Some(status) = test.status.as_mut() {
+        status.agent.timeout_extension_seconds = Some(300);
+    }
+    assert!(matches!(action(&test).await, Ok(Action::WaitForTest)));
+}
+
+#[test]
+fn timeout_extension_is_capped() {
+    use std::time::Duration;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    let max_extension = Some(Duration::from_secs(3600));
+    assert_eq!(extension_action(&test, max_extension), None);
+    let request = |test: &mut Test, request: &str, granted: Option<u64>| {
+        if let Some(status) = test.status.as_mut() {
+            status.agent.extension_request = Some(request.to_string());
+            status.agent.timeout_extension_seconds = granted;
+        }
+    };
+
+    // A request within the limit extends the timeout by the total of the granted requests.
+    request(&mut test, "30m", None);
+    assert_eq!(
+        extension_action(&test, max_extension),
+        Some(Action::GrantExtension(1800))
+    );
+    request(&mut test, "30m", Some(1800));
+    assert_eq!(
+        extension_action(&test, max_extension),
+        Some(Action::GrantExtension(3600))
+    );
+    assert_eq!(timeout_extension(&test), Duration::from_secs(1800));
+
+    // Requests over the limit are rejected, as are all requests without a limit.
+    request(&mut test, "31m", Some(1800));
+    assert!(matches!(
+        extension_action(&test, max_extension),
+        Some(Action::RejectExtension(_))
+    ));
+    request(&mut test, "2h", None);
+    assert!(matches!(
+        extension_action(&test, max_extension),
+        Some(Action::RejectExtension(_))
+    ));
+    request(&mut test, "1m", None);
+    assert!(matches!(
+        extension_action(&test, None),
+        Some(Action::RejectExtension(_))
+    ));
+}
+
+#[test]
+fn observe_generation() {
+    use testsys_model::TestStatus;
+    let mut test = Test {
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    assert_eq!(unobserved_generation(&test), None);
+    test.metadata.generation = Some(1);
+    assert_eq!(unobserved_generation(&test), Some(1));
+    if let Some(status) = test.status.as_mut() {
+        status.controller.observed_generation = Some(1);
+    }
+    assert_eq!(unobserved_generation(&test), None);
+    test.metadata.generation = Some(2);
+    assert_eq!(unobserved_generation(&test), Some(2));
+}
+
+#[tokio::test]
+async fn quarantined_test_is_skipped() {
+    use testsys_model::TestStatus;
+
+    let action = |name: &str, quarantined: bool| {
+        let mut test = Test {
+            status: Some(TestStatus::default()),
+            ..Test::default()
+        };
+        test.metadata.name = Some(name.to_string());
+        if let Some(status) = test.status.as_mut() {
+            status.controller.quarantined = quarantined;
+        }
+        let context = crate::test_controller::context::new_context(
+            crate::fake_api::fake_k8s_client(Vec::<(String, serde_json::Value)>::new()),
+            &crate::config::ControllerConfig {
+                quarantine: vec!["flaky-*".to_string()],
+                ..Default::default()
+            },
+        );
+        async move { determine_action(&TestInterface::new(test, context)?).await }
+    };
+    assert!(matches!(
+        action("flaky-test", false).await,
+        Ok(Action::Quarantine)
+    ));
+    assert!(matches!(
+        action("flaky-test", true).await,
+        Ok(Action::Quarantined)
+    ));
+    assert!(matches!(
+        action("stable-test", false).await,
+        Ok(Action::AddMainFinalizer)
+    ));
+}
+
+#[tokio::test]
+async fn suspended_test_is_not_started() {
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    let action = |suspend: bool| {
+        let mut test = Test {
+            metadata: ObjectMeta {
+                name: Some("my-test".to_string()),
+                finalizers: Some(vec![FINALIZER_MAIN.to_string()]),
+                ..ObjectMeta::default()
+            },
+            status: Some(TestStatus::default()),
+            ..Test::default()
+        };
+        test.spec.suspend = suspend;
+        let context = crate::test_controller::context::new_context(
+            crate::fake_api::fake_k8s_client::<&str>(vec![]),
+            &crate::config::ControllerConfig::default(),
+        );
+        async move { determine_action(&TestInterface::new(test, context)?).await }
+    };
+    assert!(matches!(action(true).await, Ok(Action::Suspended)));
+    assert!(matches!(action(false).await, Ok(Action::AddJobFinalizer)));
+}
+
+#[tokio::test]
+async fn suspended_test_can_be_deleted() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::Utc;
+    use kube::core::ObjectMeta;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            deletion_timestamp: Some(Time(Utc::now())),
+            finalizers: Some(vec![FINALIZER_MAIN.to_string()]),
+            ..ObjectMeta::default()
+        },
+        ..Test::default()
+    };
+    test.spec.suspend = true;
+    let context = crate::test_controller::context::new_context(
+        crate::fake_api::fake_k8s_client::<&str>(vec![]),
+        &crate::config::ControllerConfig::default(),
+    );
+    let action = async { determine_action(&TestInterface::new(test, context)?).await }.await;
+    assert!(matches!(action, Ok(Action::RemoveMainFinalizer)));
+}
+
+/// Determine the action for a test with 10 indexed completions and a 90% success threshold whose
+/// job has finished with `succeeded` successful completions.
+#[cfg(test)]
+async fn completions_test_action(
+    succeeded: i32,
+    task_state: TaskState,
+    completions: Option<Completions>,
+) -> Result<Action> {
+    indexed_test_action(
+        serde_json::json!({
+            "succeeded": succeeded,
+            "failed": 10 - succeeded,
+            "conditions": [{
+                "type": if succeeded == 10 { "Complete" } else { "Failed" },
+                "status": "True"
+            }]
+        }),
+        JobProgress {
+            desired: 10,
+            active: 0,
+            succeeded,
+            failed: 10 - succeeded,
+        },
+        task_state,
+        completions,
+    )
+    .await
+}
+
+/// Determine the action for a test with 10 indexed completions and a 90% success threshold whose
+/// job has the status `job_status`, and whose status shows the `progress` of its completions.
+#[cfg(test)]
+async fn indexed_test_action(
+    job_status: serde_json::Value,
+    progress: JobProgress,
+    task_state: TaskState,
+    completions: Option<Completions>,
+) -> Result<Action> {
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.completions = Some(10);
+    test.spec.agent.success_threshold_percent = Some(90);
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = task_state;
+        status.agent.progress = Some(progress);
+        status.controller.completions = completions;
+    }
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![(
+        format!("/jobs/{}", test.job_name()),
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": test.job_name() },
+            "spec": {
+                "completions": 10,
+                "completionMode": "Indexed",
+                "template": {}
+            },
+            "status": job_status
+        }),
+    )]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn early_failed_completion_does_not_stop_the_others() {
+    // The first completion failed while the others kept running.
+    let running = JobProgress {
+        desired: 10,
+        active: 9,
+        succeeded: 0,
+        failed: 1,
+    };
+    let action = indexed_test_action(
+        serde_json::json!({ "active": 9, "failed": 1 }),
+        running,
+        TaskState::Error,
+        None,
+    )
+    .await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+
+    // The others succeeded, and the job failed once the first completion used up its retries.
+    let action = indexed_test_action(
+        serde_json::json!({
+            "succeeded": 9,
+            "failed": 10,
+            "completedIndexes": "1-9",
+            "conditions": [{ "type": "Failed", "status": "True" }]
+        }),
+        JobProgress {
+            desired: 10,
+            active: 0,
+            succeeded: 9,
+            failed: 10,
+        },
+        TaskState::Error,
+        None,
+    )
+    .await;
+    assert!(matches!(
+        action,
+        Ok(Action::CompletionsDone {
+            completions: Completions {
+                succeeded: 9,
+                failed: 1
+            },
+            passed: true
+        })
+    ));
+}
+
+#[tokio::test]
+async fn completions_at_success_threshold() {
+    let action = completions_test_action(9, TaskState::Running, None).await;
+    assert!(matches!(
+        action,
+        Ok(Action::CompletionsDone {
+            completions: Completions {
+                succeeded: 9,
+                failed: 1
+            },
+            passed: true
+        })
+    ));
+}
+
+#[tokio::test]
+async fn failed_completion_is_held_to_success_threshold() {
+    // A completion that reported an error does not fail the test by itself.
+    let action = completions_test_action(9, TaskState::Error, None).await;
+    assert!(matches!(
+        action,
+        Ok(Action::CompletionsDone {
+            completions: Completions {
+                succeeded: 9,
+                failed: 1
+            },
+            passed: true
+        })
+    ));
+}
+
+#[tokio::test]
+async fn completions_below_success_threshold() {
+    let action = completions_test_action(8, TaskState::Running, None).await;
+    assert!(matches!(
+        action,
+        Ok(Action::CompletionsDone {
+            completions: Completions {
+                succeeded: 8,
+                failed: 2
+            },
+            passed: false
+        })
+    ));
+}
+
+#[tokio::test]
+async fn completions_are_evaluated_once() {
+    // An agent reporting that it is done does not complete the test.
+    let action = completions_test_action(10, TaskState::Completed, None).await;
+    assert!(matches!(
+        action,
+        Ok(Action::CompletionsDone { passed: true, .. })
+    ));
+    let completions = Completions {
+        succeeded: 10,
+        failed: 0,
+    };
+    let action = completions_test_action(10, TaskState::Completed, Some(completions)).await;
+    assert!(matches!(action, Ok(Action::TestDone)));
+}
+
+/// Determine the action for a running test with 10 indexed completions whose job has 3 running,
+/// 4 succeeded and 1 failed completions, and whose status shows the `recorded` progress.
+#[cfg(test)]
+async fn progress_test_action(recorded: Option<JobProgress>) -> Result<Action> {
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.completions = Some(10);
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = TaskState::Running;
+        status.agent.progress = recorded;
+    }
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![(
+        format!("/jobs/{}", test.job_name()),
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": test.job_name() },
+            "spec": {
+                "completions": 10,
+                "completionMode": "Indexed",
+                "template": {}
+            },
+            "status": {
+                "active": 3,
+                "succeeded": 4,
+                "failed": 1
+            }
+        }),
+    )]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn partially_complete_job_progress() {
+    let progress = JobProgress {
+        desired: 10,
+        active: 3,
+        succeeded: 4,
+        failed: 1,
+    };
+    assert!(matches!(
+        progress_test_action(None).await,
+        Ok(Action::UpdateProgress(update)) if update == progress
+    ));
+    // The progress is only sent when it changes.
+    assert!(matches!(
+        progress_test_action(Some(progress)).await,
+        Ok(Action::WaitForTest)
+    ));
+}
+
+/// Determine the action for a running test whose agent has a readiness probe if `probe`, whose
+/// agent container is `ready`, next to a sidecar that never is, and whose status shows the
+/// `recorded` readiness.
+#[cfg(test)]
+async fn readiness_test_action(probe: bool, ready: bool, recorded: Option<bool>) -> Result<Action> {
+    use kube::core::ObjectMeta;
+    use testsys_model::{Probe, TestStatus};
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.name = "Sonobuoy".to_string();
+    if probe {
+        test.spec.agent.readiness_probe = Some(Probe {
+            tcp_socket_port: Some(8080),
+            ..Probe::default()
+        });
+    }
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = TaskState::Running;
+        status.agent.ready = recorded;
+    }
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![
+        (
+            format!("/jobs/{}", test.job_name()),
+            serde_json::json!({
+                "apiVersion": "batch/v1",
+                "kind": "Job",
+                "metadata": { "name": test.job_name() },
+                "spec": { "template": {} },
+                "status": { "active": 1 }
+            }),
+        ),
+        (
+            "/pods".to_string(),
+            crate::fake_api::pod_list(vec![serde_json::json!({
+                "metadata": { "name": "my-test-pod" },
+                "spec": { "containers": [{ "name": "sonobuoy" }, { "name": "proxy" }] },
+                "status": {
+                    "phase": "Running",
+                    "containerStatuses": [
+                        {
+                            "name": "proxy",
+                            "image": "example.com/proxy:v1",
+                            "imageID": "",
+                            "ready": false,
+                            "restartCount": 0,
+                        },
+                        {
+                            "name": "sonobuoy",
+                            "image": "example.com/agent:v1",
+                            "imageID": "",
+                            "ready": ready,
+                            "restartCount": 0,
+                        },
+                    ]
+                }
+            })]),
+        ),
+    ]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn agent_readiness_is_recorded() {
+    assert!(matches!(
+        readiness_test_action(true, false, None).await,
+        Ok(Action::UpdateReadiness(false))
+    ));
+    assert!(matches!(
+        readiness_test_action(true, true, Some(false)).await,
+        Ok(Action::UpdateReadiness(true))
+    ));
+    assert!(matches!(
+        readiness_test_action(true, true, Some(true)).await,
+        Ok(Action::WaitForTest)
+    ));
+    // Without a readiness probe the running pod is all that matters.
+    assert!(matches!(
+        readiness_test_action(false, false, None).await,
+        Ok(Action::WaitForTest)
+    ));
+}
+
+/// Determine the action for a test whose job is running and was built with the spec hash
+/// `job_spec_hash`, or with the spec the test's agent would be given now if it is `None`.
+#[cfg(test)]
+async fn spec_change_test_action(
+    task_state: TaskState,
+    job_spec_hash: Option<&str>,
+) -> Result<Action> {
+    use kube::core::ObjectMeta;
+    use testsys_model::constants::ANNOTATION_SPEC_HASH;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.env = Some(BTreeMap::from([(
+        "CLUSTER".to_string(),
+        "edited".to_string(),
+    )]));
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = task_state;
+        status.controller.correlation_id = Some("trace-1234".to_string());
+    }
+    let context = crate::test_controller::context::new_context(
+        crate::fake_api::fake_k8s_client::<&str>(vec![]),
+        &crate::config::ControllerConfig::default(),
+    );
+    let current_spec_hash = TestInterface::new(test.clone(), context)?
+        .job_builders("trace-1234", &[], None)?
+        .into_iter()
+        .next()
+        .map(|job_builder| job_builder.spec_hash())
+        .unwrap_or_default();
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![
+        (
+            format!("/jobs/{}", test.job_name()),
+            serde_json::json!({
+                "apiVersion": "batch/v1",
+                "kind": "Job",
+                "metadata": {
+                    "name": test.job_name(),
+                    "annotations": {
+                        ANNOTATION_SPEC_HASH: job_spec_hash.unwrap_or(&current_spec_hash)
+                    }
+                },
+                "spec": { "template": {} },
+                "status": { "active": 1 }
+            }),
+        ),
+        ("/pods".to_string(), crate::fake_api::pod_list(vec![])),
+    ]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn changed_spec_recreates_job_before_running() {
+    assert!(matches!(
+        spec_change_test_action(TaskState::Unknown, Some("0123456789abcdef")).await,
+        Ok(Action::RecreateJob)
+    ));
+    assert!(matches!(
+        spec_change_test_action(TaskState::Unknown, None).await,
+        Ok(Action::WaitForTest)
+    ));
+}
+
+#[tokio::test]
+async fn changed_spec_does_not_recreate_running_job() {
+    assert!(matches!(
+        spec_change_test_action(TaskState::Running, Some("0123456789abcdef")).await,
+        Ok(Action::WaitForTest)
+    ));
+}
+
+#[cfg(test)]
+async fn allowed_images_test_action(image: &str) -> Result<Action> {
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![FINALIZER_MAIN.to_string()]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.image = image.to_string();
+    let context = crate::test_controller::context::new_context(
+        crate::fake_api::fake_k8s_client(Vec::<(String, serde_json::Value)>::new()),
+        &crate::config::ControllerConfig {
+            allowed_images: vec!["public.ecr.aws/bottlerocket-test-system/".to_string()],
+            ..Default::default()
+        },
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn allowed_image_is_run() {
+    let action =
+        allowed_images_test_action("public.ecr.aws/bottlerocket-test-system/sonobuoy:v1").await;
+    assert!(matches!(action, Ok(Action::AddJobFinalizer)));
+}
+
+#[tokio::test]
+async fn disallowed_image_is_rejected() {
+    let action = allowed_images_test_action("docker.io/someone/agent:latest").await;
+    assert!(matches!(
+        action,
+        Ok(Action::Error(ErrorState::ImageNotAllowed(message)))
+            if message.contains("docker.io/someone/agent:latest")
+                && message.contains("public.ecr.aws/bottlerocket-test-system/")
+    ));
+}
+
+/// Determine the action for a `Test` that declares `resources` resources when the controller allows
+/// each test two.
+#[cfg(test)]
+async fn resource_budget_test_action(resources: usize) -> Result<Action> {
+    use kube::core::ObjectMeta;
+    use testsys_model::{Agent, ResourceSpec, TestStatus};
+
+    let resource_names: Vec<String> = (0..resources).map(|i| format!("resource-{}", i)).collect();
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            namespace: Some(NAMESPACE.to_string()),
+            finalizers: Some(vec![FINALIZER_MAIN.to_string()]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.resources = resource_names.clone();
+    let resources = resource_names.iter().map(|name| {
+        serde_json::json!(Resource::new(
+            name,
+            ResourceSpec {
+                depends_on: None,
+                conflicts_with: None,
+                agent: Agent::default(),
+                destruction_policy: Default::default(),
+                pool: None,
+                shared: None,
+            }
+        ))
+    });
+    let context = crate::test_controller::context::new_context(
+        crate::fake_api::fake_k8s_store(resources.collect()),
+        &crate::config::ControllerConfig {
+            max_resources_per_test: Some(2),
+            ..Default::default()
+        },
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn over_budget_test_is_rejected() {
+    let action = resource_budget_test_action(3).await;
+    assert!(matches!(
+        action,
+        Ok(Action::Error(ErrorState::ResourceBudgetExceeded {
+            resources: 3,
+            budget: 2
+        }))
+    ));
+}
+
+#[tokio::test]
+async fn at_budget_test_proceeds() {
+    let action = resource_budget_test_action(2).await;
+    assert!(matches!(
+        action,
+        Ok(action) if !matches!(action, Action::Error(_))
+    ));
+}
+
+/// Determine the action for a `Test` that is being deleted and has the given `finalizers`.
+#[cfg(test)]
+async fn deleted_test_action(finalizers: &[&str]) -> Result<Action> {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::Utc;
+    use kube::core::ObjectMeta;
+
+    let context = crate::test_controller::context::new_context(
+        crate::fake_api::fake_k8s_client::<&str>(vec![]),
+        &crate::config::ControllerConfig::default(),
+    );
+    let test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            deletion_timestamp: Some(Time(Utc::now())),
+            finalizers: Some(finalizers.iter().map(|s| s.to_string()).collect()),
+            ..ObjectMeta::default()
+        },
+        ..Test::default()
+    };
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn foreign_finalizers_are_removed_first() {
+    let action = deleted_test_action(&[FINALIZER_MAIN, "example.com/cost-tracker"]).await;
+    assert!(matches!(action, Ok(Action::WaitForForeignFinalizers)));
+    // Once the other controller is done the main finalizer is removed.
+    let action = deleted_test_action(&[FINALIZER_MAIN]).await;
+    assert!(matches!(action, Ok(Action::RemoveMainFinalizer)));
+}
+
+/// A context whose clock is `clock` and whose client finds the jobs `job_names` running with a pod,
+/// created an hour ago, that cannot pull the agent image.
+#[cfg(test)]
+fn image_pull_context(
+    job_names: &[String],
+    clock: std::sync::Arc<crate::clock::FakeClock>,
+) -> crate::test_controller::context::Context {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, Utc};
+
+    let jobs = job_names.iter().map(|job_name| {
+        (
+            format!("/jobs/{}", job_name),
+            serde_json::json!({
+                "apiVersion": "batch/v1",
+                "kind": "Job",
+                "metadata": { "name": job_name },
+                "status": { "active": 1 }
+            }),
+        )
+    });
+    let pods = (
+        "/pods".to_string(),
+        crate::fake_api::pod_list(vec![serde_json::json!({
+            "metadata": {
+                "name": "agent-x7k2p",
+                "uid": "c5b1a2e4-0d7f-4a51-9f3e-6d2b8c1e7a90",
+                "creationTimestamp": Time(Utc::now() - Duration::hours(1)),
+            },
+            "status": {
+                "containerStatuses": [{
+                    "name": "agent",
+                    "image": "example.com/agent:v1",
+                    "imageID": "",
+                    "ready": false,
+                    "restartCount": 0,
+                    "state": {
+                        "waiting": {
+                            "reason": "ImagePullBackOff",
+                            "message": "Back-off pulling image \"example.com/agent:v1\"",
+                        }
+                    }
+                }]
+            }
+        })]),
+    );
+    let k8s_client = crate::fake_api::fake_k8s_client(jobs.chain(Some(pods)).collect());
+    crate::test_controller::context::with_clock(
+        &crate::test_controller::context::new_context(
+            k8s_client,
+            &crate::config::ControllerConfig {
+                image_pull_grace_period: Some("2m".to_string()),
+                ..Default::default()
+            },
+        ),
+        clock,
+    )
+}
+
+/// A `Test` whose agents have not started.
+#[cfg(test)]
+fn image_pull_test() -> Test {
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    }
+}
+
+#[tokio::test]
+async fn persistent_image_pull_error_fails_test() {
+    use crate::clock::FakeClock;
+    use k8s_openapi::chrono::{Duration, Utc};
+    use std::sync::Arc;
+
+    let test = image_pull_test();
+    let clock = Arc::new(FakeClock::new(Utc::now()));
+    let context = image_pull_context(&[test.job_name()], clock.clone());
+    let action = || {
+        let interface = TestInterface::new(test.clone(), context.clone());
+        async move { determine_action(&interface?).await }
+    };
+
+    // The grace period starts when the pull is first seen failing, not when the pod was created,
+    // and the image may still be pulled once the registry is reachable again.
+    assert!(matches!(action().await, Ok(Action::WaitForTest)));
+    clock.advance(Duration::minutes(1));
+    assert!(matches!(action().await, Ok(Action::WaitForTest)));
+    clock.advance(Duration::minutes(1));
+    assert!(matches!(
+        action().await,
+        Ok(Action::Error(ErrorState::ImagePullFailed(message)))
+            if message == "ImagePullBackOff: Back-off pulling image \"example.com/agent:v1\""
+    ));
+}
+
+#[tokio::test]
+async fn additional_agent_image_pull_error_fails_agent() {
+    use crate::clock::FakeClock;
+    use k8s_openapi::chrono::{Duration, Utc};
+    use std::sync::Arc;
+    use testsys_model::Agent;
+
+    let mut test = image_pull_test();
+    test.spec.agents = vec![Agent {
+        name: "baseline".to_string(),
+        ..Agent::default()
+    }];
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = TaskState::Running;
+    }
+    let clock = Arc::new(FakeClock::new(Utc::now()));
+    let context = image_pull_context(
+        &[test.job_name(), test.agent_job_name("baseline")],
+        clock.clone(),
+    );
+    let action = || {
+        let interface = TestInterface::new(test.clone(), context.clone());
+        async move { determine_action(&interface?).await }
+    };
+
+    assert!(matches!(action().await, Ok(Action::WaitForTest)));
+    clock.advance(Duration::minutes(3));
+    assert!(matches!(
+        action().await,
+        Ok(Action::AgentError { agent_name, error: ErrorState::ImagePullFailed(_) })
+            if agent_name == "baseline"
+    ));
+}
+
+#[tokio::test]
+async fn failed_job_termination_message_is_recorded() {
+    use kube::core::ObjectMeta;
+    use testsys_model::{AgentStatus, TestStatus};
+
+    let test = |termination_message: Option<&str>| Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus {
+            agent: AgentStatus {
+                task_state: TaskState::Running,
+                termination_message: termination_message.map(str::to_string),
+                ..AgentStatus::default()
+            },
+            ..TestStatus::default()
+        }),
+        ..Test::default()
+    };
+    let action = |test: Test| async move {
+        let k8s_client = crate::fake_api::fake_k8s_client(vec![
+            (
+                format!("/jobs/{}", test.job_name()),
+                serde_json::json!({
+                    "apiVersion": "batch/v1",
+                    "kind": "Job",
+                    "metadata": { "name": test.job_name() },
+                    "spec": { "backoffLimit": 0 },
+                    "status": { "failed": 1 }
+                }),
+            ),
+            (
+                "/pods".to_string(),
+                crate::fake_api::pod_list(vec![serde_json::json!({
+                    "metadata": { "name": format!("{}-x7k2p", test.job_name()) },
+                    "status": {
+                        "containerStatuses": [{
+                            "name": "agent",
+                            "image": "example.com/agent:v1",
+                            "imageID": "",
+                            "ready": false,
+                            "restartCount": 0,
+                            "state": {
+                                "terminated": {
+                                    "exitCode": 2,
+                                    "message": "Sonobuoy plugin 'e2e' failed",
+                                }
+                            }
+                        }]
+                    }
+                })]),
+            ),
+        ]);
+        let context = crate::test_controller::context::new_context(
+            k8s_client,
+            &crate::config::ControllerConfig::default(),
+        );
+        determine_action(&TestInterface::new(test, context)?).await
+    };
+
+    assert!(matches!(
+        action(test(None)).await,
+        Ok(Action::RecordTerminationMessage(message)) if message == "Sonobuoy plugin 'e2e' failed"
+    ));
+    // Once the message is recorded the test fails.
+    assert!(matches!(
+        action(test(Some("Sonobuoy plugin 'e2e' failed"))).await,
+        Ok(Action::Error(ErrorState::JobFailure))
+    ));
+}
+
+#[tokio::test]
+async fn out_of_memory_agent_is_relaunched_with_more_memory() {
+    use kube::core::ObjectMeta;
+    use std::collections::BTreeMap;
+    use testsys_model::{AgentStatus, ContainerResources, OomRetry, TestStatus};
+
+    let test = |memory_limits: Vec<&str>| {
+        let mut test = Test {
+            metadata: ObjectMeta {
+                name: Some("my-test".to_string()),
+                finalizers: Some(vec![
+                    FINALIZER_MAIN.to_string(),
+                    FINALIZER_TEST_JOB.to_string(),
+                ]),
+                ..ObjectMeta::default()
+            },
+            status: Some(TestStatus {
+                agent: AgentStatus {
+                    task_state: TaskState::Running,
+                    termination_message: Some("Killed".to_string()),
+                    memory_limits: memory_limits.into_iter().map(str::to_string).collect(),
+                    ..AgentStatus::default()
+                },
+                ..TestStatus::default()
+            }),
+            ..Test::default()
+        };
+        test.spec.agent.container_resources = Some(ContainerResources {
+            limits: Some(BTreeMap::from([("memory".to_string(), "1Gi".to_string())])),
+            requests: None,
+        });
+        test.spec.agent.oom_retry = Some(OomRetry {
+            factor: Some(4),
+            max_memory: "8Gi".to_string(),
+        });
+        test
+    };
+    let action = |test: Test| async move {
+        let k8s_client = crate::fake_api::fake_k8s_client(vec![
+            (
+                format!("/jobs/{}", test.job_name()),
+                serde_json::json!({
+                    "apiVersion": "batch/v1",
+                    "kind": "Job",
+                    "metadata": { "name": test.job_name() },
+                    "spec": { "backoffLimit": 0 },
+                    "status": { "failed": 1 }
+                }),
+            ),
+            (
+                "/pods".to_string(),
+                crate::fake_api::pod_list(vec![serde_json::json!({
+                    "metadata": { "name": format!("{}-x7k2p", test.job_name()) },
+                    "status": {
+                        "containerStatuses": [{
+                            "name": "agent",
+                            "image": "example.com/agent:v1",
+                            "imageID": "",
+                            "ready": false,
+                            "restartCount": 0,
+                            "state": {
+                                "terminated": { "exitCode": 137, "reason": "OOMKilled" }
+                            }
+                        }]
+                    }
+                })]),
+            ),
+        ]);
+        let context = crate::test_controller::context::new_context(
+            k8s_client,
+            &crate::config::ControllerConfig::default(),
+        );
+        determine_action(&TestInterface::new(test, context)?).await
+    };
+
+    assert!(matches!(
+        action(test(vec![])).await,
+        Ok(Action::RelaunchWithMoreMemory(limits)) if limits == vec!["4Gi".to_string()]
+    ));
+    assert!(matches!(
+        action(test(vec!["4Gi"])).await,
+        Ok(Action::RelaunchWithMoreMemory(limits))
+            if limits == vec!["4Gi".to_string(), "8Gi".to_string()]
+    ));
+    // The test fails once the agent runs out of memory with the max.
+    assert!(matches!(
+        action(test(vec!["4Gi", "8Gi"])).await,
+        Ok(Action::Error(ErrorState::JobFailure))
+    ));
+}
+
+/// Determine the action for a running test with 10 indexed completions and the completions
+/// `deadline` whose job started two hours ago and is waiting to retry a failed completion.
+#[cfg(test)]
+async fn completions_deadline_test_action(deadline: &str) -> Result<Action> {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, Utc};
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.completions = Some(10);
+    test.spec.agent.completions_deadline = Some(deadline.to_string());
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = TaskState::Running;
+        status.agent.progress = Some(JobProgress {
+            desired: 10,
+            active: 0,
+            succeeded: 6,
+            failed: 2,
+        });
+    }
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![(
+        format!("/jobs/{}", test.job_name()),
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": test.job_name() },
+            "spec": {
+                "completions": 10,
+                "completionMode": "Indexed",
+                "template": {}
+            },
+            "status": {
+                "active": 0,
+                "succeeded": 6,
+                "failed": 2,
+                "startTime": Time(Utc::now() - Duration::hours(2)),
+            }
+        }),
+    )]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn completions_overrunning_deadline_fail() {
+    let action = completions_deadline_test_action("1h").await;
+    assert!(matches!(
+        action,
+        Ok(Action::Error(ErrorState::CompletionsDeadline))
+    ));
+    let action = completions_deadline_test_action("3h").await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+}
+
+#[test]
+fn assertions_are_evaluated_once() {
+    use testsys_model::{
+        Comparison, Outcome, ResultAssertion, ResultField, TestResults, TestStatus,
+    };
+    let mut test = Test {
+        status: Some(TestStatus {
+            agent: testsys_model::AgentStatus {
+                task_state: TaskState::Completed,
+                results: vec![TestResults {
+                    outcome: Outcome::Pass,
+                    num_passed: 98,
+                    num_skipped: 2,
+                    ..TestResults::default()
+                }],
+                ..Default::default()
+            },
+            ..TestStatus::default()
+        }),
+        ..Test::default()
+    };
+    assert_eq!(unevaluated_assertions(&test), None);
+    test.spec.assertions = vec![
+        ResultAssertion {
+            field: ResultField::NumFailed,
+            comparison: Comparison::Eq,
+            value: 0,
+        },
+        ResultAssertion {
+            field: ResultField::NumPassed,
+            comparison: Comparison::Ge,
+            value: 100,
+        },
+    ];
+    assert_eq!(
+        unevaluated_assertions(&test),
+        Some(vec!["numPassed ge 100".to_string()])
+    );
+    if let Some(status) = test.status.as_mut() {
+        status.controller.failed_assertions = Some(vec!["numPassed ge 100".to_string()]);
+    }
+    assert_eq!(unevaluated_assertions(&test), None);
+    assert_eq!(test.test_user_state(), testsys_model::TestUserState::Failed);
+}
+
+/// Determine the action for a test whose agent has scheduling gates and whose job started five
+/// minutes ago, with a pod that still has the scheduling gates in `pod_gates` and that was scheduled
+/// `scheduled_ago`, if it was scheduled.
+#[cfg(test)]
+async fn scheduling_gated_test_action(
+    pod_gates: serde_json::Value,
+    scheduled_ago: Option<k8s_openapi::chrono::Duration>,
+) -> Result<Action> {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, Utc};
+    use kube::core::ObjectMeta;
+    use testsys_model::TestStatus;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.scheduling_gates = Some(vec!["example.com/quota-approval".to_string()]);
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![
+        (
+            format!("/jobs/{}", test.job_name()),
+            serde_json::json!({
+                "apiVersion": "batch/v1",
+                "kind": "Job",
+                "metadata": { "name": test.job_name() },
+                "status": {
+                    "active": 1,
+                    "startTime": Time(Utc::now() - Duration::minutes(5)),
+                }
+            }),
+        ),
+        (
+            "/pods".to_string(),
+            crate::fake_api::pod_list(vec![serde_json::json!({
+                "metadata": { "name": format!("{}-x7k2p", test.job_name()) },
+                "spec": {
+                    "containers": [{ "name": "agent" }],
+                    "schedulingGates": pod_gates,
+                },
+                "status": {
+                    "phase": "Pending",
+                    "conditions": [match scheduled_ago {
+                        Some(ago) => serde_json::json!({
+                            "type": "PodScheduled",
+                            "status": "True",
+                            "lastTransitionTime": Time(Utc::now() - ago),
+                        }),
+                        None => serde_json::json!({
+                            "type": "PodScheduled",
+                            "status": "False",
+                            "reason": "SchedulingGated",
+                            "lastTransitionTime": Time(Utc::now() - Duration::minutes(5)),
+                        }),
+                    }],
+                }
+            })]),
+        ),
+    ]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn scheduling_gated_pod_is_not_a_failure() {
+    use k8s_openapi::chrono::Duration;
+
+    let gates = serde_json::json!([{ "name": "example.com/quota-approval" }]);
+    let action = scheduling_gated_test_action(gates, None).await;
+    assert!(matches!(action, Ok(Action::SchedulingGated)));
+    // Once the gate is removed the agent's time limits start when the pod is scheduled, not when
+    // the job started five minutes ago.
+    let action = scheduling_gated_test_action(serde_json::json!([]), None).await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+    let action =
+        scheduling_gated_test_action(serde_json::json!([]), Some(Duration::seconds(10))).await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+    // Then the pod has to start in time as usual.
+    let action =
+        scheduling_gated_test_action(serde_json::json!([]), Some(Duration::minutes(1))).await;
+    assert!(matches!(action, Ok(Action::Error(ErrorState::JobStart))));
+}
+
+/// Determine the action for a test that waits for an endpoint, whose agent has a timeout of two
+/// minutes and whose job started five minutes ago, with a pod whose endpoint wait init container is
+/// in the state `init_state` and whose agent has not reported that it is running.
+#[cfg(test)]
+async fn endpoint_wait_test_action(init_state: serde_json::Value) -> Result<Action> {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, Utc};
+    use kube::core::ObjectMeta;
+    use testsys_model::{ResourceEndpoint, TestStatus};
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agent.timeout = Some("2m".to_string());
+    test.spec.wait_for_endpoints = vec![ResourceEndpoint {
+        resource: "my-cluster".to_string(),
+        field: "endpoint".to_string(),
+    }];
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![
+        (
+            "/resources/my-cluster".to_string(),
+            serde_json::json!({
+                "apiVersion": "testsys.system/v1",
+                "kind": "Resource",
+                "metadata": { "name": "my-cluster" },
+                "spec": { "agent": { "name": "eks", "image": "eks", "keepRunning": false } },
+                "status": {
+                    "creation": { "taskState": "completed" },
+                    "destruction": { "taskState": "unknown" },
+                    "createdResource": { "endpoint": "https://my-cluster.example.com/healthz" },
+                }
+            }),
+        ),
+        (
+            format!("/jobs/{}", test.job_name()),
+            serde_json::json!({
+                "apiVersion": "batch/v1",
+                "kind": "Job",
+                "metadata": { "name": test.job_name() },
+                "status": {
+                    "active": 1,
+                    "startTime": Time(Utc::now() - Duration::minutes(5)),
+                }
+            }),
+        ),
+        (
+            "/pods".to_string(),
+            crate::fake_api::pod_list(vec![serde_json::json!({
+                "metadata": { "name": format!("{}-x7k2p", test.job_name()) },
+                "spec": {
+                    "containers": [{ "name": "agent" }],
+                    "initContainers": [{ "name": "wait-for-endpoints" }],
+                },
+                "status": {
+                    "phase": "Pending",
+                    "initContainerStatuses": [{
+                        "name": "wait-for-endpoints",
+                        "image": "busybox",
+                        "imageID": "",
+                        "ready": false,
+                        "restartCount": 0,
+                        "state": init_state,
+                    }],
+                }
+            })]),
+        ),
+    ]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn endpoint_wait_does_not_count_against_time_limits() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, Utc};
+
+    // The pod has been waiting for longer than the agent has to start, and to run.
+    let action = endpoint_wait_test_action(serde_json::json!({
+        "running": { "startedAt": Time(Utc::now() - Duration::minutes(5)) }
+    }))
+    .await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+    // The agent was released a moment ago and has time left to start.
+    let released = |ago: Duration| {
+        serde_json::json!({
+            "terminated": {
+                "exitCode": 0,
+                "finishedAt": Time(Utc::now() - ago),
+            }
+        })
+    };
+    let action = endpoint_wait_test_action(released(Duration::seconds(10))).await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+    // Once released, the agent has to start in time as usual.
+    let action = endpoint_wait_test_action(released(Duration::seconds(40))).await;
+    assert!(matches!(action, Ok(Action::Error(ErrorState::JobStart))));
+    let action = endpoint_wait_test_action(released(Duration::minutes(3))).await;
+    assert!(matches!(action, Ok(Action::Error(ErrorState::JobTimeout))));
+}
+
+/// Determine the action for a running test whose running additional agent has a timeout of two
+/// minutes and whose job started `agent_started_ago`.
+#[cfg(test)]
+async fn additional_agent_test_action(
+    agent_started_ago: k8s_openapi::chrono::Duration,
+) -> Result<Action> {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use k8s_openapi::chrono::{Duration, Utc};
+    use kube::core::ObjectMeta;
+    use testsys_model::{Agent, AgentStatus, TestStatus};
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+    test.spec.agents = vec![Agent {
+        name: "candidate".to_string(),
+        timeout: Some("2m".to_string()),
+        ..Agent::default()
+    }];
+    if let Some(status) = test.status.as_mut() {
+        status.agent.task_state = TaskState::Running;
+        status.agents.insert(
+            "candidate".to_string(),
+            AgentStatus {
+                task_state: TaskState::Running,
+                ..AgentStatus::default()
+            },
+        );
+    }
+    let job = |name: String, started_ago: Duration| {
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": name },
+            "status": {
+                "active": 1,
+                "startTime": Time(Utc::now() - started_ago),
+            }
+        })
+    };
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![
+        (
+            format!("/jobs/{}", test.job_name()),
+            job(test.job_name().to_string(), Duration::minutes(1)),
+        ),
+        (
+            format!("/jobs/{}", test.agent_job_name("candidate")),
+            job(test.agent_job_name("candidate"), agent_started_ago),
+        ),
+        ("/pods".to_string(), crate::fake_api::pod_list(vec![])),
+    ]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    determine_action(&TestInterface::new(test, context)?).await
+}
+
+#[tokio::test]
+async fn additional_agent_times_out_while_test_agent_runs() {
+    use k8s_openapi::chrono::Duration;
+
+    let action = additional_agent_test_action(Duration::minutes(1)).await;
+    assert!(matches!(action, Ok(Action::WaitForTest)));
+    let action = additional_agent_test_action(Duration::minutes(5)).await;
+    assert!(matches!(
+        action,
+        Ok(Action::AgentError {
+            error: ErrorState::JobTimeout,
+            ..
+        })
+    ));
 }
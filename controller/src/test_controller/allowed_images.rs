@@ -0,0 +1,60 @@
+/// The image prefixes that test agent images must start with, e.g. the approved registries. A
+/// prefix without a `/` is a registry host and only matches images from that host, i.e. `ecr.aws`
+/// matches `ecr.aws/agent:v1` but not `ecr.aws.example.com/agent:v1`. Every image is allowed if
+/// there are no prefixes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AllowedImages {
+    prefixes: Vec<String>,
+}
+
+impl AllowedImages {
+    pub(crate) fn new<S: AsRef<str>>(prefixes: &[S]) -> Self {
+        Self {
+            prefixes: prefixes
+                .iter()
+                .map(|prefix| prefix.as_ref().trim())
+                .filter(|prefix| !prefix.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Whether `image` may be run as a test agent.
+    pub(crate) fn allows(&self, image: &str) -> bool {
+        self.prefixes.is_empty()
+            || self.prefixes.iter().any(|prefix| {
+                if prefix.contains('/') {
+                    image.starts_with(prefix.as_str())
+                } else {
+                    image
+                        .strip_prefix(prefix.as_str())
+                        .map(|rest| rest.starts_with('/'))
+                        .unwrap_or(false)
+                }
+            })
+    }
+
+    /// The allowed prefixes, for error messages.
+    pub(crate) fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+}
+
+#[test]
+fn allowed_image_prefixes() {
+    let allowed = AllowedImages::new(&[
+        "123456789012.dkr.ecr.us-west-2.amazonaws.com",
+        " public.ecr.aws/bottlerocket-test-system/",
+    ]);
+    assert!(allowed.allows("123456789012.dkr.ecr.us-west-2.amazonaws.com/sonobuoy-test-agent:v1"));
+    assert!(allowed.allows("public.ecr.aws/bottlerocket-test-system/ec2-resource-agent:v0.0.13"));
+    assert!(!allowed.allows("docker.io/library/busybox:latest"));
+    assert!(!allowed.allows("public.ecr.aws/someone-else/agent:v1"));
+    // A registry host does not match a different host that starts with the same name.
+    assert!(!allowed.allows("123456789012.dkr.ecr.us-west-2.amazonaws.com.example.com/agent:v1"));
+}
+
+#[test]
+fn everything_allowed_without_prefixes() {
+    assert!(AllowedImages::new(&[""]).allows("docker.io/library/busybox:latest"));
+}
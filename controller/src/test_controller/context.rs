@@ -1,26 +1,180 @@
+use crate::clock::{Clock, SystemClock};
+use crate::config::ControllerConfig;
 use crate::error::Result;
-use crate::job::{archive_logs, delete_job, get_job_state, JobState};
+use crate::instance::Instance;
+use crate::job::{
+    archive_logs, delete_job, delete_job_in_foreground, get_agent_ready, get_endpoints_reached_at,
+    get_image_pull_error, get_job_age, get_job_progress, get_job_spec_hash, get_job_state,
+    get_out_of_memory, get_scheduling, get_termination_message, get_test_jobs, input_hash,
+    resolve_env, ImagePullFailures, JobBuilder, JobResult, JobSettings, JobState, JobType,
+    LogForwarder, LogSink, ReadinessChecks, Scheduling,
+};
+use crate::test_controller::allowed_images::AllowedImages;
+use crate::test_controller::debounce::{Debouncer, DEBOUNCE_WINDOW};
+use crate::test_controller::quarantine::Quarantine;
+use crate::utils::parse_duration;
 use anyhow::Context as AnyhowContext;
-use kube::{Api, Client};
-use log::error;
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::chrono::{DateTime, Duration, Utc};
+use k8s_openapi::ByteString;
+use kube::api::{DeleteParams, PostParams};
+use kube::{Api, Client, ResourceExt};
+use log::{error, warn};
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
 use std::sync::Arc;
-use testsys_model::clients::{CrdClient, TestClient};
-use testsys_model::Test;
+use testsys_model::clients::{AllowNotFound, CrdClient, TestClient};
+use testsys_model::constants::{
+    ANNOTATION_CORRELATION_ID, ENV_CORRELATION_ID, ENV_MAX_STATUS_FIELD_LEN, ENV_RESULTS_ENDPOINT,
+    ENV_TEST_AGENT_NAME, ENV_TEST_NAME, ENV_TEST_UID, NAMESPACE, RESOURCE_OUTPUTS_FILE,
+};
+use testsys_model::system::results_endpoint;
+use testsys_model::{JobProgress, Resource, Test};
 
 /// This is used by `kube-runtime` to pass any custom information we need when [`reconcile`] is
 /// called.
 pub(crate) type Context = Arc<ContextData>;
 
-pub(crate) fn new_context(client: Client) -> Context {
+pub(crate) fn new_context(client: Client, config: &ControllerConfig) -> Context {
+    let mut test_client = TestClient::new_from_k8s_client(client.clone());
+    if let Some(max_status_field_len) = config.max_status_field_len {
+        test_client = test_client.with_max_status_field_len(max_status_field_len);
+    }
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
     Arc::new(ContextData {
-        test_client: TestClient::new_from_k8s_client(client),
+        log_forwarder: config
+            .log_sink
+            .as_deref()
+            .and_then(LogSink::parse)
+            .map(|sink| LogForwarder::new(client.clone(), sink, clock.clone())),
+        test_client,
+        archive_logs: config.archive_logs,
+        quarantine: Quarantine::new(&config.quarantine),
+        allowed_images: AllowedImages::new(&config.allowed_images),
+        debouncer: Arc::new(Debouncer::new(DEBOUNCE_WINDOW, clock.clone())),
+        observe_only: config.observe_only,
+        image_pull_grace_period: image_pull_grace_period(config),
+        image_pull_failures: Default::default(),
+        readiness_checks: Default::default(),
+        max_resources_per_test: config.max_resources_per_test,
+        max_timeline_entries: config.max_timeline_entries,
+        max_timeout_extension: max_timeout_extension(config),
+        instance: Instance::new(config),
+        job_settings: JobSettings::new(config),
+        annotate_input_hash: config.annotate_input_hash,
+        results_endpoint: config
+            .results_address
+            .as_deref()
+            .filter(|_| !config.observe_only)
+            .and_then(results_endpoint),
+        clock,
+        #[cfg(feature = "cloudwatch-metrics")]
+        cloudwatch_metrics: config
+            .cloudwatch_metrics_namespace
+            .as_deref()
+            .map(|namespace| {
+                crate::cloudwatch_metrics::CloudWatchMetrics::new(
+                    namespace,
+                    config
+                        .cloudwatch_metrics_log_group
+                        .as_deref()
+                        .unwrap_or(crate::cloudwatch_metrics::DEFAULT_METRICS_LOG_GROUP),
+                )
+            }),
     })
 }
 
+/// Whether a job is deleted right away, while its pods are deleted in the background, or only once
+/// its pods are gone.
+#[derive(Debug, Clone, Copy)]
+enum JobDeletion {
+    Background,
+    Foreground,
+}
+
+/// The suffix of the name of the `Secret` with the outputs of a test's resources, after the name
+/// of the test's job.
+const RESOURCE_OUTPUTS_SUFFIX: &str = "resource-outputs";
+
+/// How long an agent's image may fail to be pulled before the test fails, five minutes unless the
+/// controller was configured otherwise.
+fn image_pull_grace_period(config: &ControllerConfig) -> Duration {
+    let default = Duration::minutes(5);
+    let grace_period = match &config.image_pull_grace_period {
+        Some(grace_period) => grace_period,
+        None => return default,
+    };
+    match parse_duration(grace_period.trim()).map(Duration::from_std) {
+        Ok(Ok(grace_period)) => grace_period,
+        _ => {
+            warn!(
+                "Invalid image pull grace period '{}', using {} instead",
+                grace_period, default
+            );
+            default
+        }
+    }
+}
+
+/// The most time, in total, that agents may add to their `timeout`. Agents cannot extend their
+/// timeout unless the controller was configured with a valid limit.
+fn max_timeout_extension(config: &ControllerConfig) -> Option<std::time::Duration> {
+    let max_extension = config.max_timeout_extension.as_ref()?;
+    match parse_duration(max_extension.trim()) {
+        Ok(max_extension) => Some(max_extension),
+        Err(_) => {
+            warn!(
+                "Invalid maximum timeout extension '{}', agents will not be able to extend their timeout",
+                max_extension
+            );
+            None
+        }
+    }
+}
+
 /// This type is wrapped by [`kube::Context`] and contains information we need during [`reconcile`].
 #[derive(Clone)]
 pub(crate) struct ContextData {
     test_client: TestClient,
+    /// Forwards the logs of running test agents if a log sink has been configured.
+    log_forwarder: Option<LogForwarder>,
+    /// Whether the logs of test agents are archived before their jobs are deleted.
+    archive_logs: bool,
+    /// Tests that are skipped instead of run.
+    quarantine: Quarantine,
+    /// The images that test agents may run.
+    allowed_images: AllowedImages,
+    /// Tests whose reconciliation can be skipped while only their status changes.
+    debouncer: Arc<Debouncer>,
+    /// Whether actions are only logged instead of taken.
+    observe_only: bool,
+    /// How long an agent's image may fail to be pulled before the test fails.
+    image_pull_grace_period: Duration,
+    /// When the agents' pods were first found unable to pull their images.
+    image_pull_failures: Arc<ImagePullFailures>,
+    /// When the readiness of the test agents was last checked.
+    readiness_checks: Arc<ReadinessChecks>,
+    /// The most resources a test may declare.
+    max_resources_per_test: Option<usize>,
+    /// The most entries kept in a test's timeline, if timelines are kept.
+    max_timeline_entries: Option<usize>,
+    /// The most time, in total, that an agent may add to its `timeout`.
+    max_timeout_extension: Option<std::time::Duration>,
+    /// The controller instance that claims the tests it reconciles.
+    instance: Instance,
+    /// The settings that agent jobs are built with.
+    job_settings: JobSettings,
+    /// Whether test agent pods are annotated with the hash of their resolved inputs.
+    annotate_input_hash: bool,
+    /// Where test agents stream their results to, if the controller serves the results endpoint.
+    results_endpoint: Option<String>,
+    /// Tells the time for the controller's time-based decisions.
+    clock: Arc<dyn Clock>,
+    /// Publishes the metrics of finished tests if a CloudWatch metrics namespace was configured.
+    #[cfg(feature = "cloudwatch-metrics")]
+    cloudwatch_metrics: Option<crate::cloudwatch_metrics::CloudWatchMetrics>,
 }
 
 impl ContextData {
@@ -29,18 +183,56 @@ impl ContextData {
     }
 }
 
+/// The `context` with its clock replaced by `clock`.
+#[cfg(test)]
+pub(crate) fn with_clock(context: &Context, clock: Arc<dyn Clock>) -> Context {
+    Arc::new(ContextData {
+        debouncer: Arc::new(Debouncer::new(DEBOUNCE_WINDOW, clock.clone())),
+        clock,
+        ..ContextData::clone(context)
+    })
+}
+
 /// The [`reconcile`] function has [`Test`] and [`Context`] as its inputs. For convenience, we
 /// combine these and provide accessor and helper functions.
 pub(crate) struct TestInterface {
     /// The cached [`Test`] object.
     test: Test,
     context: Context,
+    /// Writes the test's status, skipping the writes that would not change the cached test.
+    test_client: TestClient,
+    /// The name of the k8s `Job` that runs the test agent for the current run of the test.
+    job_name: String,
+    /// The names of the k8s `Job`s that run the additional agents in `spec.agents`, in order.
+    agent_job_names: Vec<String>,
+    /// The name of the `Secret` with the outputs of the test's resources for the current run,
+    /// if the test has resources.
+    resource_outputs_name: Option<String>,
 }
 
 impl TestInterface {
     /// Create a new `TestInterface` from the [`Test`] and [`Context`].
     pub(crate) fn new(test: Test, context: Context) -> Result<Self> {
-        Ok(Self { test, context })
+        let job_name = test.job_name();
+        let agent_job_names = test
+            .spec
+            .agents
+            .iter()
+            .map(|agent| test.agent_job_name(&agent.name))
+            .collect();
+        let resource_outputs_name = if test.spec.resources.is_empty() {
+            None
+        } else {
+            Some(format!("{}-{}", job_name, RESOURCE_OUTPUTS_SUFFIX))
+        };
+        Ok(Self {
+            test_client: context.test_client.clone().skip_unchanged(&test),
+            test,
+            context,
+            job_name,
+            agent_job_names,
+            resource_outputs_name,
+        })
     }
 
     /// Get the name of the test. In the `Test` struct the name field is optional, but in practice
@@ -54,6 +246,11 @@ impl TestInterface {
             .map_or("", |value| value.as_str())
     }
 
+    /// The name of the k8s `Job` that runs the test agent.
+    pub(crate) fn job_name(&self) -> &str {
+        &self.job_name
+    }
+
     pub(crate) fn test(&self) -> &Test {
         &self.test
     }
@@ -66,23 +263,689 @@ impl TestInterface {
         self.context.api()
     }
 
-    /// Access the inner `TestClient` object with fewer keystrokes.
+    /// Whether the test's name matches the controller's quarantine list.
+    pub(crate) fn is_quarantined(&self) -> bool {
+        self.context.quarantine.contains(self.name())
+    }
+
+    /// The reason one of the test's agent images may not be run, if one may not.
+    pub(crate) fn disallowed_image(&self) -> Option<String> {
+        let spec = &self.test.spec;
+        let image = std::iter::once(&spec.agent)
+            .chain(&spec.agents)
+            .map(|agent| &agent.image)
+            .find(|image| !self.context.allowed_images.allows(image))?;
+        Some(format!(
+            "The agent image '{}' is not allowed, images must start with one of: {}",
+            image,
+            self.context.allowed_images.prefixes().join(", ")
+        ))
+    }
+
+    /// Labels that agent jobs and pods always have.
+    pub(crate) fn protected_labels(&self) -> &BTreeMap<String, String> {
+        &self.context.job_settings.protected_labels
+    }
+
+    /// The most entries kept in the test's timeline, `None` if no timeline is kept.
+    pub(crate) fn max_timeline_entries(&self) -> Option<usize> {
+        self.context.max_timeline_entries
+    }
+
+    /// The most time, in total, that the agent may add to its `timeout`, `None` if it may not.
+    pub(crate) fn max_timeout_extension(&self) -> Option<std::time::Duration> {
+        self.context.max_timeout_extension
+    }
+
+    /// The number of resources the test declares and the controller's budget, if it declares more
+    /// than the budget allows.
+    pub(crate) fn exceeded_resource_budget(&self) -> Option<(usize, usize)> {
+        let budget = self.context.max_resources_per_test?;
+        let resources = self.test.spec.resources.len();
+        Some((resources, budget)).filter(|_| resources > budget)
+    }
+
+    /// Publish the CloudWatch metrics of the test, which has just reached a terminal state, if the
+    /// controller is configured to. Failures are logged, they do not affect the test.
+    pub(crate) async fn publish_metrics(&self, test: &Test) {
+        #[cfg(feature = "cloudwatch-metrics")]
+        if let Some(cloudwatch_metrics) = &self.context.cloudwatch_metrics {
+            let finished_at = test.finished_at().unwrap_or_else(|| self.now());
+            if let Err(e) = cloudwatch_metrics.publish(test, finished_at).await {
+                warn!(
+                    "Unable to publish CloudWatch metrics for '{}': {:?}",
+                    self.name(),
+                    e
+                );
+            }
+        }
+        #[cfg(not(feature = "cloudwatch-metrics"))]
+        let _ = test;
+    }
+
+    /// The controller instance that claims the tests it reconciles.
+    pub(crate) fn instance(&self) -> &Instance {
+        &self.context.instance
+    }
+
+    /// The ID of the other controller instance that claimed the test, if it was claimed by one.
+    pub(crate) fn other_instance(&self) -> Option<&str> {
+        self.context.instance.other_owner(&self.test)
+    }
+
+    /// Whether the controller only logs the actions it would take.
+    pub(crate) fn is_observe_only(&self) -> bool {
+        self.context.observe_only
+    }
+
+    /// Whether the test's agent has been started recently and only the test's status has changed
+    /// since, in which case there is nothing to do.
+    pub(crate) fn is_settled(&self) -> bool {
+        self.context.debouncer.is_settled(&self.test)
+    }
+
+    /// Remember that the test's agent has been started and there is nothing to do until the test's
+    /// spec changes.
+    pub(crate) fn settle(&self) {
+        self.context.debouncer.settle(&self.test)
+    }
+
+    /// Access the inner `TestClient` object with fewer keystrokes. Status writes that would not
+    /// change the cached test are skipped.
     pub(super) fn test_client(&self) -> &TestClient {
-        &self.context.test_client
+        &self.test_client
+    }
+
+    /// The current time according to the controller's clock.
+    pub(super) fn now(&self) -> DateTime<Utc> {
+        self.context.clock.now()
     }
 
     pub(super) async fn get_job_state(&self) -> Result<JobState> {
-        get_job_state(self.k8s_client(), self.name())
+        get_job_state(self.k8s_client(), self.job_name(), self.now())
             .await
             .with_context(|| format!("Unable to get job state for test '{}'", self.name()))
     }
 
+    /// The test's correlation ID, see [`correlation_id`].
+    pub(super) fn correlation_id(&self) -> String {
+        correlation_id(&self.test)
+    }
+
+    /// The builders of the jobs that run the test agent and the additional agents in
+    /// `spec.agents`, in that order. The test agent waits for the `wait_for_endpoints`, see
+    /// [`TestInterface::resolve_endpoints`].
+    pub(super) fn job_builders<'a>(
+        &'a self,
+        correlation_id: &str,
+        wait_for_endpoints: &'a [String],
+        resource_outputs: Option<&str>,
+    ) -> Result<Vec<JobBuilder<'a>>> {
+        let mut controller_variables = vec![
+            (ENV_TEST_NAME, self.name().to_owned()),
+            (
+                ENV_TEST_UID,
+                self.test.metadata.uid.clone().unwrap_or_default(),
+            ),
+            (ENV_CORRELATION_ID, correlation_id.to_owned()),
+        ];
+        // The agents truncate what they write to the test's status like the controller does.
+        if let Some(max_len) = self.test_client().max_status_field_len() {
+            controller_variables.push((ENV_MAX_STATUS_FIELD_LEN, max_len.to_string()));
+        }
+        let agents = std::iter::once((&self.test.spec.agent, &self.job_name, None)).chain(
+            self.test
+                .spec
+                .agents
+                .iter()
+                .zip(&self.agent_job_names)
+                .map(|(agent, job_name)| (agent, job_name, Some(agent.name.clone()))),
+        );
+        let mut job_builders = Vec::new();
+        for (agent, job_name, agent_name) in agents {
+            // Only the test agent is relaunched with more memory when it runs out, and waits for
+            // the test's endpoints.
+            let (memory_limit, wait_for_endpoints) = match &agent_name {
+                Some(_) => (None, &[][..]),
+                None => (
+                    self.test
+                        .status
+                        .as_ref()
+                        .and_then(|status| status.agent.memory_limits.last())
+                        .map(String::as_str),
+                    wait_for_endpoints,
+                ),
+            };
+            // The agent's `env` comes first so that it cannot override the variables that
+            // identify the test and the agent.
+            let env = resolve_env(agent, &self.test.metadata)?;
+            let input_hash = resource_outputs
+                .filter(|_| self.context.annotate_input_hash)
+                .map(|resource_outputs| input_hash(&env, resource_outputs));
+            let mut environment_variables = env;
+            environment_variables.extend(controller_variables.iter().cloned());
+            match agent_name {
+                Some(agent_name) => environment_variables.push((ENV_TEST_AGENT_NAME, agent_name)),
+                // Only the test agent streams its results, the additional agents report none.
+                None => {
+                    if let Some(results_endpoint) = &self.context.results_endpoint {
+                        environment_variables
+                            .push((ENV_RESULTS_ENDPOINT, results_endpoint.clone()));
+                    }
+                }
+            }
+            job_builders.push(JobBuilder {
+                agent,
+                job_name,
+                job_type: JobType::TestAgent,
+                environment_variables,
+                resource_outputs: self.resource_outputs_name.as_deref(),
+                settings: &self.context.job_settings,
+                memory_limit,
+                wait_for_endpoints,
+                input_hash,
+                test_uid: self.test.metadata.uid.as_deref(),
+            });
+        }
+        Ok(job_builders)
+    }
+
+    /// The URLs of the test's `wait_for_endpoints`, read from the created outputs of its resources.
+    pub(super) async fn resolve_endpoints(&self) -> Result<Vec<String>> {
+        let resource_api: Api<Resource> = Api::namespaced(self.k8s_client(), NAMESPACE);
+        let mut endpoints = Vec::new();
+        for endpoint in &self.test.spec.wait_for_endpoints {
+            let resource = resource_api
+                .get(&endpoint.resource)
+                .await
+                .with_context(|| format!("Unable to get resource '{}'", endpoint.resource))?;
+            let url = resource
+                .created_resource()
+                .and_then(|outputs| outputs.get(&endpoint.field))
+                .and_then(serde_json::Value::as_str)
+                .with_context(|| {
+                    format!(
+                        "Resource '{}' has no output '{}' with the URL to wait for",
+                        endpoint.resource, endpoint.field
+                    )
+                })?;
+            endpoints.push(url.to_owned());
+        }
+        Ok(endpoints)
+    }
+
+    /// Whether any of the test's jobs was built from a different spec than its agents would be
+    /// given now, e.g. because an agent's `env` was edited. Jobs that were not annotated with the
+    /// hash of their spec are not compared.
+    pub(super) async fn job_spec_changed(&self) -> Result<bool> {
+        let wait_for_endpoints = self.resolve_endpoints().await?;
+        for job_builder in self.job_builders(&self.correlation_id(), &wait_for_endpoints, None)? {
+            let job_name = job_builder.job_name;
+            let current = get_job_spec_hash(self.k8s_client(), job_name)
+                .await
+                .with_context(|| format!("Unable to get spec hash of job '{}'", job_name))?;
+            if matches!(current, Some(current) if current != job_builder.spec_hash()) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// How long ago the test agent's job started.
+    pub(super) async fn get_job_age(&self) -> Result<Option<Duration>> {
+        get_job_age(self.k8s_client(), self.job_name(), self.now())
+            .await
+            .with_context(|| format!("Unable to get job age for test '{}'", self.name()))
+    }
+
+    /// The progress of the test agent's job if it runs indexed completions.
+    pub(super) async fn get_job_progress(&self) -> Result<Option<JobProgress>> {
+        get_job_progress(self.k8s_client(), self.job_name())
+            .await
+            .with_context(|| format!("Unable to get job progress for test '{}'", self.name()))
+    }
+
+    /// The error pulling the image of the test agent, if it has not been pulled within the
+    /// configured grace period.
+    pub(super) async fn get_image_pull_error(&self) -> Result<Option<String>> {
+        self.image_pull_error(self.job_name())
+            .await
+            .with_context(|| format!("Unable to get the pods of test '{}'", self.name()))
+    }
+
+    /// The error pulling the image of the additional agent named `agent_name` from `spec.agents`,
+    /// if it has not been pulled within the configured grace period.
+    pub(super) async fn get_agent_image_pull_error(
+        &self,
+        agent_name: &str,
+    ) -> Result<Option<String>> {
+        self.image_pull_error(&self.test.agent_job_name(agent_name))
+            .await
+            .with_context(|| {
+                format!(
+                    "Unable to get the pods of agent '{}' of test '{}'",
+                    agent_name,
+                    self.name()
+                )
+            })
+    }
+
+    async fn image_pull_error(&self, job_name: &str) -> JobResult<Option<String>> {
+        get_image_pull_error(
+            self.k8s_client(),
+            job_name,
+            self.context.image_pull_grace_period,
+            self.now(),
+            &self.context.image_pull_failures,
+        )
+        .await
+    }
+
+    /// The message the test agent's container wrote to its termination log before it failed.
+    pub(super) async fn get_termination_message(&self) -> Result<Option<String>> {
+        get_termination_message(self.k8s_client(), self.job_name())
+            .await
+            .with_context(|| format!("Unable to get the pods of test '{}'", self.name()))
+    }
+
+    /// Whether the test agent's pod is held back by scheduling gates that have not been removed yet,
+    /// or when it was scheduled once they were.
+    pub(super) async fn get_scheduling(&self) -> Result<Scheduling> {
+        get_scheduling(self.k8s_client(), self.job_name())
+            .await
+            .with_context(|| format!("Unable to get the pods of test '{}'", self.name()))
+    }
+
+    /// When the test agent's pod finished waiting for the test's endpoints, if it has.
+    pub(super) async fn get_endpoints_reached_at(&self) -> Result<Option<DateTime<Utc>>> {
+        get_endpoints_reached_at(self.k8s_client(), self.job_name())
+            .await
+            .with_context(|| format!("Unable to get the pods of test '{}'", self.name()))
+    }
+
+    /// Whether the test agent's container was killed because it ran out of memory.
+    pub(super) async fn is_out_of_memory(&self) -> Result<bool> {
+        get_out_of_memory(self.k8s_client(), self.job_name())
+            .await
+            .with_context(|| format!("Unable to get the pods of test '{}'", self.name()))
+    }
+
+    /// Whether the test agent's container passes its readiness probe, or `None` if it was checked
+    /// too recently to be checked again.
+    pub(super) async fn is_agent_ready(&self) -> Result<Option<bool>> {
+        get_agent_ready(
+            self.k8s_client(),
+            self.job_name(),
+            &self.test.spec.agent.name,
+            self.now(),
+            &self.context.readiness_checks,
+        )
+        .await
+        .with_context(|| format!("Unable to get the pods of test '{}'", self.name()))
+    }
+
+    /// The state of the job that runs the additional agent named `agent_name` from `spec.agents`.
+    pub(super) async fn get_agent_job_state(&self, agent_name: &str) -> Result<JobState> {
+        get_job_state(
+            self.k8s_client(),
+            self.test.agent_job_name(agent_name),
+            self.now(),
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "Unable to get job state for agent '{}' of test '{}'",
+                agent_name,
+                self.name()
+            )
+        })
+    }
+
+    /// Whether any of the jobs that run the test's agents still exist, including those of agents
+    /// since removed from its spec and those that are waiting for their pods to be gone.
+    pub(super) async fn has_jobs(&self) -> Result<bool> {
+        if !matches!(self.get_job_state().await?, JobState::None) {
+            return Ok(true);
+        }
+        for agent in &self.test.spec.agents {
+            if !matches!(self.get_agent_job_state(&agent.name).await?, JobState::None) {
+                return Ok(true);
+            }
+        }
+        Ok(!self.test_jobs().await?.is_empty())
+    }
+
+    /// Start forwarding the test agent's logs if a log sink has been configured.
+    pub(super) fn forward_logs(&self) {
+        if let Some(log_forwarder) = &self.context.log_forwarder {
+            log_forwarder.start(self.name(), self.job_name());
+        }
+    }
+
+    /// Stop forwarding the test agent's logs.
+    pub(super) fn stop_forwarding_logs(&self) {
+        if let Some(log_forwarder) = &self.context.log_forwarder {
+            log_forwarder.stop(self.name());
+        }
+    }
+
+    /// The `createdResource` status of each of the test's resources, serialized as the JSON object
+    /// that is mounted in the test's agents.
+    pub(super) async fn resource_outputs(&self) -> Result<String> {
+        let resource_api: Api<Resource> = Api::namespaced(self.k8s_client(), NAMESPACE);
+        let mut outputs = serde_json::Map::new();
+        for resource_name in &self.test.spec.resources {
+            let resource = resource_api
+                .get(resource_name)
+                .await
+                .with_context(|| format!("Unable to get resource '{}'", resource_name))?;
+            outputs.insert(
+                resource_name.to_owned(),
+                resource
+                    .created_resource()
+                    .cloned()
+                    .map(serde_json::Value::Object)
+                    .unwrap_or_default(),
+            );
+        }
+        serde_json::to_string_pretty(&outputs).context("Unable to serialize resource outputs")
+    }
+
+    /// Store the resource outputs in the `Secret` that is mounted in the test's agents, replacing
+    /// the outputs of an earlier attempt to start them. The outputs are kept in a `Secret` since
+    /// they can hold credentials, e.g. a cluster's kubeconfig.
+    pub(super) async fn create_resource_outputs(&self, outputs: &str) -> Result<()> {
+        let secret_name = match &self.resource_outputs_name {
+            Some(secret_name) => secret_name,
+            None => return Ok(()),
+        };
+        let secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(secret_name.to_owned()),
+                namespace: Some(NAMESPACE.to_owned()),
+                // The outputs are deleted with the test.
+                owner_references: kube::Resource::controller_owner_ref(&self.test, &())
+                    .map(|owner| vec![owner]),
+                ..ObjectMeta::default()
+            },
+            data: Some(BTreeMap::from([(
+                RESOURCE_OUTPUTS_FILE.to_owned(),
+                ByteString(outputs.as_bytes().to_vec()),
+            )])),
+            type_: Some("Opaque".to_owned()),
+            ..Secret::default()
+        };
+        self.delete_resource_outputs().await?;
+        let secret_api: Api<Secret> = Api::namespaced(self.k8s_client(), NAMESPACE);
+        secret_api
+            .create(&PostParams::default(), &secret)
+            .await
+            .with_context(|| format!("Unable to create secret '{}'", secret_name))?;
+        Ok(())
+    }
+
+    /// Delete the `Secret` with the outputs of the test's resources for the current run.
+    async fn delete_resource_outputs(&self) -> Result<()> {
+        if let Some(secret_name) = &self.resource_outputs_name {
+            let secret_api: Api<Secret> = Api::namespaced(self.k8s_client(), NAMESPACE);
+            secret_api
+                .delete(secret_name, &DeleteParams::default())
+                .await
+                .allow_not_found(|_| ())
+                .with_context(|| format!("Unable to delete secret '{}'", secret_name))?;
+        }
+        Ok(())
+    }
+
     pub(super) async fn delete_job(&self) -> Result<()> {
-        if let Err(e) = archive_logs(self.k8s_client(), self.name()).await {
-            error!("Unable to archive logs for test '{}': {}", self.name(), e);
+        self.delete_jobs(JobDeletion::Background).await
+    }
+
+    /// Delete the test's jobs to relaunch its agents, e.g. with more memory. The jobs are deleted in
+    /// the foreground, so they are only replaced once their pods are gone and the relaunched agent
+    /// is never mistaken for the one it replaces.
+    pub(super) async fn delete_job_for_relaunch(&self) -> Result<()> {
+        self.delete_jobs(JobDeletion::Foreground).await
+    }
+
+    async fn delete_jobs(&self, deletion: JobDeletion) -> Result<()> {
+        let delete = |job_name: String| async move {
+            match deletion {
+                JobDeletion::Background => delete_job(self.k8s_client(), &job_name).await,
+                JobDeletion::Foreground => {
+                    delete_job_in_foreground(self.k8s_client(), &job_name).await
+                }
+            }
+        };
+        if self.context.archive_logs {
+            if let Err(e) = archive_logs(self.k8s_client(), self.job_name()).await {
+                error!("Unable to archive logs for test '{}': {}", self.name(), e);
+            }
         }
-        delete_job(self.k8s_client(), self.name())
+        delete(self.job_name().to_owned())
             .await
-            .with_context(|| format!("Unable to delete job for test '{}'", self.name()))
+            .with_context(|| format!("Unable to delete job for test '{}'", self.name()))?;
+        for agent in &self.test.spec.agents {
+            let job_name = self.test.agent_job_name(&agent.name);
+            if self.context.archive_logs {
+                if let Err(e) = archive_logs(self.k8s_client(), &job_name).await {
+                    error!(
+                        "Unable to archive logs for agent '{}' of test '{}': {}",
+                        agent.name,
+                        self.name(),
+                        e
+                    );
+                }
+            }
+            delete(job_name).await.with_context(|| {
+                format!(
+                    "Unable to delete job for agent '{}' of test '{}'",
+                    agent.name,
+                    self.name()
+                )
+            })?;
+        }
+        // The jobs of agents that were removed from the test's spec are deleted with the others.
+        for job_name in self.test_jobs().await? {
+            if job_name != self.job_name && !self.agent_job_names.contains(&job_name) {
+                delete(job_name.clone()).await.with_context(|| {
+                    format!(
+                        "Unable to delete job '{}' of test '{}'",
+                        job_name,
+                        self.name()
+                    )
+                })?;
+            }
+        }
+        self.delete_resource_outputs().await
+    }
+
+    /// The names of the test's jobs, including those of agents since removed from its spec.
+    async fn test_jobs(&self) -> Result<Vec<String>> {
+        match self.test.metadata.uid.as_deref() {
+            Some(test_uid) => get_test_jobs(self.k8s_client(), test_uid)
+                .await
+                .with_context(|| format!("Unable to get the jobs of test '{}'", self.name())),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn get_job_state_finds_current_run() {
+    use kube::core::ObjectMeta;
+    use testsys_model::constants::ANNOTATION_RERUN;
+
+    let previous_run = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            uid: Some("8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e".to_string()),
+            ..ObjectMeta::default()
+        },
+        ..Test::default()
+    };
+    let mut current_run = previous_run.clone();
+    current_run.metadata.annotations = Some(
+        [(ANNOTATION_RERUN.to_string(), "1".to_string())]
+            .into_iter()
+            .collect(),
+    );
+    assert_ne!(previous_run.job_name(), current_run.job_name());
+
+    // Only the job for the current run exists, and it is running.
+    let k8s_client = crate::fake_api::fake_k8s_client(vec![(
+        format!("/jobs/{}", current_run.job_name()),
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": { "name": "job" },
+            "status": { "active": 1 }
+        }),
+    )]);
+    let context = new_context(k8s_client, &ControllerConfig::default());
+
+    let job_state = |test: Test| {
+        let context = context.clone();
+        async move {
+            match TestInterface::new(test, context) {
+                Ok(t) => t.get_job_state().await,
+                Err(e) => Err(e),
+            }
+        }
+    };
+    assert!(matches!(
+        job_state(current_run).await,
+        Ok(JobState::Running(_))
+    ));
+    assert!(matches!(job_state(previous_run).await, Ok(JobState::None)));
+}
+
+#[tokio::test]
+async fn jobs_of_removed_agents_are_deleted() {
+    use k8s_openapi::api::batch::v1::Job;
+    use kube::core::ObjectMeta;
+    use testsys_model::constants::LABEL_TEST_UID;
+
+    let test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            uid: Some("8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e".to_string()),
+            ..ObjectMeta::default()
+        },
+        ..Test::default()
+    };
+    // The agent `baseline` was removed from the test's spec after its job was created.
+    let job = |name: String| {
+        serde_json::json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {
+                "name": name,
+                "namespace": NAMESPACE,
+                "labels": { LABEL_TEST_UID: "8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e" },
+            },
+        })
+    };
+    let client = crate::fake_api::fake_k8s_store(vec![
+        job(test.job_name()),
+        job(test.agent_job_name("baseline")),
+    ]);
+    let t = TestInterface::new(
+        test.clone(),
+        new_context(client.clone(), &ControllerConfig::default()),
+    );
+    let deleting = async {
+        let t = t?;
+        t.delete_job_for_relaunch().await?;
+        let api: Api<Job> = Api::namespaced(client, NAMESPACE);
+        let removed_agent_job = api.get(&test.agent_job_name("baseline")).await?;
+        Ok::<_, anyhow::Error>((
+            removed_agent_job.metadata.deletion_timestamp.is_some(),
+            t.has_jobs().await?,
+        ))
     }
+    .await;
+    // The jobs are only recreated once they are all gone.
+    assert!(matches!(deleting, Ok((true, true))));
+}
+
+#[tokio::test]
+async fn agent_env_cannot_override_identity() {
+    use kube::core::ObjectMeta;
+
+    let mut test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            uid: Some("8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e".to_string()),
+            ..ObjectMeta::default()
+        },
+        ..Test::default()
+    };
+    test.spec.agent.env = Some(BTreeMap::from([
+        (ENV_TEST_NAME.to_string(), "other-test".to_string()),
+        ("REGION".to_string(), "us-west-2".to_string()),
+    ]));
+    let client = crate::fake_api::fake_k8s_client::<String>(Vec::new());
+    let environment_variables = async {
+        let t = TestInterface::new(test, new_context(client, &ControllerConfig::default()))?;
+        let job_builders = t.job_builders("trace-1234", &[], None)?;
+        Ok::<_, anyhow::Error>(
+            job_builders
+                .into_iter()
+                .next()
+                .map(|job_builder| job_builder.environment_variables)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect::<Vec<_>>(),
+        )
+    }
+    .await;
+    // The agent's `env` comes before, and is replaced by, the variables that identify the test.
+    let test_name = |environment_variables: &[(String, String)]| {
+        environment_variables
+            .iter()
+            .rev()
+            .find(|(name, _)| name == ENV_TEST_NAME)
+            .map(|(_, value)| value.clone())
+    };
+    assert!(matches!(
+        environment_variables.as_deref(),
+        Ok([(region, _), (name, other), ..]) if region == "REGION"
+            && name == ENV_TEST_NAME && other == "other-test"
+    ));
+    assert_eq!(
+        environment_variables
+            .ok()
+            .and_then(|environment_variables| test_name(&environment_variables)),
+        Some("my-test".to_string())
+    );
+}
+
+/// The test's correlation ID, which is taken from its annotation if it has one, otherwise the one
+/// recorded in its status is reused, or a new random one is generated.
+fn correlation_id(test: &Test) -> String {
+    if let Some(correlation_id) = test.annotations().get(ANNOTATION_CORRELATION_ID) {
+        return correlation_id.clone();
+    }
+    if let Some(correlation_id) = test.correlation_id() {
+        return correlation_id.to_string();
+    }
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write(test.metadata.uid.as_deref().unwrap_or_default().as_bytes());
+        hasher.finish()
+    };
+    format!("{:016x}{:016x}", random(), random())
+}
+
+#[test]
+fn correlation_id_annotation_is_reused() {
+    let mut test = Test::new("my-test", Default::default());
+    assert_ne!(correlation_id(&test), correlation_id(&test));
+    test.metadata.annotations = Some(std::collections::BTreeMap::from([(
+        ANNOTATION_CORRELATION_ID.to_string(),
+        "trace-1234".to_string(),
+    )]));
+    assert_eq!(correlation_id(&test), "trace-1234");
 }
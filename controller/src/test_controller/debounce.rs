@@ -0,0 +1,187 @@
+use crate::clock::Clock;
+use k8s_openapi::chrono::{DateTime, Utc};
+use kube::ResourceExt;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use testsys_model::{CrdExt, TaskState, Test};
+
+/// How long after a test agent was started, or found to be running, the reconciliations caused by
+/// status updates alone are skipped.
+pub(crate) const DEBOUNCE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Test agents update their test's status frequently, and each update causes a reconciliation.
+/// While a test agent is running there is nothing for the controller to do, so the `Debouncer`
+/// remembers which tests have settled and lets reconciliations of them return early until their
+/// spec changes, one of their agents finishes or the debounce window passes.
+#[derive(Debug)]
+pub(crate) struct Debouncer {
+    window: Duration,
+    settled: Mutex<HashMap<String, Settled>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// What we knew about a test when it settled.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Settled {
+    generation: Option<i64>,
+    /// The job name changes when the test is rerun.
+    job_name: String,
+    /// The task states of the test agent and the additional agents, which the controller acts on
+    /// as soon as an agent finishes.
+    task_states: Vec<TaskState>,
+    at: DateTime<Utc>,
+}
+
+impl Debouncer {
+    pub(crate) fn new(window: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            window,
+            settled: Default::default(),
+            clock,
+        }
+    }
+
+    /// Remember that the test has settled, i.e. its agent has been started and nothing needs to
+    /// be done until it changes.
+    pub(crate) fn settle(&self, test: &Test) {
+        let settled = Settled {
+            generation: test.metadata.generation,
+            job_name: test.job_name(),
+            task_states: task_states(test),
+            at: self.clock.now(),
+        };
+        self.settled().insert(test.name_any(), settled);
+    }
+
+    /// Whether the test settled less than the debounce window ago and only its status has changed
+    /// since, without any of its agents finishing.
+    pub(crate) fn is_settled(&self, test: &Test) -> bool {
+        let mut settled = self.settled();
+        let name = test.name_any();
+        let is_settled = settled
+            .get(&name)
+            .map(|settled| {
+                (self.clock.now() - settled.at)
+                    .to_std()
+                    .map(|elapsed| elapsed < self.window)
+                    .unwrap_or(false)
+                    && settled.generation == test.metadata.generation
+                    && settled.job_name == test.job_name()
+                    && settled.task_states == task_states(test)
+                    && !test.is_delete_requested()
+                    && !test.is_archive_requested()
+            })
+            .unwrap_or(false);
+        if !is_settled {
+            settled.remove(&name);
+        }
+        is_settled
+    }
+
+    fn settled(&self) -> std::sync::MutexGuard<'_, HashMap<String, Settled>> {
+        match self.settled.lock() {
+            Ok(settled) => settled,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// The task states of the test agent followed by those of the additional agents in `spec.agents`.
+fn task_states(test: &Test) -> Vec<TaskState> {
+    std::iter::once(test.agent_status().task_state)
+        .chain(
+            test.spec
+                .agents
+                .iter()
+                .map(|agent| test.additional_agent_status(&agent.name).task_state),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+use crate::clock::{FakeClock, SystemClock};
+
+#[cfg(test)]
+fn settled_test() -> Test {
+    let mut test = Test::default();
+    test.metadata.name = Some("my-test".to_string());
+    test.metadata.generation = Some(1);
+    test
+}
+
+#[test]
+fn status_only_change_is_settled() {
+    let debouncer = Debouncer::new(DEBOUNCE_WINDOW, Arc::new(SystemClock));
+    let test = settled_test();
+    assert!(!debouncer.is_settled(&test));
+    debouncer.settle(&test);
+    let mut status_update = test.clone();
+    status_update.status = Some(Default::default());
+    assert!(debouncer.is_settled(&status_update));
+}
+
+#[test]
+fn spec_change_is_not_settled() {
+    let debouncer = Debouncer::new(DEBOUNCE_WINDOW, Arc::new(SystemClock));
+    let test = settled_test();
+    debouncer.settle(&test);
+    let mut spec_update = test.clone();
+    spec_update.metadata.generation = Some(2);
+    assert!(!debouncer.is_settled(&spec_update));
+}
+
+#[test]
+fn finished_agent_is_not_settled() {
+    use testsys_model::{Agent, AgentStatus, TestStatus};
+
+    let debouncer = Debouncer::new(DEBOUNCE_WINDOW, Arc::new(SystemClock));
+    let mut test = settled_test();
+    test.spec.agents = vec![Agent {
+        name: "baseline".to_string(),
+        ..Agent::default()
+    }];
+    let running = AgentStatus {
+        task_state: TaskState::Running,
+        ..AgentStatus::default()
+    };
+    test.status = Some(TestStatus {
+        agent: running.clone(),
+        agents: [("baseline".to_string(), running)].into_iter().collect(),
+        ..TestStatus::default()
+    });
+    debouncer.settle(&test);
+    assert!(debouncer.is_settled(&test));
+
+    // The test's outcome is written as soon as the test agent finishes.
+    let mut finished = test.clone();
+    if let Some(status) = finished.status.as_mut() {
+        status.agent.task_state = TaskState::Completed;
+    }
+    debouncer.settle(&test);
+    assert!(!debouncer.is_settled(&finished));
+
+    // So is the outcome of an additional agent.
+    let mut errored = test.clone();
+    if let Some(agent) = errored
+        .status
+        .as_mut()
+        .and_then(|status| status.agents.get_mut("baseline"))
+    {
+        agent.task_state = TaskState::Error;
+    }
+    debouncer.settle(&test);
+    assert!(!debouncer.is_settled(&errored));
+}
+
+#[test]
+fn settled_test_expires() {
+    let clock = Arc::new(FakeClock::new(Utc::now()));
+    let debouncer = Debouncer::new(DEBOUNCE_WINDOW, clock.clone());
+    let test = settled_test();
+    debouncer.settle(&test);
+    clock.advance(k8s_openapi::chrono::Duration::seconds(4));
+    assert!(debouncer.is_settled(&test));
+    clock.advance(k8s_openapi::chrono::Duration::seconds(1));
+    assert!(!debouncer.is_settled(&test));
+}
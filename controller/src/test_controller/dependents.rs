@@ -0,0 +1,184 @@
+use kube::ResourceExt;
+use kube_runtime::reflector::ObjectRef;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use testsys_model::constants::NAMESPACE;
+use testsys_model::{Test, TestUserState};
+
+/// Tests wait for the tests they depend on to pass. Instead of polling their dependencies, the
+/// `DependencyIndex` remembers which tests depend on each test as the controller watches them, so
+/// that only the dependents of a test are reconciled when the test passes, stops passing, or is
+/// deleted.
+#[derive(Debug, Default)]
+pub(crate) struct DependencyIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// The tests each test depends on.
+    depends_on: BTreeMap<String, BTreeSet<String>>,
+    /// The tests that depend on each test.
+    dependents: BTreeMap<String, BTreeSet<String>>,
+    /// The tests that passed.
+    passed: BTreeSet<String>,
+}
+
+impl DependencyIndex {
+    /// Update the index with a watch event for `test` and return the tests that need to be
+    /// reconciled because of it.
+    pub(crate) fn on_test_event(&self, test: &Test) -> Vec<ObjectRef<Test>> {
+        let name = test.name_any();
+        let mut inner = self.inner();
+        let changed = if test.metadata.deletion_timestamp.is_some() {
+            inner.remove(&name);
+            inner.passed.remove(&name);
+            true
+        } else {
+            inner.set_depends_on(&name, test.spec.depends_on.iter().flatten().cloned());
+            if test.test_user_state() == TestUserState::Passed {
+                inner.passed.insert(name.clone())
+            } else {
+                inner.passed.remove(&name)
+            }
+        };
+        if !changed {
+            return Vec::new();
+        }
+        inner
+            .dependents
+            .get(&name)
+            .into_iter()
+            .flatten()
+            .map(|dependent| ObjectRef::new(dependent).within(NAMESPACE))
+            .collect()
+    }
+
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Inner {
+    fn set_depends_on<I>(&mut self, name: &str, depends_on: I)
+    where
+        I: IntoIterator<Item = String>,
+    {
+        self.remove(name);
+        let depends_on: BTreeSet<String> = depends_on.into_iter().collect();
+        for dependency in &depends_on {
+            self.dependents
+                .entry(dependency.clone())
+                .or_default()
+                .insert(name.to_string());
+        }
+        if !depends_on.is_empty() {
+            self.depends_on.insert(name.to_string(), depends_on);
+        }
+    }
+
+    /// Forget which tests `name` depends on. Tests that depend on `name` are still remembered.
+    fn remove(&mut self, name: &str) {
+        for dependency in self.depends_on.remove(name).into_iter().flatten() {
+            if let Some(dependents) = self.dependents.get_mut(&dependency) {
+                dependents.remove(name);
+                if dependents.is_empty() {
+                    self.dependents.remove(&dependency);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use testsys_model::Outcome;
+
+#[cfg(test)]
+fn test_with(name: &str, depends_on: &[&str], outcome: Option<Outcome>) -> Test {
+    let mut test = Test::new(
+        name,
+        testsys_model::TestSpec {
+            depends_on: Some(depends_on.iter().map(|name| name.to_string()).collect()),
+            ..Default::default()
+        },
+    );
+    if let Some(outcome) = outcome {
+        test.status = Some(testsys_model::TestStatus {
+            agent: testsys_model::AgentStatus {
+                task_state: testsys_model::TaskState::Completed,
+                results: vec![testsys_model::TestResults {
+                    outcome,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+    }
+    test
+}
+
+#[test]
+fn completing_dependency_reconciles_its_dependents() {
+    let index = DependencyIndex::default();
+    let names = |refs: Vec<ObjectRef<Test>>| {
+        refs.into_iter()
+            .map(|object_ref| object_ref.name)
+            .collect::<Vec<_>>()
+    };
+    for test in [
+        test_with("setup", &[], None),
+        test_with("other", &[], None),
+        test_with("conformance", &["setup"], None),
+        test_with("workload", &["setup", "other"], None),
+        test_with("unrelated", &["other"], None),
+    ] {
+        assert!(index.on_test_event(&test).is_empty());
+    }
+
+    // Status updates that do not complete the dependency do not reconcile anything.
+    assert!(index
+        .on_test_event(&test_with("setup", &[], Some(Outcome::Fail)))
+        .is_empty());
+    assert_eq!(
+        names(index.on_test_event(&test_with("setup", &[], Some(Outcome::Pass)))),
+        vec!["conformance", "workload"]
+    );
+    // Only the change is acted on.
+    assert!(index
+        .on_test_event(&test_with("setup", &[], Some(Outcome::Pass)))
+        .is_empty());
+
+    // A dependent that no longer depends on the test is not reconciled.
+    index.on_test_event(&test_with("workload", &["other"], None));
+    assert_eq!(
+        names(index.on_test_event(&test_with("setup", &[], Some(Outcome::Fail)))),
+        vec!["conformance"]
+    );
+}
+
+#[test]
+fn dependency_with_failed_assertions_has_not_passed() {
+    let index = DependencyIndex::default();
+    assert!(index
+        .on_test_event(&test_with("conformance", &["setup"], None))
+        .is_empty());
+
+    // The agent passed, but the controller found that the results fail the test's assertions.
+    let mut setup = test_with("setup", &[], Some(Outcome::Pass));
+    if let Some(status) = setup.status.as_mut() {
+        status.controller.failed_assertions = Some(vec!["numPassed ge 100".to_string()]);
+    }
+    assert!(index.on_test_event(&setup).is_empty());
+    assert_eq!(
+        index
+            .on_test_event(&test_with("setup", &[], Some(Outcome::Pass)))
+            .into_iter()
+            .map(|object_ref| object_ref.name)
+            .collect::<Vec<_>>(),
+        vec!["conformance"]
+    );
+}
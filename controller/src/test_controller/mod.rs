@@ -1,7 +1,12 @@
+use crate::config::ControllerConfig;
 use crate::constants::requeue;
 use crate::error::ReconciliationError;
+use crate::instance::Instance;
+use crate::resync::resync;
 use crate::test_controller::context::{new_context, Context};
+use crate::test_controller::dependents::DependencyIndex;
 use crate::test_controller::reconcile::reconcile;
+use crate::test_controller::validation::{is_decode_error, mark_undecodable_tests};
 use futures::StreamExt;
 use kube_runtime::controller::Action as RequeueAction;
 use kube_runtime::{controller, watcher, Controller};
@@ -10,15 +15,43 @@ use std::sync::Arc;
 use testsys_model::Test;
 
 mod action;
+mod allowed_images;
 mod context;
+mod debounce;
+mod dependents;
+mod oom_retry;
+mod preflight;
+mod quarantine;
 mod reconcile;
+mod validation;
 
-pub(super) async fn run_test_controller(client: kube::Client) {
-    let context = new_context(client);
-    Controller::new(context.api().clone(), watcher::Config::default())
+pub(crate) use validation::{invalid_agent, overridden_protected_label, unsupported_oom_retry};
+
+pub(super) async fn run_test_controller(client: kube::Client, config: &ControllerConfig) {
+    let context = new_context(client.clone(), config);
+    let list_params = Instance::new(config).list_params();
+    // Reconcile the dependents of a test when it passes instead of having them poll it.
+    let dependency_index = DependencyIndex::default();
+    // Every test is reconciled again once an expired watch has restarted.
+    let (resync, resync_trigger) = resync();
+    Controller::new(context.api().clone(), watcher_config(config))
+        .watches(context.api().clone(), watcher_config(config), move |test| {
+            dependency_index.on_test_event(&test)
+        })
+        .reconcile_all_on(resync_trigger)
         .run(reconcile, handle_reconciliation_error, context)
-        .for_each(|reconciliation_result| async move {
+        .for_each(|reconciliation_result| async {
             if let Err(reconciliation_err) = reconciliation_result {
+                if resync.on_error(&reconciliation_err) {
+                    return;
+                }
+                if let controller::Error::QueueError(watcher_err) = &reconciliation_err {
+                    if is_decode_error(watcher_err) {
+                        if let Err(e) = mark_undecodable_tests(client.clone(), &list_params).await {
+                            error!("{:?}", e);
+                        }
+                    }
+                }
                 match &reconciliation_err {
                     controller::Error::ObjectNotFound { .. } => {
                         debug!("Object is gone: {}", reconciliation_err)
@@ -30,8 +63,33 @@ pub(super) async fn run_test_controller(client: kube::Client) {
         .await;
 }
 
+/// The configuration of the watches on tests, which only list tests that match the controller's
+/// label selector if it has one.
+fn watcher_config(config: &ControllerConfig) -> watcher::Config {
+    match config.test_label_selector() {
+        Some(selector) => watcher::Config::default().labels(selector),
+        None => watcher::Config::default(),
+    }
+}
+
 /// `handle_reconciliation_error` is called when `reconcile` returns an error.
 fn handle_reconciliation_error(_: Arc<Test>, e: &ReconciliationError, _: Context) -> RequeueAction {
     error!("Reconciliation error: {}", e);
     requeue()
 }
+
+#[test]
+fn watcher_label_selector() {
+    let config = ControllerConfig {
+        label_selector: Some(" team=platform,tier!=canary ".to_string()),
+        ..ControllerConfig::default()
+    };
+    assert_eq!(
+        watcher_config(&config).label_selector.as_deref(),
+        Some("team=platform,tier!=canary")
+    );
+    assert_eq!(
+        watcher_config(&ControllerConfig::default()).label_selector,
+        None
+    );
+}
@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+use testsys_model::{Qos, Test};
+
+/// The factor the memory limit of an agent is multiplied by when its `oom_retry` does not give one.
+const DEFAULT_FACTOR: u32 = 2;
+
+/// The memory limits a test agent that ran out of memory is relaunched with, the ones it was
+/// relaunched with before and the new one last, or `None` if it does not opt in to `oom_retry`, has
+/// no memory limit, or already ran with its `max_memory`.
+pub(super) fn next_memory_limits(test: &Test) -> Option<Vec<String>> {
+    let agent = &test.spec.agent;
+    let oom_retry = agent.oom_retry.as_ref()?;
+    let mut memory_limits = test.agent_status().memory_limits.clone();
+    let current = match memory_limits.last() {
+        Some(memory_limit) => Cow::Borrowed(memory_limit),
+        None => {
+            let mut container_resources = Cow::Borrowed(agent.container_resources.as_ref()?);
+            if agent.qos == Some(Qos::Guaranteed) {
+                container_resources = Cow::Owned(container_resources.guaranteed());
+            }
+            Cow::Owned(
+                container_resources
+                    .limits
+                    .as_ref()?
+                    .get("memory")?
+                    .to_owned(),
+            )
+        }
+    };
+    let current = parse_memory(&current)?;
+    let max = parse_memory(&oom_retry.max_memory)?;
+    let next = current
+        .saturating_mul(u64::from(oom_retry.factor.unwrap_or(DEFAULT_FACTOR)))
+        .min(max);
+    if next <= current {
+        return None;
+    }
+    memory_limits.push(format_memory(next));
+    Some(memory_limits)
+}
+
+/// The number of bytes of a memory quantity, e.g. `512Mi` or `1G`.
+fn parse_memory(quantity: &str) -> Option<u64> {
+    let quantity = quantity.trim();
+    let number_len = quantity
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(number_len);
+    let multiplier: u64 = match suffix {
+        "" => 1,
+        "k" => 1000,
+        "M" => 1000_u64.pow(2),
+        "G" => 1000_u64.pow(3),
+        "T" => 1000_u64.pow(4),
+        "P" => 1000_u64.pow(5),
+        "E" => 1000_u64.pow(6),
+        "Ki" => 1 << 10,
+        "Mi" => 1 << 20,
+        "Gi" => 1 << 30,
+        "Ti" => 1 << 40,
+        "Pi" => 1 << 50,
+        "Ei" => 1 << 60,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier as f64) as u64)
+}
+
+/// A memory quantity of `bytes` in the largest binary unit that represents it exactly.
+fn format_memory(bytes: u64) -> String {
+    for (suffix, shift) in [
+        ("Ei", 60),
+        ("Pi", 50),
+        ("Ti", 40),
+        ("Gi", 30),
+        ("Mi", 20),
+        ("Ki", 10),
+    ] {
+        if bytes >= 1 << shift && bytes & ((1 << shift) - 1) == 0 {
+            return format!("{}{}", bytes >> shift, suffix);
+        }
+    }
+    bytes.to_string()
+}
+
+#[test]
+fn memory_quantities() {
+    assert_eq!(parse_memory("512Mi"), Some(512 << 20));
+    assert_eq!(parse_memory("1.5Gi"), Some(3 << 29));
+    assert_eq!(parse_memory("1G"), Some(1_000_000_000));
+    assert_eq!(parse_memory("1024"), Some(1024));
+    assert_eq!(parse_memory("lots"), None);
+    assert_eq!(format_memory(3 << 29), "1536Mi");
+    assert_eq!(format_memory(2 << 30), "2Gi");
+    assert_eq!(format_memory(1_000_000_000), "1000000000");
+}
+
+#[test]
+fn memory_is_bumped_up_to_the_max() {
+    use std::collections::BTreeMap;
+    use testsys_model::{ContainerResources, OomRetry, TestStatus};
+
+    let mut test = Test::default();
+    test.spec.agent.container_resources = Some(ContainerResources {
+        limits: Some(BTreeMap::from([("memory".to_string(), "1Gi".to_string())])),
+        requests: None,
+    });
+    // Agents that do not opt in fail as usual.
+    assert_eq!(next_memory_limits(&test), None);
+
+    test.spec.agent.oom_retry = Some(OomRetry {
+        factor: None,
+        max_memory: "3Gi".to_string(),
+    });
+    test.status = Some(TestStatus::default());
+    let mut bumps = Vec::new();
+    while let Some(memory_limits) = next_memory_limits(&test) {
+        bumps = memory_limits.clone();
+        if let Some(status) = test.status.as_mut() {
+            status.agent.memory_limits = memory_limits;
+        }
+    }
+    assert_eq!(bumps, vec!["2Gi".to_string(), "3Gi".to_string()]);
+}
@@ -0,0 +1,135 @@
+use crate::error::Result;
+use anyhow::Context;
+use k8s_openapi::api::apps::v1::{DaemonSet, Deployment};
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::Api;
+use log::trace;
+use testsys_model::clients::AllowNotFound;
+
+/// Well-known capabilities and the k8s object whose existence shows the capability is present.
+const KNOWN_CAPABILITIES: &[(&str, &str)] = &[
+    (
+        "ebs-csi-driver",
+        "deployment/kube-system/ebs-csi-controller",
+    ),
+    (
+        "efs-csi-driver",
+        "deployment/kube-system/efs-csi-controller",
+    ),
+    (
+        "vsphere-csi-driver",
+        "deployment/vmware-system-csi/vsphere-csi-controller",
+    ),
+];
+
+/// A cluster capability that a test can require, represented by the k8s object that provides it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Capability {
+    Crd(String),
+    Deployment { namespace: String, name: String },
+    DaemonSet { namespace: String, name: String },
+}
+
+impl Capability {
+    /// Parse a capability from either a well-known capability name or an object reference like
+    /// `crd/<name>`, `deployment/<namespace>/<name>` or `daemonset/<namespace>/<name>`.
+    fn parse(capability: &str) -> Option<Self> {
+        let reference = KNOWN_CAPABILITIES
+            .iter()
+            .find(|(name, _)| *name == capability)
+            .map(|(_, reference)| *reference)
+            .unwrap_or(capability);
+        let parts: Vec<&str> = reference.split('/').collect();
+        match parts.as_slice() {
+            ["crd", name] => Some(Self::Crd(name.to_string())),
+            ["deployment", namespace, name] => Some(Self::Deployment {
+                namespace: namespace.to_string(),
+                name: name.to_string(),
+            }),
+            ["daemonset", namespace, name] => Some(Self::DaemonSet {
+                namespace: namespace.to_string(),
+                name: name.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Check whether the object providing this capability exists in the cluster.
+    async fn is_present(&self, k8s_client: kube::Client) -> Result<bool> {
+        let found = match self {
+            Capability::Crd(name) => Api::<CustomResourceDefinition>::all(k8s_client)
+                .get(name)
+                .await
+                .allow_not_found(|_| ())?
+                .is_some(),
+            Capability::Deployment { namespace, name } => {
+                Api::<Deployment>::namespaced(k8s_client, namespace)
+                    .get(name)
+                    .await
+                    .allow_not_found(|_| ())?
+                    .is_some()
+            }
+            Capability::DaemonSet { namespace, name } => {
+                Api::<DaemonSet>::namespaced(k8s_client, namespace)
+                    .get(name)
+                    .await
+                    .allow_not_found(|_| ())?
+                    .is_some()
+            }
+        };
+        Ok(found)
+    }
+}
+
+/// Check each of the `required` capabilities and return a description of every capability that is
+/// missing from the cluster or cannot be understood.
+pub(super) async fn missing_capabilities(
+    k8s_client: kube::Client,
+    required: &[String],
+) -> Result<Vec<String>> {
+    let mut missing = Vec::new();
+    for required_capability in required {
+        match Capability::parse(required_capability) {
+            None => missing.push(format!("'{}' (unknown capability)", required_capability)),
+            Some(capability) => {
+                trace!("Checking for capability '{}'", required_capability);
+                if !capability
+                    .is_present(k8s_client.clone())
+                    .await
+                    .with_context(|| {
+                        format!("Unable to check for capability '{}'", required_capability)
+                    })?
+                {
+                    missing.push(format!("'{}'", required_capability));
+                }
+            }
+        }
+    }
+    Ok(missing)
+}
+
+#[test]
+fn parse_capabilities() {
+    assert_eq!(
+        Capability::parse("ebs-csi-driver"),
+        Some(Capability::Deployment {
+            namespace: "kube-system".to_string(),
+            name: "ebs-csi-controller".to_string()
+        })
+    );
+    assert_eq!(
+        Capability::parse("crd/volumesnapshots.snapshot.storage.k8s.io"),
+        Some(Capability::Crd(
+            "volumesnapshots.snapshot.storage.k8s.io".to_string()
+        ))
+    );
+    assert_eq!(
+        Capability::parse("daemonset/kube-system/aws-node"),
+        Some(Capability::DaemonSet {
+            namespace: "kube-system".to_string(),
+            name: "aws-node".to_string()
+        })
+    );
+    assert_eq!(Capability::parse("deployment/missing-namespace"), None);
+    assert_eq!(Capability::parse("gpu"), None);
+}
@@ -0,0 +1,70 @@
+/// Glob patterns of the names of tests that are known to be broken. The controller skips matching
+/// tests instead of running them. A `*` in a pattern matches any number of characters and a `?`
+/// matches exactly one.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Quarantine {
+    patterns: Vec<String>,
+}
+
+impl Quarantine {
+    pub(crate) fn new<S: AsRef<str>>(patterns: &[S]) -> Self {
+        Self {
+            patterns: patterns
+                .iter()
+                .map(|pattern| pattern.as_ref().trim())
+                .filter(|pattern| !pattern.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Whether the test named `test_name` matches any of the quarantine patterns.
+    pub(crate) fn contains(&self, test_name: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| glob_match(pattern.as_bytes(), test_name.as_bytes()))
+    }
+}
+
+/// Match `name` against a glob `pattern` that supports `*` and `?` wildcards.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` in the pattern and the position in the name it was tried at.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` match one more character and try again.
+                Some((star, star_n)) => {
+                    backtrack = Some((star, star_n + 1));
+                    p = star + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+#[test]
+fn quarantine_patterns() {
+    let quarantine = Quarantine::new(&["sonobuoy-*-flaky", " exact-test", "migration-?"]);
+    assert!(quarantine.contains("sonobuoy-aws-flaky"));
+    assert!(quarantine.contains("sonobuoy--flaky"));
+    assert!(quarantine.contains("exact-test"));
+    assert!(quarantine.contains("migration-1"));
+    assert!(!quarantine.contains("sonobuoy-aws"));
+    assert!(!quarantine.contains("exact-test-2"));
+    assert!(!quarantine.contains("migration-12"));
+    assert!(!Quarantine::new(&[""]).contains("any-test"));
+    assert!(Quarantine::new(&["*"]).contains("any-test"));
+}
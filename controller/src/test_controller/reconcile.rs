@@ -1,16 +1,17 @@
 use crate::constants::{no_requeue, requeue, requeue_slow};
 use crate::error::{ReconciliationResult, Result};
-use crate::job::{JobBuilder, JobType};
+use crate::finalizer::{add_finalizer, remove_finalizer};
 use crate::test_controller::action::{determine_action, Action};
 use crate::test_controller::context::{Context, TestInterface};
+use crate::version::{kube_server_version, CONTROLLER_VERSION};
 use anyhow::Context as AnyhowContext;
 use kube_runtime::controller::Action as RequeueAction;
-use log::{debug, error, trace};
+use log::{debug, error, info, trace};
 use std::ops::Deref;
 use std::sync::Arc;
-use testsys_model::clients::CrdClient;
-use testsys_model::constants::{ENV_TEST_NAME, FINALIZER_MAIN, FINALIZER_TEST_JOB};
-use testsys_model::{TaskState, Test};
+use testsys_model::clients::{CrdClient, ResourceClient};
+use testsys_model::constants::{FINALIZER_MAIN, FINALIZER_TEST_JOB};
+use testsys_model::{Completions, CrdExt, Outcome, Test, TestResults};
 
 /// `reconcile` is called when a new `Test` object arrives, or when a `Test` object has been
 /// re-queued. This is the entrypoint to the controller logic.
@@ -19,9 +20,47 @@ pub(crate) async fn reconcile(
     context: Context,
 ) -> ReconciliationResult<RequeueAction> {
     let mut t = TestInterface::new(t.deref().clone(), context)?;
+    // Tests claimed by other controller instances are left to them entirely.
+    if let Some(owner) = t.other_instance() {
+        trace!(
+            "Ignoring '{}', it is reconciled by controller instance '{}'",
+            t.name(),
+            owner
+        );
+        return Ok(no_requeue());
+    }
+    if t.is_settled() {
+        trace!(
+            "Test '{}' has settled, skipping status-only update",
+            t.name()
+        );
+        return Ok(requeue());
+    }
     let action = determine_action(&t).await?;
     trace!("action {:?}", action);
+    if t.is_observe_only() {
+        info!(
+            "Observe only, not taking action {:?} for test '{}'",
+            action,
+            t.name()
+        );
+        return Ok(requeue_slow());
+    }
+    update_conditions(&t).await?;
+    update_timeline(&t).await?;
     match action {
+        Action::ClaimForInstance(instance_id) => {
+            t.test_client()
+                .claim_for_instance(t.test(), &instance_id)
+                .await
+                .context(format!(
+                    "Unable to claim '{}' for controller instance '{}'",
+                    t.name(),
+                    instance_id
+                ))?;
+            Ok(requeue())
+        }
+        Action::Scheduled => Ok(no_requeue()),
         Action::Initialize => {
             t.test_client()
                 .initialize_status(t.name())
@@ -29,14 +68,93 @@ pub(crate) async fn reconcile(
                 .context(format!("Unable to initialize status for '{}'", t.name()))?;
             Ok(requeue())
         }
+        Action::Suspended => {
+            debug!("Test '{}' is suspended", t.name());
+            Ok(no_requeue())
+        }
         // Action::Acknowledge => acknowledge_new_test(&mut test).await,
-        Action::AddMainFinalizer => {
+        Action::Quarantine => {
+            debug!("Test '{}' is quarantined and will not be run", t.name());
             t.test_client()
-                .add_finalizer(FINALIZER_MAIN, t.test())
+                .send_quarantined(t.name())
+                .await
+                .context(format!("Unable to mark '{}' as quarantined", t.name()))?;
+            record_finished(&t).await?;
+            Ok(requeue())
+        }
+        Action::Quarantined => Ok(no_requeue()),
+        Action::AddMainFinalizer => {
+            add_finalizer(t.test_client(), FINALIZER_MAIN, t.test(), t.now())
                 .await
                 .context(format!("Unable to add main finalizer for '{}'", t.name()))?;
             Ok(requeue())
         }
+        Action::ObserveGeneration(generation) => {
+            // Status fields from an earlier generation of the spec are stale, but there is nothing
+            // to reset the first time a test is seen.
+            let reset = t.test().observed_generation().is_some();
+            if reset {
+                debug!(
+                    "Spec of '{}' changed to generation {}, resetting controller status",
+                    t.name(),
+                    generation
+                );
+            }
+            t.test_client()
+                .send_observed_generation(t.name(), generation, reset)
+                .await
+                .context(format!(
+                    "Unable to record observed generation for '{}'",
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::CopyMetadata => {
+            t.test_client()
+                .send_metadata(t.name(), &t.test().spec.metadata)
+                .await
+                .context(format!(
+                    "Unable to copy metadata to status for '{}'",
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::InvalidSpec(reason) => {
+            error!("Test '{}' has an invalid spec: {}", t.name(), reason);
+            t.test_client()
+                .send_invalid_spec(t.name(), &reason)
+                .await
+                .context(format!("Unable to record invalid spec for '{}'", t.name()))?;
+            record_finished(&t).await?;
+            Ok(no_requeue())
+        }
+        Action::InvalidSpecRecorded => Ok(no_requeue()),
+        Action::UpdateResourceSummaries(summaries) => {
+            t.test_client()
+                .send_resource_summaries(t.name(), &summaries)
+                .await
+                .context(format!(
+                    "Unable to update resource summaries for '{}'",
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::AddResourceReference(resource_name) => {
+            let resource_client = ResourceClient::new_from_k8s_client(t.k8s_client());
+            let resource = resource_client
+                .get(&resource_name)
+                .await
+                .context(format!("Unable to get resource '{}'", resource_name))?;
+            resource_client
+                .add_reference(&resource, t.name())
+                .await
+                .context(format!(
+                    "Unable to add reference of '{}' to resource '{}'",
+                    t.name(),
+                    resource_name
+                ))?;
+            Ok(requeue())
+        }
         Action::WaitForResources => Ok(requeue()),
         Action::RegisterResourceCreationError(msg) => {
             t.test_client()
@@ -49,33 +167,274 @@ pub(crate) async fn reconcile(
                 ))?;
             Ok(requeue())
         }
-        Action::WaitForDependency(_) => Ok(requeue()),
-        Action::AddJobFinalizer => {
+        // The dependency index reconciles the test when its dependency passes.
+        Action::WaitForDependency(_) => Ok(requeue_slow()),
+        Action::PreflightFailed(msg) => {
             t.test_client()
-                .add_finalizer(FINALIZER_TEST_JOB, t.test())
+                .send_preflight_error(t.name(), &msg)
+                .await
+                .context(format!(
+                    "Unable to register preflight error '{}' for '{}'",
+                    msg,
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::AddJobFinalizer => {
+            add_finalizer(t.test_client(), FINALIZER_TEST_JOB, t.test(), t.now())
                 .await
                 .context(format!("Unable to add job finalizer for '{}'", t.name()))?;
             Ok(requeue())
         }
         Action::StartTest => {
-            create_job(&mut t).await?;
+            // Record which controller and cluster produce the results of this run before it
+            // starts, so that the test is not started until they are recorded. Once the job
+            // exists the test is not started again, and they would never be recorded.
+            let kube_server_version = kube_server_version(&t.k8s_client()).await;
+            t.test_client()
+                .send_versions(t.name(), CONTROLLER_VERSION, kube_server_version.as_deref())
+                .await
+                .context(format!("Unable to send versions for '{}'", t.name()))?;
+            if let Err(e) = create_job(&mut t).await {
+                // Record the failure so that the circuit breaker can stop retrying.
+                t.test_client()
+                    .send_job_creation_failures(t.name(), t.test().job_creation_failures() + 1)
+                    .await
+                    .context(format!(
+                        "Unable to record job creation failure for '{}'",
+                        t.name()
+                    ))?;
+                return Err(e.into());
+            }
+            // Only consecutive failures open the circuit.
+            if t.test().job_creation_failures() > 0 {
+                t.test_client()
+                    .send_job_creation_failures(t.name(), 0)
+                    .await
+                    .context(format!(
+                        "Unable to reset job creation failures for '{}'",
+                        t.name()
+                    ))?;
+            }
+            t.settle();
+            Ok(requeue())
+        }
+        Action::RecreateJob => {
+            info!(
+                "The agents of test '{}' changed before they started, recreating its jobs",
+                t.name()
+            );
+            t.stop_forwarding_logs();
+            t.delete_job_for_relaunch().await?;
+            Ok(requeue())
+        }
+        Action::RelaunchWithMoreMemory(memory_limits) => {
+            info!(
+                "Test '{}' ran out of memory, relaunching it with a memory limit of {}",
+                t.name(),
+                memory_limits.last().map(String::as_str).unwrap_or_default()
+            );
+            t.test_client()
+                .send_memory_limits(t.name(), &memory_limits)
+                .await
+                .context(format!("Unable to send memory limits for '{}'", t.name()))?;
+            t.stop_forwarding_logs();
+            t.delete_job_for_relaunch().await?;
+            Ok(requeue())
+        }
+        Action::SchedulingGated => Ok(requeue()),
+        Action::WaitForTest => {
+            t.forward_logs();
+            t.settle();
+            Ok(requeue())
+        }
+        Action::RecordTerminationMessage(message) => {
+            t.test_client()
+                .send_termination_message(t.name(), &message)
+                .await
+                .context(format!(
+                    "Unable to send termination message for '{}'",
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::RecordFailedAssertions(failed) => {
+            if !failed.is_empty() {
+                debug!(
+                    "Test '{}' failed assertions: {}",
+                    t.name(),
+                    failed.join(", ")
+                );
+            }
+            t.test_client()
+                .send_failed_assertions(t.name(), &failed)
+                .await
+                .context(format!(
+                    "Unable to send failed assertions for '{}'",
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::UpdateProgress(progress) => {
+            trace!("Test '{}' has {}", t.name(), progress);
+            t.test_client()
+                .send_progress(t.name(), progress)
+                .await
+                .context(format!("Unable to send progress for '{}'", t.name()))?;
+            Ok(requeue())
+        }
+        Action::UpdateReadiness(ready) => {
+            trace!("Test '{}' agent ready: {}", t.name(), ready);
+            t.test_client()
+                .send_agent_ready(t.name(), ready)
+                .await
+                .context(format!("Unable to send readiness for '{}'", t.name()))?;
+            Ok(requeue())
+        }
+        Action::GrantExtension(total_seconds) => {
+            info!(
+                "Extending the timeout of test '{}' by {}s in total",
+                t.name(),
+                total_seconds
+            );
+            t.test_client()
+                .send_timeout_extension(t.name(), total_seconds)
+                .await
+                .context(format!(
+                    "Unable to send timeout extension for '{}'",
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::RejectExtension(reason) => {
+            info!(
+                "Rejecting the timeout extension of test '{}': {}",
+                t.name(),
+                reason
+            );
+            t.test_client()
+                .send_rejected_extension(t.name(), &reason)
+                .await
+                .context(format!(
+                    "Unable to send rejected extension for '{}'",
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::CompletionsDone {
+            completions,
+            passed,
+        } => {
+            let Completions { succeeded, failed } = completions;
+            debug!(
+                "Test '{}' finished with {} succeeded and {} failed completions",
+                t.name(),
+                succeeded,
+                failed
+            );
+            t.stop_forwarding_logs();
+            let results = TestResults {
+                outcome: if passed { Outcome::Pass } else { Outcome::Fail },
+                num_passed: succeeded.max(0) as u64,
+                num_failed: failed.max(0) as u64,
+                num_skipped: 0,
+                other_info: Some(format!(
+                    "{} of {} completions succeeded, {}% were required",
+                    succeeded,
+                    succeeded + failed,
+                    t.test().spec.agent.success_threshold_percent.unwrap_or(100)
+                )),
+            };
+            t.test_client()
+                .send_completions(t.name(), completions, results)
+                .await
+                .context(format!("Unable to send completions for '{}'", t.name()))?;
+            Ok(requeue())
+        }
+        Action::AgentError { agent_name, error } => {
+            error!(
+                "Error state for agent '{}' of test '{}': {}",
+                agent_name,
+                t.name(),
+                error
+            );
+            t.test_client()
+                .clone()
+                .for_agent(&agent_name)
+                .set_agent_error(t.test(), &error.to_string())
+                .await
+                .context(format!(
+                    "Unable to send error message for agent '{}' of '{}'",
+                    agent_name,
+                    t.name()
+                ))?;
             Ok(requeue())
         }
-        Action::WaitForTest => Ok(requeue()),
         Action::DeleteJob => {
+            t.stop_forwarding_logs();
             t.delete_job().await?;
             Ok(requeue())
         }
+        Action::RemoveResourceReference(resource_name) => {
+            debug!(
+                "Removing reference of test '{}' from resource '{}'",
+                t.name(),
+                resource_name
+            );
+            let resource_client = ResourceClient::new_from_k8s_client(t.k8s_client());
+            let resource = resource_client
+                .get(&resource_name)
+                .await
+                .context(format!("Unable to get resource '{}'", resource_name))?;
+            resource_client
+                .remove_reference(&resource, t.name())
+                .await
+                .context(format!(
+                    "Unable to remove reference of '{}' from resource '{}'",
+                    t.name(),
+                    resource_name
+                ))?;
+            Ok(requeue())
+        }
+        Action::DestroyResource(resource_name) => {
+            debug!(
+                "Destroying resource '{}' of test '{}'",
+                resource_name,
+                t.name()
+            );
+            ResourceClient::new_from_k8s_client(t.k8s_client())
+                .delete(&resource_name)
+                .await
+                .context(format!(
+                    "Unable to delete resource '{}' of '{}'",
+                    resource_name,
+                    t.name()
+                ))?;
+            Ok(requeue())
+        }
+        Action::WaitForResourceDestruction(resource_name) => {
+            trace!(
+                "Waiting for resource '{}' of '{}' to be destroyed",
+                resource_name,
+                t.name()
+            );
+            Ok(requeue())
+        }
         Action::RemoveJobFinalizer => {
-            t.test_client()
-                .remove_finalizer(FINALIZER_TEST_JOB, t.test())
+            remove_finalizer(t.test_client(), FINALIZER_TEST_JOB, t.test(), t.now())
                 .await
                 .context(format!("Unable to remove job finalizer for '{}'", t.name()))?;
             Ok(requeue())
         }
+        Action::WaitForForeignFinalizers => {
+            trace!(
+                "Waiting for finalizers of other controllers to be removed from '{}'",
+                t.name()
+            );
+            Ok(requeue())
+        }
         Action::RemoveMainFinalizer => {
-            t.test_client()
-                .remove_finalizer(FINALIZER_MAIN, t.test())
+            remove_finalizer(t.test_client(), FINALIZER_MAIN, t.test(), t.now())
                 .await
                 .context(format!(
                     "Unable to remove main finalizer for '{}'",
@@ -83,28 +442,50 @@ pub(crate) async fn reconcile(
                 ))?;
             Ok(no_requeue())
         }
+        Action::Archive => {
+            debug!("Test '{}' is archived", t.name());
+            t.test_client()
+                .send_archived(t.name())
+                .await
+                .context(format!("Unable to mark '{}' as archived", t.name()))?;
+            record_finished(&t).await?;
+            Ok(no_requeue())
+        }
+        Action::Archived => Ok(no_requeue()),
         Action::TestDone => {
             debug!("Test '{}' is done", t.name());
+            t.stop_forwarding_logs();
+            record_finished(&t).await?;
             Ok(requeue_slow())
         }
         Action::Error(state) => {
             error!("Error state for test '{}': {}", t.name(), state);
+            t.stop_forwarding_logs();
+            // The error is sent again each time the test is reconciled until it is resolved, only
+            // changes to it are written.
             t.test_client()
-                .send_agent_task_state(t.name(), TaskState::Error)
-                .await
-                .context(format!(
-                    "Unable to send error task state for '{}'",
-                    t.name()
-                ))?;
-            t.test_client()
-                .send_agent_error(t.name(), &state.to_string())
+                .set_agent_error(t.test(), &state.to_string())
                 .await
                 .context(format!("Unable to send error message for '{}'", t.name()))?;
+            record_finished(&t).await?;
             Ok(requeue_slow())
         }
     }
 }
 
+/// Record when the test reached a terminal state unless we already have.
+async fn record_finished(t: &TestInterface) -> Result<()> {
+    if t.test().finished_at().is_none() {
+        let test = t
+            .test_client()
+            .send_finished_at(t.name())
+            .await
+            .context(format!("Unable to send finished time for '{}'", t.name()))?;
+        t.publish_metrics(&test).await;
+    }
+    Ok(())
+}
+
 /// Runs a k8s `Job` to run our test pod. Adds the pod finalizer to ensure we don't forget to clean
 /// up the `Job` later.
 ///
@@ -113,15 +494,1216 @@ pub(crate) async fn reconcile(
 /// Assumes that the pod finalizer is not present. If it is, A duplicate finalizer error will occur.
 ///
 pub(crate) async fn create_job(t: &mut TestInterface) -> Result<()> {
-    debug!("Creating test job '{}'", t.name());
-    JobBuilder {
-        agent: &t.test().spec.agent,
-        job_name: t.name(),
-        job_type: JobType::TestAgent,
-        environment_variables: vec![(ENV_TEST_NAME, t.name().to_owned())],
-    }
-    .deploy(t.k8s_client())
-    .await
-    .context(format!("Unable to create job '{}'", t.name()))?;
+    let correlation_id = t.correlation_id();
+    if t.test().correlation_id() != Some(correlation_id.as_str()) {
+        t.test_client()
+            .send_correlation_id(t.name(), &correlation_id)
+            .await
+            .context(format!("Unable to send correlation ID for '{}'", t.name()))?;
+    }
+    debug!(
+        "Test '{}' has correlation ID '{}'",
+        t.name(),
+        correlation_id
+    );
+    let agents = &t.test().spec.agents;
+    if !agents.is_empty() {
+        let agent_names: Vec<&str> = agents.iter().map(|agent| agent.name.as_str()).collect();
+        t.test_client()
+            .initialize_agent_statuses(t.name(), &agent_names)
+            .await
+            .context(format!(
+                "Unable to initialize agent statuses for '{}'",
+                t.name()
+            ))?;
+    }
+    let resource_outputs = t.resource_outputs().await?;
+    t.create_resource_outputs(&resource_outputs).await?;
+    let wait_for_endpoints = t.resolve_endpoints().await?;
+    for job_builder in t.job_builders(
+        &correlation_id,
+        &wait_for_endpoints,
+        Some(&resource_outputs),
+    )? {
+        let job_name = job_builder.job_name;
+        debug!(
+            "Creating job '{}' for agent '{}' of test '{}'",
+            job_name,
+            job_builder.agent.name,
+            t.name()
+        );
+        job_builder
+            .deploy(t.k8s_client())
+            .await
+            .context(format!("Unable to create job '{}'", job_name))?;
+    }
+    Ok(())
+}
+
+/// Keep the test's standard conditions in step with its state, they change after the actions that
+/// change the state have been taken.
+async fn update_conditions(t: &TestInterface) -> Result<()> {
+    if t.test().is_delete_requested() {
+        return Ok(());
+    }
+    if let Some(conditions) = t.test().updated_conditions(t.now()) {
+        t.test_client()
+            .send_conditions(t.name(), &conditions)
+            .await
+            .context(format!("Unable to send conditions for '{}'", t.name()))?;
+    }
     Ok(())
 }
+
+/// Append the test's new state to its timeline if the controller keeps timelines. Like the
+/// conditions, the timeline lags one reconciliation behind the actions that change the state.
+async fn update_timeline(t: &TestInterface) -> Result<()> {
+    let max_entries = match t.max_timeline_entries() {
+        Some(max_entries) if !t.test().is_delete_requested() => max_entries,
+        _ => return Ok(()),
+    };
+    if let Some(timeline) = t.test().updated_timeline(t.now(), max_entries) {
+        t.test_client()
+            .send_timeline(t.name(), &timeline)
+            .await
+            .context(format!("Unable to send timeline for '{}'", t.name()))?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn status_only_update_does_not_recreate_job() {
+    use http::{Method, Request, Response, StatusCode};
+    use hyper::Body;
+    use kube::core::ObjectMeta;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use testsys_model::TestStatus;
+
+    let test = Test {
+        metadata: ObjectMeta {
+            name: Some("my-test".to_string()),
+            namespace: Some(testsys_model::constants::NAMESPACE.to_string()),
+            uid: Some("0123".to_string()),
+            finalizers: Some(vec![
+                FINALIZER_MAIN.to_string(),
+                FINALIZER_TEST_JOB.to_string(),
+            ]),
+            ..ObjectMeta::default()
+        },
+        status: Some(TestStatus::default()),
+        ..Test::default()
+    };
+
+    // The job is never found, e.g. because the API server has not caught up yet, so every
+    // reconciliation that does real work would try to create it.
+    let job_creations = Arc::new(AtomicUsize::new(0));
+    let service = {
+        let job_creations = job_creations.clone();
+        let test = serde_json::json!(test);
+        tower::service_fn(move |request: Request<Body>| {
+            let job_creations = job_creations.clone();
+            let test = test.clone();
+            async move {
+                let path = request.uri().path().to_string();
+                let response = match *request.method() {
+                    Method::POST if path.ends_with("/jobs") => {
+                        job_creations.fetch_add(1, Ordering::SeqCst);
+                        let body = hyper::body::to_bytes(request.into_body())
+                            .await
+                            .unwrap_or_default();
+                        Response::new(Body::from(body))
+                    }
+                    Method::GET if path.ends_with("/jobs") => Response::new(Body::from(
+                        serde_json::json!({
+                            "apiVersion": "batch/v1",
+                            "kind": "JobList",
+                            "metadata": {},
+                            "items": [],
+                        })
+                        .to_string(),
+                    )),
+                    Method::PATCH => Response::new(Body::from(test.to_string())),
+                    _ => {
+                        let mut response = Response::new(Body::from(
+                            serde_json::json!({
+                                "kind": "Status",
+                                "apiVersion": "v1",
+                                "status": "Failure",
+                                "reason": "NotFound",
+                                "code": 404,
+                            })
+                            .to_string(),
+                        ));
+                        *response.status_mut() = StatusCode::NOT_FOUND;
+                        response
+                    }
+                };
+                Ok::<_, Infallible>(response)
+            }
+        })
+    };
+    let context = crate::test_controller::context::new_context(
+        kube::Client::new(service, testsys_model::constants::NAMESPACE),
+        &crate::config::ControllerConfig::default(),
+    );
+
+    assert!(reconcile(Arc::new(test.clone()), context.clone())
+        .await
+        .is_ok());
+    assert_eq!(job_creations.load(Ordering::SeqCst), 1);
+
+    // The agent updates the test's status, which does not change its generation.
+    let mut status_update = test;
+    status_update.status = Some(TestStatus {
+        last_update: Some("2022-01-01T00:00:00Z".to_string()),
+        ..TestStatus::default()
+    });
+    assert!(reconcile(Arc::new(status_update), context).await.is_ok());
+    assert_eq!(job_creations.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn archived_test_destroys_resources_and_is_kept() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::constants::ANNOTATION_ARCHIVE;
+    use testsys_model::CrdExt;
+    use testsys_model::{Agent, Resource, ResourceSpec, TestSpec, TestStatus};
+
+    let test = |name: &str, resources: &[&str]| {
+        let mut test = Test::new(
+            name,
+            TestSpec {
+                resources: resources.iter().map(|s| s.to_string()).collect(),
+                ..TestSpec::default()
+            },
+        );
+        test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+        test.status = Some(TestStatus::default());
+        serde_json::json!(test)
+    };
+    let resource = |name: &str| {
+        serde_json::json!(Resource::new(
+            name,
+            ResourceSpec {
+                depends_on: None,
+                conflicts_with: None,
+                agent: Agent::default(),
+                destruction_policy: Default::default(),
+                pool: None,
+                shared: None,
+            }
+        ))
+    };
+    let mut archived = test("archived", &["cluster", "shared"]);
+    archived["metadata"]["finalizers"] = serde_json::json!([FINALIZER_MAIN, FINALIZER_TEST_JOB]);
+    archived["metadata"]["annotations"] = serde_json::json!({ ANNOTATION_ARCHIVE: "true" });
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![
+        archived,
+        test("other", &["shared"]),
+        resource("cluster"),
+        resource("shared"),
+    ]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig::default(),
+    );
+
+    // Reconcile until the controller has nothing left to do.
+    for _ in 0..10 {
+        let test = test_client.get("archived").await;
+        assert!(test.is_ok());
+        let test = test.unwrap_or_default();
+        if test.is_archived() {
+            break;
+        }
+        assert!(reconcile(Arc::new(test), context.clone()).await.is_ok());
+    }
+
+    let test = test_client.get("archived").await.unwrap_or_default();
+    assert!(test.is_archived());
+    assert!(test.finished_at().is_some());
+    assert!(!test.has_finalizer(FINALIZER_MAIN) && !test.has_finalizer(FINALIZER_TEST_JOB));
+    let resources: kube::Api<Resource> =
+        kube::Api::namespaced(k8s_client, testsys_model::constants::NAMESPACE);
+    assert!(matches!(resources.get_opt("cluster").await, Ok(None)));
+    // The resource is still used by a test that was not archived.
+    assert!(matches!(resources.get_opt("shared").await, Ok(Some(_))));
+}
+
+/// Delete a test of the resources `vpc` and `cluster`, which is created in the VPC, and record
+/// which of them remain after each reconcile until the test can be removed.
+#[cfg(test)]
+async fn remaining_resources_after_deletion(
+    destroy_resources: bool,
+    annotations: serde_json::Value,
+) -> crate::error::Result<Vec<Vec<String>>> {
+    use kube::Resource as _;
+    use kube::ResourceExt;
+    use testsys_model::clients::TestClient;
+    use testsys_model::CrdExt;
+    use testsys_model::{Agent, Resource, ResourceSpec, TestSpec, TestStatus};
+
+    let mut test = Test::new(
+        "deleted",
+        TestSpec {
+            resources: vec!["vpc".to_string(), "cluster".to_string()],
+            destroy_resources,
+            ..TestSpec::default()
+        },
+    );
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.status = Some(TestStatus::default());
+    let mut test = serde_json::json!(test);
+    test["metadata"]["finalizers"] = serde_json::json!([FINALIZER_MAIN]);
+    test["metadata"]["deletionTimestamp"] = serde_json::json!("2022-01-01T00:00:00Z");
+    test["metadata"]["annotations"] = annotations;
+    let resource = |name: &str, depends_on: Option<&str>| {
+        serde_json::json!(Resource::new(
+            name,
+            ResourceSpec {
+                depends_on: depends_on.map(|depends_on| vec![depends_on.to_string()]),
+                conflicts_with: None,
+                agent: Agent::default(),
+                destruction_policy: Default::default(),
+                pool: None,
+                shared: None,
+            }
+        ))
+    };
+    // The test lists the resources in the order they are created.
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![
+        test,
+        resource("vpc", None),
+        resource("cluster", Some("vpc")),
+    ]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+    let resources: kube::Api<Resource> =
+        kube::Api::namespaced(k8s_client.clone(), testsys_model::constants::NAMESPACE);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+
+    let mut remaining = Vec::new();
+    for _ in 0..10 {
+        let test = test_client.get("deleted").await?;
+        if !test.has_finalizer(FINALIZER_MAIN) {
+            break;
+        }
+        reconcile(Arc::new(test), context.clone()).await?;
+        let names = resources
+            .list(&Default::default())
+            .await?
+            .items
+            .iter()
+            .map(|resource| resource.name_any())
+            .collect();
+        remaining.push(names);
+    }
+    Ok(remaining)
+}
+
+#[tokio::test]
+async fn deleted_test_destroys_resources_in_reverse_dependency_order() {
+    let remaining = remaining_resources_after_deletion(true, serde_json::json!({})).await;
+    assert!(matches!(
+        remaining.as_deref(),
+        // The main finalizer is only removed after all resources are gone.
+        Ok([vpc, empty, removed]) if vpc == &["vpc"] && empty.is_empty() && removed.is_empty()
+    ));
+}
+
+#[tokio::test]
+async fn deleted_test_keeps_resources_by_default() {
+    let remaining = remaining_resources_after_deletion(false, serde_json::json!({})).await;
+    assert!(matches!(
+        remaining.as_deref(),
+        Ok([kept]) if kept == &["cluster", "vpc"]
+    ));
+}
+
+#[tokio::test]
+async fn restarted_test_keeps_resources() {
+    use testsys_model::constants::ANNOTATION_KEEP_RESOURCES;
+
+    let remaining = remaining_resources_after_deletion(
+        true,
+        serde_json::json!({ ANNOTATION_KEEP_RESOURCES: "true" }),
+    )
+    .await;
+    assert!(matches!(
+        remaining.as_deref(),
+        Ok([kept]) if kept == &["cluster", "vpc"]
+    ));
+}
+
+#[tokio::test]
+async fn resource_teardown_waits_for_dependents() {
+    use kube::Resource as _;
+    use testsys_model::{Agent, Resource, ResourceSpec, TestSpec, TestStatus};
+
+    let mut test = Test::new(
+        "deleted",
+        TestSpec {
+            resources: vec!["vpc".to_string(), "cluster".to_string()],
+            destroy_resources: true,
+            ..TestSpec::default()
+        },
+    );
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+    test.meta_mut().deletion_timestamp = Some(
+        k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(k8s_openapi::chrono::Utc::now()),
+    );
+    test.status = Some(TestStatus::default());
+    let mut cluster = Resource::new(
+        "cluster",
+        ResourceSpec {
+            depends_on: Some(vec!["vpc".to_string()]),
+            conflicts_with: None,
+            agent: Agent::default(),
+            destruction_policy: Default::default(),
+            pool: None,
+            shared: None,
+        },
+    );
+    // The cluster's destruction job is still running.
+    cluster.meta_mut().finalizers = Some(vec![
+        testsys_model::constants::FINALIZER_RESOURCE.to_string()
+    ]);
+    cluster.meta_mut().deletion_timestamp = test.meta().deletion_timestamp.clone();
+    let vpc = Resource::new(
+        "vpc",
+        ResourceSpec {
+            depends_on: None,
+            conflicts_with: None,
+            agent: Agent::default(),
+            destruction_policy: Default::default(),
+            pool: None,
+            shared: None,
+        },
+    );
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![
+        serde_json::json!(test),
+        serde_json::json!(cluster),
+        serde_json::json!(vpc),
+    ]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    let action = async {
+        let t = TestInterface::new(test, context)?;
+        determine_action(&t).await
+    }
+    .await;
+    assert!(matches!(
+        action,
+        Ok(Action::WaitForResourceDestruction(name)) if name == "cluster"
+    ));
+}
+
+#[tokio::test]
+async fn test_with_two_agents_fails_if_one_fails() {
+    use k8s_openapi::api::batch::v1::Job;
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::{Agent, TestSpec, TestUserState};
+
+    let mut test = Test::new(
+        "compare",
+        TestSpec {
+            agent: Agent {
+                name: "baseline".to_string(),
+                image: "example.com/agent:v1".to_string(),
+                ..Agent::default()
+            },
+            agents: vec![Agent {
+                name: "candidate".to_string(),
+                image: "example.com/agent:v2".to_string(),
+                ..Agent::default()
+            }],
+            ..TestSpec::default()
+        },
+    );
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    let mut test = serde_json::json!(test);
+    // The fake API only matches `null` in JSON patch tests against fields that are present.
+    test["status"] = serde_json::Value::Null;
+    test["metadata"]["finalizers"] = serde_json::Value::Null;
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![test]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+    let jobs: kube::Api<Job> =
+        kube::Api::namespaced(k8s_client.clone(), testsys_model::constants::NAMESPACE);
+    let reconcile_until = |done: fn(&Test, usize) -> bool| {
+        let test_client = test_client.clone();
+        let jobs = jobs.clone();
+        let k8s_client = k8s_client.clone();
+        async move {
+            for _ in 0..10 {
+                let test = test_client.get("compare").await.unwrap_or_default();
+                let job_count = jobs
+                    .list(&Default::default())
+                    .await
+                    .map(|jobs| jobs.items.len())
+                    .unwrap_or_default();
+                if done(&test, job_count) {
+                    return Some(test);
+                }
+                // A new context each time so that status updates are not debounced.
+                let context = crate::test_controller::context::new_context(
+                    k8s_client.clone(),
+                    &crate::config::ControllerConfig::default(),
+                );
+                assert!(reconcile(Arc::new(test), context).await.is_ok());
+            }
+            None
+        }
+    };
+
+    // A job is started for each agent.
+    let test = reconcile_until(|_, job_count| job_count == 2).await;
+    assert!(test.is_some());
+    let test = test.unwrap_or_default();
+    assert_eq!(test.test_user_state(), TestUserState::Waiting);
+    let candidate_job = jobs.get(&test.agent_job_name("candidate")).await;
+    assert!(candidate_job.is_ok());
+    let candidate_env = candidate_job
+        .ok()
+        .and_then(|job| job.spec)
+        .and_then(|spec| spec.template.spec)
+        .and_then(|spec| spec.containers.into_iter().next())
+        .and_then(|container| container.env)
+        .unwrap_or_default();
+    assert!(candidate_env.iter().any(|env| env.name
+        == testsys_model::constants::ENV_TEST_AGENT_NAME
+        && env.value.as_deref() == Some("candidate")));
+
+    // Each agent reports its results to its own status.
+    let results = |outcome, num_passed, num_failed| TestResults {
+        outcome,
+        num_passed,
+        num_failed,
+        num_skipped: 0,
+        other_info: None,
+    };
+    assert!(test_client
+        .send_test_completed("compare", results(Outcome::Pass, 3, 0))
+        .await
+        .is_ok());
+    assert!(test_client
+        .clone()
+        .for_agent("candidate")
+        .send_test_completed("compare", results(Outcome::Fail, 2, 1))
+        .await
+        .is_ok());
+
+    let test = reconcile_until(|test, _| test.finished_at().is_some()).await;
+    assert!(test.is_some());
+    let test = test.unwrap_or_default();
+    assert_eq!(test.agent_status().results.len(), 1);
+    assert_eq!(
+        test.additional_agent_status("candidate").results.last(),
+        Some(&results(Outcome::Fail, 2, 1))
+    );
+    assert_eq!(test.test_user_state(), TestUserState::Failed);
+}
+
+#[tokio::test]
+async fn created_resource_summary_appears_in_status() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::{
+        Agent, OutputField, OutputType, Resource, ResourceAgentState, ResourceSpec, ResourceStatus,
+        TaskState, TestSpec, TestStatus,
+    };
+
+    let mut test = Test::new(
+        "my-test",
+        TestSpec {
+            resources: vec!["cluster".to_string()],
+            ..TestSpec::default()
+        },
+    );
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().finalizers = Some(vec![
+        FINALIZER_MAIN.to_string(),
+        FINALIZER_TEST_JOB.to_string(),
+    ]);
+    test.status = Some(TestStatus::default());
+    let mut resource = Resource::new(
+        "cluster",
+        ResourceSpec {
+            agent: Agent {
+                name: "eks-provider".to_string(),
+                ..Agent::default()
+            },
+            ..ResourceSpec::default()
+        },
+    );
+    resource.status = Some(ResourceStatus {
+        creation: ResourceAgentState {
+            task_state: TaskState::Completed,
+            error: None,
+        },
+        created_resource: serde_json::json!({
+            "endpoint": "https://example.com",
+            "token": "secret",
+        })
+        .as_object()
+        .cloned(),
+        output_schema: Some(vec![
+            OutputField::new("endpoint", OutputType::String),
+            OutputField::new("token", OutputType::String).sensitive(),
+        ]),
+        ..ResourceStatus::default()
+    });
+    let k8s_client =
+        crate::fake_api::fake_k8s_store(vec![serde_json::json!(test), serde_json::json!(resource)]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig::default(),
+    );
+
+    let summary = async {
+        reconcile(Arc::new(test), context).await?;
+        let test = TestClient::new_from_k8s_client(k8s_client)
+            .get("my-test")
+            .await?;
+        Ok::<_, anyhow::Error>(
+            test.resource_summaries()
+                .and_then(|summaries| summaries.get("cluster"))
+                .cloned(),
+        )
+    }
+    .await;
+    assert!(matches!(
+        summary,
+        Ok(Some(summary)) if summary.agent == "eks-provider"
+            && summary.creation_state == TaskState::Completed
+            && summary.outputs == [("endpoint".to_string(), "https://example.com".to_string())].into()
+    ));
+}
+
+#[tokio::test]
+async fn observe_only_does_not_change_anything() {
+    use std::sync::atomic::Ordering;
+    use testsys_model::TestStatus;
+
+    let (k8s_client, writes) = crate::fake_api::fake_k8s_client_counting_writes::<&str>(vec![]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig {
+            observe_only: true,
+            ..Default::default()
+        },
+    );
+    // A new test would be initialized and a test without a job would have its job created.
+    let new_test = Test::new("new-test", Default::default());
+    let mut ready_test = Test::new("ready-test", Default::default());
+    ready_test.metadata.finalizers = Some(vec![
+        FINALIZER_MAIN.to_string(),
+        FINALIZER_TEST_JOB.to_string(),
+    ]);
+    ready_test.status = Some(TestStatus::default());
+
+    for test in [new_test, ready_test] {
+        assert!(reconcile(Arc::new(test), context.clone()).await.is_ok());
+    }
+    assert_eq!(writes.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_of_other_instance_is_ignored() {
+    use std::collections::BTreeMap;
+    use std::sync::atomic::Ordering;
+    use testsys_model::constants::LABEL_CONTROLLER_INSTANCE;
+
+    let (k8s_client, writes) = crate::fake_api::fake_k8s_client_counting_writes::<&str>(vec![]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig {
+            instance_id: Some("green".to_string()),
+            ..Default::default()
+        },
+    );
+    // A new test would be initialized if it belonged to this instance.
+    let mut test = Test::new("blue-test", Default::default());
+    test.metadata.labels = Some(BTreeMap::from([(
+        LABEL_CONTROLLER_INSTANCE.to_string(),
+        "blue".to_string(),
+    )]));
+
+    assert!(reconcile(Arc::new(test), context).await.is_ok());
+    assert_eq!(writes.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn unclaimed_test_is_claimed() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+
+    let mut test = Test::new("new-test", Default::default());
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test)]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig {
+            instance_id: Some("green".to_string()),
+            ..Default::default()
+        },
+    );
+
+    assert!(reconcile(Arc::new(test), context).await.is_ok());
+    let test = TestClient::new_from_k8s_client(k8s_client)
+        .get("new-test")
+        .await
+        .unwrap_or_default();
+    assert_eq!(test.controller_instance(), Some("green"));
+    // Nothing else is done until the claimed test is reconciled again.
+    assert!(test.status.is_none());
+}
+
+#[tokio::test]
+async fn test_that_changed_is_not_claimed() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+
+    let mut test = Test::new("new-test", Default::default());
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().resource_version = Some("2".to_string());
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test)]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig {
+            instance_id: Some("green".to_string()),
+            ..Default::default()
+        },
+    );
+
+    // Another instance may have claimed the test since this version was read.
+    test.meta_mut().resource_version = Some("1".to_string());
+    assert!(reconcile(Arc::new(test), context).await.is_err());
+    let test = TestClient::new_from_k8s_client(k8s_client)
+        .get("new-test")
+        .await
+        .unwrap_or_default();
+    assert_eq!(test.controller_instance(), None);
+}
+
+#[tokio::test]
+async fn invalid_schedule_is_parked() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::{Schedule, TestUserState};
+
+    let mut test = Test::new("nightly", Default::default());
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().generation = Some(1);
+    test.spec.schedule = Some(Schedule {
+        cron: "0 3 * *".to_string(),
+        keep_runs: None,
+    });
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test)]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+
+    // The test is given a status to record the invalid schedule in, and is not requeued once it
+    // was recorded.
+    let parked = async {
+        let mut actions = Vec::new();
+        for _ in 0..4 {
+            let test = test_client.get("nightly").await?;
+            actions.push(reconcile(Arc::new(test), context.clone()).await?);
+        }
+        Ok::<_, anyhow::Error>((actions, test_client.get("nightly").await?))
+    }
+    .await;
+    assert!(
+        matches!(
+            &parked,
+            Ok((actions, test)) if actions[2..].iter().all(|action| *action == no_requeue())
+                && test.test_user_state() == TestUserState::InvalidSpec
+                && test.invalid_spec().map(String::as_str)
+                    == Some("The schedule is invalid: Cron expression '0 3 * *' must have 5 \
+                        fields, it has 4")
+        ),
+        "{:?}",
+        parked
+    );
+}
+
+#[tokio::test]
+async fn invalid_spec_is_parked() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::{TestStatus, TestUserState};
+
+    let mut test = Test::new("invalid", Default::default());
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+    test.spec.agent.timeout = Some("10 minutes".to_string());
+    test.status = Some(TestStatus::default());
+    let mut test = serde_json::json!(test);
+    // Add the fields the controller writes so that the fake API server can patch them.
+    test["status"]["controller"]["invalidSpec"] = serde_json::Value::Null;
+    test["status"]["controller"]["finishedAt"] = serde_json::Value::Null;
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![test]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+
+    // The test is not requeued, neither when the invalid spec is found nor after it was recorded.
+    let parked = async {
+        let mut actions = Vec::new();
+        for _ in 0..2 {
+            let test = test_client.get("invalid").await?;
+            actions.push(reconcile(Arc::new(test), context.clone()).await?);
+        }
+        Ok::<_, anyhow::Error>((actions, test_client.get("invalid").await?))
+    }
+    .await;
+    assert!(matches!(
+        &parked,
+        Ok((actions, test)) if actions.iter().all(|action| *action == no_requeue())
+            && test.test_user_state() == TestUserState::InvalidSpec
+            && test.invalid_spec().map(String::as_str)
+                == Some("The timeout '10 minutes' of agent '' is not a duration")
+    ));
+}
+
+#[tokio::test]
+async fn conditions_are_only_sent_when_they_change() {
+    use std::sync::atomic::Ordering;
+    use testsys_model::TestStatus;
+
+    // A quarantined test is left alone, so only its conditions can be written.
+    let mut test = Test::new("quarantined", Default::default());
+    let mut status = TestStatus::default();
+    status.controller.quarantined = true;
+    test.status = Some(status);
+    let (k8s_client, writes) = crate::fake_api::fake_k8s_client_counting_writes(vec![(
+        "/tests/quarantined/status",
+        serde_json::json!(test),
+    )]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+
+    assert!(reconcile(Arc::new(test.clone()), context.clone())
+        .await
+        .is_ok());
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+
+    if let (Some(conditions), Some(status)) = (
+        test.updated_conditions(k8s_openapi::chrono::Utc::now()),
+        test.status.as_mut(),
+    ) {
+        status.conditions = conditions;
+    }
+    assert!(reconcile(Arc::new(test), context).await.is_ok());
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn timeline_is_only_sent_when_configured() {
+    use std::sync::atomic::Ordering;
+    use testsys_model::TestStatus;
+
+    let mut test = Test::new("quarantined", Default::default());
+    let mut status = TestStatus::default();
+    status.controller.quarantined = true;
+    test.status = Some(status);
+    let (k8s_client, writes) = crate::fake_api::fake_k8s_client_counting_writes(vec![(
+        "/tests/quarantined/status",
+        serde_json::json!(test),
+    )]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig::default(),
+    );
+    assert!(reconcile(Arc::new(test.clone()), context).await.is_ok());
+    // Only the conditions are written.
+    assert_eq!(writes.load(Ordering::SeqCst), 1);
+
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig {
+            max_timeline_entries: Some(10),
+            ..Default::default()
+        },
+    );
+    assert!(reconcile(Arc::new(test.clone()), context.clone())
+        .await
+        .is_ok());
+    assert_eq!(writes.load(Ordering::SeqCst), 3);
+
+    let conditions = test
+        .updated_conditions(k8s_openapi::chrono::Utc::now())
+        .unwrap_or_default();
+    let timeline = test
+        .updated_timeline(k8s_openapi::chrono::Utc::now(), 10)
+        .unwrap_or_default();
+    if let Some(status) = test.status.as_mut() {
+        status.conditions = conditions;
+        status.timeline = timeline;
+    }
+    assert!(reconcile(Arc::new(test), context).await.is_ok());
+    assert_eq!(writes.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn agent_gets_correlation_id() {
+    use k8s_openapi::api::batch::v1::Job;
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::constants::{
+        DEFAULT_MAX_STATUS_FIELD_LEN, ENV_CORRELATION_ID, ENV_MAX_STATUS_FIELD_LEN,
+        ENV_RESULTS_ENDPOINT,
+    };
+    use testsys_model::TestStatus;
+
+    let mut test = Test::new("my-test", Default::default());
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().uid = Some("0123abcd".to_string());
+    test.meta_mut().finalizers = Some(vec![
+        FINALIZER_MAIN.to_string(),
+        FINALIZER_TEST_JOB.to_string(),
+    ]);
+    test.status = Some(TestStatus::default());
+    let job_name = test.job_name();
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test)]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig {
+            results_address: Some("0.0.0.0:50051".to_string()),
+            ..Default::default()
+        },
+    );
+    assert!(reconcile(Arc::new(test), context).await.is_ok());
+
+    let correlation_id = TestClient::new_from_k8s_client(k8s_client.clone())
+        .get("my-test")
+        .await
+        .ok()
+        .and_then(|test| test.correlation_id().map(str::to_string));
+    assert!(correlation_id.is_some());
+    let job: Option<Job> = kube::Api::namespaced(k8s_client, testsys_model::constants::NAMESPACE)
+        .get(&job_name)
+        .await
+        .ok();
+    let env = job
+        .and_then(|job| job.spec)
+        .and_then(|spec| spec.template.spec)
+        .and_then(|spec| spec.containers.into_iter().next())
+        .and_then(|container| container.env)
+        .unwrap_or_default();
+    let env_value = |name: &str| {
+        env.iter()
+            .find(|var| var.name == name)
+            .and_then(|var| var.value.clone())
+    };
+    assert_eq!(env_value(ENV_CORRELATION_ID), correlation_id);
+    // The agent truncates status strings like the controller.
+    assert_eq!(
+        env_value(ENV_MAX_STATUS_FIELD_LEN),
+        Some(DEFAULT_MAX_STATUS_FIELD_LEN.to_string())
+    );
+    // The agent streams its results through the controller's service.
+    assert_eq!(
+        env_value(ENV_RESULTS_ENDPOINT).as_deref(),
+        Some("http://testsys-controller.testsys.svc:50051")
+    );
+}
+
+#[tokio::test]
+async fn job_creation_resets_failures() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::{ControllerStatus, TestStatus};
+
+    let mut test = Test::new("my-test", Default::default());
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().uid = Some("0123abcd".to_string());
+    test.meta_mut().finalizers = Some(vec![
+        FINALIZER_MAIN.to_string(),
+        FINALIZER_TEST_JOB.to_string(),
+    ]);
+    test.status = Some(TestStatus {
+        controller: ControllerStatus {
+            job_creation_failures: 2,
+            ..ControllerStatus::default()
+        },
+        ..TestStatus::default()
+    });
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test)]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig::default(),
+    );
+    assert!(reconcile(Arc::new(test), context).await.is_ok());
+
+    let failures = TestClient::new_from_k8s_client(k8s_client)
+        .get("my-test")
+        .await
+        .map(|test| test.job_creation_failures());
+    assert!(matches!(failures, Ok(0)));
+}
+
+#[tokio::test]
+async fn versions_are_recorded_when_the_job_is_created() {
+    use k8s_openapi::api::batch::v1::Job;
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::TestStatus;
+
+    let mut test = Test::new("my-test", Default::default());
+    test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+    test.meta_mut().uid = Some("0123abcd".to_string());
+    test.meta_mut().finalizers = Some(vec![
+        FINALIZER_MAIN.to_string(),
+        FINALIZER_TEST_JOB.to_string(),
+    ]);
+    test.status = Some(TestStatus::default());
+    let job_name = test.job_name();
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test)]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig::default(),
+    );
+    let started = async {
+        reconcile(Arc::new(test), context).await?;
+        let test = TestClient::new_from_k8s_client(k8s_client.clone())
+            .get("my-test")
+            .await?;
+        let job: Job = kube::Api::namespaced(k8s_client, testsys_model::constants::NAMESPACE)
+            .get(&job_name)
+            .await?;
+        Ok::<_, anyhow::Error>((test.controller_version().map(str::to_string), job))
+    }
+    .await;
+    assert!(matches!(
+        started,
+        Ok((Some(version), _)) if version == CONTROLLER_VERSION
+    ));
+}
+
+#[tokio::test]
+async fn resource_outputs_are_mounted() {
+    use k8s_openapi::api::batch::v1::Job;
+    use k8s_openapi::api::core::v1::Secret;
+    use kube::Resource as _;
+    use testsys_model::constants::{NAMESPACE, RESOURCE_OUTPUTS_FILE, RESOURCE_OUTPUTS_PATH};
+    use testsys_model::{Resource, ResourceSpec, TestSpec, TestStatus};
+
+    let mut resource = Resource::new("my-cluster", ResourceSpec::default());
+    resource.meta_mut().namespace = Some(NAMESPACE.to_string());
+    let mut resource = serde_json::json!(resource);
+    resource["status"] = serde_json::json!({
+        "creation": { "taskState": "completed" },
+        "destruction": { "taskState": "unknown" },
+        "createdResource": { "clusterName": "my-cluster", "endpoint": "https://example.com" }
+    });
+    let mut test = Test::new(
+        "my-test",
+        TestSpec {
+            resources: vec!["my-cluster".to_string()],
+            ..Default::default()
+        },
+    );
+    test.meta_mut().namespace = Some(NAMESPACE.to_string());
+    test.meta_mut().uid = Some("0123abcd".to_string());
+    test.status = Some(TestStatus::default());
+    let job_name = test.job_name();
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test), resource]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig::default(),
+    );
+    let outputs = async {
+        let mut t = TestInterface::new(test, context)?;
+        create_job(&mut t).await?;
+        let job: Job = kube::Api::namespaced(k8s_client.clone(), NAMESPACE)
+            .get(&job_name)
+            .await?;
+        let pod_spec = job
+            .spec
+            .and_then(|spec| spec.template.spec)
+            .unwrap_or_default();
+        let mounted = pod_spec
+            .containers
+            .iter()
+            .flat_map(|container| container.volume_mounts.iter().flatten())
+            .any(|mount| mount.mount_path == RESOURCE_OUTPUTS_PATH);
+        // The outputs can hold credentials, so they are only ever mounted from a secret.
+        let secret_name = pod_spec
+            .volumes
+            .unwrap_or_default()
+            .into_iter()
+            .find(|volume| volume.name == "resource-outputs")
+            .and_then(|volume| volume.secret)
+            .and_then(|secret| secret.secret_name)
+            .unwrap_or_default();
+        let secret: Secret = kube::Api::namespaced(k8s_client, NAMESPACE)
+            .get(&secret_name)
+            .await?;
+        let outputs: Option<serde_json::Value> = secret
+            .data
+            .and_then(|data| data.get(RESOURCE_OUTPUTS_FILE).cloned())
+            .map(|outputs| serde_json::from_slice(&outputs.0))
+            .transpose()?;
+        Ok::<_, anyhow::Error>((mounted, outputs))
+    }
+    .await;
+    assert!(matches!(
+        outputs,
+        Ok((true, Some(outputs))) if outputs == serde_json::json!({
+            "my-cluster": { "clusterName": "my-cluster", "endpoint": "https://example.com" }
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_agent_waits_for_resolved_endpoint() {
+    use k8s_openapi::api::batch::v1::Job;
+    use kube::Resource as _;
+    use testsys_model::constants::NAMESPACE;
+    use testsys_model::{Resource, ResourceEndpoint, ResourceSpec, TestSpec, TestStatus};
+
+    let mut resource = Resource::new("my-cluster", ResourceSpec::default());
+    resource.meta_mut().namespace = Some(NAMESPACE.to_string());
+    let mut resource = serde_json::json!(resource);
+    resource["status"] = serde_json::json!({
+        "creation": { "taskState": "completed" },
+        "destruction": { "taskState": "unknown" },
+        "createdResource": { "endpoint": "https://my-cluster.example.com/healthz" }
+    });
+    let mut test = Test::new(
+        "my-test",
+        TestSpec {
+            resources: vec!["my-cluster".to_string()],
+            wait_for_endpoints: vec![ResourceEndpoint {
+                resource: "my-cluster".to_string(),
+                field: "endpoint".to_string(),
+            }],
+            ..Default::default()
+        },
+    );
+    test.meta_mut().namespace = Some(NAMESPACE.to_string());
+    test.meta_mut().uid = Some("0123abcd".to_string());
+    test.status = Some(TestStatus::default());
+    let job_name = test.job_name();
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![serde_json::json!(test), resource]);
+    let context = crate::test_controller::context::new_context(
+        k8s_client.clone(),
+        &crate::config::ControllerConfig::default(),
+    );
+    let result = async {
+        let mut t = TestInterface::new(test, context)?;
+        create_job(&mut t).await
+    }
+    .await;
+    assert!(result.is_ok());
+
+    let job: Option<Job> = kube::Api::namespaced(k8s_client, NAMESPACE)
+        .get(&job_name)
+        .await
+        .ok();
+    let init_containers = job
+        .and_then(|job| job.spec)
+        .and_then(|spec| spec.template.spec)
+        .and_then(|pod_spec| pod_spec.init_containers)
+        .unwrap_or_default();
+    assert_eq!(init_containers.len(), 1);
+    assert_eq!(
+        init_containers
+            .first()
+            .and_then(|container| container.args.clone()),
+        Some(vec!["https://my-cluster.example.com/healthz".to_string()])
+    );
+}
+
+#[tokio::test]
+async fn shared_resource_is_destroyed_with_last_reference() {
+    use kube::Resource as _;
+    use testsys_model::clients::TestClient;
+    use testsys_model::{Agent, Resource, ResourceSpec, ResourceStatus, TestSpec, TestStatus};
+
+    let deleted_test = |name: &str| {
+        let mut test = Test::new(
+            name,
+            TestSpec {
+                resources: vec!["bastion".to_string()],
+                destroy_resources: true,
+                ..TestSpec::default()
+            },
+        );
+        test.meta_mut().namespace = Some(testsys_model::constants::NAMESPACE.to_string());
+        test.status = Some(TestStatus::default());
+        let mut test = serde_json::json!(test);
+        test["metadata"]["finalizers"] = serde_json::json!([FINALIZER_MAIN]);
+        test["metadata"]["deletionTimestamp"] = serde_json::json!("2022-01-01T00:00:00Z");
+        test
+    };
+    let mut bastion = Resource::new(
+        "bastion",
+        ResourceSpec {
+            depends_on: None,
+            conflicts_with: None,
+            agent: Agent::default(),
+            destruction_policy: Default::default(),
+            pool: None,
+            shared: Some(true),
+        },
+    );
+    bastion.status = Some(ResourceStatus {
+        created_resource: serde_json::json!({ "ip": "10.0.0.1" }).as_object().cloned(),
+        references: Some(vec!["first".to_string(), "second".to_string()]),
+        ..ResourceStatus::default()
+    });
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![
+        deleted_test("first"),
+        deleted_test("second"),
+        serde_json::json!(bastion),
+    ]);
+    let test_client = TestClient::new_from_k8s_client(k8s_client.clone());
+    let resources: kube::Api<Resource> =
+        kube::Api::namespaced(k8s_client.clone(), testsys_model::constants::NAMESPACE);
+    let context = crate::test_controller::context::new_context(
+        k8s_client,
+        &crate::config::ControllerConfig::default(),
+    );
+    let remove = |name: &'static str| {
+        let (test_client, resources) = (test_client.clone(), resources.clone());
+        let context = context.clone();
+        async move {
+            for _ in 0..5 {
+                let test = test_client.get(name).await?;
+                if !test.has_finalizer(FINALIZER_MAIN) {
+                    break;
+                }
+                reconcile(Arc::new(test), context.clone()).await?;
+            }
+            Ok::<_, anyhow::Error>(resources.get_opt("bastion").await?)
+        }
+    };
+
+    // The second test still references the bastion, so it survives the first test's deletion.
+    assert!(matches!(
+        remove("first").await,
+        Ok(Some(bastion)) if bastion.references() == ["second".to_string()]
+            && !bastion.is_delete_requested()
+    ));
+
+    // It is destroyed once the last reference is removed.
+    assert!(matches!(remove("second").await, Ok(None)));
+}
@@ -0,0 +1,316 @@
+use crate::error::Result;
+use crate::schedule::Cron;
+use crate::utils::parse_duration;
+use anyhow::Context;
+use kube::api::{ApiResource, DynamicObject, ListParams, Patch, PatchParams};
+use kube::{Api, ResourceExt};
+use kube_runtime::watcher;
+use log::error;
+use std::collections::BTreeMap;
+use testsys_model::constants::NAMESPACE;
+use testsys_model::{Agent, ContainerResources, Qos, Test};
+
+/// Check the parts of the test's spec that deserialize but cannot be used, e.g. a timeout that is
+/// not a duration. Such a test can never run, so it is parked in the `InvalidSpec` state instead of
+/// failing reconciliation over and over.
+pub(super) fn invalid_spec(test: &Test) -> Option<String> {
+    std::iter::once(&test.spec.agent)
+        .chain(&test.spec.agents)
+        .find_map(invalid_agent)
+        .or_else(|| test.spec.agents.iter().find_map(unsupported_oom_retry))
+        .or_else(|| invalid_schedule(test))
+}
+
+/// A schedule whose cron expression cannot be parsed would never create a run of the test.
+fn invalid_schedule(test: &Test) -> Option<String> {
+    let schedule = test.spec.schedule.as_ref()?;
+    Cron::parse(&schedule.cron)
+        .err()
+        .map(|e| format!("The schedule is invalid: {:#}", e))
+}
+
+/// Only the test agent of a test is relaunched with more memory when it runs out, so the test's
+/// additional agents and resource agents cannot set `oom_retry`.
+pub(crate) fn unsupported_oom_retry(agent: &Agent) -> Option<String> {
+    agent.oom_retry.as_ref()?;
+    Some(format!(
+        "Agent '{}' sets oomRetry, only the test agent of a test can be relaunched with more \
+        memory",
+        agent.name
+    ))
+}
+
+/// Check the parts of the spec of a test's or resource's agent that deserialize but cannot be used.
+pub(crate) fn invalid_agent(agent: &Agent) -> Option<String> {
+    if let Some(timeout) = &agent.timeout {
+        if parse_duration(timeout).is_err() {
+            return Some(format!(
+                "The timeout '{}' of agent '{}' is not a duration",
+                timeout, agent.name
+            ));
+        }
+    }
+    if let Some(deadline) = &agent.completions_deadline {
+        if parse_duration(deadline).is_err() {
+            return Some(format!(
+                "The completions deadline '{}' of agent '{}' is not a duration",
+                deadline, agent.name
+            ));
+        }
+    }
+    if let Some(percent) = agent
+        .success_threshold_percent
+        .filter(|percent| *percent > 100)
+    {
+        return Some(format!(
+            "The success threshold of agent '{}' is {}%, it cannot be more than 100%",
+            agent.name, percent
+        ));
+    }
+    if let Some(completions) = agent.completions.filter(|completions| *completions < 1) {
+        return Some(format!(
+            "Agent '{}' has {} completions, it needs at least 1",
+            agent.name, completions
+        ));
+    }
+    if agent.qos == Some(Qos::Guaranteed) {
+        // k8s only puts a pod in the guaranteed class if both its cpu and memory are limited.
+        let resources = agent
+            .container_resources
+            .as_ref()
+            .map(ContainerResources::guaranteed)
+            .and_then(|resources| resources.limits)
+            .unwrap_or_default();
+        if !resources.contains_key("cpu") || !resources.contains_key("memory") {
+            return Some(format!(
+                "Agent '{}' has the guaranteed QoS class, its container resources need both cpu \
+                and memory",
+                agent.name
+            ));
+        }
+    }
+    None
+}
+
+/// Check that none of the `agents` of a test or resource set a protected label to a value other
+/// than the one the controller enforces, which would silently be replaced on the agent's pod.
+pub(crate) fn overridden_protected_label<'a, I>(
+    agents: I,
+    protected_labels: &BTreeMap<String, String>,
+) -> Option<String>
+where
+    I: IntoIterator<Item = &'a Agent>,
+{
+    agents.into_iter().find_map(|agent| {
+        let (key, value) = agent.pod_labels.iter().flatten().find(|(key, value)| {
+            protected_labels
+                .get(*key)
+                .map(|protected| protected != *value)
+                .unwrap_or(false)
+        })?;
+        Some(format!(
+            "Agent '{}' sets the protected label '{}' to '{}', it can only be '{}'",
+            agent.name,
+            key,
+            value,
+            protected_labels
+                .get(key)
+                .map(String::as_str)
+                .unwrap_or_default()
+        ))
+    })
+}
+
+/// Whether the watch on tests failed because a test could not be deserialized.
+pub(super) fn is_decode_error(error: &watcher::Error) -> bool {
+    matches!(
+        error,
+        watcher::Error::InitialListFailed(kube::Error::SerdeError(_))
+            | watcher::Error::WatchFailed(kube::Error::SerdeError(_))
+    )
+}
+
+/// Record why each of the tests listed by `list_params` that cannot be deserialized is invalid.
+/// Such tests never reach `reconcile`, the watch on tests fails when it sees one of them, so they
+/// are read as untyped objects to find them.
+pub(super) async fn mark_undecodable_tests(
+    k8s_client: kube::Client,
+    list_params: &ListParams,
+) -> Result<()> {
+    let api: Api<DynamicObject> =
+        Api::namespaced_with(k8s_client, NAMESPACE, &ApiResource::erase::<Test>(&()));
+    let tests = api
+        .list(list_params)
+        .await
+        .context("Unable to list tests as untyped objects")?;
+    for test in tests {
+        let reason = match serde_json::to_value(&test).and_then(serde_json::from_value::<Test>) {
+            Ok(_) => continue,
+            Err(e) => format!("The test cannot be read: {}", e),
+        };
+        if test.data["status"]["controller"]["invalidSpec"] == reason.as_str() {
+            continue;
+        }
+        error!("Test '{}' is invalid: {}", test.name_any(), reason);
+        let patch = serde_json::json!({ "status": { "controller": { "invalidSpec": reason } } });
+        api.patch_status(
+            &test.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await
+        .with_context(|| format!("Unable to mark test '{}' invalid", test.name_any()))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn valid_spec() {
+    let mut test = Test::default();
+    test.spec.agent.timeout = Some("1h30m".to_string());
+    test.spec.agent.completions = Some(4);
+    test.spec.agent.success_threshold_percent = Some(75);
+    assert_eq!(invalid_spec(&test), None);
+}
+
+#[test]
+fn invalid_timeout() {
+    let mut test = Test::default();
+    test.spec.agent.name = "sonobuoy".to_string();
+    test.spec.agent.timeout = Some("10 minutes".to_string());
+    assert_eq!(
+        invalid_spec(&test).as_deref(),
+        Some("The timeout '10 minutes' of agent 'sonobuoy' is not a duration")
+    );
+}
+
+#[test]
+fn invalid_schedule_cron() {
+    use testsys_model::Schedule;
+
+    let mut test = Test::default();
+    test.spec.schedule = Some(Schedule {
+        cron: "0 25 * * *".to_string(),
+        keep_runs: None,
+    });
+    assert_eq!(
+        invalid_spec(&test).as_deref(),
+        Some(
+            "The schedule is invalid: Invalid field '25' in cron expression '0 25 * * *': Values \
+            must be between 0 and 23"
+        )
+    );
+}
+
+#[test]
+fn oom_retry_is_only_for_the_test_agent() {
+    use testsys_model::OomRetry;
+
+    let oom_retry = Some(OomRetry {
+        factor: None,
+        max_memory: "4Gi".to_string(),
+    });
+    let mut test = Test::default();
+    test.spec.agent.oom_retry = oom_retry.clone();
+    assert_eq!(invalid_spec(&test), None);
+
+    test.spec.agents = vec![Agent {
+        name: "load".to_string(),
+        oom_retry,
+        ..Agent::default()
+    }];
+    assert_eq!(
+        invalid_spec(&test).as_deref(),
+        Some(
+            "Agent 'load' sets oomRetry, only the test agent of a test can be relaunched with \
+            more memory"
+        )
+    );
+}
+
+#[test]
+fn protected_label_cannot_be_overridden() {
+    let protected_labels = BTreeMap::from([("network-policy".to_string(), "agents".to_string())]);
+    let mut test = Test::default();
+    test.spec.agent.name = "sonobuoy".to_string();
+    test.spec.agent.pod_labels = Some(BTreeMap::from([
+        ("team".to_string(), "platform".to_string()),
+        ("network-policy".to_string(), "agents".to_string()),
+    ]));
+    assert_eq!(
+        overridden_protected_label([&test.spec.agent], &protected_labels),
+        None
+    );
+
+    test.spec.agent.pod_labels = Some(BTreeMap::from([(
+        "network-policy".to_string(),
+        "open".to_string(),
+    )]));
+    assert_eq!(
+        overridden_protected_label([&test.spec.agent], &protected_labels).as_deref(),
+        Some(
+            "Agent 'sonobuoy' sets the protected label 'network-policy' to 'open', it can only be \
+            'agents'"
+        )
+    );
+}
+
+#[test]
+fn guaranteed_qos_needs_cpu_and_memory() {
+    let mut agent = Agent {
+        name: "sonobuoy".to_string(),
+        qos: Some(Qos::Guaranteed),
+        ..Agent::default()
+    };
+    let reason = Some(
+        "Agent 'sonobuoy' has the guaranteed QoS class, its container resources need both cpu and \
+        memory",
+    );
+    assert_eq!(invalid_agent(&agent).as_deref(), reason);
+
+    agent.container_resources = Some(ContainerResources {
+        limits: Some(BTreeMap::from([("cpu".to_string(), "1".to_string())])),
+        requests: None,
+    });
+    assert_eq!(invalid_agent(&agent).as_deref(), reason);
+
+    agent.container_resources = Some(ContainerResources {
+        limits: Some(BTreeMap::from([("cpu".to_string(), "1".to_string())])),
+        requests: Some(BTreeMap::from([("memory".to_string(), "1Gi".to_string())])),
+    });
+    assert_eq!(invalid_agent(&agent), None);
+}
+
+#[tokio::test]
+async fn undecodable_test_is_marked_invalid() {
+    let mut valid = serde_json::json!(Test::new("valid", Default::default()));
+    valid["metadata"]["namespace"] = NAMESPACE.into();
+    let mut invalid = valid.clone();
+    invalid["metadata"]["name"] = "invalid".into();
+    invalid["spec"]["agent"]["restartPolicy"] = "Sometimes".into();
+    let k8s_client = crate::fake_api::fake_k8s_store(vec![valid, invalid]);
+    let api: Api<DynamicObject> = Api::namespaced_with(
+        k8s_client.clone(),
+        NAMESPACE,
+        &ApiResource::erase::<Test>(&()),
+    );
+
+    let marked = async {
+        mark_undecodable_tests(k8s_client.clone(), &ListParams::default()).await?;
+        Ok::<_, anyhow::Error>((api.get("valid").await?, api.get("invalid").await?))
+    }
+    .await;
+    assert!(
+        matches!(
+            &marked,
+            Ok((valid, invalid))
+                if valid.data["status"].is_null()
+                    && invalid.data["status"]["controller"]["invalidSpec"]
+                        .as_str()
+                        .map(|reason| reason.starts_with("The test cannot be read: unknown variant"))
+                        .unwrap_or(false)
+        ),
+        "{:?}",
+        marked
+    );
+}
@@ -0,0 +1,24 @@
+use log::debug;
+
+/// The version of the controller. Builds can set it with the `TESTSYS_CONTROLLER_VERSION`
+/// environment variable, otherwise the crate's version is used.
+pub(crate) const CONTROLLER_VERSION: &str = match option_env!("TESTSYS_CONTROLLER_VERSION") {
+    Some(version) => version,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+/// Ask the k8s API server for its version. Returns `None` if the version could not be detected.
+pub(crate) async fn kube_server_version(k8s_client: &kube::Client) -> Option<String> {
+    match k8s_client.apiserver_version().await {
+        Ok(info) => Some(info.git_version),
+        Err(e) => {
+            debug!("Unable to detect the k8s API server version: {}", e);
+            None
+        }
+    }
+}
+
+#[test]
+fn controller_version_is_set() {
+    assert!(!CONTROLLER_VERSION.is_empty());
+}
@@ -0,0 +1,15 @@
+/// Generate the gRPC service that test agents stream their results to the controller with.
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/results.proto");
+        // Use a vendored `protoc` so that building does not need one installed.
+        if let Ok(protoc) = protoc_bin_vendored::protoc_bin_path() {
+            std::env::set_var("PROTOC", protoc);
+        }
+        if let Err(e) = tonic_build::compile_protos("proto/results.proto") {
+            eprintln!("Unable to generate the results service: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
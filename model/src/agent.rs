@@ -33,6 +33,83 @@ impl Default for TaskState {
 
 serde_plain::derive_display_from_serialize!(TaskState);
 
+/// What happens to an agent's container when it exits with a failure.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+pub enum RestartPolicy {
+    /// The agent fails as soon as its container fails.
+    #[default]
+    Never,
+    /// The agent's container is restarted in place, with a backoff, a limited number of times
+    /// before the agent fails.
+    OnFailure,
+}
+
+serde_plain::derive_display_from_serialize!(RestartPolicy);
+
+/// When a test agent's results directory is collected into its results tarball.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+pub enum ArtifactsOn {
+    /// The results are collected whatever the outcome of the test.
+    #[default]
+    Always,
+    /// The results are only collected if the test did not pass, or the agent failed.
+    OnFailure,
+    /// The results are never collected.
+    Never,
+}
+
+serde_plain::derive_display_from_serialize!(ArtifactsOn);
+
+impl ArtifactsOn {
+    /// Whether the results of a test should be collected, given whether it passed.
+    pub fn collect(&self, passed: bool) -> bool {
+        match self {
+            ArtifactsOn::Always => true,
+            ArtifactsOn::OnFailure => !passed,
+            ArtifactsOn::Never => false,
+        }
+    }
+}
+
+/// The format of the results file a test framework writes, which the test agent library can parse
+/// into `TestResults` for the agent.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ResultsFormat {
+    /// JUnit XML, a `<testsuites>` or `<testsuite>` document of `<testcase>` elements.
+    Junit,
+    /// The Test Anything Protocol, `ok` and `not ok` lines.
+    Tap,
+}
+
+serde_plain::derive_display_from_serialize!(ResultsFormat);
+
+/// The kind of capacity that the nodes an agent pod is scheduled on are provisioned with.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum CapacityType {
+    /// Spot capacity, which is cheaper but can be reclaimed while the agent runs.
+    Spot,
+    /// On-demand capacity.
+    OnDemand,
+}
+
+serde_plain::derive_display_from_serialize!(CapacityType);
+
+/// The quality of service class of an agent pod, which decides how k8s treats it when its node is
+/// short of resources.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum Qos {
+    /// Every resource of the agent container is requested and limited to the same amount, which
+    /// keeps latency-sensitive tests from being throttled or evicted in favor of other pods.
+    Guaranteed,
+    /// The requests and limits of `container_resources` are used as they are.
+    Burstable,
+}
+
+serde_plain::derive_display_from_serialize!(Qos);
+
 #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Agent {
@@ -40,11 +117,16 @@ pub struct Agent {
     pub name: String,
     /// The URI of the agent container image.
     pub image: String,
+    /// The URI of the image that runs the agent's destroy job, for resource agents whose destroy
+    /// logic lives in a separate image. `image` is used when this is not set.
+    pub destroy_image: Option<String>,
     /// The name of an image registry pull secret if one is needed to pull the agent image.
     pub pull_secret: Option<String>,
     /// Determine if the pod should keep running after it has finished or encountered and error.
     pub keep_running: bool,
-    /// The maximum amount of time an agent should be left to run.
+    /// The maximum amount of time an agent should be left to run. The controller enforces it rather
+    /// than the agent's job, which has no `activeDeadlineSeconds`, and adds the extensions a test
+    /// agent asks for with `InfoClient::request_extension` to it.
     #[schemars(schema_with = "timeout_schema")]
     pub timeout: Option<String>,
     /// The configuration to pass to the agent. This is 'open' to allow agents to define their own
@@ -55,10 +137,301 @@ pub struct Agent {
     /// use it, and `SecretName` is provided by the user. `SecretName` is constrained to ascii
     /// alphanumerics plus underscores and dashes.
     pub secrets: Option<BTreeMap<SecretType, SecretName>>,
+    /// Secrets that are mounted as files at a chosen path in the agent container. Unlike
+    /// environment variables, their values do not show up in the pod's description.
+    pub secret_mounts: Option<Vec<SecretMount>>,
+    /// Additional environment variables for the agent container. Values can refer to the metadata
+    /// of the test or resource with `{{ .name }}`, `{{ .labels.<key> }}` and
+    /// `{{ .annotations.<key> }}`, the controller resolves them when it creates the agent's job.
+    /// A variable replaces the one the controller sets with the same name, e.g. `SSL_CERT_FILE`.
+    pub env: Option<BTreeMap<String, String>>,
     /// Linux capabilities to add for the agent container, e.g. NET_ADMIN
     pub capabilities: Option<Vec<String>>,
     /// Whether the agent container needs to be privileged or not
     pub privileged: Option<bool>,
+    /// A supplemental group applied to all containers in the agent pod. Volumes that support
+    /// ownership management will be owned and writable by this group.
+    pub fs_group: Option<i64>,
+    /// Defines the behavior of changing ownership and permission of volumes to `fs_group`, either
+    /// `OnRootMismatch` or `Always`.
+    pub fs_group_change_policy: Option<String>,
+    /// The service account the agent pod should run as. If this is not provided, the default
+    /// TestSys service account for the agent type is used. A custom service account needs the same
+    /// permissions as the default one.
+    pub service_account: Option<String>,
+    /// Whether a failed agent container is restarted in place (`OnFailure`) instead of failing the
+    /// agent (`Never` is the default).
+    #[serde(deserialize_with = "crate::schema_utils::null_to_default")]
+    #[serde(default)]
+    #[schemars(schema_with = "crate::schema_utils::nullable_enum::<RestartPolicy>")]
+    pub restart_policy: RestartPolicy,
+    /// The compute resources the agent container requests and is limited to. Limits and requests
+    /// are independent, a limit does not imply a request of the same amount.
+    pub container_resources: Option<ContainerResources>,
+    /// The quality of service class of the agent pod. With `guaranteed`, each resource only needs
+    /// to be given once in `container_resources`, either as a limit or as a request, and is both
+    /// requested and limited to that amount. The limit is used if both are given. Both `cpu` and
+    /// `memory` must be given, otherwise the agent is rejected.
+    pub qos: Option<Qos>,
+    /// Existing `PersistentVolumeClaim`s to mount into the agent container, e.g. for scratch space
+    /// that outlives a restarted agent pod.
+    pub persistent_volumes: Option<Vec<PersistentVolumeMount>>,
+    /// The number of indexed completions of the agent to run in parallel, e.g. one for each shard
+    /// of a distributed test. Each agent pod finds its index in the `JOB_COMPLETION_INDEX`
+    /// environment variable.
+    pub completions: Option<i32>,
+    /// The percentage of `completions` that must succeed for the test to pass (`100` is the
+    /// default). The agent's job tolerates a failure for each of its completions, or
+    /// `backoff_limit` failures for each if it is given, so a failed completion does not stop the
+    /// others. A failed completion may be retried while the job's failures are within that limit,
+    /// and the completions that never succeeded count as failed.
+    #[schemars(range(min = 0, max = 100))]
+    pub success_threshold_percent: Option<u8>,
+    /// The maximum amount of time the agent's `completions` may take altogether, from the start of
+    /// its job until all of them are done. Unlike `timeout`, the time between a failed completion
+    /// and its retry counts towards it.
+    #[schemars(schema_with = "timeout_schema")]
+    pub completions_deadline: Option<String>,
+    /// The format of the results file the agent's test framework writes, so that the agent can
+    /// parse it with the test agent library.
+    pub results_format: Option<ResultsFormat>,
+    /// Entries to add to the agent pod's `/etc/hosts`, e.g. for endpoints that are not registered
+    /// in DNS yet.
+    pub host_aliases: Option<Vec<HostAlias>>,
+    /// The number of times a failed agent pod is retried before the agent fails. Test agents fail
+    /// fast by default, resource agents are retried a few times.
+    #[schemars(range(min = 0))]
+    pub backoff_limit: Option<i32>,
+    /// A probe that must succeed before the agent container is considered started, for agents that
+    /// take a while to initialize. Liveness checks only begin once the startup probe succeeds.
+    pub startup_probe: Option<Probe>,
+    /// A probe that must succeed before the agent container is considered ready. If the test's
+    /// agent has one, the test is only reported as running once its agent is ready instead of as
+    /// soon as the agent has started.
+    pub readiness_probe: Option<Probe>,
+    /// The seccomp profile the agent container runs with, e.g. for nodes that require one.
+    pub seccomp_profile: Option<SeccompProfile>,
+    /// The AppArmor profile the agent container runs with, either `runtime/default`,
+    /// `localhost/<profile>` or `unconfined`. It is set with an annotation on the agent pod, which
+    /// is how AppArmor is configured on clusters older than Kubernetes 1.30.
+    pub app_armor_profile: Option<String>,
+    /// Node labels that the agent pod must be scheduled on a node with.
+    pub node_selector: Option<BTreeMap<String, String>>,
+    /// The architecture of the agent's image, e.g. `arm64`, which schedules the agent pod on a node
+    /// of that architecture. It takes precedence over a `kubernetes.io/arch` in `node_selector`.
+    pub arch: Option<String>,
+    /// The capacity type of the nodes the agent pod is scheduled on. It is selected with the node
+    /// label the controller is configured with, Karpenter's `karpenter.sh/capacity-type` unless
+    /// configured otherwise, and spot nodes tainted with that label are tolerated. It takes
+    /// precedence over that label in `node_selector`.
+    pub capacity_type: Option<CapacityType>,
+    /// DNS settings for the agent pod, added to the ones of the cluster's DNS.
+    pub dns_config: Option<DnsConfig>,
+    /// Additional domains to search when resolving short names in the agent pod, e.g. for internal
+    /// service names in split-horizon clusters. They are searched after those of `dns_config`.
+    pub dns_search_domains: Option<Vec<String>>,
+    /// Configuration that is too large to be convenient in `configuration`, e.g. a multi-kilobyte
+    /// JSON document. The controller stores it in a `ConfigMap` that is mounted in the agent
+    /// container as [`AGENT_CONFIG_FILE`] in [`AGENT_CONFIG_PATH`], and the agent libraries add its
+    /// fields to the agent's configuration, replacing those of `configuration` with the same name.
+    ///
+    /// [`AGENT_CONFIG_FILE`]: crate::constants::AGENT_CONFIG_FILE
+    /// [`AGENT_CONFIG_PATH`]: crate::constants::AGENT_CONFIG_PATH
+    #[schemars(schema_with = "config_schema")]
+    pub config_blob: Option<Map<String, Value>>,
+    /// Labels added to the agent's job and pod, e.g. for selection by network policies. They
+    /// cannot override the labels TestSys adds or the labels that the controller protects.
+    pub pod_labels: Option<BTreeMap<String, String>>,
+    /// Scheduling gates of the agent pod, which is not scheduled until an external process, e.g. a
+    /// quota approval, removes all of them from the pod. The agent's start time limit and `timeout`
+    /// are measured from when the pod is scheduled after its gates are removed.
+    pub scheduling_gates: Option<Vec<String>>,
+    /// Relaunch the test agent with a higher memory limit when its container is killed for running
+    /// out of memory, instead of failing the test. The agent must have a memory limit. Only a
+    /// test's own agent can set it, not its additional `agents` or the agents of resources.
+    pub oom_retry: Option<OomRetry>,
+    /// When the test agent library saves the test's results directory as the results tarball,
+    /// depending on the outcome of the test. `Always` if not set.
+    pub artifacts_on: Option<ArtifactsOn>,
+    /// Ports that the agent container listens on, e.g. for the other pods of a distributed test.
+    pub ports: Option<Vec<ContainerPort>>,
+    /// Create a headless `Service`, named after the agent's job, that selects the agent's pods so
+    /// that they can find each other by DNS. The pods of an agent with `completions` are reachable
+    /// as `<job name>-<index>.<job name>`.
+    pub headless_service: Option<bool>,
+}
+
+/// How the memory limit of an agent that ran out of memory is raised before it is relaunched.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OomRetry {
+    /// The factor the memory limit is multiplied by for each relaunch, `2` if not set.
+    #[schemars(range(min = 2))]
+    pub factor: Option<u32>,
+    /// The highest memory limit the agent is relaunched with, e.g. `8Gi`. Once the agent runs out
+    /// of memory with this limit the test fails as usual.
+    pub max_memory: String,
+}
+
+/// A seccomp profile for an agent container.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SeccompProfile {
+    /// Which kind of profile is applied.
+    #[serde(rename = "type")]
+    pub profile_type: SeccompProfileType,
+    /// The path of the profile on the node, relative to the kubelet's seccomp profile directory.
+    /// Only used, and required, for `Localhost` profiles.
+    pub localhost_profile: Option<String>,
+}
+
+/// The kinds of seccomp profile.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+pub enum SeccompProfileType {
+    /// The container runtime's default profile.
+    #[default]
+    RuntimeDefault,
+    /// A profile from a file on the node.
+    Localhost,
+    /// No profile is applied.
+    Unconfined,
+}
+
+serde_plain::derive_display_from_serialize!(SeccompProfileType);
+
+/// A port that an agent container listens on.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerPort {
+    /// The name of the port, which the agent's headless service publishes it under.
+    pub name: Option<String>,
+    /// The port number.
+    pub container_port: i32,
+    /// The protocol of the port, `TCP` if not set.
+    pub protocol: Option<PortProtocol>,
+}
+
+/// The network protocols of container ports.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PortProtocol {
+    #[default]
+    Tcp,
+    Udp,
+    Sctp,
+}
+
+serde_plain::derive_display_from_serialize!(PortProtocol);
+
+/// An `/etc/hosts` entry for an agent pod.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HostAlias {
+    /// The IP address the hostnames resolve to.
+    pub ip: String,
+    /// The hostnames that resolve to `ip`.
+    pub hostnames: Vec<String>,
+}
+
+/// DNS settings for an agent pod.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DnsConfig {
+    /// The IP addresses of additional name servers.
+    pub nameservers: Option<Vec<String>>,
+    /// Additional domains to search when resolving short names.
+    pub searches: Option<Vec<String>>,
+    /// Resolver options, e.g. `ndots`.
+    pub options: Option<Vec<DnsOption>>,
+}
+
+/// A resolver option for an agent pod, e.g. `ndots` with a value of `2`.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DnsOption {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+/// A check of an agent container that is run periodically by the kubelet. Exactly one of `exec`,
+/// `http_get` and `tcp_socket_port` should be given.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Probe {
+    /// A command to run in the container, the probe succeeds if it exits with status `0`.
+    pub exec: Option<Vec<String>>,
+    /// An HTTP `GET` request to the container, the probe succeeds if the response status is at
+    /// least `200` and less than `400`.
+    pub http_get: Option<HttpGetProbe>,
+    /// A port of the container, the probe succeeds if a TCP connection to it can be opened.
+    pub tcp_socket_port: Option<i32>,
+    /// The number of seconds after the container has started before the probe is first run.
+    pub initial_delay_seconds: Option<i32>,
+    /// How often the probe is run, in seconds.
+    pub period_seconds: Option<i32>,
+    /// The number of seconds after which a single run of the probe times out.
+    pub timeout_seconds: Option<i32>,
+    /// The number of consecutive failed runs of the probe after which the container is restarted.
+    pub failure_threshold: Option<i32>,
+}
+
+/// An HTTP `GET` request made by a [`Probe`].
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpGetProbe {
+    /// The path to request, e.g. `/healthz`.
+    pub path: String,
+    /// The port of the container to send the request to.
+    pub port: i32,
+}
+
+/// A secret in the TestSys namespace, where to mount it in an agent container, and optionally which
+/// configuration field the agent runtime loads its keys into.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretMount {
+    /// The name of the existing secret.
+    pub secret_name: SecretName,
+    /// The directory in the agent container where each key of the secret is mounted as a file.
+    pub mount_path: String,
+    /// The configuration field that the resource agent runtime sets to a map of the secret's keys
+    /// and values, read from the mounted files. The secret is only mounted if this is not set.
+    pub config_field: Option<String>,
+}
+
+/// A `PersistentVolumeClaim` in the TestSys namespace and where to mount it in an agent container.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PersistentVolumeMount {
+    /// The name of the existing `PersistentVolumeClaim`.
+    pub claim_name: String,
+    /// The path in the agent container where the volume is mounted.
+    pub mount_path: String,
+    /// Whether the volume is mounted read-only (`false` is the default).
+    pub read_only: Option<bool>,
+}
+
+/// Compute resources for an agent container, e.g. `cpu: 500m` or `memory: 1Gi`.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerResources {
+    /// The maximum amount of each resource the agent container may use.
+    pub limits: Option<BTreeMap<String, String>>,
+    /// The amount of each resource that must be available to schedule the agent container.
+    pub requests: Option<BTreeMap<String, String>>,
+}
+
+impl ContainerResources {
+    /// The resources with each resource that is limited or requested both limited and requested to
+    /// the same amount, its limit if it has one.
+    pub fn guaranteed(&self) -> Self {
+        let mut amounts = self.requests.clone().unwrap_or_default();
+        amounts.extend(self.limits.clone().unwrap_or_default());
+        Self {
+            limits: Some(amounts.clone()),
+            requests: Some(amounts),
+        }
+    }
 }
 
 impl Agent {
@@ -68,6 +441,13 @@ impl Agent {
             .map(|secrets_map| secrets_map.values().collect::<BTreeSet<&SecretName>>())
             .unwrap_or_default()
     }
+
+    /// Whether enough of the agent's completions succeeded to meet its
+    /// `success_threshold_percent`.
+    pub fn success_threshold_met(&self, succeeded: i32, failed: i32) -> bool {
+        let threshold = i64::from(self.success_threshold_percent.unwrap_or(100).min(100));
+        i64::from(succeeded) * 100 >= threshold * (i64::from(succeeded) + i64::from(failed))
+    }
 }
 
 pub fn config_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
@@ -85,6 +465,10 @@ pub fn config_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema
     schema.into()
 }
 
+/// The format of an agent's `timeout`, e.g. `1d2h30m` or a number of seconds.
+pub(crate) const TIMEOUT_PATTERN: &str =
+    r"^((([0-9]+)d)?(([0-9]+)h)?(([0-9]+)m)?(([0-9]+)s)?|\d+)$";
+
 pub fn timeout_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
     let mut extensions = BTreeMap::<String, Value>::new();
     extensions.insert("nullable".to_string(), Value::Bool(true));
@@ -92,7 +476,7 @@ pub fn timeout_schema(_: &mut schemars::gen::SchemaGenerator) -> schemars::schem
         string: Some(Box::new(StringValidation {
             max_length: Some(253),
             min_length: Some(1),
-            pattern: Some(r"^((([0-9]+)d)?(([0-9]+)h)?(([0-9]+)m)?(([0-9]+)s)?|\d+)$".to_string()),
+            pattern: Some(TIMEOUT_PATTERN.to_string()),
         })),
         instance_type: Some(InstanceType::String.into()),
         extensions,
@@ -295,3 +679,33 @@ fn secret_name_deserialize() {
     let deserialized = serde_json::from_value::<Something>(good_json).unwrap();
     assert_eq!(deserialized.foo.as_str(), "bar-baz");
 }
+
+#[cfg(test)]
+fn agent_with_threshold(success_threshold_percent: Option<u8>) -> Agent {
+    Agent {
+        completions: Some(10),
+        success_threshold_percent,
+        ..Agent::default()
+    }
+}
+
+#[test]
+fn success_threshold_exactly_met() {
+    let agent = agent_with_threshold(Some(90));
+    assert!(agent.success_threshold_met(9, 1));
+    assert!(agent.success_threshold_met(90, 10));
+}
+
+#[test]
+fn success_threshold_just_missed() {
+    let agent = agent_with_threshold(Some(90));
+    assert!(!agent.success_threshold_met(8, 2));
+    assert!(!agent.success_threshold_met(899, 101));
+}
+
+#[test]
+fn success_threshold_defaults_to_all() {
+    let agent = agent_with_threshold(None);
+    assert!(agent.success_threshold_met(10, 0));
+    assert!(!agent.success_threshold_met(99, 1));
+}
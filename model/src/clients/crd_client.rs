@@ -1,6 +1,6 @@
 use super::HttpStatusCode;
 use crate::clients::error::{self, Result};
-use crate::constants::NAMESPACE;
+use crate::constants::{LABEL_CONTROLLER_INSTANCE, NAMESPACE, TRUNCATED_MARKER};
 use crate::CrdExt;
 use chrono::{DateTime, SecondsFormat, Utc};
 use core::fmt::Debug;
@@ -8,14 +8,18 @@ use http::StatusCode;
 use json_patch::{AddOperation, PatchOperation, RemoveOperation, ReplaceOperation, TestOperation};
 use k8s_openapi::NamespaceResourceScope;
 use kube::api::{ListParams, Patch, PatchParams, PostParams};
-use kube::{Api, Resource};
+use kube::{Api, Resource, ResourceExt};
 use log::trace;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
-use snafu::{ensure, OptionExt, ResultExt};
+use snafu::ResultExt;
+use std::collections::BTreeMap;
 use std::time::{Duration, SystemTime};
 
+/// The path of the time a status was last updated, which every status patch sets.
+const TIMESTAMP_PATH: &str = "/status/lastUpdate";
+
 /// A trait with implementations of code that is shared between more than one CRD object.
 #[async_trait::async_trait]
 pub trait CrdClient: Sized {
@@ -36,6 +40,12 @@ pub trait CrdClient: Sized {
     fn kind(&self) -> &'static str;
     fn api(&self) -> &Api<Self::Crd>;
 
+    /// The maximum length of the strings written by [`CrdClient::patch_status`]. Longer strings
+    /// are truncated and end with [`TRUNCATED_MARKER`]. `None` means strings are not truncated.
+    fn max_status_field_len(&self) -> Option<usize> {
+        None
+    }
+
     async fn new() -> Result<Self> {
         let k8s_client = kube::Client::try_default()
             .await
@@ -155,9 +165,17 @@ pub trait CrdClient: Sized {
         .await
     }
 
-    /// Add a finalizer. Checks `crd` to make sure the finalizer is not a duplicate. Replaces the
-    /// finalizer array with those found in `crd` plus the new `finalizer`.
+    /// Add a finalizer. Nothing is done if `crd` already has the finalizer. Replaces the finalizer
+    /// array with those found in `crd` plus the new `finalizer`.
     async fn add_finalizer(&self, finalizer: &str, crd: &Self::Crd) -> Result<Self::Crd> {
+        if crd.has_finalizer(finalizer) {
+            trace!(
+                "finalizer {} already exists for {}",
+                finalizer,
+                crd.object_name()
+            );
+            return Ok(crd.clone());
+        }
         trace!("adding finalizer {} for {}", finalizer, crd.object_name());
 
         // Initialize finalizer array if it doesn't exist.
@@ -173,10 +191,6 @@ pub trait CrdClient: Sized {
             )
             .await
         } else {
-            ensure!(
-                !crd.has_finalizer(finalizer),
-                error::DuplicateFinalizerSnafu { finalizer }
-            );
             self.patch(
                 crd.object_name(),
                 vec![
@@ -193,15 +207,22 @@ pub trait CrdClient: Sized {
         }
     }
 
-    /// Remove a finalizer. Checks `crd` to make sure the finalizer actually existed. Replaces the
+    /// Remove a finalizer. Nothing is done if `crd` does not have the finalizer. Replaces the
     /// finalizer array with those found in `crd` minus the removed `finalizer`.
     async fn remove_finalizer(&self, finalizer: &str, crd: &Self::Crd) -> Result<Self::Crd> {
+        let finalizer_idx = match crd.finalizer_position(finalizer) {
+            Some(finalizer_idx) => finalizer_idx,
+            None => {
+                trace!(
+                    "finalizer {} already removed for {}",
+                    finalizer,
+                    crd.object_name()
+                );
+                return Ok(crd.clone());
+            }
+        };
         trace!("removing finalizer {} for {}", finalizer, crd.object_name());
 
-        let finalizer_idx = crd
-            .finalizer_position(finalizer)
-            .context(error::DeleteMissingFinalizerSnafu { finalizer })?;
-
         self.patch(
             crd.object_name(),
             vec![
@@ -258,10 +279,16 @@ pub trait CrdClient: Sized {
         I: IntoIterator<Item = JsonPatch> + Send,
     {
         let name = name.as_ref();
+        let max_len = self.max_status_field_len();
         let patch = json_patch::Patch(
             patches
                 .into_iter()
-                .map(|item| item.into_json_patch_operation())
+                .map(|mut item| {
+                    if let Some(max_len) = max_len {
+                        item.truncate_strings(max_len);
+                    }
+                    item.into_json_patch_operation()
+                })
                 .collect(),
         );
         Ok(self
@@ -277,6 +304,91 @@ pub trait CrdClient: Sized {
                 name,
             })?)
     }
+
+    /// Apply JSON patches that apply to the `/status` path of `current`, the caller's copy of the
+    /// object, unless they would leave it as it is. Timestamps are not compared, every patch would
+    /// change them. Returns `None` if there was nothing to send.
+    async fn patch_status_if_changed<I, S>(
+        &self,
+        current: &Self::Crd,
+        patches: I,
+        description: S,
+    ) -> Result<Option<Self::Crd>>
+    where
+        S: Into<String> + Send,
+        I: IntoIterator<Item = JsonPatch> + Send,
+    {
+        let patches: Vec<JsonPatch> = patches.into_iter().collect();
+        let max_len = self.max_status_field_len();
+        let changes = json_patch::Patch(
+            patches
+                .iter()
+                .filter(|item| !item.is_timestamp())
+                .cloned()
+                .map(|mut item| {
+                    if let Some(max_len) = max_len {
+                        item.truncate_strings(max_len);
+                    }
+                    item.into_json_patch_operation()
+                })
+                .collect(),
+        );
+        // Anything that cannot be compared is sent.
+        let unchanged = serde_json::to_value(current)
+            .map(|before| {
+                let mut after = before.clone();
+                json_patch::patch(&mut after, &changes).is_ok() && after == before
+            })
+            .unwrap_or(false);
+        if unchanged {
+            return Ok(None);
+        }
+        self.patch_status(current.name_any(), patches, description)
+            .await
+            .map(Some)
+    }
+
+    /// Claim the `object` for the controller instance `instance_id` by setting the
+    /// `testsys.system/controller-instance` label, so that other controller instances that share
+    /// the cluster leave it alone. The claim fails if the object has changed since it was read, so
+    /// two instances that race to claim it cannot both succeed.
+    async fn claim_for_instance(&self, object: &Self::Crd, instance_id: &str) -> Result<Self::Crd> {
+        let mut patches = Vec::new();
+        if let Some(version) = object.resource_version() {
+            patches.push(JsonPatch::new_test_operation(
+                "/metadata/resourceVersion",
+                version,
+            ));
+        }
+        patches.push(metadata_patch(
+            "labels",
+            object.object_meta().labels.is_some(),
+            LABEL_CONTROLLER_INSTANCE,
+            instance_id,
+        ));
+        self.patch(object.name_any(), patches, "claim for instance")
+            .await
+    }
+}
+
+/// A patch that sets `key` to `value` in the metadata `field`, e.g. `labels`, which must be created
+/// unless it `exists`.
+pub(super) fn metadata_patch(field: &str, exists: bool, key: &str, value: &str) -> JsonPatch {
+    if exists {
+        JsonPatch::new_add_operation(
+            format!(
+                "/metadata/{}/{}",
+                field,
+                key.replace('~', "~0").replace('/', "~1")
+            ),
+            value,
+        )
+    } else {
+        JsonPatch::new_add_operation(
+            format!("/metadata/{}", field),
+            BTreeMap::from([(key, value)]),
+        )
+    }
 }
 
 /// The JSON patch operation type.
@@ -289,6 +401,7 @@ pub(super) enum PatchOp {
 }
 
 /// Information for constructing a JSON patch.
+#[derive(Debug, Clone)]
 pub struct JsonPatch {
     op: PatchOp,
     path: String,
@@ -343,6 +456,14 @@ impl JsonPatch {
         }
     }
 
+    /// Truncate the strings in the value being written to `max_len` bytes. The values of test
+    /// operations are compared with what is stored and are left alone.
+    pub(super) fn truncate_strings(&mut self, max_len: usize) {
+        if !matches!(self.op, PatchOp::Test) {
+            truncate_strings(&mut self.value, max_len);
+        }
+    }
+
     pub(super) fn into_json_patch_operation(self) -> PatchOperation {
         match self.op {
             PatchOp::Add => PatchOperation::Add(AddOperation {
@@ -361,10 +482,15 @@ impl JsonPatch {
         }
     }
 
+    /// Whether this is the patch of the status's last update time from [`Self::new_timestamp`].
+    pub(super) fn is_timestamp(&self) -> bool {
+        self.path == TIMESTAMP_PATH
+    }
+
     pub fn new_timestamp() -> Self {
         Self {
             op: PatchOp::Replace,
-            path: "/status/lastUpdate".to_string(),
+            path: TIMESTAMP_PATH.to_string(),
             value: serde_json::Value::String(
                 Into::<DateTime<Utc>>::into(SystemTime::now())
                     .to_rfc3339_opts(SecondsFormat::Secs, true),
@@ -372,3 +498,46 @@ impl JsonPatch {
         }
     }
 }
+
+/// Truncate every string in `value` that is longer than `max_len` bytes so that it is `max_len`
+/// bytes at most. The [`TRUNCATED_MARKER`] replaces the end of a truncated string unless `max_len`
+/// is too short to hold it.
+fn truncate_strings(value: &mut Value, max_len: usize) {
+    match value {
+        Value::String(s) if s.len() > max_len => {
+            let marker = if max_len >= TRUNCATED_MARKER.len() {
+                TRUNCATED_MARKER
+            } else {
+                ""
+            };
+            let mut end = max_len - marker.len();
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            s.truncate(end);
+            s.push_str(marker);
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| truncate_strings(value, max_len)),
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|value| truncate_strings(value, max_len)),
+        _ => {}
+    }
+}
+
+#[test]
+fn truncated_strings_fit_max_len() {
+    let mut value = serde_json::json!({ "log": "é".repeat(100), "short": "ok" });
+    truncate_strings(&mut value, 64);
+    let log = value["log"].as_str().unwrap_or_default();
+    assert!(log.len() <= 64);
+    assert!(log.ends_with(TRUNCATED_MARKER));
+    assert_eq!(value["short"], "ok");
+
+    // A limit too short for the marker still holds.
+    let mut value = serde_json::json!("é".repeat(100));
+    truncate_strings(&mut value, 5);
+    assert_eq!(value, "éé");
+}
@@ -45,20 +45,17 @@ pub(crate) enum InnerError {
         source: kube::Error,
     },
 
-    #[snafu(display(
-        "An attempt was made to add the finalizer '{}' more than once",
-        finalizer,
-    ))]
-    DuplicateFinalizer { finalizer: String },
-
-    #[snafu(display(
-        "An attempt was made to delete the non-existent finalizer '{}'",
-        finalizer,
-    ))]
-    DeleteMissingFinalizer { finalizer: String },
-
     #[snafu(display("A resource errored during deletion '{}'", name))]
     DeleteFail { name: String },
+
+    #[snafu(display("Test '{}' was deleted before it finished", name))]
+    TestDeleted { name: String },
+
+    #[snafu(display("Test '{}' did not finish within {:?}", name, timeout))]
+    WaitTimeout {
+        name: String,
+        timeout: std::time::Duration,
+    },
 }
 
 impl From<ModelError> for Error {
@@ -84,9 +81,9 @@ impl HttpStatusCode for InnerError {
                 name: _,
                 source: e,
             } => e.status_code(),
-            InnerError::DuplicateFinalizer { .. }
-            | InnerError::DeleteMissingFinalizer { .. }
-            | InnerError::DeleteFail { .. } => None,
+            InnerError::DeleteFail { .. }
+            | InnerError::TestDeleted { .. }
+            | InnerError::WaitTimeout { .. } => None,
         }
     }
 }
@@ -2,9 +2,9 @@ use super::error::{self, Result};
 use super::HttpStatusCode;
 use crate::clients::crd_client::JsonPatch;
 use crate::clients::CrdClient;
-use crate::constants::{FINALIZER_RESOURCE, NAMESPACE};
-use crate::resource::{ResourceAction, ResourceError};
-use crate::{Configuration, Resource, ResourceSpec, ResourceStatus, TaskState};
+use crate::constants::{FINALIZER_RESOURCE, LABEL_POOL_BORROWER, LABEL_POOL_KEY, NAMESPACE};
+use crate::resource::{OutputField, ResourceAction, ResourceError};
+use crate::{Configuration, DestructionPolicy, Resource, ResourceSpec, ResourceStatus, TaskState};
 use async_recursion::async_recursion;
 use futures::stream::{self, StreamExt};
 use http::StatusCode;
@@ -125,6 +125,158 @@ impl ResourceClient {
         .await
     }
 
+    /// Take the idle pool entry `name` out of the pool with `pool_key` so that its created resource
+    /// can be borrowed by the resource with the UID `borrower_uid`. The entry is labeled with the
+    /// borrower so that an interrupted borrow can be finished, and will no longer be destroyed when
+    /// it is deleted. Fails if another resource claimed the entry first.
+    pub async fn claim_pool_entry(
+        &self,
+        name: &str,
+        pool_key: &str,
+        borrower_uid: &str,
+    ) -> Result<Resource> {
+        trace!("claiming pool entry '{}'", name);
+        let label_path = format!("/metadata/labels/{}", LABEL_POOL_KEY.replace('/', "~1"));
+        self.patch(
+            name,
+            vec![
+                JsonPatch::new_test_operation(&label_path, pool_key),
+                JsonPatch::new_remove_operation(&label_path),
+                JsonPatch::new_add_operation(
+                    format!(
+                        "/metadata/labels/{}",
+                        LABEL_POOL_BORROWER.replace('/', "~1")
+                    ),
+                    borrower_uid,
+                ),
+                JsonPatch::new_replace_operation(
+                    "/spec/destructionPolicy",
+                    DestructionPolicy::Never,
+                ),
+            ],
+            "claim pool entry",
+        )
+        .await
+    }
+
+    /// Add a reference to the shared `resource` for the test named `test_name`. The first reference
+    /// creates the list of references, so it fails if the resource changed since `resource` was
+    /// read, and two tests that add the first reference at the same time cannot both succeed.
+    pub async fn add_reference(&self, resource: &Resource, test_name: &str) -> Result<Resource> {
+        trace!(
+            "adding reference of test '{}' to resource '{}'",
+            test_name,
+            resource.name_any()
+        );
+        let mut patches = vec![JsonPatch::new_timestamp()];
+        if resource.references().is_empty() {
+            if let Some(version) = resource.resource_version() {
+                patches.push(JsonPatch::new_test_operation(
+                    "/metadata/resourceVersion",
+                    version,
+                ));
+            }
+            patches.push(JsonPatch::new_add_operation(
+                "/status/references",
+                [test_name],
+            ));
+        } else {
+            patches.push(JsonPatch::new_add_operation(
+                "/status/references/-",
+                test_name,
+            ));
+        }
+        self.patch_status(resource.name_any(), patches, "add reference")
+            .await
+    }
+
+    /// Remove the reference of the test named `test_name` from the shared `resource`. Fails if the
+    /// references changed since `resource` was read.
+    pub async fn remove_reference(&self, resource: &Resource, test_name: &str) -> Result<Resource> {
+        trace!(
+            "removing reference of test '{}' from resource '{}'",
+            test_name,
+            resource.name_any()
+        );
+        let mut patches = vec![JsonPatch::new_timestamp()];
+        if let Some(index) = resource
+            .references()
+            .iter()
+            .position(|reference| reference == test_name)
+        {
+            let path = format!("/status/references/{}", index);
+            patches.push(JsonPatch::new_test_operation(&path, test_name));
+            patches.push(JsonPatch::new_remove_operation(path));
+        }
+        self.patch_status(resource.name_any(), patches, "remove reference")
+            .await
+    }
+
+    /// Copy the fields of `status` that describe a created resource that was borrowed from, or
+    /// returned to, a pool to `resource`, see [`Resource::pooled_status`]. The rest of the status
+    /// of `resource` is kept. If `resource` has no status yet, the status is only added if it
+    /// still has none.
+    pub async fn send_pooled_status(
+        &self,
+        resource: &Resource,
+        status: ResourceStatus,
+    ) -> Result<Resource> {
+        trace!(
+            "patching pooled status for resource '{}'",
+            resource.name_any()
+        );
+        let patches = if resource.status.is_none() {
+            vec![
+                JsonPatch::new_test_operation("/status", Option::<ResourceStatus>::None),
+                JsonPatch::new_add_operation("/status", status),
+                JsonPatch::new_timestamp(),
+            ]
+        } else {
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/creation", status.creation),
+                JsonPatch::new_add_operation("/status/agentInfo", status.agent_info),
+                JsonPatch::new_add_operation("/status/createdResource", status.created_resource),
+                JsonPatch::new_add_operation("/status/outputSchema", status.output_schema),
+                JsonPatch::new_add_operation("/status/ready", status.ready),
+            ]
+        };
+        self.patch_status(resource.name_any(), patches, "send pooled status")
+            .await
+    }
+
+    /// Record the fields of the created resource that the resource agent declares.
+    pub async fn send_output_schema(
+        &self,
+        name: &str,
+        output_schema: &[OutputField],
+    ) -> Result<Resource> {
+        trace!("patching output schema for resource '{}'", name);
+        self.patch_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/outputSchema", output_schema),
+            ],
+            "send output schema",
+        )
+        .await
+    }
+
+    /// Record whether the created resource is ready for use.
+    pub async fn send_ready(&self, name: &str, ready: bool) -> Result<Resource> {
+        trace!("patching ready '{}' for resource '{}'", ready, name);
+        self.patch_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/ready", ready),
+            ],
+            "send ready",
+        )
+        .await
+    }
+
     pub async fn get_created_resource<R>(&self, name: &str) -> Result<Option<R>>
     where
         R: Configuration,
@@ -574,11 +726,12 @@ mod test {
         // The finalizer is present
         assert!(rc.get(RESOURCE_NAME).await.unwrap().has_finalizer("foobar"));
 
-        // Cannot add the finalizer twice
+        // Adding the finalizer again does nothing
         assert!(rc
             .add_finalizer("foobar", &rc.get(RESOURCE_NAME).await.unwrap())
             .await
-            .is_err());
+            .unwrap()
+            .has_finalizer("foobar"));
 
         // Remove the finalizer
         rc.remove_finalizer("foobar", &rc.get(RESOURCE_NAME).await.unwrap())
@@ -588,10 +741,11 @@ mod test {
         // No longer present
         assert!(!rc.get(RESOURCE_NAME).await.unwrap().has_finalizer("foobar"));
 
-        // Cannot remove it if it is not present
-        assert!(rc
+        // Removing it again does nothing
+        assert!(!rc
             .remove_finalizer("foobar", &rc.get(RESOURCE_NAME).await.unwrap())
             .await
-            .is_err());
+            .unwrap()
+            .has_finalizer("foobar"));
     }
 }
@@ -1,11 +1,28 @@
-use super::error::Result;
-use crate::clients::crd_client::JsonPatch;
-use crate::clients::CrdClient;
-use crate::constants::NAMESPACE;
-use crate::{AgentStatus, TaskState, Test, TestResults, TestSpec, TestStatus};
+use super::error::{self, Result};
+use crate::clients::crd_client::{metadata_patch, JsonPatch};
+use crate::clients::{AllowNotFound, CrdClient};
+use crate::constants::{
+    ANNOTATION_ARCHIVE, ANNOTATION_KEEP_RESOURCES, ANNOTATION_RECONCILE_REQUESTED,
+    DEFAULT_MAX_STATUS_FIELD_LEN, NAMESPACE,
+};
+use crate::{
+    AgentStatus, Completions, JobProgress, ResourceSummary, TaskState, Test, TestCondition,
+    TestResults, TestSpec, TestStatus, TestUserState, TimelineEntry,
+};
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::{Event, EventSource, ObjectReference};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::{SecondsFormat, Utc};
+use kube::api::{PostParams, WatchEvent, WatchParams};
 use kube::core::ObjectMeta;
-use kube::Api;
+use kube::{Api, ResourceExt};
+use serde_json::Value;
+use snafu::ResultExt;
 use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The component that events recorded with a `TestClient` are reported by.
+const EVENT_SOURCE: &str = "testsys-test-agent";
 
 /// An API Client for TestSys Test CRD objects.
 ///
@@ -21,9 +38,95 @@ use std::collections::BTreeMap;
 #[derive(Clone)]
 pub struct TestClient {
     api: Api<Test>,
+    /// The additional agent from `spec.agents` whose status is sent, `None` for `spec.agent`.
+    agent_name: Option<String>,
+    /// Strings written to the test's status are truncated to this many bytes.
+    max_status_field_len: usize,
+    /// The caller's copy of the test, if status writes that would not change it are skipped.
+    current: Option<Box<Test>>,
 }
 
 impl TestClient {
+    /// Send agent status updates for the additional agent named `agent_name` from `spec.agents` to
+    /// `status.agents.<agent_name>` instead of `status.agent`.
+    pub fn for_agent<S>(mut self, agent_name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.agent_name = Some(agent_name.into());
+        self
+    }
+
+    /// Truncate strings written to the test's status, e.g. agent errors, to `max_len` bytes instead
+    /// of [`DEFAULT_MAX_STATUS_FIELD_LEN`].
+    pub fn with_max_status_field_len(mut self, max_len: usize) -> Self {
+        self.max_status_field_len = max_len;
+        self
+    }
+
+    /// Skip the status writes to `test`, the caller's copy of the test, that would leave its status
+    /// as it is, see [`CrdClient::patch_status_if_changed`]. The writes that are skipped return
+    /// `test` instead of the test as it is stored.
+    pub fn skip_unchanged(mut self, test: &Test) -> Self {
+        self.current = Some(Box::new(test.clone()));
+        self
+    }
+
+    /// Patch the status of the test `name`. Nothing is sent if it is the test that unchanged writes
+    /// are skipped for and the patches would not change it.
+    async fn send_status<S1, S2>(
+        &self,
+        name: S1,
+        patches: Vec<JsonPatch>,
+        description: S2,
+    ) -> Result<Test>
+    where
+        S1: AsRef<str> + Send,
+        S2: Into<String> + Send,
+    {
+        match &self.current {
+            Some(current) if current.name_any() == name.as_ref() => Ok(self
+                .patch_status_if_changed(current, patches, description)
+                .await?
+                .unwrap_or_else(|| current.as_ref().clone())),
+            _ => self.patch_status(name, patches, description).await,
+        }
+    }
+
+    /// The JSON pointer to `field` in the status of the agent this client sends updates for.
+    fn agent_status_path(&self, field: &str) -> String {
+        match &self.agent_name {
+            // '~' and '/' must be escaped in a JSON pointer.
+            Some(agent_name) => format!(
+                "/status/agents/{}/{}",
+                agent_name.replace('~', "~0").replace('/', "~1"),
+                field
+            ),
+            None => format!("/status/agent/{}", field),
+        }
+    }
+
+    /// Reset the statuses of the test's additional agents from `spec.agents` before they are
+    /// started.
+    pub async fn initialize_agent_statuses<S>(&self, name: &str, agent_names: &[S]) -> Result<Test>
+    where
+        S: AsRef<str>,
+    {
+        let agents: BTreeMap<&str, AgentStatus> = agent_names
+            .iter()
+            .map(|agent_name| (agent_name.as_ref(), AgentStatus::default()))
+            .collect();
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/agents", agents),
+            ],
+            "initialize agent statuses",
+        )
+        .await
+    }
+
     /// Mark the TestSys [`Test`] as ok to delete by setting the `keep_running`
     /// flag to false
     pub async fn send_keep_running<S>(&self, name: S, keep_running: bool) -> Result<Test>
@@ -41,16 +144,30 @@ impl TestClient {
         .await
     }
 
-    /// Get the TestSys [`Test`]'s `status.agent` field.
+    /// Get the TestSys [`Test`] if it exists. Returns `Ok(None)` if the [`Test`] is not found
+    /// instead of returning an error.
+    pub async fn get_opt<S>(&self, name: S) -> Result<Option<Test>>
+    where
+        S: AsRef<str> + Send,
+    {
+        self.get(name).await.allow_not_found(|_| ())
+    }
+
+    /// Get the status of the agent this client sends updates for, by default the TestSys
+    /// [`Test`]'s `status.agent` field.
     pub async fn get_agent_status<S>(&self, name: S) -> Result<AgentStatus>
     where
         S: AsRef<str> + Send,
     {
-        Ok(self.get(name).await?.status.unwrap_or_default().agent)
+        let test = self.get(name).await?;
+        Ok(match &self.agent_name {
+            Some(agent_name) => test.additional_agent_status(agent_name).into_owned(),
+            None => test.status.unwrap_or_default().agent,
+        })
     }
 
     pub async fn send_resource_error(&self, test_name: &str, error: &str) -> Result<Test> {
-        self.patch_status(
+        self.send_status(
             test_name,
             vec![
                 JsonPatch::new_timestamp(),
@@ -61,25 +178,447 @@ impl TestClient {
         .await
     }
 
-    pub async fn send_agent_task_state(&self, name: &str, task_state: TaskState) -> Result<Test> {
-        self.patch_status(
+    /// Copy the user metadata from the test's spec into its status.
+    pub async fn send_metadata(
+        &self,
+        name: &str,
+        metadata: &BTreeMap<String, String>,
+    ) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/metadata", metadata),
+            ],
+            "send metadata",
+        )
+        .await
+    }
+
+    /// Write summaries of the test's resources, keyed by resource name, to its status.
+    pub async fn send_resource_summaries(
+        &self,
+        name: &str,
+        summaries: &BTreeMap<String, ResourceSummary>,
+    ) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/resources", summaries),
+            ],
+            "send resource summaries",
+        )
+        .await
+    }
+
+    pub async fn send_preflight_error(&self, test_name: &str, error: &str) -> Result<Test> {
+        self.send_status(
+            test_name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/preflightError", error),
+            ],
+            "send preflight error",
+        )
+        .await
+    }
+
+    /// Record why the test's spec is invalid.
+    pub async fn send_invalid_spec(&self, name: &str, reason: &str) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/invalidSpec", reason),
+            ],
+            "send invalid spec",
+        )
+        .await
+    }
+
+    /// Record that the controller has seen `generation` of the test's spec. If `reset` is `true`
+    /// the transient controller status fields, which describe an earlier generation of the spec,
+    /// are reset. The agent's status, including its results, is left alone.
+    pub async fn send_observed_generation(
+        &self,
+        name: &str,
+        generation: i64,
+        reset: bool,
+    ) -> Result<Test> {
+        let mut patches = vec![
+            JsonPatch::new_timestamp(),
+            JsonPatch::new_add_operation("/status/controller/observedGeneration", generation),
+        ];
+        if reset {
+            patches.extend([
+                JsonPatch::new_add_operation("/status/controller/resourceError", Value::Null),
+                JsonPatch::new_add_operation("/status/controller/jobCreationFailures", 0),
+                JsonPatch::new_add_operation("/status/controller/preflightError", Value::Null),
+                JsonPatch::new_add_operation("/status/controller/invalidSpec", Value::Null),
+                JsonPatch::new_add_operation("/status/controller/finishedAt", Value::Null),
+                JsonPatch::new_add_operation("/status/controller/failedAssertions", Value::Null),
+            ]);
+        }
+        self.send_status(name, patches, "send observed generation")
+            .await
+    }
+
+    /// Record the versions of the controller and the k8s API server that are running the test so
+    /// that its results can be traced back to them.
+    pub async fn send_versions(
+        &self,
+        name: &str,
+        controller_version: &str,
+        kube_server_version: Option<&str>,
+    ) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation(
+                    "/status/controller/controllerVersion",
+                    controller_version,
+                ),
+                JsonPatch::new_add_operation(
+                    "/status/controller/kubeServerVersion",
+                    kube_server_version,
+                ),
+            ],
+            "send versions",
+        )
+        .await
+    }
+
+    /// Record the ID that ties the controller's logs for the test to the logs of its agents.
+    pub async fn send_correlation_id(&self, name: &str, correlation_id: &str) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/correlationId", correlation_id),
+            ],
+            "send correlation id",
+        )
+        .await
+    }
+
+    /// Wait for the test named `name` to reach a terminal state, e.g. `Passed` or `Failed`, and
+    /// return it. The test is watched instead of polled. An error is returned if the test is not
+    /// done within `timeout` or if it is deleted.
+    pub async fn wait_for_terminal(&self, name: &str, timeout: Duration) -> Result<TestUserState> {
+        tokio::time::timeout(timeout, self.watch_until_terminal(name))
+            .await
+            .map_err(|_| {
+                error::WaitTimeoutSnafu {
+                    name,
+                    timeout: timeout.to_owned(),
+                }
+                .build()
+            })?
+    }
+
+    async fn watch_until_terminal(&self, name: &str) -> Result<TestUserState> {
+        let watch_params = WatchParams::default().fields(&format!("metadata.name={}", name));
+        loop {
+            // The watch starts from the test's current version so that no change is missed.
+            let test = self.get(name).await?;
+            let state = test.test_user_state();
+            if state.is_terminal() {
+                return Ok(state);
+            }
+            let version = test.resource_version().unwrap_or_default();
+            let mut events = self
+                .api
+                .watch(&watch_params, &version)
+                .await
+                .context(error::KubeApiCallForSnafu {
+                    operation: "watch test",
+                    name,
+                })?
+                .boxed();
+            while let Some(event) = events
+                .try_next()
+                .await
+                .context(error::KubeApiCallForSnafu {
+                    operation: "watch test",
+                    name,
+                })?
+            {
+                match event {
+                    WatchEvent::Added(test) | WatchEvent::Modified(test) => {
+                        let state = test.test_user_state();
+                        if state.is_terminal() {
+                            return Ok(state);
+                        }
+                    }
+                    WatchEvent::Deleted(_) => {
+                        return Err(error::TestDeletedSnafu { name }.build().into())
+                    }
+                    WatchEvent::Bookmark(_) => {}
+                    // The version is too old to watch from, start over from the current one.
+                    WatchEvent::Error(_) => break,
+                }
+            }
+            // The API server ended the watch, start a new one.
+        }
+    }
+
+    /// Record that the test has reached a terminal state.
+    pub async fn send_finished_at(&self, name: &str) -> Result<Test> {
+        self.send_status(
             name,
             vec![
                 JsonPatch::new_timestamp(),
-                JsonPatch::new_add_operation("/status/agent/taskState", task_state),
+                JsonPatch::new_add_operation(
+                    "/status/controller/finishedAt",
+                    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                ),
             ],
+            "send finished at",
+        )
+        .await
+    }
+
+    /// Mark the test as skipped because it is quarantined.
+    pub async fn send_quarantined(&self, name: &str) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/quarantined", true),
+            ],
+            "send quarantined",
+        )
+        .await
+    }
+
+    /// Mark the test as archived once its job and resources have been torn down.
+    pub async fn send_archived(&self, name: &str) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/archived", true),
+            ],
+            "send archived",
+        )
+        .await
+    }
+
+    /// Ask the controller to archive the test by setting the `testsys.system/archive` annotation.
+    pub async fn request_archive(&self, name: &str) -> Result<Test> {
+        let test = self.get(name).await?;
+        let patch = annotation_patch(&test, ANNOTATION_ARCHIVE, "true");
+        self.patch(name, vec![patch], "request archive").await
+    }
+
+    /// Ask the controller to keep the test's resources once the test is deleted by setting the
+    /// `testsys.system/keep-resources` annotation, e.g. because the test is deleted to be restarted.
+    pub async fn request_keep_resources(&self, name: &str) -> Result<Test> {
+        let test = self.get(name).await?;
+        let patch = annotation_patch(&test, ANNOTATION_KEEP_RESOURCES, "true");
+        self.patch(name, vec![patch], "request keep resources")
+            .await
+    }
+
+    /// Ask the controller to reconcile the test right away by setting the
+    /// `testsys.system/reconcile-requested` annotation to the current time. The controller
+    /// reconciles a test whenever it changes, so this does not wait for the test's next requeue.
+    pub async fn request_reconcile(&self, name: &str) -> Result<Test> {
+        let test = self.get(name).await?;
+        let requested_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let patch = annotation_patch(&test, ANNOTATION_RECONCILE_REQUESTED, &requested_at);
+        self.patch(name, vec![patch], "request reconcile").await
+    }
+
+    /// Complete the test with the `results` the controller determined from the agent's indexed
+    /// `completions`.
+    pub async fn send_completions(
+        &self,
+        name: &str,
+        completions: Completions,
+        results: TestResults,
+    ) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/completions", completions),
+                JsonPatch::new_add_operation("/status/agent/taskState", TaskState::Completed),
+                JsonPatch::new_add_operation("/status/agent/results/-", results),
+            ],
+            "send completions",
+        )
+        .await
+    }
+
+    /// Record the assertions of the test's spec that did not hold for the agent's results, `failed`
+    /// is empty if all of them held.
+    pub async fn send_failed_assertions(&self, name: &str, failed: &[String]) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/failedAssertions", failed),
+            ],
+            "send failed assertions",
+        )
+        .await
+    }
+
+    /// Ask the controller to extend the test agent's `timeout` by `duration`. The controller grants
+    /// the request unless the agent's extensions would add up to more than its limit.
+    pub async fn request_extension(&self, name: &str, duration: Duration) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation(
+                    "/status/agent/extensionRequest",
+                    format!("{}s", duration.as_secs()),
+                ),
+            ],
+            "request extension",
+        )
+        .await
+    }
+
+    /// Grant the test agent's request for more time, extending its `timeout` by a total of
+    /// `total_seconds`.
+    pub async fn send_timeout_extension(&self, name: &str, total_seconds: u64) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation(
+                    "/status/agent/timeoutExtensionSeconds",
+                    total_seconds,
+                ),
+                JsonPatch::new_add_operation("/status/agent/extensionRequest", Value::Null),
+                JsonPatch::new_add_operation("/status/agent/rejectedExtension", Value::Null),
+            ],
+            "send timeout extension",
+        )
+        .await
+    }
+
+    /// Reject the test agent's request for more time because of `reason`.
+    pub async fn send_rejected_extension(&self, name: &str, reason: &str) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/agent/rejectedExtension", reason),
+                JsonPatch::new_add_operation("/status/agent/extensionRequest", Value::Null),
+            ],
+            "send rejected extension",
+        )
+        .await
+    }
+
+    /// Record whether the test agent container passes its readiness probe.
+    pub async fn send_agent_ready(&self, name: &str, ready: bool) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/agent/ready", ready),
+            ],
+            "send agent ready",
+        )
+        .await
+    }
+
+    /// Replace the test's `timeline`.
+    pub async fn send_timeline(&self, name: &str, timeline: &[TimelineEntry]) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/timeline", timeline),
+            ],
+            "send timeline",
+        )
+        .await
+    }
+
+    /// Replace the test's standard k8s `conditions`.
+    pub async fn send_conditions(&self, name: &str, conditions: &[TestCondition]) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/conditions", conditions),
+            ],
+            "send conditions",
+        )
+        .await
+    }
+
+    /// Record the `progress` of the test agent's indexed completions.
+    pub async fn send_progress(&self, name: &str, progress: JobProgress) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/agent/progress", progress),
+            ],
+            "send progress",
+        )
+        .await
+    }
+
+    pub async fn send_job_creation_failures(&self, name: &str, failures: u32) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/controller/jobCreationFailures", failures),
+            ],
+            "send job creation failures",
+        )
+        .await
+    }
+
+    pub async fn send_agent_task_state(&self, name: &str, task_state: TaskState) -> Result<Test> {
+        self.send_status(
+            name,
+            self.agent_task_state_patches(task_state),
             "send agent task state",
         )
         .await
     }
 
+    /// Like [`Self::send_agent_task_state`], but nothing is sent if `test`, the caller's copy of
+    /// the test, already has the `task_state`. Returns `None` if nothing was sent.
+    pub async fn set_agent_task_state(
+        &self,
+        test: &Test,
+        task_state: TaskState,
+    ) -> Result<Option<Test>> {
+        self.patch_status_if_changed(
+            test,
+            self.agent_task_state_patches(task_state),
+            "send agent task state",
+        )
+        .await
+    }
+
+    fn agent_task_state_patches(&self, task_state: TaskState) -> Vec<JsonPatch> {
+        vec![
+            JsonPatch::new_timestamp(),
+            JsonPatch::new_add_operation(self.agent_status_path("taskState"), task_state),
+        ]
+    }
+
     pub async fn send_test_results(&self, name: &str, results: TestResults) -> Result<Test> {
-        self.patch_status(
+        self.send_status(
             name,
             vec![
                 JsonPatch::new_timestamp(),
-                JsonPatch::new_remove_operation("/status/agent/currentTest"),
-                JsonPatch::new_add_operation("/status/agent/results/-", results),
+                JsonPatch::new_remove_operation(self.agent_status_path("currentTest")),
+                JsonPatch::new_add_operation(self.agent_status_path("results/-"), results),
             ],
             "send test results",
         )
@@ -87,11 +626,11 @@ impl TestClient {
     }
 
     pub async fn send_test_update(&self, name: &str, results: TestResults) -> Result<Test> {
-        self.patch_status(
+        self.send_status(
             name,
             vec![
                 JsonPatch::new_timestamp(),
-                JsonPatch::new_add_operation("/status/agent/currentTest".to_string(), results),
+                JsonPatch::new_add_operation(self.agent_status_path("currentTest"), results),
             ],
             "update test results",
         )
@@ -99,30 +638,132 @@ impl TestClient {
     }
 
     pub async fn send_test_completed(&self, name: &str, results: TestResults) -> Result<Test> {
-        self.patch_status(
+        self.send_status(
             name,
             vec![
                 JsonPatch::new_timestamp(),
-                JsonPatch::new_add_operation("/status/agent/taskState", TaskState::Completed),
-                JsonPatch::new_add_operation("/status/agent/results/-", results),
+                JsonPatch::new_add_operation(
+                    self.agent_status_path("taskState"),
+                    TaskState::Completed,
+                ),
+                JsonPatch::new_add_operation(self.agent_status_path("results/-"), results),
             ],
             "send test completion results",
         )
         .await
     }
 
+    /// Create a k8s `Event` about the test named `name`, e.g. to report a step of a long running
+    /// test so that it shows up in `kubectl describe test`. `uid` is the UID of the `Test` object;
+    /// events without it are not associated with the test by `kubectl describe`.
+    pub async fn record_event(
+        &self,
+        name: &str,
+        uid: Option<&str>,
+        reason: &str,
+        message: &str,
+    ) -> Result<Event> {
+        let now = Time(Utc::now());
+        let event = Event {
+            metadata: ObjectMeta {
+                generate_name: Some(format!("{}.", name)),
+                namespace: Some(NAMESPACE.to_string()),
+                ..ObjectMeta::default()
+            },
+            involved_object: ObjectReference {
+                api_version: Some(<Test as kube::Resource>::api_version(&()).into_owned()),
+                kind: Some(<Test as kube::Resource>::kind(&()).into_owned()),
+                name: Some(name.to_string()),
+                namespace: Some(NAMESPACE.to_string()),
+                uid: uid.map(str::to_string),
+                ..ObjectReference::default()
+            },
+            reason: Some(reason.to_string()),
+            message: Some(message.to_string()),
+            type_: Some("Normal".to_string()),
+            count: Some(1),
+            first_timestamp: Some(now.clone()),
+            last_timestamp: Some(now),
+            source: Some(EventSource {
+                component: Some(EVENT_SOURCE.to_string()),
+                host: None,
+            }),
+            reporting_component: Some(EVENT_SOURCE.to_string()),
+            ..Event::default()
+        };
+        Ok(
+            Api::<Event>::namespaced(self.api.clone().into_client(), NAMESPACE)
+                .create(&PostParams::default(), &event)
+                .await
+                .context(error::KubeApiCallForSnafu {
+                    operation: "record event",
+                    name,
+                })?,
+        )
+    }
+
     pub async fn send_agent_error(&self, name: &str, error: &str) -> Result<Test> {
-        self.patch_status(
+        self.send_status(name, self.agent_error_patches(error), "send agent error")
+            .await
+    }
+
+    /// Like [`Self::send_agent_error`], but nothing is sent if `test`, the caller's copy of the
+    /// test, already has the `error`. Returns `None` if nothing was sent.
+    pub async fn set_agent_error(&self, test: &Test, error: &str) -> Result<Option<Test>> {
+        self.patch_status_if_changed(test, self.agent_error_patches(error), "send agent error")
+            .await
+    }
+
+    /// Record the message the agent's container wrote to its termination log before it failed.
+    pub async fn send_termination_message(&self, name: &str, message: &str) -> Result<Test> {
+        self.send_status(
             name,
             vec![
                 JsonPatch::new_timestamp(),
-                JsonPatch::new_add_operation("/status/agent/taskState", TaskState::Error),
-                JsonPatch::new_add_operation("/status/agent/error", error),
+                JsonPatch::new_add_operation(self.agent_status_path("terminationMessage"), message),
             ],
-            "send agent error",
+            "send termination message",
         )
         .await
     }
+
+    /// Record that the test agent ran out of memory and is relaunched with the last of
+    /// `memory_limits`. The agent's task state is reset so that the relaunched agent starts over.
+    pub async fn send_memory_limits(&self, name: &str, memory_limits: &[String]) -> Result<Test> {
+        self.send_status(
+            name,
+            vec![
+                JsonPatch::new_timestamp(),
+                JsonPatch::new_add_operation("/status/agent/memoryLimits", memory_limits),
+                JsonPatch::new_add_operation("/status/agent/taskState", TaskState::Unknown),
+                JsonPatch::new_add_operation(
+                    "/status/agent/terminationMessage",
+                    Option::<String>::None,
+                ),
+            ],
+            "send memory limits",
+        )
+        .await
+    }
+
+    fn agent_error_patches(&self, error: &str) -> Vec<JsonPatch> {
+        vec![
+            JsonPatch::new_timestamp(),
+            JsonPatch::new_add_operation(self.agent_status_path("taskState"), TaskState::Error),
+            JsonPatch::new_add_operation(self.agent_status_path("error"), error),
+        ]
+    }
+}
+
+/// A patch that sets the annotation `key` of the `test` to `value`, which must create the
+/// annotations if the test has none.
+fn annotation_patch(test: &Test, key: &str, value: &str) -> JsonPatch {
+    metadata_patch(
+        "annotations",
+        test.metadata.annotations.is_some(),
+        key,
+        value,
+    )
 }
 
 impl CrdClient for TestClient {
@@ -130,7 +771,12 @@ impl CrdClient for TestClient {
     type CrdStatus = TestStatus;
 
     fn new_from_api(api: Api<Self::Crd>) -> Self {
-        Self { api }
+        Self {
+            api,
+            agent_name: None,
+            max_status_field_len: DEFAULT_MAX_STATUS_FIELD_LEN,
+            current: None,
+        }
     }
 
     fn kind(&self) -> &'static str {
@@ -140,6 +786,10 @@ impl CrdClient for TestClient {
     fn api(&self) -> &Api<Self::Crd> {
         &self.api
     }
+
+    fn max_status_field_len(&self) -> Option<usize> {
+        Some(self.max_status_field_len)
+    }
 }
 
 pub fn create_test_crd<S1>(
@@ -162,6 +812,445 @@ where
     }
 }
 
+#[cfg(test)]
+mod get_opt_test {
+    use super::*;
+    use http::{Request, Response, StatusCode};
+    use hyper::Body;
+    use serde_json::json;
+    use std::convert::Infallible;
+
+    /// Create a `TestClient` backed by a fake k8s API server that knows about a single test named
+    /// `existing-test` and responds with `status` for everything else.
+    fn fake_test_client(status: StatusCode) -> TestClient {
+        let service = tower::service_fn(move |request: Request<Body>| async move {
+            let response = if request.uri().path().ends_with("/tests/existing-test") {
+                let test = create_test_crd("existing-test", None, TestSpec::default());
+                Response::new(Body::from(json!(test).to_string()))
+            } else {
+                let status_body = json!({
+                    "kind": "Status",
+                    "apiVersion": "v1",
+                    "status": "Failure",
+                    "message": "fake error",
+                    "reason": "Fake",
+                    "code": status.as_u16(),
+                });
+                let mut response = Response::new(Body::from(status_body.to_string()));
+                *response.status_mut() = status;
+                response
+            };
+            Ok::<_, Infallible>(response)
+        });
+        TestClient::new_from_k8s_client(kube::Client::new(service, NAMESPACE))
+    }
+
+    #[tokio::test]
+    async fn get_opt_found() {
+        let result = fake_test_client(StatusCode::NOT_FOUND)
+            .get_opt("existing-test")
+            .await;
+        assert!(matches!(
+            result,
+            Ok(Some(test)) if test.metadata.name.as_deref() == Some("existing-test")
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_opt_not_found() {
+        let result = fake_test_client(StatusCode::NOT_FOUND)
+            .get_opt("missing-test")
+            .await;
+        assert!(matches!(result, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn get_opt_error() {
+        let result = fake_test_client(StatusCode::FORBIDDEN)
+            .get_opt("missing-test")
+            .await;
+        assert!(result.is_err());
+    }
+}
+
+#[cfg(test)]
+mod status_patch_test {
+    use super::*;
+    use crate::constants::TRUNCATED_MARKER;
+    use crate::{AgentStatus, ControllerStatus, Outcome, TestStatus};
+    use http::{Method, Request, Response};
+    use hyper::Body;
+    use maplit::btreemap;
+    use serde_json::json;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Create a `TestClient` backed by a fake k8s API server that applies status patches to its
+    /// copy of `test`.
+    fn fake_test_client(test: &Test) -> TestClient {
+        fake_test_client_counting_patches(test).0
+    }
+
+    /// Like [`fake_test_client`], but also counts the status patches that were sent.
+    fn fake_test_client_counting_patches(test: &Test) -> (TestClient, Arc<AtomicUsize>) {
+        let patches = Arc::new(AtomicUsize::new(0));
+        let service_patches = patches.clone();
+        let stored = Arc::new(Mutex::new(json!(test)));
+        let status_path = format!(
+            "/tests/{}/status",
+            test.metadata.name.as_deref().unwrap_or_default()
+        );
+        let service = tower::service_fn(move |request: Request<Body>| {
+            let stored = stored.clone();
+            let is_status_patch =
+                request.method() == Method::PATCH && request.uri().path().ends_with(&status_path);
+            if is_status_patch {
+                service_patches.fetch_add(1, Ordering::SeqCst);
+            }
+            async move {
+                let body = hyper::body::to_bytes(request.into_body())
+                    .await
+                    .unwrap_or_default();
+                let mut stored = match stored.lock() {
+                    Ok(stored) => stored,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                if is_status_patch {
+                    if let Ok(patch) = serde_json::from_slice::<json_patch::Patch>(&body) {
+                        let _ = json_patch::patch(&mut stored, &patch);
+                    }
+                }
+                Ok::<_, Infallible>(Response::new(Body::from(stored.to_string())))
+            }
+        });
+        (
+            TestClient::new_from_k8s_client(kube::Client::new(service, NAMESPACE)),
+            patches,
+        )
+    }
+
+    #[tokio::test]
+    async fn metadata_round_trips_into_status() {
+        let metadata = btreemap! {
+            "gitSha".to_string() => "0123abcd".to_string(),
+            "triggeredBy".to_string() => "nightly".to_string(),
+        };
+        let mut test = create_test_crd(
+            "my-test",
+            None,
+            TestSpec {
+                metadata: metadata.clone(),
+                ..TestSpec::default()
+            },
+        );
+        test.status = Some(Default::default());
+        let test_client = fake_test_client(&test);
+
+        let result = test_client.send_metadata("my-test", &metadata).await;
+        assert!(matches!(
+            result.as_ref().map(|test| test.status_metadata()),
+            Ok(Some(status_metadata)) if status_metadata == &metadata
+        ));
+        // Everything else about the test is left alone.
+        assert!(matches!(
+            result.map(|test| json!(test.spec)),
+            Ok(spec) if spec == json!(TestSpec { metadata, ..TestSpec::default() })
+        ));
+    }
+
+    #[tokio::test]
+    async fn unchanged_status_is_not_sent() {
+        let mut test = create_test_crd("my-test", None, TestSpec::default());
+        test.status = Some(Default::default());
+        let (test_client, patches) = fake_test_client_counting_patches(&test);
+
+        let result = test_client
+            .set_agent_task_state(&test, TaskState::Running)
+            .await;
+        assert_eq!(patches.load(Ordering::SeqCst), 1);
+        let test = result.ok().flatten().unwrap_or_default();
+        assert_eq!(test.agent_status().task_state, TaskState::Running);
+
+        // The cached test already has the task state.
+        let result = test_client
+            .set_agent_task_state(&test, TaskState::Running)
+            .await;
+        assert!(matches!(result, Ok(None)));
+        assert_eq!(patches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn unchanged_writes_are_skipped_for_the_cached_test() {
+        let mut test = create_test_crd("my-test", None, TestSpec::default());
+        test.status = Some(Default::default());
+        let (test_client, patches) = fake_test_client_counting_patches(&test);
+
+        let result = test_client
+            .clone()
+            .skip_unchanged(&test)
+            .send_preflight_error("my-test", "missing ebs-csi-driver")
+            .await;
+        assert_eq!(patches.load(Ordering::SeqCst), 1);
+        let test = result.unwrap_or_default();
+        assert_eq!(
+            test.preflight_error().map(String::as_str),
+            Some("missing ebs-csi-driver")
+        );
+
+        // The cached test already has the error, but not the agent's readiness.
+        let skipping = test_client.clone().skip_unchanged(&test);
+        let result = skipping
+            .send_preflight_error("my-test", "missing ebs-csi-driver")
+            .await;
+        assert!(matches!(result, Ok(cached) if cached == test));
+        assert_eq!(patches.load(Ordering::SeqCst), 1);
+        assert!(skipping.send_agent_ready("my-test", true).await.is_ok());
+        assert_eq!(patches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn oversized_status_string_is_truncated() {
+        let mut test = create_test_crd("my-test", None, TestSpec::default());
+        test.status = Some(Default::default());
+        let test_client = fake_test_client(&test).with_max_status_field_len(1024);
+        let log = "é".repeat(4096);
+
+        let result = test_client.send_agent_error("my-test", &log).await;
+        let error = result
+            .ok()
+            .and_then(|test| test.agent_error().map(str::to_string))
+            .unwrap_or_default();
+        assert!(error.len() <= 1024, "{}", error.len());
+        assert!(error.len() > 1024 - TRUNCATED_MARKER.len() - "é".len());
+        assert!(error.ends_with(TRUNCATED_MARKER));
+        assert!(log.starts_with(error.trim_end_matches(TRUNCATED_MARKER)));
+
+        // Strings within the limit are written as they are.
+        let result = test_client.send_agent_error("my-test", "short").await;
+        assert!(matches!(
+            result.as_ref().map(|test| test.agent_error()),
+            Ok(Some("short"))
+        ));
+    }
+
+    fn test_with_stale_status() -> Test {
+        let mut test = create_test_crd("my-test", None, TestSpec::default());
+        test.status = Some(TestStatus {
+            controller: ControllerStatus {
+                resource_error: Some("stale resource error".to_string()),
+                job_creation_failures: 3,
+                preflight_error: Some("stale preflight error".to_string()),
+                invalid_spec: Some("stale invalid spec".to_string()),
+                observed_generation: Some(1),
+                ..ControllerStatus::default()
+            },
+            agent: AgentStatus {
+                task_state: TaskState::Completed,
+                results: vec![TestResults {
+                    outcome: Outcome::Pass,
+                    num_passed: 1,
+                    ..TestResults::default()
+                }],
+                ..AgentStatus::default()
+            },
+            agents: Default::default(),
+            resources: Default::default(),
+            conditions: Default::default(),
+            timeline: Default::default(),
+            last_update: None,
+        });
+        test
+    }
+
+    #[tokio::test]
+    async fn new_generation_resets_transient_status() {
+        let test = test_with_stale_status();
+        let result = fake_test_client(&test)
+            .send_observed_generation("my-test", 2, true)
+            .await;
+        assert!(matches!(
+            result.as_ref().map(|test| test.observed_generation()),
+            Ok(Some(2))
+        ));
+        assert!(matches!(
+            result.as_ref().map(|test| (
+                test.resource_error(),
+                test.job_creation_failures(),
+                test.preflight_error(),
+                test.invalid_spec()
+            )),
+            Ok((None, 0, None, None))
+        ));
+        // The results of the test are kept.
+        assert!(matches!(
+            result.map(|test| test.agent_status().into_owned()),
+            Ok(agent_status) if Some(&agent_status) == test.status.as_ref().map(|status| &status.agent)
+        ));
+    }
+
+    #[tokio::test]
+    async fn first_generation_keeps_status() {
+        let test = test_with_stale_status();
+        let result = fake_test_client(&test)
+            .send_observed_generation("my-test", 1, false)
+            .await;
+        assert!(matches!(
+            result.as_ref().map(|test| (
+                test.resource_error().map(String::as_str),
+                test.job_creation_failures(),
+            )),
+            Ok((Some("stale resource error"), 3))
+        ));
+    }
+
+    #[tokio::test]
+    async fn versions_are_recorded_in_status() {
+        let mut test = create_test_crd("my-test", None, TestSpec::default());
+        test.status = Some(Default::default());
+        let test_client = fake_test_client(&test);
+
+        let result = test_client
+            .send_versions("my-test", "0.0.9", Some("v1.24.10"))
+            .await;
+        assert!(matches!(
+            result
+                .as_ref()
+                .map(|test| (test.controller_version(), test.kube_server_version())),
+            Ok((Some("0.0.9"), Some("v1.24.10")))
+        ));
+
+        // The k8s API server version is optional.
+        let result = test_client.send_versions("my-test", "0.0.10", None).await;
+        assert!(matches!(
+            result
+                .as_ref()
+                .map(|test| (test.controller_version(), test.kube_server_version())),
+            Ok((Some("0.0.10"), None))
+        ));
+    }
+
+    #[tokio::test]
+    async fn record_event_with_reason() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let service = {
+            let recorded = recorded.clone();
+            tower::service_fn(move |request: Request<Body>| {
+                let recorded = recorded.clone();
+                let is_event_create =
+                    request.method() == Method::POST && request.uri().path().ends_with("/events");
+                async move {
+                    let body = hyper::body::to_bytes(request.into_body())
+                        .await
+                        .unwrap_or_default();
+                    let event = serde_json::from_slice::<Value>(&body).unwrap_or_default();
+                    if is_event_create {
+                        match recorded.lock() {
+                            Ok(mut recorded) => recorded.push(event.clone()),
+                            Err(poisoned) => poisoned.into_inner().push(event.clone()),
+                        }
+                    }
+                    Ok::<_, Infallible>(Response::new(Body::from(event.to_string())))
+                }
+            })
+        };
+        let test_client = TestClient::new_from_k8s_client(kube::Client::new(service, NAMESPACE));
+
+        let result = test_client
+            .record_event(
+                "my-test",
+                Some("0123-abcd"),
+                "InstanceLaunched",
+                "Launched i-0123",
+            )
+            .await;
+        assert!(matches!(
+            result.as_ref().map(|event| event.reason.as_deref()),
+            Ok(Some("InstanceLaunched"))
+        ));
+        let recorded = match recorded.lock() {
+            Ok(recorded) => recorded.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0]["reason"], "InstanceLaunched");
+        assert_eq!(recorded[0]["message"], "Launched i-0123");
+        assert_eq!(recorded[0]["involvedObject"]["kind"], "Test");
+        assert_eq!(recorded[0]["involvedObject"]["name"], "my-test");
+        assert_eq!(recorded[0]["involvedObject"]["uid"], "0123-abcd");
+    }
+
+    /// Status updates must only use the status subresource so that they work for controllers and
+    /// agents that may only write a test's status.
+    #[tokio::test]
+    async fn status_updates_use_status_subresource() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mut test = create_test_crd("my-test", None, TestSpec::default());
+        test.status = Some(Default::default());
+        let service = {
+            let requests = requests.clone();
+            let test = json!(test);
+            tower::service_fn(move |request: Request<Body>| {
+                let requests = requests.clone();
+                let test = test.clone();
+                async move {
+                    let request = (request.method().clone(), request.uri().path().to_string());
+                    match requests.lock() {
+                        Ok(mut requests) => requests.push(request),
+                        Err(poisoned) => poisoned.into_inner().push(request),
+                    }
+                    Ok::<_, Infallible>(Response::new(Body::from(test.to_string())))
+                }
+            })
+        };
+        let test_client = TestClient::new_from_k8s_client(kube::Client::new(service, NAMESPACE));
+
+        let results = TestResults::default();
+        let sent = [
+            test_client.initialize_status("my-test").await.is_ok(),
+            test_client
+                .send_agent_task_state("my-test", TaskState::Running)
+                .await
+                .is_ok(),
+            test_client
+                .send_test_update("my-test", results.clone())
+                .await
+                .is_ok(),
+            test_client
+                .send_test_results("my-test", results.clone())
+                .await
+                .is_ok(),
+            test_client
+                .send_test_completed("my-test", results)
+                .await
+                .is_ok(),
+            test_client
+                .send_agent_error("my-test", "error")
+                .await
+                .is_ok(),
+            test_client
+                .send_resource_error("my-test", "error")
+                .await
+                .is_ok(),
+            test_client
+                .send_job_creation_failures("my-test", 1)
+                .await
+                .is_ok(),
+            test_client.send_finished_at("my-test").await.is_ok(),
+        ];
+        assert!(sent.iter().all(|sent| *sent));
+
+        let requests = match requests.lock() {
+            Ok(requests) => requests.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        assert_eq!(requests.len(), sent.len());
+        assert!(requests.iter().all(
+            |(method, path)| method == Method::PATCH && path.ends_with("/tests/my-test/status")
+        ));
+    }
+}
+
 #[cfg(test)]
 #[cfg(feature = "integ")]
 mod test {
@@ -246,6 +1335,9 @@ mod test {
         .await
         .unwrap();
 
+        assert!(tc.get_opt(TEST_NAME).await.unwrap().is_some());
+        assert!(tc.get_opt("not-a-test").await.unwrap().is_none());
+
         tc.initialize_status(TEST_NAME).await.unwrap();
 
         // If status is already initialized, it should be an error to do so again.
@@ -304,3 +1396,86 @@ mod test {
         ));
     }
 }
+
+#[cfg(test)]
+mod wait_for_terminal_test {
+    use super::*;
+    use crate::Outcome;
+    use http::{Request, Response};
+    use hyper::Body;
+    use serde_json::json;
+    use std::convert::Infallible;
+
+    fn test_with_state(task_state: TaskState, outcome: Option<Outcome>) -> Test {
+        let mut test = create_test_crd("my-test", None, TestSpec::default());
+        test.metadata.resource_version = Some("1".to_string());
+        test.metadata.finalizers = Some(vec![crate::constants::FINALIZER_MAIN.to_string()]);
+        test.status = Some(TestStatus {
+            agent: AgentStatus {
+                task_state,
+                results: outcome
+                    .map(|outcome| {
+                        vec![TestResults {
+                            outcome,
+                            num_passed: 1,
+                            ..TestResults::default()
+                        }]
+                    })
+                    .unwrap_or_default(),
+                ..AgentStatus::default()
+            },
+            ..TestStatus::default()
+        });
+        test
+    }
+
+    /// Create a `TestClient` backed by a fake k8s API server that returns a running test and
+    /// streams the `events` to watches, or never ends the watch if there are none.
+    fn fake_test_client(events: Vec<Test>) -> TestClient {
+        let running = json!(test_with_state(TaskState::Running, None)).to_string();
+        let service = tower::service_fn(move |request: Request<Body>| {
+            let is_watch = request
+                .uri()
+                .query()
+                .unwrap_or_default()
+                .contains("watch=true");
+            let running = running.clone();
+            let events: String = events
+                .iter()
+                .map(|test| format!("{}\n", json!({ "type": "MODIFIED", "object": test })))
+                .collect();
+            async move {
+                let body = match (is_watch, events.is_empty()) {
+                    (false, _) => Body::from(running),
+                    (true, false) => Body::from(events),
+                    (true, true) => Body::wrap_stream(futures::stream::pending::<
+                        std::result::Result<bytes::Bytes, Infallible>,
+                    >()),
+                };
+                Ok::<_, Infallible>(Response::new(body))
+            }
+        });
+        TestClient::new_from_k8s_client(kube::Client::new(service, NAMESPACE))
+    }
+
+    #[tokio::test]
+    async fn running_test_passes() {
+        let test_client = fake_test_client(vec![
+            test_with_state(TaskState::Running, None),
+            test_with_state(TaskState::Completed, Some(Outcome::Pass)),
+        ]);
+        let state = test_client
+            .wait_for_terminal("my-test", Duration::from_secs(10))
+            .await;
+        assert!(matches!(state, Ok(TestUserState::Passed)), "{:?}", state);
+    }
+
+    #[tokio::test]
+    async fn running_test_times_out() {
+        let test_client = fake_test_client(Vec::new());
+        let state = test_client
+            .wait_for_terminal("my-test", Duration::from_millis(100))
+            .await;
+        assert!(state.is_err());
+    }
+}
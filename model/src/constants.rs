@@ -32,15 +32,53 @@ pub const LABEL_TEST_NAME: &str = testsys!("test-name");
 pub const LABEL_TEST_UID: &str = testsys!("test-uid");
 pub const LABEL_PROVIDER_NAME: &str = testsys!("provider-name");
 pub const LABEL_COMPONENT: &str = testsys!("component");
+pub const LABEL_SCHEDULED_BY: &str = testsys!("scheduled-by");
+pub const LABEL_SCHEDULED_AT: &str = testsys!("scheduled-at");
+pub const LABEL_POOL_KEY: &str = testsys!("pool-key");
+pub const LABEL_POOL_BORROWER: &str = testsys!("pool-borrower");
+pub const LABEL_CONTROLLER_INSTANCE: &str = testsys!("controller-instance");
+
+// Annotation keys
+pub const ANNOTATION_RERUN: &str = testsys!("rerun");
+pub const ANNOTATION_ARCHIVE: &str = testsys!("archive");
+pub const ANNOTATION_KEEP_RESOURCES: &str = testsys!("keep-resources");
+pub const ANNOTATION_CORRELATION_ID: &str = testsys!("correlation-id");
+pub const ANNOTATION_SPEC_HASH: &str = testsys!("spec-hash");
+pub const ANNOTATION_INPUT_HASH: &str = testsys!("input-hash");
+pub const ANNOTATION_RECONCILE_REQUESTED: &str = testsys!("reconcile-requested");
+
+// Keys of the tags that resource providers apply to the cloud resources they create
+pub const TAG_RESOURCE_NAME: &str = testsys!("resource-name");
+pub const TAG_TEST_NAME: &str = testsys!("test-name");
+pub const TAG_TEST_UID: &str = testsys!("test-uid");
 
 // Environment variables
+pub const ENV_CORRELATION_ID: &str = "TESTSYS_CORRELATION_ID";
+pub const ENV_MAX_STATUS_FIELD_LEN: &str = "TESTSYS_MAX_STATUS_FIELD_LEN";
 pub const ENV_PROVIDER_NAME: &str = "TESTSYS_PROVIDER_NAME";
 pub const ENV_RESOURCE_ACTION: &str = "TESTSYS_RESOURCE_ACTION";
 pub const ENV_RESOURCE_NAME: &str = "TESTSYS_RESOURCE_NAME";
+pub const ENV_RESULTS_ENDPOINT: &str = "TESTSYS_RESULTS_ENDPOINT";
+pub const ENV_TEST_AGENT_NAME: &str = "TESTSYS_TEST_AGENT_NAME";
 pub const ENV_TEST_NAME: &str = "TESTSYS_TEST_NAME";
+pub const ENV_TEST_UID: &str = "TESTSYS_TEST_UID";
 
 // Paths
 pub const SECRETS_PATH: &str = "/secrets";
+/// The directory the outputs of a test's resources are mounted in, see [`RESOURCE_OUTPUTS_FILE`].
+pub const RESOURCE_OUTPUTS_PATH: &str = "/resource-outputs";
+/// The name of the JSON file in [`RESOURCE_OUTPUTS_PATH`] that maps the name of each of a test's
+/// resources to its `createdResource` status.
+pub const RESOURCE_OUTPUTS_FILE: &str = "outputs.json";
+/// The directory an agent's `config_blob` is mounted in, see [`AGENT_CONFIG_FILE`].
+pub const AGENT_CONFIG_PATH: &str = "/agent-config";
+/// The name of the JSON file in [`AGENT_CONFIG_PATH`] with the agent's `config_blob`.
+pub const AGENT_CONFIG_FILE: &str = "config.json";
+/// The directory the controller's CA bundle is mounted in, see [`CA_BUNDLE_FILE`].
+pub const CA_BUNDLE_PATH: &str = "/ca-bundle";
+/// The name of the PEM file in [`CA_BUNDLE_PATH`] with the controller's CA bundle. It is also the
+/// key of the bundle in the `ConfigMap` or `Secret` it is mounted from.
+pub const CA_BUNDLE_FILE: &str = "ca-bundle.crt";
 
 // Standard tags https://kubernetes.io/docs/concepts/overview/working-with-objects/common-labels/
 pub const APP_NAME: &str = "app.kubernetes.io/name";
@@ -63,6 +101,16 @@ pub const TESTSYS_RESULTS_DIRECTORY: &str = "/output";
 // Used by the controller to truncate resource names
 pub const TRUNC_LEN: usize = 15;
 
+// The maximum length of a k8s `Job` name
+pub const MAX_JOB_NAME_LEN: usize = 63;
+
+// The default maximum length, in bytes, of a string written to a test's status. Longer strings,
+// e.g. large agent error logs, would push the object past etcd's size limit.
+pub const DEFAULT_MAX_STATUS_FIELD_LEN: usize = 64 * 1024;
+
+// Appended to status strings that were truncated to the maximum length
+pub const TRUNCATED_MARKER: &str = "...truncated";
+
 #[test]
 fn testsys_constants_macro_test() {
     assert_eq!("testsys.system", testsys!());
@@ -1,3 +1,4 @@
+use crate::constants::{LABEL_CONTROLLER_INSTANCE, TESTSYS};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use serde::Serialize;
 use std::collections::HashSet;
@@ -61,8 +62,33 @@ pub trait CrdExt: Serialize {
         finalizers.position(|item| item == finalizer)
     }
 
+    /// Does the object have finalizers that were not added by TestSys, e.g. by an external
+    /// controller that needs to clean up before the object is removed.
+    fn has_foreign_finalizers(&self) -> bool {
+        self.object_meta()
+            .finalizers
+            .iter()
+            .flatten()
+            .any(|finalizer| {
+                finalizer
+                    .strip_prefix(TESTSYS)
+                    .map(|rest| !rest.starts_with('/'))
+                    .unwrap_or(true)
+            })
+    }
+
     /// Has someone requested that the object be deleted.
     fn is_delete_requested(&self) -> bool {
         self.object_meta().deletion_timestamp.is_some()
     }
+
+    /// The ID of the controller instance that has claimed the object, from its
+    /// `testsys.system/controller-instance` label.
+    fn controller_instance(&self) -> Option<&str> {
+        self.object_meta()
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(LABEL_CONTROLLER_INSTANCE))
+            .map(String::as_str)
+    }
 }
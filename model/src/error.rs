@@ -28,6 +28,45 @@ pub(crate) enum OpaqueError {
         regex: &'static str,
     },
 
+    #[snafu(display("Unable to build test, '{}' is required", field))]
+    TestBuilderMissingField { field: &'static str },
+
+    #[snafu(display(
+        "Unable to build test, the timeout '{}' is invalid, it must match regex pattern '{}'",
+        timeout,
+        regex
+    ))]
+    TimeoutValidation {
+        timeout: String,
+        regex: &'static str,
+    },
+
+    #[snafu(display("Unable to create client from kubeconfig: {}", source))]
+    KubeconfigClient { source: kube::Error },
+
+    #[snafu(display("Unable to decode base64 kubeconfig: {}", source))]
+    KubeconfigDecode { source: base64::DecodeError },
+
+    #[snafu(display("Unable to read kubeconfig file '{}': {}", path, source))]
+    KubeconfigFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Unable to parse kubeconfig: {}", source))]
+    KubeconfigParse {
+        source: kube::config::KubeconfigError,
+    },
+
+    #[snafu(display("Unable to serialize kubeconfig: {}", source))]
+    KubeconfigSerialize { source: serde_yaml::Error },
+
+    #[snafu(display("Unable to write kubeconfig to a temporary file: {}", source))]
+    KubeconfigTempFile { source: std::io::Error },
+
+    #[snafu(display("Kubeconfig is not valid UTF-8: {}", source))]
+    KubeconfigUtf8 { source: std::string::FromUtf8Error },
+
     #[snafu(display("Parse error: {}", source))]
     SerdePlain { source: serde_plain::Error },
 }
@@ -0,0 +1,170 @@
+use crate::error::{self, Result};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::fmt::{Debug, Formatter};
+use std::io::Write;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// The kubeconfig of a cluster that a resource agent created, as a base64 encoded string. It is
+/// serialized as that string, so a resource agent can return it as an output field and a test agent
+/// can take the field (e.g. `${cluster.kubeconfig}`) into a `KubeconfigOutput` of its own
+/// configuration.
+#[derive(Serialize, Deserialize, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(transparent)]
+pub struct KubeconfigOutput(String);
+
+/// The kubeconfig holds the cluster's credentials, so they are redacted wherever the output is
+/// logged.
+impl Debug for KubeconfigOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("KubeconfigOutput")
+            .field(&"<redacted>")
+            .finish()
+    }
+}
+
+impl KubeconfigOutput {
+    /// Create a `KubeconfigOutput` from the YAML of a kubeconfig.
+    pub fn from_yaml<S: AsRef<[u8]>>(yaml: S) -> Self {
+        Self(base64::encode(yaml))
+    }
+
+    /// Create a `KubeconfigOutput` from a `Kubeconfig`.
+    pub fn from_kubeconfig(kubeconfig: &Kubeconfig) -> Result<Self> {
+        Ok(Self::from_yaml(
+            serde_yaml::to_string(kubeconfig).context(error::KubeconfigSerializeSnafu)?,
+        ))
+    }
+
+    /// Create a `KubeconfigOutput` from a kubeconfig file, e.g. the one that `eksctl` wrote.
+    pub fn read_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        Ok(Self::from_yaml(std::fs::read(path).context(
+            error::KubeconfigFileSnafu {
+                path: path.display().to_string(),
+            },
+        )?))
+    }
+
+    /// The base64 encoded kubeconfig.
+    pub fn encoded(&self) -> &str {
+        &self.0
+    }
+
+    /// The YAML of the kubeconfig.
+    pub fn yaml(&self) -> Result<String> {
+        let decoded = base64::decode(&self.0).context(error::KubeconfigDecodeSnafu)?;
+        Ok(String::from_utf8(decoded).context(error::KubeconfigUtf8Snafu)?)
+    }
+
+    /// Parse the kubeconfig.
+    pub fn kubeconfig(&self) -> Result<Kubeconfig> {
+        Ok(Kubeconfig::from_yaml(&self.yaml()?).context(error::KubeconfigParseSnafu)?)
+    }
+
+    /// Write the kubeconfig to a temporary file, for tools like `kubectl` that take a
+    /// `--kubeconfig` path. The file is deleted when the returned `NamedTempFile` is dropped.
+    pub fn write_temp_file(&self) -> Result<NamedTempFile> {
+        let yaml = self.yaml()?;
+        let mut file = NamedTempFile::new().context(error::KubeconfigTempFileSnafu)?;
+        file.write_all(yaml.as_bytes())
+            .context(error::KubeconfigTempFileSnafu)?;
+        Ok(file)
+    }
+
+    /// The client configuration for the kubeconfig's current context.
+    pub async fn client_config(&self) -> Result<kube::Config> {
+        Ok(
+            kube::Config::from_custom_kubeconfig(self.kubeconfig()?, &KubeConfigOptions::default())
+                .await
+                .context(error::KubeconfigParseSnafu)?,
+        )
+    }
+
+    /// A client for the cluster of the kubeconfig's current context.
+    pub async fn client(&self) -> Result<kube::Client> {
+        Ok(kube::Client::try_from(self.client_config().await?)
+            .context(error::KubeconfigClientSnafu)?)
+    }
+}
+
+#[cfg(test)]
+const KUBECONFIG: &str = r#"apiVersion: v1
+kind: Config
+clusters:
+- name: my-cluster
+  cluster:
+    server: https://my-cluster.example.com
+contexts:
+- name: my-context
+  context:
+    cluster: my-cluster
+    user: my-user
+current-context: my-context
+users:
+- name: my-user
+  user:
+    token: my-token
+"#;
+
+#[test]
+fn kubeconfig_output_round_trip() {
+    let output = KubeconfigOutput::from_yaml(KUBECONFIG);
+    assert_eq!(output.encoded(), base64::encode(KUBECONFIG));
+    assert_eq!(output.yaml().unwrap(), KUBECONFIG);
+
+    // The output is the encoded string in a resource's outputs and a test agent's configuration.
+    let value = serde_json::to_value(&output).unwrap();
+    assert_eq!(value, serde_json::json!(base64::encode(KUBECONFIG)));
+    assert_eq!(
+        serde_json::from_value::<KubeconfigOutput>(value).unwrap(),
+        output
+    );
+
+    let kubeconfig = output.kubeconfig().unwrap();
+    assert_eq!(kubeconfig.current_context.as_deref(), Some("my-context"));
+    let reencoded = KubeconfigOutput::from_kubeconfig(&kubeconfig).unwrap();
+    assert_eq!(
+        reencoded.kubeconfig().unwrap().current_context.as_deref(),
+        Some("my-context")
+    );
+
+    let file = output.write_temp_file().unwrap();
+    assert_eq!(std::fs::read_to_string(file.path()).unwrap(), KUBECONFIG);
+    assert_eq!(KubeconfigOutput::read_from(file.path()).unwrap(), output);
+}
+
+#[tokio::test]
+async fn kubeconfig_output_client_config() {
+    let config = KubeconfigOutput::from_yaml(KUBECONFIG)
+        .client_config()
+        .await
+        .unwrap();
+    assert_eq!(
+        config.cluster_url.to_string(),
+        "https://my-cluster.example.com/"
+    );
+    assert!(KubeconfigOutput::from_yaml(KUBECONFIG)
+        .client()
+        .await
+        .is_ok());
+}
+
+#[test]
+fn kubeconfig_output_debug_is_redacted() {
+    let output = KubeconfigOutput::from_yaml(KUBECONFIG);
+    let debug = format!("{:?}", output);
+    assert_eq!(debug, r#"KubeconfigOutput("<redacted>")"#);
+    assert!(!debug.contains(output.encoded()));
+}
+
+#[test]
+fn kubeconfig_output_invalid() {
+    assert!(KubeconfigOutput("not base64!".to_string()).yaml().is_err());
+    assert!(KubeconfigOutput::from_yaml("- not a kubeconfig")
+        .kubeconfig()
+        .is_err());
+}
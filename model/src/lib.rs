@@ -14,21 +14,31 @@ This library provides the Kubernetes custom resource definitions and their API c
     clippy::unwrap_used
 )]
 
-pub use agent::{Agent, SecretName, SecretType, TaskState};
+pub use agent::{
+    Agent, ArtifactsOn, CapacityType, ContainerPort, ContainerResources, DnsConfig, DnsOption,
+    HostAlias, HttpGetProbe, OomRetry, PersistentVolumeMount, PortProtocol, Probe, Qos,
+    RestartPolicy, ResultsFormat, SeccompProfile, SeccompProfileType, SecretMount, SecretName,
+    SecretType, TaskState,
+};
 pub use clients::{create_resource_crd, create_test_crd, AllowNotFound};
 pub use configuration::{ConfigValue, Configuration};
 pub use crd_ext::CrdExt;
 pub use error::{Error, Result};
 use kube::ResourceExt;
+pub use kubeconfig::KubeconfigOutput;
 pub use resource::{
-    DestructionPolicy, ErrorResources, Resource, ResourceAction, ResourceError, ResourceSpec,
-    ResourceStatus,
+    DestructionPolicy, ErrorResources, OutputField, OutputType, Resource, ResourceAction,
+    ResourceAgentState, ResourceError, ResourcePool, ResourceSpec, ResourceStatus,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 pub use test::{
-    AgentStatus, ControllerStatus, Outcome, Test, TestResults, TestSpec, TestStatus, TestUserState,
+    fnv1a_hash, AgentStatus, Comparison, Completions, ConditionStatus, ControllerStatus,
+    JobProgress, Outcome, ResourceEndpoint, ResourceSummary, ResultAssertion, ResultField,
+    Schedule, Test, TestCondition, TestConditionType, TestResults, TestSpec, TestStatus,
+    TestUserState, TimelineEntry,
 };
+pub use test_builder::TestBuilder;
 
 mod agent;
 pub mod clients;
@@ -36,10 +46,14 @@ mod configuration;
 pub mod constants;
 mod crd_ext;
 mod error;
+mod kubeconfig;
 mod resource;
+#[cfg(feature = "grpc")]
+pub mod results_stream;
 mod schema_utils;
 pub mod system;
 mod test;
+mod test_builder;
 pub mod test_manager;
 
 /// `CrdName` provides a way of determining which type of testsys object a name refers to.
@@ -61,6 +75,7 @@ impl CrdName {
 /// `Crd` provides an interface to combine `Test` and `Resource` when actions can be performed on both.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum Crd {
     Test(Test),
     Resource(Resource),
@@ -1,15 +1,18 @@
-use crate::constants::TRUNC_LEN;
+use crate::constants::{LABEL_POOL_KEY, TRUNC_LEN};
 use crate::test_manager::ResourceState;
-use crate::{agent::config_schema, Agent, CrdExt, TaskState};
+use crate::{agent::config_schema, fnv1a_hash, Agent, CrdExt, ResourceSummary, TaskState};
 use core::option::Option;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
-use kube::{CustomResource, Resource as Kresource};
+use kube::{CustomResource, Resource as Kresource, ResourceExt};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use serde_plain::{derive_display_from_serialize, derive_fromstr_from_deserialize};
 use std::fmt::{Display, Formatter};
 
+/// The maximum number of outputs of the created resource that are included in its summary.
+const MAX_SUMMARY_OUTPUTS: usize = 16;
+
 /// A resource required by a test. For example, a compute instance or cluster. The `CustomResource`
 /// derive also produces a struct named `Resource` which represents a resource CRD object in the k8s
 /// API.
@@ -45,9 +48,98 @@ pub struct ResourceSpec {
     #[serde(default)]
     #[schemars(schema_with = "crate::schema_utils::nullable_enum::<DestructionPolicy>")]
     pub destruction_policy: DestructionPolicy,
+    /// Borrow the resource from a pool of created resources with the same agent instead of
+    /// creating it, and return it to the pool instead of destroying it.
+    pub pool: Option<ResourcePool>,
+    /// Whether the resource is shared by tests that run concurrently. A shared resource keeps a
+    /// reference to each test that uses it and is only destroyed once the last of those tests is
+    /// deleted.
+    pub shared: Option<bool>,
+}
+
+/// How a resource is shared through a pool. Resources are pooled with the other resources that
+/// have the same agent, see [`Resource::pool_key`].
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourcePool {
+    /// The maximum number of idle resources kept in the pool. Resources returned to a full pool
+    /// are destroyed (`1` is the default).
+    pub capacity: Option<u32>,
 }
 
 impl Resource {
+    /// Summarize the resource for the status of the tests that use it. Only the scalar top-level
+    /// fields of the created resource that its agent declared in its output schema, and did not
+    /// mark as sensitive, are included as outputs. The outputs of agents that do not declare an
+    /// output schema are left out, since they may hold credentials.
+    pub fn summary(&self) -> ResourceSummary {
+        let output_schema = self.output_schema().unwrap_or_default();
+        let is_summarized = |key: &String| {
+            output_schema
+                .iter()
+                .any(|field| &field.name == key && !field.sensitive)
+        };
+        let outputs = self
+            .created_resource()
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| is_summarized(key))
+            .filter_map(|(key, value)| match value {
+                Value::String(s) => Some((key.clone(), s.clone())),
+                Value::Number(_) | Value::Bool(_) => Some((key.clone(), value.to_string())),
+                _ => None,
+            })
+            .take(MAX_SUMMARY_OUTPUTS)
+            .collect();
+        ResourceSummary {
+            agent: self.spec.agent.name.clone(),
+            creation_state: self.creation_task_state(),
+            ready: self.status.as_ref().and_then(|status| status.ready),
+            outputs,
+        }
+    }
+
+    /// The key of the pool the resource is borrowed from and returned to, if it is pooled. It is a
+    /// hash of the resource's agent, so resources are only shared when they are created the same
+    /// way.
+    pub fn pool_key(&self) -> Option<String> {
+        self.spec.pool.as_ref()?;
+        let agent = serde_json::to_vec(&self.spec.agent).unwrap_or_default();
+        Some(format!("{:016x}", fnv1a_hash(&agent)))
+    }
+
+    /// Whether the resource is shared by the tests that use it, see [`ResourceSpec::shared`].
+    pub fn is_shared(&self) -> bool {
+        self.spec.shared.unwrap_or(false)
+    }
+
+    /// The names of the tests that hold a reference to the shared resource.
+    pub fn references(&self) -> &[String] {
+        self.status
+            .as_ref()
+            .and_then(|s| s.references.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Whether the resource is an idle resource in a pool, waiting to be borrowed.
+    pub fn is_pool_entry(&self) -> bool {
+        self.labels().contains_key(LABEL_POOL_KEY)
+    }
+
+    /// The status that a resource borrowed from, or returned to, a pool takes over from this
+    /// resource, which describes the created resource.
+    pub fn pooled_status(&self) -> ResourceStatus {
+        let status = self.status.clone().unwrap_or_default();
+        ResourceStatus {
+            creation: status.creation,
+            agent_info: status.agent_info,
+            created_resource: status.created_resource,
+            output_schema: status.output_schema,
+            ready: status.ready,
+            ..ResourceStatus::default()
+        }
+    }
+
     /// Gets the information for the resource created.
     pub fn created_resource(&self) -> Option<&Map<String, Value>> {
         self.status
@@ -55,6 +147,13 @@ impl Resource {
             .and_then(|s| s.created_resource.as_ref())
     }
 
+    /// Gets the output fields declared by the resource agent (if any).
+    pub fn output_schema(&self) -> Option<&[OutputField]> {
+        self.status
+            .as_ref()
+            .and_then(|s| s.output_schema.as_deref())
+    }
+
     /// Gets the error that occurred during resource creation (if any).
     pub fn creation_error(&self) -> Option<&ResourceError> {
         self.status.as_ref().and_then(|s| s.creation.error.as_ref())
@@ -68,6 +167,13 @@ impl Resource {
             .unwrap_or_default()
     }
 
+    /// Whether the resource has been created and its agent reports that it is ready for use.
+    /// Resource agents that do not report readiness are considered ready once creation completes.
+    pub fn is_ready(&self) -> bool {
+        self.creation_task_state() == TaskState::Completed
+            && self.status.as_ref().and_then(|s| s.ready).unwrap_or(true)
+    }
+
     /// Gets the error that occurred during resource destruction (if any).
     pub fn destruction_error(&self) -> Option<&ResourceError> {
         self.status
@@ -204,6 +310,20 @@ pub struct ResourceStatus {
     #[schemars(schema_with = "config_schema")]
     pub created_resource: Option<Map<String, Value>>,
 
+    /// The fields of the created resource that the resource agent declares, so that consumers
+    /// can discover the outputs of a resource before depending on them.
+    pub output_schema: Option<Vec<OutputField>>,
+
+    /// Whether the created resource is ready for use. Set to `false` by the resource agent after
+    /// creation completes and to `true` once its readiness check passes. `None` means the resource
+    /// agent does not report readiness.
+    pub ready: Option<bool>,
+
+    /// The names of the tests that hold a reference to the resource if it is shared. The test
+    /// controller adds a test's reference once the resource is created and removes it when the test
+    /// is deleted or archived.
+    pub references: Option<Vec<String>>,
+
     /// The time of the last change to this CRD.
     pub last_update: Option<String>,
 }
@@ -242,6 +362,61 @@ impl Display for ResourceError {
     }
 }
 
+/// A field of a created resource, as declared by its resource agent.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputField {
+    /// The name of the field, e.g. `endpoint`.
+    pub name: String,
+    /// The JSON type of the field's value.
+    #[serde(rename = "type")]
+    pub field_type: OutputType,
+    /// What the field holds.
+    pub description: Option<String>,
+    /// Whether the field holds a secret, e.g. a password or a token. Sensitive fields are left out
+    /// of the summaries in the status of the tests that use the resource.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sensitive: bool,
+}
+
+impl OutputField {
+    pub fn new<S: Into<String>>(name: S, field_type: OutputType) -> Self {
+        Self {
+            name: name.into(),
+            field_type,
+            description: None,
+            sensitive: false,
+        }
+    }
+
+    pub fn with_description<S: Into<String>>(mut self, description: S) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Mark the field as holding a secret, see [`OutputField::sensitive`].
+    pub fn sensitive(mut self) -> Self {
+        self.sensitive = true;
+        self
+    }
+}
+
+/// The JSON type of an [`OutputField`].
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputType {
+    String,
+    Number,
+    Boolean,
+    Array,
+    Object,
+    /// The field can hold any JSON value.
+    Any,
+}
+
+derive_display_from_serialize!(OutputType);
+derive_fromstr_from_deserialize!(OutputType);
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum DestructionPolicy {
@@ -263,3 +438,73 @@ impl Default for DestructionPolicy {
 
 derive_display_from_serialize!(DestructionPolicy);
 derive_fromstr_from_deserialize!(DestructionPolicy);
+
+#[cfg(test)]
+mod summary_test {
+    use super::{
+        OutputField, OutputType, Resource, ResourceAgentState, ResourceSpec, ResourceStatus,
+    };
+    use crate::{Agent, TaskState};
+    use serde_json::json;
+
+    #[test]
+    fn summary_outputs_are_declared_scalars() {
+        let mut resource = Resource::new(
+            "cluster",
+            ResourceSpec {
+                agent: Agent {
+                    name: "eks-provider".to_string(),
+                    ..Agent::default()
+                },
+                ..ResourceSpec::default()
+            },
+        );
+        resource.status = Some(ResourceStatus {
+            creation: ResourceAgentState {
+                task_state: TaskState::Completed,
+                error: None,
+            },
+            created_resource: json!({
+                "endpoint": "https://example.com",
+                "nodeCount": 3,
+                "private": false,
+                "subnetIds": ["subnet-1"],
+                "config": { "region": "us-west-2" },
+                "password": "hunter2",
+                "token": "not declared",
+            })
+            .as_object()
+            .cloned(),
+            output_schema: Some(vec![
+                OutputField::new("endpoint", OutputType::String),
+                OutputField::new("nodeCount", OutputType::Number),
+                OutputField::new("private", OutputType::Boolean),
+                OutputField::new("subnetIds", OutputType::Array),
+                OutputField::new("config", OutputType::Object),
+                OutputField::new("password", OutputType::String).sensitive(),
+            ]),
+            ready: Some(true),
+            ..ResourceStatus::default()
+        });
+
+        let summary = resource.summary();
+        assert_eq!(summary.agent, "eks-provider");
+        assert_eq!(summary.creation_state, TaskState::Completed);
+        assert_eq!(summary.ready, Some(true));
+        assert_eq!(
+            summary.outputs.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("endpoint".to_string(), "https://example.com".to_string()),
+                ("nodeCount".to_string(), "3".to_string()),
+                ("private".to_string(), "false".to_string()),
+            ]
+        );
+
+        // Nothing is summarized for agents that do not declare their outputs.
+        resource
+            .status
+            .iter_mut()
+            .for_each(|status| status.output_schema = None);
+        assert!(resource.summary().outputs.is_empty());
+    }
+}
@@ -0,0 +1,95 @@
+/*!
+
+The gRPC service that test agents stream their results to the controller with, see
+`proto/results.proto`. Agents stream their results instead of patching their test's status for
+each of them when the controller gives them an endpoint in [`ENV_RESULTS_ENDPOINT`].
+
+[`ENV_RESULTS_ENDPOINT`]: crate::constants::ENV_RESULTS_ENDPOINT
+
+!*/
+
+use crate::{Outcome, TestResults};
+
+/// The generated messages, client and server of the results service.
+#[allow(clippy::all, clippy::unwrap_used, clippy::panic)]
+pub mod proto {
+    tonic::include_proto!("testsys.results.v1");
+}
+
+/// The gRPC metadata that names the test whose results an agent streams.
+pub const TEST_NAME_METADATA: &str = "testsys-test";
+
+/// The largest message the controller accepts from an agent. Results are small, anything larger
+/// is a misbehaving agent.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+impl From<Outcome> for proto::Outcome {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Pass => Self::Pass,
+            Outcome::Fail => Self::Fail,
+            Outcome::Timeout => Self::Timeout,
+            Outcome::Unknown => Self::Unknown,
+            Outcome::InProgress => Self::InProgress,
+        }
+    }
+}
+
+impl From<proto::Outcome> for Outcome {
+    fn from(outcome: proto::Outcome) -> Self {
+        match outcome {
+            proto::Outcome::Pass => Self::Pass,
+            proto::Outcome::Fail => Self::Fail,
+            proto::Outcome::Timeout => Self::Timeout,
+            proto::Outcome::Unknown => Self::Unknown,
+            proto::Outcome::InProgress => Self::InProgress,
+        }
+    }
+}
+
+impl From<TestResults> for proto::TestResults {
+    fn from(results: TestResults) -> Self {
+        Self {
+            outcome: proto::Outcome::from(results.outcome).into(),
+            num_passed: results.num_passed,
+            num_failed: results.num_failed,
+            num_skipped: results.num_skipped,
+            other_info: results.other_info,
+        }
+    }
+}
+
+impl From<proto::TestResults> for TestResults {
+    /// Outcomes that this version of TestSys does not know are `Unknown`.
+    fn from(results: proto::TestResults) -> Self {
+        Self {
+            outcome: proto::Outcome::try_from(results.outcome)
+                .unwrap_or(proto::Outcome::Unknown)
+                .into(),
+            num_passed: results.num_passed,
+            num_failed: results.num_failed,
+            num_skipped: results.num_skipped,
+            other_info: results.other_info,
+        }
+    }
+}
+
+#[test]
+fn results_round_trip() {
+    let results = TestResults {
+        outcome: Outcome::InProgress,
+        num_passed: 3,
+        num_failed: 1,
+        num_skipped: 2,
+        other_info: Some("running".to_string()),
+    };
+    assert_eq!(
+        TestResults::from(proto::TestResults::from(results.clone())),
+        results
+    );
+    let unknown = proto::TestResults {
+        outcome: 42,
+        ..proto::TestResults::default()
+    };
+    assert_eq!(TestResults::from(unknown).outcome, Outcome::Unknown);
+}
@@ -116,7 +116,14 @@ impl AgentType {
                     .to_string()]),
                 verbs: vec!["get".to_string()],
                 ..Default::default()
-            })
+            });
+            // Test agents can record events about their test.
+            policy_rules.push(PolicyRule {
+                api_groups: Some(vec!["".to_string()]),
+                resources: Some(vec!["events".to_string()]),
+                verbs: vec!["create".to_string()],
+                ..Default::default()
+            });
         }
 
         policy_rules
@@ -1,22 +1,90 @@
 use crate::constants::{
     APP_COMPONENT, APP_MANAGED_BY, APP_PART_OF, LABEL_COMPONENT, NAMESPACE, TESTSYS,
 };
+use k8s_openapi::api::admissionregistration::v1::{
+    MutatingWebhook, MutatingWebhookConfiguration, RuleWithOperations, ServiceReference,
+    WebhookClientConfig,
+};
 use k8s_openapi::api::apps::v1::{
     Deployment, DeploymentSpec, DeploymentStrategy, RollingUpdateDeployment,
 };
 use k8s_openapi::api::core::v1::{
     Affinity, Container, EnvVar, LocalObjectReference, NodeAffinity, NodeSelector,
-    NodeSelectorRequirement, NodeSelectorTerm, PodSpec, PodTemplateSpec, ServiceAccount,
+    NodeSelectorRequirement, NodeSelectorTerm, PodSpec, PodTemplateSpec, Secret,
+    SecretVolumeSource, Service, ServiceAccount, ServicePort, ServiceSpec, Volume, VolumeMount,
 };
 use k8s_openapi::api::rbac::v1::{ClusterRole, ClusterRoleBinding, PolicyRule, RoleRef, Subject};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use k8s_openapi::ByteString;
 use kube::api::ObjectMeta;
 use maplit::btreemap;
+use std::net::SocketAddr;
 
 const TESTSYS_CONTROLLER_SERVICE_ACCOUNT: &str = "testsys-controller-service-account";
 const TESTSYS_CONTROLLER_CLUSTER_ROLE: &str = "testsys-controller-role";
+const TESTSYS_CONTROLLER_SERVICE: &str = "testsys-controller";
+const TESTSYS_CONTROLLER_WEBHOOK: &str = "testsys-controller-webhook";
+/// The secret with the certificate and key that the controller serves its webhook with.
+pub const TESTSYS_CONTROLLER_WEBHOOK_TLS_SECRET: &str = "testsys-controller-webhook-tls";
+/// The port of the controller's service that the k8s API server calls the webhook on.
+const WEBHOOK_SERVICE_PORT: i32 = 443;
+/// Where the controller finds the certificate (`tls.crt`) and key (`tls.key`) that it serves its
+/// mutating admission webhook with.
+pub const TESTSYS_CONTROLLER_WEBHOOK_TLS_PATH: &str = "/etc/testsys/webhook-tls";
 pub const TESTSYS_CONTROLLER_ARCHIVE_LOGS: &str = "TESTSYS_CONTROLLER_ARCHIVE_LOGS";
+pub const TESTSYS_CONTROLLER_LOG_SINK: &str = "TESTSYS_CONTROLLER_LOG_SINK";
+pub const TESTSYS_CONTROLLER_API_ADDRESS: &str = "TESTSYS_CONTROLLER_API_ADDRESS";
+pub const TESTSYS_CONTROLLER_RESULTS_ADDRESS: &str = "TESTSYS_CONTROLLER_RESULTS_ADDRESS";
+pub const TESTSYS_CONTROLLER_WEBHOOK_ADDRESS: &str = "TESTSYS_CONTROLLER_WEBHOOK_ADDRESS";
+pub const TESTSYS_CONTROLLER_QUARANTINE: &str = "TESTSYS_CONTROLLER_QUARANTINE";
+pub const TESTSYS_CONTROLLER_TEST_RETENTION: &str = "TESTSYS_CONTROLLER_TEST_RETENTION";
+pub const TESTSYS_CONTROLLER_ALLOWED_IMAGES: &str = "TESTSYS_CONTROLLER_ALLOWED_IMAGES";
+pub const TESTSYS_CONTROLLER_INSTALL_CRDS: &str = "TESTSYS_CONTROLLER_INSTALL_CRDS";
+pub const TESTSYS_CONTROLLER_OBSERVE_ONLY: &str = "TESTSYS_CONTROLLER_OBSERVE_ONLY";
+pub const TESTSYS_CONTROLLER_MAX_STATUS_FIELD_LEN: &str = "TESTSYS_CONTROLLER_MAX_STATUS_FIELD_LEN";
+pub const TESTSYS_CONTROLLER_IMAGE_PULL_GRACE_PERIOD: &str =
+    "TESTSYS_CONTROLLER_IMAGE_PULL_GRACE_PERIOD";
+pub const TESTSYS_CONTROLLER_KUBE_QPS: &str = "TESTSYS_CONTROLLER_KUBE_QPS";
+pub const TESTSYS_CONTROLLER_KUBE_BURST: &str = "TESTSYS_CONTROLLER_KUBE_BURST";
+pub const TESTSYS_CONTROLLER_MAX_RESOURCES_PER_TEST: &str =
+    "TESTSYS_CONTROLLER_MAX_RESOURCES_PER_TEST";
+pub const TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_NAMESPACE: &str =
+    "TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_NAMESPACE";
+pub const TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_LOG_GROUP: &str =
+    "TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_LOG_GROUP";
+pub const TESTSYS_CONTROLLER_INSTANCE_ID: &str = "TESTSYS_CONTROLLER_INSTANCE_ID";
+pub const TESTSYS_CONTROLLER_PROTECTED_LABELS: &str = "TESTSYS_CONTROLLER_PROTECTED_LABELS";
+pub const TESTSYS_CONTROLLER_LABEL_SELECTOR: &str = "TESTSYS_CONTROLLER_LABEL_SELECTOR";
+pub const TESTSYS_CONTROLLER_CAPACITY_TYPE_LABEL: &str = "TESTSYS_CONTROLLER_CAPACITY_TYPE_LABEL";
+pub const TESTSYS_CONTROLLER_CA_BUNDLE: &str = "TESTSYS_CONTROLLER_CA_BUNDLE";
+pub const TESTSYS_CONTROLLER_MAX_TIMELINE_ENTRIES: &str = "TESTSYS_CONTROLLER_MAX_TIMELINE_ENTRIES";
+pub const TESTSYS_CONTROLLER_MAX_TIMEOUT_EXTENSION: &str =
+    "TESTSYS_CONTROLLER_MAX_TIMEOUT_EXTENSION";
+pub const TESTSYS_CONTROLLER_ENDPOINT_WAIT_IMAGE: &str = "TESTSYS_CONTROLLER_ENDPOINT_WAIT_IMAGE";
+pub const TESTSYS_CONTROLLER_ANNOTATE_INPUT_HASH: &str = "TESTSYS_CONTROLLER_ANNOTATE_INPUT_HASH";
+
+/// Optional controller behavior, passed to the controller through environment variables.
+#[derive(Debug, Clone, Default)]
+pub struct ControllerOptions {
+    /// Forward the logs of running test agents to this sink, either `stdout` or an HTTP endpoint.
+    pub log_sink: Option<String>,
+    /// Serve the controller's HTTP API for tests on this address.
+    pub api_address: Option<String>,
+    /// Serve the gRPC endpoint that test agents stream their results to on this address, e.g.
+    /// `0.0.0.0:50051`. It is exposed to the agents by the controller's `Service`.
+    pub results_address: Option<String>,
+    /// Serve the mutating admission webhook that fills the controller's agent defaults into new
+    /// tests over TLS on this address, e.g. `0.0.0.0:8443`. Installing registers the webhook with
+    /// the k8s API server and creates the certificate it is served with.
+    pub webhook_address: Option<String>,
+    /// Skip tests with names matching one of these glob patterns.
+    pub quarantine: Vec<String>,
+    /// Delete tests that finished longer ago than this duration, e.g. `7d` or `12h`.
+    pub test_retention: Option<String>,
+    /// Only run test agent images that start with one of these prefixes, e.g. approved registries.
+    pub allowed_images: Vec<String>,
+}
 
 /// Defines the testsys-controller service account
 pub fn controller_service_account() -> ServiceAccount {
@@ -98,6 +166,20 @@ pub fn controller_cluster_role() -> ClusterRole {
                 .collect(),
                 ..Default::default()
             },
+            PolicyRule {
+                api_groups: Some(vec!["apps".to_string()]),
+                resources: Some(vec!["daemonsets".to_string()]),
+                verbs: vec!["get".to_string()],
+                ..Default::default()
+            },
+            PolicyRule {
+                api_groups: Some(vec!["apiextensions.k8s.io".to_string()]),
+                resources: Some(vec!["customresourcedefinitions".to_string()]),
+                // The controller only checks that the CRDs are installed. Creating them is not
+                // granted since it lets the controller define any API in the cluster.
+                verbs: vec!["get".to_string()],
+                ..Default::default()
+            },
             PolicyRule {
                 api_groups: Some(vec!["batch".to_string()]),
                 resources: Some(vec!["jobs".to_string()]),
@@ -121,6 +203,52 @@ pub fn controller_cluster_role() -> ClusterRole {
                 verbs: ["get", "list"].iter().map(|s| s.to_string()).collect(),
                 ..Default::default()
             },
+            PolicyRule {
+                api_groups: Some(vec!["".to_string()]),
+                resources: Some(vec!["configmaps".to_string()]),
+                verbs: ["create", "get", "patch", "update"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                ..Default::default()
+            },
+            PolicyRule {
+                api_groups: Some(vec!["".to_string()]),
+                resources: Some(vec!["secrets".to_string()]),
+                verbs: ["create", "delete", "get"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                ..Default::default()
+            },
+            PolicyRule {
+                api_groups: Some(vec!["".to_string()]),
+                resources: Some(vec!["services".to_string()]),
+                verbs: ["create", "get", "patch", "update"]
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                ..Default::default()
+            },
+            PolicyRule {
+                api_groups: Some(vec!["".to_string()]),
+                resources: Some(vec!["events".to_string()]),
+                verbs: vec!["create".to_string()],
+                ..Default::default()
+            },
+            // The API server authenticates and authorizes its callers with k8s.
+            PolicyRule {
+                api_groups: Some(vec!["authentication.k8s.io".to_string()]),
+                resources: Some(vec!["tokenreviews".to_string()]),
+                verbs: vec!["create".to_string()],
+                ..Default::default()
+            },
+            PolicyRule {
+                api_groups: Some(vec!["authorization.k8s.io".to_string()]),
+                resources: Some(vec!["subjectaccessreviews".to_string()]),
+                verbs: vec!["create".to_string()],
+                ..Default::default()
+            },
         ]),
         ..Default::default()
     }
@@ -148,14 +276,200 @@ pub fn controller_cluster_role_binding() -> ClusterRoleBinding {
     }
 }
 
+/// Defines the testsys-controller service that test agents stream their results to the controller
+/// through, and that the k8s API server calls the mutating admission webhook through. Returns
+/// `None` if the controller serves neither.
+pub fn controller_service(options: &ControllerOptions) -> Option<Service> {
+    let results = options
+        .results_address
+        .as_deref()
+        .and_then(address_port)
+        .map(|port| ServicePort {
+            name: Some("results".to_string()),
+            port: port.into(),
+            target_port: Some(IntOrString::Int(port.into())),
+            ..Default::default()
+        });
+    let webhook = options
+        .webhook_address
+        .as_deref()
+        .and_then(address_port)
+        .map(|port| ServicePort {
+            name: Some("webhook".to_string()),
+            port: WEBHOOK_SERVICE_PORT,
+            target_port: Some(IntOrString::Int(port.into())),
+            ..Default::default()
+        });
+    let ports: Vec<_> = results.into_iter().chain(webhook).collect();
+    if ports.is_empty() {
+        return None;
+    }
+    Some(Service {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_SERVICE.to_string()),
+            namespace: Some(NAMESPACE.to_string()),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            selector: Some(btreemap! {
+                LABEL_COMPONENT.to_string() => "controller".to_string(),
+            }),
+            ports: Some(ports),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// The DNS names that the k8s API server reaches the controller's webhook at. The webhook's
+/// certificate must be valid for them.
+pub fn controller_webhook_dns_names() -> Vec<String> {
+    vec![
+        format!("{}.{}.svc", TESTSYS_CONTROLLER_SERVICE, NAMESPACE),
+        format!(
+            "{}.{}.svc.cluster.local",
+            TESTSYS_CONTROLLER_SERVICE, NAMESPACE
+        ),
+    ]
+}
+
+/// Defines the `kubernetes.io/tls` secret with the PEM encoded certificate and key that the
+/// controller serves its webhook with.
+pub fn controller_webhook_tls_secret(certificate: &str, key: &str) -> Secret {
+    Secret {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_WEBHOOK_TLS_SECRET.to_string()),
+            namespace: Some(NAMESPACE.to_string()),
+            ..Default::default()
+        },
+        type_: Some("kubernetes.io/tls".to_string()),
+        data: Some(btreemap! {
+            "tls.crt".to_string() => ByteString(certificate.as_bytes().to_vec()),
+            "tls.key".to_string() => ByteString(key.as_bytes().to_vec()),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Defines the registration of the controller's mutating admission webhook for new tests. The k8s
+/// API server trusts the webhook's certificate if it is signed by `ca_bundle`, a PEM encoded
+/// certificate. Tests are admitted unchanged if the webhook cannot be reached.
+pub fn controller_webhook_configuration(ca_bundle: Vec<u8>) -> MutatingWebhookConfiguration {
+    MutatingWebhookConfiguration {
+        metadata: ObjectMeta {
+            name: Some(TESTSYS_CONTROLLER_WEBHOOK.to_string()),
+            ..Default::default()
+        },
+        webhooks: Some(vec![MutatingWebhook {
+            name: format!("agent-defaults.{}", TESTSYS),
+            admission_review_versions: vec!["v1".to_string()],
+            client_config: WebhookClientConfig {
+                ca_bundle: Some(ByteString(ca_bundle)),
+                service: Some(ServiceReference {
+                    name: TESTSYS_CONTROLLER_SERVICE.to_string(),
+                    namespace: NAMESPACE.to_string(),
+                    path: Some("/mutate".to_string()),
+                    port: Some(WEBHOOK_SERVICE_PORT),
+                }),
+                url: None,
+            },
+            failure_policy: Some("Ignore".to_string()),
+            rules: Some(vec![RuleWithOperations {
+                api_groups: Some(vec![TESTSYS.to_string()]),
+                api_versions: Some(vec!["v1".to_string()]),
+                operations: Some(vec!["CREATE".to_string()]),
+                resources: Some(vec!["tests".to_string()]),
+                scope: Some("Namespaced".to_string()),
+            }]),
+            side_effects: "None".to_string(),
+            ..Default::default()
+        }]),
+    }
+}
+
+/// The URL that test agents reach the results endpoint the controller serves on `results_address`
+/// at, through the controller's service. Returns `None` if the address is not valid.
+pub fn results_endpoint(results_address: &str) -> Option<String> {
+    address_port(results_address).map(|port| {
+        format!(
+            "http://{}.{}.svc:{}",
+            TESTSYS_CONTROLLER_SERVICE, NAMESPACE, port
+        )
+    })
+}
+
+fn address_port(address: &str) -> Option<u16> {
+    address
+        .trim()
+        .parse::<SocketAddr>()
+        .ok()
+        .map(|address| address.port())
+}
+
 /// Defines the testsys-controller deployment
 pub fn controller_deployment(
     controller_image: String,
     image_pull_secret: Option<String>,
     enable_logging: bool,
+    options: ControllerOptions,
 ) -> Deployment {
     let image_pull_secrets =
         image_pull_secret.map(|secret| vec![LocalObjectReference { name: Some(secret) }]);
+    let mut env = vec![EnvVar {
+        name: TESTSYS_CONTROLLER_ARCHIVE_LOGS.to_string(),
+        value: Some(enable_logging.to_string()),
+        ..Default::default()
+    }];
+    if let Some(log_sink) = options.log_sink {
+        env.push(EnvVar {
+            name: TESTSYS_CONTROLLER_LOG_SINK.to_string(),
+            value: Some(log_sink),
+            ..Default::default()
+        });
+    }
+    if let Some(api_address) = options.api_address {
+        env.push(EnvVar {
+            name: TESTSYS_CONTROLLER_API_ADDRESS.to_string(),
+            value: Some(api_address),
+            ..Default::default()
+        });
+    }
+    if let Some(results_address) = options.results_address {
+        env.push(EnvVar {
+            name: TESTSYS_CONTROLLER_RESULTS_ADDRESS.to_string(),
+            value: Some(results_address),
+            ..Default::default()
+        });
+    }
+    let webhook_tls = options.webhook_address.is_some();
+    if let Some(webhook_address) = options.webhook_address {
+        env.push(EnvVar {
+            name: TESTSYS_CONTROLLER_WEBHOOK_ADDRESS.to_string(),
+            value: Some(webhook_address),
+            ..Default::default()
+        });
+    }
+    if !options.quarantine.is_empty() {
+        env.push(EnvVar {
+            name: TESTSYS_CONTROLLER_QUARANTINE.to_string(),
+            value: Some(options.quarantine.join(",")),
+            ..Default::default()
+        });
+    }
+    if !options.allowed_images.is_empty() {
+        env.push(EnvVar {
+            name: TESTSYS_CONTROLLER_ALLOWED_IMAGES.to_string(),
+            value: Some(options.allowed_images.join(",")),
+            ..Default::default()
+        });
+    }
+    if let Some(test_retention) = options.test_retention {
+        env.push(EnvVar {
+            name: TESTSYS_CONTROLLER_TEST_RETENTION.to_string(),
+            value: Some(test_retention),
+            ..Default::default()
+        });
+    }
 
     Deployment {
         metadata: ObjectMeta {
@@ -222,13 +536,29 @@ pub fn controller_deployment(
                         image: Some(controller_image),
                         image_pull_policy: None,
                         name: "controller".to_string(),
-                        env: Some(vec![EnvVar {
-                            name: TESTSYS_CONTROLLER_ARCHIVE_LOGS.to_string(),
-                            value: Some(enable_logging.to_string()),
-                            ..Default::default()
-                        }]),
+                        env: Some(env),
+                        volume_mounts: webhook_tls.then(|| {
+                            vec![VolumeMount {
+                                name: "webhook-tls".to_string(),
+                                mount_path: TESTSYS_CONTROLLER_WEBHOOK_TLS_PATH.to_string(),
+                                read_only: Some(true),
+                                ..Default::default()
+                            }]
+                        }),
                         ..Default::default()
                     }],
+                    volumes: webhook_tls.then(|| {
+                        vec![Volume {
+                            name: "webhook-tls".to_string(),
+                            secret: Some(SecretVolumeSource {
+                                secret_name: Some(
+                                    TESTSYS_CONTROLLER_WEBHOOK_TLS_SECRET.to_string(),
+                                ),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        }]
+                    }),
                     image_pull_secrets,
                     service_account_name: Some(TESTSYS_CONTROLLER_SERVICE_ACCOUNT.to_string()),
                     ..Default::default()
@@ -6,6 +6,21 @@ mod namespace;
 pub use agent::{agent_cluster_role, agent_cluster_role_binding, agent_service_account, AgentType};
 pub use controller::{
     controller_cluster_role, controller_cluster_role_binding, controller_deployment,
-    controller_service_account, TESTSYS_CONTROLLER_ARCHIVE_LOGS,
+    controller_service, controller_service_account, controller_webhook_configuration,
+    controller_webhook_dns_names, controller_webhook_tls_secret, results_endpoint,
+    ControllerOptions, TESTSYS_CONTROLLER_ALLOWED_IMAGES, TESTSYS_CONTROLLER_ANNOTATE_INPUT_HASH,
+    TESTSYS_CONTROLLER_API_ADDRESS, TESTSYS_CONTROLLER_ARCHIVE_LOGS,
+    TESTSYS_CONTROLLER_CAPACITY_TYPE_LABEL, TESTSYS_CONTROLLER_CA_BUNDLE,
+    TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_LOG_GROUP,
+    TESTSYS_CONTROLLER_CLOUDWATCH_METRICS_NAMESPACE, TESTSYS_CONTROLLER_ENDPOINT_WAIT_IMAGE,
+    TESTSYS_CONTROLLER_IMAGE_PULL_GRACE_PERIOD, TESTSYS_CONTROLLER_INSTALL_CRDS,
+    TESTSYS_CONTROLLER_INSTANCE_ID, TESTSYS_CONTROLLER_KUBE_BURST, TESTSYS_CONTROLLER_KUBE_QPS,
+    TESTSYS_CONTROLLER_LABEL_SELECTOR, TESTSYS_CONTROLLER_LOG_SINK,
+    TESTSYS_CONTROLLER_MAX_RESOURCES_PER_TEST, TESTSYS_CONTROLLER_MAX_STATUS_FIELD_LEN,
+    TESTSYS_CONTROLLER_MAX_TIMELINE_ENTRIES, TESTSYS_CONTROLLER_MAX_TIMEOUT_EXTENSION,
+    TESTSYS_CONTROLLER_OBSERVE_ONLY, TESTSYS_CONTROLLER_PROTECTED_LABELS,
+    TESTSYS_CONTROLLER_QUARANTINE, TESTSYS_CONTROLLER_RESULTS_ADDRESS,
+    TESTSYS_CONTROLLER_TEST_RETENTION, TESTSYS_CONTROLLER_WEBHOOK_ADDRESS,
+    TESTSYS_CONTROLLER_WEBHOOK_TLS_PATH, TESTSYS_CONTROLLER_WEBHOOK_TLS_SECRET,
 };
 pub use namespace::testsys_namespace;
@@ -1,12 +1,18 @@
-use crate::constants::FINALIZER_MAIN;
+use crate::constants::{
+    ANNOTATION_ARCHIVE, ANNOTATION_KEEP_RESOURCES, ANNOTATION_RERUN, FINALIZER_MAIN,
+    MAX_JOB_NAME_LEN, TRUNCATED_MARKER,
+};
 use crate::crd_ext::CrdExt;
 use crate::{Agent, TaskState};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::chrono::{DateTime, SecondsFormat, Utc};
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_plain::derive_display_from_serialize;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 
 /// A TestSys Test. The `CustomResource` derive also produces a struct named `Test` which represents
 /// a test CRD object in the k8s API.
@@ -25,7 +31,8 @@ use std::borrow::Cow;
     category = "testsys",
     version = "v1",
     printcolumn = r#"{"name":"State", "type":"string", "jsonPath":".status.agent.taskState"}"#,
-    printcolumn = r#"{"name":"Result", "type":"string", "jsonPath":".status.agent.results.outcome"}"#
+    printcolumn = r#"{"name":"Result", "type":"string", "jsonPath":".status.agent.results.outcome"}"#,
+    printcolumn = r#"{"name":"Suspended", "type":"boolean", "jsonPath":".spec.suspend"}"#
 )]
 #[serde(rename_all = "camelCase")]
 pub struct TestSpec {
@@ -36,10 +43,146 @@ pub struct TestSpec {
     pub depends_on: Option<Vec<String>>,
     /// Information about the test agent.
     pub agent: Agent,
+    /// Additional test agents that are run side by side with `agent`, each in its own job, e.g. to
+    /// compare two versions of an agent image running the same workload. Their statuses are
+    /// reported in `status.agents` keyed by agent name, so every agent must have a unique name. The
+    /// test only passes if all of its agents pass.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agents: Vec<Agent>,
     /// The number of retries the agent is allowed to perform after a failed test.
     pub retries: Option<u32>,
+    /// Informational tests report their results as usual, but a failure does not cause the set of
+    /// tests they are run with to be considered failed. This is useful for flaky or experimental
+    /// tests.
+    #[serde(default)]
+    pub informational: bool,
+    /// Cluster capabilities that must be present before the test agent is started. Each entry is
+    /// either a well-known capability name, e.g. `ebs-csi-driver`, or a reference to a k8s object
+    /// in the form `crd/<name>`, `deployment/<namespace>/<name>` or `daemonset/<namespace>/<name>`.
+    /// If any capability is missing the test fails without being run.
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Arbitrary user information about the test, e.g. the git commit or pull request that
+    /// triggered it. TestSys does not interpret it, the controller copies it verbatim into the
+    /// test's status so that it is reported alongside the results.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// Run the test on a recurring schedule. A test with a schedule is not run itself, instead the
+    /// controller creates a copy of it, without the schedule, at each tick of the schedule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    /// Rules that the agent's results must satisfy for the test to pass, e.g. that no test cases
+    /// failed or that at least 100 passed. The controller evaluates them against the agent's last
+    /// results once it completes, and their verdict overrides the outcome the agent reported: the
+    /// test fails if any of them does not hold and passes otherwise. Additional agents in `agents`
+    /// are not affected.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assertions: Vec<ResultAssertion>,
+    /// Endpoints from the outputs of the test's resources that the test agent pod waits for before
+    /// the agent starts. The controller resolves them when it creates the agent's job and adds an
+    /// init container that polls each of them until it responds. The time spent waiting does not
+    /// count against the agent's `timeout`, or the time it has to start.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wait_for_endpoints: Vec<ResourceEndpoint>,
+    /// Suspend the test. The controller does not create the test agent's job, or act on a job that
+    /// already exists, until `suspend` is set back to `false`. A suspended test with a `schedule`
+    /// does not create runs. A suspended test can still be deleted.
+    #[serde(default)]
+    pub suspend: bool,
+    /// Destroy the test's resources when the test is deleted, one at a time in reverse dependency
+    /// order. Resources that are never destroyed, or that other tests or resources still use, are
+    /// kept. By default a deleted test's resources are kept, and they are always kept when a test
+    /// is restarted.
+    #[serde(default)]
+    pub destroy_resources: bool,
 }
 
+/// A URL in the created outputs of one of the test's resources.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceEndpoint {
+    /// The name of the resource, which must be one of the test's `resources`.
+    pub resource: String,
+    /// The output of the resource that holds the URL, e.g. `endpoint`.
+    pub field: String,
+}
+
+/// A recurring schedule for a test.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    /// When to run the test, as a cron expression with five fields: minute, hour, day of month,
+    /// month and day of week, in UTC. For example `0 3 * * *` runs the test every night at 3am.
+    pub cron: String,
+    /// How many of the most recent runs are kept, older runs are deleted. Defaults to 5.
+    pub keep_runs: Option<u32>,
+}
+
+/// A comparison of one of the counts in the agent's [`TestResults`] with a `value`, e.g.
+/// `numFailed` `eq` `0`.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultAssertion {
+    /// The count that is compared.
+    pub field: ResultField,
+    /// How the count is compared with `value`.
+    pub comparison: Comparison,
+    pub value: u64,
+}
+
+impl ResultAssertion {
+    /// Whether the assertion holds for `results`.
+    pub fn holds(&self, results: &TestResults) -> bool {
+        let count = match self.field {
+            ResultField::NumPassed => results.num_passed,
+            ResultField::NumFailed => results.num_failed,
+            ResultField::NumSkipped => results.num_skipped,
+            ResultField::Total => results.total(),
+        };
+        match self.comparison {
+            Comparison::Lt => count < self.value,
+            Comparison::Le => count <= self.value,
+            Comparison::Eq => count == self.value,
+            Comparison::Ne => count != self.value,
+            Comparison::Ge => count >= self.value,
+            Comparison::Gt => count > self.value,
+        }
+    }
+}
+
+impl Display for ResultAssertion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.field, self.comparison, self.value)
+    }
+}
+
+/// The counts of [`TestResults`] that a [`ResultAssertion`] can compare.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ResultField {
+    NumPassed,
+    NumFailed,
+    NumSkipped,
+    /// The sum of the passed, failed and skipped test cases.
+    Total,
+}
+
+derive_display_from_serialize!(ResultField);
+
+/// How a [`ResultAssertion`] compares a count with its value.
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+derive_display_from_serialize!(Comparison);
+
 /// The status field of the TestSys Test CRD. This is where the controller and agents will write
 /// information about the status of the test run.
 #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
@@ -49,10 +192,44 @@ pub struct TestStatus {
     pub controller: ControllerStatus,
     /// Information written by the test agent.
     pub agent: AgentStatus,
+    /// Information written by each of the additional agents in `spec.agents`, keyed by agent name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub agents: BTreeMap<String, AgentStatus>,
+    /// A summary of each of the test's resources, keyed by resource name, written by the controller
+    /// until the test agent is done.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources: BTreeMap<String, ResourceSummary>,
+    /// The test's state as standard k8s conditions of type `Ready`, `Progressing` and `Failed`,
+    /// maintained by the controller for tools that do not understand the fields above.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<TestCondition>,
+    /// The states the test went through, oldest first, appended by the controller whenever the
+    /// test's state changes if it is configured to keep a timeline. Only the most recent entries
+    /// are kept.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<TimelineEntry>,
     /// The time of the last change to this CRD.
     pub last_update: Option<String>,
 }
 
+/// A summary of a resource used by a test, copied from the `Resource` by the controller so that it
+/// can be seen alongside the test.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceSummary {
+    /// The name of the resource agent.
+    pub agent: String,
+    /// The state of the resource agent when creating the resource.
+    pub creation_state: TaskState,
+    /// Whether the created resource is ready for use, if its agent reports readiness.
+    pub ready: Option<bool>,
+    /// The top-level string, number and boolean fields of the created resource that its agent
+    /// declared in its output schema, e.g. endpoints and IDs. Fields the agent marked as sensitive
+    /// are left out.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, String>,
+}
+
 /// The `Outcome` of a test run, reported by the test agent.
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Copy, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
@@ -100,12 +277,138 @@ pub struct AgentStatus {
     pub error: Option<String>,
     pub results: Vec<TestResults>,
     pub current_test: Option<TestResults>,
+    /// The progress of the agent's indexed completions, e.g. the shards of a sharded test, copied
+    /// from its job's status by the controller while the job runs.
+    pub progress: Option<JobProgress>,
+    /// The message the agent's container wrote to its termination log, `/dev/termination-log`,
+    /// before it exited with an error. The controller copies it from the pod when the job fails.
+    pub termination_message: Option<String>,
+    /// More time the agent asked for beyond its `timeout`, e.g. `30m`. The controller grants or
+    /// rejects the request and then clears it.
+    pub extension_request: Option<String>,
+    /// The total number of seconds the controller has added to the agent's `timeout` at its
+    /// request.
+    pub timeout_extension_seconds: Option<u64>,
+    /// Why the controller rejected the agent's last request for more time.
+    pub rejected_extension: Option<String>,
+    /// Whether the agent container passes its readiness probe, copied from its pod by the
+    /// controller while the agent runs. Only set for a test agent that has a readiness probe.
+    pub ready: Option<bool>,
+    /// The memory limits the controller relaunched the agent with after it ran out of memory, in
+    /// order. The last one is the limit of the current run.
+    #[serde(default)]
+    pub memory_limits: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ControllerStatus {
     pub resource_error: Option<String>,
+    /// The number of consecutive times the controller has failed to create the test agent's
+    /// `Job`. Once this reaches the controller's limit the test is put in an error state and no
+    /// further attempts are made.
+    #[serde(default)]
+    pub job_creation_failures: u32,
+    /// The reason the pre-flight check of the test's required capabilities failed.
+    pub preflight_error: Option<String>,
+    /// Why the test's spec is invalid. The test is not run until its spec is fixed.
+    pub invalid_spec: Option<String>,
+    /// A copy of the user metadata from the test's spec.
+    pub metadata: Option<BTreeMap<String, String>>,
+    /// The generation of the test's spec that the controller last saw. When the spec changes the
+    /// controller resets the fields above that describe the previous generation.
+    pub observed_generation: Option<i64>,
+    /// Whether the test matched the controller's quarantine list and was skipped without running.
+    #[serde(default)]
+    pub quarantined: bool,
+    /// Whether the test was archived. Its job and resources have been torn down and the controller
+    /// no longer manages it, but the test is kept for its history.
+    #[serde(default)]
+    pub archived: bool,
+    /// How many of the agent's indexed completions succeeded and failed, once the controller has
+    /// evaluated them against the agent's success threshold.
+    pub completions: Option<Completions>,
+    /// When the test reached a terminal state (RFC 3339), used to delete old tests.
+    pub finished_at: Option<String>,
+    /// The version of the controller that last started the test agent.
+    pub controller_version: Option<String>,
+    /// The version of the k8s API server the test agent was last started on, if it was detected.
+    pub kube_server_version: Option<String>,
+    /// The ID that ties the controller's logs for the test to the logs of its agents, which get it
+    /// in their environment. It is taken from the test's `correlation-id` annotation if it has one.
+    pub correlation_id: Option<String>,
+    /// The assertions in the test's spec that did not hold for the agent's results, once the
+    /// controller has evaluated them. Empty if all of them held.
+    pub failed_assertions: Option<Vec<String>>,
+}
+
+/// The number of an agent's indexed completions that succeeded and failed.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Completions {
+    pub succeeded: i32,
+    pub failed: i32,
+}
+
+/// The number of an agent's indexed completions that are desired, running, succeeded and failed
+/// according to its job.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub desired: i32,
+    pub active: i32,
+    pub succeeded: i32,
+    pub failed: i32,
+}
+
+impl Display for JobProgress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "completions: {}/{}", self.succeeded, self.desired)
+    }
+}
+
+/// A condition of a test following the k8s conventions for status conditions.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestCondition {
+    #[serde(rename = "type")]
+    pub condition_type: TestConditionType,
+    pub status: ConditionStatus,
+    /// The `TestUserState` of the test when the condition last changed, in `PascalCase`.
+    pub reason: String,
+    /// When the condition's status last changed (RFC 3339).
+    pub last_transition_time: String,
+}
+
+/// A change of the state of a test.
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineEntry {
+    /// When the controller saw the change (RFC 3339).
+    pub timestamp: String,
+    /// The `TestUserState` the test changed to, in `camelCase`.
+    pub phase: String,
+    /// The state the test changed from and why it changed, if that is known.
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+pub enum TestConditionType {
+    /// The test passed.
+    #[default]
+    Ready,
+    /// The test is waiting for its resources or its agents are running.
+    Progressing,
+    /// The test failed or could not be run.
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Eq, PartialEq, Clone, Copy, JsonSchema)]
+pub enum ConditionStatus {
+    True,
+    False,
+    #[default]
+    Unknown,
 }
 
 /// A simplified summary of the test's current state. This can be used by a user interface to
@@ -131,8 +434,55 @@ pub enum TestUserState {
     Error,
     /// Resource creation failed and the test will not be started.
     ResourceError,
+    /// The cluster is missing a capability required by the test and the test will not be started.
+    PreflightFailed,
+    /// The test's spec is invalid and the test will not be started.
+    InvalidSpec,
+    /// The test is quarantined by the controller and was skipped without running.
+    Quarantined,
+    /// The test's spec has `suspend` set and the controller is not acting on it.
+    Suspended,
     /// The test is in the process of being deleted.
     Deleting,
+    /// The test was archived and is kept only for its history.
+    Archived,
+}
+
+impl TestUserState {
+    /// Whether the test will not change state anymore unless it is rerun, e.g. it passed, failed
+    /// or could not be run.
+    pub fn is_terminal(&self) -> bool {
+        !matches!(
+            self,
+            Self::Unknown | Self::Waiting | Self::Running | Self::Suspended | Self::Deleting
+        )
+    }
+
+    /// Whether the test finished without passing, e.g. it failed or could not be run.
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::Failed
+                | Self::Error
+                | Self::ResourceError
+                | Self::PreflightFailed
+                | Self::InvalidSpec
+        )
+    }
+
+    /// Orders the states an agent can be in, unfinished states first and then outcomes from worst to
+    /// best, which is how the states of a test's agents are combined.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Unknown => 0,
+            Self::Waiting => 1,
+            Self::Running => 2,
+            Self::Error => 3,
+            Self::Failed => 4,
+            Self::NoTests => 5,
+            _ => 6,
+        }
+    }
 }
 
 impl Default for TestUserState {
@@ -144,6 +494,76 @@ impl Default for TestUserState {
 serde_plain::derive_display_from_serialize!(TestUserState);
 
 impl Test {
+    /// The number of times this test has been rerun, read from the `testsys.system/rerun`
+    /// annotation. Defaults to `0` if the annotation is missing or invalid.
+    pub fn rerun(&self) -> u32 {
+        self.metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(ANNOTATION_RERUN))
+            .and_then(|rerun| rerun.parse().ok())
+            .unwrap_or_default()
+    }
+
+    /// Whether the test should be archived, i.e. the `testsys.system/archive` annotation is `true`.
+    pub fn is_archive_requested(&self) -> bool {
+        self.metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(ANNOTATION_ARCHIVE))
+            .map(|archive| archive == "true")
+            .unwrap_or_default()
+    }
+
+    /// Whether the test's resources are destroyed once it is deleted, i.e. the test opted into
+    /// `destroy_resources` and was not deleted to be restarted, which sets the
+    /// `testsys.system/keep-resources` annotation.
+    pub fn destroys_resources_on_deletion(&self) -> bool {
+        self.spec.destroy_resources
+            && self
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(ANNOTATION_KEEP_RESOURCES))
+                .map(|keep| keep != "true")
+                .unwrap_or(true)
+    }
+
+    /// Gets the name of the k8s `Job` that runs the test agent. The name consists of the test name,
+    /// a short hash of the test's UID and the rerun counter so that each run of a test has a unique
+    /// but deterministic name. The test name is truncated so that the `Job` name is within the
+    /// k8s-enforced 63-character limit.
+    pub fn job_name(&self) -> String {
+        self.job_name_with_suffix(format!(
+            "-{:08x}-{}",
+            short_hash(self.metadata.uid.as_deref().unwrap_or_default()),
+            self.rerun()
+        ))
+    }
+
+    /// Gets the name of the k8s `Job` that runs the additional agent named `agent_name` from
+    /// `spec.agents`. It is the name of the test's `Job` followed by a short hash of the agent
+    /// name.
+    pub fn agent_job_name(&self, agent_name: &str) -> String {
+        self.job_name_with_suffix(format!(
+            "-{:08x}-{}-{:08x}",
+            short_hash(self.metadata.uid.as_deref().unwrap_or_default()),
+            self.rerun(),
+            short_hash(agent_name)
+        ))
+    }
+
+    fn job_name_with_suffix(&self, suffix: String) -> String {
+        let name = self.metadata.name.as_deref().unwrap_or_default();
+        let truncated_name: String = name.chars().take(MAX_JOB_NAME_LEN - suffix.len()).collect();
+        // The name must end with an alphanumeric character before we append the suffix.
+        format!(
+            "{}{}",
+            truncated_name.trim_end_matches(|c: char| !c.is_ascii_alphanumeric()),
+            suffix
+        )
+    }
+
     pub fn agent_status(&self) -> Cow<'_, AgentStatus> {
         match self.status.as_ref() {
             None => Cow::Owned(AgentStatus::default()),
@@ -151,6 +571,18 @@ impl Test {
         }
     }
 
+    /// The status of the additional agent named `agent_name` from `spec.agents`.
+    pub fn additional_agent_status(&self, agent_name: &str) -> Cow<'_, AgentStatus> {
+        match self
+            .status
+            .as_ref()
+            .and_then(|status| status.agents.get(agent_name))
+        {
+            None => Cow::Owned(AgentStatus::default()),
+            Some(agent_status) => Cow::Borrowed(agent_status),
+        }
+    }
+
     pub fn agent_error(&self) -> Option<&str> {
         self.status
             .as_ref()
@@ -165,14 +597,305 @@ impl Test {
             .and_then(|some| some.resource_error.as_ref())
     }
 
+    pub fn preflight_error(&self) -> Option<&String> {
+        self.status
+            .as_ref()
+            .map(|some| &some.controller)
+            .and_then(|some| some.preflight_error.as_ref())
+    }
+
+    /// The resource summaries that the controller has written to the test's status.
+    pub fn resource_summaries(&self) -> Option<&BTreeMap<String, ResourceSummary>> {
+        self.status.as_ref().map(|some| &some.resources)
+    }
+
+    /// Why the controller found the test's spec to be invalid, if it did.
+    pub fn invalid_spec(&self) -> Option<&String> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.invalid_spec.as_ref())
+    }
+
+    /// The user metadata that the controller has copied into the test's status.
+    pub fn status_metadata(&self) -> Option<&BTreeMap<String, String>> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.metadata.as_ref())
+    }
+
+    /// When the test reached a terminal state, if it has.
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.finished_at.as_deref())
+            .and_then(|finished_at| DateTime::parse_from_rfc3339(finished_at).ok())
+            .map(|finished_at| finished_at.with_timezone(&Utc))
+    }
+
+    /// The version of the controller that last started the test agent.
+    pub fn controller_version(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.controller_version.as_deref())
+    }
+
+    /// The version of the k8s API server the test agent was last started on.
+    pub fn kube_server_version(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.kube_server_version.as_deref())
+    }
+
+    /// The ID that ties the controller's logs for the test to the logs of its agents.
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.correlation_id.as_deref())
+    }
+
+    /// The assertions that did not hold for the agent's results if the controller has evaluated
+    /// them.
+    pub fn failed_assertions(&self) -> Option<&[String]> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.failed_assertions.as_deref())
+    }
+
+    /// The agent's indexed completions if the controller has evaluated them.
+    pub fn completions(&self) -> Option<Completions> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.completions)
+    }
+
+    /// The test's standard k8s conditions.
+    pub fn conditions(&self) -> &[TestCondition] {
+        self.status
+            .as_ref()
+            .map(|some| some.conditions.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The test's conditions for its current state, or `None` if they have not changed. The
+    /// `lastTransitionTime` of a condition is set to `now` only if its status changed.
+    pub fn updated_conditions(&self, now: DateTime<Utc>) -> Option<Vec<TestCondition>> {
+        self.status.as_ref()?;
+        let state = self.test_user_state();
+        let status = |is_true: bool| {
+            if is_true {
+                ConditionStatus::True
+            } else {
+                ConditionStatus::False
+            }
+        };
+        let desired = [
+            (
+                TestConditionType::Ready,
+                status(state == TestUserState::Passed),
+            ),
+            (
+                TestConditionType::Progressing,
+                status(matches!(
+                    state,
+                    TestUserState::Waiting | TestUserState::Running
+                )),
+            ),
+            (
+                TestConditionType::Failed,
+                status(matches!(
+                    state,
+                    TestUserState::Failed
+                        | TestUserState::Error
+                        | TestUserState::ResourceError
+                        | TestUserState::PreflightFailed
+                        | TestUserState::InvalidSpec
+                )),
+            ),
+        ];
+        let conditions: Vec<TestCondition> = desired
+            .into_iter()
+            .map(|(condition_type, status)| {
+                match self
+                    .conditions()
+                    .iter()
+                    .find(|condition| condition.condition_type == condition_type)
+                {
+                    Some(condition) if condition.status == status => condition.clone(),
+                    _ => TestCondition {
+                        condition_type,
+                        status,
+                        reason: format!("{:?}", state),
+                        last_transition_time: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    },
+                }
+            })
+            .collect();
+        if conditions == self.conditions() {
+            None
+        } else {
+            Some(conditions)
+        }
+    }
+
+    /// The states the test went through, oldest first.
+    pub fn timeline(&self) -> &[TimelineEntry] {
+        self.status
+            .as_ref()
+            .map(|some| some.timeline.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The test's timeline with an entry for its current state appended, or `None` if the timeline
+    /// would not change. Only the last `max_entries` entries are kept and each entry's message is
+    /// truncated to `MAX_TIMELINE_MESSAGE_LEN` bytes.
+    pub fn updated_timeline(
+        &self,
+        now: DateTime<Utc>,
+        max_entries: usize,
+    ) -> Option<Vec<TimelineEntry>> {
+        self.status.as_ref()?;
+        if max_entries == 0 {
+            return if self.timeline().is_empty() {
+                None
+            } else {
+                Some(Vec::new())
+            };
+        }
+        let state = self.test_user_state();
+        let phase = state.to_string();
+        let previous = self.timeline().last();
+        if previous.map(|entry| &entry.phase) == Some(&phase) {
+            return None;
+        }
+        let mut message = match previous {
+            Some(previous) => format!("{} -> {}", previous.phase, phase),
+            None => phase.clone(),
+        };
+        if let Some(reason) = self.state_reason(state) {
+            message = format!("{}: {}", message, reason);
+        }
+        truncate_message(&mut message);
+        let mut timeline = self.timeline().to_vec();
+        timeline.push(TimelineEntry {
+            timestamp: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            phase,
+            message,
+        });
+        let evicted = timeline.len().saturating_sub(max_entries);
+        timeline.drain(..evicted);
+        Some(timeline)
+    }
+
+    /// Why the test is in `state`, for the states that have a reason.
+    fn state_reason(&self, state: TestUserState) -> Option<String> {
+        match state {
+            TestUserState::ResourceError => self.resource_error().cloned(),
+            TestUserState::PreflightFailed => self.preflight_error().cloned(),
+            TestUserState::InvalidSpec => self.invalid_spec().cloned(),
+            TestUserState::Error => self.agent_error().map(str::to_string),
+            TestUserState::Failed => match self.failed_assertions() {
+                Some(failed) if !failed.is_empty() => {
+                    Some(format!("failed assertions {}", failed.join(", ")))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the controller has archived the test.
+    pub fn is_archived(&self) -> bool {
+        self.status
+            .as_ref()
+            .map(|some| some.controller.archived)
+            .unwrap_or_default()
+    }
+
+    /// Whether the controller skipped the test because it is quarantined.
+    pub fn is_quarantined(&self) -> bool {
+        self.status
+            .as_ref()
+            .map(|some| some.controller.quarantined)
+            .unwrap_or_default()
+    }
+
+    /// The generation of the test's spec that the controller last saw.
+    pub fn observed_generation(&self) -> Option<i64> {
+        self.status
+            .as_ref()
+            .and_then(|some| some.controller.observed_generation)
+    }
+
+    pub fn job_creation_failures(&self) -> u32 {
+        self.status
+            .as_ref()
+            .map(|some| some.controller.job_creation_failures)
+            .unwrap_or_default()
+    }
+
     pub fn test_user_state(&self) -> TestUserState {
         let agent_status = self.agent_status();
         if self.is_delete_requested() && !matches!(agent_status.task_state, TaskState::Unknown) {
             return TestUserState::Deleting;
         }
+        if self.is_archived() {
+            return TestUserState::Archived;
+        }
         if self.resource_error().is_some() {
             return TestUserState::ResourceError;
         }
+        if self.preflight_error().is_some() {
+            return TestUserState::PreflightFailed;
+        }
+        if self.invalid_spec().is_some() {
+            return TestUserState::InvalidSpec;
+        }
+        if self.is_quarantined() {
+            return TestUserState::Quarantined;
+        }
+        let state = match self.failed_assertions() {
+            // The assertions replace the verdict of the agent once they have been evaluated.
+            Some(failed) if agent_status.task_state == TaskState::Completed => {
+                if failed.is_empty() {
+                    TestUserState::Passed
+                } else {
+                    TestUserState::Failed
+                }
+            }
+            _ => self.agent_user_state(&agent_status),
+        };
+        // An agent with a readiness probe has only started working once it is ready.
+        let state = if state == TestUserState::Running
+            && self.spec.agent.readiness_probe.is_some()
+            && agent_status.ready != Some(true)
+        {
+            TestUserState::Waiting
+        } else {
+            state
+        };
+        // Every agent has to pass for the test to pass. The test is running while any of its agents
+        // is, after that the worst outcome of its agents is reported.
+        let state = self
+            .spec
+            .agents
+            .iter()
+            .map(|agent| self.agent_user_state(&self.additional_agent_status(&agent.name)))
+            .fold(state, |state, agent_state| {
+                if agent_state.rank() < state.rank() {
+                    agent_state
+                } else {
+                    state
+                }
+            });
+        // Suspending a test that has already finished does not change its outcome.
+        if self.spec.suspend && !state.is_terminal() {
+            TestUserState::Suspended
+        } else {
+            state
+        }
+    }
+
+    fn agent_user_state(&self, agent_status: &AgentStatus) -> TestUserState {
         match agent_status.task_state {
             TaskState::Unknown => {
                 if self.has_finalizer(FINALIZER_MAIN) {
@@ -207,8 +930,447 @@ impl Test {
     }
 }
 
+/// The longest message a timeline entry keeps, in bytes, so that a long agent error does not get
+/// copied into the status once per entry.
+const MAX_TIMELINE_MESSAGE_LEN: usize = 256;
+
+/// Truncate `message` to `MAX_TIMELINE_MESSAGE_LEN` bytes, ending it with [`TRUNCATED_MARKER`].
+fn truncate_message(message: &mut String) {
+    if message.len() <= MAX_TIMELINE_MESSAGE_LEN {
+        return;
+    }
+    let mut end = MAX_TIMELINE_MESSAGE_LEN - TRUNCATED_MARKER.len();
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    message.truncate(end);
+    message.push_str(TRUNCATED_MARKER);
+}
+
+/// A 64-bit FNV-1a hash of `bytes`, which unlike `std`'s hashers is stable across Rust versions.
+/// It is used wherever a generated name or annotation has to be derived from an object.
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The low 32 bits of the [`fnv1a_hash`] of `value`, used to shorten UIDs in generated names.
+fn short_hash(value: &str) -> u32 {
+    fnv1a_hash(value.as_bytes()) as u32
+}
+
 impl CrdExt for Test {
     fn object_meta(&self) -> &ObjectMeta {
         &self.metadata
     }
 }
+
+#[cfg(test)]
+mod job_name_test {
+    use super::*;
+    use maplit::btreemap;
+
+    fn test_crd(name: &str, uid: &str, rerun: Option<u32>) -> Test {
+        Test {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                uid: Some(uid.to_string()),
+                annotations: rerun.map(|rerun| {
+                    btreemap! { ANNOTATION_RERUN.to_string() => rerun.to_string() }
+                }),
+                ..ObjectMeta::default()
+            },
+            ..Test::default()
+        }
+    }
+
+    fn is_valid_job_name(name: &str) -> bool {
+        name.len() <= MAX_JOB_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+    }
+
+    #[test]
+    fn job_name_is_deterministic() {
+        let test = test_crd("my-test", "8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e", None);
+        assert_eq!(test.job_name(), test.clone().job_name());
+        assert!(test.job_name().starts_with("my-test-"));
+        assert!(is_valid_job_name(&test.job_name()));
+    }
+
+    #[test]
+    fn job_name_differs_between_reruns() {
+        let uid = "8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e";
+        let first = test_crd("my-test", uid, None).job_name();
+        let second = test_crd("my-test", uid, Some(1)).job_name();
+        let third = test_crd("my-test", "d2a4b9b0-3c1c-4f2e-9b5b-2e8f0a6c1b7d", Some(1)).job_name();
+        assert_ne!(first, second);
+        assert_ne!(second, third);
+        assert!(is_valid_job_name(&first));
+        assert!(is_valid_job_name(&second));
+        assert!(is_valid_job_name(&third));
+    }
+
+    #[test]
+    fn job_name_is_truncated() {
+        let long_name = format!("{}-", "a".repeat(80));
+        let job_name = test_crd(&long_name, "uid", Some(4_000_000_000)).job_name();
+        assert!(is_valid_job_name(&job_name), "{}", job_name);
+        assert!(job_name.ends_with("-4000000000"));
+        // Truncation leaves a trailing '-' which must be removed.
+        let trailing_dash = format!("{}-bbbb", "a".repeat(51));
+        let job_name = test_crd(&trailing_dash, "uid", None).job_name();
+        assert!(is_valid_job_name(&job_name), "{}", job_name);
+        assert!(job_name.starts_with(&format!("{}-", "a".repeat(51))));
+        assert!(!job_name.contains("--"));
+    }
+
+    #[test]
+    fn agent_job_names_differ() {
+        let test = test_crd("my-test", "8a2b5b58-8f8e-4bba-b1b3-0c1c9a3d7d2e", None);
+        let baseline = test.agent_job_name("baseline");
+        let candidate = test.agent_job_name("candidate");
+        assert_ne!(baseline, candidate);
+        assert_ne!(baseline, test.job_name());
+        assert!(baseline.starts_with("my-test-"));
+        assert!(is_valid_job_name(&baseline));
+    }
+}
+
+#[cfg(test)]
+mod conditions_test {
+    use super::*;
+    use k8s_openapi::chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 15, 12, minute, 0)
+            .single()
+            .unwrap_or_default()
+    }
+
+    fn with_conditions(test: &mut Test, now: DateTime<Utc>) {
+        if let Some(conditions) = test.updated_conditions(now) {
+            if let Some(status) = test.status.as_mut() {
+                status.conditions = conditions;
+            }
+        }
+    }
+
+    fn condition(test: &Test, condition_type: TestConditionType) -> Option<&TestCondition> {
+        test.conditions()
+            .iter()
+            .find(|condition| condition.condition_type == condition_type)
+    }
+
+    #[test]
+    fn transition_updates_timestamp() {
+        let mut test = Test::new("my-test", TestSpec::default());
+        test.metadata.finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+        test.status = Some(TestStatus::default());
+        with_conditions(&mut test, at(0));
+        assert_eq!(
+            condition(&test, TestConditionType::Progressing),
+            Some(&TestCondition {
+                condition_type: TestConditionType::Progressing,
+                status: ConditionStatus::True,
+                reason: "Waiting".to_string(),
+                last_transition_time: "2026-10-15T12:00:00Z".to_string(),
+            })
+        );
+
+        // Nothing changed, so there is nothing to update.
+        assert_eq!(test.updated_conditions(at(1)), None);
+
+        // Running is still progressing, so the condition is kept as it was.
+        if let Some(status) = test.status.as_mut() {
+            status.agent.task_state = TaskState::Running;
+        }
+        assert_eq!(test.updated_conditions(at(2)), None);
+
+        if let Some(status) = test.status.as_mut() {
+            status.agent.task_state = TaskState::Completed;
+            status.agent.results = vec![TestResults {
+                outcome: Outcome::Pass,
+                num_passed: 1,
+                ..TestResults::default()
+            }];
+        }
+        with_conditions(&mut test, at(3));
+        let transition = |condition_type| {
+            condition(&test, condition_type)
+                .map(|condition| (condition.status, condition.last_transition_time.as_str()))
+        };
+        assert_eq!(
+            transition(TestConditionType::Ready),
+            Some((ConditionStatus::True, "2026-10-15T12:03:00Z"))
+        );
+        assert_eq!(
+            transition(TestConditionType::Progressing),
+            Some((ConditionStatus::False, "2026-10-15T12:03:00Z"))
+        );
+        assert_eq!(
+            transition(TestConditionType::Failed),
+            Some((ConditionStatus::False, "2026-10-15T12:00:00Z"))
+        );
+        assert_eq!(test.updated_conditions(at(4)), None);
+    }
+}
+
+#[cfg(test)]
+mod timeline_test {
+    use super::*;
+    use k8s_openapi::chrono::TimeZone;
+
+    fn at(minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 15, 12, minute, 0)
+            .single()
+            .unwrap_or_default()
+    }
+
+    fn with_timeline(test: &mut Test, now: DateTime<Utc>, max_entries: usize) {
+        if let Some(timeline) = test.updated_timeline(now, max_entries) {
+            if let Some(status) = test.status.as_mut() {
+                status.timeline = timeline;
+            }
+        }
+    }
+
+    fn set_task_state(test: &mut Test, task_state: TaskState, error: Option<&str>) {
+        if let Some(status) = test.status.as_mut() {
+            status.agent.task_state = task_state;
+            status.agent.error = error.map(str::to_string);
+        }
+    }
+
+    #[test]
+    fn transitions_are_appended() {
+        let mut test = Test::new("my-test", TestSpec::default());
+        test.metadata.finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+        test.status = Some(TestStatus::default());
+        with_timeline(&mut test, at(0), 10);
+        // Nothing changed, so there is nothing to append.
+        assert_eq!(test.updated_timeline(at(1), 10), None);
+
+        set_task_state(&mut test, TaskState::Running, None);
+        with_timeline(&mut test, at(2), 10);
+        set_task_state(&mut test, TaskState::Error, Some("The job failed"));
+        with_timeline(&mut test, at(3), 10);
+        assert_eq!(
+            test.timeline(),
+            [
+                TimelineEntry {
+                    timestamp: "2026-10-15T12:00:00Z".to_string(),
+                    phase: "waiting".to_string(),
+                    message: "waiting".to_string(),
+                },
+                TimelineEntry {
+                    timestamp: "2026-10-15T12:02:00Z".to_string(),
+                    phase: "running".to_string(),
+                    message: "waiting -> running".to_string(),
+                },
+                TimelineEntry {
+                    timestamp: "2026-10-15T12:03:00Z".to_string(),
+                    phase: "error".to_string(),
+                    message: "running -> error: The job failed".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn old_entries_are_evicted() {
+        let mut test = Test::new("my-test", TestSpec::default());
+        test.metadata.finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+        test.status = Some(TestStatus::default());
+        for (minute, task_state) in [
+            TaskState::Unknown,
+            TaskState::Running,
+            TaskState::Unknown,
+            TaskState::Running,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            set_task_state(&mut test, task_state, None);
+            with_timeline(&mut test, at(minute as u32), 3);
+        }
+        let timestamps: Vec<&str> = test
+            .timeline()
+            .iter()
+            .map(|entry| entry.timestamp.as_str())
+            .collect();
+        assert_eq!(
+            timestamps,
+            [
+                "2026-10-15T12:01:00Z",
+                "2026-10-15T12:02:00Z",
+                "2026-10-15T12:03:00Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_timeline_is_cleared_once() {
+        let mut test = Test::new("my-test", TestSpec::default());
+        test.metadata.finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+        test.status = Some(TestStatus::default());
+        with_timeline(&mut test, at(0), 10);
+        assert_eq!(test.updated_timeline(at(1), 0), Some(Vec::new()));
+        with_timeline(&mut test, at(1), 0);
+        assert_eq!(test.updated_timeline(at(2), 0), None);
+    }
+
+    #[test]
+    fn long_messages_are_truncated() {
+        let mut test = Test::new("my-test", TestSpec::default());
+        test.metadata.finalizers = Some(vec![FINALIZER_MAIN.to_string()]);
+        test.status = Some(TestStatus::default());
+        set_task_state(&mut test, TaskState::Error, Some(&"é".repeat(1000)));
+        with_timeline(&mut test, at(0), 10);
+        let message = &test.timeline()[0].message;
+        assert!(message.len() <= MAX_TIMELINE_MESSAGE_LEN);
+        assert!(message.starts_with("error: é"));
+        assert!(message.ends_with(TRUNCATED_MARKER));
+    }
+}
+
+#[cfg(test)]
+mod user_state_test {
+    use super::*;
+    use crate::Probe;
+
+    fn completed(outcome: Outcome) -> AgentStatus {
+        AgentStatus {
+            task_state: TaskState::Completed,
+            results: vec![TestResults {
+                outcome,
+                num_passed: 1,
+                ..TestResults::default()
+            }],
+            ..AgentStatus::default()
+        }
+    }
+
+    fn test_with_agents(agent: AgentStatus, agents: Vec<(&str, AgentStatus)>) -> Test {
+        let mut test = Test::new(
+            "my-test",
+            TestSpec {
+                agents: agents
+                    .iter()
+                    .map(|(name, _)| Agent {
+                        name: name.to_string(),
+                        ..Agent::default()
+                    })
+                    .collect(),
+                ..TestSpec::default()
+            },
+        );
+        test.status = Some(TestStatus {
+            agent,
+            agents: agents
+                .into_iter()
+                .map(|(name, status)| (name.to_string(), status))
+                .collect(),
+            ..TestStatus::default()
+        });
+        test
+    }
+
+    #[test]
+    fn all_agents_pass() {
+        let test = test_with_agents(
+            completed(Outcome::Pass),
+            vec![("candidate", completed(Outcome::Pass))],
+        );
+        assert_eq!(test.test_user_state(), TestUserState::Passed);
+    }
+
+    #[test]
+    fn one_agent_fails() {
+        let test = test_with_agents(
+            completed(Outcome::Pass),
+            vec![
+                ("candidate", completed(Outcome::Fail)),
+                ("other", completed(Outcome::Pass)),
+            ],
+        );
+        assert_eq!(test.test_user_state(), TestUserState::Failed);
+    }
+
+    #[test]
+    fn running_agent_is_not_done() {
+        let running = AgentStatus {
+            task_state: TaskState::Running,
+            ..AgentStatus::default()
+        };
+        let test = test_with_agents(completed(Outcome::Fail), vec![("candidate", running)]);
+        assert_eq!(test.test_user_state(), TestUserState::Running);
+    }
+
+    #[test]
+    fn running_agent_with_readiness_probe_waits_until_ready() {
+        let running = |ready| AgentStatus {
+            task_state: TaskState::Running,
+            ready,
+            ..AgentStatus::default()
+        };
+        // Without a readiness probe the agent is running as soon as it says so.
+        let test = test_with_agents(running(None), Vec::new());
+        assert_eq!(test.test_user_state(), TestUserState::Running);
+
+        let mut test = test_with_agents(running(None), Vec::new());
+        test.spec.agent.readiness_probe = Some(Probe {
+            tcp_socket_port: Some(8080),
+            ..Probe::default()
+        });
+        assert_eq!(test.test_user_state(), TestUserState::Waiting);
+        if let Some(status) = test.status.as_mut() {
+            status.agent.ready = Some(true);
+        }
+        assert_eq!(test.test_user_state(), TestUserState::Running);
+    }
+
+    #[test]
+    fn failed_assertion_overrides_agent_verdict() {
+        let assertion = ResultAssertion {
+            field: ResultField::NumPassed,
+            comparison: Comparison::Ge,
+            value: 100,
+        };
+        let agent = completed(Outcome::Pass);
+        assert!(!agent.results.iter().all(|results| assertion.holds(results)));
+        assert_eq!(assertion.to_string(), "numPassed ge 100");
+
+        let mut test = test_with_agents(agent, Vec::new());
+        test.spec.assertions = vec![assertion];
+        assert_eq!(test.test_user_state(), TestUserState::Passed);
+        if let Some(status) = test.status.as_mut() {
+            status.controller.failed_assertions = Some(vec![assertion.to_string()]);
+        }
+        assert_eq!(test.test_user_state(), TestUserState::Failed);
+    }
+
+    #[test]
+    fn suspended_test_is_not_terminal() {
+        let mut test = test_with_agents(AgentStatus::default(), Vec::new());
+        test.spec.suspend = true;
+        assert_eq!(test.test_user_state(), TestUserState::Suspended);
+        assert!(!TestUserState::Suspended.is_terminal());
+        test.spec.suspend = false;
+        assert_ne!(test.test_user_state(), TestUserState::Suspended);
+    }
+
+    #[test]
+    fn suspended_finished_test_keeps_its_outcome() {
+        let mut test = test_with_agents(completed(Outcome::Pass), Vec::new());
+        test.spec.suspend = true;
+        assert_eq!(test.test_user_state(), TestUserState::Passed);
+        let mut test = test_with_agents(completed(Outcome::Fail), Vec::new());
+        test.spec.suspend = true;
+        assert_eq!(test.test_user_state(), TestUserState::Failed);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::agent::TIMEOUT_PATTERN;
+use crate::clients::create_test_crd;
+use crate::error::{self, Result};
+use crate::{Agent, RestartPolicy, SecretName, SecretType, Test, TestSpec};
+use regex::Regex;
+use serde_json::{Map, Value};
+use snafu::{ensure, OptionExt};
+use std::collections::BTreeMap;
+
+/// The number of retries a test agent is allowed if none are specified.
+const DEFAULT_RETRIES: u32 = 5;
+
+/// Builds a TestSys [`Test`] for programmatic callers. Only the test's name and the agent image are
+/// required, the rest of the `Test` is filled in with the same defaults that are used for tests
+/// created from YAML.
+///
+/// ```
+/// use testsys_model::TestBuilder;
+///
+/// let test = TestBuilder::default()
+///     .name("sonobuoy-quick")
+///     .agent_image("example.com/sonobuoy-test-agent:v0.0.1")
+///     .configuration("mode", "quick")
+///     .env("REGION", "us-west-2")
+///     .resources("my-cluster")
+///     .timeout("2h")
+///     .build();
+/// assert!(test.is_ok());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct TestBuilder {
+    name: Option<String>,
+    labels: BTreeMap<String, String>,
+    agent_name: Option<String>,
+    agent_image: Option<String>,
+    image_pull_secret: Option<String>,
+    keep_running: Option<bool>,
+    timeout: Option<String>,
+    configuration: Map<String, Value>,
+    env: BTreeMap<String, String>,
+    secrets: BTreeMap<SecretType, SecretName>,
+    restart_policy: RestartPolicy,
+    resources: Vec<String>,
+    depends_on: Vec<String>,
+    retries: Option<u32>,
+    informational: bool,
+    requires: Vec<String>,
+    metadata: BTreeMap<String, String>,
+}
+
+impl TestBuilder {
+    /// The name of the `Test` object (required).
+    pub fn name<S>(&mut self, name: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Add a label to the `Test` object.
+    pub fn label<S1, S2>(&mut self, key: S1, value: S2) -> &mut Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// The name of the test agent. Defaults to `agent`.
+    pub fn agent_name<S>(&mut self, agent_name: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.agent_name = Some(agent_name.into());
+        self
+    }
+
+    /// The URI of the test agent container image (required).
+    pub fn agent_image<S>(&mut self, agent_image: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.agent_image = Some(agent_image.into());
+        self
+    }
+
+    /// The name of an image registry pull secret if one is needed to pull the agent image.
+    pub fn image_pull_secret<S>(&mut self, image_pull_secret: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.image_pull_secret = Some(image_pull_secret.into());
+        self
+    }
+
+    /// Whether the agent pod should keep running after the test has finished. Defaults to `true`.
+    pub fn keep_running(&mut self, keep_running: bool) -> &mut Self {
+        self.keep_running = Some(keep_running);
+        self
+    }
+
+    /// The maximum amount of time the agent is allowed to run, e.g. `1d2h30m` or a number of
+    /// seconds.
+    pub fn timeout<S>(&mut self, timeout: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.timeout = Some(timeout.into());
+        self
+    }
+
+    /// Add a value to the configuration that is passed to the test agent.
+    pub fn configuration<S, V>(&mut self, key: S, value: V) -> &mut Self
+    where
+        S: Into<String>,
+        V: Into<Value>,
+    {
+        self.configuration.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add an environment variable to the agent container. The value can refer to the test's
+    /// metadata, e.g. `{{ .labels.team }}`.
+    pub fn env<S1, S2>(&mut self, name: S1, value: S2) -> &mut Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        self.env.insert(name.into(), value.into());
+        self
+    }
+
+    /// Make the k8s secret `secret_name` available to the agent as `secret_type`.
+    pub fn secret<S>(&mut self, secret_type: S, secret_name: SecretName) -> &mut Self
+    where
+        S: Into<SecretType>,
+    {
+        self.secrets.insert(secret_type.into(), secret_name);
+        self
+    }
+
+    /// Whether a failed agent container is restarted in place. Defaults to `Never`.
+    pub fn restart_policy(&mut self, restart_policy: RestartPolicy) -> &mut Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Add a resource that must be ready before the test agent is started.
+    pub fn resources<S>(&mut self, resource: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.resources.push(resource.into());
+        self
+    }
+
+    /// Add a test that must pass before this test is started.
+    pub fn depends_on<S>(&mut self, test: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.depends_on.push(test.into());
+        self
+    }
+
+    /// The number of retries the agent is allowed after a failed test. Defaults to `5`.
+    pub fn retries(&mut self, retries: u32) -> &mut Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    /// Whether the test is informational, i.e. its failure does not fail the set of tests it is
+    /// run with.
+    pub fn informational(&mut self, informational: bool) -> &mut Self {
+        self.informational = informational;
+        self
+    }
+
+    /// Add a cluster capability that must be present before the test agent is started.
+    pub fn requires<S>(&mut self, capability: S) -> &mut Self
+    where
+        S: Into<String>,
+    {
+        self.requires.push(capability.into());
+        self
+    }
+
+    /// Add user metadata that is copied into the test's status.
+    pub fn metadata<S1, S2>(&mut self, key: S1, value: S2) -> &mut Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+    {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Validate the builder's values and create the `Test`.
+    pub fn build(&self) -> Result<Test> {
+        let name = self
+            .name
+            .as_ref()
+            .filter(|name| !name.is_empty())
+            .context(error::TestBuilderMissingFieldSnafu { field: "name" })?;
+        let image = self
+            .agent_image
+            .as_ref()
+            .filter(|image| !image.is_empty())
+            .context(error::TestBuilderMissingFieldSnafu {
+                field: "agent_image",
+            })?;
+        if let Some(timeout) = &self.timeout {
+            ensure!(
+                TIMEOUT_REGEX.is_match(timeout),
+                error::TimeoutValidationSnafu {
+                    timeout,
+                    regex: TIMEOUT_PATTERN
+                }
+            );
+        }
+
+        Ok(create_test_crd(
+            name,
+            Some(&self.labels),
+            TestSpec {
+                resources: self.resources.clone(),
+                depends_on: Some(self.depends_on.clone()),
+                agent: Agent {
+                    name: self
+                        .agent_name
+                        .clone()
+                        .unwrap_or_else(|| "agent".to_string()),
+                    image: image.to_owned(),
+                    pull_secret: self.image_pull_secret.clone(),
+                    keep_running: self.keep_running.unwrap_or(true),
+                    timeout: self.timeout.clone(),
+                    configuration: Some(self.configuration.clone()),
+                    secrets: Some(self.secrets.clone()),
+                    restart_policy: self.restart_policy,
+                    env: Some(self.env.clone()).filter(|env| !env.is_empty()),
+                    ..Agent::default()
+                },
+                agents: Vec::new(),
+                retries: Some(self.retries.unwrap_or(DEFAULT_RETRIES)),
+                informational: self.informational,
+                requires: self.requires.clone(),
+                metadata: self.metadata.clone(),
+                schedule: None,
+                assertions: Vec::new(),
+                wait_for_endpoints: Vec::new(),
+                suspend: false,
+                destroy_resources: false,
+            },
+        ))
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TIMEOUT_REGEX: Regex = {
+        #[allow(clippy::unwrap_used)]
+        Regex::new(TIMEOUT_PATTERN).unwrap()
+    };
+}
+
+#[cfg(test)]
+mod test_builder_test {
+    use super::*;
+    use crate::constants::NAMESPACE;
+
+    #[test]
+    fn build_test() {
+        let test = TestBuilder::default()
+            .name("my-test")
+            .label("suite", "conformance")
+            .agent_image("example.com/test-agent:v1")
+            .configuration("mode", "quick")
+            .resources("my-cluster")
+            .depends_on("other-test")
+            .env("REGION", "us-west-2")
+            .timeout("1h30m")
+            .build();
+        assert!(matches!(&test, Ok(test) if test.metadata.name.as_deref() == Some("my-test")));
+        if let Ok(test) = test {
+            assert_eq!(test.metadata.namespace.as_deref(), Some(NAMESPACE));
+            assert_eq!(
+                test.metadata
+                    .labels
+                    .as_ref()
+                    .and_then(|labels| labels.get("suite"))
+                    .map(String::as_str),
+                Some("conformance")
+            );
+            assert_eq!(test.spec.agent.image, "example.com/test-agent:v1");
+            assert_eq!(
+                test.spec
+                    .agent
+                    .configuration
+                    .as_ref()
+                    .and_then(|configuration| configuration.get("mode")),
+                Some(&Value::from("quick"))
+            );
+            assert_eq!(test.spec.resources, vec!["my-cluster".to_string()]);
+            assert_eq!(test.spec.depends_on, Some(vec!["other-test".to_string()]));
+            assert_eq!(
+                test.spec
+                    .agent
+                    .env
+                    .as_ref()
+                    .and_then(|env| env.get("REGION"))
+                    .map(String::as_str),
+                Some("us-west-2")
+            );
+            assert_eq!(test.spec.agent.timeout.as_deref(), Some("1h30m"));
+            assert_eq!(test.spec.retries, Some(DEFAULT_RETRIES));
+        }
+    }
+
+    #[test]
+    fn missing_image() {
+        let test = TestBuilder::default().name("my-test").build();
+        assert!(matches!(
+            test,
+            Err(e) if e.to_string() == "Unable to build test, 'agent_image' is required"
+        ));
+    }
+
+    #[test]
+    fn invalid_timeout() {
+        let test = TestBuilder::default()
+            .name("my-test")
+            .agent_image("example.com/test-agent:v1")
+            .timeout("soon")
+            .build();
+        assert!(test.is_err());
+    }
+}
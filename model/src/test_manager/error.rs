@@ -7,6 +7,9 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(super)))]
 pub enum Error {
+    #[snafu(display("Unable to generate a certificate: {}", source))]
+    Certificate { source: rcgen::RcgenError },
+
     #[snafu(display("Unable to {}: {}", action, source))]
     Client {
         action: String,
@@ -50,6 +53,12 @@ pub enum Error {
     #[snafu(display("Unable to find {}", what))]
     NotFound { what: String },
 
+    #[snafu(display(
+        "No artifacts were collected for test '{}', see its agent's 'artifacts_on'",
+        test_name
+    ))]
+    NoArtifacts { test_name: String },
+
     #[snafu(display("Some resources are still in the cluster"))]
     ResourceExisting,
 
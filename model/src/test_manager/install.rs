@@ -3,18 +3,30 @@ use crate::clients::AllowNotFound;
 use crate::constants::NAMESPACE;
 use crate::system::{
     agent_cluster_role, agent_cluster_role_binding, agent_service_account, controller_cluster_role,
-    controller_cluster_role_binding, controller_deployment, controller_service_account,
-    testsys_namespace, AgentType,
+    controller_cluster_role_binding, controller_deployment, controller_service,
+    controller_service_account, controller_webhook_configuration, controller_webhook_dns_names,
+    controller_webhook_tls_secret, testsys_namespace, AgentType, ControllerOptions,
+    TESTSYS_CONTROLLER_WEBHOOK_TLS_SECRET,
 };
 use crate::test_manager::TestManager;
 use crate::{Resource, Test};
-use k8s_openapi::api::core::v1::Namespace;
+use k8s_openapi::api::admissionregistration::v1::MutatingWebhookConfiguration;
+use k8s_openapi::api::core::v1::{Namespace, Secret};
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
 use kube::{Api, CustomResourceExt, ResourceExt};
 use log::info;
 use snafu::ResultExt;
 use std::time::Duration;
 
+/// The YAML manifest of the TestSys CRDs, `Test` followed by `Resource`, generated from the model
+/// so that it can be applied by tools that do not use `install`, e.g. GitOps repositories.
+pub fn crd_manifest() -> std::result::Result<String, serde_yaml::Error> {
+    [Test::crd(), Resource::crd()]
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect()
+}
+
 impl TestManager {
     /// Create the testsys namespace
     pub(super) async fn create_namespace(&self) -> Result<()> {
@@ -115,13 +127,70 @@ impl TestManager {
         Ok(())
     }
 
+    /// Create the service that test agents reach the controller through, if the controller serves
+    /// an endpoint for them.
+    pub(super) async fn create_controller_service(
+        &self,
+        options: &ControllerOptions,
+    ) -> Result<()> {
+        match controller_service(options) {
+            Some(service) => {
+                self.create_or_update(self.namespaced_api(), &service, "Controller Service")
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Register the controller's mutating admission webhook with the k8s API server, if the
+    /// controller serves it. The certificate that the webhook is served with is created the first
+    /// time and kept by later installs, so that the registration keeps trusting it.
+    pub(super) async fn create_controller_webhook(
+        &self,
+        options: &ControllerOptions,
+    ) -> Result<()> {
+        if options.webhook_address.is_none() {
+            return Ok(());
+        }
+        let secret_api = self.namespaced_api::<Secret>();
+        let existing = secret_api
+            .get_opt(TESTSYS_CONTROLLER_WEBHOOK_TLS_SECRET)
+            .await
+            .context(error::KubeSnafu {
+                action: "get the webhook certificate",
+            })?;
+        let certificate = match existing
+            .and_then(|secret| secret.data)
+            .and_then(|mut data| data.remove("tls.crt"))
+        {
+            Some(certificate) => certificate.0,
+            None => {
+                let (certificate, key) = webhook_certificate().context(error::CertificateSnafu)?;
+                self.create_or_update(
+                    secret_api,
+                    &controller_webhook_tls_secret(&certificate, &key),
+                    "Controller Webhook Certificate",
+                )
+                .await?;
+                certificate.into_bytes()
+            }
+        };
+        self.create_or_update(
+            self.api(),
+            &controller_webhook_configuration(certificate),
+            "Controller Webhook",
+        )
+        .await
+    }
+
     pub(super) async fn create_deployment(
         &self,
         uri: String,
         secret: Option<String>,
         enable_logging: bool,
+        options: ControllerOptions,
     ) -> Result<()> {
-        let controller_deployment = controller_deployment(uri, secret, enable_logging);
+        let controller_deployment = controller_deployment(uri, secret, enable_logging, options);
 
         // If the controller deployment already exists, update it with the new one using Patch. If
         // not create a new controller deployment.
@@ -137,6 +206,18 @@ impl TestManager {
             .context(error::KubeSnafu {
                 action: "delete TestSys namespace",
             })?;
+        // The webhook registration is not namespaced so it is not deleted with the namespace.
+        let webhook_api: Api<MutatingWebhookConfiguration> = self.api();
+        webhook_api
+            .delete(
+                &controller_webhook_configuration(Vec::new()).name_any(),
+                &Default::default(),
+            )
+            .await
+            .allow_not_found(|_| ())
+            .context(error::KubeSnafu {
+                action: "delete TestSys webhook",
+            })?;
         let crd_api: Api<CustomResourceDefinition> = self.api();
         crd_api
             .delete(&Test::crd().name_any(), &Default::default())
@@ -170,3 +251,36 @@ impl TestManager {
         Ok(())
     }
 }
+
+/// A self-signed certificate for the controller's webhook service, and its key, PEM encoded. The
+/// certificate is its own CA bundle.
+fn webhook_certificate() -> std::result::Result<(String, String), rcgen::RcgenError> {
+    let certificate = rcgen::generate_simple_self_signed(controller_webhook_dns_names())?;
+    Ok((
+        certificate.serialize_pem()?,
+        certificate.serialize_private_key_pem(),
+    ))
+}
+
+#[cfg(test)]
+mod crd_manifest_test {
+    use super::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn manifest_contains_crds() {
+        let manifest = crd_manifest().unwrap_or_default();
+        let crds: Vec<CustomResourceDefinition> = serde_yaml::Deserializer::from_str(&manifest)
+            .filter_map(|document| CustomResourceDefinition::deserialize(document).ok())
+            .collect();
+        let names: Vec<(&str, &str)> = crds
+            .iter()
+            .map(|crd| (crd.spec.group.as_str(), crd.spec.names.kind.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            [("testsys.system", "Test"), ("testsys.system", "Resource")]
+        );
+        assert_eq!(crds.first(), Some(&Test::crd()));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{Test, TestUserState};
+use kube::ResourceExt;
+use std::fmt::Write;
+
+/// Render a JUnit XML report for `tests`. The report has a single `testsuite` named `suite_name`
+/// with one `testcase` for each test. Tests that failed are reported as failures, tests that could
+/// not run or ended in an error are reported as errors, and tests that have not finished or did not
+/// run any tests are reported as skipped.
+pub fn junit_report(suite_name: &str, tests: &[Test]) -> String {
+    let testcases: Vec<(String, TestCaseResult)> = tests
+        .iter()
+        .map(|test| (test.name_any(), TestCaseResult::new(test)))
+        .collect();
+    let count =
+        |f: fn(&TestCaseResult) -> bool| testcases.iter().filter(|(_, result)| f(result)).count();
+    let counts = format!(
+        r#"tests="{}" failures="{}" errors="{}" skipped="{}""#,
+        testcases.len(),
+        count(|result| matches!(result, TestCaseResult::Failure { .. })),
+        count(|result| matches!(result, TestCaseResult::Error(_))),
+        count(|result| matches!(result, TestCaseResult::Skipped(_))),
+    );
+
+    let suite_name = escape(suite_name);
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    xml.push('\n');
+    let _ = writeln!(xml, r#"<testsuites name="{}" {}>"#, suite_name, counts);
+    let _ = writeln!(xml, r#"  <testsuite name="{}" {}>"#, suite_name, counts);
+    for (name, result) in &testcases {
+        let _ = write!(
+            xml,
+            r#"    <testcase name="{}" classname="{}""#,
+            escape(name),
+            suite_name
+        );
+        match result {
+            TestCaseResult::Passed => xml.push_str("/>\n"),
+            TestCaseResult::Failure { message, details } => {
+                let _ = writeln!(
+                    xml,
+                    r#"><failure message="{}">{}</failure></testcase>"#,
+                    escape(message),
+                    escape(details)
+                );
+            }
+            TestCaseResult::Error(message) => {
+                let _ = writeln!(xml, r#"><error message="{}"/></testcase>"#, escape(message));
+            }
+            TestCaseResult::Skipped(message) => {
+                let _ = writeln!(
+                    xml,
+                    r#"><skipped message="{}"/></testcase>"#,
+                    escape(message)
+                );
+            }
+        }
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// How a single test is reported in a JUnit report.
+enum TestCaseResult {
+    Passed,
+    Failure { message: String, details: String },
+    Error(String),
+    Skipped(String),
+}
+
+impl TestCaseResult {
+    fn new(test: &Test) -> Self {
+        let state = test.test_user_state();
+        match state {
+            TestUserState::Passed => Self::Passed,
+            TestUserState::Failed => {
+                let agent_status = test.agent_status();
+                let results = agent_status.results.last();
+                Self::Failure {
+                    message: results
+                        .map(|results| {
+                            format!("{} of {} tests failed", results.num_failed, results.total())
+                        })
+                        .unwrap_or_else(|| "The test failed".to_string()),
+                    details: results
+                        .and_then(|results| results.other_info.clone())
+                        .unwrap_or_default(),
+                }
+            }
+            TestUserState::Error => Self::Error(
+                test.agent_error()
+                    .unwrap_or("The test agent reported an error")
+                    .to_string(),
+            ),
+            TestUserState::ResourceError => Self::Error(
+                test.resource_error()
+                    .cloned()
+                    .unwrap_or_else(|| "Resource creation failed".to_string()),
+            ),
+            TestUserState::PreflightFailed => Self::Error(
+                test.preflight_error()
+                    .cloned()
+                    .unwrap_or_else(|| "The pre-flight check failed".to_string()),
+            ),
+            TestUserState::InvalidSpec => Self::Error(
+                test.invalid_spec()
+                    .cloned()
+                    .unwrap_or_else(|| "The test's spec is invalid".to_string()),
+            ),
+            TestUserState::NoTests => Self::Skipped("The test agent reported no tests".to_string()),
+            TestUserState::Quarantined => Self::Skipped("The test is quarantined".to_string()),
+            TestUserState::Archived => Self::Skipped("The test is archived".to_string()),
+            TestUserState::Unknown
+            | TestUserState::Waiting
+            | TestUserState::Running
+            | TestUserState::Suspended
+            | TestUserState::Deleting => Self::Skipped(format!(
+                "The test did not finish, its state is '{:?}'",
+                state
+            )),
+        }
+    }
+}
+
+/// Escape `value` for use in XML text and attribute values.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not allowed in XML 1.0.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod junit_test {
+    use super::junit_report;
+    use crate::{AgentStatus, Outcome, TaskState, Test, TestResults, TestSpec, TestStatus};
+
+    fn test_with_status(name: &str, agent: AgentStatus) -> Test {
+        let mut test = Test::new(name, TestSpec::default());
+        test.status = Some(TestStatus {
+            agent,
+            ..TestStatus::default()
+        });
+        test
+    }
+
+    fn completed(name: &str, outcome: Outcome, num_passed: u64, num_failed: u64) -> Test {
+        test_with_status(
+            name,
+            AgentStatus {
+                task_state: TaskState::Completed,
+                results: vec![TestResults {
+                    outcome,
+                    num_passed,
+                    num_failed,
+                    num_skipped: 0,
+                    other_info: Some("see <results> & logs".to_string()),
+                }],
+                ..AgentStatus::default()
+            },
+        )
+    }
+
+    #[test]
+    fn mixed_results() {
+        let tests = vec![
+            completed("passed", Outcome::Pass, 10, 0),
+            completed("failed", Outcome::Fail, 8, 2),
+            test_with_status(
+                "error",
+                AgentStatus {
+                    task_state: TaskState::Error,
+                    error: Some("agent \"crashed\"".to_string()),
+                    ..AgentStatus::default()
+                },
+            ),
+            test_with_status(
+                "running",
+                AgentStatus {
+                    task_state: TaskState::Running,
+                    ..AgentStatus::default()
+                },
+            ),
+        ];
+        let xml = junit_report("suite=nightly&aws", &tests);
+
+        // The report is well formed XML.
+        let mut open_elements = Vec::new();
+        let mut testcases = 0;
+        let mut failures = 0;
+        let tokens: Result<Vec<_>, _> = xmlparser::Tokenizer::from(xml.as_str()).collect();
+        assert!(tokens.is_ok(), "Invalid XML: {:?}", tokens);
+        for token in tokens.unwrap_or_default() {
+            match token {
+                xmlparser::Token::ElementStart { local, .. } => {
+                    match local.as_str() {
+                        "testcase" => testcases += 1,
+                        "failure" => failures += 1,
+                        _ => {}
+                    }
+                    open_elements.push(local.to_string());
+                }
+                xmlparser::Token::ElementEnd { end, .. } => match end {
+                    xmlparser::ElementEnd::Open => {}
+                    xmlparser::ElementEnd::Close(_, local) => {
+                        assert_eq!(open_elements.pop().as_deref(), Some(local.as_str()))
+                    }
+                    xmlparser::ElementEnd::Empty => {
+                        open_elements.pop();
+                    }
+                },
+                _ => {}
+            }
+        }
+        assert!(open_elements.is_empty());
+        assert_eq!(testcases, 4);
+        assert_eq!(failures, 1);
+
+        assert!(xml.contains(
+            r#"<testsuites name="suite=nightly&amp;aws" tests="4" failures="1" errors="1" skipped="1">"#
+        ));
+        assert!(xml.contains(r#"<testcase name="passed" classname="suite=nightly&amp;aws"/>"#));
+        assert!(xml.contains(
+            r#"<failure message="2 of 10 tests failed">see &lt;results&gt; &amp; logs</failure>"#
+        ));
+        assert!(xml.contains(r#"<error message="agent &quot;crashed&quot;"/>"#));
+    }
+}
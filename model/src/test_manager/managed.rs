@@ -0,0 +1,242 @@
+use super::status::{crd_state, crd_type};
+use super::ResourceState;
+use crate::Crd;
+use k8s_openapi::api::batch::v1::Job;
+use kube::ResourceExt;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use tabled::builder::Builder;
+use tabled::{Style, Width};
+
+/// `ManagedSnapshot` lists everything the controller manages: each `Test` and `Resource` with the
+/// `Job`s that run its agents and the finalizers that keep it from being deleted, and the
+/// controller's `Job`s that belong to no object, which have leaked.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedSnapshot {
+    objects: Vec<ManagedObject>,
+    orphaned_jobs: Vec<ManagedJob>,
+}
+
+/// A `Test` or `Resource` and what the controller manages for it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedObject {
+    pub name: String,
+    pub crd_type: String,
+    pub state: String,
+    pub jobs: Vec<ManagedJob>,
+    pub finalizers: Vec<String>,
+}
+
+/// A `Job` the controller created and the state of its pods.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedJob {
+    pub name: String,
+    pub state: String,
+}
+
+impl ManagedSnapshot {
+    /// Correlate the controller's `jobs` with the `crds` whose agents they run.
+    pub(super) fn new(crds: Vec<Crd>, jobs: Vec<Job>) -> Self {
+        let mut jobs: BTreeMap<String, Job> =
+            jobs.into_iter().map(|job| (job.name_any(), job)).collect();
+        let objects = crds
+            .iter()
+            .map(|crd| ManagedObject {
+                name: crd.name().unwrap_or_default(),
+                crd_type: crd_type(crd).concat(),
+                state: crd_state(crd).concat(),
+                jobs: job_names(crd)
+                    .into_iter()
+                    .filter_map(|job_name| jobs.remove(&job_name))
+                    .map(|job| ManagedJob::from(&job))
+                    .collect(),
+                finalizers: match crd {
+                    Crd::Test(test) => test.finalizers().to_vec(),
+                    Crd::Resource(resource) => resource.finalizers().to_vec(),
+                },
+            })
+            .collect();
+        Self {
+            objects,
+            orphaned_jobs: jobs.values().map(ManagedJob::from).collect(),
+        }
+    }
+
+    pub fn objects(&self) -> &[ManagedObject] {
+        &self.objects
+    }
+
+    pub fn orphaned_jobs(&self) -> &[ManagedJob] {
+        &self.orphaned_jobs
+    }
+}
+
+/// The names of the `Job`s that run the agents of the `crd`.
+fn job_names(crd: &Crd) -> Vec<String> {
+    match crd {
+        Crd::Test(test) => std::iter::once(test.job_name())
+            .chain(
+                test.spec
+                    .agents
+                    .iter()
+                    .map(|agent| test.agent_job_name(&agent.name)),
+            )
+            .collect(),
+        Crd::Resource(resource) => vec![
+            resource.job_name(ResourceState::Creation),
+            resource.job_name(ResourceState::Destruction),
+        ],
+    }
+}
+
+impl From<&Job> for ManagedJob {
+    fn from(job: &Job) -> Self {
+        let status = job.status.clone().unwrap_or_default();
+        let state = if status.active.unwrap_or_default() > 0 {
+            "running"
+        } else if status.failed.unwrap_or_default() > 0 {
+            "failed"
+        } else if status.succeeded.unwrap_or_default() > 0 {
+            "succeeded"
+        } else {
+            "pending"
+        };
+        Self {
+            name: job.name_any(),
+            state: state.to_string(),
+        }
+    }
+}
+
+impl Display for ManagedSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let job_lines = |jobs: &[ManagedJob]| {
+            jobs.iter()
+                .map(|job| format!("{} ({})", job.name, job.state))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut builder = Builder::default();
+        builder.set_columns(["NAME", "TYPE", "STATE", "JOBS", "FINALIZERS"]);
+        for object in &self.objects {
+            builder.add_record([
+                object.name.clone(),
+                object.crd_type.clone(),
+                object.state.clone(),
+                job_lines(&object.jobs),
+                object.finalizers.join("\n"),
+            ]);
+        }
+        for job in &self.orphaned_jobs {
+            builder.add_record([
+                job.name.clone(),
+                "Job".to_string(),
+                "orphaned".to_string(),
+                job_lines(std::slice::from_ref(job)),
+                String::new(),
+            ]);
+        }
+        let mut table = builder.build();
+        table.with(Style::blank());
+        match f.width() {
+            Some(width) => write!(f, "{}", table.with(Width::truncate(width))),
+            None => write!(f, "{}", table),
+        }
+    }
+}
+
+#[cfg(test)]
+mod managed_test {
+    use super::*;
+    use crate::{Agent, Resource, ResourceSpec, Test, TestSpec};
+
+    #[test]
+    fn test_is_correlated_with_its_jobs_and_finalizers() -> serde_json::Result<()> {
+        let mut test = Test::new(
+            "my-test",
+            TestSpec {
+                agents: vec![Agent {
+                    name: "load".to_string(),
+                    ..Agent::default()
+                }],
+                ..TestSpec::default()
+            },
+        );
+        test.metadata.uid = Some("4d2f7ad4-8b1e-4bd5-a2f1-1d2c7a3b9e10".to_string());
+        test.metadata.finalizers = Some(vec![
+            "testsys.system/test-main".to_string(),
+            "testsys.system/test-job".to_string(),
+        ]);
+        let mut resource = Resource::new("my-cluster", ResourceSpec::default());
+        resource.metadata.uid = Some("0b6f1e3c-4f5a-4c0e-9a8d-7e2b1c3d4f5a".to_string());
+        resource.metadata.finalizers = Some(vec!["testsys.system/resource-main".to_string()]);
+        let job = |name: String, status: serde_json::Value| -> serde_json::Result<Job> {
+            serde_json::from_value(serde_json::json!({
+                "apiVersion": "batch/v1",
+                "kind": "Job",
+                "metadata": { "name": name },
+                "status": status
+            }))
+        };
+        let jobs = vec![
+            job(test.job_name(), serde_json::json!({ "active": 1 }))?,
+            job(
+                test.agent_job_name("load"),
+                serde_json::json!({ "failed": 1 }),
+            )?,
+            job(
+                resource.job_name(ResourceState::Creation),
+                serde_json::json!({ "succeeded": 1 }),
+            )?,
+            job("leaked-job".to_string(), serde_json::json!({}))?,
+        ];
+
+        let snapshot = ManagedSnapshot::new(
+            vec![Crd::Test(test.clone()), Crd::Resource(resource.clone())],
+            jobs,
+        );
+
+        let objects = snapshot.objects();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].name, "my-test");
+        assert_eq!(objects[0].crd_type, "Test");
+        assert_eq!(
+            objects[0].jobs,
+            vec![
+                ManagedJob {
+                    name: test.job_name(),
+                    state: "running".to_string()
+                },
+                ManagedJob {
+                    name: test.agent_job_name("load"),
+                    state: "failed".to_string()
+                }
+            ]
+        );
+        assert_eq!(
+            objects[0].finalizers,
+            vec!["testsys.system/test-main", "testsys.system/test-job"]
+        );
+        assert_eq!(objects[1].name, "my-cluster");
+        assert_eq!(
+            objects[1].jobs,
+            vec![ManagedJob {
+                name: resource.job_name(ResourceState::Creation),
+                state: "succeeded".to_string()
+            }]
+        );
+        assert_eq!(objects[1].finalizers, vec!["testsys.system/resource-main"]);
+        assert_eq!(
+            snapshot.orphaned_jobs(),
+            &[ManagedJob {
+                name: "leaked-job".to_string(),
+                state: "pending".to_string()
+            }]
+        );
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
 use super::{
-    error, CrdState, CrdType, DeleteEvent, DockerConfigJson, ImageConfig, ResourceState, Result,
-    SelectionParams, StatusSnapshot,
+    error, junit_report, CrdState, CrdType, DeleteEvent, DockerConfigJson, ImageConfig,
+    ManagedSnapshot, ResourceState, Result, SelectionParams, StatusSnapshot,
 };
 use crate::clients::{AllowNotFound, CrdClient, ResourceClient, TestClient};
-use crate::constants::TESTSYS_RESULTS_FILE;
-use crate::system::AgentType;
+use crate::constants::{
+    ANNOTATION_KEEP_RESOURCES, ANNOTATION_RERUN, APP_MANAGED_BY, CONTROLLER, NAMESPACE,
+    TESTSYS_RESULTS_FILE,
+};
+use crate::system::{AgentType, ControllerOptions};
 use crate::{Crd, CrdName, Resource, SecretName, TaskState, Test, TestUserState};
 use bytes::Bytes;
 use futures::{Stream, StreamExt};
+use k8s_openapi::api::batch::v1::Job;
 use k8s_openapi::api::core::v1::{Pod, Secret};
 use kube::api::{ListParams, LogParams};
 use kube::config::{KubeConfigOptions, Kubeconfig};
@@ -130,8 +134,14 @@ impl TestManager {
         Ok(secret)
     }
 
-    /// Install testsys to a cluster.
-    pub async fn install(&self, controller_config: ImageConfig, store_logs: bool) -> Result<()> {
+    /// Install testsys to a cluster. The optional behavior of the controller, e.g. forwarding the
+    /// logs of running test agents or serving its HTTP API, is configured with `options`.
+    pub async fn install(
+        &self,
+        controller_config: ImageConfig,
+        store_logs: bool,
+        options: ControllerOptions,
+    ) -> Result<()> {
         self.create_namespace().await?;
         self.create_crd().await?;
         self.create_roles(AgentType::Test).await?;
@@ -145,7 +155,10 @@ impl TestManager {
             ImageConfig::WithCreds { secret, image } => (image, Some(secret)),
             ImageConfig::Image(image) => (image, None),
         };
-        self.create_deployment(image, secret, store_logs).await?;
+        self.create_controller_service(&options).await?;
+        self.create_controller_webhook(&options).await?;
+        self.create_deployment(image, secret, store_logs, options)
+            .await?;
 
         Ok(())
     }
@@ -170,16 +183,27 @@ impl TestManager {
     }
 
     /// Restart a crd object by deleting the crd from the cluster and adding a copy of it with its
-    /// status cleared.
+    /// status cleared and its rerun counter incremented. The test's resources are kept for the
+    /// copy even if the test destroys them when it is deleted.
     pub async fn restart_test(&self, name: &str) -> Result<()> {
         let test_client = TestClient::new_from_k8s_client(self.k8s_client.clone());
         let mut test = test_client
             .get(name)
             .await
             .context(error::ClientSnafu { action: "get test" })?;
+        let rerun = test.rerun() + 1;
         // Created objects are not allowed to have `resource_version` set.
         test.metadata.resource_version = None;
         test.status = None;
+        test.annotations_mut()
+            .insert(ANNOTATION_RERUN.to_string(), rerun.to_string());
+        test.annotations_mut().remove(ANNOTATION_KEEP_RESOURCES);
+        test_client
+            .request_keep_resources(name)
+            .await
+            .context(error::ClientSnafu {
+                action: "keep resources of test",
+            })?;
         test_client.delete(name).await.context(error::ClientSnafu {
             action: "delete test",
         })?;
@@ -190,6 +214,18 @@ impl TestManager {
         Ok(())
     }
 
+    /// Archive a test. The controller tears down its job and the resources no other test uses, but
+    /// keeps the test itself for its history.
+    pub async fn archive_test(&self, name: &str) -> Result<()> {
+        self.test_client()
+            .request_archive(name)
+            .await
+            .context(error::ClientSnafu {
+                action: "archive test",
+            })?;
+        Ok(())
+    }
+
     /// Add a testsys crd (`Test`, `Resource`) to the cluster.
     pub async fn create_object(&self, crd: Crd) -> Result<Crd> {
         match &crd {
@@ -219,6 +255,7 @@ impl TestManager {
                         action: "list tests from label params",
                     })?
                     .into_iter()
+                    .filter(|test| selection_params.include_archived || !test.is_archived())
                     .filter(|test| filter_test_by_state(test, &selection_params.state))
                     .map(Crd::Test),
             );
@@ -302,6 +339,49 @@ impl TestManager {
         Ok(StatusSnapshot::new(crds))
     }
 
+    /// Collect every `Test` and `Resource`, including archived `Test`s, with the `Job`s the
+    /// controller runs their agents in and their outstanding finalizers, as well as the
+    /// controller's `Job`s that no object owns anymore.
+    pub async fn managed(&self) -> Result<ManagedSnapshot> {
+        let crds = self
+            .list(&SelectionParams {
+                include_archived: true,
+                ..SelectionParams::default()
+            })
+            .await?;
+        let jobs = Api::<Job>::namespaced(self.k8s_client.clone(), NAMESPACE)
+            .list(&ListParams::default().labels(&format!("{}={}", APP_MANAGED_BY, CONTROLLER)))
+            .await
+            .context(error::KubeSnafu {
+                action: "list controller jobs",
+            })?
+            .items;
+
+        Ok(ManagedSnapshot::new(crds, jobs))
+    }
+
+    /// Render a JUnit XML report of the `Test`s meeting `selection_params`. `suite_name` names the
+    /// report's test suite.
+    pub async fn junit_report(
+        &self,
+        suite_name: &str,
+        selection_params: &SelectionParams,
+    ) -> Result<String> {
+        let tests: Vec<Test> = self
+            .list(&SelectionParams {
+                crd_type: Some(CrdType::Test),
+                ..selection_params.clone()
+            })
+            .await?
+            .into_iter()
+            .filter_map(|crd| match crd {
+                Crd::Test(test) => Some(test),
+                Crd::Resource(_) => None,
+            })
+            .collect();
+        Ok(junit_report(suite_name, &tests))
+    }
+
     /// Retrieve the logs of a test.
     pub async fn test_logs<S>(
         &self,
@@ -411,9 +491,25 @@ impl TestManager {
                 what: "results stdout",
             })?);
 
+        // The agent does not write a results file when its `artifacts_on` skipped the tarball, so
+        // `cat` has no output.
+        let first = match cat_out.next().await {
+            Some(data) => data.context(error::IoSnafu {
+                action: "get results line",
+            })?,
+            None => {
+                return error::NoArtifactsSnafu {
+                    test_name: test_name.to_string(),
+                }
+                .fail()
+            }
+        };
         let mut out_file = tokio::fs::File::create(destination)
             .await
             .context(error::FileSnafu { path: destination })?;
+        out_file.write_all(&first).await.context(error::IoSnafu {
+            action: "write results",
+        })?;
         while let Some(data) = cat_out.next().await {
             out_file
                 .write(&data.context(error::IoSnafu {
@@ -468,6 +564,10 @@ fn filter_test_by_state(test: &Test, state: &Option<CrdState>) -> bool {
                     | TestUserState::Failed
                     | TestUserState::Error
                     | TestUserState::ResourceError
+                    | TestUserState::PreflightFailed
+                    | TestUserState::InvalidSpec
+                    | TestUserState::Quarantined
+                    | TestUserState::Archived
             ),
             CrdState::Passed => {
                 matches!(test.test_user_state(), TestUserState::Passed)
@@ -475,12 +575,19 @@ fn filter_test_by_state(test: &Test, state: &Option<CrdState>) -> bool {
             CrdState::Failed => {
                 matches!(
                     test.test_user_state(),
-                    TestUserState::Failed | TestUserState::Error | TestUserState::ResourceError
+                    TestUserState::Failed
+                        | TestUserState::Error
+                        | TestUserState::ResourceError
+                        | TestUserState::PreflightFailed
+                        | TestUserState::InvalidSpec
                 )
             }
             CrdState::NotFinished => matches!(
                 test.test_user_state(),
-                TestUserState::Running | TestUserState::Waiting | TestUserState::Unknown
+                TestUserState::Running
+                    | TestUserState::Waiting
+                    | TestUserState::Suspended
+                    | TestUserState::Unknown
             ),
         }
     } else {
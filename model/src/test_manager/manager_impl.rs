@@ -117,9 +117,15 @@ impl TestManager {
         let pod_api: Api<Pod> = self.namespaced_api();
         Ok(match crd {
             CrdName::Test(test) => {
+                let job_name = self
+                    .test_client()
+                    .get(test)
+                    .await
+                    .context(error::ClientSnafu { action: "get test" })?
+                    .job_name();
                 pod_api
                     .list(&ListParams {
-                        label_selector: Some(format!("job-name={}", test)),
+                        label_selector: Some(format!("job-name={}", job_name)),
                         ..Default::default()
                     })
                     .await
@@ -179,10 +185,16 @@ impl TestManager {
     where
         S: Into<String>,
     {
+        let job_name = self
+            .test_client()
+            .get(test.into())
+            .await
+            .context(error::ClientSnafu { action: "get test" })?
+            .job_name();
         let pod_api: Api<Pod> = self.namespaced_api();
         pod_api
             .list(&ListParams {
-                label_selector: Some(format!("job-name={}", test.into())),
+                label_selector: Some(format!("job-name={}", job_name)),
                 ..Default::default()
             })
             .await
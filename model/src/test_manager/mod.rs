@@ -1,5 +1,8 @@
 pub use delete::DeleteEvent;
 pub use error::{Error, Result};
+pub use install::crd_manifest;
+pub use junit::junit_report;
+pub use managed::{ManagedJob, ManagedObject, ManagedSnapshot};
 pub use manager::{read_manifest, TestManager};
 use serde::{Deserialize, Serialize};
 use serde_plain::derive_fromstr_from_deserialize;
@@ -9,9 +12,12 @@ use std::collections::HashMap;
 mod delete;
 mod error;
 mod install;
+mod junit;
+mod managed;
 mod manager;
 mod manager_impl;
 mod status;
+mod validate;
 
 #[derive(Default, Debug, Clone)]
 /// `SelectionParams` are used to select a group (or single) object from a testsys cluster. For any
@@ -25,6 +31,8 @@ pub struct SelectionParams {
     pub name: Option<String>,
     /// Filter based on the state of the CRD
     pub state: Option<CrdState>,
+    /// Include archived `Test`s, which are left out by default.
+    pub include_archived: bool,
 }
 
 #[derive(Debug, Clone)]
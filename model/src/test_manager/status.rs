@@ -67,6 +67,8 @@ pub struct StatusSnapshot {
     finished: bool,
     passed: bool,
     failed_tests: Vec<String>,
+    /// Failed tests that are marked `informational` and do not affect `passed`.
+    informational_failed_tests: Vec<String>,
     crds: Vec<Crd>,
     #[serde(skip)]
     columns: Vec<StatusColumn>,
@@ -77,19 +79,28 @@ impl StatusSnapshot {
         let mut passed = true;
         let mut finished = true;
         let mut failed_tests = Vec::new();
+        let mut informational_failed_tests = Vec::new();
         for crd in &crds {
             match crd {
-                Crd::Test(test) => match test.agent_status().task_state {
-                    TaskState::Unknown | TaskState::Running => {
-                        passed = false;
+                // Informational tests are reported, but they do not gate `passed`.
+                Crd::Test(test) if test.spec.informational => {
+                    let state = test.test_user_state();
+                    if !state.is_terminal() {
                         finished = false
+                    } else if state.is_failure() {
+                        informational_failed_tests.push(test.name_any())
                     }
-                    TaskState::Error => {
+                }
+                Crd::Test(test) => {
+                    let state = test.test_user_state();
+                    if !state.is_terminal() {
+                        passed = false;
+                        finished = false
+                    } else if state.is_failure() {
                         passed = false;
                         failed_tests.push(test.name_any());
                     }
-                    _ => continue,
-                },
+                }
                 Crd::Resource(resource) => {
                     match resource.creation_task_state() {
                         TaskState::Unknown | TaskState::Running => {
@@ -113,6 +124,7 @@ impl StatusSnapshot {
             passed,
             finished,
             failed_tests,
+            informational_failed_tests,
             crds,
             columns: Default::default(),
         }
@@ -309,7 +321,7 @@ fn crd_time(crd: &Crd) -> Vec<String> {
 }
 
 /// Determine the type of the CRD
-fn crd_type(crd: &Crd) -> Vec<String> {
+pub(super) fn crd_type(crd: &Crd) -> Vec<String> {
     match crd {
         Crd::Test(_) => vec!["Test".to_string()],
         Crd::Resource(_) => vec!["Resource".to_string()],
@@ -317,7 +329,7 @@ fn crd_type(crd: &Crd) -> Vec<String> {
 }
 
 /// Determine the state of the CRD
-fn crd_state(crd: &Crd) -> Vec<String> {
+pub(super) fn crd_state(crd: &Crd) -> Vec<String> {
     match crd {
         Crd::Test(test) => vec![test.test_user_state().to_string()],
         Crd::Resource(resource) => {
@@ -369,12 +381,95 @@ fn crd_results(crd: &Crd, res_type: ResultType) -> Vec<String> {
 fn crd_progress(crd: &Crd) -> Vec<String> {
     match crd {
         Crd::Resource(_) => Default::default(),
-        Crd::Test(test) => test
-            .agent_status()
-            .current_test
-            .as_ref()
-            .and_then(|res| res.other_info.to_owned())
-            .into_iter()
-            .collect(),
+        Crd::Test(test) => {
+            let agent_status = test.agent_status();
+            agent_status
+                .progress
+                .map(|progress| progress.to_string())
+                .into_iter()
+                .chain(
+                    agent_status
+                        .current_test
+                        .as_ref()
+                        .and_then(|res| res.other_info.to_owned()),
+                )
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StatusSnapshot;
+    use crate::{AgentStatus, Crd, Outcome, TaskState, Test, TestResults, TestSpec, TestStatus};
+
+    fn test_crd(name: &str, informational: bool, task_state: TaskState) -> Crd {
+        let mut test = Test::new(
+            name,
+            TestSpec {
+                informational,
+                ..TestSpec::default()
+            },
+        );
+        test.status = Some(TestStatus {
+            agent: AgentStatus {
+                task_state,
+                ..AgentStatus::default()
+            },
+            ..TestStatus::default()
+        });
+        Crd::Test(test)
+    }
+
+    #[test]
+    fn informational_failure_does_not_fail() {
+        let snapshot = StatusSnapshot::new(vec![
+            test_crd("gating", false, TaskState::Completed),
+            test_crd("flaky", true, TaskState::Error),
+        ]);
+        assert!(snapshot.finished);
+        assert!(snapshot.passed);
+        assert!(snapshot.failed_tests.is_empty());
+        assert_eq!(
+            snapshot.informational_failed_tests,
+            vec!["flaky".to_string()]
+        );
+    }
+
+    #[test]
+    fn gating_failure_fails() {
+        let snapshot = StatusSnapshot::new(vec![
+            test_crd("gating", false, TaskState::Error),
+            test_crd("flaky", true, TaskState::Completed),
+        ]);
+        assert!(snapshot.finished);
+        assert!(!snapshot.passed);
+        assert_eq!(snapshot.failed_tests, vec!["gating".to_string()]);
+        assert!(snapshot.informational_failed_tests.is_empty());
+    }
+
+    #[test]
+    fn completed_test_with_failures_fails() {
+        let mut failing = test_crd("gating", false, TaskState::Completed);
+        if let Crd::Test(test) = &mut failing {
+            if let Some(status) = test.status.as_mut() {
+                status.agent.results = vec![TestResults {
+                    outcome: Outcome::Fail,
+                    num_failed: 1,
+                    ..TestResults::default()
+                }];
+            }
+        }
+        let snapshot = StatusSnapshot::new(vec![failing]);
+        assert!(snapshot.finished);
+        assert!(!snapshot.passed);
+        assert_eq!(snapshot.failed_tests, vec!["gating".to_string()]);
+    }
+
+    #[test]
+    fn informational_running_is_not_finished() {
+        let snapshot = StatusSnapshot::new(vec![test_crd("flaky", true, TaskState::Running)]);
+        assert!(!snapshot.finished);
+        assert!(snapshot.passed);
     }
 }
@@ -0,0 +1,326 @@
+use super::{error, Result, TestManager};
+use crate::agent::TIMEOUT_PATTERN;
+use crate::constants::{NAMESPACE, TEST_AGENT_SERVICE_ACCOUNT};
+use crate::{Agent, Crd, Resource, Test};
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+};
+use k8s_openapi::api::core::v1::{PersistentVolumeClaim, Secret, ServiceAccount};
+use k8s_openapi::NamespaceResourceScope;
+use kube::api::PostParams;
+use kube::Api;
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use snafu::ResultExt;
+use std::fmt::{Debug, Display};
+
+/// An object in the TestSys namespace that a test refers to and that must exist for it to run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Reference {
+    kind: ReferenceKind,
+    name: String,
+    /// What refers to the object, e.g. `Agent 'sonobuoy'`.
+    referrer: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReferenceKind {
+    Secret,
+    ServiceAccount,
+    PersistentVolumeClaim,
+    Resource,
+    Test,
+}
+
+impl Display for ReferenceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ReferenceKind::Secret => "secret",
+            ReferenceKind::ServiceAccount => "service account",
+            ReferenceKind::PersistentVolumeClaim => "persistent volume claim",
+            ReferenceKind::Resource => "resource",
+            ReferenceKind::Test => "test",
+        })
+    }
+}
+
+impl TestManager {
+    /// Check that `test` can run in the cluster without creating it: that its spec is usable, that
+    /// the secrets, service accounts, volumes, resources and tests it refers to exist, and that the
+    /// caller is allowed to create tests. The resources and tests in `manifest`, e.g. the file the
+    /// test is defined in, count as existing. Every problem that is found is returned, an empty
+    /// list means the test is valid. Only read-only requests are made.
+    pub async fn validate_test(&self, test: &Test, manifest: &[Crd]) -> Result<Vec<String>> {
+        let mut problems = spec_problems(test);
+        for reference in references(test) {
+            let in_manifest = manifest.iter().any(|crd| {
+                let kind = match crd {
+                    Crd::Test(_) => ReferenceKind::Test,
+                    Crd::Resource(_) => ReferenceKind::Resource,
+                };
+                kind == reference.kind && crd.name().as_deref() == Some(reference.name.as_str())
+            });
+            if !in_manifest && !self.reference_exists(&reference).await? {
+                problems.push(format!(
+                    "{} refers to {} '{}', which does not exist",
+                    reference.referrer, reference.kind, reference.name
+                ));
+            }
+        }
+        if !self.can_create_tests().await? {
+            problems.push(format!(
+                "You are not allowed to create tests in the '{}' namespace",
+                NAMESPACE
+            ));
+        }
+        Ok(problems)
+    }
+
+    async fn reference_exists(&self, reference: &Reference) -> Result<bool> {
+        match reference.kind {
+            ReferenceKind::Secret => self.object_exists::<Secret>(reference).await,
+            ReferenceKind::ServiceAccount => self.object_exists::<ServiceAccount>(reference).await,
+            ReferenceKind::PersistentVolumeClaim => {
+                self.object_exists::<PersistentVolumeClaim>(reference).await
+            }
+            ReferenceKind::Resource => self.object_exists::<Resource>(reference).await,
+            ReferenceKind::Test => self.object_exists::<Test>(reference).await,
+        }
+    }
+
+    async fn object_exists<K>(&self, reference: &Reference) -> Result<bool>
+    where
+        K: kube::Resource<Scope = NamespaceResourceScope> + Clone + DeserializeOwned + Debug,
+        <K as kube::Resource>::DynamicType: Default,
+    {
+        Ok(Api::<K>::namespaced(self.k8s_client.clone(), NAMESPACE)
+            .get_metadata_opt(&reference.name)
+            .await
+            .context(error::KubeSnafu {
+                action: format!("get {} '{}'", reference.kind, reference.name),
+            })?
+            .is_some())
+    }
+
+    /// Whether the caller's RBAC permissions allow creating tests.
+    async fn can_create_tests(&self) -> Result<bool> {
+        let review = SelfSubjectAccessReview {
+            spec: SelfSubjectAccessReviewSpec {
+                resource_attributes: Some(ResourceAttributes {
+                    group: Some("testsys.system".to_string()),
+                    resource: Some("tests".to_string()),
+                    namespace: Some(NAMESPACE.to_string()),
+                    verb: Some("create".to_string()),
+                    ..ResourceAttributes::default()
+                }),
+                ..SelfSubjectAccessReviewSpec::default()
+            },
+            ..SelfSubjectAccessReview::default()
+        };
+        Ok(Api::<SelfSubjectAccessReview>::all(self.k8s_client.clone())
+            .create(&PostParams::default(), &review)
+            .await
+            .context(error::KubeSnafu {
+                action: "review permission to create tests",
+            })?
+            .status
+            .map(|status| status.allowed)
+            .unwrap_or(false))
+    }
+}
+
+/// Problems with the `test`'s spec that can be found without the cluster.
+fn spec_problems(test: &Test) -> Vec<String> {
+    let timeout = Regex::new(TIMEOUT_PATTERN).ok();
+    agents(test)
+        .flat_map(|agent| {
+            let mut problems = Vec::new();
+            if !valid_image(&agent.image) {
+                problems.push(format!(
+                    "The image '{}' of agent '{}' is not a valid image reference",
+                    agent.image, agent.name
+                ));
+            }
+            if let (Some(timeout), Some(pattern)) = (&agent.timeout, &timeout) {
+                if !pattern.is_match(timeout) {
+                    problems.push(format!(
+                        "The timeout '{}' of agent '{}' is not a duration",
+                        timeout, agent.name
+                    ));
+                }
+            }
+            problems
+        })
+        .collect()
+}
+
+/// Whether `image` can be resolved by a container runtime, e.g. `public.ecr.aws/org/agent:v1`. The
+/// repository must be lower case and there must be no whitespace.
+fn valid_image(image: &str) -> bool {
+    let repository = image.split('@').next().unwrap_or_default();
+    let repository = match repository.rsplit_once(':') {
+        // A colon followed by a path is the port of the registry, not a tag.
+        Some((repository, tag)) if !tag.contains('/') => repository,
+        _ => repository,
+    };
+    !image.is_empty()
+        && !image.contains(char::is_whitespace)
+        && !repository.is_empty()
+        && !repository.contains(|c: char| c.is_ascii_uppercase())
+}
+
+fn agents(test: &Test) -> impl Iterator<Item = &Agent> {
+    std::iter::once(&test.spec.agent).chain(&test.spec.agents)
+}
+
+/// The objects the `test` refers to.
+fn references(test: &Test) -> Vec<Reference> {
+    let mut references = Vec::new();
+    for agent in agents(test) {
+        let referrer = format!("Agent '{}'", agent.name);
+        let mut add = |kind, name: &str| {
+            references.push(Reference {
+                kind,
+                name: name.to_string(),
+                referrer: referrer.clone(),
+            })
+        };
+        for secret in agent.secret_names() {
+            add(ReferenceKind::Secret, secret.as_str());
+        }
+        for mount in agent.secret_mounts.iter().flatten() {
+            add(ReferenceKind::Secret, mount.secret_name.as_str());
+        }
+        if let Some(pull_secret) = &agent.pull_secret {
+            add(ReferenceKind::Secret, pull_secret);
+        }
+        add(
+            ReferenceKind::ServiceAccount,
+            agent
+                .service_account
+                .as_deref()
+                .unwrap_or(TEST_AGENT_SERVICE_ACCOUNT),
+        );
+        for volume in agent.persistent_volumes.iter().flatten() {
+            add(ReferenceKind::PersistentVolumeClaim, &volume.claim_name);
+        }
+    }
+    for resource in &test.spec.resources {
+        references.push(Reference {
+            kind: ReferenceKind::Resource,
+            name: resource.to_owned(),
+            referrer: "The test".to_string(),
+        });
+    }
+    for dependency in test.spec.depends_on.iter().flatten() {
+        references.push(Reference {
+            kind: ReferenceKind::Test,
+            name: dependency.to_owned(),
+            referrer: "The test".to_string(),
+        });
+    }
+    references
+}
+
+#[cfg(test)]
+mod validate_test {
+    use super::*;
+    use crate::{SecretName, TestSpec};
+    use hyper::{Body, Request, Response, StatusCode};
+    use std::collections::BTreeMap;
+    use std::convert::Infallible;
+
+    /// Create a `TestManager` backed by a fake k8s API server that has every object except those
+    /// whose path ends with one of the `missing` paths, and allows every access review.
+    fn fake_test_manager(missing: Vec<&'static str>) -> TestManager {
+        let service = tower::service_fn(move |request: Request<Body>| {
+            let path = request.uri().path().to_string();
+            let missing = missing.clone();
+            async move {
+                let response = if missing.iter().any(|missing| path.ends_with(missing)) {
+                    let mut response = Response::new(Body::from(
+                        serde_json::json!({
+                            "kind": "Status",
+                            "apiVersion": "v1",
+                            "status": "Failure",
+                            "reason": "NotFound",
+                            "code": 404,
+                        })
+                        .to_string(),
+                    ));
+                    *response.status_mut() = StatusCode::NOT_FOUND;
+                    response
+                } else if path.ends_with("/selfsubjectaccessreviews") {
+                    Response::new(Body::from(
+                        serde_json::json!({
+                            "apiVersion": "authorization.k8s.io/v1",
+                            "kind": "SelfSubjectAccessReview",
+                            "spec": {},
+                            "status": { "allowed": true }
+                        })
+                        .to_string(),
+                    ))
+                } else {
+                    let name = path.rsplit('/').next().unwrap_or_default();
+                    Response::new(Body::from(
+                        serde_json::json!({ "metadata": { "name": name } }).to_string(),
+                    ))
+                };
+                Ok::<_, Infallible>(response)
+            }
+        });
+        TestManager {
+            k8s_client: kube::Client::new(service, NAMESPACE),
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_secret_and_dependency_are_reported_together() {
+        let test = Test::new(
+            "my-test",
+            TestSpec {
+                depends_on: Some(vec!["setup".to_string(), "missing-setup".to_string()]),
+                agent: Agent {
+                    name: "sonobuoy".to_string(),
+                    image: "public.ecr.aws/testsys/sonobuoy:v1".to_string(),
+                    secrets: SecretName::new("aws-creds")
+                        .ok()
+                        .map(|secret| BTreeMap::from([("aws".to_string(), secret)])),
+                    ..Agent::default()
+                },
+                ..TestSpec::default()
+            },
+        );
+        let problems = fake_test_manager(vec!["/secrets/aws-creds", "/tests/missing-setup"])
+            .validate_test(&test, &[])
+            .await;
+        assert!(
+            matches!(
+                &problems,
+                Ok(problems) if problems == &vec![
+                    "Agent 'sonobuoy' refers to secret 'aws-creds', which does not exist"
+                        .to_string(),
+                    "The test refers to test 'missing-setup', which does not exist".to_string(),
+                ]
+            ),
+            "{:?}",
+            problems
+        );
+
+        // With every object present the test is valid.
+        assert!(matches!(
+            fake_test_manager(vec![]).validate_test(&test, &[]).await,
+            Ok(problems) if problems.is_empty()
+        ));
+    }
+
+    #[test]
+    fn image_references() {
+        assert!(valid_image("public.ecr.aws/testsys/sonobuoy:v1"));
+        assert!(valid_image("localhost:5000/agent"));
+        assert!(valid_image("agent@sha256:0123abcd"));
+        assert!(!valid_image("Public.ecr.aws/agent:v1"));
+        assert!(!valid_image("agent: v1"));
+        assert!(!valid_image(""));
+    }
+}